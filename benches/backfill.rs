@@ -0,0 +1,99 @@
+//! Sequential against parallel backfill of the same blocks over a `MockChain` answering
+//! after a fixed delay, like a remote node. Needs `BENCH_DATABASE_URL` pointing at a
+//! scratch Postgres; every iteration stores the range's pools and swaps under a fresh
+//! chain id from 998000000 up. Without it the bench is skipped.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use ethers::abi::Token;
+use ethers::types::Address;
+use moonshot_indexer::config::Config;
+use moonshot_indexer::indexer::Indexer;
+use moonshot_indexer::testing::{pool_created_log, swap_log, MockChain};
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+const FACTORY: u64 = 0xfac;
+const HEAD: u64 = 2_000;
+const POOLS: u64 = 20;
+// Round trip of every request to the mock node
+const RPC_LATENCY: Duration = Duration::from_millis(2);
+const FIRST_CHAIN_ID: u64 = 998_000_000;
+
+/// Pools created in the first blocks, then a swap in one of them every other block.
+fn scripted_chain() -> MockChain {
+    let chain = MockChain::new(HEAD);
+    chain.set_latency(RPC_LATENCY);
+    let factory = Address::from_low_u64_be(FACTORY);
+
+    for n in 0..POOLS {
+        let (token0, token1, pool) = pool_addresses(n);
+        chain.add_log(1 + n, pool_created_log(factory, token0, token1, 3000, 60, pool));
+        for token in [token0, token1] {
+            chain.on_call(token, "symbol()", vec![Token::String(format!("T{}", n))]);
+            chain.on_call(token, "decimals()", vec![Token::Uint(18.into())]);
+        }
+    }
+    for block in (POOLS + 1..=HEAD).step_by(2) {
+        let (_, _, pool) = pool_addresses(block % POOLS);
+        chain.add_log(block, swap_log(pool, Address::from_low_u64_be(0xbeef), 1_000, -950, 12));
+    }
+    chain
+}
+
+fn pool_addresses(n: u64) -> (Address, Address, Address) {
+    (
+        Address::from_low_u64_be(0xa000 + 2 * n),
+        Address::from_low_u64_be(0xa001 + 2 * n),
+        Address::from_low_u64_be(0xb000 + n),
+    )
+}
+
+fn config(database_url: &str, chain_id: u64, workers: usize) -> Config {
+    Config::from_lookup(|name| match name {
+        "RPC_URL" => Some("wss://rpc.example.com".to_string()),
+        "DATABASE_URL" => Some(database_url.to_string()),
+        "MOONSHOT_FACTORY_ADDRESS" => Some(format!("{:?}", Address::from_low_u64_be(FACTORY))),
+        "CHAIN_ID" => Some(chain_id.to_string()),
+        "PARALLEL_BACKFILL_WORKERS" => Some(workers.to_string()),
+        "SUPPLY_REFRESH_INTERVAL_SECS" | "HOLDER_SNAPSHOT_INTERVAL_SECS" => Some("0".to_string()),
+        _ => None,
+    })
+    .expect("bench config is valid")
+}
+
+fn backfill(c: &mut Criterion) {
+    let Ok(database_url) = env::var("BENCH_DATABASE_URL") else {
+        eprintln!("BENCH_DATABASE_URL is not set, skipping the backfill benches");
+        return;
+    };
+
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+    let chain = scripted_chain();
+    // A fresh chain id per iteration, or every run after the first only hits known swaps
+    let chain_id = AtomicU64::new(FIRST_CHAIN_ID);
+
+    let mut group = c.benchmark_group("backfill");
+    group.throughput(Throughput::Elements(HEAD));
+    group.sample_size(10);
+
+    for workers in [1, 4] {
+        group.bench_function(format!("{}_blocks_{}_workers", HEAD, workers), |b| {
+            b.iter_batched(
+                || {
+                    let config = config(&database_url, chain_id.fetch_add(1, Ordering::Relaxed), workers);
+                    runtime
+                        .block_on(Indexer::with_provider(config, chain.provider()))
+                        .expect("indexer starts over the mock chain")
+                },
+                |mut indexer| runtime.block_on(indexer.backfill(1, HEAD)).expect("backfill succeeds"),
+                BatchSize::PerIteration,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, backfill);
+criterion_main!(benches);
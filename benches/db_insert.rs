@@ -0,0 +1,53 @@
+//! Needs `BENCH_DATABASE_URL` pointing at a scratch Postgres; every iteration adds 10k
+//! swaps on chain id 999999. Without it the bench is skipped.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use moonshot_indexer::db::Database;
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod fixtures;
+
+const BENCH_CHAIN_ID: i64 = 999_999;
+
+fn insert_swaps(c: &mut Criterion) {
+    let Ok(database_url) = env::var("BENCH_DATABASE_URL") else {
+        eprintln!("BENCH_DATABASE_URL is not set, skipping the database benches");
+        return;
+    };
+
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+    let database = runtime.block_on(async {
+        let database = Database::new(&database_url).await.expect("database connects");
+        database.init_schema().await.expect("schema initialises");
+        database
+    });
+
+    // Fresh tx hashes every iteration, or the inserts would all hit the conflict path
+    let first_run = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() << 20;
+    let run = AtomicU64::new(first_run);
+
+    let mut group = c.benchmark_group("db");
+    group.throughput(Throughput::Elements(fixtures::BATCH));
+    group.sample_size(10);
+
+    group.bench_function("insert_swaps_10k", |b| {
+        b.iter_batched(
+            || fixtures::swap_events(fixtures::BATCH, BENCH_CHAIN_ID, run.fetch_add(1, Ordering::Relaxed)),
+            |swaps| {
+                runtime.block_on(async {
+                    for swap in &swaps {
+                        database.insert_swap(swap).await.expect("swap inserts");
+                    }
+                })
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, insert_swaps);
+criterion_main!(benches);
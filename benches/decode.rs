@@ -0,0 +1,36 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use moonshot_indexer::moonshot::{decode, LogDecoder};
+use std::sync::Arc;
+
+mod fixtures;
+
+fn decode_swap_logs(c: &mut Criterion) {
+    let decoder = Arc::new(LogDecoder::new());
+    let logs = fixtures::swap_logs(fixtures::BATCH);
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(fixtures::BATCH));
+
+    group.bench_function("swap_logs_10k", |b| {
+        b.iter(|| {
+            for log in &logs {
+                black_box(decoder.decode_swap(log, 2741).expect("fixture decodes"));
+            }
+        })
+    });
+
+    // The indexer's path: the whole batch handed to the blocking pool
+    group.bench_function("swap_logs_10k_offloaded", |b| {
+        b.iter_batched(
+            || logs.clone(),
+            |logs| runtime.block_on(decode::decode_swaps(decoder.clone(), logs, 2741)).expect("batch decodes"),
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, decode_swap_logs);
+criterion_main!(benches);
@@ -0,0 +1,74 @@
+//! Deterministic inputs shared by the benches, so runs are comparable across commits.
+
+// Each bench compiles this module on its own and uses only some of it
+#![allow(dead_code)]
+
+use ethers::abi::{encode, Token};
+use ethers::types::{Address, Log, H256, I256, U256, U64};
+use moonshot_indexer::moonshot::get_pool_abi;
+use moonshot_indexer::SwapEvent;
+
+pub const BATCH: u64 = 10_000;
+
+const POOL: u64 = 0xaa;
+
+/// `count` Swap logs of one pool, alternating direction, ten per block.
+pub fn swap_logs(count: u64) -> Vec<Log> {
+    let signature = get_pool_abi().event("Swap").expect("pool ABI has Swap").signature();
+
+    (0..count)
+        .map(|n| {
+            let (amount0, amount1) = if n % 2 == 0 {
+                (I256::from(1_000 + n as i64), I256::from(-(950 + n as i64)))
+            } else {
+                (I256::from(-(950 + n as i64)), I256::from(1_000 + n as i64))
+            };
+
+            Log {
+                address: Address::from_low_u64_be(POOL),
+                topics: vec![
+                    signature,
+                    H256::from(Address::from_low_u64_be(0xbeef + n % 7)),
+                    H256::from(Address::from_low_u64_be(0xcafe)),
+                ],
+                data: encode(&[
+                    Token::Int(amount0.into_raw()),
+                    Token::Int(amount1.into_raw()),
+                    Token::Uint(U256::one() << 96),
+                    Token::Uint(U256::from(10u64.pow(18))),
+                    Token::Int(I256::from(-(n as i64 % 887)).into_raw()),
+                ])
+                .into(),
+                block_number: Some(U64::from(1_000_000 + n / 10)),
+                log_index: Some((n % 10).into()),
+                transaction_hash: Some(H256::from_low_u64_be(n + 1)),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// `count` fully populated swaps on `chain_id`; `run` keeps tx hashes unique across runs.
+pub fn swap_events(count: u64, chain_id: i64, run: u64) -> Vec<SwapEvent> {
+    (0..count)
+        .map(|n| {
+            let mut swap = SwapEvent::new(
+                format!("0x{:032x}{:032x}", run, n),
+                format!("0x{:040x}", POOL),
+                "token0".to_string(),
+                "token1".to_string(),
+                1_000 + n as i64,
+                950 + n as i64,
+                1_700_000_000 + n as i64,
+                1_000_000 + n as i64 / 10,
+                (n % 10) as i32,
+                chain_id,
+            );
+            swap.sender_address = Some(format!("0x{:040x}", 0xbeef + n % 7));
+            swap.amount_in_usd = Some(10.0 + n as f64 / 100.0);
+            swap.amount_out_usd = Some(9.5 + n as f64 / 100.0);
+            swap.indexed_at = Some(1_700_000_000_000 + n as i64);
+            swap
+        })
+        .collect()
+}
@@ -0,0 +1,24 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use moonshot_indexer::SwapEvent;
+
+mod fixtures;
+
+fn swap_event_json(c: &mut Criterion) {
+    let swaps = fixtures::swap_events(fixtures::BATCH, 2741, 0);
+    let json = serde_json::to_vec(&swaps).expect("swaps serialize");
+
+    let mut group = c.benchmark_group("serde");
+    group.throughput(Throughput::Elements(fixtures::BATCH));
+
+    group.bench_function("serialize_swaps_10k", |b| {
+        b.iter(|| serde_json::to_vec(black_box(&swaps)).expect("swaps serialize"))
+    });
+    group.bench_function("deserialize_swaps_10k", |b| {
+        b.iter(|| serde_json::from_slice::<Vec<SwapEvent>>(black_box(&json)).expect("swaps deserialize"))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, swap_event_json);
+criterion_main!(benches);
@@ -0,0 +1,110 @@
+use crate::types::PoolData;
+
+// Volatility is measured over this trailing window, ending at the latest observation
+const VOLATILITY_WINDOW_SECS: i64 = 7 * 24 * 3600;
+
+// Tick standard deviations below these suit the 0.05% and 0.3% tiers; above, the 1% tier
+const LOW_VOLATILITY_TICKS: f64 = 100.0;
+const MEDIUM_VOLATILITY_TICKS: f64 = 500.0;
+
+const FEE_TIERS: [i32; 3] = [500, 3000, 10000];
+
+/// Population standard deviation of the ticks observed within seven days of the latest
+/// `(tick, timestamp)` entry; `None` with fewer than two observations in that window.
+pub fn tick_std_dev(tick_history: &[(i32, i64)]) -> Option<f64> {
+    let latest = tick_history.iter().map(|(_, timestamp)| *timestamp).max()?;
+    let ticks: Vec<f64> = tick_history
+        .iter()
+        .filter(|(_, timestamp)| latest - timestamp < VOLATILITY_WINDOW_SECS)
+        .map(|(tick, _)| *tick as f64)
+        .collect();
+    if ticks.len() < 2 {
+        return None;
+    }
+
+    let mean = ticks.iter().sum::<f64>() / ticks.len() as f64;
+    let variance = ticks.iter().map(|tick| (tick - mean).powi(2)).sum::<f64>() / ticks.len() as f64;
+    Some(variance.sqrt())
+}
+
+/// Fee tier to LP a pair in, from its 7-day tick volatility: calm pairs earn more volume at
+/// 0.05%, volatile ones need 1% to outweigh impermanent loss.
+///
+/// With existing `pools` for the pair, the closest tier among theirs is returned so the
+/// liquidity joins a live pool; without any, the heuristic's own tier.
+pub fn best_fee_tier_for_pair(pools: &[PoolData], tick_history: &[(i32, i64)]) -> Option<i32> {
+    let std_dev = tick_std_dev(tick_history)?;
+    let ideal = if std_dev < LOW_VOLATILITY_TICKS {
+        FEE_TIERS[0]
+    } else if std_dev < MEDIUM_VOLATILITY_TICKS {
+        FEE_TIERS[1]
+    } else {
+        FEE_TIERS[2]
+    };
+
+    let tier_index = |fee: i32| FEE_TIERS.iter().position(|tier| *tier == fee);
+    let ideal_index = tier_index(ideal)?;
+    // Ties go to the higher tier, the safer side against volatility
+    let closest = pools
+        .iter()
+        .filter_map(|pool| pool.fee_tier)
+        .filter_map(|fee| tier_index(fee).map(|index| (fee, index)))
+        .min_by_key(|(_, index)| (index.abs_diff(ideal_index), usize::MAX - index))
+        .map(|(fee, _)| fee);
+
+    Some(closest.unwrap_or(ideal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::MOONSHOT;
+
+    const HOUR: i64 = 3600;
+
+    // Hourly ticks alternating around 0 by `swing`, so the std dev is exactly `swing`
+    fn series(swing: i32) -> Vec<(i32, i64)> {
+        (0..168).map(|hour| (if hour % 2 == 0 { swing } else { -swing }, hour * HOUR)).collect()
+    }
+
+    fn pool(fee_tier: i32) -> PoolData {
+        let mut pool = PoolData::new(
+            format!("0x{:040x}", fee_tier),
+            "0x00000000000000000000000000000000000000a0".to_string(),
+            "0x00000000000000000000000000000000000000a1".to_string(),
+            2741,
+            MOONSHOT,
+        );
+        pool.fee_tier = Some(fee_tier);
+        pool
+    }
+
+    #[test]
+    fn test_tier_follows_volatility() {
+        assert_eq!(tick_std_dev(&series(40)), Some(40.0));
+
+        assert_eq!(best_fee_tier_for_pair(&[], &series(40)), Some(500));
+        assert_eq!(best_fee_tier_for_pair(&[], &series(250)), Some(3000));
+        assert_eq!(best_fee_tier_for_pair(&[], &series(2_000)), Some(10000));
+        // Boundaries belong to the higher tier
+        assert_eq!(best_fee_tier_for_pair(&[], &series(100)), Some(3000));
+    }
+
+    #[test]
+    fn test_only_the_last_seven_days_count() {
+        // A violent month-old move followed by a calm week
+        let mut history = vec![(50_000, -30 * 24 * HOUR), (-50_000, -29 * 24 * HOUR)];
+        history.extend(series(40));
+
+        assert_eq!(best_fee_tier_for_pair(&[], &history), Some(500));
+        assert_eq!(best_fee_tier_for_pair(&[], &[(10, 0)]), None);
+        assert_eq!(best_fee_tier_for_pair(&[], &[]), None);
+    }
+
+    #[test]
+    fn test_prefers_existing_pools() {
+        assert_eq!(best_fee_tier_for_pair(&[pool(3000), pool(10000)], &series(40)), Some(3000));
+        assert_eq!(best_fee_tier_for_pair(&[pool(500), pool(10000)], &series(250)), Some(10000));
+        assert_eq!(best_fee_tier_for_pair(&[pool(500)], &series(2_000)), Some(500));
+    }
+}
@@ -0,0 +1,606 @@
+use anyhow::Result;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use ethers::providers::{Provider, Ws};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::candles::{self, PriceAnchors, RebuildOptions};
+use crate::catchup::SyncProgress;
+use crate::chain::{self, CachedHeaders};
+use crate::correlation::{self, REQUEST_ID_HEADER};
+use crate::db::{Database, PoolUpdateCursor, TimelineCursor, MAX_BLOCK_STATS_RANGE};
+use crate::dex::{DexId, MOONSHOT};
+use crate::doctor::{self, CheckResult};
+use crate::error::IndexerError;
+use crate::metrics::{self, ThroughputWindow};
+use crate::migration;
+use crate::reload::ConfigReloader;
+use crate::runtime::RuntimeSettings;
+use crate::types::{AddressParseError, GasStats, IndexingStats, PoolAtBlock, PoolSummary, ProtocolStats};
+use crate::watchlist::PoolWatcher;
+
+// Latest AMM swaps included in a token's timeline
+const TIMELINE_SWAP_LIMIT: i64 = 1000;
+
+// Events per page of `/tokens/:address/timeline` and pools per page of `/pools/updated`,
+// by default and at most
+const DEFAULT_TIMELINE_PAGE: i64 = 100;
+const MAX_TIMELINE_PAGE: i64 = 1000;
+
+// Blocks `/block-stats` covers unless asked otherwise
+const DEFAULT_BLOCK_STATS_BLOCKS: u64 = 100;
+
+// How long `/stats` serves the dashboard totals from memory
+const PROTOCOL_STATS_TTL: Duration = Duration::from_secs(60);
+
+// Indexing throughput on `/metrics` is averaged over scrapes this far back
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(30);
+
+// Same as the `STATS_STALE_AFTER_SECS` default
+const DEFAULT_STATS_STALE_AFTER_SECS: u64 = 300;
+
+#[derive(Clone)]
+pub struct ApiState {
+    database: Database,
+    chain_id: i64,
+    // DEX of the indexer the throughput metrics describe
+    dex: DexId,
+    // Needed only to resolve timestamps to blocks
+    provider: Option<Arc<Provider<Ws>>>,
+    // Needed only for `/control/reload`
+    reloader: Option<Arc<ConfigReloader>>,
+    runtime: Option<RuntimeSettings>,
+    // Reported by `/health` when the indexer runs in this process
+    progress: Option<Arc<SyncProgress>>,
+    // `/ready` fails once the read pool trails the write pool by more blocks than this
+    max_replica_lag_blocks: Option<u64>,
+    // Converts pool ages to days
+    seconds_per_block: f64,
+    // Needed only for `/watch`, which hands pools to the indexer in this process
+    pool_watcher: Option<PoolWatcher>,
+    // Needed only for `/control/rebuild-candles`
+    price_anchors: Option<PriceAnchors>,
+    // Stats rows not updated for this long are flagged by `/stats`
+    stats_stale_after_secs: u64,
+    protocol_stats: Arc<Mutex<Option<(Instant, ProtocolStats)>>>,
+    throughput: Arc<ThroughputWindow>,
+}
+
+impl ApiState {
+    pub fn new(database: Database, chain_id: i64) -> Self {
+        Self {
+            database,
+            chain_id,
+            dex: MOONSHOT,
+            provider: None,
+            reloader: None,
+            runtime: None,
+            progress: None,
+            max_replica_lag_blocks: None,
+            seconds_per_block: chain::chain_info(chain_id).map_or(12.0, |chain| chain.seconds_per_block),
+            pool_watcher: None,
+            price_anchors: None,
+            stats_stale_after_secs: DEFAULT_STATS_STALE_AFTER_SECS,
+            protocol_stats: Arc::new(Mutex::new(None)),
+            throughput: Arc::new(ThroughputWindow::new(THROUGHPUT_WINDOW)),
+        }
+    }
+
+    /// The DEX the indexer in this process stores pools under, `moonshot` by default.
+    pub fn with_dex(mut self, dex: DexId) -> Self {
+        self.dex = dex;
+        self
+    }
+
+    pub fn with_provider(mut self, provider: Arc<Provider<Ws>>) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    pub fn with_reloader(mut self, reloader: Arc<ConfigReloader>) -> Self {
+        self.reloader = Some(reloader);
+        self
+    }
+
+    /// Reported by `/stats`.
+    pub fn with_runtime(mut self, runtime: RuntimeSettings) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    pub fn with_progress(mut self, progress: Arc<SyncProgress>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    pub fn with_max_replica_lag(mut self, blocks: u64) -> Self {
+        self.max_replica_lag_blocks = Some(blocks);
+        self
+    }
+
+    /// Overrides the chain's usual block time for pool ages.
+    pub fn with_seconds_per_block(mut self, seconds_per_block: f64) -> Self {
+        self.seconds_per_block = seconds_per_block;
+        self
+    }
+
+    pub fn with_pool_watcher(mut self, pool_watcher: PoolWatcher) -> Self {
+        self.pool_watcher = Some(pool_watcher);
+        self
+    }
+
+    pub fn with_price_anchors(mut self, price_anchors: PriceAnchors) -> Self {
+        self.price_anchors = Some(price_anchors);
+        self
+    }
+
+    pub fn with_stats_stale_after(mut self, secs: u64) -> Self {
+        self.stats_stale_after_secs = secs;
+        self
+    }
+}
+
+/// HTTP API over the indexed data; `/control/reload`, `/control/rebuild-candles` and `/watch`
+/// are its only write endpoints.
+/// Every request runs under the id from its `x-request-id` header, or a fresh one, which is
+/// echoed back in that header and in error bodies.
+pub fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/pools/updated", get(get_updated_pools))
+        .route("/pools/:address/at/:block", get(get_pool_at_block))
+        .route("/pools/:address/at-time/:timestamp", get(get_pool_at_time))
+        .route("/tokens/:address", get(get_token))
+        .route("/tokens/:address/timeline", get(get_token_timeline))
+        .route("/health", get(get_health))
+        .route("/ready", get(get_ready))
+        .route("/metrics", get(get_metrics))
+        .route("/stats", get(get_stats))
+        .route("/gas", get(get_gas))
+        .route("/block-stats", get(get_block_stats))
+        .route("/control/reload", post(reload_config))
+        .route("/control/rebuild-candles", post(rebuild_candles))
+        .route("/watch", post(watch_pool))
+        .layer(middleware::from_fn(request_id))
+        .with_state(state)
+}
+
+/// Serves the request inside a `request` span carrying its id, which database statements
+/// and the indexer's handling of `/watch` requests log too.
+async fn request_id(request: Request, next: Next) -> Response {
+    let incoming = request.headers().get(REQUEST_ID_HEADER).and_then(|value| value.to_str().ok());
+    let request_id = correlation::accept(incoming);
+    let span = info_span!("request", request_id = %request_id, method = %request.method(), path = %request.uri().path());
+
+    let mut response = correlation::scope(request_id.clone(), next.run(request)).instrument(span).await;
+    // `accept` only keeps ids that are valid header values
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+pub async fn serve(bind_address: &str, state: ApiState) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(bind_address).await?;
+    info!("API listening on {}", bind_address);
+    axum::serve(listener, router(state)).await?;
+    Ok(())
+}
+
+struct ApiError(anyhow::Error);
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        Self(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        error!("API error: {}", self.0);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            error_body(self.0.to_string()),
+        )
+            .into_response()
+    }
+}
+
+fn not_found(message: String) -> Response {
+    (StatusCode::NOT_FOUND, error_body(message)).into_response()
+}
+
+fn bad_request(message: String) -> Response {
+    (StatusCode::BAD_REQUEST, error_body(message)).into_response()
+}
+
+/// `{"error": message}`, plus the id of the request being served.
+fn error_body(message: String) -> Json<serde_json::Value> {
+    let mut body = serde_json::json!({ "error": message });
+    if let Some(request_id) = correlation::current() {
+        body["request_id"] = request_id.into();
+    }
+    Json(body)
+}
+
+#[derive(Debug, Deserialize)]
+struct VolumeQuery {
+    /// Leave sandwich and arbitrage legs out of the volume
+    #[serde(default)]
+    exclude_mev: bool,
+}
+
+async fn get_pool_at_block(
+    State(state): State<ApiState>,
+    Path((address, block)): Path<(String, i64)>,
+    Query(query): Query<VolumeQuery>,
+) -> Result<Response, ApiError> {
+    pool_at_block(&state, &address, block, query.exclude_mev).await
+}
+
+async fn get_pool_at_time(
+    State(state): State<ApiState>,
+    Path((address, timestamp)): Path<(String, u64)>,
+    Query(query): Query<VolumeQuery>,
+) -> Result<Response, ApiError> {
+    let provider = match &state.provider {
+        Some(provider) => provider,
+        None => return Ok(bad_request("timestamp queries need an RPC connection".to_string())),
+    };
+
+    let headers = CachedHeaders::new(provider.as_ref(), &state.database, state.chain_id);
+    let block = match chain::block_for_timestamp(&headers, timestamp).await {
+        Ok(block) => block,
+        Err(e) => return Ok(bad_request(e.to_string())),
+    };
+
+    pool_at_block(&state, &address, block as i64, query.exclude_mev).await
+}
+
+/// Prometheus metrics, with indexing throughput updated from the stats at each scrape.
+async fn get_metrics(State(state): State<ApiState>) -> String {
+    match indexing_stats(&state).await {
+        Ok(stats) => {
+            state.throughput.record(stats);
+        }
+        Err(e) => warn!("Error reading indexing stats for metrics: {}", e),
+    }
+    metrics::render()
+}
+
+async fn indexing_stats(state: &ApiState) -> Result<IndexingStats> {
+    state.database.dexes().check_chain(&state.dex, state.chain_id)?;
+    let (total_pools, total_swaps) = state.database.get_stats().await?;
+    Ok(IndexingStats {
+        last_processed_block: state.progress.as_ref().map_or(0, |progress| progress.last_processed_block()) as i64,
+        total_pools_indexed: total_pools as i64,
+        total_swaps_indexed: total_swaps as i64,
+        chain_id: state.chain_id,
+        dex_name: state.dex.clone(),
+        updated_at: metrics::unix_millis() / 1000,
+        head_block: state.progress.as_ref().map(|progress| progress.head_block() as i64).filter(|head_block| *head_block > 0),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    #[serde(flatten)]
+    sync: Option<SyncReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct SyncReport {
+    last_processed_block: u64,
+    head_block: u64,
+    blocks_behind: u64,
+    blocks_per_second: Option<f64>,
+    /// None until the indexer has a processing rate to go by.
+    catch_up_eta_secs: Option<u64>,
+}
+
+/// Liveness, plus how far behind the head the indexer is and when it should catch up.
+async fn get_health(State(state): State<ApiState>) -> Json<HealthResponse> {
+    let sync = state.progress.as_ref().map(|progress| {
+        let (last_processed_block, head_block) = (progress.last_processed_block(), progress.head_block());
+        let blocks_behind = head_block.saturating_sub(last_processed_block);
+        let eta = progress.estimate(blocks_behind);
+        SyncReport {
+            last_processed_block,
+            head_block,
+            blocks_behind,
+            blocks_per_second: progress.blocks_per_second(),
+            catch_up_eta_secs: (eta != Duration::MAX).then_some(eta.as_secs()),
+        }
+    });
+    Json(HealthResponse { status: "ok", sync })
+}
+
+#[derive(Debug, Serialize)]
+struct ReadyResponse {
+    status: &'static str,
+    primary_block: i64,
+    replica_block: i64,
+    replica_lag_blocks: i64,
+    checks: Vec<CheckResult>,
+}
+
+/// Ready while the database passes `doctor::check_database` and the read pool, which serves
+/// every query, trails the write pool by at most the allowed lag; 503 otherwise so a load
+/// balancer can route around a broken or stale replica.
+async fn get_ready(State(state): State<ApiState>) -> Result<Response, ApiError> {
+    let database_check = doctor::check_database(&state.database).await;
+    if !database_check.passed {
+        let body = serde_json::json!({ "status": "database unavailable", "checks": [database_check] });
+        return Ok((StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response());
+    }
+
+    let lag = state.database.replica_lag(state.chain_id).await?;
+    let ready = state.max_replica_lag_blocks.is_none_or(|max| lag.blocks() as u64 <= max);
+    let response = ReadyResponse {
+        status: if ready { "ready" } else { "replica lagging" },
+        primary_block: lag.primary_block,
+        replica_block: lag.replica_block,
+        replica_lag_blocks: lag.blocks(),
+        checks: vec![database_check],
+    };
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    Ok((status, Json(response)).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct GasQuery {
+    from_ts: i64,
+    to_ts: i64,
+}
+
+/// Base fee and block fullness of blocks with `from_ts <= timestamp < to_ts`.
+async fn get_gas(State(state): State<ApiState>, Query(query): Query<GasQuery>) -> Result<Response, ApiError> {
+    if query.from_ts >= query.to_ts {
+        return Ok(bad_request("from_ts must be before to_ts".to_string()));
+    }
+    let stats: Vec<GasStats> = state.database.get_gas_stats(state.chain_id, query.from_ts, query.to_ts).await?;
+    Ok(Json(stats).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockStatsQuery {
+    last_n: Option<u64>,
+}
+
+/// Swap activity of each of the last `last_n` indexed blocks, including blocks without swaps,
+/// up to the indexer's last processed block or, without an indexer in this process, the
+/// last block with indexed data.
+async fn get_block_stats(State(state): State<ApiState>, Query(query): Query<BlockStatsQuery>) -> Result<Response, ApiError> {
+    let last_n = query.last_n.unwrap_or(DEFAULT_BLOCK_STATS_BLOCKS);
+    if !(1..=MAX_BLOCK_STATS_RANGE as u64).contains(&last_n) {
+        return Ok(bad_request(format!("last_n must be between 1 and {}", MAX_BLOCK_STATS_RANGE)));
+    }
+
+    let to_block = match state.progress.as_ref().map(|progress| progress.last_processed_block()) {
+        Some(block) if block > 0 => block,
+        _ => protocol_stats(&state).await?.last_block.max(0) as u64,
+    };
+    let from_block = (to_block + 1).saturating_sub(last_n);
+    let stats = state.database.get_block_stats_range(state.chain_id, from_block as i64, to_block as i64).await?;
+    Ok(Json(stats).into_response())
+}
+
+#[derive(Debug, Serialize)]
+struct StatsResponse {
+    #[serde(flatten)]
+    protocol: ProtocolStats,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    runtime: Option<RuntimeSettings>,
+    /// Every chain and DEX indexed into the database.
+    indexers: Vec<IndexerStatus>,
+}
+
+#[derive(Debug, Serialize)]
+struct IndexerStatus {
+    #[serde(flatten)]
+    stats: IndexingStats,
+    blocks_behind: Option<i64>,
+    update_age_secs: i64,
+    stale: bool,
+}
+
+/// Dashboard totals for the indexed chain, at most a minute old, the effective runtime
+/// sizing, and the progress of each chain and DEX.
+async fn get_stats(State(state): State<ApiState>) -> Result<Response, ApiError> {
+    let protocol = protocol_stats(&state).await?;
+    let now = metrics::unix_millis() / 1000;
+    let indexers = state
+        .database
+        .get_all_stats()
+        .await?
+        .into_iter()
+        .map(|stats| IndexerStatus {
+            blocks_behind: stats.blocks_behind(),
+            update_age_secs: stats.update_age_secs(now),
+            stale: stats.is_stale(now, state.stats_stale_after_secs),
+            stats,
+        })
+        .collect();
+    Ok(Json(StatsResponse { protocol, runtime: state.runtime, indexers }).into_response())
+}
+
+async fn protocol_stats(state: &ApiState) -> Result<ProtocolStats> {
+    if let Some((fetched_at, stats)) = state.protocol_stats.lock().unwrap().as_ref() {
+        if fetched_at.elapsed() < PROTOCOL_STATS_TTL {
+            return Ok(stats.clone());
+        }
+    }
+
+    let stats = state.database.get_protocol_stats(state.chain_id).await?;
+    *state.protocol_stats.lock().unwrap() = Some((Instant::now(), stats.clone()));
+    Ok(stats)
+}
+
+/// Re-reads the configuration like SIGHUP does and reports the applied and rejected changes.
+async fn reload_config(State(state): State<ApiState>) -> Response {
+    let reloader = match &state.reloader {
+        Some(reloader) => reloader,
+        None => return not_found("config reloading is not enabled".to_string()),
+    };
+
+    match reloader.reload() {
+        Ok(diff) => Json(diff).into_response(),
+        Err(e) => bad_request(e.to_string()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RebuildCandlesRequest {
+    pool_address: String,
+    interval: Option<String>,
+    from_ts: Option<i64>,
+    to_ts: Option<i64>,
+}
+
+/// Rebuilds the price buckets read from a pool, answering with what was replaced once done.
+async fn rebuild_candles(State(state): State<ApiState>, Json(request): Json<RebuildCandlesRequest>) -> Result<Response, ApiError> {
+    let price_anchors = match &state.price_anchors {
+        Some(price_anchors) => price_anchors,
+        None => return Ok(not_found("candle rebuilding is not enabled".to_string())),
+    };
+    let interval_secs = match candles::parse_interval(request.interval.as_deref().unwrap_or(candles::DEFAULT_INTERVAL)) {
+        Ok(interval_secs) => interval_secs,
+        Err(e) => return Ok(bad_request(e.to_string())),
+    };
+    if state.database.get_pool(&request.pool_address).await?.is_none() {
+        return Ok(not_found(format!("pool {} not found", request.pool_address)));
+    }
+
+    let options = RebuildOptions {
+        chain_id: state.chain_id,
+        pool_address: request.pool_address,
+        interval_secs,
+        from_ts: request.from_ts,
+        to_ts: request.to_ts,
+    };
+    let report = candles::rebuild(&state.database, price_anchors, &options).await?;
+    Ok(Json(report).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct WatchRequest {
+    pool_address: String,
+}
+
+/// Queues a pool for the indexer to watch: 202 once queued, 409 if it is watched already.
+async fn watch_pool(State(state): State<ApiState>, Json(request): Json<WatchRequest>) -> Result<Response, ApiError> {
+    let pool_watcher = match &state.pool_watcher {
+        Some(pool_watcher) => pool_watcher,
+        None => return Ok(not_found("pool watching is not enabled".to_string())),
+    };
+
+    match pool_watcher.watch(&request.pool_address) {
+        Ok(()) => {
+            info!("Queued pool {} to watch", request.pool_address);
+            let body = serde_json::json!({ "pool_address": request.pool_address, "status": "queued" });
+            Ok((StatusCode::ACCEPTED, Json(body)).into_response())
+        }
+        Err(e) if e.downcast_ref::<AddressParseError>().is_some() => Ok(bad_request(e.to_string())),
+        Err(e) if matches!(e.downcast_ref::<IndexerError>(), Some(IndexerError::AlreadyWatched { .. })) => {
+            Ok((StatusCode::CONFLICT, error_body(e.to_string())).into_response())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Token metadata plus its curve trades and AMM swaps as one timeline.
+async fn get_token(
+    State(state): State<ApiState>,
+    Path(address): Path<String>,
+) -> Result<Response, ApiError> {
+    let timeline = migration::load_timeline(&state.database, &address, state.chain_id, TIMELINE_SWAP_LIMIT).await?;
+    if timeline.is_empty() {
+        return Ok(not_found(format!("token {} not found", address)));
+    }
+    Ok(Json(timeline).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct TimelineQuery {
+    from_ts: Option<i64>,
+    to_ts: Option<i64>,
+    limit: Option<i64>,
+    cursor: Option<String>,
+}
+
+/// Paginated events of a token across its curve and AMM phases, in chain order.
+async fn get_token_timeline(
+    State(state): State<ApiState>,
+    Path(address): Path<String>,
+    Query(query): Query<TimelineQuery>,
+) -> Result<Response, ApiError> {
+    let cursor = match query.cursor.as_deref().map(str::parse::<TimelineCursor>).transpose() {
+        Ok(cursor) => cursor,
+        Err(e) => return Ok(bad_request(e.to_string())),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_TIMELINE_PAGE);
+    if !(1..=MAX_TIMELINE_PAGE).contains(&limit) {
+        return Ok(bad_request(format!("limit must be between 1 and {}", MAX_TIMELINE_PAGE)));
+    }
+
+    let page = state
+        .database
+        .get_token_timeline(
+            &address,
+            state.chain_id,
+            query.from_ts.unwrap_or(0),
+            query.to_ts.unwrap_or(i64::MAX),
+            limit,
+            cursor.as_ref(),
+        )
+        .await?;
+    Ok(Json(page).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdatedPoolsQuery {
+    since: i64,
+    dex: Option<String>,
+    limit: Option<i64>,
+    cursor: Option<String>,
+}
+
+/// Pools changed at or after `since`, oldest change first, for invalidating cached pools.
+/// `dex` keeps those of one DEX of the registry; an unknown one lists the known ones.
+async fn get_updated_pools(
+    State(state): State<ApiState>,
+    Query(query): Query<UpdatedPoolsQuery>,
+) -> Result<Response, ApiError> {
+    let cursor = match query.cursor.as_deref().map(str::parse::<PoolUpdateCursor>).transpose() {
+        Ok(cursor) => cursor,
+        Err(e) => return Ok(bad_request(e.to_string())),
+    };
+    let dex = match query.dex.as_deref().map(|dex| state.database.dexes().parse(dex)).transpose() {
+        Ok(dex) => dex,
+        Err(e) => return Ok(bad_request(e.to_string())),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_TIMELINE_PAGE);
+    if !(1..=MAX_TIMELINE_PAGE).contains(&limit) {
+        return Ok(bad_request(format!("limit must be between 1 and {}", MAX_TIMELINE_PAGE)));
+    }
+
+    let page = state.database.get_pools_updated_since(state.chain_id, query.since, dex, limit, cursor.as_ref()).await?;
+    Ok(Json(page).into_response())
+}
+
+async fn pool_at_block(state: &ApiState, address: &str, block: i64, exclude_mev: bool) -> Result<Response, ApiError> {
+    let pool = match state.database.get_pool_at_block(address, block).await? {
+        Some(pool) => PoolSummary::new(pool, block as u64, state.seconds_per_block),
+        None => return Ok(not_found(format!("pool {} not found at block {}", address, block))),
+    };
+    let cumulative_volume = state.database.get_cumulative_volume(address, state.chain_id, block, exclude_mev).await?;
+
+    Ok(Json(PoolAtBlock { pool, cumulative_volume }).into_response())
+}
@@ -0,0 +1,311 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+use crate::budget::MemoryBudget;
+use crate::config::Config;
+use crate::db::Database;
+use crate::types::SwapEvent;
+
+// Failed uploads are retried after this delay, doubling up to the maximum
+const RETRY_INITIAL_DELAY: Duration = Duration::from_secs(1);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(300);
+
+/// Where archive objects are written.
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    async fn put_object(&self, key: &str, body: Vec<u8>) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchivePolicy {
+    pub chain_id: i64,
+    pub window_blocks: u64,
+    pub retry_initial_delay: Duration,
+    pub retry_max_delay: Duration,
+}
+
+impl ArchivePolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            chain_id: config.chain_id as i64,
+            window_blocks: config.archive_window_blocks.max(1),
+            retry_initial_delay: RETRY_INITIAL_DELAY,
+            retry_max_delay: RETRY_MAX_DELAY,
+        }
+    }
+}
+
+/// The window of `window_blocks` blocks containing `block`; windows start at multiples of
+/// `window_blocks`, so every run cuts the chain the same way.
+pub fn window_of(block: u64, window_blocks: u64) -> (u64, u64) {
+    let from = block - block % window_blocks;
+    (from, from + window_blocks - 1)
+}
+
+/// `chain=<id>/date=<yyyy-mm-dd>/swaps-<from>-<to>.jsonl.gz`, dated by the window's first swap.
+pub fn object_key(chain_id: i64, from_block: u64, to_block: u64, first_timestamp: i64) -> String {
+    format!("chain={}/date={}/swaps-{}-{}.jsonl.gz", chain_id, utc_date(first_timestamp), from_block, to_block)
+}
+
+/// The UTC calendar date of a unix timestamp as `yyyy-mm-dd`.
+pub fn utc_date(timestamp: i64) -> String {
+    // Days since the epoch to a proleptic Gregorian date, counting eras from 0000-03-01
+    let days = timestamp.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// One JSON swap per line, gzipped.
+pub fn gzip_jsonl(swaps: &[SwapEvent]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    for swap in swaps {
+        serde_json::to_writer(&mut encoder, swap)?;
+        encoder.write_all(b"\n")?;
+    }
+    Ok(encoder.finish()?)
+}
+
+/// The indexer's end of a running archiver.
+pub struct ArchiveHandle {
+    /// Takes each block range once its swaps are stored, in order.
+    pub ranges: mpsc::UnboundedSender<(u64, u64)>,
+    /// Charged with windows waiting to be uploaded; the indexer pauses while it is exhausted.
+    pub budget: Arc<MemoryBudget>,
+}
+
+/// Starts the archiver `ARCHIVE_S3_BUCKET` asks for, if any.
+pub async fn from_config(config: &Config, database: &Database) -> Result<Option<ArchiveHandle>> {
+    let Some(bucket) = &config.archive_s3_bucket else {
+        return Ok(None);
+    };
+
+    #[cfg(feature = "s3")]
+    {
+        let store = Arc::new(S3Store::new(bucket.clone(), config.archive_s3_endpoint.clone()).await);
+        let budget = Arc::new(MemoryBudget::from_mb(config.memory_budget_mb).named("archive"));
+        let (ranges, _) = Archiver::spawn(store, database.clone(), budget.clone(), ArchivePolicy::from_config(config));
+        info!("Archiving swaps to bucket {} in windows of {} blocks", bucket, config.archive_window_blocks);
+        Ok(Some(ArchiveHandle { ranges, budget }))
+    }
+    #[cfg(not(feature = "s3"))]
+    {
+        let _ = database;
+        anyhow::bail!("ARCHIVE_S3_BUCKET={} needs a build with the s3 feature", bucket)
+    }
+}
+
+/// Uploads the stored swaps of each completed block window as one object.
+///
+/// A window is sealed once the indexer reports a range reaching its last block, then read
+/// back from the database, so it is whole even when an earlier run indexed its start.
+/// Windows listed in `archived_ranges` are skipped. Uploads retry with backoff in the
+/// background; until one succeeds its swaps stay charged to the archive's memory budget.
+pub struct Archiver<S: ?Sized> {
+    store: Arc<S>,
+    database: Database,
+    budget: Arc<MemoryBudget>,
+    policy: ArchivePolicy,
+    // Start of the oldest window not sealed yet
+    next_window: Option<u64>,
+    uploads: JoinSet<()>,
+}
+
+impl<S: ObjectStore + ?Sized + 'static> Archiver<S> {
+    /// Runs the archiver on its own task. Once the sender is dropped, the task finishes the
+    /// pending uploads and exits.
+    pub fn spawn(
+        store: Arc<S>,
+        database: Database,
+        budget: Arc<MemoryBudget>,
+        policy: ArchivePolicy,
+    ) -> (mpsc::UnboundedSender<(u64, u64)>, JoinHandle<()>) {
+        let (tx, mut rx) = mpsc::unbounded_channel::<(u64, u64)>();
+        let mut archiver = Self { store, database, budget, policy, next_window: None, uploads: JoinSet::new() };
+
+        let task = tokio::spawn(async move {
+            while let Some((from_block, to_block)) = rx.recv().await {
+                // A window that fails to seal is tried again after the next range
+                if let Err(e) = archiver.range_indexed(from_block, to_block).await {
+                    error!("Error archiving blocks up to {}: {}", to_block, e);
+                }
+            }
+            while archiver.uploads.join_next().await.is_some() {}
+        });
+
+        (tx, task)
+    }
+
+    async fn range_indexed(&mut self, from_block: u64, to_block: u64) -> Result<()> {
+        while self.uploads.try_join_next().is_some() {}
+
+        let window_blocks = self.policy.window_blocks;
+        let mut next = *self.next_window.get_or_insert(window_of(from_block, window_blocks).0);
+        while next + window_blocks - 1 <= to_block {
+            self.seal(next, next + window_blocks - 1).await?;
+            next += window_blocks;
+            self.next_window = Some(next);
+        }
+        Ok(())
+    }
+
+    async fn seal(&mut self, from_block: u64, to_block: u64) -> Result<()> {
+        let chain_id = self.policy.chain_id;
+        if self.database.is_range_archived(chain_id, from_block as i64, to_block as i64).await? {
+            debug!("Blocks {} to {} are already archived", from_block, to_block);
+            return Ok(());
+        }
+
+        let swaps = self.database.get_swaps_in_block_range(chain_id, from_block as i64, to_block as i64).await?;
+        let Some(first) = swaps.first() else {
+            return Ok(());
+        };
+        let key = object_key(chain_id, from_block, to_block, first.timestamp);
+
+        let bytes = MemoryBudget::estimate_batch_bytes(&swaps);
+        self.budget.charge(bytes);
+        let (store, database, budget, policy) =
+            (self.store.clone(), self.database.clone(), self.budget.clone(), self.policy);
+        self.uploads.spawn(async move {
+            upload(store.as_ref(), &database, &policy, &key, (from_block, to_block), &swaps).await;
+            budget.release(bytes);
+        });
+        Ok(())
+    }
+}
+
+async fn upload<S: ObjectStore + ?Sized>(
+    store: &S,
+    database: &Database,
+    policy: &ArchivePolicy,
+    key: &str,
+    (from_block, to_block): (u64, u64),
+    swaps: &[SwapEvent],
+) {
+    let body = match gzip_jsonl(swaps) {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Error encoding archive object {}: {}", key, e);
+            return;
+        }
+    };
+
+    let mut delay = policy.retry_initial_delay;
+    while let Err(e) = store.put_object(key, body.clone()).await {
+        warn!("Uploading {} failed, retrying in {:?}: {}", key, delay, e);
+        sleep(delay).await;
+        delay = (delay * 2).min(policy.retry_max_delay);
+    }
+
+    // Unrecorded, the window is uploaded again after a restart, to the same key
+    if let Err(e) = database
+        .record_archived_range(policy.chain_id, from_block as i64, to_block as i64, key, swaps.len() as i64)
+        .await
+    {
+        warn!("Error recording archived blocks {} to {}: {}", from_block, to_block, e);
+    }
+    info!("Archived {} swaps to {}", swaps.len(), key);
+}
+
+/// An S3 bucket, or one on an S3-compatible store such as MinIO.
+#[cfg(feature = "s3")]
+pub struct S3Store {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+}
+
+#[cfg(feature = "s3")]
+impl S3Store {
+    /// Credentials and region come from the usual `AWS_*` variables and profiles.
+    pub async fn new(bucket: String, endpoint: Option<String>) -> Self {
+        let shared = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+        let mut config = aws_sdk_s3::config::Builder::from(&shared);
+        if let Some(endpoint) = endpoint {
+            // S3-compatible stores mostly serve path-style requests only
+            config = config.endpoint_url(endpoint).force_path_style(true);
+        }
+        Self { client: aws_sdk_s3::Client::from_conf(config.build()), bucket }
+    }
+}
+
+#[cfg(feature = "s3")]
+#[async_trait]
+impl ObjectStore for S3Store {
+    async fn put_object(&self, key: &str, body: Vec<u8>) -> Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type("application/gzip")
+            .body(aws_sdk_s3::primitives::ByteStream::from(body))
+            .send()
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", aws_sdk_s3::error::DisplayErrorContext(e)))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn test_windows_are_aligned() {
+        assert_eq!(window_of(0, 1000), (0, 999));
+        assert_eq!(window_of(999, 1000), (0, 999));
+        assert_eq!(window_of(1_234_567, 1000), (1_234_000, 1_234_999));
+        assert_eq!(window_of(7, 1), (7, 7));
+    }
+
+    #[test]
+    fn test_object_key_uses_utc_date() {
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(951_782_400), "2000-02-29");
+        // Just before midnight UTC on new year's eve
+        assert_eq!(utc_date(1_735_689_599), "2024-12-31");
+        assert_eq!(utc_date(-1), "1969-12-31");
+        assert_eq!(object_key(2741, 1000, 1999, 1_700_000_000), "chain=2741/date=2023-11-14/swaps-1000-1999.jsonl.gz");
+    }
+
+    #[test]
+    fn test_gzip_jsonl_round_trip() {
+        let swaps: Vec<SwapEvent> = (0..3)
+            .map(|log_index| {
+                SwapEvent::new(
+                    format!("0x{:064x}", log_index),
+                    "0x00000000000000000000000000000000000000aa".to_string(),
+                    "token0".to_string(),
+                    "token1".to_string(),
+                    1000,
+                    950,
+                    1_700_000_000,
+                    100,
+                    log_index,
+                    2741,
+                )
+            })
+            .collect();
+
+        let mut jsonl = String::new();
+        GzDecoder::new(gzip_jsonl(&swaps).unwrap().as_slice()).read_to_string(&mut jsonl).unwrap();
+        let decoded: Vec<SwapEvent> = jsonl.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(decoded, swaps);
+    }
+}
@@ -0,0 +1,339 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use ethers::providers::{JsonRpcClient, JsonRpcError, ProviderError, PubsubClient, RpcError};
+use ethers::types::U256;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+use crate::config::Config;
+use crate::metrics;
+
+/// Methods collected into batches; everything else, subscriptions included, goes straight
+/// to the wrapped transport.
+pub const BATCHED_METHODS: &[&str] = &["eth_call", "eth_getBlockByNumber"];
+
+/// One request of a batch.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RpcCall {
+    pub method: String,
+    pub params: Value,
+}
+
+/// Sends a JSON-RPC batch, answering each call in request order with its result or its own
+/// error. An `Err` fails the whole batch.
+#[async_trait]
+pub trait BatchTransport: Send + Sync {
+    async fn send_batch(&self, calls: &[RpcCall]) -> Result<Vec<Result<Value, JsonRpcError>>>;
+}
+
+/// JSON-RPC batches POSTed to an HTTP endpoint; WebSocket transports cannot send them.
+#[derive(Debug, Clone)]
+pub struct HttpBatchTransport {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl HttpBatchTransport {
+    pub fn new(url: &str) -> Self {
+        Self { http: reqwest::Client::new(), url: url.to_string() }
+    }
+}
+
+#[derive(Deserialize)]
+struct BatchResponse {
+    id: usize,
+    #[serde(default)]
+    result: Value,
+    error: Option<JsonRpcError>,
+}
+
+#[async_trait]
+impl BatchTransport for HttpBatchTransport {
+    async fn send_batch(&self, calls: &[RpcCall]) -> Result<Vec<Result<Value, JsonRpcError>>> {
+        let body: Vec<Value> = calls
+            .iter()
+            .enumerate()
+            .map(|(id, call)| json!({ "jsonrpc": "2.0", "id": id, "method": call.method, "params": call.params }))
+            .collect();
+        let responses: Vec<BatchResponse> = self.http.post(&self.url).json(&body).send().await?.error_for_status()?.json().await?;
+
+        // Nodes may answer a batch in any order
+        let mut results = vec![None; calls.len()];
+        for response in responses {
+            if let Some(slot) = results.get_mut(response.id) {
+                *slot = Some(match response.error {
+                    Some(error) => Err(error),
+                    None => Ok(response.result),
+                });
+            }
+        }
+        Ok(results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| {
+                    Err(JsonRpcError { code: -32603, message: "no response in the batch".to_string(), data: None })
+                })
+            })
+            .collect())
+    }
+}
+
+/// How long a batch waits for more calls, and how many it holds at most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchSettings {
+    pub window: Duration,
+    pub max_batch_len: usize,
+}
+
+impl BatchSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            window: Duration::from_millis(config.rpc_batch_window_ms),
+            max_batch_len: config.rpc_batch_max_size.max(1),
+        }
+    }
+}
+
+/// Error of a `BatchingProvider` request.
+#[derive(Debug)]
+pub enum BatchingError {
+    /// From the wrapped transport.
+    Inner(ProviderError),
+    /// The node answered this call of a batch with an error.
+    JsonRpc(JsonRpcError),
+    Serde(serde_json::Error),
+    /// The batch holding the call failed as a whole.
+    Batch(String),
+}
+
+impl fmt::Display for BatchingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Inner(e) => write!(f, "{}", e),
+            Self::JsonRpc(e) => write!(f, "{}", e),
+            Self::Serde(e) => write!(f, "{}", e),
+            Self::Batch(message) => write!(f, "JSON-RPC batch failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for BatchingError {}
+
+impl RpcError for BatchingError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Inner(e) => e.as_error_response(),
+            Self::JsonRpc(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Inner(e) => e.as_serde_error(),
+            Self::Serde(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<BatchingError> for ProviderError {
+    fn from(error: BatchingError) -> Self {
+        match error {
+            BatchingError::Inner(e) => e,
+            other => ProviderError::JsonRpcClientError(Box::new(other)),
+        }
+    }
+}
+
+struct QueuedCall {
+    call: RpcCall,
+    reply: oneshot::Sender<Result<Value, BatchingError>>,
+}
+
+impl Debug for QueuedCall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueuedCall").field("call", &self.call).finish()
+    }
+}
+
+/// Wraps a transport, collecting `BATCHED_METHODS` requests issued within a window into
+/// one JSON-RPC batch on a `BatchTransport`. Each caller gets its own result or error.
+/// Against a rate-limited endpoint a batch is a single request.
+#[derive(Debug)]
+pub struct BatchingProvider<P> {
+    inner: P,
+    // None when batching is off and every request goes to `inner`
+    calls: Option<mpsc::UnboundedSender<QueuedCall>>,
+}
+
+impl<P> BatchingProvider<P> {
+    /// Sends every request through `inner`.
+    pub fn passthrough(inner: P) -> Self {
+        Self { inner, calls: None }
+    }
+
+    /// Batches through `transport`; must be called inside a Tokio runtime.
+    pub fn new<T: BatchTransport + 'static>(inner: P, transport: T, settings: BatchSettings) -> Self {
+        let (calls, queued) = mpsc::unbounded_channel();
+        tokio::spawn(run_batches(Arc::new(transport), settings, queued));
+        Self { inner, calls: Some(calls) }
+    }
+}
+
+// Collects calls until the window closes or the batch is full, then sends it; ends once
+// the provider is dropped
+async fn run_batches<T: BatchTransport + 'static>(
+    transport: Arc<T>,
+    settings: BatchSettings,
+    mut queued: mpsc::UnboundedReceiver<QueuedCall>,
+) {
+    while let Some(first) = queued.recv().await {
+        let mut batch = vec![first];
+        let deadline = Instant::now() + settings.window;
+        while batch.len() < settings.max_batch_len {
+            match tokio::time::timeout_at(deadline, queued.recv()).await {
+                Ok(Some(call)) => batch.push(call),
+                _ => break,
+            }
+        }
+        metrics::RPC_BATCH_FILL_RATIO.observe(batch.len() as f64 / settings.max_batch_len as f64);
+        tokio::spawn(send_batch(transport.clone(), batch));
+    }
+}
+
+async fn send_batch<T: BatchTransport>(transport: Arc<T>, batch: Vec<QueuedCall>) {
+    let (calls, replies): (Vec<RpcCall>, Vec<_>) = batch.into_iter().map(|queued| (queued.call, queued.reply)).unzip();
+    let results = match transport.send_batch(&calls).await {
+        Ok(results) if results.len() == calls.len() => results,
+        Ok(results) => {
+            let message = format!("{} answers to {} calls", results.len(), calls.len());
+            return fail_batch(replies, &message);
+        }
+        Err(e) => return fail_batch(replies, &e.to_string()),
+    };
+
+    metrics::RPC_BATCHES.with_label_values(&["sent"]).inc();
+    for (reply, result) in replies.into_iter().zip(results) {
+        // The caller may have given up on its request
+        let _ = reply.send(result.map_err(BatchingError::JsonRpc));
+    }
+}
+
+fn fail_batch(replies: Vec<oneshot::Sender<Result<Value, BatchingError>>>, message: &str) {
+    metrics::RPC_BATCHES.with_label_values(&["failed"]).inc();
+    for reply in replies {
+        let _ = reply.send(Err(BatchingError::Batch(message.to_string())));
+    }
+}
+
+#[async_trait]
+impl<P: JsonRpcClient> JsonRpcClient for BatchingProvider<P> {
+    type Error = BatchingError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, BatchingError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let calls = match &self.calls {
+            Some(calls) if BATCHED_METHODS.contains(&method) => calls,
+            _ => return self.inner.request(method, params).await.map_err(|e| BatchingError::Inner(e.into())),
+        };
+
+        let call = RpcCall { method: method.to_string(), params: serde_json::to_value(params).map_err(BatchingError::Serde)? };
+        let (reply, result) = oneshot::channel();
+        calls
+            .send(QueuedCall { call, reply })
+            .map_err(|_| BatchingError::Batch("the batching task stopped".to_string()))?;
+        let value = result.await.map_err(|_| BatchingError::Batch("the batch was dropped".to_string()))??;
+        serde_json::from_value(value).map_err(BatchingError::Serde)
+    }
+}
+
+impl<P: PubsubClient> PubsubClient for BatchingProvider<P> {
+    type NotificationStream = P::NotificationStream;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, BatchingError> {
+        self.inner.subscribe(id).map_err(|e| BatchingError::Inner(e.into()))
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), BatchingError> {
+        self.inner.unsubscribe(id).map_err(|e| BatchingError::Inner(e.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockChain;
+    use ethers::providers::Middleware;
+    use std::sync::Mutex;
+
+    // Echoes each call's params; calls whose first param is "revert" fail on their own
+    #[derive(Clone, Default)]
+    struct MockTransport {
+        batch_lens: Arc<Mutex<Vec<usize>>>,
+    }
+
+    #[async_trait]
+    impl BatchTransport for MockTransport {
+        async fn send_batch(&self, calls: &[RpcCall]) -> Result<Vec<Result<Value, JsonRpcError>>> {
+            self.batch_lens.lock().unwrap().push(calls.len());
+            Ok(calls
+                .iter()
+                .map(|call| match call.params[0].as_str() {
+                    Some("revert") => Err(JsonRpcError { code: 3, message: "execution reverted".to_string(), data: None }),
+                    _ => Ok(call.params.clone()),
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_calls_share_batches() {
+        let transport = MockTransport::default();
+        let settings = BatchSettings { window: Duration::from_millis(50), max_batch_len: 5 };
+        let provider = Arc::new(BatchingProvider::new(MockChain::new(0), transport.clone(), settings));
+
+        // 23 calls issued together go out as ceil(23 / 5) batches
+        let requests = (0..23).map(|i| {
+            let provider = provider.clone();
+            tokio::spawn(async move { provider.request::<_, Value>("eth_call", (format!("call {}", i), "latest")).await })
+        });
+        let results = futures::future::join_all(requests).await;
+        for (i, result) in results.into_iter().enumerate() {
+            assert_eq!(result.unwrap().unwrap(), json!([format!("call {}", i), "latest"]));
+        }
+        let mut batch_lens = transport.batch_lens.lock().unwrap().clone();
+        batch_lens.sort();
+        assert_eq!(batch_lens, vec![3, 5, 5, 5, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_errors_reach_only_their_caller() {
+        let transport = MockTransport::default();
+        let settings = BatchSettings { window: Duration::from_millis(10), max_batch_len: 10 };
+        let chain = MockChain::new(7);
+        let provider = BatchingProvider::new(chain.clone(), transport.clone(), settings);
+
+        let (reverted, answered) = tokio::join!(
+            provider.request::<_, Value>("eth_call", ("revert", "latest")),
+            provider.request::<_, Value>("eth_getBlockByNumber", ("0x1", false)),
+        );
+        assert_eq!(reverted.unwrap_err().as_error_response().unwrap().message, "execution reverted");
+        assert_eq!(answered.unwrap(), json!(["0x1", false]));
+        assert_eq!(*transport.batch_lens.lock().unwrap(), vec![2]);
+
+        // Other methods bypass the batches
+        let provider = ethers::providers::Provider::new(provider);
+        assert_eq!(provider.get_block_number().await.unwrap().as_u64(), 7);
+        assert_eq!(chain.requests(), vec!["eth_blockNumber"]);
+    }
+}
@@ -0,0 +1,236 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tokio::sync::Notify;
+
+use crate::metrics::{BUFFERED_SWAP_BYTES, MEMORY_BUDGET_SUSPENDED};
+use crate::types::SwapEvent;
+
+// Once suspended, pulling resumes only after usage falls below this share of the budget
+const LOW_WATER_PERCENT: usize = 75;
+
+/// Tracks the approximate memory held by decoded events that are waiting to be written.
+///
+/// The decoder charges each buffered batch and checks `wait_for_capacity` before pulling
+/// the next block range; the writer releases the batch once it is stored. Exceeding the
+/// budget suspends the decoder until the writer drains usage below the low-water mark.
+pub struct MemoryBudget {
+    name: &'static str,
+    budget_bytes: usize,
+    low_water_bytes: usize,
+    in_flight_bytes: AtomicUsize,
+    suspended: AtomicBool,
+    drained: Notify,
+}
+
+impl MemoryBudget {
+    pub fn new(budget_bytes: usize) -> Self {
+        Self {
+            name: "writer",
+            budget_bytes,
+            low_water_bytes: budget_bytes * LOW_WATER_PERCENT / 100,
+            in_flight_bytes: AtomicUsize::new(0),
+            suspended: AtomicBool::new(false),
+            drained: Notify::new(),
+        }
+    }
+
+    pub fn from_mb(budget_mb: usize) -> Self {
+        Self::new(budget_mb * 1024 * 1024)
+    }
+
+    /// Sets the `budget` label of the exported gauges; defaults to "writer".
+    pub fn named(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Approximate heap + inline cost of a decoded swap.
+    pub fn estimate_swap_bytes(swap: &SwapEvent) -> usize {
+        std::mem::size_of::<SwapEvent>()
+            + swap.tx_hash.capacity()
+            + swap.pool_address.capacity()
+            + swap.token_in.capacity()
+            + swap.token_out.capacity()
+    }
+
+    pub fn estimate_batch_bytes(swaps: &[SwapEvent]) -> usize {
+        swaps.iter().map(Self::estimate_swap_bytes).sum()
+    }
+
+    pub fn charge(&self, bytes: usize) {
+        let in_flight = self.in_flight_bytes.fetch_add(bytes, Ordering::SeqCst) + bytes;
+        BUFFERED_SWAP_BYTES.with_label_values(&[self.name]).set(in_flight as f64);
+        if in_flight > self.budget_bytes {
+            self.suspended.store(true, Ordering::SeqCst);
+            MEMORY_BUDGET_SUSPENDED.with_label_values(&[self.name]).set(1.0);
+        }
+    }
+
+    pub fn release(&self, bytes: usize) {
+        // Releasing more than was charged is a bug, but must not wrap the count around
+        let previous = self
+            .in_flight_bytes
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |in_flight| Some(in_flight.saturating_sub(bytes)))
+            .unwrap_or_else(|in_flight| in_flight);
+        debug_assert!(bytes <= previous, "released {} bytes with only {} charged", bytes, previous);
+        let in_flight = previous.saturating_sub(bytes);
+        BUFFERED_SWAP_BYTES.with_label_values(&[self.name]).set(in_flight as f64);
+        if in_flight < self.low_water_bytes && self.suspended.swap(false, Ordering::SeqCst) {
+            MEMORY_BUDGET_SUSPENDED.with_label_values(&[self.name]).set(0.0);
+            self.drained.notify_waiters();
+        }
+    }
+
+    /// Waits until the decoder is allowed to pull another range.
+    pub async fn wait_for_capacity(&self) {
+        loop {
+            let drained = self.drained.notified();
+            tokio::pin!(drained);
+            drained.as_mut().enable();
+
+            if !self.is_suspended() {
+                return;
+            }
+            drained.await;
+        }
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.load(Ordering::SeqCst)
+    }
+
+    /// Bytes currently buffered between decoder and writer, also exported as
+    /// `moonshot_buffered_swap_bytes`.
+    pub fn in_flight_bytes(&self) -> usize {
+        self.in_flight_bytes.load(Ordering::SeqCst)
+    }
+
+    pub fn budget_bytes(&self) -> usize {
+        self.budget_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    fn swap(tx_hash: &str) -> SwapEvent {
+        SwapEvent::new(
+            tx_hash.to_string(),
+            "0xPoolAddress".to_string(),
+            "token0".to_string(),
+            "token1".to_string(),
+            1000,
+            950,
+            1640995200,
+            12345,
+            0,
+            8453,
+        )
+    }
+
+    #[test]
+    fn test_swap_size_estimate() {
+        let event = swap("0x1234567890abcdef");
+        let estimate = MemoryBudget::estimate_swap_bytes(&event);
+
+        assert_eq!(
+            estimate,
+            std::mem::size_of::<SwapEvent>() + 18 + 13 + 6 + 6
+        );
+        assert_eq!(
+            MemoryBudget::estimate_batch_bytes(&[event.clone(), event]),
+            estimate * 2
+        );
+    }
+
+    #[test]
+    fn test_charge_and_release_accounting() {
+        let budget = MemoryBudget::new(1000);
+
+        budget.charge(400);
+        budget.charge(500);
+        assert_eq!(budget.in_flight_bytes(), 900);
+        assert!(!budget.is_suspended());
+
+        budget.charge(200);
+        assert_eq!(budget.in_flight_bytes(), 1100);
+        assert!(budget.is_suspended());
+
+        // Above the low-water mark (750): still suspended
+        budget.release(300);
+        assert_eq!(budget.in_flight_bytes(), 800);
+        assert!(budget.is_suspended());
+
+        budget.release(100);
+        assert_eq!(budget.in_flight_bytes(), 700);
+        assert!(!budget.is_suspended());
+
+        // Back under budget but over low water: not suspended again until exceeded
+        budget.charge(200);
+        assert!(!budget.is_suspended());
+
+        let budget = MemoryBudget::new(100).named("test_gauges");
+        budget.charge(150);
+        let rendered = crate::metrics::render();
+        assert!(rendered.contains(r#"moonshot_buffered_swap_bytes{budget="test_gauges"} 150"#));
+        assert!(rendered.contains(r#"moonshot_memory_budget_suspended{budget="test_gauges"} 1"#));
+        budget.release(150);
+        let rendered = crate::metrics::render();
+        assert!(rendered.contains(r#"moonshot_buffered_swap_bytes{budget="test_gauges"} 0"#));
+        assert!(rendered.contains(r#"moonshot_memory_budget_suspended{budget="test_gauges"} 0"#));
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "released 200 bytes with only 100 charged"))]
+    fn test_release_more_than_charged() {
+        let budget = MemoryBudget::new(1000);
+        budget.charge(100);
+        budget.release(200);
+        assert_eq!(budget.in_flight_bytes(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_suspend_resume_with_slow_writer() {
+        let batch_bytes = 100;
+        let budget = Arc::new(MemoryBudget::new(450));
+        let (tx, mut rx) = mpsc::unbounded_channel::<usize>();
+
+        // Slow fake writer: drains one batch at a time
+        let writer_budget = budget.clone();
+        let writer = tokio::spawn(async move {
+            let mut written = 0;
+            while let Some(bytes) = rx.recv().await {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                writer_budget.release(bytes);
+                written += 1;
+            }
+            written
+        });
+
+        let mut peak = 0;
+        let mut resumed_at = Vec::new();
+        for _ in 0..20 {
+            let was_suspended = budget.is_suspended();
+            budget.wait_for_capacity().await;
+            if was_suspended {
+                resumed_at.push(budget.in_flight_bytes());
+            }
+
+            budget.charge(batch_bytes);
+            peak = peak.max(budget.in_flight_bytes());
+            tx.send(batch_bytes).unwrap();
+        }
+        drop(tx);
+
+        assert_eq!(writer.await.unwrap(), 20);
+        assert_eq!(budget.in_flight_bytes(), 0);
+        // Never more than one batch past the budget
+        assert!(peak <= budget.budget_bytes() + batch_bytes);
+        // The decoder was held back and only resumed below the low-water mark
+        assert!(!resumed_at.is_empty());
+        assert!(resumed_at.iter().all(|&bytes| bytes < 450 * LOW_WATER_PERCENT / 100));
+    }
+}
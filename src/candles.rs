@@ -0,0 +1,260 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use tracing::info;
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::indexer::TOKEN_PRICE_BUCKET_SECS;
+use crate::native_price::NativePrice;
+use crate::types::{normalize_address, BucketSwap, PoolData};
+
+const SECS_PER_DAY: i64 = 86_400;
+
+/// Interval of `rebuild` when none is given, the width of the stored buckets.
+pub const DEFAULT_INTERVAL: &str = "1h";
+
+/// A token's USD price over one bucket of `token_usd_prices`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TokenPriceBucket {
+    pub token_address: String,
+    pub bucket_start: i64,
+    pub price_usd: f64,
+}
+
+/// Tokens whose USD price is known without a pool: stablecoins at one dollar, and the
+/// wrapped native token at its readings in `native_prices`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PriceAnchors {
+    pub wrapped_native: Option<String>,
+    pub stablecoins: Vec<String>,
+}
+
+impl PriceAnchors {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            wrapped_native: config.native_wrapped_token.as_deref().map(normalize_address),
+            stablecoins: config.native_stablecoins.iter().map(|stablecoin| normalize_address(stablecoin)).collect(),
+        }
+    }
+
+    fn is_stablecoin(&self, token: &str) -> bool {
+        self.stablecoins.iter().any(|stablecoin| stablecoin.eq_ignore_ascii_case(token))
+    }
+
+    fn is_native(&self, token: &str) -> bool {
+        self.wrapped_native.as_deref().is_some_and(|native| native.eq_ignore_ascii_case(token))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebuildOptions {
+    pub chain_id: i64,
+    pub pool_address: String,
+    /// Bucket width in seconds, from `parse_interval`.
+    pub interval_secs: i64,
+    /// Defaults to the pool's first swap.
+    pub from_ts: Option<i64>,
+    /// Exclusive; defaults to just after the pool's last swap.
+    pub to_ts: Option<i64>,
+}
+
+/// What `rebuild` replaced, over buckets starting `from_ts <= bucket_start < to_ts`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RebuildReport {
+    pub pool_address: String,
+    pub from_ts: i64,
+    pub to_ts: i64,
+    pub days: u64,
+    pub token_prices: u64,
+    pub native_prices: u64,
+}
+
+/// Seconds of an interval such as `1h`. Only the width prices are stored in is accepted.
+pub fn parse_interval(interval: &str) -> Result<i64> {
+    match interval {
+        "1h" => Ok(TOKEN_PRICE_BUCKET_SECS),
+        _ => bail!("unsupported interval {}: prices are stored in 1h buckets", interval),
+    }
+}
+
+/// A pool token whose USD price the pool gives, from its price in the pool's other token.
+struct Quote {
+    token: String,
+    is_token0: bool,
+    // The other token is the wrapped native token rather than a stablecoin
+    in_native: bool,
+}
+
+/// Tokens the pool prices: each paired with a stablecoin or the wrapped native token,
+/// except stablecoins themselves.
+fn quotes(pool: &PoolData, anchors: &PriceAnchors) -> Vec<Quote> {
+    [(&pool.token0_address, &pool.token1_address, true), (&pool.token1_address, &pool.token0_address, false)]
+        .into_iter()
+        .filter(|(token, _, _)| !anchors.is_stablecoin(token))
+        .filter_map(|(token, other, is_token0)| {
+            let in_native = match (anchors.is_stablecoin(other), anchors.is_native(other)) {
+                (true, _) => false,
+                (false, true) => true,
+                (false, false) => return None,
+            };
+            Some(Quote { token: normalize_address(token), is_token0, in_native })
+        })
+        .collect()
+}
+
+/// Rebuilds the price buckets read from the pool by re-aggregating its swaps, after a change
+/// to how prices are derived: the hourly USD prices of each token it pairs with a stablecoin
+/// or the wrapped native token and, for a wrapped-native/stablecoin pool, its native token
+/// readings. A bucket is priced at the last swap before it ends; native readings are one per
+/// bucket the pool swapped in, at its last swap there.
+///
+/// Works one day at a time, reading each day's swaps and native prices in one query, so
+/// memory stays bounded by a day of buckets whatever the range. Each day's buckets are
+/// replaced in one transaction under the pool's price lock, which live writes of prices
+/// read from the pool wait for. Tokens priced through other pools are left alone.
+pub async fn rebuild(database: &Database, anchors: &PriceAnchors, options: &RebuildOptions) -> Result<RebuildReport> {
+    let interval = options.interval_secs;
+    if interval != TOKEN_PRICE_BUCKET_SECS {
+        bail!("unsupported interval of {} seconds: prices are stored in 1h buckets", interval);
+    }
+    let Some(pool) = database.get_pool(&options.pool_address).await? else {
+        bail!("pool {} not found", options.pool_address);
+    };
+    let (Some(token0_decimals), Some(token1_decimals)) = (pool.token0_decimals, pool.token1_decimals) else {
+        bail!("pool {} has no token decimals to price from", pool.pool_address);
+    };
+    let quotes = quotes(&pool, anchors);
+    if quotes.is_empty() {
+        bail!("pool {} pairs no token with a stablecoin or the wrapped native token", pool.pool_address);
+    }
+    let native_pool = quotes.iter().any(|quote| anchors.is_native(&quote.token) && !quote.in_native);
+
+    let Some((first_swap, last_swap)) = database.get_pool_swap_time_range(&pool.pool_address, options.chain_id).await? else {
+        bail!("pool {} has no swaps to rebuild from", pool.pool_address);
+    };
+    let from_ts = options.from_ts.unwrap_or(first_swap).div_euclid(interval) * interval;
+    let to_ts = options.to_ts.unwrap_or(last_swap + 1);
+    let tokens: Vec<String> = quotes.iter().map(|quote| quote.token.clone()).collect();
+    let mut report = RebuildReport { pool_address: pool.pool_address.clone(), from_ts, to_ts, ..RebuildReport::default() };
+
+    let mut day = from_ts.div_euclid(SECS_PER_DAY) * SECS_PER_DAY;
+    while day < to_ts {
+        let (start, end) = (day.max(from_ts), (day + SECS_PER_DAY).min(to_ts));
+        let swaps = database.get_pool_bucket_swaps(&pool.pool_address, options.chain_id, start, end, interval).await?;
+
+        let mut token_prices = Vec::new();
+        let mut native_prices = Vec::new();
+        for swap in &swaps {
+            let Some(token0_price) = token0_price(&pool, swap, token0_decimals, token1_decimals) else {
+                continue;
+            };
+            for quote in &quotes {
+                let price_in_other = if quote.is_token0 { token0_price } else { 1.0 / token0_price };
+                let other_usd = match quote.in_native {
+                    true => match swap.native_price_usd {
+                        Some(native_price_usd) => native_price_usd,
+                        None => continue,
+                    },
+                    false => 1.0,
+                };
+                let price_usd = price_in_other * other_usd;
+                if !price_usd.is_finite() || price_usd <= 0.0 {
+                    continue;
+                }
+
+                token_prices.push(TokenPriceBucket { token_address: quote.token.clone(), bucket_start: swap.bucket_start, price_usd });
+                if native_pool && anchors.is_native(&quote.token) && swapped_in(swap) {
+                    native_prices.push(NativePrice {
+                        price_usd,
+                        pool_address: pool.pool_address.clone(),
+                        block_number: swap.block_number,
+                        timestamp: swap.timestamp,
+                    });
+                }
+            }
+        }
+
+        database
+            .replace_price_buckets(options.chain_id, &pool.pool_address, start, end, &tokens, &token_prices, &native_prices)
+            .await?;
+        info!("Rebuilt prices of pool {} for the day from {} - {} token buckets, {} native readings",
+              pool.pool_address, day, token_prices.len(), native_prices.len());
+        report.days += 1;
+        report.token_prices += token_prices.len() as u64;
+        report.native_prices += native_prices.len() as u64;
+        day += SECS_PER_DAY;
+    }
+
+    info!("Rebuilt prices of pool {} - {} days, {} token buckets, {} native readings",
+          pool.pool_address, report.days, report.token_prices, report.native_prices);
+    Ok(report)
+}
+
+/// Price of token0 in token1 the swap executed at, decimal adjusted. Swaps record their
+/// tokens either by address or as `token0`/`token1`.
+fn token0_price(pool: &PoolData, swap: &BucketSwap, token0_decimals: i32, token1_decimals: i32) -> Option<f64> {
+    let is_token = |address: &str, name: &str| swap.token_in == name || swap.token_in.eq_ignore_ascii_case(address);
+    let (amount0, amount1) = if is_token(&pool.token0_address, "token0") {
+        (swap.amount_in, swap.amount_out)
+    } else if is_token(&pool.token1_address, "token1") {
+        (swap.amount_out, swap.amount_in)
+    } else {
+        return None;
+    };
+    Some((amount1 / 10f64.powi(token1_decimals)) / (amount0 / 10f64.powi(token0_decimals)))
+}
+
+// Whether the bucket's last swap is its own rather than carried over from an earlier one
+fn swapped_in(swap: &BucketSwap) -> bool {
+    swap.timestamp >= swap.bucket_start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::MOONSHOT;
+
+    fn pool(token0: &str, token1: &str) -> PoolData {
+        PoolData::new("0xpool".to_string(), token0.to_string(), token1.to_string(), 8453, MOONSHOT)
+    }
+
+    #[test]
+    fn test_quotes_against_anchors() {
+        let anchors = PriceAnchors { wrapped_native: Some("0xweth".to_string()), stablecoins: vec!["0xusdc".to_string()] };
+        let summary = |pool: &PoolData| -> Vec<(String, bool, bool)> {
+            quotes(pool, &anchors).into_iter().map(|quote| (quote.token, quote.is_token0, quote.in_native)).collect()
+        };
+
+        // The native pool prices the native token; the stablecoin stays at a dollar
+        assert_eq!(summary(&pool("0xWETH", "0xusdc")), vec![("0xweth".to_string(), true, false)]);
+        assert_eq!(summary(&pool("0xtoken", "0xweth")), vec![("0xtoken".to_string(), true, true)]);
+        assert_eq!(summary(&pool("0xusdc", "0xtoken")), vec![("0xtoken".to_string(), false, false)]);
+        // Neither side has a USD price to start from
+        assert!(summary(&pool("0xtoken", "0xother")).is_empty());
+    }
+
+    #[test]
+    fn test_token0_price_from_either_direction() {
+        let pool = pool("0xweth", "0xusdc");
+        let swap = |token_in: &str, amount_in: f64, amount_out: f64| BucketSwap {
+            bucket_start: 0,
+            token_in: token_in.to_string(),
+            amount_in,
+            amount_out,
+            block_number: 1,
+            timestamp: 0,
+            native_price_usd: None,
+        };
+
+        // 1 WETH for 2000 USDC, and 3000 USDC for 1.5 WETH
+        assert_eq!(token0_price(&pool, &swap("0xWETH", 1e18, 2_000e6), 18, 6), Some(2_000.0));
+        assert_eq!(token0_price(&pool, &swap("token1", 3_000e6, 1.5e18), 18, 6), Some(2_000.0));
+        assert_eq!(token0_price(&pool, &swap("0xother", 1e18, 1e18), 18, 6), None);
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("1h").unwrap(), 3600);
+        assert!(parse_interval("5m").is_err());
+    }
+}
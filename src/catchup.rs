@@ -0,0 +1,321 @@
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::chain::BlockHeaders;
+use crate::config::Config;
+
+// Processing history the catch-up estimate is measured over
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    Normal,
+    /// Far behind the head, e.g. after downtime: bigger ranges, less per-range work.
+    CatchUp,
+}
+
+/// Per-poll indexing settings for a sync mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncSettings {
+    pub batch_size: u64,
+    /// Consecutive ranges indexed per poll before sleeping.
+    pub ranges_per_poll: u64,
+    pub refresh_pool_state: bool,
+    pub log_swaps: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CatchUpPolicy {
+    pub threshold_blocks: u64,
+    pub batch_size: u64,
+    pub catch_up_batch_size: u64,
+    pub catch_up_ranges_per_poll: u64,
+}
+
+impl CatchUpPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        let batch_size = config.batch_size as u64;
+        Self {
+            threshold_blocks: config.catch_up_threshold_blocks,
+            batch_size,
+            catch_up_batch_size: batch_size * config.catch_up_batch_multiplier,
+            catch_up_ranges_per_poll: config.catch_up_ranges_per_poll,
+        }
+    }
+
+    pub fn mode(&self, head: u64, indexed: u64) -> SyncMode {
+        if head.saturating_sub(indexed) > self.threshold_blocks {
+            SyncMode::CatchUp
+        } else {
+            SyncMode::Normal
+        }
+    }
+
+    pub fn settings(&self, mode: SyncMode) -> SyncSettings {
+        match mode {
+            SyncMode::Normal => SyncSettings {
+                batch_size: self.batch_size,
+                ranges_per_poll: 1,
+                refresh_pool_state: true,
+                log_swaps: true,
+            },
+            SyncMode::CatchUp => SyncSettings {
+                batch_size: self.catch_up_batch_size,
+                ranges_per_poll: self.catch_up_ranges_per_poll,
+                refresh_pool_state: false,
+                log_swaps: false,
+            },
+        }
+    }
+
+    /// The ranges to index next, in order and never past `head`.
+    pub fn ranges(&self, head: u64, indexed: u64) -> Vec<(u64, u64)> {
+        let settings = self.settings(self.mode(head, indexed));
+        let mut ranges = Vec::new();
+        let mut from = indexed + 1;
+
+        while from <= head && (ranges.len() as u64) < settings.ranges_per_poll.max(1) {
+            let to = head.min(from + settings.batch_size.max(1) - 1);
+            ranges.push((from, to));
+            from = to + 1;
+        }
+        ranges
+    }
+}
+
+/// Splits `[from, to]` into at most `parts` contiguous ranges whose sizes differ by at most
+/// one block; earlier ranges get the extra blocks.
+pub fn split_range(from: u64, to: u64, parts: usize) -> Vec<(u64, u64)> {
+    if from > to {
+        return Vec::new();
+    }
+
+    let blocks = to - from + 1;
+    let parts = (parts.max(1) as u64).min(blocks);
+    let (size, extra) = (blocks / parts, blocks % parts);
+
+    let mut ranges = Vec::with_capacity(parts as usize);
+    let mut start = from;
+    for part in 0..parts {
+        let end = start + size - 1 + u64::from(part < extra);
+        ranges.push((start, end));
+        start = end + 1;
+    }
+    ranges
+}
+
+/// How far indexing has got and how fast it went over the last minute, shared with the API.
+#[derive(Debug, Default)]
+pub struct SyncProgress {
+    state: Mutex<ProgressState>,
+}
+
+#[derive(Debug, Default)]
+struct ProgressState {
+    // `(completed at, blocks indexed)` per range, oldest first
+    history: VecDeque<(Instant, u64)>,
+    last_processed_block: u64,
+    head_block: u64,
+}
+
+impl SyncProgress {
+    pub fn new(last_processed_block: u64) -> Self {
+        let progress = Self::default();
+        progress.state.lock().unwrap().last_processed_block = last_processed_block;
+        progress
+    }
+
+    pub fn set_head(&self, head_block: u64) {
+        self.state.lock().unwrap().head_block = head_block;
+    }
+
+    /// Records a range of `block_count` blocks completed at `at`, ending at `last_processed_block`.
+    pub fn record(&self, at: Instant, block_count: u64, last_processed_block: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.history.push_back((at, block_count));
+        while state.history.front().is_some_and(|(completed, _)| at.duration_since(*completed) > RATE_WINDOW) {
+            state.history.pop_front();
+        }
+        state.last_processed_block = last_processed_block;
+    }
+
+    /// Blocks per second over the recorded history; `None` with fewer than two ranges.
+    pub fn blocks_per_second(&self) -> Option<f64> {
+        let state = self.state.lock().unwrap();
+        let (first, last) = (state.history.front()?, state.history.back()?);
+        let elapsed = last.0.duration_since(first.0).as_secs_f64();
+        if state.history.len() < 2 || elapsed == 0.0 {
+            return None;
+        }
+
+        // The first range's blocks were indexed before the measured interval began
+        let blocks: u64 = state.history.iter().skip(1).map(|(_, block_count)| block_count).sum();
+        Some(blocks as f64 / elapsed)
+    }
+
+    /// Time to index `blocks_remaining` at the recent rate; `Duration::MAX` while no rate is known.
+    pub fn estimate(&self, blocks_remaining: u64) -> Duration {
+        match self.blocks_per_second() {
+            Some(rate) if rate > 0.0 => Duration::try_from_secs_f64(blocks_remaining as f64 / rate).unwrap_or(Duration::MAX),
+            _ => Duration::MAX,
+        }
+    }
+
+    pub fn last_processed_block(&self) -> u64 {
+        self.state.lock().unwrap().last_processed_block
+    }
+
+    pub fn head_block(&self) -> u64 {
+        self.state.lock().unwrap().head_block
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PollPlan {
+    pub head: u64,
+    pub mode: SyncMode,
+    pub settings: SyncSettings,
+    pub ranges: Vec<(u64, u64)>,
+}
+
+/// Reads the head and decides what the next poll indexes.
+pub async fn plan_poll<H: BlockHeaders + ?Sized>(headers: &H, policy: &CatchUpPolicy, indexed: u64) -> Result<PollPlan> {
+    let head = headers.head_block_number().await?;
+    let mode = policy.mode(head, indexed);
+
+    Ok(PollPlan {
+        head,
+        mode,
+        settings: policy.settings(mode),
+        ranges: policy.ranges(head, indexed),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    fn policy() -> CatchUpPolicy {
+        CatchUpPolicy {
+            threshold_blocks: 1_000,
+            batch_size: 100,
+            catch_up_batch_size: 1_000,
+            catch_up_ranges_per_poll: 4,
+        }
+    }
+
+    #[test]
+    fn test_mode_switches_at_threshold() {
+        let policy = policy();
+
+        assert_eq!(policy.mode(10_000, 9_000), SyncMode::Normal);
+        assert_eq!(policy.mode(10_000, 8_999), SyncMode::CatchUp);
+        assert_eq!(policy.mode(10_000, 10_000), SyncMode::Normal);
+        // An indexer ahead of a lagging RPC node is not behind
+        assert_eq!(policy.mode(10_000, 10_500), SyncMode::Normal);
+    }
+
+    #[test]
+    fn test_catch_up_settings_are_boosted() {
+        let policy = policy();
+        let normal = policy.settings(SyncMode::Normal);
+        let catch_up = policy.settings(SyncMode::CatchUp);
+
+        assert_eq!((normal.batch_size, normal.ranges_per_poll), (100, 1));
+        assert!(normal.refresh_pool_state && normal.log_swaps);
+        assert_eq!((catch_up.batch_size, catch_up.ranges_per_poll), (1_000, 4));
+        assert!(!catch_up.refresh_pool_state && !catch_up.log_swaps);
+    }
+
+    #[test]
+    fn test_ranges_stop_at_head() {
+        let policy = policy();
+
+        assert_eq!(policy.ranges(250, 0), vec![(1, 100)]);
+        assert_eq!(policy.ranges(2_500, 0), vec![(1, 1_000), (1_001, 2_000), (2_001, 2_500)]);
+        assert!(policy.ranges(100, 100).is_empty());
+    }
+
+    #[test]
+    fn test_split_range_covers_every_block_once() {
+        assert_eq!(split_range(1, 10, 3), vec![(1, 4), (5, 7), (8, 10)]);
+        assert_eq!(split_range(100, 199, 4), vec![(100, 124), (125, 149), (150, 174), (175, 199)]);
+        // Never more ranges than blocks, and never an empty one
+        assert_eq!(split_range(5, 6, 4), vec![(5, 5), (6, 6)]);
+        assert_eq!(split_range(5, 5, 0), vec![(5, 5)]);
+        assert!(split_range(6, 5, 4).is_empty());
+    }
+
+    struct FarAheadHeaders {
+        head: u64,
+    }
+
+    #[async_trait]
+    impl BlockHeaders for FarAheadHeaders {
+        async fn head_block_number(&self) -> Result<u64> {
+            Ok(self.head)
+        }
+
+        async fn block_timestamp(&self, block_number: u64) -> Result<u64> {
+            Ok(block_number * 2)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_catches_up_then_resumes_normal_settings() {
+        let policy = CatchUpPolicy { threshold_blocks: 5_000, ..policy() };
+        let headers = FarAheadHeaders { head: 20_000 };
+        let mut indexed = 0;
+        let mut modes = Vec::new();
+
+        loop {
+            let plan = plan_poll(&headers, &policy, indexed).await.unwrap();
+            if plan.ranges.is_empty() {
+                break;
+            }
+            for (from, to) in &plan.ranges {
+                assert_eq!(*from, indexed + 1);
+                assert!(to - from < plan.settings.batch_size);
+                indexed = *to;
+            }
+            modes.push((plan.mode, plan.ranges.len()));
+        }
+
+        assert_eq!(indexed, 20_000);
+        // 4 x 1_000 blocks per poll until within 5_000 of the head, then 100-block polls
+        assert_eq!(modes[..4], [(SyncMode::CatchUp, 4); 4]);
+        assert_eq!(modes[4..], [(SyncMode::Normal, 1); 40]);
+    }
+
+    #[test]
+    fn test_estimate_from_recent_rate() {
+        let progress = SyncProgress::default();
+        let start = Instant::now();
+        assert_eq!(progress.estimate(1_000), Duration::MAX);
+
+        // One 10-block range a second
+        for second in 0..=30 {
+            progress.record(start + Duration::from_secs(second), 10, 10 * second);
+        }
+        assert_eq!(progress.blocks_per_second(), Some(10.0));
+        let estimate = progress.estimate(1_000);
+        assert!(estimate.abs_diff(Duration::from_secs(100)) < Duration::from_secs(1), "{:?}", estimate);
+    }
+
+    #[test]
+    fn test_rate_forgets_history_older_than_a_minute() {
+        let progress = SyncProgress::default();
+        let start = Instant::now();
+
+        // A fast start, then a minute and more at one block a second
+        progress.record(start, 10_000, 10_000);
+        for second in 1..=90 {
+            progress.record(start + Duration::from_secs(second), 1, 10_000 + second);
+        }
+        assert_eq!(progress.blocks_per_second(), Some(1.0));
+        assert_eq!(progress.last_processed_block(), 10_090);
+    }
+}
@@ -0,0 +1,339 @@
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use ethers::providers::{JsonRpcClient, Middleware, Provider};
+use ethers::types::{Filter, Log};
+
+use crate::db::Database;
+use crate::types::GasStats;
+
+/// Static per-chain metadata.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChainInfo {
+    pub chain_id: i64,
+    pub name: &'static str,
+    pub explorer_url: &'static str,
+    /// Typical block time, the default for `SECONDS_PER_BLOCK`.
+    pub seconds_per_block: f64,
+}
+
+/// Abstract, the chain Moonshot runs on and the default `CHAIN_ID`.
+pub const ABSTRACT_CHAIN_ID: i64 = 2741;
+
+const CHAINS: &[ChainInfo] = &[
+    ChainInfo { chain_id: 1, name: "Ethereum", explorer_url: "https://etherscan.io", seconds_per_block: 12.0 },
+    ChainInfo { chain_id: ABSTRACT_CHAIN_ID, name: "Abstract", explorer_url: "https://abscan.org", seconds_per_block: 1.0 },
+    ChainInfo { chain_id: 8453, name: "Base", explorer_url: "https://basescan.org", seconds_per_block: 2.0 },
+];
+
+pub fn chain_info(chain_id: i64) -> Option<&'static ChainInfo> {
+    CHAINS.iter().find(|chain| chain.chain_id == chain_id)
+}
+
+/// The fields kept from a fetched block header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockHeader {
+    pub timestamp: u64,
+    /// In wei; None on chains without an EIP-1559 base fee.
+    pub base_fee_per_gas: Option<i64>,
+    /// Gas used over the gas limit; None when the source only knows timestamps.
+    pub gas_used_ratio: Option<f64>,
+}
+
+/// Minimal view of the chain needed to map timestamps to blocks.
+#[async_trait]
+pub trait BlockHeaders: Send + Sync {
+    async fn head_block_number(&self) -> Result<u64>;
+    async fn block_timestamp(&self, block_number: u64) -> Result<u64>;
+
+    /// The whole header; sources without gas fields report only the timestamp.
+    async fn block_header(&self, block_number: u64) -> Result<BlockHeader> {
+        let timestamp = self.block_timestamp(block_number).await?;
+        Ok(BlockHeader { timestamp, base_fee_per_gas: None, gas_used_ratio: None })
+    }
+}
+
+#[async_trait]
+impl<P: JsonRpcClient> BlockHeaders for Provider<P> {
+    async fn head_block_number(&self) -> Result<u64> {
+        Ok(self.get_block_number().await?.as_u64())
+    }
+
+    async fn block_timestamp(&self, block_number: u64) -> Result<u64> {
+        Ok(self.block_header(block_number).await?.timestamp)
+    }
+
+    async fn block_header(&self, block_number: u64) -> Result<BlockHeader> {
+        let block = self
+            .get_block(block_number)
+            .await?
+            .ok_or_else(|| anyhow!("block {} not found", block_number))?;
+        let gas_used_ratio = match block.gas_limit.is_zero() {
+            true => 0.0,
+            false => block.gas_used.low_u128() as f64 / block.gas_limit.low_u128() as f64,
+        };
+        Ok(BlockHeader {
+            timestamp: block.timestamp.as_u64(),
+            base_fee_per_gas: block.base_fee_per_gas.map(|fee| fee.min(i64::MAX.into()).as_u64() as i64),
+            gas_used_ratio: Some(gas_used_ratio),
+        })
+    }
+}
+
+/// Source of `eth_getLogs` results.
+#[async_trait]
+pub trait LogSource: Send + Sync {
+    async fn logs(&self, filter: &Filter) -> Result<Vec<Log>>;
+}
+
+#[async_trait]
+impl<P: JsonRpcClient> LogSource for Provider<P> {
+    async fn logs(&self, filter: &Filter) -> Result<Vec<Log>> {
+        Ok(self.get_logs(filter).await?)
+    }
+}
+
+/// The logs matching `filter` over `[from_block, to_block]`, in block order, requested
+/// `max_blocks` blocks at a time since many nodes refuse wider `eth_getLogs` ranges.
+pub async fn get_logs_chunked<S: LogSource + ?Sized>(
+    source: &S,
+    filter: &Filter,
+    from_block: u64,
+    to_block: u64,
+    max_blocks: u64,
+) -> Result<Vec<Log>> {
+    let max_blocks = max_blocks.max(1);
+    let mut logs = Vec::new();
+    let mut start = from_block;
+    while start <= to_block {
+        let end = to_block.min(start.saturating_add(max_blocks - 1));
+        let range = filter.clone().from_block(start).to_block(end);
+        logs.extend(source.logs(&range).await?);
+        start = end + 1;
+    }
+    Ok(logs)
+}
+
+/// Serves probed headers from the `blocks` table, fetching and storing misses. The gas
+/// fields of fetched headers go to `gas_stats` at no extra RPC cost.
+pub struct CachedHeaders<'a, H> {
+    inner: &'a H,
+    database: &'a Database,
+    chain_id: i64,
+}
+
+impl<'a, H: BlockHeaders> CachedHeaders<'a, H> {
+    pub fn new(inner: &'a H, database: &'a Database, chain_id: i64) -> Self {
+        Self { inner, database, chain_id }
+    }
+}
+
+#[async_trait]
+impl<H: BlockHeaders> BlockHeaders for CachedHeaders<'_, H> {
+    async fn head_block_number(&self) -> Result<u64> {
+        self.inner.head_block_number().await
+    }
+
+    async fn block_timestamp(&self, block_number: u64) -> Result<u64> {
+        if let Some(timestamp) = self
+            .database
+            .get_block_timestamp(block_number as i64, self.chain_id)
+            .await?
+        {
+            return Ok(timestamp as u64);
+        }
+
+        let header = self.inner.block_header(block_number).await?;
+        self.database
+            .upsert_block(block_number as i64, header.timestamp as i64, self.chain_id)
+            .await?;
+        if let Some(gas_used_ratio) = header.gas_used_ratio {
+            let stats = GasStats {
+                chain_id: self.chain_id,
+                block_number: block_number as i64,
+                timestamp: header.timestamp as i64,
+                base_fee_per_gas: header.base_fee_per_gas,
+                gas_used_ratio,
+                median_priority_fee: None,
+            };
+            self.database.upsert_gas_stats(&stats).await?;
+        }
+        Ok(header.timestamp)
+    }
+}
+
+/// Returns the last block whose timestamp is at or before `timestamp`.
+///
+/// Timestamps past the head resolve to the head block; timestamps before genesis are an
+/// error since no block satisfies the bound. Costs at most `log2(head) + 2` header probes.
+pub async fn block_for_timestamp<H: BlockHeaders + ?Sized>(headers: &H, timestamp: u64) -> Result<u64> {
+    let head = headers.head_block_number().await?;
+    if timestamp >= headers.block_timestamp(head).await? {
+        return Ok(head);
+    }
+
+    let genesis_timestamp = headers.block_timestamp(0).await?;
+    if timestamp < genesis_timestamp {
+        bail!(
+            "timestamp {} is before genesis (block 0 at {})",
+            timestamp,
+            genesis_timestamp
+        );
+    }
+
+    // Invariant: block `low` is at or before the timestamp, block `high` is after it
+    let (mut low, mut high) = (0, head);
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if headers.block_timestamp(mid).await? <= timestamp {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    Ok(low)
+}
+
+/// Returns the first block whose timestamp is at or after `timestamp`, the lower bound of
+/// a time window where `block_for_timestamp` is the upper one.
+///
+/// Timestamps before genesis resolve to block 0; timestamps past the head are an error
+/// since no block satisfies the bound yet.
+pub async fn first_block_at_or_after_timestamp<H: BlockHeaders + ?Sized>(headers: &H, timestamp: u64) -> Result<u64> {
+    if timestamp <= headers.block_timestamp(0).await? {
+        return Ok(0);
+    }
+
+    let head = headers.head_block_number().await?;
+    let head_timestamp = headers.block_timestamp(head).await?;
+    if timestamp > head_timestamp {
+        bail!(
+            "timestamp {} is after the head (block {} at {})",
+            timestamp,
+            head,
+            head_timestamp
+        );
+    }
+
+    // Invariant: block `low` is before the timestamp, block `high` is at or after it
+    let (mut low, mut high) = (0, head);
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if headers.block_timestamp(mid).await? < timestamp {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    Ok(high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Block n is mined at 1_000 + 2n, so odd timestamps fall between blocks
+    struct MockHeaders {
+        head: u64,
+        probes: AtomicUsize,
+    }
+
+    impl MockHeaders {
+        fn new(head: u64) -> Self {
+            Self { head, probes: AtomicUsize::new(0) }
+        }
+    }
+
+    #[async_trait]
+    impl BlockHeaders for MockHeaders {
+        async fn head_block_number(&self) -> Result<u64> {
+            Ok(self.head)
+        }
+
+        async fn block_timestamp(&self, block_number: u64) -> Result<u64> {
+            assert!(block_number <= self.head, "probed past head");
+            self.probes.fetch_add(1, Ordering::SeqCst);
+            Ok(1_000 + 2 * block_number)
+        }
+    }
+
+    // One log per request, tagged with the requested range
+    #[derive(Default)]
+    struct MockLogs {
+        requests: std::sync::Mutex<Vec<(u64, u64)>>,
+    }
+
+    #[async_trait]
+    impl LogSource for MockLogs {
+        async fn logs(&self, filter: &Filter) -> Result<Vec<Log>> {
+            let range = (filter.get_from_block().unwrap().as_u64(), filter.get_to_block().unwrap().as_u64());
+            self.requests.lock().unwrap().push(range);
+            Ok(vec![Log { block_number: Some(range.0.into()), ..Default::default() }])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_log_requests_are_capped() {
+        let source = MockLogs::default();
+
+        let logs = get_logs_chunked(&source, &Filter::new(), 100, 124, 10).await.unwrap();
+
+        assert_eq!(*source.requests.lock().unwrap(), vec![(100, 109), (110, 119), (120, 124)]);
+        let blocks: Vec<_> = logs.iter().map(|log| log.block_number.unwrap().as_u64()).collect();
+        assert_eq!(blocks, vec![100, 110, 120]);
+    }
+
+    #[tokio::test]
+    async fn test_exact_timestamp() {
+        let headers = MockHeaders::new(1_000_000);
+
+        assert_eq!(block_for_timestamp(&headers, 1_000).await.unwrap(), 0);
+        assert_eq!(block_for_timestamp(&headers, 1_010).await.unwrap(), 5);
+        assert_eq!(block_for_timestamp(&headers, 1_000 + 2 * 777_777).await.unwrap(), 777_777);
+    }
+
+    #[tokio::test]
+    async fn test_timestamp_between_blocks() {
+        let headers = MockHeaders::new(1_000_000);
+
+        assert_eq!(block_for_timestamp(&headers, 1_011).await.unwrap(), 5);
+        assert_eq!(block_for_timestamp(&headers, 1_001).await.unwrap(), 0);
+        assert_eq!(
+            block_for_timestamp(&headers, 1_000 + 2 * 999_999 + 1).await.unwrap(),
+            999_999
+        );
+    }
+
+    #[tokio::test]
+    async fn test_timestamp_before_genesis_and_after_head() {
+        let headers = MockHeaders::new(100);
+
+        assert!(block_for_timestamp(&headers, 999).await.is_err());
+        assert_eq!(block_for_timestamp(&headers, 1_200).await.unwrap(), 100);
+        assert_eq!(block_for_timestamp(&headers, u64::MAX).await.unwrap(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_first_block_at_or_after_timestamp() {
+        let headers = MockHeaders::new(100);
+
+        assert_eq!(first_block_at_or_after_timestamp(&headers, 1_010).await.unwrap(), 5);
+        // Between blocks 5 and 6: the window starts at 6, where `block_for_timestamp` gives 5
+        assert_eq!(first_block_at_or_after_timestamp(&headers, 1_011).await.unwrap(), 6);
+        assert_eq!(block_for_timestamp(&headers, 1_011).await.unwrap(), 5);
+        assert_eq!(first_block_at_or_after_timestamp(&headers, 1_001).await.unwrap(), 1);
+        assert_eq!(first_block_at_or_after_timestamp(&headers, 0).await.unwrap(), 0);
+        assert_eq!(first_block_at_or_after_timestamp(&headers, 1_200).await.unwrap(), 100);
+        assert!(first_block_at_or_after_timestamp(&headers, 1_201).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_probe_count_is_bounded() {
+        let headers = MockHeaders::new(1 << 20);
+
+        block_for_timestamp(&headers, 1_000 + 2 * 12_345 + 1).await.unwrap();
+        assert!(headers.probes.load(Ordering::SeqCst) <= 20 + 2);
+    }
+}
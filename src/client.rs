@@ -0,0 +1,182 @@
+use anyhow::Result;
+use futures::stream::{self, Stream, TryStreamExt};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::migration::TokenTimeline;
+use crate::types::{GasStats, PoolAtBlock, ProtocolStats, TokenEvent, TokenTimelinePage};
+
+/// A non-success answer of the API, with the message of its `{"error": ...}` body. It
+/// travels inside `anyhow::Error`; transport and decoding failures do not use it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl ClientError {
+    /// A 4xx: the request was wrong or asked for something the API does not have.
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.status)
+    }
+
+    /// A 5xx: the request may succeed when retried.
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.status)
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.status == StatusCode::NOT_FOUND.as_u16()
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "API answered {}: {}", self.status, self.message)
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: String,
+}
+
+/// Bounds of `IndexerClient::token_timeline`; unset fields use the API's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TimelineFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_ts: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to_ts: Option<i64>,
+    /// Events per page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+}
+
+/// Typed client of the HTTP API in `api`, answering with the types the API serializes.
+#[derive(Debug, Clone)]
+pub struct IndexerClient {
+    http: reqwest::Client,
+    base_url: String,
+    // Sent as `X-Api-Key`, for deployments behind a gateway that checks one
+    api_key: Option<String>,
+}
+
+impl IndexerClient {
+    pub fn new(base_url: &str, api_key: Option<&str>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.map(str::to_string),
+        }
+    }
+
+    /// The pool as of `block`, with its swap totals up to it.
+    pub async fn pool_at_block(&self, pool_address: &str, block: u64, exclude_mev: bool) -> Result<PoolAtBlock> {
+        let url = format!("{}/pools/{}/at/{}", self.base_url, pool_address, block);
+        self.fetch(self.http.get(url).query(&[("exclude_mev", exclude_mev)])).await
+    }
+
+    /// The pool as of the last block at or before `timestamp`.
+    pub async fn pool_at_time(&self, pool_address: &str, timestamp: u64, exclude_mev: bool) -> Result<PoolAtBlock> {
+        let url = format!("{}/pools/{}/at-time/{}", self.base_url, pool_address, timestamp);
+        self.fetch(self.http.get(url).query(&[("exclude_mev", exclude_mev)])).await
+    }
+
+    /// Token metadata plus its curve trades and latest AMM swaps.
+    pub async fn token(&self, token_address: &str) -> Result<TokenTimeline> {
+        self.fetch(self.http.get(format!("{}/tokens/{}", self.base_url, token_address))).await
+    }
+
+    /// One page of the token's timeline, after `cursor` when given.
+    pub async fn token_timeline_page(
+        &self,
+        token_address: &str,
+        filter: &TimelineFilter,
+        cursor: Option<&str>,
+    ) -> Result<TokenTimelinePage> {
+        let mut request = self.http.get(format!("{}/tokens/{}/timeline", self.base_url, token_address)).query(filter);
+        if let Some(cursor) = cursor {
+            request = request.query(&[("cursor", cursor)]);
+        }
+        self.fetch(request).await
+    }
+
+    /// Every event of the token's timeline in chain order, fetching pages as the stream is
+    /// read.
+    pub fn token_timeline<'a>(
+        &'a self,
+        token_address: &'a str,
+        filter: TimelineFilter,
+    ) -> impl Stream<Item = Result<TokenEvent>> + 'a {
+        // `None` once the last page was read, `Some(None)` before the first one
+        let pages = stream::try_unfold(Some(None::<String>), move |cursor| {
+            let filter = filter.clone();
+            async move {
+                let Some(cursor) = cursor else { return Ok::<_, anyhow::Error>(None) };
+                let page = self.token_timeline_page(token_address, &filter, cursor.as_deref()).await?;
+                let events = stream::iter(page.events.into_iter().map(Ok));
+                Ok(Some((events, page.next_cursor.map(Some))))
+            }
+        });
+        pages.try_flatten()
+    }
+
+    /// Dashboard totals of the indexed chain.
+    pub async fn stats(&self) -> Result<ProtocolStats> {
+        self.fetch(self.http.get(format!("{}/stats", self.base_url))).await
+    }
+
+    /// Gas market of blocks with `from_ts <= timestamp < to_ts`.
+    pub async fn gas(&self, from_ts: i64, to_ts: i64) -> Result<Vec<GasStats>> {
+        self.fetch(self.http.get(format!("{}/gas", self.base_url)).query(&[("from_ts", from_ts), ("to_ts", to_ts)])).await
+    }
+
+    /// Queues a pool for the indexer to watch; a 409 `ClientError` if it is watched already.
+    pub async fn watch_pool(&self, pool_address: &str) -> Result<()> {
+        let request = self
+            .http
+            .post(format!("{}/watch", self.base_url))
+            .json(&serde_json::json!({ "pool_address": pool_address }));
+        self.send(request).await?;
+        Ok(())
+    }
+
+    async fn fetch<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    /// Sends the request, turning a non-success status into a `ClientError`.
+    async fn send(&self, mut request: RequestBuilder) -> Result<reqwest::Response> {
+        if let Some(api_key) = &self.api_key {
+            request = request.header("X-Api-Key", api_key);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<ErrorBody>(&body).map_or(body, |body| body.error);
+        Err(ClientError { status: status.as_u16(), message }.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_classes() {
+        let not_found = ClientError { status: 404, message: "pool not found".to_string() };
+        assert!(not_found.is_client_error() && not_found.is_not_found() && !not_found.is_server_error());
+        let unavailable = ClientError { status: 503, message: String::new() };
+        assert!(unavailable.is_server_error() && !unavailable.is_client_error());
+        assert_eq!(not_found.to_string(), "API answered 404: pool not found");
+    }
+}
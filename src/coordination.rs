@@ -0,0 +1,79 @@
+use anyhow::Result;
+
+use crate::db::{AdvisoryLock, Database};
+use crate::dex::DexId;
+use crate::error::IndexerError;
+
+/// Blocks covered by one backfill lock. Backfills lock every span their range touches, so
+/// two backfills conflict when their ranges share a span.
+pub const BACKFILL_LOCK_SPAN: u64 = 100_000;
+
+/// Advisory lock key of `name`: FNV-1a, which unlike `DefaultHasher` is the same in every
+/// build, so processes of different versions agree on it.
+pub(crate) fn lock_key(name: &str) -> i64 {
+    let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3));
+    hash as i64
+}
+
+/// Key of the lock a live indexer holds for the chain and DEX it follows.
+pub fn live_lock_key(chain_id: u64, dex: &DexId) -> i64 {
+    lock_key(&format!("live:{}:{}", chain_id, dex))
+}
+
+/// Key of the lock a price rebuild of the pool holds while it replaces a day of buckets;
+/// live writes of prices read from the pool share it.
+pub fn price_lock_key(chain_id: i64, pool_address: &str) -> i64 {
+    lock_key(&format!("prices:{}:{}", chain_id, pool_address.to_lowercase()))
+}
+
+/// Keys of the `BACKFILL_LOCK_SPAN` spans `[from_block, to_block]` touches.
+pub fn backfill_lock_keys(chain_id: u64, from_block: u64, to_block: u64) -> Vec<i64> {
+    (from_block / BACKFILL_LOCK_SPAN..=to_block / BACKFILL_LOCK_SPAN)
+        .map(|span| lock_key(&format!("backfill:{}:{}", chain_id, span)))
+        .collect()
+}
+
+/// Takes the live indexing lock of the chain and DEX, failing with `IndexerError::Locked`
+/// while another live indexer holds it.
+pub async fn lock_live(database: &Database, chain_id: u64, dex: &DexId) -> Result<AdvisoryLock> {
+    match database.try_advisory_lock(&[live_lock_key(chain_id, dex)]).await? {
+        Some(lock) => Ok(lock),
+        None => Err(IndexerError::Locked { lock: format!("live indexing of {} on chain {}", dex, chain_id) }.into()),
+    }
+}
+
+/// Takes the locks of every span of `[from_block, to_block]`, failing with
+/// `IndexerError::Locked` while another backfill holds any of them.
+pub async fn lock_backfill(database: &Database, chain_id: u64, from_block: u64, to_block: u64) -> Result<AdvisoryLock> {
+    match database.try_advisory_lock(&backfill_lock_keys(chain_id, from_block, to_block)).await? {
+        Some(lock) => Ok(lock),
+        None => Err(IndexerError::Locked {
+            lock: format!("backfill of blocks {} to {} on chain {}", from_block, to_block, chain_id),
+        }
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::MOONSHOT;
+
+    #[test]
+    fn test_lock_keys() {
+        assert_eq!(lock_key(""), 0xcbf2_9ce4_8422_2325_u64 as i64);
+        assert_eq!(live_lock_key(8453, &MOONSHOT), live_lock_key(8453, &MOONSHOT));
+        assert_ne!(live_lock_key(8453, &MOONSHOT), live_lock_key(1, &MOONSHOT));
+        assert_ne!(live_lock_key(8453, &MOONSHOT), live_lock_key(8453, &DexId::new("uniswap-v3").unwrap()));
+
+        assert_eq!(backfill_lock_keys(8453, 150_000, 199_999).len(), 1);
+        let keys = backfill_lock_keys(8453, 99_999, 200_000);
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys[1], backfill_lock_keys(8453, 100_000, 100_000)[0]);
+        assert!(!keys.contains(&live_lock_key(8453, &MOONSHOT)));
+
+        let pool = "0x00000000000000000000000000000000000000AA";
+        assert_eq!(price_lock_key(8453, pool), price_lock_key(8453, &pool.to_lowercase()));
+        assert_ne!(price_lock_key(8453, pool), price_lock_key(1, pool));
+    }
+}
@@ -0,0 +1,64 @@
+use std::future::Future;
+
+/// Header an API request's id is read from and echoed back in.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// Longest incoming id that is kept; longer ones are replaced by a fresh id
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// A fresh request id, a random UUID.
+pub fn generate() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// The caller's id when it is usable in logs and headers, a fresh one otherwise.
+pub fn accept(incoming: Option<&str>) -> String {
+    match incoming.map(str::trim) {
+        Some(id) if is_valid(id) => id.to_string(),
+        _ => generate(),
+    }
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+/// Runs `future` with `request_id` as the current request id.
+pub async fn scope<F: Future>(request_id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+/// Id of the request or command the calling task works for, if any.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accept_keeps_usable_ids() {
+        assert_eq!(accept(Some(" trace-42:a_b.c ")), "trace-42:a_b.c");
+
+        // Missing, empty, overlong or header-breaking ids are replaced
+        for incoming in [None, Some(""), Some("has space"), Some("line\nbreak")] {
+            assert!(uuid::Uuid::parse_str(&accept(incoming)).is_ok(), "{:?} was kept", incoming);
+        }
+        assert_ne!(accept(Some(&"a".repeat(MAX_REQUEST_ID_LEN + 1))).len(), MAX_REQUEST_ID_LEN + 1);
+    }
+
+    #[tokio::test]
+    async fn test_scope_sets_the_current_id() {
+        assert_eq!(current(), None);
+        let inner = scope("outer".to_string(), async { current() }).await;
+        assert_eq!(inner.as_deref(), Some("outer"));
+        assert_eq!(current(), None);
+    }
+}
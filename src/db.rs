@@ -1,15 +1,506 @@
-use sqlx::{PgPool, Row};
-use anyhow::Result;
-use crate::types::{PoolData, SwapEvent};
+use ethers::types::U256;
+use sqlx::postgres::PgRow;
+use serde::Serialize;
+use sqlx::pool::PoolConnection;
+use sqlx::{PgPool, Postgres, Row};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::{bail, Result};
+use crate::candles::TokenPriceBucket;
+use crate::config::Config;
+use crate::coordination;
+use crate::correlation;
+use crate::dex::{DexId, DexRegistry, MAX_DEX_ID_LEN};
+use crate::enrich::UnpricedSwap;
+use crate::error::IndexerError;
+use crate::native_price::NativePrice;
+use crate::lifecycle::PoolStatus;
+use crate::maintenance::{TableHealth, TableReport};
+use crate::metrics;
+use crate::mev::MevType;
+use crate::nonstandard::TokenBehavior;
+use crate::price;
+use crate::types::{
+    BlockGap, BlockStats, BucketSwap, CumulativeVolume, CurveTrade, GasStats, HexBytes, HolderBalance, HolderSnapshot, IndexingStats, PoolBalanceSnapshot, PoolData, PoolFeeRevenue,
+    PoolRank, PoolRankingMetric, PoolStats, PoolUpdatesPage, PricePoint, ProtocolStats, SenderActivity, SwapEvent, SwapSizeDistribution, TickData, TokenData, TokenEvent, TokenMigration, TokenTimelinePage, TradeSide,
+    VolumeBreakdown, WalletPnL, normalize_address,
+};
+use tracing::{debug_span, warn, Instrument};
 
-pub struct Database {
+// Column list for reading pools back; chain_id is INTEGER in the table but i64 in PoolData
+const POOL_COLUMNS: &str = "pool_address, token0_address, token1_address, token0_symbol, token1_symbol, \
+    token0_decimals, token1_decimals, fee_tier, tick_spacing, liquidity, sqrt_price_x96, tick, \
+    chain_id::BIGINT AS chain_id, dex_name, created_at_block, updated_at_block, has_nonstandard_token, \
+    EXTRACT(EPOCH FROM date_trunc('second', created_at))::BIGINT AS created_at, \
+    EXTRACT(EPOCH FROM date_trunc('second', updated_at))::BIGINT AS updated_at";
+
+// Column list for reading curve trades back; amounts are NUMERIC and read as text
+const CURVE_TRADE_COLUMNS: &str = "tx_hash, log_index, block_number, timestamp, chain_id::BIGINT AS chain_id, \
+    curve_address, token_address, trader, side, \
+    token_amount::TEXT AS token_amount, collateral_amount::TEXT AS collateral_amount";
+
+const MIGRATION_COLUMNS: &str = "token_address, chain_id::BIGINT AS chain_id, curve_address, pool_address, \
+    tx_hash, block_number, log_index, timestamp, final_curve_price";
+
+// Column list for reading swaps back; NUMERIC/INTEGER columns are cast to match SwapEvent
+const SWAP_COLUMNS: &str = "tx_hash, pool_address, token_in, token_out, \
+    amount_in::BIGINT AS amount_in, amount_out::BIGINT AS amount_out, \
+    amount_in_usd::FLOAT8 AS amount_in_usd, amount_out_usd::FLOAT8 AS amount_out_usd, \
+    timestamp, block_number, log_index, chain_id::BIGINT AS chain_id, sender_address, recipient_address, \
+    indexed_at, calldata";
+
+// Fewest priced swaps `get_pool_swap_size_distribution` computes percentiles from
+const MIN_DISTRIBUTION_SWAPS: i64 = 10;
+
+// Most swaps `get_swaps_by_amount_range` returns
+const MAX_AMOUNT_RANGE_SWAPS: i64 = 10_000;
+
+// Rows removed per statement by `delete_pool_data`/`delete_token_data`; each batch commits
+// on its own so locks stay short and replicas keep up
+const PURGE_BATCH_SIZE: i64 = 5_000;
+
+// Tables holding a pool's own rows, deleted before the `pools` row itself so an
+// interrupted purge can be run again
+const POOL_DATA_TABLES: &[&str] = &["swaps", "pool_snapshots", "tick_history", "tick_data", "watchlist"];
+
+// Tables recording the DEX of their rows in a `dex_name` column
+const DEX_NAME_TABLES: &[&str] = &["pools", "indexing_stats"];
+
+// Tables holding a token's own rows; holder balances go with their snapshots
+const TOKEN_DATA_TABLES: &[&str] = &["curve_trades", "token_migrations", "token_supply_history", "token_usd_prices"];
+
+// Most points `get_price_at_timestamp_range` samples
+const MAX_PRICE_POINTS: i64 = 1000;
+
+// Most senders `get_swap_count_per_sender` returns
+const MAX_SENDER_ACTIVITY: i64 = 1000;
+
+/// Most blocks `get_block_stats_range` covers at once.
+pub const MAX_BLOCK_STATS_RANGE: i64 = 10_000;
+
+// Order of event kinds sharing a block and log index in a token timeline
+const TIMELINE_POOL_CREATED: i32 = 0;
+const TIMELINE_CURVE_TRADE: i32 = 1;
+const TIMELINE_MIGRATION: i32 = 2;
+const TIMELINE_SWAP: i32 = 3;
+
+/// Position of an event in a token timeline: block, log index, event kind, then the tx hash
+/// (pool address for pool creations) to make it unique. Pages continue after the cursor.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimelineCursor {
+    block_number: i64,
+    log_index: i32,
+    kind: i32,
+    key: String,
+}
+
+impl TimelineCursor {
+    fn start() -> Self {
+        Self { block_number: i64::MIN, log_index: i32::MIN, kind: i32::MIN, key: String::new() }
+    }
+
+    pub fn of(event: &TokenEvent) -> Self {
+        let (block_number, log_index, kind, key) = match event {
+            // Pool creations have no stored log index and go first in their block
+            TokenEvent::PoolCreated(pool) => {
+                (pool.created_at_block.unwrap_or_default(), -1, TIMELINE_POOL_CREATED, &pool.pool_address)
+            }
+            TokenEvent::CurveTrade(trade) => (trade.block_number, trade.log_index, TIMELINE_CURVE_TRADE, &trade.tx_hash),
+            TokenEvent::Migration(migration) => {
+                (migration.block_number, migration.log_index, TIMELINE_MIGRATION, &migration.tx_hash)
+            }
+            TokenEvent::Swap(swap) => (swap.block_number, swap.log_index, TIMELINE_SWAP, &swap.tx_hash),
+        };
+        Self { block_number, log_index, kind, key: key.clone() }
+    }
+
+    pub fn block_number(&self) -> i64 {
+        self.block_number
+    }
+
+    pub fn log_index(&self) -> i32 {
+        self.log_index
+    }
+}
+
+impl fmt::Display for TimelineCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}:{}", self.block_number, self.log_index, self.kind, self.key)
+    }
+}
+
+impl FromStr for TimelineCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(cursor: &str) -> Result<Self> {
+        let parts: Vec<&str> = cursor.splitn(4, ':').collect();
+        if parts.len() != 4 {
+            anyhow::bail!("malformed timeline cursor {:?}", cursor);
+        }
+
+        Ok(Self {
+            block_number: parts[0].parse()?,
+            log_index: parts[1].parse()?,
+            kind: parts[2].parse()?,
+            key: parts[3].to_string(),
+        })
+    }
+}
+
+/// Position of a pool in the updated pools feed: the second it last changed, then its
+/// address. Pages continue after the cursor.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PoolUpdateCursor {
+    updated_at: i64,
+    pool_address: String,
+}
+
+impl PoolUpdateCursor {
+    pub fn of(pool: &PoolData) -> Self {
+        Self { updated_at: pool.updated_at.unwrap_or_default(), pool_address: pool.pool_address.clone() }
+    }
+}
+
+impl fmt::Display for PoolUpdateCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.updated_at, self.pool_address)
+    }
+}
+
+impl FromStr for PoolUpdateCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(cursor: &str) -> Result<Self> {
+        let Some((updated_at, pool_address)) = cursor.split_once(':') else {
+            anyhow::bail!("malformed pool update cursor {:?}", cursor);
+        };
+
+        Ok(Self { updated_at: updated_at.parse()?, pool_address: pool_address.to_string() })
+    }
+}
+
+/// A connection pool that counts the queries sent through it.
+#[derive(Clone)]
+struct CountedPool {
     pool: PgPool,
+    queries: Arc<AtomicU64>,
+}
+
+impl CountedPool {
+    fn new(pool: PgPool) -> Self {
+        Self { pool, queries: Arc::new(AtomicU64::new(0)) }
+    }
+
+    fn get(&self) -> &PgPool {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        &self.pool
+    }
+
+    /// The same database and count behind a fresh pool whose sessions cancel any statement
+    /// still running after `timeout`.
+    fn with_statement_timeout(&self, timeout: Duration) -> Self {
+        let connect_options = (*self.pool.connect_options())
+            .clone()
+            .options([("statement_timeout", timeout.as_millis().to_string())]);
+        Self { pool: self.pool.options().clone().connect_lazy_with(connect_options), queries: self.queries.clone() }
+    }
+}
+
+/// Queries sent to each side of a `Database` so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryCounts {
+    pub reads: u64,
+    pub writes: u64,
+}
+
+/// Rows removed by a purge, per table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PurgeCounts {
+    pub tables: BTreeMap<String, u64>,
+}
+
+impl PurgeCounts {
+    fn add(&mut self, table: &str, rows: u64) {
+        *self.tables.entry(table.to_string()).or_default() += rows;
+    }
+
+    fn merge(&mut self, other: PurgeCounts) {
+        for (table, rows) in other.tables {
+            self.add(&table, rows);
+        }
+    }
+
+    /// Rows removed from `table`, 0 if it was not touched.
+    pub fn get(&self, table: &str) -> u64 {
+        self.tables.get(table).copied().unwrap_or_default()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.tables.values().sum()
+    }
+}
+
+/// Postgres advisory locks held on a connection of their own. They are session-scoped,
+/// so they last until `release` or until the guard is dropped, which closes the
+/// connection rather than return it to the pool still holding them.
+pub struct AdvisoryLock {
+    connection: Option<PoolConnection<Postgres>>,
+    keys: Vec<i64>,
+}
+
+impl AdvisoryLock {
+    pub fn keys(&self) -> &[i64] {
+        &self.keys
+    }
+
+    /// Unlocks every key and hands the connection back to the pool.
+    pub async fn release(mut self) -> Result<()> {
+        if let Some(mut connection) = self.connection.take() {
+            sqlx::query("SELECT pg_advisory_unlock_all()").execute(&mut *connection).await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AdvisoryLock {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            drop(connection.detach());
+        }
+    }
+}
+
+impl fmt::Debug for AdvisoryLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdvisoryLock").field("keys", &self.keys).finish()
+    }
+}
+
+/// Whether `error` is Postgres cancelling a statement, as `statement_timeout` does.
+fn is_query_canceled(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::Database(error)) => error.code().as_deref() == Some(QUERY_CANCELED),
+        _ => false,
+    }
+}
+
+/// Latest indexed block on the write pool and on the read pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ReplicaLag {
+    pub primary_block: i64,
+    pub replica_block: i64,
+}
+
+impl ReplicaLag {
+    /// How many blocks the read pool trails; 0 without a replica.
+    pub fn blocks(&self) -> i64 {
+        (self.primary_block - self.replica_block).max(0)
+    }
+}
+
+/// How long `Database` methods may run, by side, and when one counts as slow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryLimits {
+    /// Query methods; these include the API's analytics.
+    pub read_timeout: Duration,
+    /// Mutations, mostly the indexer's upserts.
+    pub write_timeout: Duration,
+    pub slow_query_threshold: Duration,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self {
+            read_timeout: Duration::from_secs(10),
+            write_timeout: Duration::from_secs(30),
+            slow_query_threshold: Duration::from_millis(500),
+        }
+    }
+}
+
+impl QueryLimits {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            read_timeout: Duration::from_millis(config.database_read_timeout_ms),
+            write_timeout: Duration::from_millis(config.database_write_timeout_ms),
+            slow_query_threshold: Duration::from_millis(config.slow_query_threshold_ms),
+        }
+    }
+}
+
+// How long past its timeout a method is abandoned when Postgres has not cancelled it,
+// e.g. while its connection is still being acquired or between its statements
+const TIMEOUT_GRACE: Duration = Duration::from_secs(1);
+
+// SQLSTATE of a statement cancelled by `statement_timeout`
+const QUERY_CANCELED: &str = "57014";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+}
+
+/// Access to the indexed data. Mutations go to the write pool and query methods (`get_*`,
+/// `is_*`, `count_*`) to the read pool, which is the same database unless a read replica
+/// is configured. Reads from a replica may lag the writes behind them.
+///
+/// Every method runs under its side's timeout from `QueryLimits`, failing with
+/// `IndexerError::Database` when it expires, and is timed in `moonshot_db_query_seconds`
+/// under its own name. Each pool's sessions carry their side's timeout as
+/// `statement_timeout`, so Postgres cancels the statement rather than finish it unobserved.
+/// `init_schema` and `create_views` are not limited, a migration may take a while.
+#[derive(Clone)]
+pub struct Database {
+    writer: CountedPool,
+    reader: CountedPool,
+    // The write pool as connected, without a statement timeout
+    schema: CountedPool,
+    limits: QueryLimits,
+    dexes: DexRegistry,
 }
 
 impl Database {
     pub async fn new(database_url: &str) -> Result<Self> {
-        let pool = PgPool::connect(database_url).await?;
-        Ok(Self { pool })
+        Self::with_read_url(database_url, None).await
+    }
+
+    /// Sends query methods to `read_url`, typically a replica, when given; otherwise
+    /// everything shares one pool.
+    pub async fn with_read_url(database_url: &str, read_url: Option<&str>) -> Result<Self> {
+        let writer = CountedPool::new(PgPool::connect(database_url).await?);
+        let reader = match read_url {
+            Some(read_url) => CountedPool::new(PgPool::connect(read_url).await?),
+            None => CountedPool::new(writer.pool.clone()),
+        };
+        let database = Self {
+            writer: writer.clone(),
+            reader,
+            schema: writer,
+            limits: QueryLimits::default(),
+            dexes: DexRegistry::presets(),
+        };
+        Ok(database.with_limits(QueryLimits::default()))
+    }
+
+    /// Applies `limits`, reconnecting each pool with its side's statement timeout.
+    pub fn with_limits(mut self, limits: QueryLimits) -> Self {
+        self.writer = self.schema.with_statement_timeout(limits.write_timeout);
+        self.reader = self.reader.with_statement_timeout(limits.read_timeout);
+        self.limits = limits;
+        self
+    }
+
+    /// Checks the DEX of written rows against `dexes` rather than the presets alone.
+    pub fn with_dexes(mut self, dexes: DexRegistry) -> Self {
+        self.dexes = dexes;
+        self
+    }
+
+    pub fn dexes(&self) -> &DexRegistry {
+        &self.dexes
+    }
+
+    /// Runs the statements of method `statement` under its side's timeout, recording how
+    /// long they took and logging them when slow. They run in a `db` span carrying the id of
+    /// the API request or command they serve, if any.
+    async fn timed<T>(&self, statement: &'static str, access: Access, query: impl Future<Output = Result<T>>) -> Result<T> {
+        let timeout = match access {
+            Access::Read => self.limits.read_timeout,
+            Access::Write => self.limits.write_timeout,
+        };
+        let request_id = correlation::current();
+        let span = debug_span!("db", statement, request_id = request_id.as_deref());
+        let started = Instant::now();
+        let result = tokio::time::timeout(timeout + TIMEOUT_GRACE, query).instrument(span.clone()).await;
+        let elapsed = started.elapsed();
+
+        metrics::DB_QUERY_SECONDS.with_label_values(&[statement]).observe(elapsed.as_secs_f64());
+        if elapsed >= self.limits.slow_query_threshold {
+            metrics::DB_SLOW_QUERIES.with_label_values(&[statement]).inc();
+            span.in_scope(|| warn!("Slow query {} took {} ms", statement, elapsed.as_millis()));
+        }
+        let timed_out = || IndexerError::Database { statement, timeout, retryable: access == Access::Write }.into();
+        match result {
+            Ok(Err(error)) if is_query_canceled(&error) => Err(timed_out()),
+            Ok(result) => result,
+            Err(_) => Err(timed_out()),
+        }
+    }
+
+    pub fn query_counts(&self) -> QueryCounts {
+        QueryCounts {
+            reads: self.reader.queries.load(Ordering::Relaxed),
+            writes: self.writer.queries.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The block of the latest indexed swap of `chain_id` as each pool sees it.
+    pub async fn replica_lag(&self, chain_id: i64) -> Result<ReplicaLag> {
+        self.timed("replica_lag", Access::Read, async {
+            let query = "SELECT COALESCE(MAX(block_number), 0) FROM swaps WHERE chain_id = $1";
+            let primary_block = sqlx::query_scalar(query).bind(chain_id).fetch_one(self.writer.get()).await?;
+            let replica_block = sqlx::query_scalar(query).bind(chain_id).fetch_one(self.reader.get()).await?;
+            Ok(ReplicaLag { primary_block, replica_block })
+        })
+        .await
+    }
+
+    /// Which of `tables` do not exist yet, i.e. were never created by `init_schema`.
+    pub async fn get_missing_tables(&self, tables: &[&str]) -> Result<Vec<String>> {
+        self.timed("get_missing_tables", Access::Read, async {
+            let missing = sqlx::query_scalar(
+                "SELECT name FROM UNNEST($1::TEXT[]) WITH ORDINALITY AS t(name, position) WHERE to_regclass(name) IS NULL ORDER BY position",
+            )
+            .bind(tables)
+            .fetch_all(self.reader.get())
+            .await?;
+            Ok(missing)
+        })
+        .await
+    }
+
+    /// Inserts and deletes a sentinel `sync_cursors` row, failing if the connection may not
+    /// write.
+    pub async fn probe_write(&self) -> Result<()> {
+        self.timed("probe_write", Access::Write, async {
+            let mut tx = self.writer.get().begin().await?;
+            sqlx::query("INSERT INTO sync_cursors (name, chain_id, position) VALUES ('doctor-probe', -1, 0) ON CONFLICT DO NOTHING")
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM sync_cursors WHERE name = 'doctor-probe' AND chain_id = -1")
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Takes every advisory lock in `keys` on one connection, or none of them: `None` when
+    /// another session holds any of them.
+    pub async fn try_advisory_lock(&self, keys: &[i64]) -> Result<Option<AdvisoryLock>> {
+        self.timed("try_advisory_lock", Access::Write, async {
+            let mut lock = AdvisoryLock { connection: Some(self.writer.get().acquire().await?), keys: keys.to_vec() };
+            let connection = lock.connection.as_mut().expect("connection is taken only on release");
+            for key in keys {
+                let acquired: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+                    .bind(key)
+                    .fetch_one(&mut **connection)
+                    .await?;
+                if !acquired {
+                    lock.release().await?;
+                    return Ok(None);
+                }
+            }
+            Ok(Some(lock))
+        })
+        .await
     }
 
     pub async fn init_schema(&self) -> Result<()> {
@@ -33,11 +524,14 @@ impl Database {
                 created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
                 updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
                 chain_id INTEGER NOT NULL,
-                dex_name VARCHAR(50) DEFAULT 'moonshot'
+                dex_name VARCHAR(50) DEFAULT 'moonshot',
+                created_at_block BIGINT,
+                status VARCHAR(16) NOT NULL DEFAULT 'active',
+                tracked BOOLEAN NOT NULL DEFAULT TRUE
             )
             "#,
         )
-        .execute(&self.pool)
+        .execute(self.schema.get())
         .await?;
 
         // Create swaps table
@@ -57,189 +551,4076 @@ impl Database {
                 block_number BIGINT NOT NULL,
                 log_index INTEGER NOT NULL,
                 chain_id INTEGER NOT NULL,
+                sender_address VARCHAR(42),
                 created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
                 UNIQUE(tx_hash, log_index, chain_id)
             )
             "#,
         )
-        .execute(&self.pool)
+        .execute(self.schema.get())
         .await?;
 
-        // Create indexes for better query performance
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_pools_address ON pools(pool_address)")
-            .execute(&self.pool)
-            .await?;
-        
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_pools_tokens ON pools(token0_address, token1_address)")
-            .execute(&self.pool)
-            .await?;
-        
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_tx_hash ON swaps(tx_hash)")
-            .execute(&self.pool)
-            .await?;
-        
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_pool ON swaps(pool_address)")
-            .execute(&self.pool)
-            .await?;
-        
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_timestamp ON swaps(timestamp)")
-            .execute(&self.pool)
-            .await?;
-
-        Ok(())
-    }
-
-    pub async fn upsert_pool(&self, pool: &PoolData) -> Result<()> {
+        // Pool state as of a block, recorded whenever the indexer refreshes a pool
         sqlx::query(
             r#"
-            INSERT INTO pools (
-                pool_address, token0_address, token1_address, token0_symbol, token1_symbol,
-                token0_decimals, token1_decimals, fee_tier, tick_spacing, liquidity,
-                sqrt_price_x96, tick, chain_id, dex_name, updated_at
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, CURRENT_TIMESTAMP)
-            ON CONFLICT (pool_address) DO UPDATE SET
-                liquidity = EXCLUDED.liquidity,
-                sqrt_price_x96 = EXCLUDED.sqrt_price_x96,
-                tick = EXCLUDED.tick,
-                updated_at = CURRENT_TIMESTAMP
+            CREATE TABLE IF NOT EXISTS pool_snapshots (
+                id SERIAL PRIMARY KEY,
+                pool_address VARCHAR(42) NOT NULL,
+                block_number BIGINT NOT NULL,
+                liquidity BIGINT,
+                sqrt_price_x96 VARCHAR(100),
+                tick INTEGER,
+                chain_id INTEGER NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE(pool_address, block_number, chain_id)
+            )
             "#,
         )
-        .bind(&pool.pool_address)
-        .bind(&pool.token0_address)
-        .bind(&pool.token1_address)
-        .bind(&pool.token0_symbol)
-        .bind(&pool.token1_symbol)
-        .bind(pool.token0_decimals)
-        .bind(pool.token1_decimals)
-        .bind(pool.fee_tier)
-        .bind(pool.tick_spacing)
-        .bind(pool.liquidity)
-        .bind(&pool.sqrt_price_x96)
-        .bind(pool.tick)
-        .bind(pool.chain_id)
-        .bind(&pool.dex_name)
-        .execute(&self.pool)
+        .execute(self.schema.get())
         .await?;
 
-        Ok(())
-    }
-
-    pub async fn insert_swap(&self, swap: &SwapEvent) -> Result<()> {
+        // Pool tick after each swap, as emitted in its Swap event
         sqlx::query(
             r#"
-            INSERT INTO swaps (
-                tx_hash, pool_address, token_in, token_out, amount_in, amount_out,
-                amount_in_usd, amount_out_usd, timestamp, block_number, log_index, chain_id
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
-            ON CONFLICT (tx_hash, log_index, chain_id) DO NOTHING
+            CREATE TABLE IF NOT EXISTS tick_history (
+                pool_address VARCHAR(42) NOT NULL,
+                chain_id INTEGER NOT NULL,
+                block_number BIGINT NOT NULL,
+                log_index INTEGER NOT NULL,
+                tx_hash VARCHAR(66) NOT NULL,
+                tick INTEGER NOT NULL,
+                PRIMARY KEY (pool_address, chain_id, block_number, log_index)
+            )
             "#,
         )
-        .bind(&swap.tx_hash)
-        .bind(&swap.pool_address)
-        .bind(&swap.token_in)
-        .bind(&swap.token_out)
-        .bind(swap.amount_in)
-        .bind(swap.amount_out)
-        .bind(swap.amount_in_usd)
-        .bind(swap.amount_out_usd)
-        .bind(swap.timestamp)
-        .bind(swap.block_number)
-        .bind(swap.log_index)
-        .bind(swap.chain_id)
-        .execute(&self.pool)
+        .execute(self.schema.get())
         .await?;
 
-        Ok(())
-    }
-
-    pub async fn get_pool(&self, pool_address: &str) -> Result<Option<PoolData>> {
-        let row = sqlx::query(
-            "SELECT * FROM pools WHERE pool_address = $1"
+        // Token metadata, keyed per chain
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS tokens (
+                address VARCHAR(42) NOT NULL,
+                chain_id INTEGER NOT NULL,
+                name VARCHAR(100),
+                symbol VARCHAR(20),
+                decimals INTEGER,
+                total_supply NUMERIC(78, 0),
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (address, chain_id)
+            )
+            "#,
         )
-        .bind(pool_address)
-        .fetch_optional(&self.pool)
+        .execute(self.schema.get())
         .await?;
 
-        if let Some(row) = row {
-            Ok(Some(PoolData {
-                pool_address: row.get("pool_address"),
-                token0_address: row.get("token0_address"),
-                token1_address: row.get("token1_address"),
-                token0_symbol: row.get("token0_symbol"),
-                token1_symbol: row.get("token1_symbol"),
-                token0_decimals: row.get("token0_decimals"),
-                token1_decimals: row.get("token1_decimals"),
-                fee_tier: row.get("fee_tier"),
-                tick_spacing: row.get("tick_spacing"),
-                liquidity: row.get("liquidity"),
-                sqrt_price_x96: row.get("sqrt_price_x96"),
-                tick: row.get("tick"),
-                chain_id: row.get("chain_id"),
-                dex_name: row.get("dex_name"),
-            }))
-        } else {
-            Ok(None)
-        }
-    }
+        // Block headers probed while mapping timestamps to blocks
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS blocks (
+                block_number BIGINT NOT NULL,
+                chain_id INTEGER NOT NULL,
+                timestamp BIGINT NOT NULL,
+                PRIMARY KEY (block_number, chain_id)
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
 
-    pub async fn get_pools_by_tokens(&self, token0: &str, token1: &str) -> Result<Vec<PoolData>> {
-        let rows = sqlx::query(
-            "SELECT * FROM pools WHERE (token0_address = $1 AND token1_address = $2) OR (token0_address = $2 AND token1_address = $1)"
+        // Gas market per block, from headers fetched for timestamps
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS gas_stats (
+                block_number BIGINT NOT NULL,
+                chain_id INTEGER NOT NULL,
+                timestamp BIGINT NOT NULL,
+                base_fee_per_gas BIGINT,
+                gas_used_ratio DOUBLE PRECISION NOT NULL,
+                median_priority_fee BIGINT,
+                PRIMARY KEY (block_number, chain_id)
+            )
+            "#,
         )
-        .bind(token0)
-        .bind(token1)
-        .fetch_all(&self.pool)
+        .execute(self.schema.get())
         .await?;
 
-        let pools = rows.into_iter().map(|row| PoolData {
-            pool_address: row.get("pool_address"),
-            token0_address: row.get("token0_address"),
-            token1_address: row.get("token1_address"),
-            token0_symbol: row.get("token0_symbol"),
-            token1_symbol: row.get("token1_symbol"),
-            token0_decimals: row.get("token0_decimals"),
-            token1_decimals: row.get("token1_decimals"),
-            fee_tier: row.get("fee_tier"),
-            tick_spacing: row.get("tick_spacing"),
-            liquidity: row.get("liquidity"),
-            sqrt_price_x96: row.get("sqrt_price_x96"),
-            tick: row.get("tick"),
-            chain_id: row.get("chain_id"),
-            dex_name: row.get("dex_name"),
-        }).collect();
-
-        Ok(pools)
-    }
+        // Native token USD readings, the root of USD pricing
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS native_prices (
+                block_number BIGINT NOT NULL,
+                chain_id INTEGER NOT NULL,
+                timestamp BIGINT NOT NULL,
+                price_usd DOUBLE PRECISION NOT NULL,
+                pool_address VARCHAR(42) NOT NULL,
+                PRIMARY KEY (block_number, chain_id)
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
 
-    pub async fn get_all_pool_addresses(&self) -> Result<Vec<String>> {
-        let rows = sqlx::query("SELECT pool_address FROM pools")
-            .fetch_all(&self.pool)
-            .await?;
+        // Supply readings that moved by more than the configured threshold
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS token_supply_history (
+                id SERIAL PRIMARY KEY,
+                token_address VARCHAR(42) NOT NULL,
+                chain_id INTEGER NOT NULL,
+                total_supply NUMERIC(78, 0) NOT NULL,
+                recorded_at BIGINT NOT NULL
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
 
-        let addresses = rows.into_iter()
-            .map(|row| row.get("pool_address"))
-            .collect();
+        // Latest known state of pool ticks; NUMERIC columns hold u128/i128/U256 values
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS tick_data (
+                pool_address VARCHAR(42) NOT NULL,
+                chain_id INTEGER NOT NULL,
+                tick_index INTEGER NOT NULL,
+                liquidity_gross NUMERIC(78, 0) NOT NULL,
+                liquidity_net NUMERIC(78, 0) NOT NULL,
+                fee_growth_outside_0 NUMERIC(78, 0) NOT NULL,
+                fee_growth_outside_1 NUMERIC(78, 0) NOT NULL,
+                block_number BIGINT NOT NULL,
+                PRIMARY KEY (pool_address, chain_id, tick_index)
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
 
-        Ok(addresses)
-    }
+        // Bonding curve trades from before a token graduates to a pool
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS curve_trades (
+                id SERIAL PRIMARY KEY,
+                tx_hash VARCHAR(66) NOT NULL,
+                log_index INTEGER NOT NULL,
+                block_number BIGINT NOT NULL,
+                timestamp BIGINT NOT NULL,
+                chain_id INTEGER NOT NULL,
+                curve_address VARCHAR(42) NOT NULL,
+                token_address VARCHAR(42) NOT NULL,
+                trader VARCHAR(42) NOT NULL,
+                side VARCHAR(4) NOT NULL,
+                token_amount NUMERIC(78, 0) NOT NULL,
+                collateral_amount NUMERIC(78, 0) NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE(tx_hash, log_index, chain_id)
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
 
-    pub async fn get_stats(&self) -> Result<(u64, u64)> {
-        let pool_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pools")
-            .fetch_one(&self.pool)
+        // Links a graduated curve token to the pool its liquidity moved into
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS token_migrations (
+                token_address VARCHAR(42) NOT NULL,
+                chain_id INTEGER NOT NULL,
+                curve_address VARCHAR(42) NOT NULL,
+                pool_address VARCHAR(42) NOT NULL,
+                tx_hash VARCHAR(66) NOT NULL,
+                block_number BIGINT NOT NULL,
+                timestamp BIGINT NOT NULL,
+                final_curve_price DOUBLE PRECISION,
+                PRIMARY KEY (token_address, chain_id)
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
+
+        // Periodic top-holder snapshots of launch tokens
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS token_holder_snapshots (
+                id SERIAL PRIMARY KEY,
+                token_address VARCHAR(42) NOT NULL,
+                chain_id INTEGER NOT NULL,
+                taken_at BIGINT NOT NULL,
+                top10_concentration_pct DOUBLE PRECISION
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS token_holder_balances (
+                snapshot_id INTEGER NOT NULL REFERENCES token_holder_snapshots(id) ON DELETE CASCADE,
+                rank INTEGER NOT NULL,
+                holder_address VARCHAR(42) NOT NULL,
+                balance NUMERIC(78, 0) NOT NULL,
+                PRIMARY KEY (snapshot_id, rank)
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
+
+        // Block ranges whose swaps were uploaded to the archive, so restarts skip them
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS archived_ranges (
+                chain_id INTEGER NOT NULL,
+                from_block BIGINT NOT NULL,
+                to_block BIGINT NOT NULL,
+                object_key TEXT NOT NULL,
+                swap_count BIGINT NOT NULL,
+                uploaded_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (chain_id, from_block, to_block)
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
+
+        // USD price of a token per time bucket, the history swaps are priced from
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS token_usd_prices (
+                token_address VARCHAR(42) NOT NULL,
+                chain_id INTEGER NOT NULL,
+                bucket_start BIGINT NOT NULL,
+                price_usd FLOAT8 NOT NULL,
+                PRIMARY KEY (token_address, chain_id, bucket_start)
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
+
+        // Indexing progress per chain and DEX, moved by each committed range
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS indexing_stats (
+                chain_id INTEGER NOT NULL,
+                dex_name VARCHAR(50) NOT NULL,
+                last_processed_block BIGINT NOT NULL,
+                head_block BIGINT,
+                total_pools_indexed BIGINT NOT NULL DEFAULT 0,
+                total_swaps_indexed BIGINT NOT NULL DEFAULT 0,
+                updated_at BIGINT NOT NULL,
+                PRIMARY KEY (chain_id, dex_name)
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
+
+        // Resume points of jobs that page through stored rows
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sync_cursors (
+                name VARCHAR(100) NOT NULL,
+                chain_id INTEGER NOT NULL,
+                position BIGINT NOT NULL,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (name, chain_id)
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
+
+        // Last event each named sink acknowledged, so restarts resume after it
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sink_offsets (
+                sink_name VARCHAR(100) NOT NULL,
+                chain_id INTEGER NOT NULL,
+                block_number BIGINT NOT NULL,
+                log_index INTEGER NOT NULL,
+                position TEXT NOT NULL,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (sink_name, chain_id)
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
+
+        // One row per table per maintenance pass
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS maintenance_log (
+                id BIGSERIAL PRIMARY KEY,
+                checked_at BIGINT NOT NULL,
+                table_name VARCHAR(64) NOT NULL,
+                table_bytes BIGINT NOT NULL,
+                index_bytes BIGINT NOT NULL,
+                live_tuples BIGINT NOT NULL,
+                dead_tuples BIGINT NOT NULL,
+                last_vacuum_at BIGINT,
+                needs_vacuum_full BOOLEAN NOT NULL
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
+
+        // Pools added at runtime through `Indexer::watch_pool`, tracked whatever the scope
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS watchlist (
+                pool_address VARCHAR(42) NOT NULL,
+                chain_id BIGINT NOT NULL,
+                added_at BIGINT NOT NULL,
+                PRIMARY KEY (pool_address, chain_id)
+            )
+            "#,
+        )
+        .execute(self.schema.get())
+        .await?;
+
+        // Columns added after the initial schema
+        sqlx::query("ALTER TABLE swaps ADD COLUMN IF NOT EXISTS sender_address VARCHAR(42)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE swaps ADD COLUMN IF NOT EXISTS indexed_at BIGINT")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE pools ADD COLUMN IF NOT EXISTS created_at_block BIGINT")
+            .execute(self.schema.get())
+            .await?;
+
+        // Block of the last change, for syncing only the pools changed since a block
+        sqlx::query("ALTER TABLE pools ADD COLUMN IF NOT EXISTS updated_at_block BIGINT")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE pools ADD COLUMN IF NOT EXISTS status VARCHAR(16) NOT NULL DEFAULT 'active'")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE pools ADD COLUMN IF NOT EXISTS tracked BOOLEAN NOT NULL DEFAULT TRUE")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE tokens ADD COLUMN IF NOT EXISTS supply_updated_at BIGINT")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE tokens ADD COLUMN IF NOT EXISTS is_fee_on_transfer BOOLEAN NOT NULL DEFAULT FALSE")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE tokens ADD COLUMN IF NOT EXISTS is_rebasing BOOLEAN NOT NULL DEFAULT FALSE")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE pools ADD COLUMN IF NOT EXISTS has_nonstandard_token BOOLEAN NOT NULL DEFAULT FALSE")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE token_migrations ADD COLUMN IF NOT EXISTS log_index INTEGER NOT NULL DEFAULT 0")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE tokens ADD COLUMN IF NOT EXISTS creator_address VARCHAR(42)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE swaps ADD COLUMN IF NOT EXISTS calldata BYTEA")
+            .execute(self.schema.get())
+            .await?;
+
+        // Role in a sandwich or arbitrage, from `mev::classify_block`
+        sqlx::query("ALTER TABLE swaps ADD COLUMN IF NOT EXISTS mev_type VARCHAR(16)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE swaps ADD COLUMN IF NOT EXISTS recipient_address VARCHAR(42)")
+            .execute(self.schema.get())
+            .await?;
+
+        // Set by the batch scorer in `wash`
+        sqlx::query("ALTER TABLE swaps ADD COLUMN IF NOT EXISTS is_suspected_wash BOOLEAN NOT NULL DEFAULT FALSE")
+            .execute(self.schema.get())
+            .await?;
+
+        // Set on swaps USD enrichment found no historical price for
+        sqlx::query("ALTER TABLE tick_history ADD COLUMN IF NOT EXISTS timestamp BIGINT")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE swaps ADD COLUMN IF NOT EXISTS usd_unpriced BOOLEAN NOT NULL DEFAULT FALSE")
+            .execute(self.schema.get())
+            .await?;
+
+        // Set on swaps whose USD amounts failed enrichment's sanity checks, which are left NULL
+        sqlx::query("ALTER TABLE swaps ADD COLUMN IF NOT EXISTS usd_flagged BOOLEAN NOT NULL DEFAULT FALSE")
+            .execute(self.schema.get())
+            .await?;
+
+        // The pool's own token balances, sampled with the snapshot when enabled
+        sqlx::query("ALTER TABLE pool_snapshots ADD COLUMN IF NOT EXISTS token0_balance NUMERIC(78, 0)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("ALTER TABLE pool_snapshots ADD COLUMN IF NOT EXISTS token1_balance NUMERIC(78, 0)")
+            .execute(self.schema.get())
+            .await?;
+
+        self.constrain_dex_names().await?;
+
+        // Create indexes for better query performance
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_native_prices_chain_ts ON native_prices(chain_id, timestamp)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_gas_stats_chain_ts ON gas_stats(chain_id, timestamp)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_token_supply_history_token ON token_supply_history(token_address, chain_id, recorded_at)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_curve_trades_token ON curve_trades(token_address, chain_id, block_number)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_token_holder_snapshots_token ON token_holder_snapshots(token_address, chain_id, taken_at)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_pools_address ON pools(pool_address)")
+            .execute(self.schema.get())
+            .await?;
+        
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_pools_tokens ON pools(token0_address, token1_address)")
+            .execute(self.schema.get())
+            .await?;
+        
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_tx_hash ON swaps(tx_hash)")
+            .execute(self.schema.get())
+            .await?;
+        
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_pool ON swaps(pool_address)")
+            .execute(self.schema.get())
+            .await?;
+        
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_timestamp ON swaps(timestamp)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_chain_ts ON swaps(chain_id, timestamp)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_tx_chain ON swaps(tx_hash, chain_id)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_block_sender ON swaps(block_number, sender_address)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_sender_ts ON swaps(sender_address, timestamp)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_sender_chain ON swaps(sender_address, chain_id, timestamp)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_large ON swaps(amount_in DESC) WHERE amount_in_usd > 10000")
+            .execute(self.schema.get())
+            .await?;
+
+        // Latest swap per pool, read from the index alone
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_pool_max_ts ON swaps(pool_address, timestamp DESC)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_swaps_missing_usd ON swaps(chain_id, id) WHERE amount_in_usd IS NULL AND NOT usd_unpriced")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_pools_created_at_block ON pools(created_at_block)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_pools_liquidity ON pools(liquidity DESC NULLS LAST)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_pools_fee_chain ON pools(fee_tier, chain_id)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_pools_updated_block ON pools(updated_at_block, chain_id)")
+            .execute(self.schema.get())
+            .await?;
+
+        // Pools stored before the column was added were last changed, as far as is known, when created
+        sqlx::query("UPDATE pools SET updated_at_block = created_at_block WHERE updated_at_block IS NULL AND created_at_block IS NOT NULL")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_pools_status ON pools(status)")
+            .execute(self.schema.get())
+            .await?;
+
+        // Serves the updated pools feed in the order it pages through them
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_pools_updated_at ON pools(chain_id, date_trunc('second', updated_at), pool_address)",
+        )
+        .execute(self.schema.get())
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_pool_snapshots_block ON pool_snapshots(pool_address, block_number)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_tick_history_pool_block ON tick_history(pool_address, block_number)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_tick_history_pool_timestamp ON tick_history(pool_address, chain_id, timestamp)")
+            .execute(self.schema.get())
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_maintenance_log_table ON maintenance_log(table_name, checked_at)")
+            .execute(self.schema.get())
+            .await?;
+
+        self.create_views().await?;
+
+        Ok(())
+    }
+
+    /// Stores every `dex_name` as a canonical DEX id, lowercased and trimmed, and adds CHECK
+    /// constraints keeping it so. `indexing_stats` rows that differed only in case are merged.
+    /// Fails, listing them, while a stored name is still not a well-formed id; `rename-dex`
+    /// relabels those.
+    async fn constrain_dex_names(&self) -> Result<()> {
+        let mut tx = self.schema.get().begin().await?;
+        // Processes starting together would otherwise both add the constraints
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(coordination::lock_key("dex_names"))
+            .execute(&mut *tx)
+            .await?;
+
+        // Checked before anything is changed, so the names listed are the stored ones
+        let invalid_query = DEX_NAME_TABLES
+            .iter()
+            .map(|table| {
+                format!("SELECT DISTINCT dex_name FROM {} WHERE NOT ({})", table, dex_name_check("LOWER(TRIM(dex_name))"))
+            })
+            .collect::<Vec<_>>()
+            .join(" UNION ");
+        let invalid: Vec<String> = sqlx::query_scalar(&format!("{} ORDER BY 1", invalid_query)).fetch_all(&mut *tx).await?;
+        if !invalid.is_empty() {
+            bail!(
+                "stored DEX names {:?} are not DEX ids of 1 to {} letters, digits and dashes; relabel them with rename-dex",
+                invalid,
+                MAX_DEX_ID_LEN
+            );
+        }
+
+        sqlx::query("UPDATE pools SET dex_name = LOWER(TRIM(dex_name)) WHERE dex_name <> LOWER(TRIM(dex_name))")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            r#"
+            WITH folded AS (
+                DELETE FROM indexing_stats WHERE dex_name <> LOWER(TRIM(dex_name)) RETURNING *
+            )
+            INSERT INTO indexing_stats
+                (chain_id, dex_name, last_processed_block, head_block, total_pools_indexed, total_swaps_indexed, updated_at)
+            SELECT chain_id, LOWER(TRIM(dex_name)), MAX(last_processed_block), MAX(head_block),
+                SUM(total_pools_indexed), SUM(total_swaps_indexed), MAX(updated_at)
+            FROM folded
+            GROUP BY chain_id, LOWER(TRIM(dex_name))
+            ON CONFLICT (chain_id, dex_name) DO UPDATE SET
+                last_processed_block = GREATEST(indexing_stats.last_processed_block, EXCLUDED.last_processed_block),
+                head_block = GREATEST(indexing_stats.head_block, EXCLUDED.head_block),
+                total_pools_indexed = indexing_stats.total_pools_indexed + EXCLUDED.total_pools_indexed,
+                total_swaps_indexed = indexing_stats.total_swaps_indexed + EXCLUDED.total_swaps_indexed,
+                updated_at = GREATEST(indexing_stats.updated_at, EXCLUDED.updated_at)
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        for table in DEX_NAME_TABLES {
+            sqlx::query(&format!(
+                "DO $$ BEGIN \
+                     IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conname = '{table}_dex_name_check') THEN \
+                         ALTER TABLE {table} ADD CONSTRAINT {table}_dex_name_check CHECK ({check}); \
+                     END IF; \
+                 END $$",
+                table = table,
+                check = dex_name_check("dex_name"),
+            ))
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Recreates the views over the tables: `v_pool_stats`, every pool with its swap count,
+    /// last swap time and USD volume. Dropped first, as the view takes on columns added to
+    /// `pools` since it was created.
+    pub async fn create_views(&self) -> Result<()> {
+        let mut tx = self.schema.get().begin().await?;
+        // Processes starting together would otherwise race to replace the view
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(coordination::lock_key("views"))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DROP VIEW IF EXISTS v_pool_stats").execute(&mut *tx).await?;
+        sqlx::query(
+            r#"
+            CREATE VIEW v_pool_stats AS
+            SELECT p.*, COUNT(s.id) AS swap_count, MAX(s.timestamp) AS last_swap_ts, SUM(s.amount_in_usd) AS volume_usd
+            FROM pools p
+            LEFT JOIN swaps s ON s.pool_address = p.pool_address AND s.chain_id = p.chain_id
+            GROUP BY p.id
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Stores the pool, or its new state when it is stored already. Fails unless `dex_name`
+    /// is a DEX of the registry deployed on the pool's chain.
+    pub async fn upsert_pool(&self, pool: &PoolData) -> Result<()> {
+        self.dexes.check_chain(&pool.dex_name, pool.chain_id)?;
+        self.timed("upsert_pool", Access::Write, async {
+            sqlx::query(
+                r#"
+                INSERT INTO pools (
+                    pool_address, token0_address, token1_address, token0_symbol, token1_symbol,
+                    token0_decimals, token1_decimals, fee_tier, tick_spacing, liquidity,
+                    sqrt_price_x96, tick, chain_id, dex_name, created_at_block, updated_at_block, updated_at
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, COALESCE($16, $15), CURRENT_TIMESTAMP)
+                ON CONFLICT (pool_address) DO UPDATE SET
+                    liquidity = EXCLUDED.liquidity,
+                    sqrt_price_x96 = EXCLUDED.sqrt_price_x96,
+                    tick = EXCLUDED.tick,
+                    updated_at_block = COALESCE(EXCLUDED.updated_at_block, pools.updated_at_block),
+                    updated_at = CURRENT_TIMESTAMP
+                -- A refresh reading the same state leaves the row, and updated_at, alone
+                WHERE (pools.liquidity, pools.sqrt_price_x96, pools.tick)
+                    IS DISTINCT FROM (EXCLUDED.liquidity, EXCLUDED.sqrt_price_x96, EXCLUDED.tick)
+                "#,
+            )
+            .bind(&pool.pool_address)
+            .bind(&pool.token0_address)
+            .bind(&pool.token1_address)
+            .bind(&pool.token0_symbol)
+            .bind(&pool.token1_symbol)
+            .bind(pool.token0_decimals)
+            .bind(pool.token1_decimals)
+            .bind(pool.fee_tier)
+            .bind(pool.tick_spacing)
+            .bind(pool.liquidity)
+            .bind(&pool.sqrt_price_x96)
+            .bind(pool.tick)
+            .bind(pool.chain_id)
+            .bind(pool.dex_name.as_str())
+            .bind(pool.created_at_block)
+            .bind(pool.updated_at_block)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Addresses of the chain's pools changed after `block_number`, by `updated_at_block`,
+    /// earliest change first, for clients syncing only what changed since their last query.
+    pub async fn get_pools_updated_after_block(&self, block_number: i64, chain_id: i64) -> Result<Vec<String>> {
+        self.timed("get_pools_updated_after_block", Access::Read, async {
+            let pools = sqlx::query_scalar(
+                r#"
+                SELECT pool_address FROM pools
+                WHERE updated_at_block > $1 AND chain_id = $2
+                ORDER BY updated_at_block ASC, pool_address
+                "#,
+            )
+            .bind(block_number)
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(pools)
+        })
+        .await
+    }
+
+    pub async fn insert_pool_snapshot(&self, pool: &PoolData, block_number: i64) -> Result<()> {
+        self.timed("insert_pool_snapshot", Access::Write, async {
+            sqlx::query(
+                r#"
+                INSERT INTO pool_snapshots (
+                    pool_address, block_number, liquidity, sqrt_price_x96, tick, chain_id
+                ) VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (pool_address, block_number, chain_id) DO UPDATE SET
+                    liquidity = EXCLUDED.liquidity,
+                    sqrt_price_x96 = EXCLUDED.sqrt_price_x96,
+                    tick = EXCLUDED.tick
+                "#,
+            )
+            .bind(&pool.pool_address)
+            .bind(block_number)
+            .bind(pool.liquidity)
+            .bind(&pool.sqrt_price_x96)
+            .bind(pool.tick)
+            .bind(pool.chain_id)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Attaches sampled token balances to the pool's snapshot at `balances.block_number`,
+    /// which must already be recorded.
+    pub async fn store_pool_balances(&self, pool_address: &str, chain_id: i64, balances: &PoolBalanceSnapshot) -> Result<()> {
+        self.timed("store_pool_balances", Access::Write, async {
+            sqlx::query(
+                r#"
+                UPDATE pool_snapshots
+                SET token0_balance = $4::NUMERIC, token1_balance = $5::NUMERIC
+                WHERE pool_address = $1 AND chain_id = $2 AND block_number = $3
+                "#,
+            )
+            .bind(pool_address)
+            .bind(chain_id)
+            .bind(balances.block_number)
+            .bind(&balances.token0_balance)
+            .bind(&balances.token1_balance)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// The pool's snapshots from `from_block` on that carry sampled balances, oldest first.
+    pub async fn get_pool_balance_snapshots(&self, pool_address: &str, chain_id: i64, from_block: i64) -> Result<Vec<PoolBalanceSnapshot>> {
+        self.timed("get_pool_balance_snapshots", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT block_number, token0_balance::TEXT AS token0_balance, token1_balance::TEXT AS token1_balance
+                FROM pool_snapshots
+                WHERE pool_address = $1 AND chain_id = $2 AND block_number >= $3
+                  AND (token0_balance IS NOT NULL OR token1_balance IS NOT NULL)
+                ORDER BY block_number
+                "#,
+            )
+            .bind(pool_address)
+            .bind(chain_id)
+            .bind(from_block)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows
+                .iter()
+                .map(|row| PoolBalanceSnapshot {
+                    block_number: row.get("block_number"),
+                    token0_balance: row.get("token0_balance"),
+                    token1_balance: row.get("token1_balance"),
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Rejects events that fail `SwapEvent::validate` with an `InvalidEventError`.
+    /// `indexed_at` defaults to the time of the call. Returns false for a swap that was
+    /// already stored, by this process or another one indexing the same blocks.
+    pub async fn insert_swap(&self, swap: &SwapEvent) -> Result<bool> {
+        self.timed("insert_swap", Access::Write, async {
+            swap.validate()?;
+
+            let inserted = sqlx::query(
+                r#"
+                INSERT INTO swaps (
+                    tx_hash, pool_address, token_in, token_out, amount_in, amount_out,
+                    amount_in_usd, amount_out_usd, timestamp, block_number, log_index, chain_id,
+                    sender_address, recipient_address, indexed_at, calldata
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+                ON CONFLICT (tx_hash, log_index, chain_id) DO NOTHING
+                "#,
+            )
+            .bind(&swap.tx_hash)
+            .bind(&swap.pool_address)
+            .bind(&swap.token_in)
+            .bind(&swap.token_out)
+            .bind(swap.amount_in)
+            .bind(swap.amount_out)
+            .bind(swap.amount_in_usd)
+            .bind(swap.amount_out_usd)
+            .bind(swap.timestamp)
+            .bind(swap.block_number)
+            .bind(swap.log_index)
+            .bind(swap.chain_id)
+            .bind(&swap.sender_address)
+            .bind(&swap.recipient_address)
+            .bind(swap.indexed_at.unwrap_or_else(metrics::unix_millis))
+            .bind(swap.calldata.as_deref())
+            .execute(self.writer.get())
+            .await?
+            .rows_affected() > 0;
+
+            if let Some(tick) = swap.tick {
+                sqlx::query(
+                    r#"
+                    INSERT INTO tick_history (pool_address, chain_id, block_number, log_index, tx_hash, tick, timestamp)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7)
+                    ON CONFLICT (pool_address, chain_id, block_number, log_index) DO NOTHING
+                    "#,
+                )
+                .bind(&swap.pool_address)
+                .bind(swap.chain_id)
+                .bind(swap.block_number)
+                .bind(swap.log_index)
+                .bind(&swap.tx_hash)
+                .bind(tick)
+                .bind(swap.timestamp)
+                .execute(self.writer.get())
+                .await?;
+            }
+
+            Ok(inserted)
+        })
+        .await
+    }
+
+    /// How far a swap moved its pool's tick: its own tick in `tick_history` minus the tick
+    /// left by the swap before it in the pool. 0 when the swap stayed within a tick; large
+    /// moves mark swaps with a big price impact. Fails when either tick is not recorded.
+    pub async fn get_swap_impact_on_tick(&self, pool_address: &str, chain_id: i64, tx_hash: &str, log_index: i32) -> Result<i32> {
+        self.timed("get_swap_impact_on_tick", Access::Read, async {
+            let row = sqlx::query(
+                r#"
+                SELECT swap.tick AS after_tick, (
+                    SELECT previous.tick FROM tick_history previous
+                    WHERE previous.pool_address = swap.pool_address AND previous.chain_id = swap.chain_id
+                        AND (previous.block_number, previous.log_index) < (swap.block_number, swap.log_index)
+                    ORDER BY previous.block_number DESC, previous.log_index DESC
+                    LIMIT 1
+                ) AS before_tick
+                FROM tick_history swap
+                WHERE swap.pool_address = $1 AND swap.chain_id = $2 AND swap.tx_hash = $3 AND swap.log_index = $4
+                "#,
+            )
+            .bind(pool_address)
+            .bind(chain_id)
+            .bind(tx_hash)
+            .bind(log_index)
+            .fetch_optional(self.reader.get())
+            .await?;
+
+            let Some(row) = row else {
+                bail!("no tick recorded for swap {}:{} in pool {}", tx_hash, log_index, pool_address);
+            };
+            let (after_tick, before_tick): (i32, Option<i32>) = (row.get("after_tick"), row.get("before_tick"));
+            let Some(before_tick) = before_tick else {
+                bail!("no tick recorded before swap {}:{} in pool {}", tx_hash, log_index, pool_address);
+            };
+            Ok(after_tick - before_tick)
+        })
+        .await
+    }
+
+    /// `SwapEvent::compute_price_impact_bps` against the last tick recorded in the pool
+    /// before the swap. Fails when the pool has no tick history before it.
+    pub async fn get_swap_price_impact_bps(&self, swap: &SwapEvent, pool: &PoolData) -> Result<i64> {
+        let tick_before = self
+            .timed("get_swap_price_impact_bps", Access::Read, async {
+                let tick: Option<i32> = sqlx::query_scalar(
+                    r#"
+                    SELECT tick FROM tick_history
+                    WHERE pool_address = $1 AND chain_id = $2 AND (block_number, log_index) < ($3, $4)
+                    ORDER BY block_number DESC, log_index DESC
+                    LIMIT 1
+                    "#,
+                )
+                .bind(&swap.pool_address)
+                .bind(swap.chain_id)
+                .bind(swap.block_number)
+                .bind(swap.log_index)
+                .fetch_optional(self.reader.get())
+                .await?;
+                Ok(tick)
+            })
+            .await?;
+
+        let Some(tick_before) = tick_before else {
+            bail!("no tick recorded before swap {}:{} in pool {}", swap.tx_hash, swap.log_index, swap.pool_address);
+        };
+        swap.compute_price_impact_bps(pool, tick_before)
+    }
+
+    /// The pool's price sampled every `interval` seconds over `from_ts <= t < to_ts`, each
+    /// point from the last tick recorded at or before `t`. Boundaries before the pool's
+    /// first recorded swap have no point; at most `MAX_PRICE_POINTS` are returned.
+    pub async fn get_price_at_timestamp_range(
+        &self,
+        pool_address: &str,
+        chain_id: i64,
+        from_ts: i64,
+        to_ts: i64,
+        interval: i64,
+    ) -> Result<Vec<PricePoint>> {
+        if interval <= 0 {
+            bail!("price sampling interval must be positive, got {}", interval);
+        }
+        let Some(pool) = self.get_pool(pool_address).await? else {
+            bail!("pool {} not found", pool_address);
+        };
+
+        let rows = self
+            .timed("get_price_at_timestamp_range", Access::Read, async {
+                let rows = sqlx::query(
+                    r#"
+                    WITH buckets AS (
+                        SELECT generate_series($3::BIGINT, $4::BIGINT - 1, $5::BIGINT) AS bucket
+                        LIMIT $6
+                    )
+                    SELECT buckets.bucket, last_tick.tick
+                    FROM buckets
+                    CROSS JOIN LATERAL (
+                        SELECT tick FROM tick_history
+                        WHERE pool_address = $1 AND chain_id = $2 AND timestamp <= buckets.bucket
+                        ORDER BY timestamp DESC, block_number DESC, log_index DESC
+                        LIMIT 1
+                    ) last_tick
+                    ORDER BY buckets.bucket
+                    "#,
+                )
+                .bind(pool_address)
+                .bind(chain_id)
+                .bind(from_ts)
+                .bind(to_ts)
+                .bind(interval)
+                .bind(MAX_PRICE_POINTS)
+                .fetch_all(self.reader.get())
+                .await?;
+                Ok(rows)
+            })
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                let tick: i32 = row.get("tick");
+                Ok(PricePoint { timestamp: row.get("bucket"), tick, price: pool.price_at_tick(tick)? })
+            })
+            .collect()
+    }
+
+    /// All swaps emitted by a transaction, in log order (multi-hop routes emit several).
+    pub async fn get_swap_by_tx_hash(&self, tx_hash: &str, chain_id: i64) -> Result<Vec<SwapEvent>> {
+        self.timed("get_swap_by_tx_hash", Access::Read, async {
+            let rows = sqlx::query(&format!(
+                "SELECT {} FROM swaps WHERE tx_hash = $1 AND chain_id = $2 ORDER BY log_index ASC",
+                SWAP_COLUMNS
+            ))
+            .bind(tx_hash)
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(swap_from_row).collect())
+        })
+        .await
+    }
+
+    /// Swaps with `from_ts <= timestamp < to_ts` that sold or bought between `min_amount` and
+    /// `max_amount` (raw, inclusive) of a token: the input amount where the token went in,
+    /// the output amount where it came out. Tokens match as addresses in any case or as
+    /// `token0`/`token1` of the swap's pool. At most 10,000 swaps, in chain order.
+    pub async fn get_swaps_by_amount_range(
+        &self,
+        chain_id: i64,
+        token_address: &str,
+        min_amount: i64,
+        max_amount: i64,
+        from_ts: i64,
+        to_ts: i64,
+    ) -> Result<Vec<SwapEvent>> {
+        self.timed("get_swaps_by_amount_range", Access::Read, async {
+            let rows = sqlx::query(&format!(
+                r#"
+                WITH token_pools AS (
+                    SELECT pool_address,
+                        CASE WHEN LOWER(token0_address) = LOWER($2) THEN 'token0' ELSE 'token1' END AS label
+                    FROM pools
+                    WHERE chain_id = $1 AND (LOWER(token0_address) = LOWER($2) OR LOWER(token1_address) = LOWER($2))
+                )
+                SELECT {} FROM swaps
+                WHERE chain_id = $1 AND timestamp >= $5 AND timestamp < $6
+                    AND (
+                        (amount_in BETWEEN $3 AND $4
+                            AND (LOWER(token_in) = LOWER($2) OR (pool_address, token_in) IN (SELECT * FROM token_pools)))
+                        OR (amount_out BETWEEN $3 AND $4
+                            AND (LOWER(token_out) = LOWER($2) OR (pool_address, token_out) IN (SELECT * FROM token_pools)))
+                    )
+                ORDER BY block_number, log_index
+                LIMIT $7
+                "#,
+                SWAP_COLUMNS
+            ))
+            .bind(chain_id)
+            .bind(token_address)
+            .bind(min_amount)
+            .bind(max_amount)
+            .bind(from_ts)
+            .bind(to_ts)
+            .bind(MAX_AMOUNT_RANGE_SWAPS)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(swap_from_row).collect())
+        })
+        .await
+    }
+
+    /// The latest `limit` swaps of a pool, in chain order.
+    pub async fn get_swaps_by_pool(&self, pool_address: &str, chain_id: i64, limit: i64) -> Result<Vec<SwapEvent>> {
+        self.timed("get_swaps_by_pool", Access::Read, async {
+            let rows = sqlx::query(&format!(
+                "SELECT * FROM (SELECT {} FROM swaps WHERE pool_address = $1 AND chain_id = $2 \
+                 ORDER BY block_number DESC, log_index DESC LIMIT $3) latest ORDER BY block_number, log_index",
+                SWAP_COLUMNS
+            ))
+            .bind(pool_address)
+            .bind(chain_id)
+            .bind(limit)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(swap_from_row).collect())
+        })
+        .await
+    }
+
+    pub async fn get_swaps_by_block_and_sender(
+        &self,
+        block_number: i64,
+        sender_address: &str,
+        chain_id: i64,
+    ) -> Result<Vec<SwapEvent>> {
+        self.timed("get_swaps_by_block_and_sender", Access::Read, async {
+            let rows = sqlx::query(&format!(
+                "SELECT {} FROM swaps WHERE block_number = $1 AND sender_address = $2 AND chain_id = $3 ORDER BY log_index ASC",
+                SWAP_COLUMNS
+            ))
+            .bind(block_number)
+            .bind(sender_address)
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(swap_from_row).collect())
+        })
+        .await
+    }
+
+    /// Swaps in `[from_block, to_block]`, in log order.
+    pub async fn get_swaps_in_block_range(&self, chain_id: i64, from_block: i64, to_block: i64) -> Result<Vec<SwapEvent>> {
+        self.timed("get_swaps_in_block_range", Access::Read, async {
+            let rows = sqlx::query(&format!(
+                "SELECT {} FROM swaps WHERE chain_id = $1 AND block_number BETWEEN $2 AND $3 ORDER BY block_number, log_index",
+                SWAP_COLUMNS
+            ))
+            .bind(chain_id)
+            .bind(from_block)
+            .bind(to_block)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(swap_from_row).collect())
+        })
+        .await
+    }
+
+    pub async fn record_archived_range(
+        &self,
+        chain_id: i64,
+        from_block: i64,
+        to_block: i64,
+        object_key: &str,
+        swap_count: i64,
+    ) -> Result<()> {
+        self.timed("record_archived_range", Access::Write, async {
+            sqlx::query(
+                r#"
+                INSERT INTO archived_ranges (chain_id, from_block, to_block, object_key, swap_count)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (chain_id, from_block, to_block) DO UPDATE SET
+                    object_key = EXCLUDED.object_key,
+                    swap_count = EXCLUDED.swap_count,
+                    uploaded_at = CURRENT_TIMESTAMP
+                "#,
+            )
+            .bind(chain_id)
+            .bind(from_block)
+            .bind(to_block)
+            .bind(object_key)
+            .bind(swap_count)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn is_range_archived(&self, chain_id: i64, from_block: i64, to_block: i64) -> Result<bool> {
+        self.timed("is_range_archived", Access::Read, async {
+            let archived: bool = sqlx::query_scalar(
+                "SELECT EXISTS (SELECT 1 FROM archived_ranges WHERE chain_id = $1 AND from_block = $2 AND to_block = $3)",
+            )
+            .bind(chain_id)
+            .bind(from_block)
+            .bind(to_block)
+            .fetch_one(self.reader.get())
+            .await?;
+
+            Ok(archived)
+        })
+        .await
+    }
+
+    /// Sets `mev_type` of each swap, clearing it where the tag is None.
+    pub async fn set_swap_mev_types(&self, chain_id: i64, tags: &[(&SwapEvent, Option<MevType>)]) -> Result<()> {
+        self.timed("set_swap_mev_types", Access::Write, async {
+            let tx_hashes: Vec<&str> = tags.iter().map(|(swap, _)| swap.tx_hash.as_str()).collect();
+            let log_indices: Vec<i32> = tags.iter().map(|(swap, _)| swap.log_index).collect();
+            let mev_types: Vec<Option<&str>> = tags.iter().map(|(_, tag)| tag.map(|tag| tag.as_str())).collect();
+
+            sqlx::query(
+                r#"
+                UPDATE swaps SET mev_type = tagged.mev_type
+                FROM UNNEST($1::VARCHAR[], $2::INTEGER[], $3::VARCHAR[]) AS tagged(tx_hash, log_index, mev_type)
+                WHERE swaps.tx_hash = tagged.tx_hash AND swaps.log_index = tagged.log_index AND swaps.chain_id = $4
+                    AND swaps.mev_type IS DISTINCT FROM tagged.mev_type
+                "#,
+            )
+            .bind(&tx_hashes)
+            .bind(&log_indices)
+            .bind(&mev_types)
+            .bind(chain_id)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn get_swap_mev_type(&self, tx_hash: &str, log_index: i32, chain_id: i64) -> Result<Option<MevType>> {
+        self.timed("get_swap_mev_type", Access::Read, async {
+            let mev_type: Option<String> = sqlx::query_scalar(
+                "SELECT mev_type FROM swaps WHERE tx_hash = $1 AND log_index = $2 AND chain_id = $3",
+            )
+            .bind(tx_hash)
+            .bind(log_index)
+            .bind(chain_id)
+            .fetch_optional(self.reader.get())
+            .await?
+            .flatten();
+
+            Ok(mev_type.as_deref().and_then(MevType::parse))
+        })
+        .await
+    }
+
+    /// Swaps with `from_ts <= timestamp < to_ts`, in log order.
+    pub async fn get_swaps_in_time_range(&self, chain_id: i64, from_ts: i64, to_ts: i64) -> Result<Vec<SwapEvent>> {
+        self.timed("get_swaps_in_time_range", Access::Read, async {
+            let rows = sqlx::query(&format!(
+                "SELECT {} FROM swaps WHERE chain_id = $1 AND timestamp >= $2 AND timestamp < $3 ORDER BY block_number, log_index",
+                SWAP_COLUMNS
+            ))
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(swap_from_row).collect())
+        })
+        .await
+    }
+
+    /// Sets `is_suspected_wash` of each swap, clearing it where the flag is false.
+    pub async fn set_swap_wash_flags(&self, chain_id: i64, flags: &[(&SwapEvent, bool)]) -> Result<()> {
+        self.timed("set_swap_wash_flags", Access::Write, async {
+            let tx_hashes: Vec<&str> = flags.iter().map(|(swap, _)| swap.tx_hash.as_str()).collect();
+            let log_indices: Vec<i32> = flags.iter().map(|(swap, _)| swap.log_index).collect();
+            let suspected: Vec<bool> = flags.iter().map(|(_, flag)| *flag).collect();
+
+            sqlx::query(
+                r#"
+                UPDATE swaps SET is_suspected_wash = flagged.suspected
+                FROM UNNEST($1::VARCHAR[], $2::INTEGER[], $3::BOOLEAN[]) AS flagged(tx_hash, log_index, suspected)
+                WHERE swaps.tx_hash = flagged.tx_hash AND swaps.log_index = flagged.log_index AND swaps.chain_id = $4
+                    AND swaps.is_suspected_wash <> flagged.suspected
+                "#,
+            )
+            .bind(&tx_hashes)
+            .bind(&log_indices)
+            .bind(&suspected)
+            .bind(chain_id)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// None when the swap is not indexed.
+    pub async fn is_suspected_wash(&self, tx_hash: &str, log_index: i32, chain_id: i64) -> Result<Option<bool>> {
+        self.timed("is_suspected_wash", Access::Read, async {
+            let suspected = sqlx::query_scalar(
+                "SELECT is_suspected_wash FROM swaps WHERE tx_hash = $1 AND log_index = $2 AND chain_id = $3",
+            )
+            .bind(tx_hash)
+            .bind(log_index)
+            .bind(chain_id)
+            .fetch_optional(self.reader.get())
+            .await?;
+
+            Ok(suspected)
+        })
+        .await
+    }
+
+    /// Stores ticks read at `block_number`, replacing older readings of the same ticks.
+    pub async fn upsert_tick_data(&self, pool_address: &str, chain_id: i64, ticks: &[TickData], block_number: i64) -> Result<()> {
+        self.timed("upsert_tick_data", Access::Write, async {
+            for tick in ticks {
+                sqlx::query(
+                    r#"
+                    INSERT INTO tick_data (
+                        pool_address, chain_id, tick_index, liquidity_gross, liquidity_net,
+                        fee_growth_outside_0, fee_growth_outside_1, block_number
+                    ) VALUES ($1, $2, $3, $4::NUMERIC, $5::NUMERIC, $6::NUMERIC, $7::NUMERIC, $8)
+                    ON CONFLICT (pool_address, chain_id, tick_index) DO UPDATE SET
+                        liquidity_gross = EXCLUDED.liquidity_gross,
+                        liquidity_net = EXCLUDED.liquidity_net,
+                        fee_growth_outside_0 = EXCLUDED.fee_growth_outside_0,
+                        fee_growth_outside_1 = EXCLUDED.fee_growth_outside_1,
+                        block_number = EXCLUDED.block_number
+                    "#,
+                )
+                .bind(pool_address)
+                .bind(chain_id)
+                .bind(tick.tick_index)
+                .bind(tick.liquidity_gross.to_string())
+                .bind(tick.liquidity_net.to_string())
+                .bind(tick.fee_growth_outside_0.to_string())
+                .bind(tick.fee_growth_outside_1.to_string())
+                .bind(block_number)
+                .execute(self.writer.get())
+                .await?;
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Stored ticks of a pool within `[tick_lower, tick_upper]`, lowest first.
+    pub async fn get_tick_data(&self, pool_address: &str, chain_id: i64, tick_lower: i32, tick_upper: i32) -> Result<Vec<TickData>> {
+        self.timed("get_tick_data", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT tick_index, liquidity_gross::TEXT AS liquidity_gross, liquidity_net::TEXT AS liquidity_net,
+                    fee_growth_outside_0::TEXT AS fee_growth_outside_0, fee_growth_outside_1::TEXT AS fee_growth_outside_1
+                FROM tick_data
+                WHERE pool_address = $1 AND chain_id = $2 AND tick_index BETWEEN $3 AND $4
+                ORDER BY tick_index
+                "#,
+            )
+            .bind(pool_address)
+            .bind(chain_id)
+            .bind(tick_lower)
+            .bind(tick_upper)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            rows.iter()
+                .map(|row| {
+                    Ok(TickData {
+                        tick_index: row.get("tick_index"),
+                        liquidity_gross: row.get::<String, _>("liquidity_gross").parse()?,
+                        liquidity_net: row.get::<String, _>("liquidity_net").parse()?,
+                        fee_growth_outside_0: U256::from_dec_str(&row.get::<String, _>("fee_growth_outside_0"))?,
+                        fee_growth_outside_1: U256::from_dec_str(&row.get::<String, _>("fee_growth_outside_1"))?,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    pub async fn insert_curve_trade(&self, trade: &CurveTrade) -> Result<()> {
+        self.timed("insert_curve_trade", Access::Write, async {
+            sqlx::query(
+                r#"
+                INSERT INTO curve_trades (
+                    tx_hash, log_index, block_number, timestamp, chain_id, curve_address,
+                    token_address, trader, side, token_amount, collateral_amount
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10::NUMERIC, $11::NUMERIC)
+                ON CONFLICT (tx_hash, log_index, chain_id) DO NOTHING
+                "#,
+            )
+            .bind(&trade.tx_hash)
+            .bind(trade.log_index)
+            .bind(trade.block_number)
+            .bind(trade.timestamp)
+            .bind(trade.chain_id)
+            .bind(&trade.curve_address)
+            .bind(&trade.token_address)
+            .bind(&trade.trader)
+            .bind(trade.side.as_str())
+            .bind(&trade.token_amount)
+            .bind(&trade.collateral_amount)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Curve trades of a token, in chain order.
+    pub async fn get_curve_trades(&self, token_address: &str, chain_id: i64) -> Result<Vec<CurveTrade>> {
+        self.timed("get_curve_trades", Access::Read, async {
+            let rows = sqlx::query(&format!(
+                "SELECT {} FROM curve_trades WHERE token_address = $1 AND chain_id = $2 ORDER BY block_number, log_index",
+                CURVE_TRADE_COLUMNS
+            ))
+            .bind(token_address)
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            rows.iter().map(curve_trade_from_row).collect()
+        })
+        .await
+    }
+
+    /// The most recent curve trade of a token.
+    pub async fn get_last_curve_trade(&self, token_address: &str, chain_id: i64) -> Result<Option<CurveTrade>> {
+        self.timed("get_last_curve_trade", Access::Read, async {
+            let row = sqlx::query(&format!(
+                "SELECT {} FROM curve_trades WHERE token_address = $1 AND chain_id = $2 \
+                 ORDER BY block_number DESC, log_index DESC LIMIT 1",
+                CURVE_TRADE_COLUMNS
+            ))
+            .bind(token_address)
+            .bind(chain_id)
+            .fetch_optional(self.reader.get())
+            .await?;
+
+            row.as_ref().map(curve_trade_from_row).transpose()
+        })
+        .await
+    }
+
+    pub async fn insert_token_migration(&self, migration: &TokenMigration) -> Result<()> {
+        self.timed("insert_token_migration", Access::Write, async {
+            sqlx::query(
+                r#"
+                INSERT INTO token_migrations (
+                    token_address, chain_id, curve_address, pool_address, tx_hash, block_number,
+                    log_index, timestamp, final_curve_price
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                ON CONFLICT (token_address, chain_id) DO NOTHING
+                "#,
+            )
+            .bind(&migration.token_address)
+            .bind(migration.chain_id)
+            .bind(&migration.curve_address)
+            .bind(&migration.pool_address)
+            .bind(&migration.tx_hash)
+            .bind(migration.block_number)
+            .bind(migration.log_index)
+            .bind(migration.timestamp)
+            .bind(migration.final_curve_price)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn get_token_migration(&self, token_address: &str, chain_id: i64) -> Result<Option<TokenMigration>> {
+        self.timed("get_token_migration", Access::Read, async {
+            let row = sqlx::query(&format!(
+                "SELECT {} FROM token_migrations WHERE token_address = $1 AND chain_id = $2",
+                MIGRATION_COLUMNS
+            ))
+            .bind(token_address)
+            .bind(chain_id)
+            .fetch_optional(self.reader.get())
+            .await?;
+
+            Ok(row.as_ref().map(migration_from_row))
+        })
+        .await
+    }
+
+    /// A token's curve trades, migration, pool creations and pool swaps with
+    /// `from_ts <= timestamp < to_ts`, in chain order, `limit` at a time. Pass the previous
+    /// page's `next_cursor` to continue after it.
+    ///
+    /// Pool creations are placed by the cached timestamp of their creation block and left
+    /// out while that block has not been fetched.
+    pub async fn get_token_timeline(
+        &self,
+        token_address: &str,
+        chain_id: i64,
+        from_ts: i64,
+        to_ts: i64,
+        limit: i64,
+        cursor: Option<&TimelineCursor>,
+    ) -> Result<TokenTimelinePage> {
+        self.timed("get_token_timeline", Access::Read, async {
+            let after = cursor.cloned().unwrap_or_else(TimelineCursor::start);
+            let window = (token_address, chain_id, from_ts, to_ts, &after, limit + 1);
+
+            let pools = self
+                .timeline_rows(
+                    &format!(
+                        "SELECT * FROM ( \
+                             SELECT {}, (SELECT b.timestamp FROM blocks b \
+                                 WHERE b.block_number = pools.created_at_block AND b.chain_id = pools.chain_id) AS created_timestamp \
+                             FROM pools \
+                             WHERE chain_id = $2 AND (token0_address = $1 OR token1_address = $1) AND created_at_block IS NOT NULL \
+                         ) p \
+                         WHERE created_timestamp >= $3 AND created_timestamp < $4 \
+                             AND (created_at_block, -1, {}, pool_address) > ($5, $6, $7, $8) \
+                         ORDER BY created_at_block, pool_address LIMIT $9",
+                        POOL_COLUMNS, TIMELINE_POOL_CREATED
+                    ),
+                    window,
+                )
+                .await?;
+
+            let trades = self
+                .timeline_rows(
+                    &format!(
+                        "SELECT {} FROM curve_trades \
+                         WHERE token_address = $1 AND chain_id = $2 AND timestamp >= $3 AND timestamp < $4 \
+                             AND (block_number, log_index, {}, tx_hash) > ($5, $6, $7, $8) \
+                         ORDER BY block_number, log_index, tx_hash LIMIT $9",
+                        CURVE_TRADE_COLUMNS, TIMELINE_CURVE_TRADE
+                    ),
+                    window,
+                )
+                .await?;
+
+            let migrations = self
+                .timeline_rows(
+                    &format!(
+                        "SELECT {} FROM token_migrations \
+                         WHERE token_address = $1 AND chain_id = $2 AND timestamp >= $3 AND timestamp < $4 \
+                             AND (block_number, log_index, {}, tx_hash) > ($5, $6, $7, $8) \
+                         LIMIT $9",
+                        MIGRATION_COLUMNS, TIMELINE_MIGRATION
+                    ),
+                    window,
+                )
+                .await?;
+
+            let swaps = self
+                .timeline_rows(
+                    &format!(
+                        "SELECT {} FROM swaps \
+                         WHERE chain_id = $2 \
+                             AND pool_address IN (SELECT pool_address FROM pools WHERE token0_address = $1 OR token1_address = $1) \
+                             AND timestamp >= $3 AND timestamp < $4 \
+                             AND (block_number, log_index, {}, tx_hash) > ($5, $6, $7, $8) \
+                         ORDER BY block_number, log_index, tx_hash LIMIT $9",
+                        SWAP_COLUMNS, TIMELINE_SWAP
+                    ),
+                    window,
+                )
+                .await?;
+
+            let mut events = pools
+                .iter()
+                .map(|row| Ok(TokenEvent::PoolCreated(pool_from_row(row))))
+                .chain(trades.iter().map(|row| curve_trade_from_row(row).map(TokenEvent::CurveTrade)))
+                .chain(migrations.iter().map(|row| Ok(TokenEvent::Migration(migration_from_row(row)))))
+                .chain(swaps.iter().map(|row| Ok(TokenEvent::Swap(swap_from_row(row)))))
+                .collect::<Result<Vec<_>>>()?;
+            events.sort_by_cached_key(TimelineCursor::of);
+
+            // Each source returned up to limit + 1 events, so a longer merge means more pages
+            let next_cursor = if events.len() as i64 > limit {
+                events.truncate(limit.max(0) as usize);
+                events.last().map(|event| TimelineCursor::of(event).to_string())
+            } else {
+                None
+            };
+
+            Ok(TokenTimelinePage { events, next_cursor })
+        })
+        .await
+    }
+
+    /// Pool creations and swaps of a whole chain with `from_ts <= timestamp < to_ts`, in
+    /// timeline order and paged like `get_token_timeline`. Pool creations are placed by their
+    /// creation block's cached timestamp.
+    pub async fn get_chain_timeline(
+        &self,
+        chain_id: i64,
+        from_ts: i64,
+        to_ts: i64,
+        limit: i64,
+        cursor: Option<&TimelineCursor>,
+    ) -> Result<TokenTimelinePage> {
+        self.timed("get_chain_timeline", Access::Read, async {
+            let after = cursor.cloned().unwrap_or_else(TimelineCursor::start);
+            let window = (chain_id, from_ts, to_ts, &after, limit + 1);
+
+            let pools = self
+                .chain_timeline_rows(
+                    &format!(
+                        "SELECT * FROM ( \
+                             SELECT {}, (SELECT b.timestamp FROM blocks b \
+                                 WHERE b.block_number = pools.created_at_block AND b.chain_id = pools.chain_id) AS created_timestamp \
+                             FROM pools \
+                             WHERE chain_id = $1 AND created_at_block IS NOT NULL \
+                         ) p \
+                         WHERE created_timestamp >= $2 AND created_timestamp < $3 \
+                             AND (created_at_block, -1, {}, pool_address) > ($4, $5, $6, $7) \
+                         ORDER BY created_at_block, pool_address LIMIT $8",
+                        POOL_COLUMNS, TIMELINE_POOL_CREATED
+                    ),
+                    window,
+                )
+                .await?;
+
+            let swaps = self
+                .chain_timeline_rows(
+                    &format!(
+                        "SELECT {} FROM swaps \
+                         WHERE chain_id = $1 AND timestamp >= $2 AND timestamp < $3 \
+                             AND (block_number, log_index, {}, tx_hash) > ($4, $5, $6, $7) \
+                         ORDER BY block_number, log_index, tx_hash LIMIT $8",
+                        SWAP_COLUMNS, TIMELINE_SWAP
+                    ),
+                    window,
+                )
+                .await?;
+
+            let mut events: Vec<TokenEvent> = pools
+                .iter()
+                .map(|row| TokenEvent::PoolCreated(pool_from_row(row)))
+                .chain(swaps.iter().map(|row| TokenEvent::Swap(swap_from_row(row))))
+                .collect();
+            events.sort_by_cached_key(TimelineCursor::of);
+
+            let next_cursor = if events.len() as i64 > limit {
+                events.truncate(limit.max(0) as usize);
+                events.last().map(|event| TimelineCursor::of(event).to_string())
+            } else {
+                None
+            };
+
+            Ok(TokenTimelinePage { events, next_cursor })
+        })
+        .await
+    }
+
+    async fn timeline_rows(
+        &self,
+        sql: &str,
+        (token_address, chain_id, from_ts, to_ts, after, limit): (&str, i64, i64, i64, &TimelineCursor, i64),
+    ) -> Result<Vec<PgRow>> {
+        Ok(sqlx::query(sql)
+            .bind(token_address)
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .bind(after.block_number)
+            .bind(after.log_index)
+            .bind(after.kind)
+            .bind(&after.key)
+            .bind(limit)
+            .fetch_all(self.reader.get())
+            .await?)
+    }
+
+    async fn chain_timeline_rows(
+        &self,
+        sql: &str,
+        (chain_id, from_ts, to_ts, after, limit): (i64, i64, i64, &TimelineCursor, i64),
+    ) -> Result<Vec<PgRow>> {
+        Ok(sqlx::query(sql)
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .bind(after.block_number)
+            .bind(after.log_index)
+            .bind(after.kind)
+            .bind(&after.key)
+            .bind(limit)
+            .fetch_all(self.reader.get())
+            .await?)
+    }
+
+    /// The pool a curve token graduated into, once both the migration and the pool are indexed.
+    pub async fn get_graduated_pool(&self, token_address: &str, chain_id: i64) -> Result<Option<PoolData>> {
+        self.timed("get_graduated_pool", Access::Read, async {
+            let row = sqlx::query(&format!(
+                "SELECT {} FROM pools WHERE pool_address = \
+                 (SELECT pool_address FROM token_migrations WHERE token_address = $1 AND chain_id = $2)",
+                POOL_COLUMNS
+            ))
+            .bind(token_address)
+            .bind(chain_id)
+            .fetch_optional(self.reader.get())
+            .await?;
+
+            Ok(row.as_ref().map(pool_from_row))
+        })
+        .await
+    }
+
+    pub async fn upsert_token(&self, token: &TokenData) -> Result<()> {
+        self.timed("upsert_token", Access::Write, async {
+            sqlx::query(
+                r#"
+                INSERT INTO tokens (address, chain_id, name, symbol, decimals, total_supply, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $6::NUMERIC, CURRENT_TIMESTAMP)
+                ON CONFLICT (address, chain_id) DO UPDATE SET
+                    name = EXCLUDED.name,
+                    symbol = EXCLUDED.symbol,
+                    decimals = EXCLUDED.decimals,
+                    total_supply = EXCLUDED.total_supply,
+                    updated_at = CURRENT_TIMESTAMP
+                "#,
+            )
+            .bind(&token.address)
+            .bind(token.chain_id)
+            .bind(&token.name)
+            .bind(&token.symbol)
+            .bind(token.decimals)
+            .bind(&token.total_supply)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn get_token(&self, address: &str, chain_id: i64) -> Result<Option<TokenData>> {
+        self.timed("get_token", Access::Read, async {
+            let row = sqlx::query(
+                r#"
+                SELECT address, chain_id::BIGINT AS chain_id, name, symbol, decimals,
+                       total_supply::TEXT AS total_supply, supply_updated_at, is_fee_on_transfer, is_rebasing,
+                       creator_address
+                FROM tokens WHERE address = $1 AND chain_id = $2
+                "#,
+            )
+            .bind(address)
+            .bind(chain_id)
+            .fetch_optional(self.reader.get())
+            .await?;
+
+            Ok(row.map(|row| TokenData {
+                address: row.get("address"),
+                name: row.get("name"),
+                symbol: row.get("symbol"),
+                decimals: row.get("decimals"),
+                total_supply: row.get("total_supply"),
+                supply_updated_at: row.get("supply_updated_at"),
+                is_fee_on_transfer: row.get("is_fee_on_transfer"),
+                is_rebasing: row.get("is_rebasing"),
+                creator_address: row.get("creator_address"),
+                chain_id: row.get("chain_id"),
+            }))
+        })
+        .await
+    }
+
+    /// Records who launched a token, creating a bare token row if metadata was never synced.
+    pub async fn set_token_creator(&self, address: &str, chain_id: i64, creator_address: &str) -> Result<()> {
+        self.timed("set_token_creator", Access::Write, async {
+            sqlx::query(
+                r#"
+                INSERT INTO tokens (address, chain_id, creator_address, updated_at)
+                VALUES ($1, $2, $3, CURRENT_TIMESTAMP)
+                ON CONFLICT (address, chain_id) DO UPDATE SET
+                    creator_address = EXCLUDED.creator_address,
+                    updated_at = CURRENT_TIMESTAMP
+                "#,
+            )
+            .bind(address)
+            .bind(chain_id)
+            .bind(creator_address)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Flags the token and every pool on the chain that trades it.
+    pub async fn flag_nonstandard_token(&self, address: &str, chain_id: i64, behavior: TokenBehavior) -> Result<()> {
+        self.timed("flag_nonstandard_token", Access::Write, async {
+            let (fee_on_transfer, rebasing) = match behavior {
+                TokenBehavior::FeeOnTransfer => (true, false),
+                TokenBehavior::Rebasing => (false, true),
+            };
+
+            sqlx::query(
+                r#"
+                INSERT INTO tokens (address, chain_id, is_fee_on_transfer, is_rebasing, updated_at)
+                VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP)
+                ON CONFLICT (address, chain_id) DO UPDATE SET
+                    is_fee_on_transfer = tokens.is_fee_on_transfer OR EXCLUDED.is_fee_on_transfer,
+                    is_rebasing = tokens.is_rebasing OR EXCLUDED.is_rebasing,
+                    updated_at = CURRENT_TIMESTAMP
+                "#,
+            )
+            .bind(address)
+            .bind(chain_id)
+            .bind(fee_on_transfer)
+            .bind(rebasing)
+            .execute(self.writer.get())
+            .await?;
+
+            sqlx::query(
+                r#"
+                UPDATE pools SET has_nonstandard_token = TRUE, updated_at = CURRENT_TIMESTAMP
+                WHERE chain_id = $2 AND (token0_address = $1 OR token1_address = $1)
+                "#,
+            )
+            .bind(address)
+            .bind(chain_id)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Tokens of the chain's tracked pools, i.e. the ones whose supply is refreshed.
+    pub async fn get_tracked_pool_token_addresses(&self, chain_id: i64) -> Result<Vec<String>> {
+        self.timed("get_tracked_pool_token_addresses", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT token0_address AS address FROM pools WHERE chain_id = $1 AND tracked
+                UNION
+                SELECT token1_address AS address FROM pools WHERE chain_id = $1 AND tracked
+                ORDER BY address
+                "#,
+            )
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(|row| row.get("address")).collect())
+        })
+        .await
+    }
+
+    /// Tokens whose holders are snapshotted: those of tracked pools and those launched on the curve.
+    pub async fn get_holder_snapshot_token_addresses(&self, chain_id: i64) -> Result<Vec<String>> {
+        self.timed("get_holder_snapshot_token_addresses", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT token0_address AS address FROM pools WHERE chain_id = $1 AND tracked
+                UNION
+                SELECT token1_address AS address FROM pools WHERE chain_id = $1 AND tracked
+                UNION
+                SELECT token_address AS address FROM curve_trades WHERE chain_id = $1
+                ORDER BY address
+                "#,
+            )
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(|row| row.get("address")).collect())
+        })
+        .await
+    }
+
+    /// Pools on the chain that trade the token, tracked or not.
+    pub async fn get_pool_addresses_for_token(&self, token_address: &str, chain_id: i64) -> Result<Vec<String>> {
+        self.timed("get_pool_addresses_for_token", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT pool_address FROM pools
+                WHERE chain_id = $2 AND (token0_address = $1 OR token1_address = $1)
+                ORDER BY pool_address
+                "#,
+            )
+            .bind(token_address)
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(|row| row.get("pool_address")).collect())
+        })
+        .await
+    }
+
+    /// Stores a snapshot and its ranked holders in one transaction.
+    pub async fn insert_holder_snapshot(&self, snapshot: &HolderSnapshot) -> Result<()> {
+        self.timed("insert_holder_snapshot", Access::Write, async {
+            let mut tx = self.writer.get().begin().await?;
+
+            let snapshot_id: i32 = sqlx::query_scalar(
+                r#"
+                INSERT INTO token_holder_snapshots (token_address, chain_id, taken_at, top10_concentration_pct)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id
+                "#,
+            )
+            .bind(&snapshot.token_address)
+            .bind(snapshot.chain_id)
+            .bind(snapshot.taken_at)
+            .bind(snapshot.top10_concentration_pct)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            for (rank, holder) in snapshot.top_holders.iter().enumerate() {
+                sqlx::query(
+                    r#"
+                    INSERT INTO token_holder_balances (snapshot_id, rank, holder_address, balance)
+                    VALUES ($1, $2, $3, $4::NUMERIC)
+                    "#,
+                )
+                .bind(snapshot_id)
+                .bind(rank as i32 + 1)
+                .bind(&holder.holder_address)
+                .bind(&holder.balance)
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The most recent holder snapshot of a token, holders largest first.
+    pub async fn get_latest_holder_snapshot(&self, token_address: &str, chain_id: i64) -> Result<Option<HolderSnapshot>> {
+        self.timed("get_latest_holder_snapshot", Access::Read, async {
+            let Some(row) = sqlx::query(
+                r#"
+                SELECT id, taken_at, top10_concentration_pct FROM token_holder_snapshots
+                WHERE token_address = $1 AND chain_id = $2
+                ORDER BY taken_at DESC, id DESC
+                LIMIT 1
+                "#,
+            )
+            .bind(token_address)
+            .bind(chain_id)
+            .fetch_optional(self.reader.get())
+            .await?
+            else {
+                return Ok(None);
+            };
+
+            let holders = sqlx::query(
+                r#"
+                SELECT holder_address, balance::TEXT AS balance FROM token_holder_balances
+                WHERE snapshot_id = $1
+                ORDER BY rank
+                "#,
+            )
+            .bind(row.get::<i32, _>("id"))
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(Some(HolderSnapshot {
+                token_address: token_address.to_string(),
+                chain_id,
+                taken_at: row.get("taken_at"),
+                top_holders: holders
+                    .iter()
+                    .map(|holder| HolderBalance {
+                        holder_address: holder.get("holder_address"),
+                        balance: holder.get("balance"),
+                    })
+                    .collect(),
+                top10_concentration_pct: row.get("top10_concentration_pct"),
+            }))
+        })
+        .await
+    }
+
+    /// Stores a supply reading, creating a bare token row if metadata was never synced.
+    pub async fn update_token_supply(&self, address: &str, chain_id: i64, total_supply: &str, updated_at: i64) -> Result<()> {
+        self.timed("update_token_supply", Access::Write, async {
+            sqlx::query(
+                r#"
+                INSERT INTO tokens (address, chain_id, total_supply, supply_updated_at, updated_at)
+                VALUES ($1, $2, $3::NUMERIC, $4, CURRENT_TIMESTAMP)
+                ON CONFLICT (address, chain_id) DO UPDATE SET
+                    total_supply = EXCLUDED.total_supply,
+                    supply_updated_at = EXCLUDED.supply_updated_at,
+                    updated_at = CURRENT_TIMESTAMP
+                "#,
+            )
+            .bind(address)
+            .bind(chain_id)
+            .bind(total_supply)
+            .bind(updated_at)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn insert_token_supply_history(&self, address: &str, chain_id: i64, total_supply: &str, recorded_at: i64) -> Result<()> {
+        self.timed("insert_token_supply_history", Access::Write, async {
+            sqlx::query(
+                r#"
+                INSERT INTO token_supply_history (token_address, chain_id, total_supply, recorded_at)
+                VALUES ($1, $2, $3::NUMERIC, $4)
+                "#,
+            )
+            .bind(address)
+            .bind(chain_id)
+            .bind(total_supply)
+            .bind(recorded_at)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Recorded supplies as (total_supply, recorded_at), oldest first.
+    pub async fn get_token_supply_history(&self, address: &str, chain_id: i64) -> Result<Vec<(String, i64)>> {
+        self.timed("get_token_supply_history", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT total_supply::TEXT AS total_supply, recorded_at
+                FROM token_supply_history
+                WHERE token_address = $1 AND chain_id = $2
+                ORDER BY recorded_at, id
+                "#,
+            )
+            .bind(address)
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(|row| (row.get("total_supply"), row.get("recorded_at"))).collect())
+        })
+        .await
+    }
+
+    /// Token addresses referenced by pools on the chain that have no `tokens` row yet.
+    pub async fn get_missing_token_addresses(&self, chain_id: i64) -> Result<Vec<String>> {
+        self.timed("get_missing_token_addresses", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT pool_tokens.address FROM (
+                    SELECT DISTINCT token0_address AS address FROM pools WHERE chain_id = $1
+                    UNION
+                    SELECT DISTINCT token1_address AS address FROM pools WHERE chain_id = $1
+                ) pool_tokens
+                LEFT JOIN tokens t ON t.address = pool_tokens.address AND t.chain_id = $1
+                WHERE t.address IS NULL
+                ORDER BY pool_tokens.address
+                "#,
+            )
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.into_iter().map(|row| row.get("address")).collect())
+        })
+        .await
+    }
+
+    pub async fn upsert_block(&self, block_number: i64, timestamp: i64, chain_id: i64) -> Result<()> {
+        self.timed("upsert_block", Access::Write, async {
+            sqlx::query(
+                r#"
+                INSERT INTO blocks (block_number, chain_id, timestamp) VALUES ($1, $2, $3)
+                ON CONFLICT (block_number, chain_id) DO UPDATE SET timestamp = EXCLUDED.timestamp
+                "#,
+            )
+            .bind(block_number)
+            .bind(chain_id)
+            .bind(timestamp)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// A median priority fee already stored is kept when `stats` has none.
+    pub async fn upsert_gas_stats(&self, stats: &GasStats) -> Result<()> {
+        self.timed("upsert_gas_stats", Access::Write, async {
+            sqlx::query(
+                r#"
+                INSERT INTO gas_stats (block_number, chain_id, timestamp, base_fee_per_gas, gas_used_ratio, median_priority_fee)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (block_number, chain_id) DO UPDATE SET
+                    timestamp = EXCLUDED.timestamp,
+                    base_fee_per_gas = EXCLUDED.base_fee_per_gas,
+                    gas_used_ratio = EXCLUDED.gas_used_ratio,
+                    median_priority_fee = COALESCE(EXCLUDED.median_priority_fee, gas_stats.median_priority_fee)
+                "#,
+            )
+            .bind(stats.block_number)
+            .bind(stats.chain_id)
+            .bind(stats.timestamp)
+            .bind(stats.base_fee_per_gas)
+            .bind(stats.gas_used_ratio)
+            .bind(stats.median_priority_fee)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Gas stats of blocks with `from_ts <= timestamp < to_ts`, in block order.
+    pub async fn get_gas_stats(&self, chain_id: i64, from_ts: i64, to_ts: i64) -> Result<Vec<GasStats>> {
+        self.timed("get_gas_stats", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT block_number, chain_id::BIGINT AS chain_id, timestamp, base_fee_per_gas, gas_used_ratio, median_priority_fee
+                FROM gas_stats
+                WHERE chain_id = $1 AND timestamp >= $2 AND timestamp < $3
+                ORDER BY block_number
+                "#,
+            )
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows
+                .iter()
+                .map(|row| GasStats {
+                    chain_id: row.get("chain_id"),
+                    block_number: row.get("block_number"),
+                    timestamp: row.get("timestamp"),
+                    base_fee_per_gas: row.get("base_fee_per_gas"),
+                    gas_used_ratio: row.get("gas_used_ratio"),
+                    median_priority_fee: row.get("median_priority_fee"),
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Swap counts and volume of each block with `from_block <= block_number <= to_block`,
+    /// including blocks without swaps. Ranges over `MAX_BLOCK_STATS_RANGE` blocks are rejected.
+    pub async fn get_block_stats_range(&self, chain_id: i64, from_block: i64, to_block: i64) -> Result<Vec<BlockStats>> {
+        if to_block - from_block >= MAX_BLOCK_STATS_RANGE {
+            bail!("blocks {} to {} are more than {} blocks", from_block, to_block, MAX_BLOCK_STATS_RANGE);
+        }
+
+        self.timed("get_block_stats_range", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT
+                    b.block_number,
+                    COUNT(s.id) AS swap_count,
+                    COUNT(DISTINCT s.pool_address) AS pool_count,
+                    SUM(COALESCE((s.amount_in_usd + s.amount_out_usd) / 2, s.amount_in_usd, s.amount_out_usd))::FLOAT8
+                        AS volume_usd
+                FROM generate_series($2::BIGINT, $3::BIGINT) AS b(block_number)
+                LEFT JOIN swaps s ON s.chain_id = $1 AND s.block_number = b.block_number
+                GROUP BY b.block_number
+                ORDER BY b.block_number
+                "#,
+            )
+            .bind(chain_id)
+            .bind(from_block)
+            .bind(to_block)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows
+                .iter()
+                .map(|row| BlockStats {
+                    block_number: row.get("block_number"),
+                    swap_count: row.get::<i64, _>("swap_count") as u64,
+                    pool_count: row.get::<i64, _>("pool_count") as u64,
+                    volume_usd: row.get("volume_usd"),
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Records a native token reading, replacing an earlier one at the same block. Waits
+    /// while a price rebuild of the reading's pool replaces a day of its buckets.
+    pub async fn insert_native_price(&self, chain_id: i64, price: &NativePrice) -> Result<()> {
+        self.timed("insert_native_price", Access::Write, async {
+            let mut tx = self.writer.get().begin().await?;
+            sqlx::query("SELECT pg_advisory_xact_lock_shared($1)")
+                .bind(coordination::price_lock_key(chain_id, &price.pool_address))
+                .execute(&mut *tx)
+                .await?;
+            insert_native_price(&mut tx, chain_id, price).await?;
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Native token readings with `from_ts <= timestamp < to_ts`, in block order.
+    pub async fn get_native_prices(&self, chain_id: i64, from_ts: i64, to_ts: i64) -> Result<Vec<NativePrice>> {
+        self.timed("get_native_prices", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT block_number, timestamp, price_usd, pool_address FROM native_prices
+                WHERE chain_id = $1 AND timestamp >= $2 AND timestamp < $3
+                ORDER BY block_number
+                "#,
+            )
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows
+                .iter()
+                .map(|row| NativePrice {
+                    price_usd: row.get("price_usd"),
+                    pool_address: row.get("pool_address"),
+                    block_number: row.get("block_number"),
+                    timestamp: row.get("timestamp"),
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Sets the token's USD price bucket. `source_pool` is the pool the price was read
+    /// through, if any; the write waits while a price rebuild of it replaces a day of buckets.
+    pub async fn upsert_token_usd_price(
+        &self,
+        token_address: &str,
+        chain_id: i64,
+        bucket_start: i64,
+        price_usd: f64,
+        source_pool: Option<&str>,
+    ) -> Result<()> {
+        self.timed("upsert_token_usd_price", Access::Write, async {
+            let mut tx = self.writer.get().begin().await?;
+            if let Some(source_pool) = source_pool {
+                sqlx::query("SELECT pg_advisory_xact_lock_shared($1)")
+                    .bind(coordination::price_lock_key(chain_id, source_pool))
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            let bucket = TokenPriceBucket { token_address: token_address.to_string(), bucket_start, price_usd };
+            upsert_token_usd_price(&mut tx, chain_id, &bucket).await?;
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The token's USD price buckets starting `from_ts <= bucket_start < to_ts`, in order.
+    pub async fn get_token_usd_prices(&self, token_address: &str, chain_id: i64, from_ts: i64, to_ts: i64) -> Result<Vec<TokenPriceBucket>> {
+        self.timed("get_token_usd_prices", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT token_address, bucket_start, price_usd FROM token_usd_prices
+                WHERE token_address = $1 AND chain_id = $2 AND bucket_start >= $3 AND bucket_start < $4
+                ORDER BY bucket_start
+                "#,
+            )
+            .bind(normalize_address(token_address))
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows
+                .iter()
+                .map(|row| TokenPriceBucket {
+                    token_address: row.get("token_address"),
+                    bucket_start: row.get("bucket_start"),
+                    price_usd: row.get("price_usd"),
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Timestamps of the first and last swap the pool made, `None` without any.
+    pub async fn get_pool_swap_time_range(&self, pool_address: &str, chain_id: i64) -> Result<Option<(i64, i64)>> {
+        self.timed("get_pool_swap_time_range", Access::Read, async {
+            let (first, last): (Option<i64>, Option<i64>) =
+                sqlx::query_as("SELECT MIN(timestamp), MAX(timestamp) FROM swaps WHERE pool_address = $1 AND chain_id = $2")
+                    .bind(pool_address)
+                    .bind(chain_id)
+                    .fetch_one(self.reader.get())
+                    .await?;
+            Ok(first.zip(last))
+        })
+        .await
+    }
+
+    /// For each `interval` bucket starting `from_ts <= bucket_start < to_ts`, the last swap the
+    /// pool made before the bucket ends, joined with the last native reading taken before then.
+    /// Swaps without both amounts are skipped; buckets before the pool's first swap left out.
+    pub async fn get_pool_bucket_swaps(
+        &self,
+        pool_address: &str,
+        chain_id: i64,
+        from_ts: i64,
+        to_ts: i64,
+        interval: i64,
+    ) -> Result<Vec<BucketSwap>> {
+        if interval <= 0 {
+            bail!("bucket interval must be positive, got {}", interval);
+        }
+        self.timed("get_pool_bucket_swaps", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                WITH buckets AS (
+                    SELECT generate_series($3::BIGINT, $4::BIGINT - 1, $5::BIGINT) AS bucket
+                )
+                SELECT buckets.bucket, last_swap.token_in, last_swap.amount_in, last_swap.amount_out,
+                       last_swap.block_number, last_swap.timestamp, native.price_usd AS native_price_usd
+                FROM buckets
+                CROSS JOIN LATERAL (
+                    SELECT token_in, amount_in::FLOAT8 AS amount_in, amount_out::FLOAT8 AS amount_out, block_number, timestamp
+                    FROM swaps
+                    WHERE pool_address = $1 AND chain_id = $2 AND timestamp < buckets.bucket + $5
+                      AND amount_in > 0 AND amount_out > 0
+                    ORDER BY timestamp DESC, block_number DESC, log_index DESC
+                    LIMIT 1
+                ) last_swap
+                LEFT JOIN LATERAL (
+                    SELECT price_usd FROM native_prices
+                    WHERE chain_id = $2 AND timestamp < buckets.bucket + $5
+                    ORDER BY timestamp DESC, block_number DESC
+                    LIMIT 1
+                ) native ON TRUE
+                ORDER BY buckets.bucket
+                "#,
+            )
+            .bind(pool_address)
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .bind(interval)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows
+                .iter()
+                .map(|row| BucketSwap {
+                    bucket_start: row.get("bucket"),
+                    token_in: row.get("token_in"),
+                    amount_in: row.get("amount_in"),
+                    amount_out: row.get("amount_out"),
+                    block_number: row.get("block_number"),
+                    timestamp: row.get("timestamp"),
+                    native_price_usd: row.get("native_price_usd"),
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Replaces the price buckets read from the pool over `from_ts <= t < to_ts` in one
+    /// transaction: the USD price buckets of `tokens` and the native readings taken from the
+    /// pool. Holds the pool's price lock meanwhile, so live writes of its prices wait.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn replace_price_buckets(
+        &self,
+        chain_id: i64,
+        pool_address: &str,
+        from_ts: i64,
+        to_ts: i64,
+        tokens: &[String],
+        token_prices: &[TokenPriceBucket],
+        native_prices: &[NativePrice],
+    ) -> Result<()> {
+        self.timed("replace_price_buckets", Access::Write, async {
+            let mut tx = self.writer.get().begin().await?;
+            sqlx::query("SELECT pg_advisory_xact_lock($1)")
+                .bind(coordination::price_lock_key(chain_id, pool_address))
+                .execute(&mut *tx)
+                .await?;
+
+            let tokens: Vec<String> = tokens.iter().map(|token| normalize_address(token)).collect();
+            sqlx::query("DELETE FROM token_usd_prices WHERE token_address = ANY($1) AND chain_id = $2 AND bucket_start >= $3 AND bucket_start < $4")
+                .bind(&tokens)
+                .bind(chain_id)
+                .bind(from_ts)
+                .bind(to_ts)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM native_prices WHERE pool_address = $1 AND chain_id = $2 AND timestamp >= $3 AND timestamp < $4")
+                .bind(pool_address)
+                .bind(chain_id)
+                .bind(from_ts)
+                .bind(to_ts)
+                .execute(&mut *tx)
+                .await?;
+
+            for bucket in token_prices {
+                upsert_token_usd_price(&mut tx, chain_id, bucket).await?;
+            }
+            for price in native_prices {
+                insert_native_price(&mut tx, chain_id, price).await?;
+            }
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The token's USD price from the bucket holding `timestamp`, i.e. the last one starting
+    /// at or before it, or else the first one after. Buckets starting more than `max_age_secs`
+    /// away are not used.
+    pub async fn get_token_usd_price_near(
+        &self,
+        token_address: &str,
+        chain_id: i64,
+        timestamp: i64,
+        max_age_secs: i64,
+    ) -> Result<Option<f64>> {
+        self.timed("get_token_usd_price_near", Access::Read, async {
+            let price = sqlx::query_scalar(
+                r#"
+                SELECT price_usd FROM token_usd_prices
+                WHERE token_address = $1 AND chain_id = $2 AND bucket_start BETWEEN $3 - $4 AND $3 + $4
+                ORDER BY bucket_start > $3, ABS(bucket_start - $3)
+                LIMIT 1
+                "#,
+            )
+            .bind(normalize_address(token_address))
+            .bind(chain_id)
+            .bind(timestamp)
+            .bind(max_age_secs)
+            .fetch_optional(self.reader.get())
+            .await?;
+
+            Ok(price)
+        })
+        .await
+    }
+
+    /// Median of the token's USD price buckets starting `from_ts <= bucket_start <= to_ts`,
+    /// `None` without any.
+    pub async fn get_token_median_usd_price(
+        &self,
+        token_address: &str,
+        chain_id: i64,
+        from_ts: i64,
+        to_ts: i64,
+    ) -> Result<Option<f64>> {
+        self.timed("get_token_median_usd_price", Access::Read, async {
+            let median = sqlx::query_scalar(
+                r#"
+                SELECT PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY price_usd) FROM token_usd_prices
+                WHERE token_address = $1 AND chain_id = $2 AND bucket_start BETWEEN $3 AND $4
+                "#,
+            )
+            .bind(normalize_address(token_address))
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .fetch_one(self.reader.get())
+            .await?;
+
+            Ok(median)
+        })
+        .await
+    }
+
+    /// Up to `limit` swaps after row `after_id` with no USD amounts that were not already
+    /// found unpriceable or flagged, oldest row first.
+    pub async fn get_unpriced_swaps(
+        &self,
+        chain_id: i64,
+        from_ts: i64,
+        to_ts: i64,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<UnpricedSwap>> {
+        self.timed("get_unpriced_swaps", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT
+                    s.id::BIGINT AS id,
+                    s.timestamp,
+                    s.amount_in::FLOAT8 AS amount_in,
+                    s.amount_out::FLOAT8 AS amount_out,
+                    CASE s.token_in WHEN 'token0' THEN p.token0_address WHEN 'token1' THEN p.token1_address
+                        ELSE s.token_in END AS token_in,
+                    CASE s.token_out WHEN 'token0' THEN p.token0_address WHEN 'token1' THEN p.token1_address
+                        ELSE s.token_out END AS token_out,
+                    CASE WHEN s.token_in = 'token0' OR LOWER(s.token_in) = LOWER(p.token0_address) THEN p.token0_decimals
+                        WHEN s.token_in = 'token1' OR LOWER(s.token_in) = LOWER(p.token1_address) THEN p.token1_decimals
+                        END AS decimals_in,
+                    CASE WHEN s.token_out = 'token0' OR LOWER(s.token_out) = LOWER(p.token0_address) THEN p.token0_decimals
+                        WHEN s.token_out = 'token1' OR LOWER(s.token_out) = LOWER(p.token1_address) THEN p.token1_decimals
+                        END AS decimals_out,
+                    -- In-range reserves: L / sqrt(P) of token0 and L * sqrt(P) of token1
+                    CASE WHEN s.token_in = 'token0' OR LOWER(s.token_in) = LOWER(p.token0_address)
+                            THEN p.liquidity::FLOAT8 * (2::FLOAT8 ^ 96) / NULLIF(NULLIF(p.sqrt_price_x96, '')::NUMERIC::FLOAT8, 0)
+                        WHEN s.token_in = 'token1' OR LOWER(s.token_in) = LOWER(p.token1_address)
+                            THEN p.liquidity::FLOAT8 * NULLIF(p.sqrt_price_x96, '')::NUMERIC::FLOAT8 / (2::FLOAT8 ^ 96)
+                        END AS reserve_in
+                FROM swaps s
+                LEFT JOIN pools p ON p.pool_address = s.pool_address
+                WHERE s.chain_id = $1 AND s.timestamp >= $2 AND s.timestamp < $3 AND s.id > $4
+                    AND s.amount_in_usd IS NULL AND NOT s.usd_unpriced AND NOT s.usd_flagged
+                ORDER BY s.id
+                LIMIT $5
+                "#,
+            )
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .bind(after_id)
+            .bind(limit)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows
+                .iter()
+                .map(|row| UnpricedSwap {
+                    id: row.get("id"),
+                    timestamp: row.get("timestamp"),
+                    // Unknown pool: the tokens stay "token0"/"token1", which no price matches
+                    token_in: row.get::<Option<String>, _>("token_in").unwrap_or_default(),
+                    token_out: row.get::<Option<String>, _>("token_out").unwrap_or_default(),
+                    amount_in: row.get("amount_in"),
+                    amount_out: row.get("amount_out"),
+                    decimals_in: row.get("decimals_in"),
+                    decimals_out: row.get("decimals_out"),
+                    reserve_in: row.get("reserve_in"),
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Stores USD amounts as `(swap id, in, out)`, marks the `unpriced` swaps and the
+    /// `flagged` ones that failed the sanity checks, and moves the cursor to `position`, all
+    /// or nothing.
+    pub async fn store_swap_usd_amounts(
+        &self,
+        priced: &[(i64, f64, f64)],
+        unpriced: &[i64],
+        flagged: &[i64],
+        cursor: &str,
+        chain_id: i64,
+        position: i64,
+    ) -> Result<()> {
+        self.timed("store_swap_usd_amounts", Access::Write, async {
+            let mut tx = self.writer.get().begin().await?;
+
+            let ids: Vec<i64> = priced.iter().map(|(id, _, _)| *id).collect();
+            let amounts_in: Vec<f64> = priced.iter().map(|(_, amount_in_usd, _)| *amount_in_usd).collect();
+            let amounts_out: Vec<f64> = priced.iter().map(|(_, _, amount_out_usd)| *amount_out_usd).collect();
+            sqlx::query(
+                r#"
+                UPDATE swaps SET amount_in_usd = priced.amount_in_usd, amount_out_usd = priced.amount_out_usd
+                FROM UNNEST($1::BIGINT[], $2::FLOAT8[], $3::FLOAT8[]) AS priced(id, amount_in_usd, amount_out_usd)
+                WHERE swaps.id = priced.id
+                "#,
+            )
+            .bind(&ids)
+            .bind(&amounts_in)
+            .bind(&amounts_out)
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query("UPDATE swaps SET usd_unpriced = TRUE WHERE id = ANY($1::BIGINT[])")
+                .bind(unpriced)
+                .execute(&mut *tx)
+                .await?;
+
+            sqlx::query(
+                "UPDATE swaps SET amount_in_usd = NULL, amount_out_usd = NULL, usd_flagged = TRUE WHERE id = ANY($1::BIGINT[])",
+            )
+            .bind(flagged)
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query(
+                r#"
+                INSERT INTO sync_cursors (name, chain_id, position) VALUES ($1, $2, $3)
+                ON CONFLICT (name, chain_id) DO UPDATE SET position = EXCLUDED.position, updated_at = CURRENT_TIMESTAMP
+                "#,
+            )
+            .bind(cursor)
+            .bind(chain_id)
+            .bind(position)
+            .execute(&mut *tx)
+            .await?;
+
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn get_sync_cursor(&self, name: &str, chain_id: i64) -> Result<Option<i64>> {
+        self.timed("get_sync_cursor", Access::Read, async {
+            let position = sqlx::query_scalar("SELECT position FROM sync_cursors WHERE name = $1 AND chain_id = $2")
+                .bind(name)
+                .bind(chain_id)
+                .fetch_optional(self.reader.get())
+                .await?;
+
+            Ok(position)
+        })
+        .await
+    }
+
+    pub async fn set_sink_offset(&self, sink_name: &str, chain_id: i64, position: &TimelineCursor) -> Result<()> {
+        self.timed("set_sink_offset", Access::Write, async {
+            sqlx::query(
+                r#"
+                INSERT INTO sink_offsets (sink_name, chain_id, block_number, log_index, position) VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (sink_name, chain_id) DO UPDATE SET
+                    block_number = EXCLUDED.block_number, log_index = EXCLUDED.log_index,
+                    position = EXCLUDED.position, updated_at = CURRENT_TIMESTAMP
+                "#,
+            )
+            .bind(sink_name)
+            .bind(chain_id)
+            .bind(position.block_number)
+            .bind(position.log_index)
+            .bind(position.to_string())
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn get_sink_offset(&self, sink_name: &str, chain_id: i64) -> Result<Option<TimelineCursor>> {
+        self.timed("get_sink_offset", Access::Read, async {
+            let position: Option<String> = sqlx::query_scalar("SELECT position FROM sink_offsets WHERE sink_name = $1 AND chain_id = $2")
+                .bind(sink_name)
+                .bind(chain_id)
+                .fetch_optional(self.reader.get())
+                .await?;
+
+            position.map(|position| position.parse()).transpose()
+        })
+        .await
+    }
+
+    /// Refreshes the planner statistics of `table`, which must be one of ours.
+    pub async fn analyze_table(&self, table: &str) -> Result<()> {
+        self.timed("analyze_table", Access::Write, async {
+            // Table names cannot be bound; `table` comes from `maintenance::HOT_TABLES`
+            sqlx::query(&format!("ANALYZE {}", table)).execute(self.writer.get()).await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Sizes and tuple counts of those of `tables` in the current schema, by table name.
+    /// Counts are Postgres' estimates, as of the last ANALYZE or autovacuum.
+    pub async fn get_table_health(&self, tables: &[&str]) -> Result<Vec<TableHealth>> {
+        self.timed("get_table_health", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT
+                    relname::TEXT AS table_name,
+                    pg_table_size(relid) AS table_bytes,
+                    pg_indexes_size(relid) AS index_bytes,
+                    n_live_tup AS live_tuples,
+                    n_dead_tup AS dead_tuples,
+                    EXTRACT(EPOCH FROM GREATEST(last_vacuum, last_autovacuum))::BIGINT AS last_vacuum_at
+                FROM pg_stat_user_tables
+                WHERE schemaname = current_schema() AND relname = ANY($1)
+                ORDER BY relname
+                "#,
+            )
+            .bind(tables)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(table_health_from_row).collect())
+        })
+        .await
+    }
+
+    pub async fn insert_maintenance_log(&self, checked_at: i64, reports: &[TableReport]) -> Result<()> {
+        self.timed("insert_maintenance_log", Access::Write, async {
+            let mut tx = self.writer.get().begin().await?;
+            for report in reports {
+                let health = &report.health;
+                sqlx::query(
+                    r#"
+                    INSERT INTO maintenance_log (
+                        checked_at, table_name, table_bytes, index_bytes, live_tuples, dead_tuples,
+                        last_vacuum_at, needs_vacuum_full
+                    ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                    "#,
+                )
+                .bind(checked_at)
+                .bind(&health.table_name)
+                .bind(health.table_bytes)
+                .bind(health.index_bytes)
+                .bind(health.live_tuples)
+                .bind(health.dead_tuples)
+                .bind(health.last_vacuum_at)
+                .bind(report.needs_vacuum_full)
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// The most recent maintenance report of `table_name`, if any pass covered it.
+    pub async fn get_latest_maintenance_report(&self, table_name: &str) -> Result<Option<(i64, TableReport)>> {
+        self.timed("get_latest_maintenance_report", Access::Read, async {
+            let row = sqlx::query(
+                r#"
+                SELECT checked_at, table_name, table_bytes, index_bytes, live_tuples, dead_tuples,
+                    last_vacuum_at, needs_vacuum_full
+                FROM maintenance_log
+                WHERE table_name = $1
+                ORDER BY checked_at DESC, id DESC
+                LIMIT 1
+                "#,
+            )
+            .bind(table_name)
+            .fetch_optional(self.reader.get())
+            .await?;
+
+            Ok(row.map(|row| {
+                let report = TableReport { health: table_health_from_row(&row), needs_vacuum_full: row.get("needs_vacuum_full") };
+                (row.get("checked_at"), report)
+            }))
+        })
+        .await
+    }
+
+    /// Removes a pool and everything indexed for it: swaps, snapshots, tick history and
+    /// state, its watchlist entry and finally the `pools` row, which drops it from the swap
+    /// registry. Rows go in batches of `PURGE_BATCH_SIZE`, each its own transaction.
+    pub async fn delete_pool_data(&self, pool_address: &str, chain_id: i64) -> Result<PurgeCounts> {
+        let pool_address = normalize_address(pool_address);
+        let mut counts = PurgeCounts::default();
+        for table in POOL_DATA_TABLES.iter().copied().chain(["pools"]) {
+            let rows = self.delete_batched(table, "pool_address = $1 AND chain_id = $2", &pool_address, chain_id).await?;
+            counts.add(table, rows);
+        }
+        Ok(counts)
+    }
+
+    /// Removes a token, every pool trading it as `delete_pool_data` does, and its curve
+    /// trades, graduation, supply, holder and price history, in the same batches.
+    pub async fn delete_token_data(&self, token_address: &str, chain_id: i64) -> Result<PurgeCounts> {
+        let token_address = normalize_address(token_address);
+        let pools: Vec<String> = self
+            .timed("get_pools_of_token", Access::Read, async {
+                let pools = sqlx::query_scalar(
+                    "SELECT pool_address FROM pools WHERE (token0_address = $1 OR token1_address = $1) AND chain_id = $2",
+                )
+                .bind(&token_address)
+                .bind(chain_id)
+                .fetch_all(self.writer.get())
+                .await?;
+                Ok(pools)
+            })
+            .await?;
+
+        let mut counts = PurgeCounts::default();
+        for pool_address in pools {
+            counts.merge(self.delete_pool_data(&pool_address, chain_id).await?);
+        }
+
+        let token_filter = "token_address = $1 AND chain_id = $2";
+        for table in TOKEN_DATA_TABLES.iter().copied() {
+            counts.add(table, self.delete_batched(table, token_filter, &token_address, chain_id).await?);
+        }
+        let snapshot_filter = "snapshot_id IN (SELECT id FROM token_holder_snapshots WHERE token_address = $1 AND chain_id = $2)";
+        counts.add(
+            "token_holder_balances",
+            self.delete_batched("token_holder_balances", snapshot_filter, &token_address, chain_id).await?,
+        );
+        counts.add(
+            "token_holder_snapshots",
+            self.delete_batched("token_holder_snapshots", token_filter, &token_address, chain_id).await?,
+        );
+        counts.add("tokens", self.delete_batched("tokens", "address = $1 AND chain_id = $2", &token_address, chain_id).await?);
+        Ok(counts)
+    }
+
+    /// Deletes the rows of `table` matching `filter` (binding `$1` to `address` and `$2` to
+    /// `chain_id`) a batch at a time until none are left.
+    async fn delete_batched(&self, table: &str, filter: &str, address: &str, chain_id: i64) -> Result<u64> {
+        let statement = format!(
+            "DELETE FROM {table} WHERE ctid IN (SELECT ctid FROM {table} WHERE {filter} LIMIT {PURGE_BATCH_SIZE})"
+        );
+        let mut deleted = 0;
+        loop {
+            let rows = self
+                .timed("delete_batch", Access::Write, async {
+                    let result = sqlx::query(&statement).bind(address).bind(chain_id).execute(self.writer.get()).await?;
+                    Ok(result.rows_affected())
+                })
+                .await?;
+            deleted += rows;
+            if rows < PURGE_BATCH_SIZE as u64 {
+                return Ok(deleted);
+            }
+        }
+    }
+
+    /// The statements `rename_dex` runs, binding the old name, the new name and the chain.
+    pub fn rename_dex_statements() -> Vec<String> {
+        DEX_NAME_TABLES
+            .iter()
+            .map(|table| format!("UPDATE {} SET dex_name = $2 WHERE dex_name = $1 AND chain_id = $3", table))
+            .collect()
+    }
+
+    /// Relabels every row of the chain recorded under DEX `old_name` as `new_name`, in one
+    /// transaction. Returns the rows updated across all tables. `new_name` must be a DEX of
+    /// the registry deployed on the chain; `old_name` may be any stored label.
+    pub async fn rename_dex(&self, old_name: &str, new_name: &str, chain_id: i64) -> Result<u64> {
+        let new_dex = self.dexes.for_chain(new_name, chain_id)?;
+        self.timed("rename_dex", Access::Write, async {
+            warn!("Renaming DEX {} to {} on chain {}", old_name, new_name, chain_id);
+            let mut tx = self.writer.get().begin().await?;
+            let mut updated = 0;
+            for statement in Self::rename_dex_statements() {
+                updated += sqlx::query(&statement)
+                    .bind(old_name)
+                    .bind(new_dex.as_str())
+                    .bind(chain_id)
+                    .execute(&mut *tx)
+                    .await?
+                    .rows_affected();
+            }
+            tx.commit().await?;
+            Ok(updated)
+        })
+        .await
+    }
+
+    /// Adds the pool to the watchlist; false if it was already on it.
+    pub async fn insert_watched_pool(&self, pool_address: &str, chain_id: i64, added_at: i64) -> Result<bool> {
+        self.timed("insert_watched_pool", Access::Write, async {
+            let result = sqlx::query(
+                r#"
+                INSERT INTO watchlist (pool_address, chain_id, added_at)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (pool_address, chain_id) DO NOTHING
+                "#,
+            )
+            .bind(pool_address)
+            .bind(chain_id)
+            .bind(added_at)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(result.rows_affected() > 0)
+        })
+        .await
+    }
+
+    /// Watched pools of the chain, oldest first.
+    pub async fn get_watched_pools(&self, chain_id: i64) -> Result<Vec<String>> {
+        self.timed("get_watched_pools", Access::Read, async {
+            let pools = sqlx::query_scalar(
+                "SELECT pool_address FROM watchlist WHERE chain_id = $1 ORDER BY added_at, pool_address",
+            )
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(pools)
+        })
+        .await
+    }
+
+    pub async fn get_block_timestamp(&self, block_number: i64, chain_id: i64) -> Result<Option<i64>> {
+        self.timed("get_block_timestamp", Access::Read, async {
+            let timestamp = sqlx::query_scalar(
+                "SELECT timestamp FROM blocks WHERE block_number = $1 AND chain_id = $2"
+            )
+            .bind(block_number)
+            .bind(chain_id)
+            .fetch_optional(self.reader.get())
+            .await?;
+
+            Ok(timestamp)
+        })
+        .await
+    }
+
+    pub async fn get_pool(&self, pool_address: &str) -> Result<Option<PoolData>> {
+        self.timed("get_pool", Access::Read, async {
+            let row = sqlx::query(&format!(
+                "SELECT {} FROM pools WHERE pool_address = $1",
+                POOL_COLUMNS
+            ))
+            .bind(pool_address)
+            .fetch_optional(self.reader.get())
+            .await?;
+
+            Ok(row.as_ref().map(pool_from_row))
+        })
+        .await
+    }
+
+    pub async fn get_pools_by_tokens(&self, token0: &str, token1: &str) -> Result<Vec<PoolData>> {
+        self.timed("get_pools_by_tokens", Access::Read, async {
+            let rows = sqlx::query(&format!(
+                "SELECT {} FROM pools WHERE (token0_address = $1 AND token1_address = $2) OR (token0_address = $2 AND token1_address = $1)",
+                POOL_COLUMNS
+            ))
+            .bind(token0)
+            .bind(token1)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(pool_from_row).collect())
+        })
+        .await
+    }
+
+    /// Pool state as of `block_number`: the latest snapshot at or before the block merged
+    /// with the static pool fields. Pools with no snapshot yet report their creation-time
+    /// state; `None` means the pool is unknown or was created after the block.
+    pub async fn get_pool_at_block(&self, pool_address: &str, block_number: i64) -> Result<Option<PoolData>> {
+        self.timed("get_pool_at_block", Access::Read, async {
+            let mut pool = match self.get_pool(pool_address).await? {
+                Some(pool) => pool,
+                None => return Ok(None),
+            };
+
+            if pool.created_at_block.is_some_and(|created| created > block_number) {
+                return Ok(None);
+            }
+
+            let snapshot = sqlx::query(
+                r#"
+                SELECT liquidity, sqrt_price_x96, tick FROM pool_snapshots
+                WHERE pool_address = $1 AND chain_id = $2 AND block_number <= $3
+                ORDER BY block_number DESC
+                LIMIT 1
+                "#,
+            )
+            .bind(pool_address)
+            .bind(pool.chain_id)
+            .bind(block_number)
+            .fetch_optional(self.reader.get())
+            .await?;
+
+            match snapshot {
+                Some(row) => {
+                    pool.liquidity = row.get("liquidity");
+                    pool.sqrt_price_x96 = row.get("sqrt_price_x96");
+                    pool.tick = row.get("tick");
+                }
+                None => {
+                    pool.liquidity = Some(0);
+                    pool.sqrt_price_x96 = None;
+                    pool.tick = None;
+                }
+            }
+
+            Ok(Some(pool))
+        })
+        .await
+    }
+
+    /// Impermanent loss of a full-range position opened at `since_block` and held to the
+    /// pool's current tick, from `price::impermanent_loss`: assumes a 50/50 split by value
+    /// and no fees reinvested. The starting tick is the latest pool snapshot at or before
+    /// `since_block`, so the block must not predate the pool's first snapshot.
+    pub async fn get_impermanent_loss_estimate(&self, pool_address: &str, chain_id: i64, since_block: i64) -> Result<f64> {
+        self.timed("get_impermanent_loss_estimate", Access::Read, async {
+            let initial_tick: Option<i32> = sqlx::query_scalar(
+                r#"
+                SELECT tick FROM pool_snapshots
+                WHERE pool_address = $1 AND chain_id = $2 AND block_number <= $3 AND tick IS NOT NULL
+                ORDER BY block_number DESC
+                LIMIT 1
+                "#,
+            )
+            .bind(pool_address)
+            .bind(chain_id)
+            .bind(since_block)
+            .fetch_optional(self.reader.get())
+            .await?;
+            let Some(initial_tick) = initial_tick else {
+                bail!("pool {} has no tick history at or before block {}", pool_address, since_block);
+            };
+
+            let current_tick: Option<i32> = sqlx::query_scalar("SELECT tick FROM pools WHERE pool_address = $1 AND chain_id = $2")
+                .bind(pool_address)
+                .bind(chain_id)
+                .fetch_optional(self.reader.get())
+                .await?
+                .flatten();
+            let Some(current_tick) = current_tick else {
+                bail!("pool {} has no current tick", pool_address);
+            };
+
+            // Decimals scale both prices alike, so they cancel out of the ratio
+            let price_ratio = price::tick_to_price(current_tick, 0, 0) / price::tick_to_price(initial_tick, 0, 0);
+            Ok(price::impermanent_loss(price_ratio))
+        })
+        .await
+    }
+
+    /// Swap totals of a pool on `chain_id` up to and including `up_to_block`; with
+    /// `exclude_mev`, without sandwich and arbitrage legs. `volume_excluding_wash` also leaves
+    /// out suspected wash trades.
+    pub async fn get_cumulative_volume(&self, pool_address: &str, chain_id: i64, up_to_block: i64, exclude_mev: bool) -> Result<CumulativeVolume> {
+        self.timed("get_cumulative_volume", Access::Read, async {
+            let row = sqlx::query(
+                r#"
+                SELECT
+                    COUNT(*) AS swap_count,
+                    COALESCE(SUM(amount_in) FILTER (WHERE token_in = 'token0'), 0)::TEXT AS token0_in,
+                    COALESCE(SUM(amount_in) FILTER (WHERE token_in = 'token1'), 0)::TEXT AS token1_in,
+                    COALESCE(SUM(COALESCE((amount_in_usd + amount_out_usd) / 2, amount_in_usd, amount_out_usd)), 0)::FLOAT8
+                        AS volume_usd,
+                    COALESCE(SUM(COALESCE((amount_in_usd + amount_out_usd) / 2, amount_in_usd, amount_out_usd))
+                        FILTER (WHERE NOT is_suspected_wash), 0)::FLOAT8 AS volume_excluding_wash
+                FROM swaps
+                WHERE pool_address = $1 AND chain_id = $2 AND block_number <= $3
+                    AND (NOT $4 OR mev_type IS NULL OR mev_type = 'victim')
+                "#,
+            )
+            .bind(pool_address)
+            .bind(chain_id)
+            .bind(up_to_block)
+            .bind(exclude_mev)
+            .fetch_one(self.reader.get())
+            .await?;
+
+            Ok(CumulativeVolume {
+                pool_address: pool_address.to_string(),
+                up_to_block,
+                swap_count: row.get("swap_count"),
+                token0_in: row.get("token0_in"),
+                token1_in: row.get("token1_in"),
+                volume_usd: row.get("volume_usd"),
+                volume_excluding_wash: row.get("volume_excluding_wash"),
+            })
+        })
+        .await
+    }
+
+    /// Raw amounts the pool's swaps with `from_ts <= timestamp < to_ts` took in and paid out
+    /// of each token. Swap tokens match as `token0`/`token1` or as the pool's token address,
+    /// in any case. With `exclude_mev`, sandwich and arbitrage legs are left out.
+    pub async fn get_pool_volume_breakdown(
+        &self,
+        pool_address: &str,
+        chain_id: i64,
+        from_ts: i64,
+        to_ts: i64,
+        exclude_mev: bool,
+    ) -> Result<VolumeBreakdown> {
+        self.timed("get_pool_volume_breakdown", Access::Read, async {
+            let row = sqlx::query(
+                r#"
+                SELECT
+                    COALESCE(SUM(s.amount_in) FILTER (
+                        WHERE s.token_in = 'token0' OR LOWER(s.token_in) = LOWER(p.token0_address)), 0)::BIGINT AS token0_as_input,
+                    COALESCE(SUM(s.amount_out) FILTER (
+                        WHERE s.token_out = 'token0' OR LOWER(s.token_out) = LOWER(p.token0_address)), 0)::BIGINT AS token0_as_output,
+                    COALESCE(SUM(s.amount_in) FILTER (
+                        WHERE s.token_in = 'token1' OR LOWER(s.token_in) = LOWER(p.token1_address)), 0)::BIGINT AS token1_as_input,
+                    COALESCE(SUM(s.amount_out) FILTER (
+                        WHERE s.token_out = 'token1' OR LOWER(s.token_out) = LOWER(p.token1_address)), 0)::BIGINT AS token1_as_output,
+                    COUNT(s.id) AS total_swap_count
+                FROM pools p
+                LEFT JOIN swaps s ON s.pool_address = p.pool_address AND s.chain_id = p.chain_id
+                    AND s.timestamp >= $3 AND s.timestamp < $4
+                    AND (NOT $5 OR s.mev_type IS NULL OR s.mev_type = 'victim')
+                WHERE p.pool_address = $1 AND p.chain_id = $2
+                GROUP BY p.pool_address
+                "#,
+            )
+            .bind(pool_address)
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .bind(exclude_mev)
+            .fetch_optional(self.reader.get())
+            .await?;
+            let Some(row) = row else {
+                bail!("pool {} is not indexed on chain {}", pool_address, chain_id);
+            };
+
+            Ok(VolumeBreakdown {
+                token0_as_input: row.get("token0_as_input"),
+                token0_as_output: row.get("token0_as_output"),
+                token1_as_input: row.get("token1_as_input"),
+                token1_as_output: row.get("token1_as_output"),
+                total_swap_count: row.get::<i64, _>("total_swap_count") as u64,
+            })
+        })
+        .await
+    }
+
+    /// Fees earned per pool from swaps with `from_ts <= timestamp < to_ts`, highest USD
+    /// revenue first. Fees are charged on the input amount at the pool's `fee_tier` (in
+    /// hundredths of a bip). Pools with less than `min_volume_usd` of input volume in the
+    /// window are left out, as are pools without a known fee tier. With `exclude_mev`, fees
+    /// paid by sandwich and arbitrage legs are not counted.
+    pub async fn get_swap_fee_revenue_by_pool(
+        &self,
+        chain_id: i64,
+        from_ts: i64,
+        to_ts: i64,
+        min_volume_usd: f64,
+        exclude_mev: bool,
+    ) -> Result<Vec<PoolFeeRevenue>> {
+        self.timed("get_swap_fee_revenue_by_pool", Access::Read, async {
+            let missing_fee_tier: Vec<String> = sqlx::query_scalar(
+                r#"
+                SELECT DISTINCT s.pool_address
+                FROM swaps s
+                JOIN pools p ON p.pool_address = s.pool_address
+                WHERE s.chain_id = $1 AND s.timestamp >= $2 AND s.timestamp < $3 AND p.fee_tier IS NULL
+                "#,
+            )
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            if !missing_fee_tier.is_empty() {
+                warn!("Skipping fee revenue of {} pools without a fee tier: {}",
+                      missing_fee_tier.len(), missing_fee_tier.join(", "));
+            }
+
+            let rows = sqlx::query(
+                r#"
+                SELECT
+                    s.pool_address,
+                    DIV(COALESCE(SUM(s.amount_in) FILTER (WHERE s.token_in = 'token0'), 0) * MAX(p.fee_tier), 1000000)::BIGINT
+                        AS fee_revenue_token0_raw,
+                    DIV(COALESCE(SUM(s.amount_in) FILTER (WHERE s.token_in = 'token1'), 0) * MAX(p.fee_tier), 1000000)::BIGINT
+                        AS fee_revenue_token1_raw,
+                    (SUM(s.amount_in_usd) * MAX(p.fee_tier) / 1000000)::FLOAT8 AS fee_revenue_usd
+                FROM swaps s
+                JOIN pools p ON p.pool_address = s.pool_address
+                WHERE s.chain_id = $1 AND s.timestamp >= $2 AND s.timestamp < $3 AND p.fee_tier IS NOT NULL
+                    AND (NOT $5 OR s.mev_type IS NULL OR s.mev_type = 'victim')
+                GROUP BY s.pool_address
+                HAVING COALESCE(SUM(s.amount_in_usd), 0)::FLOAT8 >= $4
+                ORDER BY fee_revenue_usd DESC NULLS LAST, s.pool_address
+                "#,
+            )
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .bind(min_volume_usd)
+            .bind(exclude_mev)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows
+                .iter()
+                .map(|row| PoolFeeRevenue {
+                    pool_address: row.get("pool_address"),
+                    fee_revenue_token0_raw: row.get("fee_revenue_token0_raw"),
+                    fee_revenue_token1_raw: row.get("fee_revenue_token1_raw"),
+                    fee_revenue_usd: row.get("fee_revenue_usd"),
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// The top `limit` pools of a chain by `metric`, highest first with ties broken by
+    /// address. Pools without a value are left out: untraded ones from the 24-hour metrics,
+    /// unpriced ones from volume and fees, and unread ones from liquidity.
+    pub async fn get_pool_ranking(&self, chain_id: i64, metric: PoolRankingMetric, limit: usize) -> Result<Vec<PoolRank>> {
+        self.timed("get_pool_ranking", Access::Read, async {
+            let swaps_24h = |metric_value: &str| {
+                format!(
+                    "SELECT s.pool_address, {} AS metric_value, p.token0_symbol, p.token1_symbol, p.fee_tier \
+                     FROM swaps s JOIN pools p ON p.pool_address = s.pool_address \
+                     WHERE s.chain_id = $1 AND s.timestamp >= EXTRACT(EPOCH FROM NOW())::BIGINT - 86400 \
+                     GROUP BY s.pool_address, p.token0_symbol, p.token1_symbol, p.fee_tier",
+                    metric_value
+                )
+            };
+            let ranked = match metric {
+                PoolRankingMetric::Volume24h => {
+                    swaps_24h("SUM(COALESCE((s.amount_in_usd + s.amount_out_usd) / 2, s.amount_in_usd, s.amount_out_usd))::FLOAT8")
+                }
+                PoolRankingMetric::SwapCount24h => swaps_24h("COUNT(*)::FLOAT8"),
+                // Fees are charged on the input amount at the pool's fee tier, in hundredths of a bip
+                PoolRankingMetric::FeeRevenue24h => swaps_24h("(SUM(s.amount_in_usd) * p.fee_tier / 1000000)::FLOAT8"),
+                PoolRankingMetric::UniqueTraders24h => swaps_24h("COUNT(DISTINCT s.sender_address)::FLOAT8"),
+                PoolRankingMetric::Liquidity => "SELECT pool_address, liquidity::FLOAT8 AS metric_value, \
+                     token0_symbol, token1_symbol, fee_tier FROM pools WHERE chain_id = $1"
+                    .to_string(),
+            };
+
+            let rows = sqlx::query(&format!(
+                "SELECT * FROM ({}) ranked WHERE metric_value IS NOT NULL \
+                 ORDER BY metric_value DESC, pool_address LIMIT $2",
+                ranked
+            ))
+            .bind(chain_id)
+            .bind(limit as i64)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows
+                .iter()
+                .enumerate()
+                .map(|(index, row)| PoolRank {
+                    rank: index as u32 + 1,
+                    pool_address: row.get("pool_address"),
+                    metric_value: row.get("metric_value"),
+                    token0_symbol: row.get("token0_symbol"),
+                    token1_symbol: row.get("token1_symbol"),
+                    fee_tier: row.get("fee_tier"),
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Stretches of at least `min_gap` blocks between consecutive swaps of a pool, longest
+    /// first. Several swaps in one block count as one.
+    pub async fn get_consecutive_no_swap_blocks(&self, pool_address: &str, chain_id: i64, min_gap: i64) -> Result<Vec<BlockGap>> {
+        self.timed("get_consecutive_no_swap_blocks", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT from_block, to_block, gap_blocks FROM (
+                    SELECT
+                        LAG(block_number) OVER (ORDER BY block_number) AS from_block,
+                        block_number AS to_block,
+                        block_number - LAG(block_number) OVER (ORDER BY block_number) AS gap_blocks
+                    FROM (SELECT DISTINCT block_number FROM swaps WHERE pool_address = $1 AND chain_id = $2) AS swap_blocks
+                ) AS gaps
+                WHERE gap_blocks >= $3
+                ORDER BY gap_blocks DESC, from_block
+                "#,
+            )
+            .bind(pool_address)
+            .bind(chain_id)
+            .bind(min_gap)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows
+                .iter()
+                .map(|row| BlockGap {
+                    from_block: row.get("from_block"),
+                    to_block: row.get("to_block"),
+                    gap_blocks: row.get("gap_blocks"),
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// USD size distribution of a pool's swaps with `from_ts <= timestamp < to_ts`, by input
+    /// amount. Swaps without a USD amount are left out; fails when fewer than 10 remain.
+    pub async fn get_pool_swap_size_distribution(
+        &self,
+        pool_address: &str,
+        chain_id: i64,
+        from_ts: i64,
+        to_ts: i64,
+    ) -> Result<SwapSizeDistribution> {
+        self.timed("get_pool_swap_size_distribution", Access::Read, async {
+            // Ordered-set aggregates and AVG skip the NULL amounts; COUNT(*) still sees them
+            let row = sqlx::query(
+                r#"
+                SELECT
+                    COUNT(*) AS swaps,
+                    COUNT(amount_in_usd) AS priced_swaps,
+                    PERCENTILE_CONT(0.1) WITHIN GROUP (ORDER BY amount_in_usd::FLOAT8) AS p10,
+                    PERCENTILE_CONT(0.25) WITHIN GROUP (ORDER BY amount_in_usd::FLOAT8) AS p25,
+                    PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY amount_in_usd::FLOAT8) AS p50,
+                    PERCENTILE_CONT(0.75) WITHIN GROUP (ORDER BY amount_in_usd::FLOAT8) AS p75,
+                    PERCENTILE_CONT(0.9) WITHIN GROUP (ORDER BY amount_in_usd::FLOAT8) AS p90,
+                    PERCENTILE_CONT(0.99) WITHIN GROUP (ORDER BY amount_in_usd::FLOAT8) AS p99,
+                    AVG(amount_in_usd)::FLOAT8 AS mean
+                FROM swaps
+                WHERE pool_address = $1 AND chain_id = $2 AND timestamp >= $3 AND timestamp < $4
+                "#,
+            )
+            .bind(pool_address)
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .fetch_one(self.reader.get())
+            .await?;
+
+            let swaps: i64 = row.get("swaps");
+            let priced_swaps: i64 = row.get("priced_swaps");
+            if swaps > 0 && priced_swaps == 0 {
+                bail!("none of the {} swaps of pool {} in the window have a USD amount", swaps, pool_address);
+            }
+            if priced_swaps < MIN_DISTRIBUTION_SWAPS {
+                bail!("pool {} has {} priced swaps in the window, at least {} are needed",
+                      pool_address, priced_swaps, MIN_DISTRIBUTION_SWAPS);
+            }
+
+            Ok(SwapSizeDistribution {
+                p10: row.get("p10"),
+                p25: row.get("p25"),
+                p50: row.get("p50"),
+                p75: row.get("p75"),
+                p90: row.get("p90"),
+                p99: row.get("p99"),
+                mean: row.get("mean"),
+            })
+        })
+        .await
+    }
+
+    /// Approximate P&L of the swaps sent by `wallet_address` with `from_ts <= timestamp < to_ts`:
+    /// USD received minus USD spent, valued at each swap's own prices. Gas costs, transfers
+    /// outside the indexed pools and tokens still held are not accounted for. Fails when any of
+    /// the swaps lacks a USD amount, since the totals would silently undercount.
+    pub async fn get_wallet_pnl_estimate(
+        &self,
+        wallet_address: &str,
+        chain_id: i64,
+        from_ts: i64,
+        to_ts: i64,
+    ) -> Result<WalletPnL> {
+        self.timed("get_wallet_pnl_estimate", Access::Read, async {
+            let row = sqlx::query(
+                r#"
+                SELECT
+                    COUNT(*) AS swaps,
+                    COUNT(*) FILTER (WHERE amount_in_usd IS NULL OR amount_out_usd IS NULL) AS unpriced_swaps,
+                    COALESCE(SUM(amount_in_usd), 0)::FLOAT8 AS spent,
+                    COALESCE(SUM(amount_out_usd), 0)::FLOAT8 AS received
+                FROM swaps
+                WHERE sender_address = $1 AND chain_id = $2 AND timestamp >= $3 AND timestamp < $4
+                "#,
+            )
+            .bind(wallet_address)
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .fetch_one(self.reader.get())
+            .await?;
+
+            let swaps: i64 = row.get("swaps");
+            let unpriced_swaps: i64 = row.get("unpriced_swaps");
+            if unpriced_swaps > 0 {
+                bail!("{} of the {} swaps of wallet {} in the window have no USD amounts",
+                      unpriced_swaps, swaps, wallet_address);
+            }
+
+            let (spent, received): (f64, f64) = (row.get("spent"), row.get("received"));
+            Ok(WalletPnL {
+                total_spent_usd: spent,
+                total_received_usd: received,
+                net_pnl_usd: received - spent,
+                swap_count: swaps as u64,
+            })
+        })
+        .await
+    }
+
+    /// Senders with at least `min_swaps` swaps with `from_ts <= timestamp < to_ts`, most
+    /// active first, capped at 1000. Swaps without a known sender are left out.
+    pub async fn get_swap_count_per_sender(
+        &self,
+        chain_id: i64,
+        from_ts: i64,
+        to_ts: i64,
+        min_swaps: i64,
+    ) -> Result<Vec<SenderActivity>> {
+        self.timed("get_swap_count_per_sender", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT
+                    sender_address,
+                    COUNT(*) AS swap_count,
+                    SUM(amount_in_usd)::FLOAT8 AS total_volume_usd,
+                    COUNT(DISTINCT pool_address) AS unique_pools
+                FROM swaps
+                WHERE chain_id = $1 AND timestamp >= $2 AND timestamp < $3 AND sender_address IS NOT NULL
+                GROUP BY sender_address
+                HAVING COUNT(*) >= $4
+                ORDER BY swap_count DESC, sender_address
+                LIMIT $5
+                "#,
+            )
+            .bind(chain_id)
+            .bind(from_ts)
+            .bind(to_ts)
+            .bind(min_swaps)
+            .bind(MAX_SENDER_ACTIVITY)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows
+                .iter()
+                .map(|row| SenderActivity {
+                    sender_address: row.get("sender_address"),
+                    swap_count: row.get::<i64, _>("swap_count") as u64,
+                    total_volume_usd: row.get("total_volume_usd"),
+                    unique_pools: row.get::<i64, _>("unique_pools") as u64,
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Swap counts per UTC hour of day; index 0 is 00:00-00:59.
+    pub async fn get_pool_active_hours(&self, pool_address: &str, chain_id: i64) -> Result<Vec<u32>> {
+        self.timed("get_pool_active_hours", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT (timestamp % 86400) / 3600 AS hour, COUNT(*) AS swap_count
+                FROM swaps
+                WHERE pool_address = $1 AND chain_id = $2
+                GROUP BY hour
+                ORDER BY hour
+                "#,
+            )
+            .bind(pool_address)
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(bucket_counts(&rows, "hour", 24))
+        })
+        .await
+    }
+
+    /// Swap counts per UTC day of week; index 0 is Sunday.
+    pub async fn get_pool_active_days_of_week(&self, pool_address: &str, chain_id: i64) -> Result<Vec<u32>> {
+        self.timed("get_pool_active_days_of_week", Access::Read, async {
+            // The unix epoch fell on a Thursday (day 4)
+            let rows = sqlx::query(
+                r#"
+                SELECT (timestamp / 86400 + 4) % 7 AS day, COUNT(*) AS swap_count
+                FROM swaps
+                WHERE pool_address = $1 AND chain_id = $2
+                GROUP BY day
+                ORDER BY day
+                "#,
+            )
+            .bind(pool_address)
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(bucket_counts(&rows, "day", 7))
+        })
+        .await
+    }
+
+    pub async fn get_all_pool_addresses(&self) -> Result<Vec<String>> {
+        self.timed("get_all_pool_addresses", Access::Read, async {
+            let rows = sqlx::query("SELECT pool_address FROM pools")
+                .fetch_all(self.reader.get())
+                .await?;
+
+            let addresses = rows.into_iter()
+                .map(|row| row.get("pool_address"))
+                .collect();
+
+            Ok(addresses)
+        })
+        .await
+    }
+
+    pub async fn get_pool_addresses_by_status(&self, status: PoolStatus) -> Result<Vec<String>> {
+        self.timed("get_pool_addresses_by_status", Access::Read, async {
+            let rows = sqlx::query("SELECT pool_address FROM pools WHERE status = $1 AND tracked")
+                .bind(status.as_str())
+                .fetch_all(self.reader.get())
+                .await?;
+
+            let addresses = rows.into_iter()
+                .map(|row| row.get("pool_address"))
+                .collect();
+
+            Ok(addresses)
+        })
+        .await
+    }
+
+    pub async fn get_pools_created_in_block_range(
+        &self,
+        chain_id: i64,
+        from_block: i64,
+        to_block: i64,
+    ) -> Result<Vec<PoolData>> {
+        self.timed("get_pools_created_in_block_range", Access::Read, async {
+            let rows = sqlx::query(&format!(
+                "SELECT {} FROM pools WHERE chain_id = $1 AND created_at_block BETWEEN $2 AND $3 ORDER BY created_at_block ASC",
+                POOL_COLUMNS
+            ))
+            .bind(chain_id)
+            .bind(from_block)
+            .bind(to_block)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(pool_from_row).collect())
+        })
+        .await
+    }
+
+    /// Pools with liquidity in `[min_liquidity, max_liquidity]`, deepest first. Pools whose
+    /// liquidity was never read (`NULL`) are excluded; see `get_pools_with_null_liquidity`.
+    pub async fn get_pools_by_liquidity_range(
+        &self,
+        chain_id: i64,
+        min_liquidity: i64,
+        max_liquidity: i64,
+    ) -> Result<Vec<PoolData>> {
+        self.timed("get_pools_by_liquidity_range", Access::Read, async {
+            let rows = sqlx::query(&format!(
+                "SELECT {} FROM pools WHERE chain_id = $1 AND liquidity BETWEEN $2 AND $3 ORDER BY liquidity DESC",
+                POOL_COLUMNS
+            ))
+            .bind(chain_id)
+            .bind(min_liquidity)
+            .bind(max_liquidity)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(pool_from_row).collect())
+        })
+        .await
+    }
+
+    /// Pools of the chain with the fee tier, deepest first.
+    pub async fn get_pools_by_fee_tier(&self, fee_tier: i32, chain_id: i64) -> Result<Vec<PoolData>> {
+        self.timed("get_pools_by_fee_tier", Access::Read, async {
+            let rows = sqlx::query(&format!(
+                "SELECT {} FROM pools WHERE fee_tier = $1 AND chain_id = $2 ORDER BY liquidity DESC NULLS LAST, pool_address",
+                POOL_COLUMNS
+            ))
+            .bind(fee_tier)
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(pool_from_row).collect())
+        })
+        .await
+    }
+
+    /// `(fee_tier, pool_count)` of each fee tier of the chain, most pools first. Pools whose
+    /// fee tier was never read are left out.
+    pub async fn get_fee_tier_distribution(&self, chain_id: i64) -> Result<Vec<(i32, u64)>> {
+        self.timed("get_fee_tier_distribution", Access::Read, async {
+            let rows: Vec<(i32, i64)> = sqlx::query_as(
+                r#"
+                SELECT fee_tier, COUNT(*) AS pool_count FROM pools
+                WHERE chain_id = $1 AND fee_tier IS NOT NULL
+                GROUP BY fee_tier
+                ORDER BY pool_count DESC, fee_tier
+                "#,
+            )
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.into_iter().map(|(fee_tier, pool_count)| (fee_tier, pool_count as u64)).collect())
+        })
+        .await
+    }
+
+    pub async fn get_pools_with_null_liquidity(&self, chain_id: i64) -> Result<Vec<String>> {
+        self.timed("get_pools_with_null_liquidity", Access::Read, async {
+            let rows = sqlx::query("SELECT pool_address FROM pools WHERE chain_id = $1 AND liquidity IS NULL ORDER BY pool_address")
+                .bind(chain_id)
+                .fetch_all(self.reader.get())
+                .await?;
+
+            Ok(rows.iter().map(|row| row.get("pool_address")).collect())
+        })
+        .await
+    }
+
+    /// Pools whose state was never read, such as those added by a factory scan.
+    pub async fn count_pools_with_null_liquidity(&self, chain_id: i64) -> Result<u64> {
+        self.timed("count_pools_with_null_liquidity", Access::Read, async {
+            let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pools WHERE chain_id = $1 AND liquidity IS NULL")
+                .bind(chain_id)
+                .fetch_one(self.reader.get())
+                .await?;
+
+            Ok(count as u64)
+        })
+        .await
+    }
+
+    /// New pools per hour over the last `interval_hours`, by the time they were indexed.
+    pub async fn get_pool_creation_rate(&self, chain_id: i64, interval_hours: u32) -> Result<f64> {
+        self.timed("get_pool_creation_rate", Access::Read, async {
+            if interval_hours == 0 {
+                return Ok(0.0);
+            }
+
+            let created: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM pools WHERE chain_id = $1 AND created_at >= NOW() - make_interval(hours => $2)"
+            )
+            .bind(chain_id)
+            .bind(interval_hours as i32)
+            .fetch_one(self.reader.get())
+            .await?;
+
+            Ok(created as f64 / interval_hours as f64)
+        })
+        .await
+    }
+
+    /// Pools of a chain whose row changed at or after `since` (unix seconds), oldest change
+    /// first, up to `limit` per page, only those of `dex` when given. Pass a page's
+    /// `next_cursor` to continue after it.
+    pub async fn get_pools_updated_since(
+        &self,
+        chain_id: i64,
+        since: i64,
+        dex: Option<DexId>,
+        limit: i64,
+        cursor: Option<&PoolUpdateCursor>,
+    ) -> Result<PoolUpdatesPage> {
+        self.timed("get_pools_updated_since", Access::Read, async {
+            let (after_ts, after_pool) = cursor.map_or((since, ""), |cursor| (cursor.updated_at, &cursor.pool_address));
+            let rows = sqlx::query(&format!(
+                "SELECT {} FROM pools \
+                 WHERE chain_id = $1 \
+                     AND date_trunc('second', updated_at) >= TIMESTAMP 'epoch' + $2 * INTERVAL '1 second' \
+                     AND (date_trunc('second', updated_at), pool_address) > (TIMESTAMP 'epoch' + $3 * INTERVAL '1 second', $4) \
+                     AND ($6::TEXT IS NULL OR dex_name = $6) \
+                 ORDER BY date_trunc('second', updated_at), pool_address \
+                 LIMIT $5",
+                POOL_COLUMNS
+            ))
+            .bind(chain_id)
+            .bind(since)
+            .bind(after_ts)
+            .bind(after_pool)
+            .bind(limit + 1)
+            .bind(dex.as_ref().map(DexId::as_str))
+            .fetch_all(self.reader.get())
+            .await?;
+
+            let mut pools: Vec<PoolData> = rows.iter().map(pool_from_row).collect();
+            let next_cursor = if pools.len() as i64 > limit {
+                pools.truncate(limit.max(0) as usize);
+                pools.last().map(|pool| PoolUpdateCursor::of(pool).to_string())
+            } else {
+                None
+            };
+
+            Ok(PoolUpdatesPage { pools, next_cursor })
+        })
+        .await
+    }
+
+    /// Up to `limit` pools of a chain from `v_pool_stats`, by USD volume; pools without
+    /// priced swaps follow, by address.
+    pub async fn query_pool_stats_view(&self, chain_id: i64, limit: usize) -> Result<Vec<PoolStats>> {
+        self.timed("query_pool_stats_view", Access::Read, async {
+            let rows = sqlx::query(&format!(
+                "SELECT {}, swap_count, last_swap_ts, volume_usd::FLOAT8 AS volume_usd FROM v_pool_stats \
+                 WHERE chain_id = $1 \
+                 ORDER BY volume_usd DESC NULLS LAST, pool_address \
+                 LIMIT $2",
+                POOL_COLUMNS
+            ))
+            .bind(chain_id)
+            .bind(limit as i64)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows
+                .iter()
+                .map(|row| PoolStats {
+                    pool: pool_from_row(row),
+                    swap_count: row.get("swap_count"),
+                    last_swap_ts: row.get("last_swap_ts"),
+                    volume_usd: row.get("volume_usd"),
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// Up to `limit` pools of a chain, most recently swapped first. Pools without a swap in
+    /// the last `lookback_hours` follow, by address.
+    pub async fn get_pools_sorted_by_recent_activity(
+        &self,
+        chain_id: i64,
+        lookback_hours: u32,
+        limit: i64,
+    ) -> Result<Vec<PoolData>> {
+        self.timed("get_pools_sorted_by_recent_activity", Access::Read, async {
+            let rows = sqlx::query(&format!(
+                "SELECT {} FROM pools \
+                 LEFT JOIN ( \
+                     SELECT pool_address AS active_pool, MAX(timestamp) AS last_swap FROM swaps \
+                     WHERE chain_id = $1 AND timestamp > EXTRACT(EPOCH FROM NOW())::BIGINT - $2 * 3600 \
+                     GROUP BY pool_address \
+                 ) recent ON recent.active_pool = pools.pool_address \
+                 WHERE chain_id = $1 \
+                 ORDER BY recent.last_swap DESC NULLS LAST, pool_address \
+                 LIMIT $3",
+                POOL_COLUMNS
+            ))
+            .bind(chain_id)
+            .bind(lookback_hours as i64)
+            .bind(limit)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            Ok(rows.iter().map(pool_from_row).collect())
+        })
+        .await
+    }
+
+    pub async fn get_all_pools(&self) -> Result<Vec<PoolData>> {
+        self.timed("get_all_pools", Access::Read, async {
+            let rows = sqlx::query(&format!("SELECT {} FROM pools", POOL_COLUMNS))
+                .fetch_all(self.reader.get())
+                .await?;
+
+            Ok(rows.iter().map(pool_from_row).collect())
+        })
+        .await
+    }
+
+    pub async fn get_untracked_pool_addresses(&self) -> Result<Vec<String>> {
+        self.timed("get_untracked_pool_addresses", Access::Read, async {
+            let rows = sqlx::query("SELECT pool_address FROM pools WHERE NOT tracked")
+                .fetch_all(self.reader.get())
+                .await?;
+
+            Ok(rows.into_iter().map(|row| row.get("pool_address")).collect())
+        })
+        .await
+    }
+
+    /// Whether the pool is in the swap registry, as decided by the indexing scope.
+    pub async fn set_pool_tracked(&self, pool_address: &str, tracked: bool) -> Result<()> {
+        self.timed("set_pool_tracked", Access::Write, async {
+            sqlx::query("UPDATE pools SET tracked = $2, updated_at = CURRENT_TIMESTAMP WHERE pool_address = $1")
+                .bind(pool_address)
+                .bind(tracked)
+                .execute(self.writer.get())
+                .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn get_pool_status(&self, pool_address: &str) -> Result<Option<PoolStatus>> {
+        self.timed("get_pool_status", Access::Read, async {
+            let status: Option<String> = sqlx::query_scalar("SELECT status FROM pools WHERE pool_address = $1")
+                .bind(pool_address)
+                .fetch_optional(self.reader.get())
+                .await?;
+
+            Ok(status.as_deref().and_then(PoolStatus::parse))
+        })
+        .await
+    }
+
+    pub async fn set_pool_status(&self, pool_address: &str, status: PoolStatus) -> Result<()> {
+        self.timed("set_pool_status", Access::Write, async {
+            sqlx::query("UPDATE pools SET status = $2, updated_at = CURRENT_TIMESTAMP WHERE pool_address = $1")
+                .bind(pool_address)
+                .bind(status.as_str())
+                .execute(self.writer.get())
+                .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Every pool of a chain with its status and last activity: the latest swap, or its
+    /// creation time if it never traded.
+    pub async fn get_pool_activity(&self, chain_id: i64) -> Result<Vec<(String, PoolStatus, i64)>> {
+        self.timed("get_pool_activity", Access::Read, async {
+            let rows = sqlx::query(
+                r#"
+                SELECT p.pool_address, p.status,
+                    COALESCE(MAX(s.timestamp), EXTRACT(EPOCH FROM p.created_at)::BIGINT) AS last_activity
+                FROM pools p
+                LEFT JOIN swaps s ON s.pool_address = p.pool_address AND s.chain_id = p.chain_id
+                WHERE p.chain_id = $1
+                GROUP BY p.pool_address, p.status, p.created_at
+                "#,
+            )
+            .bind(chain_id)
+            .fetch_all(self.reader.get())
+            .await?;
+
+            let activity = rows.into_iter()
+                .filter_map(|row| {
+                    let status: String = row.get("status");
+                    PoolStatus::parse(&status)
+                        .map(|status| (row.get("pool_address"), status, row.get("last_activity")))
+                })
+                .collect();
+
+            Ok(activity)
+        })
+        .await
+    }
+
+    /// Dashboard totals for a chain in one round trip. The 24-hour windows end now: active
+    /// pools by swap timestamp, new pools by the time they were indexed.
+    pub async fn get_protocol_stats(&self, chain_id: i64) -> Result<ProtocolStats> {
+        self.timed("get_protocol_stats", Access::Read, async {
+            let row = sqlx::query(
+                r#"
+                WITH chain_pools AS (
+                    SELECT pool_address, token0_address, token1_address, created_at, created_at_block
+                    FROM pools WHERE chain_id = $1
+                ), chain_swaps AS (
+                    SELECT pool_address, COALESCE((amount_in_usd + amount_out_usd) / 2, amount_in_usd, amount_out_usd) AS volume_usd,
+                        timestamp, block_number
+                    FROM swaps WHERE chain_id = $1
+                )
+                SELECT
+                    (SELECT COUNT(*) FROM chain_pools) AS total_pools,
+                    (SELECT COUNT(*) FROM chain_swaps) AS total_swaps,
+                    (SELECT SUM(volume_usd)::FLOAT8 FROM chain_swaps) AS total_volume_usd,
+                    (SELECT COUNT(DISTINCT pool_address) FROM chain_swaps
+                        WHERE timestamp >= EXTRACT(EPOCH FROM NOW())::BIGINT - 86400) AS active_pools_24h,
+                    (SELECT COUNT(*) FROM chain_pools
+                        WHERE created_at >= NOW() - INTERVAL '24 hours') AS new_pools_24h,
+                    (SELECT COUNT(*) FROM (
+                        SELECT token0_address FROM chain_pools
+                        UNION SELECT token1_address FROM chain_pools
+                    ) tokens) AS unique_tokens,
+                    COALESCE(GREATEST(
+                        (SELECT MAX(block_number) FROM chain_swaps),
+                        (SELECT MAX(created_at_block) FROM chain_pools)
+                    ), 0) AS last_block
+                "#,
+            )
+            .bind(chain_id)
+            .fetch_one(self.reader.get())
             .await?;
 
-        let swap_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM swaps")
-            .fetch_one(&self.pool)
+            let count = |column: &str| row.get::<i64, _>(column) as u64;
+            Ok(ProtocolStats {
+                total_pools: count("total_pools"),
+                total_swaps: count("total_swaps"),
+                total_volume_usd: row.get("total_volume_usd"),
+                active_pools_24h: count("active_pools_24h"),
+                new_pools_24h: count("new_pools_24h"),
+                unique_tokens: count("unique_tokens"),
+                last_block: row.get("last_block"),
+            })
+        })
+        .await
+    }
+
+    /// Adds a committed range's new pools and stored swaps to the chain and DEX's stats row
+    /// and moves its cursor, in one statement so readers never see half an update. The DEX
+    /// is checked against the registry like `upsert_pool`'s.
+    pub async fn record_range_stats(
+        &self,
+        chain_id: i64,
+        dex: &DexId,
+        last_processed_block: i64,
+        head_block: i64,
+        new_pools: i64,
+        new_swaps: i64,
+    ) -> Result<()> {
+        self.dexes.check_chain(dex, chain_id)?;
+        self.timed("record_range_stats", Access::Write, async {
+            sqlx::query(
+                r#"
+                INSERT INTO indexing_stats
+                    (chain_id, dex_name, last_processed_block, head_block, total_pools_indexed, total_swaps_indexed, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $6, EXTRACT(EPOCH FROM NOW())::BIGINT)
+                ON CONFLICT (chain_id, dex_name) DO UPDATE SET
+                    last_processed_block = GREATEST(indexing_stats.last_processed_block, EXCLUDED.last_processed_block),
+                    head_block = GREATEST(indexing_stats.head_block, EXCLUDED.head_block),
+                    total_pools_indexed = indexing_stats.total_pools_indexed + EXCLUDED.total_pools_indexed,
+                    total_swaps_indexed = indexing_stats.total_swaps_indexed + EXCLUDED.total_swaps_indexed,
+                    updated_at = EXCLUDED.updated_at
+                "#,
+            )
+            .bind(chain_id)
+            .bind(dex.as_str())
+            .bind(last_processed_block)
+            .bind(head_block)
+            .bind(new_pools)
+            .bind(new_swaps)
+            .execute(self.writer.get())
+            .await?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Every chain and DEX's stats row, by chain then DEX.
+    pub async fn get_all_stats(&self) -> Result<Vec<IndexingStats>> {
+        self.timed("get_all_stats", Access::Read, async {
+            let rows = sqlx::query(
+                "SELECT chain_id::BIGINT AS chain_id, dex_name, last_processed_block, head_block, \
+                     total_pools_indexed, total_swaps_indexed, updated_at \
+                 FROM indexing_stats ORDER BY chain_id, dex_name",
+            )
+            .fetch_all(self.reader.get())
             .await?;
 
-        Ok((pool_count as u64, swap_count as u64))
+            Ok(rows
+                .iter()
+                .map(|row| IndexingStats {
+                    last_processed_block: row.get("last_processed_block"),
+                    total_pools_indexed: row.get("total_pools_indexed"),
+                    total_swaps_indexed: row.get("total_swaps_indexed"),
+                    chain_id: row.get("chain_id"),
+                    dex_name: DexId::from_stored(row.get("dex_name")),
+                    updated_at: row.get("updated_at"),
+                    head_block: row.get("head_block"),
+                })
+                .collect())
+        })
+        .await
+    }
+
+    pub async fn get_stats(&self) -> Result<(u64, u64)> {
+        self.timed("get_stats", Access::Read, async {
+            let pool_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pools")
+                .fetch_one(self.reader.get())
+                .await?;
+
+            let swap_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM swaps")
+                .fetch_one(self.reader.get())
+                .await?;
+
+            Ok((pool_count as u64, swap_count as u64))
+        })
+        .await
+    }
+}
+
+// Shared by the live price writes and `replace_price_buckets`
+async fn insert_native_price(tx: &mut sqlx::Transaction<'_, Postgres>, chain_id: i64, price: &NativePrice) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO native_prices (block_number, chain_id, timestamp, price_usd, pool_address) VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (block_number, chain_id) DO UPDATE SET
+            timestamp = EXCLUDED.timestamp, price_usd = EXCLUDED.price_usd, pool_address = EXCLUDED.pool_address
+        "#,
+    )
+    .bind(price.block_number)
+    .bind(chain_id)
+    .bind(price.timestamp)
+    .bind(price.price_usd)
+    .bind(&price.pool_address)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+async fn upsert_token_usd_price(tx: &mut sqlx::Transaction<'_, Postgres>, chain_id: i64, bucket: &TokenPriceBucket) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO token_usd_prices (token_address, chain_id, bucket_start, price_usd) VALUES ($1, $2, $3, $4)
+        ON CONFLICT (token_address, chain_id, bucket_start) DO UPDATE SET price_usd = EXCLUDED.price_usd
+        "#,
+    )
+    .bind(normalize_address(&bucket.token_address))
+    .bind(chain_id)
+    .bind(bucket.bucket_start)
+    .bind(bucket.price_usd)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+// What the CHECK constraints on the `dex_name` columns hold of `name`, as `DexId::new`
+// requires
+fn dex_name_check(name: &str) -> String {
+    format!("char_length({name}) BETWEEN 1 AND {} AND {name} ~ '^[a-z0-9-]+$'", MAX_DEX_ID_LEN, name = name)
+}
+
+fn bucket_counts(rows: &[PgRow], bucket_column: &str, buckets: usize) -> Vec<u32> {
+    let mut counts = vec![0u32; buckets];
+    for row in rows {
+        let bucket: i64 = row.get(bucket_column);
+        let count: i64 = row.get("swap_count");
+        counts[bucket as usize] = count as u32;
+    }
+    counts
+}
+
+fn pool_from_row(row: &PgRow) -> PoolData {
+    PoolData {
+        pool_address: row.get("pool_address"),
+        token0_address: row.get("token0_address"),
+        token1_address: row.get("token1_address"),
+        token0_symbol: row.get("token0_symbol"),
+        token1_symbol: row.get("token1_symbol"),
+        token0_decimals: row.get("token0_decimals"),
+        token1_decimals: row.get("token1_decimals"),
+        fee_tier: row.get("fee_tier"),
+        tick_spacing: row.get("tick_spacing"),
+        liquidity: row.get("liquidity"),
+        sqrt_price_x96: row.get("sqrt_price_x96"),
+        tick: row.get("tick"),
+        chain_id: row.get("chain_id"),
+        dex_name: DexId::from_stored(row.get("dex_name")),
+        created_at_block: row.get("created_at_block"),
+        updated_at_block: row.get("updated_at_block"),
+        has_nonstandard_token: row.get("has_nonstandard_token"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+fn curve_trade_from_row(row: &PgRow) -> Result<CurveTrade> {
+    let side: String = row.get("side");
+
+    Ok(CurveTrade {
+        tx_hash: row.get("tx_hash"),
+        log_index: row.get("log_index"),
+        block_number: row.get("block_number"),
+        timestamp: row.get("timestamp"),
+        chain_id: row.get("chain_id"),
+        curve_address: row.get("curve_address"),
+        token_address: row.get("token_address"),
+        trader: row.get("trader"),
+        side: TradeSide::parse(&side).ok_or_else(|| anyhow::anyhow!("unknown curve trade side {}", side))?,
+        token_amount: row.get("token_amount"),
+        collateral_amount: row.get("collateral_amount"),
+    })
+}
+
+fn migration_from_row(row: &PgRow) -> TokenMigration {
+    TokenMigration {
+        token_address: row.get("token_address"),
+        curve_address: row.get("curve_address"),
+        pool_address: row.get("pool_address"),
+        tx_hash: row.get("tx_hash"),
+        block_number: row.get("block_number"),
+        log_index: row.get("log_index"),
+        timestamp: row.get("timestamp"),
+        final_curve_price: row.get("final_curve_price"),
+        chain_id: row.get("chain_id"),
+    }
+}
+
+fn table_health_from_row(row: &PgRow) -> TableHealth {
+    TableHealth {
+        table_name: row.get("table_name"),
+        table_bytes: row.get("table_bytes"),
+        index_bytes: row.get("index_bytes"),
+        live_tuples: row.get("live_tuples"),
+        dead_tuples: row.get("dead_tuples"),
+        last_vacuum_at: row.get("last_vacuum_at"),
+    }
+}
+
+fn swap_from_row(row: &PgRow) -> SwapEvent {
+    SwapEvent {
+        tx_hash: row.get("tx_hash"),
+        pool_address: row.get("pool_address"),
+        token_in: row.get("token_in"),
+        token_out: row.get("token_out"),
+        amount_in: row.get("amount_in"),
+        amount_out: row.get("amount_out"),
+        amount_in_usd: row.get("amount_in_usd"),
+        amount_out_usd: row.get("amount_out_usd"),
+        timestamp: row.get("timestamp"),
+        block_number: row.get("block_number"),
+        log_index: row.get("log_index"),
+        chain_id: row.get("chain_id"),
+        sender_address: row.get("sender_address"),
+        recipient_address: row.get("recipient_address"),
+        indexed_at: row.get("indexed_at"),
+        calldata: row.get::<Option<Vec<u8>>, _>("calldata").map(HexBytes::from),
+        tick: None,
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-    use crate::types::{PoolData, SwapEvent};
+    use super::TimelineCursor;
+    use crate::dex::MOONSHOT;
+    use crate::types::PoolData;
+
+    #[test]
+    fn test_timeline_cursor_round_trip() {
+        let cursor: TimelineCursor = "12:-1:0:0xabc".parse().unwrap();
+        assert_eq!(cursor.to_string(), "12:-1:0:0xabc");
+        assert!(cursor < "12:0:1:0x000".parse().unwrap());
+
+        assert!("12:-1:0".parse::<TimelineCursor>().is_err());
+        assert!("twelve:0:0:0xabc".parse::<TimelineCursor>().is_err());
+    }
 
     #[tokio::test]
     async fn test_database_operations() {
@@ -259,7 +4640,12 @@ mod tests {
             sqrt_price_x96: Some("123456789".to_string()),
             tick: Some(1000),
             chain_id: 1,
-            dex_name: "moonshot".to_string(),
+            dex_name: MOONSHOT,
+            created_at_block: None,
+            updated_at_block: None,
+            has_nonstandard_token: false,
+            created_at: None,
+            updated_at: None,
         };
 
         assert_eq!(pool.pool_address, "0x1234567890123456789012345678901234567890");
@@ -0,0 +1,337 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::chain::chain_info;
+use crate::config::Config;
+use crate::moonshot::DEX_NAME;
+
+/// How the logs of a DEX are decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandlerKind {
+    /// Moonshot's own factory and pool events.
+    Moonshot,
+    /// A Uniswap V3 fork described by an `EVENT_SCHEMA_FILE` descriptor.
+    GenericV3,
+}
+
+impl HandlerKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Moonshot => "moonshot",
+            Self::GenericV3 => "generic-v3",
+        }
+    }
+}
+
+impl FromStr for HandlerKind {
+    type Err = anyhow::Error;
+
+    fn from_str(handler: &str) -> Result<Self> {
+        match handler.trim().to_ascii_lowercase().as_str() {
+            "moonshot" => Ok(Self::Moonshot),
+            "generic-v3" => Ok(Self::GenericV3),
+            _ => bail!("unknown handler {:?}; expected moonshot or generic-v3", handler),
+        }
+    }
+}
+
+impl fmt::Display for HandlerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Longest DEX id, well within the `VARCHAR(50)` columns holding them.
+pub const MAX_DEX_ID_LEN: usize = 32;
+
+/// The DEX of the pools this crate decodes natively.
+pub const MOONSHOT: DexId = DexId(Cow::Borrowed(DEX_NAME));
+
+/// A DEX id in canonical form: 1 to `MAX_DEX_ID_LEN` lowercase ASCII letters, digits and
+/// dashes, the form the `dex_name` columns are constrained to. Whether the DEX is known is
+/// up to a `DexRegistry`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DexId(Cow<'static, str>);
+
+impl DexId {
+    /// `id` in canonical form, ignoring case and surrounding whitespace.
+    pub fn new(id: &str) -> Result<Self> {
+        let normalized = id.trim().to_ascii_lowercase();
+        if !is_canonical(&normalized) {
+            bail!(
+                "invalid DEX id {:?}: expected 1 to {} letters, digits and dashes",
+                id,
+                MAX_DEX_ID_LEN
+            );
+        }
+        Ok(Self(Cow::Owned(normalized)))
+    }
+
+    /// An id read back from a `dex_name` column, whose CHECK constraint keeps it canonical.
+    pub(crate) fn from_stored(id: String) -> Self {
+        debug_assert!(is_canonical(&id), "stored DEX id {:?} is not canonical", id);
+        Self(Cow::Owned(id))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+fn is_canonical(id: &str) -> bool {
+    (1..=MAX_DEX_ID_LEN).contains(&id.len())
+        && id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+impl FromStr for DexId {
+    type Err = anyhow::Error;
+
+    fn from_str(id: &str) -> Result<Self> {
+        Self::new(id)
+    }
+}
+
+impl TryFrom<String> for DexId {
+    type Error = anyhow::Error;
+
+    fn try_from(id: String) -> Result<Self> {
+        Self::new(&id)
+    }
+}
+
+impl From<DexId> for String {
+    fn from(id: DexId) -> Self {
+        id.0.into_owned()
+    }
+}
+
+impl PartialEq<str> for DexId {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for DexId {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl fmt::Display for DexId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Padded, so ids line up in tables
+        f.pad(&self.0)
+    }
+}
+
+/// Per-DEX metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DexInfo {
+    pub id: DexId,
+    pub name: String,
+    /// Known chains the DEX is deployed on.
+    pub chain_ids: Vec<i64>,
+    pub handler: HandlerKind,
+}
+
+impl FromStr for DexInfo {
+    type Err = anyhow::Error;
+
+    /// An `EXTRA_DEXES` entry, `id:name:chain,chain:handler`.
+    fn from_str(entry: &str) -> Result<Self> {
+        let fields: Vec<&str> = entry.split(':').map(str::trim).collect();
+        let [id, name, chains, handler] = fields[..] else {
+            bail!("expected id:name:chains:handler, got {:?}", entry);
+        };
+        if name.is_empty() {
+            bail!("DEX {} has no name", id);
+        }
+        let chain_ids = chains
+            .split(',')
+            .map(str::trim)
+            .filter(|chain| !chain.is_empty())
+            .map(|chain| chain.parse().map_err(|e| anyhow!("bad chain id {:?} ({})", chain, e)))
+            .collect::<Result<Vec<i64>>>()?;
+        if chain_ids.is_empty() {
+            bail!("DEX {} lists no chains", id);
+        }
+        Ok(Self { id: DexId::new(id)?, name: name.to_string(), chain_ids, handler: handler.parse()? })
+    }
+}
+
+/// `EXTRA_DEXES`: `;`-separated `DexInfo` entries; blank entries are skipped.
+pub fn parse_dex_list(value: &str) -> Result<Vec<DexInfo>> {
+    value.split(';').map(str::trim).filter(|entry| !entry.is_empty()).map(DexInfo::from_str).collect()
+}
+
+/// The DEXes rows may be recorded under: the built-in presets, extended by `EXTRA_DEXES`.
+/// Every `dex_name` written to the database is checked against one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DexRegistry {
+    dexes: Vec<DexInfo>,
+}
+
+impl Default for DexRegistry {
+    fn default() -> Self {
+        Self::presets()
+    }
+}
+
+impl DexRegistry {
+    /// The DEXes this crate ships with.
+    pub fn presets() -> Self {
+        let preset = |id: &'static str, name: &str, chain_ids: &[i64], handler| DexInfo {
+            id: DexId(Cow::Borrowed(id)),
+            name: name.to_string(),
+            chain_ids: chain_ids.to_vec(),
+            handler,
+        };
+        Self {
+            dexes: vec![
+                preset(DEX_NAME, "Moonshot", &[2741, 8453], HandlerKind::Moonshot),
+                preset("uniswap-v3", "Uniswap V3", &[1, 8453], HandlerKind::GenericV3),
+            ],
+        }
+    }
+
+    /// The presets and the configured `EXTRA_DEXES`.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Self::presets().extended(config.extra_dexes.iter().cloned())
+    }
+
+    /// The registry with `dexes` added; fails if one reuses a registered id.
+    pub fn extended(mut self, dexes: impl IntoIterator<Item = DexInfo>) -> Result<Self> {
+        for dex in dexes {
+            if self.get(&dex.id).is_some() {
+                bail!("DEX {} is registered already", dex.id);
+            }
+            self.dexes.push(dex);
+        }
+        Ok(self)
+    }
+
+    pub fn dexes(&self) -> &[DexInfo] {
+        &self.dexes
+    }
+
+    pub fn get(&self, id: &DexId) -> Option<&DexInfo> {
+        self.dexes.iter().find(|dex| dex.id == *id)
+    }
+
+    /// The registered DEX named `name`, ignoring case and surrounding whitespace. The
+    /// error of an unknown name lists the registered ones.
+    pub fn parse(&self, name: &str) -> Result<DexId> {
+        let normalized = name.trim().to_ascii_lowercase();
+        match self.dexes.iter().find(|dex| dex.id == normalized.as_str()) {
+            Some(dex) => Ok(dex.id.clone()),
+            None => Err(self.unknown(name)),
+        }
+    }
+
+    fn unknown(&self, name: &str) -> anyhow::Error {
+        let known = self.dexes.iter().map(|dex| dex.id.as_str()).collect::<Vec<_>>().join(", ");
+        anyhow!("unknown DEX {:?}; known DEXes: {}", name, known)
+    }
+
+    /// `parse`, also failing when `chain_id` is a known chain the DEX is not deployed on.
+    /// Other chains, such as local test networks, are accepted.
+    pub fn for_chain(&self, name: &str, chain_id: i64) -> Result<DexId> {
+        let dex = self.parse(name)?;
+        self.check_chain(&dex, chain_id)?;
+        Ok(dex)
+    }
+
+    /// Fails unless `dex` is registered and, on a known chain, deployed there.
+    pub fn check_chain(&self, dex: &DexId, chain_id: i64) -> Result<()> {
+        let info = self.get(dex).ok_or_else(|| self.unknown(dex.as_str()))?;
+        if let Some(chain) = chain_info(chain_id).filter(|_| !info.chain_ids.contains(&chain_id)) {
+            let chains = info.chain_ids.iter().map(i64::to_string).collect::<Vec<_>>().join(", ");
+            bail!("DEX {} is not deployed on chain {} ({}); its chains are {}", dex, chain_id, chain.name, chains);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_ids_are_canonical() {
+        let registry = DexRegistry::presets();
+        for dex in registry.dexes() {
+            assert_eq!(DexId::new(dex.id.as_str()).unwrap(), dex.id);
+            assert_eq!(registry.get(&registry.parse(dex.id.as_str()).unwrap()), Some(dex));
+        }
+        assert_eq!(registry.get(&MOONSHOT).unwrap().handler, HandlerKind::Moonshot);
+    }
+
+    #[test]
+    fn test_new_validates_the_format() {
+        assert_eq!(DexId::new(" MoonShot ").unwrap(), MOONSHOT);
+        assert_eq!(DexId::new("Sushi-V3").unwrap().as_str(), "sushi-v3");
+        assert!(DexId::new("").is_err());
+        assert!(DexId::new("uni swap").is_err());
+        assert!(DexId::new("uniswap_v3").is_err());
+        assert!(DexId::new(&"a".repeat(MAX_DEX_ID_LEN + 1)).is_err());
+        assert!(serde_json::from_str::<DexId>("\"Bad Id\"").is_err());
+        assert_eq!(serde_json::to_string(&MOONSHOT).unwrap(), "\"moonshot\"");
+    }
+
+    #[test]
+    fn test_parse_normalizes_case() {
+        let registry = DexRegistry::presets();
+        assert_eq!(registry.parse(" MoonShot ").unwrap(), MOONSHOT);
+        assert_eq!(registry.parse("Uniswap-V3").unwrap().as_str(), "uniswap-v3");
+    }
+
+    #[test]
+    fn test_unknown_dex_lists_the_registry() {
+        let registry = DexRegistry::presets();
+        let err = registry.parse("moonshott").unwrap_err().to_string();
+        assert_eq!(err, "unknown DEX \"moonshott\"; known DEXes: moonshot, uniswap-v3");
+        assert!(registry.parse("").is_err());
+    }
+
+    #[test]
+    fn test_for_chain() {
+        let registry = DexRegistry::presets();
+        assert_eq!(registry.for_chain("moonshot", 8453).unwrap(), MOONSHOT);
+        // Chains outside `chain_info` are not checked
+        assert_eq!(registry.for_chain("moonshot", 31337).unwrap(), MOONSHOT);
+
+        let err = registry.for_chain("moonshot", 1).unwrap_err().to_string();
+        assert_eq!(err, "DEX moonshot is not deployed on chain 1 (Ethereum); its chains are 2741, 8453");
+    }
+
+    #[test]
+    fn test_config_extends_the_presets() {
+        let extra = parse_dex_list("Sushi-V3:SushiSwap V3:1, 8453:generic-v3; ;aero:Aerodrome:8453:GENERIC-V3").unwrap();
+        let registry = DexRegistry::presets().extended(extra).unwrap();
+
+        let sushi = registry.for_chain("sushi-v3", 1).unwrap();
+        assert_eq!(registry.get(&sushi).unwrap().name, "SushiSwap V3");
+        assert!(registry.for_chain("aero", 1).is_err());
+        let err = registry.parse("other").unwrap_err().to_string();
+        assert_eq!(err, "unknown DEX \"other\"; known DEXes: moonshot, uniswap-v3, sushi-v3, aero");
+
+        // Presets cannot be redefined
+        let moonshot = parse_dex_list("moonshot:Moonshot:1:moonshot").unwrap();
+        assert!(DexRegistry::presets().extended(moonshot).is_err());
+    }
+
+    #[test]
+    fn test_parse_dex_list_rejects_bad_entries() {
+        assert!(parse_dex_list("sushi:SushiSwap:1").is_err());
+        assert!(parse_dex_list("sushi swap:SushiSwap:1:generic-v3").is_err());
+        assert!(parse_dex_list("sushi:SushiSwap:mainnet:generic-v3").is_err());
+        assert!(parse_dex_list("sushi:SushiSwap::generic-v3").is_err());
+        assert!(parse_dex_list("sushi:SushiSwap:1:v2").is_err());
+        assert!(parse_dex_list("").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,166 @@
+use ethers::providers::{JsonRpcClient, Middleware, Provider};
+use ethers::types::{Address, BlockNumber, Filter};
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::chain;
+use crate::config::Config;
+use crate::db::Database;
+use crate::moonshot::LogDecoder;
+
+/// Blocks before the head searched for a `PoolCreated` of the factory, by default.
+pub const DEFAULT_SAMPLE_BLOCKS: u64 = 10_000;
+
+// Furthest the latest block's timestamp may be from the local clock
+const MAX_CLOCK_SKEW_SECS: i64 = 300;
+
+/// Tables `Database::init_schema` creates that the indexer cannot run without.
+pub const REQUIRED_TABLES: &[&str] = &[
+    "pools",
+    "swaps",
+    "pool_snapshots",
+    "tick_history",
+    "tokens",
+    "blocks",
+    "sync_cursors",
+    "watchlist",
+];
+
+/// Outcome of one prerequisite check.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub passed: bool,
+    /// What was found, or why the check failed.
+    pub detail: String,
+}
+
+impl CheckResult {
+    pub fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self { name, passed: true, detail: detail.into() }
+    }
+
+    pub fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Self { name, passed: false, detail: detail.into() }
+    }
+}
+
+/// The RPC endpoint answers and serves `CHAIN_ID`.
+pub async fn check_rpc<P: JsonRpcClient>(provider: &Provider<P>, expected_chain_id: u64) -> CheckResult {
+    match provider.get_chainid().await {
+        Ok(chain_id) if chain_id.as_u64() == expected_chain_id => CheckResult::pass("rpc", format!("chain id {}", chain_id)),
+        Ok(chain_id) => CheckResult::fail("rpc", format!("node serves chain id {}, CHAIN_ID is {}", chain_id, expected_chain_id)),
+        Err(e) => CheckResult::fail("rpc", format!("cannot read the chain id: {}", e)),
+    }
+}
+
+/// The factory is a contract that emitted at least one `PoolCreated` in the last
+/// `sample_blocks` blocks; with `EVENT_SCHEMA_FILE` set, the schema must check out and its
+/// pool creation event is looked for instead.
+pub async fn check_factory<P: JsonRpcClient>(provider: &Provider<P>, config: &Config, sample_blocks: u64) -> CheckResult {
+    let decoder = match LogDecoder::from_config(config) {
+        Ok(decoder) => decoder,
+        Err(e) => return CheckResult::fail("factory", format!("{:#}", e)),
+    };
+    let factory: Address = match config.moonshot_factory_address.parse() {
+        Ok(factory) => factory,
+        Err(e) => return CheckResult::fail("factory", format!("invalid factory address: {}", e)),
+    };
+    match provider.get_code(factory, None).await {
+        Ok(code) if code.is_empty() => {
+            return CheckResult::fail("factory", format!("no contract code at {:?}", factory));
+        }
+        Ok(_) => {}
+        Err(e) => return CheckResult::fail("factory", format!("cannot read the code at {:?}: {}", factory, e)),
+    }
+
+    let head = match provider.get_block_number().await {
+        Ok(head) => head.as_u64(),
+        Err(e) => return CheckResult::fail("factory", format!("cannot read the head block: {}", e)),
+    };
+    let from_block = head.saturating_sub(sample_blocks);
+    let filter = Filter::new().address(factory).topic0(decoder.pool_created_topic());
+    match chain::get_logs_chunked(provider, &filter, from_block, head, config.max_blocks_per_log_request).await {
+        Ok(logs) if logs.is_empty() => CheckResult::fail(
+            "factory",
+            format!("no PoolCreated events in blocks {} to {}; is this the Moonshot factory?", from_block, head),
+        ),
+        Ok(logs) => CheckResult::pass("factory", format!("{} PoolCreated events in blocks {} to {}", logs.len(), from_block, head)),
+        Err(e) => CheckResult::fail("factory", format!("cannot read factory logs: {}", e)),
+    }
+}
+
+/// The database answers and every `REQUIRED_TABLES` table exists.
+pub async fn check_database(database: &Database) -> CheckResult {
+    match database.get_missing_tables(REQUIRED_TABLES).await {
+        Ok(missing) if missing.is_empty() => CheckResult::pass("database", format!("{} tables present", REQUIRED_TABLES.len())),
+        Ok(missing) => CheckResult::fail(
+            "database",
+            format!("schema not initialized, missing {}; start the indexer once to create it", missing.join(", ")),
+        ),
+        Err(e) => CheckResult::fail("database", format!("cannot query the database: {}", e)),
+    }
+}
+
+/// The database connection may write.
+pub async fn check_write_permission(database: &Database) -> CheckResult {
+    match database.probe_write().await {
+        Ok(()) => CheckResult::pass("database write", "inserted and deleted a sentinel row"),
+        Err(e) => CheckResult::fail("database write", format!("cannot write: {}", e)),
+    }
+}
+
+/// The local clock agrees with the latest block's timestamp.
+pub async fn check_clock<P: JsonRpcClient>(provider: &Provider<P>) -> CheckResult {
+    let block = match provider.get_block(BlockNumber::Latest).await {
+        Ok(Some(block)) => block,
+        Ok(None) => return CheckResult::fail("clock", "the node returned no latest block"),
+        Err(e) => return CheckResult::fail("clock", format!("cannot read the latest block: {}", e)),
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    clock_check(now, block.timestamp.as_u64() as i64)
+}
+
+/// `check_clock` for a given local time and block timestamp.
+pub fn clock_check(now: i64, block_timestamp: i64) -> CheckResult {
+    let skew = now - block_timestamp;
+    if skew.abs() <= MAX_CLOCK_SKEW_SECS {
+        CheckResult::pass("clock", format!("{} s behind the latest block", skew))
+    } else if skew > 0 {
+        CheckResult::fail("clock", format!("latest block is {} s old; the node is stalled or the clock is ahead", skew))
+    } else {
+        CheckResult::fail("clock", format!("latest block is {} s in the future; the clock is behind", -skew))
+    }
+}
+
+/// The checks as an aligned pass/fail table.
+pub fn format_report(results: &[CheckResult]) -> String {
+    let width = results.iter().map(|result| result.name.len()).max().unwrap_or(0).max("CHECK".len());
+    let mut report = format!("{:<width$}  RESULT  DETAIL\n", "CHECK");
+    for result in results {
+        let status = if result.passed { "PASS" } else { "FAIL" };
+        report.push_str(&format!("{:<width$}  {:<6}  {}\n", result.name, status, result.detail));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_check() {
+        assert!(clock_check(1_000, 990).passed);
+        assert!(!clock_check(1_000, 1_000 - MAX_CLOCK_SKEW_SECS - 1).passed);
+        assert!(!clock_check(1_000, 1_000 + MAX_CLOCK_SKEW_SECS + 1).passed);
+    }
+
+    #[test]
+    fn test_format_report() {
+        let report = format_report(&[CheckResult::pass("rpc", "chain id 8453"), CheckResult::fail("database write", "denied")]);
+        assert_eq!(
+            report,
+            "CHECK           RESULT  DETAIL\nrpc             PASS    chain id 8453\ndatabase write  FAIL    denied\n"
+        );
+    }
+}
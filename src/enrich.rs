@@ -0,0 +1,312 @@
+use anyhow::Result;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::metrics;
+
+// Cursor of the background worker, which covers every swap
+const WORKER_CURSOR: &str = "enrich_usd";
+
+#[derive(Debug, Clone, Copy)]
+pub struct EnrichPolicy {
+    pub chain_id: i64,
+    pub batch_size: usize,
+    /// Furthest a price bucket may start from a swap's timestamp and still price it.
+    pub max_price_age_secs: i64,
+    /// Factor by which a swap's implied token price may exceed the token's median price.
+    pub max_price_multiple: f64,
+    /// Multiple of the pool's in-range reserve of the input token a swap may put in.
+    pub max_tvl_multiple: f64,
+    /// Price history before a swap its tokens' median prices are taken over.
+    pub median_window_secs: i64,
+}
+
+impl EnrichPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            chain_id: config.chain_id as i64,
+            batch_size: config.enrich_usd_batch_size.max(1),
+            max_price_age_secs: config.enrich_usd_max_price_age_secs as i64,
+            max_price_multiple: config.enrich_usd_max_price_multiple,
+            max_tvl_multiple: config.enrich_usd_max_tvl_multiple,
+            median_window_secs: config.enrich_usd_median_window_secs as i64,
+        }
+    }
+}
+
+/// A swap without USD amounts, its tokens resolved to addresses through its pool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnpricedSwap {
+    pub id: i64,
+    pub timestamp: i64,
+    pub token_in: String,
+    pub token_out: String,
+    pub amount_in: f64,
+    pub amount_out: f64,
+    pub decimals_in: Option<i32>,
+    pub decimals_out: Option<i32>,
+    /// The pool's in-range reserve of the input token at its last known liquidity and price,
+    /// in the token's smallest unit; `None` until the pool's state was read.
+    pub reserve_in: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EnrichCounts {
+    pub priced: u64,
+    /// Swaps no historical price covers, marked so they are not tried again.
+    pub unpriced: u64,
+    /// Swaps whose USD amounts failed `check_swap_usd`, stored without them.
+    pub flagged: u64,
+    /// Priced swaps without the price history or pool state to check them against.
+    pub unchecked: u64,
+}
+
+/// Outcome of `check_swap_usd`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsdCheck {
+    Passed,
+    /// Nothing to check against yet, such as a new pool without price history; the amounts
+    /// are kept on trust.
+    Grace,
+    Flagged(UsdFlag),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsdFlag {
+    /// The implied price of a token is far above its median price, typically wrong decimals.
+    Price,
+    /// The swap puts in far more than the pool holds in range.
+    Tvl,
+}
+
+impl UsdFlag {
+    /// Label of `moonshot_swap_usd_flagged_total`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UsdFlag::Price => "price",
+            UsdFlag::Tvl => "tvl",
+        }
+    }
+}
+
+/// USD amounts of a swap as (in, out), given each token's USD price at the time. Both sides
+/// trade the same value, so a side without a price or decimals takes the other side's value.
+pub fn swap_usd_amounts(swap: &UnpricedSwap, price_in: Option<f64>, price_out: Option<f64>) -> Option<(f64, f64)> {
+    let usd = |amount: f64, decimals: Option<i32>, price: Option<f64>| Some(amount / 10f64.powi(decimals?) * price?);
+    let amount_in_usd = usd(swap.amount_in, swap.decimals_in, price_in);
+    let amount_out_usd = usd(swap.amount_out, swap.decimals_out, price_out);
+
+    match (amount_in_usd, amount_out_usd) {
+        (Some(amount_in_usd), Some(amount_out_usd)) => Some((amount_in_usd, amount_out_usd)),
+        (Some(usd), None) | (None, Some(usd)) => Some((usd, usd)),
+        (None, None) => None,
+    }
+}
+
+/// Checks the USD amounts from `swap_usd_amounts` before they are stored. The swap's value
+/// implies a price for each token, which may not exceed the token's median price in
+/// `medians` (in, out) by more than `max_price_multiple`, and the input amount may not exceed
+/// `max_tvl_multiple` times the pool's in-range reserve.
+pub fn check_swap_usd(
+    swap: &UnpricedSwap,
+    (amount_in_usd, amount_out_usd): (f64, f64),
+    medians: (Option<f64>, Option<f64>),
+    policy: &EnrichPolicy,
+) -> UsdCheck {
+    let reserve_in = swap.reserve_in.filter(|reserve| *reserve > 0.0);
+    if reserve_in.is_some_and(|reserve| swap.amount_in > policy.max_tvl_multiple * reserve) {
+        return UsdCheck::Flagged(UsdFlag::Tvl);
+    }
+
+    let value = amount_in_usd.max(amount_out_usd);
+    let sides = [(swap.amount_in, swap.decimals_in, medians.0), (swap.amount_out, swap.decimals_out, medians.1)];
+    let mut checked = reserve_in.is_some();
+    for (amount, decimals, median) in sides {
+        let (Some(decimals), Some(median)) = (decimals, median.filter(|median| *median > 0.0)) else { continue };
+        let tokens = amount / 10f64.powi(decimals);
+        if tokens <= 0.0 {
+            continue;
+        }
+        if value / tokens > policy.max_price_multiple * median {
+            return UsdCheck::Flagged(UsdFlag::Price);
+        }
+        checked = true;
+    }
+
+    if checked { UsdCheck::Passed } else { UsdCheck::Grace }
+}
+
+/// The cursor a run over `from_ts <= timestamp < to_ts` resumes from.
+pub fn cursor_name(from_ts: i64, to_ts: i64) -> String {
+    format!("{}:{}:{}", WORKER_CURSOR, from_ts, to_ts)
+}
+
+/// Fills in USD amounts for swaps with `from_ts <= timestamp < to_ts` that have none, using
+/// the token price bucket closest to each swap rather than current prices. Amounts failing
+/// `check_swap_usd` are not stored; the swap is flagged instead.
+///
+/// Swaps are paged in insertion order and each page is stored together with the position
+/// reached under `cursor`, so an interrupted run picks up where it stopped.
+pub async fn enrich_usd(
+    database: &Database,
+    policy: &EnrichPolicy,
+    cursor: &str,
+    from_ts: i64,
+    to_ts: i64,
+) -> Result<EnrichCounts> {
+    let mut counts = EnrichCounts::default();
+    let mut position = database.get_sync_cursor(cursor, policy.chain_id).await?.unwrap_or(0);
+
+    loop {
+        let swaps = database
+            .get_unpriced_swaps(policy.chain_id, from_ts, to_ts, position, policy.batch_size as i64)
+            .await?;
+        let Some(last) = swaps.last() else {
+            return Ok(counts);
+        };
+        position = last.id;
+
+        let mut priced = Vec::new();
+        let mut unpriced = Vec::new();
+        let mut flagged = Vec::new();
+        for swap in &swaps {
+            let price_in = database
+                .get_token_usd_price_near(&swap.token_in, policy.chain_id, swap.timestamp, policy.max_price_age_secs)
+                .await?;
+            let price_out = database
+                .get_token_usd_price_near(&swap.token_out, policy.chain_id, swap.timestamp, policy.max_price_age_secs)
+                .await?;
+            let Some(amounts) = swap_usd_amounts(swap, price_in, price_out) else {
+                unpriced.push(swap.id);
+                continue;
+            };
+
+            let median_from = swap.timestamp - policy.median_window_secs;
+            let median_in = database.get_token_median_usd_price(&swap.token_in, policy.chain_id, median_from, swap.timestamp).await?;
+            let median_out = database.get_token_median_usd_price(&swap.token_out, policy.chain_id, median_from, swap.timestamp).await?;
+            match check_swap_usd(swap, amounts, (median_in, median_out), policy) {
+                UsdCheck::Flagged(flag) => {
+                    warn!("Swap {} failed the {} check at ${:.2}, storing it without USD amounts",
+                          swap.id, flag.as_str(), amounts.0.max(amounts.1));
+                    metrics::SWAP_USD_FLAGGED.with_label_values(&[flag.as_str()]).inc();
+                    flagged.push(swap.id);
+                    continue;
+                }
+                UsdCheck::Grace => counts.unchecked += 1,
+                UsdCheck::Passed => {}
+            }
+            priced.push((swap.id, amounts.0, amounts.1));
+        }
+
+        database.store_swap_usd_amounts(&priced, &unpriced, &flagged, cursor, policy.chain_id, position).await?;
+        counts.priced += priced.len() as u64;
+        counts.unpriced += unpriced.len() as u64;
+        counts.flagged += flagged.len() as u64;
+        info!("USD enrichment - {} priced ({} unchecked), {} without a price, {} flagged so far (cursor {} at swap {})",
+              counts.priced, counts.unchecked, counts.unpriced, counts.flagged, cursor, position);
+    }
+}
+
+/// Enriches every swap on its own task, then again every `interval` for swaps indexed since.
+pub fn spawn_worker(database: Database, policy: EnrichPolicy, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            match enrich_usd(&database, &policy, WORKER_CURSOR, 0, i64::MAX).await {
+                Ok(counts) if counts.priced + counts.unpriced + counts.flagged > 0 => {
+                    info!("USD enrichment pass - {} priced, {} without a price, {} flagged",
+                          counts.priced, counts.unpriced, counts.flagged);
+                }
+                Ok(_) => {}
+                Err(e) => error!("USD enrichment failed: {}", e),
+            }
+            sleep(interval).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unpriced_swap(decimals_in: Option<i32>, decimals_out: Option<i32>) -> UnpricedSwap {
+        UnpricedSwap {
+            id: 1,
+            timestamp: 1_700_000_000,
+            token_in: "0x00000000000000000000000000000000000000a0".to_string(),
+            token_out: "0x00000000000000000000000000000000000000a1".to_string(),
+            amount_in: 2_000_000_000_000_000_000.0,
+            amount_out: 4_100_000.0,
+            decimals_in,
+            decimals_out,
+            reserve_in: None,
+        }
+    }
+
+    fn policy() -> EnrichPolicy {
+        EnrichPolicy {
+            chain_id: 1,
+            batch_size: 100,
+            max_price_age_secs: 86_400,
+            max_price_multiple: 10.0,
+            max_tvl_multiple: 100.0,
+            median_window_secs: 86_400,
+        }
+    }
+
+    #[test]
+    fn test_both_sides_priced() {
+        let swap = unpriced_swap(Some(18), Some(6));
+        assert_eq!(swap_usd_amounts(&swap, Some(2_000.0), Some(1.0)), Some((4_000.0, 4.1)));
+    }
+
+    #[test]
+    fn test_missing_side_takes_the_other_value() {
+        let swap = unpriced_swap(Some(18), None);
+        assert_eq!(swap_usd_amounts(&swap, Some(2_000.0), Some(1.0)), Some((4_000.0, 4_000.0)));
+        assert_eq!(swap_usd_amounts(&swap, None, Some(1.0)), None);
+
+        let swap = unpriced_swap(Some(18), Some(6));
+        assert_eq!(swap_usd_amounts(&swap, None, Some(1.0)), Some((4.1, 4.1)));
+        assert_eq!(swap_usd_amounts(&swap, None, None), None);
+    }
+
+    #[test]
+    fn test_legitimate_large_trade_passes() {
+        // 2,000 tokens at $2,000 for 4.1M USDC, a $4M trade in a pool holding 10x that in range
+        let mut swap = unpriced_swap(Some(18), Some(6));
+        swap.amount_in = 2_000e18;
+        swap.amount_out = 4_100_000e6;
+        swap.reserve_in = Some(20_000e18);
+        let amounts = swap_usd_amounts(&swap, Some(2_000.0), Some(1.0)).unwrap();
+        assert_eq!(check_swap_usd(&swap, amounts, (Some(1_950.0), Some(1.0)), &policy()), UsdCheck::Passed);
+    }
+
+    #[test]
+    fn test_wrong_decimals_are_flagged() {
+        // The 18-decimal input token recorded with 6 decimals is worth a trillion times more
+        let swap = unpriced_swap(Some(6), Some(6));
+        let amounts = swap_usd_amounts(&swap, Some(2_000.0), Some(1.0)).unwrap();
+        assert!(amounts.0 > 1e15);
+        assert_eq!(
+            check_swap_usd(&swap, amounts, (Some(2_000.0), Some(1.0)), &policy()),
+            UsdCheck::Flagged(UsdFlag::Price)
+        );
+
+        // Putting in far more than the pool holds is flagged before any price is looked at
+        let mut swap = unpriced_swap(Some(18), Some(6));
+        swap.reserve_in = Some(1e15);
+        let amounts = swap_usd_amounts(&swap, Some(2_000.0), Some(1.0)).unwrap();
+        assert_eq!(check_swap_usd(&swap, amounts, (None, None), &policy()), UsdCheck::Flagged(UsdFlag::Tvl));
+    }
+
+    #[test]
+    fn test_pool_without_history_gets_grace() {
+        let swap = unpriced_swap(Some(18), Some(6));
+        let amounts = swap_usd_amounts(&swap, Some(2_000.0), None).unwrap();
+        assert_eq!(check_swap_usd(&swap, amounts, (None, None), &policy()), UsdCheck::Grace);
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Failures callers may want to handle rather than just report. They travel inside
+/// `anyhow::Error` like any other error and are told apart with `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexerError {
+    /// A `Database` method ran past its timeout and Postgres cancelled it, or it was
+    /// abandoned shortly after. Writes are upserts, so they can be retried whether or not
+    /// Postgres went on to finish them.
+    Database { statement: &'static str, timeout: Duration, retryable: bool },
+    /// `Indexer::watch_pool` was given a pool that is already on the watchlist.
+    AlreadyWatched { pool_address: String },
+    /// Another process holds the advisory lock guarding `lock`, e.g. a second live indexer
+    /// for the same chain.
+    Locked { lock: String },
+}
+
+impl IndexerError {
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Database { retryable, .. } => *retryable,
+            Self::AlreadyWatched { .. } | Self::Locked { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for IndexerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Database { statement, timeout, .. } => {
+                write!(f, "database statement {} timed out after {} ms", statement, timeout.as_millis())
+            }
+            Self::AlreadyWatched { pool_address } => write!(f, "pool {} is already watched", pool_address),
+            Self::Locked { lock } => write!(f, "{} is already running in another process", lock),
+        }
+    }
+}
+
+impl std::error::Error for IndexerError {}
+
+/// Whether `error` is an `IndexerError` that is safe to retry as is.
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<IndexerError>().is_some_and(IndexerError::is_retryable)
+}
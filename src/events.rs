@@ -0,0 +1,121 @@
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::metrics;
+use crate::types::{PoolData, SwapEvent};
+
+/// Fans events out to every stream subscribed to them. Subscribers share one buffer of
+/// `capacity` events; one that falls further behind skips the oldest events instead of
+/// holding the indexer back. Streams end once every clone of the broadcaster is dropped.
+#[derive(Clone)]
+pub struct EventBroadcaster<T> {
+    sender: broadcast::Sender<T>,
+    // Label of `moonshot_event_stream_skipped_total`
+    name: &'static str,
+}
+
+impl<T: Clone + Send + 'static> EventBroadcaster<T> {
+    /// `capacity` must be at least 1, which `Config::validate` checks for the stream buffers.
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender, name }
+    }
+
+    /// Hands the event to the current subscribers; without any it is dropped.
+    pub fn send(&self, event: T) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Events sent from now on, until the broadcaster is dropped.
+    pub fn subscribe(&self) -> EventStream<T> {
+        let lagged = Arc::new(AtomicU64::new(0));
+        let skipped = lagged.clone();
+        let name = self.name;
+        let events = stream::unfold(self.sender.subscribe(), move |mut receiver| {
+            let skipped = skipped.clone();
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(event) => return Some((event, receiver)),
+                        Err(RecvError::Lagged(count)) => {
+                            skipped.fetch_add(count, Ordering::Relaxed);
+                            metrics::EVENT_STREAM_SKIPPED.with_label_values(&[name]).inc_by(count);
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        });
+        EventStream { events: events.boxed(), lagged }
+    }
+}
+
+/// Events of an `EventBroadcaster`, in the order they were sent. The stream is lossy: when
+/// it falls a whole buffer behind, the oldest events are skipped and counted by `lagged`.
+pub struct EventStream<T> {
+    events: BoxStream<'static, T>,
+    lagged: Arc<AtomicU64>,
+}
+
+impl<T> EventStream<T> {
+    /// Events this stream skipped because it fell behind.
+    pub fn lagged(&self) -> u64 {
+        self.lagged.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Stream for EventStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.events.poll_next_unpin(cx)
+    }
+}
+
+/// The broadcasters behind `Indexer::swap_stream` and `Indexer::pool_stream`.
+#[derive(Clone)]
+pub struct IndexerEvents {
+    pub swaps: EventBroadcaster<SwapEvent>,
+    pub pools: EventBroadcaster<PoolData>,
+}
+
+impl IndexerEvents {
+    pub fn new(swap_buffer: usize, pool_buffer: usize) -> Self {
+        Self { swaps: EventBroadcaster::new("swaps", swap_buffer), pools: EventBroadcaster::new("pools", pool_buffer) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lagging_stream_skips_oldest_events() {
+        let broadcaster = EventBroadcaster::new("test", 2);
+        let mut stream = broadcaster.subscribe();
+
+        for event in 0..5 {
+            broadcaster.send(event);
+        }
+        drop(broadcaster);
+
+        // Only the last two fit the buffer; the stream ends with the broadcaster
+        assert_eq!(stream.by_ref().collect::<Vec<i32>>().await, vec![3, 4]);
+        assert_eq!(stream.lagged(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_events_before_subscribing_are_not_seen() {
+        let broadcaster = EventBroadcaster::new("test", 8);
+        broadcaster.send(1);
+        let stream = broadcaster.subscribe();
+        broadcaster.send(2);
+        drop(broadcaster);
+
+        assert_eq!(stream.collect::<Vec<i32>>().await, vec![2]);
+    }
+}
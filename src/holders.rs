@@ -0,0 +1,198 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use ethers::types::{Address, U256, U512};
+use tracing::{debug, warn};
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::types::{HolderBalance, HolderSnapshot};
+
+// Holders counted by `top10_concentration_pct`
+const CONCENTRATION_HOLDERS: usize = 10;
+
+/// Where holder balances come from.
+///
+/// Sources backed by `balanceOf` calls only see the candidate addresses they are given;
+/// one built on indexed `Transfer` events can ignore them and return every holder.
+#[async_trait]
+pub trait HolderSource: Send + Sync {
+    /// Balances of `token`, in any order; holders with a zero balance may be left out.
+    async fn holder_balances(&self, token: Address, candidates: &[Address]) -> Result<Vec<(Address, U256)>>;
+}
+
+/// Non-zero balances, largest first; ties keep address order so snapshots are stable.
+pub fn rank_holders(mut balances: Vec<(Address, U256)>) -> Vec<(Address, U256)> {
+    balances.retain(|(_, balance)| !balance.is_zero());
+    balances.sort_by(|(a, balance_a), (b, balance_b)| balance_b.cmp(balance_a).then(a.cmp(b)));
+    balances.dedup_by_key(|(holder, _)| *holder);
+    balances
+}
+
+/// Percentage of `total_supply` held by the ten largest of the `ranked` holders.
+pub fn top10_concentration_pct(ranked: &[(Address, U256)], total_supply: U256) -> Option<f64> {
+    if total_supply.is_zero() {
+        return None;
+    }
+
+    let held = ranked
+        .iter()
+        .take(CONCENTRATION_HOLDERS)
+        .fold(U256::zero(), |sum, (_, balance)| sum.saturating_add(*balance));
+    // Hundredths of a basis point keep the precision without leaving integer arithmetic
+    let millionths = held.full_mul(U256::from(1_000_000)) / U512::from(total_supply);
+    Some(millionths.low_u128() as f64 / 10_000.0)
+}
+
+#[derive(Debug, Clone)]
+pub struct HolderSnapshotPolicy {
+    /// Seconds between snapshots of a token; 0 disables snapshots.
+    pub interval_secs: i64,
+    pub top_n: usize,
+    /// Checked for every token on top of its pools, the curve and its deployer.
+    pub holders: Vec<Address>,
+    pub curve_address: Option<Address>,
+}
+
+impl HolderSnapshotPolicy {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Self {
+            interval_secs: config.holder_snapshot_interval_secs as i64,
+            top_n: config.holder_snapshot_top_n,
+            holders: config.holder_addresses.iter().map(|a| a.parse()).collect::<Result<_, _>>()?,
+            curve_address: config.moonshot_curve_address.as_deref().map(str::parse).transpose()?,
+        })
+    }
+
+    pub fn is_due(&self, last_taken_at: Option<i64>, now: i64) -> bool {
+        self.interval_secs > 0 && last_taken_at.is_none_or(|taken_at| now - taken_at >= self.interval_secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HolderSnapshotCounts {
+    pub taken: u64,
+    /// Tokens whose last snapshot is younger than the interval.
+    pub skipped: u64,
+    pub failed: u64,
+}
+
+/// Records a top-holder snapshot for every tracked pool token and curve token that is due
+/// one. `now` is a unix timestamp so callers can simulate the clock.
+pub async fn snapshot_holders<S: HolderSource + ?Sized>(
+    database: &Database,
+    source: &S,
+    policy: &HolderSnapshotPolicy,
+    chain_id: i64,
+    now: i64,
+) -> Result<HolderSnapshotCounts> {
+    let mut counts = HolderSnapshotCounts::default();
+
+    for token_address in database.get_holder_snapshot_token_addresses(chain_id).await? {
+        let last_taken_at = database
+            .get_latest_holder_snapshot(&token_address, chain_id)
+            .await?
+            .map(|snapshot| snapshot.taken_at);
+        if !policy.is_due(last_taken_at, now) {
+            counts.skipped += 1;
+            continue;
+        }
+
+        let token = database.get_token(&token_address, chain_id).await?;
+        let mut candidates = policy.holders.clone();
+        candidates.extend(policy.curve_address);
+        let creator = token.as_ref().and_then(|t| t.creator_address.as_deref());
+        candidates.extend(creator.and_then(|a| a.parse::<Address>().ok()));
+        for pool_address in database.get_pool_addresses_for_token(&token_address, chain_id).await? {
+            candidates.push(pool_address.parse()?);
+        }
+
+        let balances = match source.holder_balances(token_address.parse()?, &candidates).await {
+            Ok(balances) => balances,
+            Err(e) => {
+                warn!("Error reading holders of token {}: {}", token_address, e);
+                counts.failed += 1;
+                continue;
+            }
+        };
+
+        let ranked = rank_holders(balances);
+        let total_supply = token
+            .and_then(|t| t.total_supply)
+            .and_then(|supply| U256::from_dec_str(&supply).ok());
+        let top10_concentration_pct = total_supply.and_then(|supply| top10_concentration_pct(&ranked, supply));
+
+        let snapshot = HolderSnapshot {
+            token_address: token_address.clone(),
+            chain_id,
+            taken_at: now,
+            top_holders: ranked
+                .iter()
+                .take(policy.top_n)
+                .map(|(holder, balance)| HolderBalance {
+                    holder_address: format!("{:?}", holder),
+                    balance: balance.to_string(),
+                })
+                .collect(),
+            top10_concentration_pct,
+        };
+        database.insert_holder_snapshot(&snapshot).await?;
+        debug!("Token {} top-10 concentration is {:?}%", token_address, top10_concentration_pct);
+        counts.taken += 1;
+    }
+
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn holder(n: u64, balance: u64) -> (Address, U256) {
+        (Address::from_low_u64_be(n), U256::from(balance))
+    }
+
+    #[test]
+    fn test_rank_holders() {
+        let ranked = rank_holders(vec![holder(1, 50), holder(2, 0), holder(3, 700), holder(4, 50)]);
+
+        assert_eq!(ranked, vec![holder(3, 700), holder(1, 50), holder(4, 50)]);
+    }
+
+    #[test]
+    fn test_concentration_of_fixed_balances() {
+        // Twelve holders; only the ten largest count
+        let balances: Vec<_> = (1..=12).map(|n| holder(n, n * 1_000)).collect();
+        let ranked = rank_holders(balances);
+
+        // 3_000 + ... + 12_000 = 75_000 of 200_000
+        assert_eq!(top10_concentration_pct(&ranked, U256::from(200_000)), Some(37.5));
+        assert_eq!(top10_concentration_pct(&ranked[..1], U256::from(36_000)), Some(33.3333));
+        assert_eq!(top10_concentration_pct(&ranked, U256::zero()), None);
+        assert_eq!(top10_concentration_pct(&[], U256::from(1)), Some(0.0));
+    }
+
+    #[test]
+    fn test_concentration_of_18_decimal_supplies() {
+        let supply = U256::from(1_000_000_000u64) * U256::exp10(18);
+        let ranked = vec![(Address::from_low_u64_be(1), supply / 4)];
+
+        assert_eq!(top10_concentration_pct(&ranked, supply), Some(25.0));
+    }
+
+    #[test]
+    fn test_snapshot_cadence() {
+        let policy = HolderSnapshotPolicy {
+            interval_secs: 3600,
+            top_n: 20,
+            holders: Vec::new(),
+            curve_address: None,
+        };
+
+        assert!(policy.is_due(None, 1_000));
+        assert!(!policy.is_due(Some(1_000), 4_599));
+        assert!(policy.is_due(Some(1_000), 4_600));
+
+        let disabled = HolderSnapshotPolicy { interval_secs: 0, ..policy };
+        assert!(!disabled.is_due(None, 1_000));
+    }
+}
@@ -1,34 +1,203 @@
-use anyhow::Result;
-use ethers::providers::{Provider, Ws};
-use ethers::types::{Address, Filter, Log};
+use anyhow::{bail, Result};
+use ethers::providers::{JsonRpcClient, Middleware, Provider, PubsubClient, Ws};
+use ethers::types::{Address, Filter, Log, H256};
+use futures::StreamExt;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, oneshot, watch};
 use tokio::time::sleep;
-use tracing::{info, error, warn, debug};
+use tracing::{info, error, warn, debug, info_span, Instrument};
 
-use crate::config::Config;
-use crate::db::Database;
-use crate::moonshot::MoonshotHandler;
-use crate::types::{PoolData, SwapEvent};
+use crate::archive;
+use crate::batching::{BatchSettings, BatchingProvider, HttpBatchTransport};
+use crate::budget::MemoryBudget;
+use crate::catchup::{self, CatchUpPolicy, SyncMode, SyncProgress};
+use crate::chain::{self, BlockHeaders, CachedHeaders};
+use crate::config::{redacted, Config, MAX_PARALLEL_BACKFILL_WORKERS};
+use crate::correlation;
+use crate::db::{Database, QueryLimits};
+use crate::dex::{DexId, DexRegistry};
+use crate::error;
+use crate::events::{EventBroadcaster, EventStream, IndexerEvents};
+use crate::holders::{self, HolderSnapshotPolicy};
+use crate::lifecycle::{self, LifecyclePolicy, PoolStatus, TransitionCounts};
+use crate::metrics::{self, LatencyWindow};
+use crate::mev;
+use crate::migration;
+use crate::moonshot::{decode, CurveEvent, CurveHandler, LogDecoder, MoonshotHandler};
+use crate::native_price::{NativePrice, NativePriceTracker};
+use crate::nonstandard;
+use crate::pool_balances::{self, BalanceDropPolicy};
+use crate::pool_state::{self, CallErrorCounts, CallErrorKind, FailureTracker};
+use crate::price::PriceRouter;
+use crate::runtime;
+use crate::scope::{self, IndexingScope};
+use crate::supply;
+use crate::types::{normalize_address, BlockStats, PoolBalanceSnapshot, PoolData, SwapEvent};
+use crate::watchlist::{self, PoolWatcher, QueuedPool};
 
-pub struct Indexer {
+// How often idle pools are demoted to stale/archived
+const LIFECYCLE_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+// How often the per-chain, per-DEX stats table is logged
+const STATS_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+// Inserts averaged by `get_block_processing_latency`
+const LATENCY_WINDOW: usize = 100;
+
+// Tries per swap insert when it times out; other failures are not retried
+const SWAP_WRITE_ATTEMPTS: u32 = 3;
+
+// Token metadata is fetched this many tokens at a time, pausing between batches
+const TOKEN_SYNC_BATCH_SIZE: usize = 10;
+const TOKEN_SYNC_BATCH_DELAY: Duration = Duration::from_millis(250);
+
+// Pause between state reads of pools that were never processed
+const UNPROCESSED_POOL_DELAY: Duration = Duration::from_millis(100);
+
+// Routed token prices are kept one per hour in `token_usd_prices`
+pub(crate) const TOKEN_PRICE_BUCKET_SECS: i64 = 3600;
+
+/// What `replay_block` decoded from one block, in log order.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReplayReport {
+    /// Pools created by the factory, without token metadata.
+    pub decoded_pools: Vec<PoolData>,
+    /// Valid swaps of any pool, with the block's timestamp.
+    pub decoded_swaps: Vec<SwapEvent>,
+    /// One line per `PoolCreated` or `Swap` log that failed to decode or validate.
+    pub decoding_errors: Vec<String>,
+}
+
+/// Re-fetches and decodes the factory's `PoolCreated` logs and every `Swap` log of one
+/// block, for debugging its processing. Needs only the node and a decoder: the database
+/// is neither read nor written.
+pub async fn replay_block<P: JsonRpcClient>(provider: &Provider<P>, decoder: &LogDecoder, config: &Config, block_number: u64) -> Result<ReplayReport> {
+    let factory_address: Address = config.moonshot_factory_address.parse()?;
+    let (pool_created, swap) = (decoder.pool_created_topic(), decoder.swap_topic());
+
+    let filter = Filter::new().from_block(block_number).to_block(block_number).topic0(vec![pool_created, swap]);
+    let mut logs = provider.get_logs(&filter).await?;
+    logs.sort_by_key(|log| log.log_index);
+    let timestamp = match provider.get_block(block_number).await? {
+        Some(block) => block.timestamp.as_u64() as i64,
+        None => bail!("block {} is not available from the node", block_number),
+    };
+
+    let chain_id = config.chain_id as i64;
+    let mut report = ReplayReport::default();
+    for log in &logs {
+        let log_index = log.log_index.unwrap_or_default();
+        if log.topics.first() == Some(&pool_created) {
+            // Other factories emit the same event
+            if log.address != factory_address {
+                continue;
+            }
+            match decoder.decode_pool_created(log, chain_id) {
+                Ok(pool) => report.decoded_pools.push(pool),
+                Err(e) => report.decoding_errors.push(format!("PoolCreated log {}: {}", log_index, e)),
+            }
+        } else {
+            let decoded = decoder.decode_swap(log, chain_id).and_then(|swap| {
+                swap.validate()?;
+                Ok(swap)
+            });
+            match decoded {
+                Ok(mut swap) => {
+                    swap.timestamp = timestamp;
+                    report.decoded_swaps.push(swap);
+                }
+                Err(e) => report.decoding_errors.push(format!("Swap log {} of {:?}: {}", log_index, log.address, e)),
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Indexes over any JSON-RPC transport; streamed pool discovery needs a pubsub one such as
+/// the default WebSocket.
+pub struct Indexer<P = Ws> {
     config: Config,
-    provider: Arc<Provider<Ws>>,
+    provider: Arc<Provider<P>>,
     database: Database,
-    handler: MoonshotHandler,
-    last_processed_block: u64,
+    handler: Arc<MoonshotHandler<P>>,
+    curve_handler: CurveHandler,
+    budget: Arc<MemoryBudget>,
+    swap_writer: mpsc::UnboundedSender<WriterMessage>,
+    // Bounds swaps waiting to be archived, when `ARCHIVE_S3_BUCKET` is set
+    archive_budget: Option<Arc<MemoryBudget>>,
+    latency: Arc<LatencyWindow>,
+    // Last block of the ranges handed to the writer, which the next poll plans from; the
+    // indexed cursor in `progress` follows once the writer has stored them
+    dispatched_block: u64,
     pools_processed: u64,
     swaps_processed: u64,
+    // Head block when a log subscription started discovering new pools; pool creations
+    // above it come from the subscription, those up to it are still polled
+    pools_streamed_after: Option<u64>,
+    lifecycle_policy: LifecyclePolicy,
+    lifecycle_transitions: TransitionCounts,
+    last_lifecycle_check: Option<Instant>,
+    last_stats_report: Option<Instant>,
+    // Last block covered for stale pools, which are polled in wider, less frequent ranges
+    stale_cursor: u64,
+    scope: IndexingScope,
+    last_supply_refresh: Option<Instant>,
+    holder_policy: HolderSnapshotPolicy,
+    last_holder_snapshot: Option<Instant>,
+    // Set when `NATIVE_WRAPPED_TOKEN` is
+    native_price: Option<NativePriceTracker>,
+    last_native_price_refresh: Option<Instant>,
+    price_router: PriceRouter,
+    // Pools whose tokens were already checked for transfer fees/rebasing this run
+    checked_pools: HashSet<String>,
+    // Block each pool's token balances were last sampled at, so a block is sampled once
+    balance_sample_blocks: HashMap<String, i64>,
+    // Contracts emitting `Swap` logs that the factory did not deploy, not checked again this run
+    foreign_swap_emitters: HashSet<String>,
+    pool_state_failures: FailureTracker,
+    pool_state_errors: CallErrorCounts,
+    // Last block at which unresponsive pools were retried
+    unresponsive_cursor: u64,
+    catch_up: CatchUpPolicy,
+    sync_mode: SyncMode,
+    progress: Arc<SyncProgress>,
+    // Runtime-tunable settings published by a `ConfigReloader`
+    config_updates: Option<watch::Receiver<Config>>,
+    pool_watcher: PoolWatcher,
+    // Pools queued through `pool_watcher`, added before each poll
+    watch_requests: mpsc::Receiver<QueuedPool>,
+    events: IndexerEvents,
 }
 
-impl Indexer {
+impl Indexer<BatchingProvider<Ws>> {
     pub async fn new(config: Config) -> Result<Self> {
         // Connect to RPC
-        let provider = Arc::new(Provider::<Ws>::connect(&config.rpc_url).await?);
-        info!("Connected to RPC: {}", config.rpc_url);
+        let ws = Ws::connect(&config.rpc_url).await?;
+        info!("Connected to RPC: {}", redacted(&config.rpc_url));
 
+        let client = match &config.rpc_batch_url {
+            Some(url) => {
+                info!("Batching eth_call and eth_getBlockByNumber to {}", redacted(url));
+                BatchingProvider::new(ws, HttpBatchTransport::new(url), BatchSettings::from_config(&config))
+            }
+            None => BatchingProvider::passthrough(ws),
+        };
+        Self::with_provider(config, Arc::new(Provider::new(client))).await
+    }
+}
+
+impl<P: JsonRpcClient + 'static> Indexer<P> {
+    /// Sets up the indexer over an already connected provider, starting 100 blocks behind
+    /// its head.
+    pub async fn with_provider(config: Config, provider: Arc<Provider<P>>) -> Result<Self> {
         // Connect to database
-        let database = Database::new(&config.database_url).await?;
+        let database = Database::new(&config.database_url)
+            .await?
+            .with_limits(QueryLimits::from_config(&config))
+            .with_dexes(DexRegistry::from_config(&config)?);
         info!("Connected to database");
 
         // Initialize database schema
@@ -36,7 +205,13 @@ impl Indexer {
         info!("Database schema initialized");
 
         // Create handler
-        let handler = MoonshotHandler::new(provider.clone());
+        let decoder = LogDecoder::from_config(&config)?;
+        if let Some(path) = &config.event_schema_file {
+            info!("Decoding factory and pool events with the schema in {}", path);
+        }
+        let dex = decoder.dex();
+        let handler = Arc::new(MoonshotHandler::with_decoder(provider.clone(), Arc::new(decoder)));
+        let curve_handler = CurveHandler::from_config(&config)?;
 
         // Get current block number
         let current_block = provider.get_block_number().await?;
@@ -44,17 +219,193 @@ impl Indexer {
 
         info!("Starting from block: {}", last_processed_block);
 
+        // Decoded swaps are handed to a writer task; the budget bounds how much can pile up
+        let budget = Arc::new(MemoryBudget::from_mb(config.memory_budget_mb));
+        let latency = Arc::new(LatencyWindow::new(LATENCY_WINDOW));
+        let archive = archive::from_config(&config, &database).await?;
+        let archive_budget = archive.as_ref().map(|archive| archive.budget.clone());
+        let mev_chain_id = config.detect_mev.then_some(config.chain_id as i64);
+        let events = IndexerEvents::new(config.swap_stream_buffer, config.pool_stream_buffer);
+        let progress = Arc::new(SyncProgress::new(last_processed_block));
+        let swap_writer = Self::spawn_swap_writer(
+            database.clone(),
+            budget.clone(),
+            latency.clone(),
+            archive.map(|archive| archive.ranges),
+            mev_chain_id,
+            config.chain_id as i64,
+            dex,
+            events.swaps.clone(),
+            progress.clone(),
+        );
+
+        let lifecycle_policy = LifecyclePolicy::from_config(&config);
+        let pool_state_failures = FailureTracker::new(config.unresponsive_after_failures);
+        let catch_up = CatchUpPolicy::from_config(&config);
+        let holder_policy = HolderSnapshotPolicy::from_config(&config)?;
+
+        // Re-apply the scope so allowlist/token changes take effect for already-known pools
+        let watched_pools = database.get_watched_pools(config.chain_id as i64).await?;
+        let scope = IndexingScope::from_config(&config).with_watched_pools(watched_pools.clone());
+        let newly_tracked = scope::apply_to_existing_pools(&database, &scope).await?;
+        if !newly_tracked.is_empty() {
+            info!("{} known pools entered the indexing scope", newly_tracked.len());
+        }
+
+        let (pool_watcher, watch_requests) = PoolWatcher::new(watched_pools);
+        let native_price = NativePriceTracker::from_config(&config);
+        let price_router = PriceRouter::new(config.price_routing_max_hops);
+
         Ok(Self {
             config,
             provider,
             database,
             handler,
-            last_processed_block,
+            curve_handler,
+            budget,
+            swap_writer,
+            archive_budget,
+            latency,
+            dispatched_block: last_processed_block,
             pools_processed: 0,
             swaps_processed: 0,
+            pools_streamed_after: None,
+            lifecycle_policy,
+            lifecycle_transitions: TransitionCounts::default(),
+            last_lifecycle_check: None,
+            last_stats_report: None,
+            stale_cursor: last_processed_block,
+            scope,
+            last_supply_refresh: None,
+            holder_policy,
+            last_holder_snapshot: None,
+            native_price,
+            last_native_price_refresh: None,
+            price_router,
+            checked_pools: HashSet::new(),
+            balance_sample_blocks: HashMap::new(),
+            foreign_swap_emitters: HashSet::new(),
+            pool_state_failures,
+            pool_state_errors: CallErrorCounts::default(),
+            unresponsive_cursor: last_processed_block,
+            catch_up,
+            sync_mode: SyncMode::Normal,
+            progress,
+            config_updates: None,
+            pool_watcher,
+            watch_requests,
+            events,
         })
     }
 
+    /// Picks up reloaded settings before each poll.
+    pub fn watch_config(&mut self, updates: watch::Receiver<Config>) {
+        self.config_updates = Some(updates);
+    }
+
+    async fn apply_config_updates(&mut self) -> Result<()> {
+        let Some(updates) = &mut self.config_updates else {
+            return Ok(());
+        };
+        // A dropped reloader just means no more updates
+        if !updates.has_changed().unwrap_or(false) {
+            return Ok(());
+        }
+        self.config = updates.borrow_and_update().clone();
+        self.catch_up = CatchUpPolicy::from_config(&self.config);
+
+        let scope = IndexingScope::from_config(&self.config).with_watched_pools(self.pool_watcher.watched_pools());
+        let newly_tracked = scope::apply_to_existing_pools(&self.database, &scope).await?;
+        if !newly_tracked.is_empty() {
+            info!("{} known pools entered the indexing scope", newly_tracked.len());
+        }
+        self.scope = scope;
+        Ok(())
+    }
+
+    /// Queues a pool to be indexed from the next poll on, whatever the indexing scope. Fails
+    /// with `IndexerError::AlreadyWatched` if it is on the watchlist already.
+    pub fn watch_pool(&self, pool_address: &str) -> Result<()> {
+        self.pool_watcher.watch(pool_address)
+    }
+
+    /// DEX the indexed pools are stored under, as the decoder reads them.
+    pub fn dex(&self) -> DexId {
+        self.handler.decoder().dex()
+    }
+
+    /// A handle for watching pools from other tasks, such as the API.
+    pub fn pool_watcher(&self) -> PoolWatcher {
+        self.pool_watcher.clone()
+    }
+
+    /// Swaps as they are stored from now on, live or backfilled; swaps another process
+    /// stored first are left out. The stream ends once the indexer is dropped and its last
+    /// swaps are written. It holds `SWAP_STREAM_BUFFER` swaps for a subscriber that falls
+    /// behind and then skips the oldest, counting them in `EventStream::lagged`.
+    ///
+    /// ```no_run
+    /// # async fn run(config: moonshot_indexer::Config) -> anyhow::Result<()> {
+    /// use futures::StreamExt;
+    /// use moonshot_indexer::indexer::Indexer;
+    ///
+    /// let mut indexer = Indexer::new(config).await?;
+    /// let mut swaps = indexer.swap_stream();
+    /// let consumer = tokio::spawn(async move {
+    ///     while let Some(swap) = swaps.next().await {
+    ///         println!("{} in pool {}", swap.tx_hash, swap.pool_address);
+    ///     }
+    ///     swaps.lagged()
+    /// });
+    ///
+    /// indexer.backfill(1_000, 2_000).await?;
+    /// drop(indexer);
+    /// println!("{} swaps skipped", consumer.await?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn swap_stream(&self) -> EventStream<SwapEvent> {
+        self.events.swaps.subscribe()
+    }
+
+    /// Pools from `PoolCreated` logs as they are stored from now on, with the same
+    /// lifecycle and lossiness as `swap_stream` and a buffer of `POOL_STREAM_BUFFER` pools.
+    pub fn pool_stream(&self) -> EventStream<PoolData> {
+        self.events.pools.subscribe()
+    }
+
+    async fn apply_watch_requests(&mut self) -> Result<()> {
+        while let Ok(QueuedPool { pool_address, request_id }) = self.watch_requests.try_recv() {
+            // Logged, down to the database statements, under the id of the `/watch` request
+            let span = info_span!("watch", request_id = request_id.as_deref());
+            let apply = self.apply_watch_request(pool_address).instrument(span);
+            match request_id {
+                Some(request_id) => correlation::scope(request_id, apply).await?,
+                None => apply.await?,
+            }
+        }
+        Ok(())
+    }
+
+    async fn apply_watch_request(&mut self, pool_address: String) -> Result<()> {
+        self.scope.watch_pool(&pool_address);
+        if self.config.is_pool_excluded(&pool_address) {
+            warn!("Watched pool {} is excluded by EXCLUDED_POOLS, its swaps will not be indexed", pool_address);
+        }
+
+        let added_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let chain_id = self.config.chain_id as i64;
+        match watchlist::add_pool(&self.database, self.handler.as_ref(), chain_id, &pool_address, added_at).await {
+            Ok(Some(pool)) => {
+                info!("Registered watched pool {}", pool);
+                self.pools_processed += 1;
+            }
+            Ok(None) => info!("Watching pool {}", pool_address),
+            Err(e) => error!("Error adding watched pool {}: {}", pool_address, e),
+        }
+        Ok(())
+    }
+
     pub async fn start(&mut self) -> Result<()> {
         info!("Starting indexer...");
         info!("Chain ID: {}", self.config.chain_id);
@@ -65,72 +416,1130 @@ impl Indexer {
                 Ok(_) => {
                     // Log stats periodically
                     if self.pools_processed > 0 || self.swaps_processed > 0 {
-                        info!("Stats - Pools: {}, Swaps: {}, Last Block: {}", 
-                              self.pools_processed, self.swaps_processed, self.last_processed_block);
+                        info!("Stats - Pools: {}, Swaps: {}, Last Block: {}, Buffered: {} bytes", 
+                              self.pools_processed, self.swaps_processed, self.last_processed_block(),
+                              self.budget.in_flight_bytes());
+                    }
+                    if self.last_stats_report.is_none_or(|reported| reported.elapsed() >= STATS_REPORT_INTERVAL) {
+                        self.report_stats().await;
+                    }
+                    let errors = self.pool_state_errors;
+                    if errors != CallErrorCounts::default() {
+                        info!("Pool state call errors - {} reverts, {} missing contracts, {} transport",
+                              errors.revert, errors.missing_contract, errors.transport);
                     }
                     sleep(Duration::from_millis(self.config.poll_interval_ms)).await;
                 }
                 Err(e) => {
                     error!("Error processing blocks: {}", e);
+                    report_retryable_error(&e);
                     sleep(Duration::from_millis(5000)).await; // Wait longer on error
                 }
             }
         }
     }
 
-    async fn process_blocks(&mut self) -> Result<()> {
-        let current_block = self.provider.get_block_number().await?;
-        let current_block_num = current_block.as_u64();
+    #[allow(clippy::too_many_arguments)]
+    fn spawn_swap_writer(
+        database: Database,
+        budget: Arc<MemoryBudget>,
+        latency: Arc<LatencyWindow>,
+        archive_ranges: Option<mpsc::UnboundedSender<(u64, u64)>>,
+        // Chain whose completed ranges get MEV tags, when `DETECT_MEV` is on
+        mev_chain_id: Option<i64>,
+        chain_id: i64,
+        // DEX the range stats are recorded under
+        dex: DexId,
+        stored: EventBroadcaster<SwapEvent>,
+        // Advanced past each range once it is stored
+        progress: Arc<SyncProgress>,
+    ) -> mpsc::UnboundedSender<WriterMessage> {
+        let (tx, mut rx) = mpsc::unbounded_channel::<WriterMessage>();
 
-        if current_block_num <= self.last_processed_block {
-            return Ok(());
+        tokio::spawn(async move {
+            // Swaps stored since the last range was committed to `indexing_stats`
+            let mut stored_swaps = 0;
+            while let Some(message) = rx.recv().await {
+                let swaps = match message {
+                    WriterMessage::Swaps(swaps) => swaps,
+                    // Every swap of the range was sent before it, so they are all stored now
+                    WriterMessage::RangeDone { from_block, to_block, head_block, new_pools } => {
+                        let stats = database
+                            .record_range_stats(chain_id, &dex, to_block as i64, head_block as i64, new_pools as i64, stored_swaps)
+                            .await;
+                        match stats {
+                            Ok(()) => stored_swaps = 0,
+                            Err(e) => error!("Error recording stats of blocks {} to {}: {}", from_block, to_block, e),
+                        }
+                        if let Some(chain_id) = mev_chain_id {
+                            if let Err(e) = mev::tag_blocks(&database, chain_id, from_block as i64, to_block as i64).await {
+                                error!("Error tagging MEV swaps in blocks {} to {}: {}", from_block, to_block, e);
+                            }
+                        }
+                        if let Some(ranges) = &archive_ranges {
+                            if ranges.send((from_block, to_block)).is_err() {
+                                error!("Archiver stopped, blocks {} to {} will not be archived", from_block, to_block);
+                            }
+                        }
+                        progress.record(Instant::now(), to_block - from_block + 1, to_block);
+                        continue;
+                    }
+                };
+                let bytes = MemoryBudget::estimate_batch_bytes(&swaps);
+                for mut swap in swaps {
+                    let mut attempts = 1;
+                    loop {
+                        let indexed_at = metrics::unix_millis();
+                        swap.indexed_at = Some(indexed_at);
+                        match database.insert_swap(&swap).await {
+                            // Only swaps stored here count, not ones another process stored first
+                            Ok(true) => {
+                                stored_swaps += 1;
+                                metrics::SWAP_INSERTS.with_label_values(&["inserted"]).inc();
+                                latency.record(swap.timestamp, indexed_at);
+                                stored.send(swap.clone());
+                            }
+                            Ok(false) => metrics::SWAP_INSERTS.with_label_values(&["duplicate"]).inc(),
+                            Err(e) if attempts < SWAP_WRITE_ATTEMPTS && error::is_retryable(&e) => {
+                                warn!("Retrying swap {}: {}", swap.tx_hash, e);
+                                attempts += 1;
+                                continue;
+                            }
+                            Err(e) => error!("Error storing swap: {}", e),
+                        }
+                        break;
+                    }
+                }
+                budget.release(bytes);
+            }
+        });
+
+        tx
+    }
+
+    /// One poll: maintenance that is due, then every block range planned up to the head.
+    /// On error the next poll retries from after the last range handed to the writer, which
+    /// moves the indexed cursor past each range once its swaps are stored.
+    pub async fn process_blocks(&mut self) -> Result<()> {
+        self.apply_config_updates().await?;
+        self.apply_watch_requests().await?;
+
+        let supply_interval = Duration::from_secs(self.config.supply_refresh_interval_secs);
+        if !supply_interval.is_zero() && self.last_supply_refresh.is_none_or(|refreshed| refreshed.elapsed() >= supply_interval) {
+            // A broken multicall should never stall indexing
+            if let Err(e) = self.refresh_token_supplies().await {
+                warn!("Token supply refresh failed: {}", e);
+            }
         }
 
-        let from_block = self.last_processed_block + 1;
-        let to_block = std::cmp::min(
-            current_block_num,
-            from_block + self.config.batch_size as u64 - 1,
-        );
+        let holder_interval = Duration::from_secs(self.config.holder_snapshot_interval_secs);
+        if !holder_interval.is_zero() && self.last_holder_snapshot.is_none_or(|taken| taken.elapsed() >= holder_interval) {
+            if let Err(e) = self.snapshot_token_holders().await {
+                warn!("Token holder snapshot failed: {}", e);
+            }
+        }
+
+        let native_price_interval = Duration::from_secs(self.config.native_price_refresh_secs);
+        if self.native_price.is_some() && self.last_native_price_refresh.is_none_or(|refreshed| refreshed.elapsed() >= native_price_interval) {
+            if let Err(e) = self.refresh_native_price().await {
+                warn!("Native token price refresh failed: {}", e);
+            }
+        }
+
+        let plan = catchup::plan_poll(self.provider.as_ref(), &self.catch_up, self.dispatched_block).await?;
+        self.progress.set_head(plan.head);
+        if plan.mode != self.sync_mode {
+            let behind = plan.head.saturating_sub(self.dispatched_block);
+            match plan.mode {
+                SyncMode::CatchUp => warn!("Entering catch-up mode: {} blocks behind head {} (batch size {}, {} ranges per poll)",
+                                           behind, plan.head, plan.settings.batch_size, plan.settings.ranges_per_poll),
+                SyncMode::Normal => warn!("Leaving catch-up mode: {} blocks behind head {}", behind, plan.head),
+            }
+            self.sync_mode = plan.mode;
+        }
+
+        // While catching up, stored swaps lag the chain and busy pools would look idle
+        if self.sync_mode == SyncMode::Normal
+            && self.last_lifecycle_check.is_none_or(|checked| checked.elapsed() >= LIFECYCLE_CHECK_INTERVAL)
+        {
+            self.run_lifecycle_maintenance().await?;
+        }
+
+        for (from_block, to_block) in plan.ranges {
+            let pools_before = self.pools_processed;
+            self.process_range(from_block, to_block).await?;
+            self.swap_writer.send(WriterMessage::RangeDone {
+                from_block,
+                to_block,
+                head_block: plan.head,
+                new_pools: self.pools_processed - pools_before,
+            })?;
+            self.dispatched_block = to_block;
+        }
+
+        if self.sync_mode == SyncMode::Normal {
+            self.latency.warn_if_slow();
+        }
+        Ok(())
+    }
+
+    /// Indexes a historical block range independently of the live cursor, in
+    /// `PARALLEL_BACKFILL_WORKERS` concurrent chunks or, with a single worker, in order.
+    pub async fn backfill(&mut self, from_block: u64, to_block: u64) -> Result<()> {
+        info!("Backfilling blocks {} to {}", from_block, to_block);
+        let started = Instant::now();
+
+        if self.config.parallel_backfill_workers > 1 {
+            self.process_blocks_in_parallel(from_block, to_block, self.config.parallel_backfill_workers)
+                .await?;
+        } else {
+            self.stale_cursor = from_block.saturating_sub(1);
+            let mut start = from_block;
+            while start <= to_block {
+                let end = std::cmp::min(to_block, start + self.config.batch_size as u64 - 1);
+                self.process_range(start, end).await?;
+                start = end + 1;
+            }
+        }
+        self.drain_swap_writer().await;
+
+        if self.config.detect_mev {
+            let mut tagged = 0;
+            let mut start = from_block;
+            while start <= to_block {
+                let end = std::cmp::min(to_block, start + self.config.batch_size as u64 - 1);
+                tagged += mev::tag_blocks(&self.database, self.config.chain_id as i64, start as i64, end as i64).await?;
+                start = end + 1;
+            }
+            info!("Tagged {} MEV swaps in the backfilled blocks", tagged);
+        }
+
+        info!("Backfill complete in {:.1?} - Pools: {}, Swaps: {}",
+              started.elapsed(), self.pools_processed, self.swaps_processed);
+        Ok(())
+    }
+
+    /// Indexes `[from, to]` as `parallelism` chunks (at most 16), each on its own task with
+    /// its own `get_logs` calls, and returns the pools and swaps found.
+    ///
+    /// Every chunk's pool creations are stored before any swaps are fetched, so a pool
+    /// created in one chunk has its swaps indexed in the later ones. Curve events run in
+    /// order in between, since graduations register pools and refresh their state. Swaps of
+    /// active and stale pools are then indexed without pool state refreshes, as while
+    /// catching up. Re-indexing blocks is harmless: `insert_swap` skips known swaps.
+    pub async fn process_blocks_in_parallel(&mut self, from: u64, to: u64, parallelism: usize) -> Result<(u64, u64)> {
+        let parallelism = parallelism.clamp(1, MAX_PARALLEL_BACKFILL_WORKERS);
+        let chunks = catchup::split_range(from, to, parallelism);
+        let worker = self.range_worker();
+        info!("Indexing blocks {} to {} in {} parallel chunks", from, to, chunks.len());
+
+        // Pool creations a subscription already delivers are not polled again
+        let pool_ranges = chunks.iter().filter_map(|&(from, to)| pool_poll_range(from, to, self.pools_streamed_after));
+        let tasks = pool_ranges.map(|(from, to)| {
+            let worker = worker.clone();
+            tokio::spawn(async move {
+                let mut pools_found = 0;
+                for (start, end) in worker.batches(from, to) {
+                    pools_found += worker.process_pool_events(start, end).await?;
+                }
+                Ok(pools_found)
+            })
+        });
+        let pools_found = sum_chunk_counts(futures::future::try_join_all(tasks).await?)?;
+
+        let mut pools_discovered = 0;
+        for (start, end) in worker.batches(from, to) {
+            self.process_curve_events(start, end).await?;
+            if self.config.swap_topic_discovery {
+                pools_discovered += self.discover_pools_from_swaps(start, end).await?;
+            }
+        }
+
+        let mut pools = self.database.get_pool_addresses_by_status(PoolStatus::Active).await?;
+        pools.extend(self.database.get_pool_addresses_by_status(PoolStatus::Stale).await?);
+        let pools = Arc::new(pools);
+        let tasks = chunks.iter().map(|&(from, to)| {
+            let (worker, pools) = (worker.clone(), pools.clone());
+            tokio::spawn(async move { worker.process_swap_events(&pools, from, to).await })
+        });
+        let swaps_found = sum_chunk_counts(futures::future::try_join_all(tasks).await?)?;
+
+        let pools_found = pools_found + pools_discovered;
+        self.pools_processed += pools_found;
+        self.swaps_processed += swaps_found;
+        Ok((pools_found, swaps_found))
+    }
+
+    /// A worker sharing this indexer's connections, database and swap writer.
+    fn range_worker(&self) -> RangeWorker<P> {
+        RangeWorker {
+            provider: self.provider.clone(),
+            database: self.database.clone(),
+            handler: self.handler.clone(),
+            scope: self.scope.clone(),
+            factory_address: self.config.moonshot_factory_address.clone(),
+            chain_id: self.config.chain_id as i64,
+            batch_size: self.config.batch_size.max(1) as u64,
+            max_blocks_per_log_request: self.config.max_blocks_per_log_request,
+            decode_workers: self.config.decode_workers,
+            excluded_pools: self.config.excluded_pools.clone(),
+            budget: self.budget.clone(),
+            swap_writer: self.swap_writer.clone(),
+            new_pools: self.events.pools.clone(),
+        }
+    }
+
+    /// Adds a `tokens` row for every pool token that lacks one, returning how many were added.
+    pub async fn sync_tokens_table(&self) -> Result<u64> {
+        let chain_id = self.config.chain_id as i64;
+        let missing = self.database.get_missing_token_addresses(chain_id).await?;
+        info!("Syncing {} tokens missing from the tokens table", missing.len());
+
+        let mut added = 0;
+        for (i, batch) in missing.chunks(TOKEN_SYNC_BATCH_SIZE).enumerate() {
+            if i > 0 {
+                sleep(TOKEN_SYNC_BATCH_DELAY).await;
+            }
+
+            let fetches = batch.iter().map(|address| async move {
+                let token_address: Address = address.parse()?;
+                self.handler.fetch_token_data(token_address, chain_id).await
+            });
+
+            for (address, token) in batch.iter().zip(futures::future::join_all(fetches).await) {
+                match token {
+                    Ok(token) => {
+                        if let Err(e) = self.database.upsert_token(&token).await {
+                            error!("Error storing token {}: {}", address, e);
+                        } else {
+                            added += 1;
+                        }
+                    }
+                    Err(e) => warn!("Error fetching metadata for token {}: {}", address, e),
+                }
+            }
+        }
+
+        info!("Token sync complete - {} added", added);
+        Ok(added)
+    }
+
+    /// Pools known to the database whose state (liquidity, tick) was never read.
+    pub async fn get_unprocessed_pools_count(&self) -> Result<u64> {
+        self.database.count_pools_with_null_liquidity(self.config.chain_id as i64).await
+    }
+
+    /// Reads and stores the state of up to `max` unprocessed pools, pausing between pools.
+    /// Returns how many were stored; pools whose calls all fail stay unprocessed.
+    pub async fn process_unprocessed_pools(&self, max: usize) -> Result<u64> {
+        let pending = self.database.get_pools_with_null_liquidity(self.config.chain_id as i64).await?;
+        let pending = &pending[..pending.len().min(max)];
+        info!("Processing {} pools with no state yet", pending.len());
+
+        let mut processed = 0;
+        for (i, pool_address) in pending.iter().enumerate() {
+            if i > 0 {
+                sleep(UNPROCESSED_POOL_DELAY).await;
+            }
+
+            let previous = match self.database.get_pool(pool_address).await? {
+                Some(pool) => pool,
+                None => continue,
+            };
+            let update = match self.handler.update_pool_state(&previous).await {
+                Ok(update) if !update.all_failed => update,
+                Ok(update) => {
+                    warn!("Error reading state of pool {}: {:?}", pool_address, update.errors);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Error reading state of pool {}: {}", pool_address, e);
+                    continue;
+                }
+            };
+
+            self.database.upsert_pool(&update.pool).await?;
+            processed += 1;
+        }
+
+        info!("Pool processing complete - {} of {} stored", processed, pending.len());
+        Ok(processed)
+    }
+
+    async fn refresh_token_supplies(&mut self) -> Result<()> {
+        self.last_supply_refresh = Some(Instant::now());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        let counts = supply::refresh_supplies(
+            &self.database,
+            self.handler.as_ref(),
+            self.config.chain_id as i64,
+            self.config.supply_change_threshold_bps,
+            now,
+        )
+        .await?;
+
+        info!("Token supply refresh - {} refreshed, {} changes recorded, {} failed",
+              counts.refreshed, counts.recorded, counts.failed);
+        Ok(())
+    }
+
+    async fn refresh_native_price(&mut self) -> Result<()> {
+        self.last_native_price_refresh = Some(Instant::now());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let last_processed_block = self.last_processed_block();
+        let (anchors, wrapped_native, native_pool) = match self.native_price.as_mut() {
+            Some(tracker) => {
+                tracker.refresh(&self.database, last_processed_block as i64, now).await?;
+                let native_pool = tracker.native_usd_price().map(|reading| reading.pool_address.clone());
+                (tracker.usd_anchors(), tracker.wrapped_native().to_string(), native_pool)
+            }
+            None => return Ok(()),
+        };
+
+        // Price every known token from the anchors, through other pools where needed
+        let chain_id = self.config.chain_id as i64;
+        let pools: Vec<PoolData> = self.database.get_all_pools().await?.into_iter().filter(|pool| pool.chain_id == chain_id).collect();
+        let tokens: HashSet<String> = pools.iter().flat_map(|pool| [normalize_address(&pool.token0_address), normalize_address(&pool.token1_address)]).collect();
+        self.price_router.update_pools(pools);
+
+        let bucket_start = now - now % TOKEN_PRICE_BUCKET_SECS;
+        let mut priced = 0;
+        for token in &tokens {
+            if let Some(price_usd) = self.price_router.usd_price(token, &anchors) {
+                // The pool the price was read from: the first of its route, the native pool's
+                // for the native token, none for stablecoins
+                let source_pool = match self.price_router.route(token) {
+                    Some(route) => route.pools.first().cloned(),
+                    None if *token == wrapped_native => native_pool.clone(),
+                    None => None,
+                };
+                self.database.upsert_token_usd_price(token, chain_id, bucket_start, price_usd, source_pool.as_deref()).await?;
+                priced += 1;
+            }
+        }
+        debug!("Token USD prices - {} priced, {} unpriceable", priced, tokens.len() - priced);
+        Ok(())
+    }
+
+    /// The native token's latest USD price, None when it is not tracked or no pool can price it.
+    pub fn native_usd_price(&self) -> Option<&NativePrice> {
+        self.native_price.as_ref()?.native_usd_price()
+    }
+
+    async fn snapshot_token_holders(&mut self) -> Result<()> {
+        self.last_holder_snapshot = Some(Instant::now());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+        let counts = holders::snapshot_holders(
+            &self.database,
+            self.handler.as_ref(),
+            &self.holder_policy,
+            self.config.chain_id as i64,
+            now,
+        )
+        .await?;
+
+        info!("Token holder snapshot - {} taken, {} not due, {} failed",
+              counts.taken, counts.skipped, counts.failed);
+        Ok(())
+    }
+
+    /// Maps a unix timestamp to the last block at or before it, caching probed headers.
+    pub async fn block_for_timestamp(&self, timestamp: u64) -> Result<u64> {
+        let headers = CachedHeaders::new(self.provider.as_ref(), &self.database, self.config.chain_id as i64);
+        chain::block_for_timestamp(&headers, timestamp).await
+    }
+
+    /// Maps a unix timestamp to the first block at or after it, caching probed headers.
+    pub async fn first_block_at_or_after_timestamp(&self, timestamp: u64) -> Result<u64> {
+        let headers = CachedHeaders::new(self.provider.as_ref(), &self.database, self.config.chain_id as i64);
+        chain::first_block_at_or_after_timestamp(&headers, timestamp).await
+    }
+
+    async fn block_timestamp(&self, block_number: u64) -> Result<u64> {
+        CachedHeaders::new(self.provider.as_ref(), &self.database, self.config.chain_id as i64)
+            .block_timestamp(block_number)
+            .await
+    }
+
+    /// Average time from a swap's block timestamp to its insertion, over the last 100 inserts.
+    pub fn get_block_processing_latency(&self) -> Duration {
+        self.latency.average()
+    }
+
+    /// Demotes idle pools of this chain. Idleness is measured up to the last indexed block,
+    /// not the wall clock, since later swaps are not stored yet.
+    async fn run_lifecycle_maintenance(&mut self) -> Result<()> {
+        let now = self.block_timestamp(self.last_processed_block()).await? as i64;
+        let chain_id = self.config.chain_id as i64;
+        let transitions = lifecycle::run_maintenance(&self.database, &self.lifecycle_policy, chain_id, now).await?;
+        self.lifecycle_transitions.add(transitions);
+        self.last_lifecycle_check = Some(Instant::now());
+
+        if transitions.total() > 0 {
+            info!("Pool lifecycle - {} stale, {} archived this pass ({} status changes since start)",
+                  transitions.to_stale, transitions.to_archived, self.lifecycle_transitions.total());
+        }
+        Ok(())
+    }
+
+    /// Waits until every swap and range handed to the writer task is stored.
+    pub async fn drain_swap_writer(&self) {
+        while self.budget.in_flight_bytes() > 0 || self.last_processed_block() < self.dispatched_block {
+            sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    async fn process_range(&mut self, from_block: u64, to_block: u64) -> Result<()> {
+        if self.budget.is_suspended() {
+            warn!("Swap buffer over budget ({} bytes), waiting for writer to drain",
+                  self.budget.in_flight_bytes());
+        }
+        self.budget.wait_for_capacity().await;
+        if let Some(archive_budget) = &self.archive_budget {
+            if archive_budget.is_suspended() {
+                warn!("Archive uploads over budget ({} bytes), waiting for them to complete",
+                      archive_budget.in_flight_bytes());
+            }
+            archive_budget.wait_for_capacity().await;
+        }
 
         debug!("Processing blocks {} to {}", from_block, to_block);
 
-        // Process pool creation events
-        let pools_found = self.process_pool_events(from_block, to_block).await?;
+        // Process pool creation events, except those a subscription is already delivering
+        let pools_found = match pool_poll_range(from_block, to_block, self.pools_streamed_after) {
+            Some((from, to)) => self.process_pool_events(from, to).await?,
+            None => 0,
+        };
         self.pools_processed += pools_found;
 
-        // Process swap events
-        let swaps_found = self.process_swap_events(from_block, to_block).await?;
+        let curve_trades_found = self.process_curve_events(from_block, to_block).await?;
+
+        if self.config.swap_topic_discovery {
+            let discovered = self.discover_pools_from_swaps(from_block, to_block).await?;
+            self.pools_processed += discovered;
+        }
+
+        // Process swap events: active pools every range, stale pools once enough blocks accumulate
+        let active_pools = self.database.get_pool_addresses_by_status(PoolStatus::Active).await?;
+        let mut swaps_found = self
+            .process_swap_events(&active_pools, PoolStatus::Active, from_block, to_block)
+            .await?;
+
+        if to_block >= self.stale_cursor + self.config.stale_poll_interval_blocks {
+            let stale_pools = self.database.get_pool_addresses_by_status(PoolStatus::Stale).await?;
+            swaps_found += self
+                .process_swap_events(&stale_pools, PoolStatus::Stale, self.stale_cursor + 1, to_block)
+                .await?;
+            self.stale_cursor = to_block;
+        }
+
+        if to_block >= self.unresponsive_cursor + self.config.unresponsive_retry_interval_blocks {
+            swaps_found += self.retry_unresponsive_pools(self.unresponsive_cursor + 1, to_block).await?;
+            self.unresponsive_cursor = to_block;
+        }
         self.swaps_processed += swaps_found;
 
-        if pools_found > 0 || swaps_found > 0 {
-            info!("Processed {} pools and {} swaps in blocks {} to {}", 
-                  pools_found, swaps_found, from_block, to_block);
+        if pools_found > 0 || swaps_found > 0 || curve_trades_found > 0 {
+            info!("Processed {} pools, {} swaps and {} curve trades in blocks {} to {}",
+                  pools_found, swaps_found, curve_trades_found, from_block, to_block);
         }
 
-        self.last_processed_block = to_block;
         Ok(())
     }
 
+    async fn store_new_pool(&self, pool_data: &PoolData) -> Result<()> {
+        self.range_worker().store_new_pool(pool_data).await
+    }
+
     async fn process_pool_events(&self, from_block: u64, to_block: u64) -> Result<u64> {
+        self.range_worker().process_pool_events(from_block, to_block).await
+    }
+
+    /// Registers the factory's pools among the unknown contracts emitting `Swap` logs in the
+    /// range, returning how many. The logs of every address are fetched in ranges of
+    /// `SWAP_TOPIC_MAX_BLOCKS`; the swaps themselves are still indexed per pool, from this
+    /// range on for the pools found here.
+    async fn discover_pools_from_swaps(&mut self, from_block: u64, to_block: u64) -> Result<u64> {
         let factory_address: Address = self.config.moonshot_factory_address.parse()?;
+        let filter = Filter::new().topic0(self.handler.decoder().swap_topic());
+        let max_blocks = self.config.swap_topic_max_blocks;
+        let logs = chain::get_logs_chunked(self.provider.as_ref(), &filter, from_block, to_block, max_blocks).await?;
+        let emitters: BTreeSet<Address> = logs.iter().map(|log| log.address).collect();
 
-        let filter = Filter::new()
-            .from_block(from_block)
-            .to_block(to_block)
-            .address(factory_address)
-            .event("PoolCreated(address,address,uint24,int24,address)");
+        let mut discovered = 0;
+        for emitter in emitters {
+            let pool_address = format!("{:?}", emitter);
+            if self.foreign_swap_emitters.contains(&pool_address) || self.config.is_pool_excluded(&pool_address) {
+                continue;
+            }
+            if self.database.get_pool(&pool_address).await?.is_some() {
+                metrics::SWAP_TOPIC_POOLS.with_label_values(&["known"]).inc();
+                continue;
+            }
 
-        let logs = self.provider.get_logs(&filter).await?;
-        let mut pools_processed = 0;
+            match self.verify_factory_pool(factory_address, &pool_address).await? {
+                Some(pool) => {
+                    info!("Discovered pool {} through its swaps", pool);
+                    self.store_new_pool(&pool).await?;
+                    metrics::SWAP_TOPIC_POOLS.with_label_values(&["discovered"]).inc();
+                    discovered += 1;
+                }
+                None => {
+                    debug!("Contract {} emits swaps but is not a pool of the factory", pool_address);
+                    metrics::SWAP_TOPIC_POOLS.with_label_values(&["foreign"]).inc();
+                    self.foreign_swap_emitters.insert(pool_address);
+                }
+            }
+        }
+        Ok(discovered)
+    }
+
+    /// The pool at `pool_address` read from its contract, if `factory` deployed it: the
+    /// factory's `getPool` must return it for the tokens and fee the contract reports.
+    async fn verify_factory_pool(&self, factory: Address, pool_address: &str) -> Result<Option<PoolData>> {
+        let chain_id = self.config.chain_id as i64;
+        let skeleton = PoolData::new(pool_address.to_string(), String::new(), String::new(), chain_id, self.handler.decoder().dex());
+        let update = pool_state::refresh(self.handler.as_ref(), &skeleton).await?;
+        // A failed RPC says nothing about the contract; the range is retried
+        if update.errors.contains(&CallErrorKind::Transport) {
+            bail!("could not read the state of {}", pool_address);
+        }
+
+        let mut pool = update.pool;
+        let (Ok(token0), Ok(token1), Some(fee)) = (pool.token0_canonical(), pool.token1_canonical(), pool.fee_tier) else {
+            return Ok(None);
+        };
+        let deployed = self.handler.factory_pool(factory, token0.into(), token1.into(), fee as u32).await?;
+        if format!("{:?}", deployed) != pool_address {
+            return Ok(None);
+        }
+
+        self.handler.fetch_pool_token_metadata(&mut pool).await?;
+        Ok(Some(pool))
+    }
+
+    /// Indexes launches, trades and graduations from the bonding curve, when one is configured.
+    /// Graduations register their destination pool right away, so its swaps are indexed
+    /// from this range on even when its `PoolCreated` log was not seen.
+    async fn process_curve_events(&mut self, from_block: u64, to_block: u64) -> Result<u64> {
+        let curve_address: Address = match &self.config.moonshot_curve_address {
+            Some(address) => address.parse()?,
+            None => return Ok(0),
+        };
+        let chain_id = self.config.chain_id as i64;
+
+        let filter = Filter::new().address(curve_address).topic0(self.curve_handler.topics());
+        let max_blocks = self.config.max_blocks_per_log_request;
+        let logs = chain::get_logs_chunked(self.provider.as_ref(), &filter, from_block, to_block, max_blocks).await?;
+        let mut trades_processed = 0;
 
         for log in logs {
-            match self.handler.handle_pool_created(log, self.config.chain_id as i64).await {
+            match self.curve_handler.decode(&log, chain_id) {
+                Ok(CurveEvent::TokenCreated(token, creator)) => {
+                    info!("Curve token {:?} launched by {:?}", token, creator);
+                    self.database
+                        .set_token_creator(&format!("{:?}", token), chain_id, &format!("{:?}", creator))
+                        .await?;
+                    match self.handler.fetch_token_data(token, chain_id).await {
+                        Ok(token_data) => self.database.upsert_token(&token_data).await?,
+                        Err(e) => warn!("Error fetching metadata for curve token {:?}: {}", token, e),
+                    }
+                }
+                Ok(CurveEvent::Trade(mut trade)) => {
+                    match self.block_timestamp(trade.block_number as u64).await {
+                        Ok(timestamp) => trade.timestamp = timestamp as i64,
+                        Err(e) => warn!("Error reading timestamp of block {}: {}", trade.block_number, e),
+                    }
+                    self.database.insert_curve_trade(&trade).await?;
+                    trades_processed += 1;
+                }
+                Ok(CurveEvent::Graduated(mut migration)) => {
+                    info!("Curve token {} graduated to pool {}", migration.token_address, migration.pool_address);
+                    match self.block_timestamp(migration.block_number as u64).await {
+                        Ok(timestamp) => migration.timestamp = timestamp as i64,
+                        Err(e) => warn!("Error reading timestamp of block {}: {}", migration.block_number, e),
+                    }
+
+                    match migration::record_migration(&self.database, self.handler.as_ref(), &self.scope, &mut migration).await {
+                        Ok(Some(pool)) => {
+                            info!("Registered graduated pool {}", pool);
+                            self.pools_processed += 1;
+                            // Fills in token metadata the bare state reads do not cover
+                            if let Err(e) = self.refresh_pool_state(&pool.pool_address, migration.block_number).await {
+                                warn!("Error updating pool state: {}", e);
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(e) => warn!("Error registering pool of graduated token {}: {}", migration.token_address, e),
+                    }
+                }
+                Err(e) => error!("Error parsing curve event: {}", e),
+            }
+        }
+
+        Ok(trades_processed)
+    }
+
+    async fn process_swap_events(
+        &mut self,
+        pools: &[String],
+        group: PoolStatus,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<u64> {
+        if pools.is_empty() {
+            debug!("No {} pools found, skipping swap processing", group.as_str());
+            return Ok(0);
+        }
+
+        let mut swaps_processed = 0;
+        let settings = self.catch_up.settings(self.sync_mode);
+        let worker = self.range_worker();
+
+        // Process swap events for each pool in the group
+        for pool_address in pools {
+            if self.config.is_pool_excluded(pool_address) {
+                debug!("Skipping swaps of excluded pool {}", pool_address);
+                continue;
+            }
+            let decoded = worker.fetch_swaps(pool_address, from_block, to_block, settings.log_swaps).await?;
+
+            // Update pool state after each swap; skipped while catching up
+            if settings.refresh_pool_state {
+                for swap_event in &decoded {
+                    if let Err(e) = self.refresh_pool_state(&swap_event.pool_address, swap_event.block_number).await {
+                        warn!("Error updating pool state: {}", e);
+                    }
+                }
+            }
+
+            if self.config.detect_nonstandard_tokens
+                && !decoded.is_empty()
+                && self.checked_pools.insert(pool_address.clone())
+            {
+                if let Err(e) = self.check_nonstandard_tokens(pool_address, &decoded[0].tx_hash).await {
+                    warn!("Error checking tokens of pool {}: {}", pool_address, e);
+                }
+            }
+
+            if !decoded.is_empty() && group != PoolStatus::Active {
+                self.database.set_pool_status(pool_address, PoolStatus::Active).await?;
+                self.lifecycle_transitions.record(PoolStatus::Active);
+                info!("Pool {} is now active (was {})", pool_address, group.as_str());
+            }
+
+            if !decoded.is_empty() {
+                swaps_processed += decoded.len() as u64;
+                worker.write_swaps(decoded)?;
+            }
+        }
+
+        Ok(swaps_processed)
+    }
+
+    /// Refreshes and stores a pool's state. Failed calls keep the stored values; a pool whose
+    /// calls all fail too many times in a row is marked unresponsive.
+    async fn refresh_pool_state(&mut self, pool_address: &str, block_number: i64) -> Result<()> {
+        let previous = match self.database.get_pool(pool_address).await? {
+            Some(pool) => pool,
+            None => return Ok(()),
+        };
+        let mut update = self.handler.update_pool_state(&previous).await?;
+        for kind in &update.errors {
+            self.pool_state_errors.record(*kind);
+        }
+
+        if self.pool_state_failures.record(pool_address, &update) {
+            self.database.set_pool_status(pool_address, PoolStatus::Unresponsive).await?;
+            self.lifecycle_transitions.record(PoolStatus::Unresponsive);
+            warn!("Pool {} is now unresponsive after {} failed state refreshes (last errors: {:?})",
+                  pool_address, self.config.unresponsive_after_failures, update.errors);
+        }
+        if update.all_failed {
+            return Ok(());
+        }
+
+        update.pool.updated_at_block = Some(block_number);
+        self.database.upsert_pool(&update.pool).await?;
+        if let Err(e) = self.database.insert_pool_snapshot(&update.pool, block_number).await {
+            warn!("Error recording pool snapshot: {}", e);
+            return Ok(());
+        }
+
+        if self.config.sample_pool_balances && self.balance_sample_blocks.insert(pool_address.to_string(), block_number) != Some(block_number) {
+            if let Err(e) = self.sample_pool_balances(&update.pool, block_number).await {
+                warn!("Error sampling token balances of pool {}: {}", pool_address, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores the pool's token balances with its snapshot at `block_number` and alerts on
+    /// the balance drops that snapshot completes.
+    async fn sample_pool_balances(&self, pool: &PoolData, block_number: i64) -> Result<()> {
+        let (token0, token1) = self.handler.get_pool_token_balances(pool).await?;
+        let balances = PoolBalanceSnapshot {
+            block_number,
+            token0_balance: token0.map(|balance| balance.to_string()),
+            token1_balance: token1.map(|balance| balance.to_string()),
+        };
+        self.database.store_pool_balances(&pool.pool_address, pool.chain_id, &balances).await?;
+
+        // Whether the previous snapshot was already drained depends on its own window, so
+        // two windows are read to tell a new drop from one already reported
+        let policy = BalanceDropPolicy::from_config(&self.config);
+        let history = self
+            .database
+            .get_pool_balance_snapshots(&pool.pool_address, pool.chain_id, block_number - 2 * policy.window_blocks)
+            .await?;
+        for drop in pool_balances::detect_balance_drops(&history, &policy) {
+            if drop.block_number != block_number {
+                continue;
+            }
+            let token = if drop.token == 0 { "token0" } else { "token1" };
+            metrics::POOL_BALANCE_DROPS.with_label_values(&[token]).inc();
+            warn!("{}", drop.alert_message(pool, self.config.balance_drop_window_secs));
+        }
+        Ok(())
+    }
+
+    /// Reads a pool's ticks in `[tick_lower, tick_upper]` and stores them, returning how many
+    /// were stored. The range is bounded like `MoonshotHandler::get_all_tick_data`.
+    pub async fn sync_tick_data(&self, pool_address: &str, tick_lower: i32, tick_upper: i32) -> Result<usize> {
+        let pool = match self.database.get_pool(pool_address).await? {
+            Some(pool) => pool,
+            None => bail!("pool {} is not indexed", pool_address),
+        };
+        let tick_spacing = match pool.tick_spacing {
+            Some(tick_spacing) => tick_spacing,
+            None => bail!("pool {} has no known tick spacing", pool_address),
+        };
+
+        let block_number = self.provider.get_block_number().await?.as_u64() as i64;
+        let ticks = self
+            .handler
+            .get_all_tick_data(pool_address.parse()?, tick_lower, tick_upper, tick_spacing)
+            .await?;
+        self.database.upsert_tick_data(pool_address, pool.chain_id, &ticks, block_number).await?;
+
+        Ok(ticks.len())
+    }
+
+    /// Re-reads unresponsive pools; those answering again are reactivated and their swaps
+    /// since the last retry indexed. Returns the number of swaps found.
+    async fn retry_unresponsive_pools(&mut self, from_block: u64, to_block: u64) -> Result<u64> {
+        let mut recovered = Vec::new();
+
+        for pool_address in self.database.get_pool_addresses_by_status(PoolStatus::Unresponsive).await? {
+            let previous = match self.database.get_pool(&pool_address).await? {
+                Some(pool) => pool,
+                None => continue,
+            };
+            let update = self.handler.update_pool_state(&previous).await?;
+            for kind in &update.errors {
+                self.pool_state_errors.record(*kind);
+            }
+            if update.all_failed {
+                continue;
+            }
+
+            self.database.upsert_pool(&update.pool).await?;
+            self.database.set_pool_status(&pool_address, PoolStatus::Active).await?;
+            self.lifecycle_transitions.record(PoolStatus::Active);
+            info!("Pool {} is responsive again and now active", pool_address);
+            recovered.push(pool_address);
+        }
+
+        self.process_swap_events(&recovered, PoolStatus::Active, from_block, to_block).await
+    }
+
+    /// Flags pool tokens whose transfers in the swap's receipt do not match the swap amounts.
+    async fn check_nonstandard_tokens(&self, pool_address: &str, tx_hash: &str) -> Result<()> {
+        let pool = match self.database.get_pool(pool_address).await? {
+            Some(pool) => pool,
+            None => return Ok(()),
+        };
+        let receipt = match self.provider.get_transaction_receipt(tx_hash.parse::<H256>()?).await? {
+            Some(receipt) => receipt,
+            None => return Ok(()),
+        };
+
+        let detected = nonstandard::detect_in_receipt(
+            pool_address.parse()?,
+            pool.token0_address.parse()?,
+            pool.token1_address.parse()?,
+            &receipt.logs,
+        );
+        for (token, behavior) in detected {
+            let token = format!("{:?}", token);
+            warn!("Token {} in pool {} looks {:?}; swap amounts for it are not what recipients receive",
+                  token, pool_address, behavior);
+            self.database.flag_nonstandard_token(&token, self.config.chain_id as i64, behavior).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Last block of the ranges whose swaps the writer has stored.
+    pub fn last_processed_block(&self) -> u64 {
+        self.progress.last_processed_block()
+    }
+
+    /// How long until blocks up to `current_block` are indexed, at the rate of the last
+    /// minute of polls; `Duration::MAX` until two ranges have been indexed.
+    pub fn estimate_time_to_catch_up(&self, current_block: u64) -> Duration {
+        self.progress.estimate(current_block.saturating_sub(self.last_processed_block()))
+    }
+
+    /// Indexing progress for `/health`, updated as ranges complete.
+    pub fn sync_progress(&self) -> Arc<SyncProgress> {
+        self.progress.clone()
+    }
+
+    /// Logs the stats rows of every chain and DEX sharing the database, warning about stale ones.
+    async fn report_stats(&mut self) {
+        self.last_stats_report = Some(Instant::now());
+        let stats = match self.database.get_all_stats().await {
+            Ok(stats) => stats,
+            Err(e) => return warn!("Error reading indexing stats: {}", e),
+        };
+        let now = metrics::unix_millis() / 1000;
+        let stale_after_secs = self.config.stats_stale_after_secs;
+        info!("Indexing stats:\n{}", metrics::stats_table(&stats, now, stale_after_secs));
+        for row in stats.iter().filter(|row| row.is_stale(now, stale_after_secs)) {
+            warn!("Stats of {} on chain {} not updated for {}s", row.dex_name, row.chain_id, row.update_age_secs(now));
+        }
+    }
+
+    /// Swap activity of each of the last `n` processed blocks, oldest first.
+    pub async fn get_last_n_blocks_stats(&self, n: u64) -> Result<Vec<BlockStats>> {
+        let to_block = self.last_processed_block();
+        let from_block = (to_block + 1).saturating_sub(n);
+        self.database.get_block_stats_range(self.config.chain_id as i64, from_block as i64, to_block as i64).await
+    }
+
+    pub async fn get_stats(&self) -> Result<(u64, u64, u64)> {
+        let (total_pools, total_swaps) = self.database.get_stats().await?;
+        Ok((self.last_processed_block(), total_pools, total_swaps))
+    }
+}
+
+impl<P: PubsubClient + 'static> Indexer<P> {
+    /// Discovers pools through a `PoolCreated` log subscription while swaps keep being polled.
+    ///
+    /// New pools arrive as soon as the factory log is mined instead of on the next poll.
+    /// Swaps stay on `eth_getLogs` polling: one subscription per pool does not scale to
+    /// thousands of pools, and subscriptions drop logs across reconnects whereas the poll
+    /// cursor resumes exactly where it stopped. Pool creations up to the head block at the
+    /// time the subscription opened, downtime and catch-up included, are still polled. If
+    /// the subscription ends, pool discovery falls back to polling.
+    pub async fn start_streaming(&mut self) -> Result<()> {
+        info!("Starting indexer with streamed pool discovery...");
+        let factory_address: Address = self.config.moonshot_factory_address.parse()?;
+        let chain_id = self.config.chain_id as i64;
+        let handler = MoonshotHandler::with_decoder(self.provider.clone(), self.handler.decoder());
+        let (tx, mut rx) = mpsc::unbounded_channel::<Result<PoolData>>();
+        let (started_tx, mut started_rx) = oneshot::channel::<u64>();
+        let provider = self.provider.clone();
+
+        tokio::spawn(async move {
+            let pools = match handler.subscribe_pool_created(factory_address, chain_id).await {
+                Ok(pools) => pools,
+                Err(e) => {
+                    error!("Failed to subscribe to pool creation logs: {}", e);
+                    return;
+                }
+            };
+            futures::pin_mut!(pools);
+            // The subscription only sees logs from now on; the head it opened at is still polled
+            match provider.get_block_number().await {
+                Ok(head) => {
+                    let _ = started_tx.send(head.as_u64());
+                }
+                Err(e) => {
+                    error!("Failed to read the head block for pool creation streaming: {}", e);
+                    return;
+                }
+            }
+
+            while let Some(pool) = pools.next().await {
+                if tx.send(pool).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut streaming = true;
+        let mut poll = tokio::time::interval(Duration::from_millis(self.config.poll_interval_ms));
+
+        loop {
+            tokio::select! {
+                started = &mut started_rx, if streaming && self.pools_streamed_after.is_none() => match started {
+                    Ok(head) => {
+                        info!("Streaming pool creations after block {}", head);
+                        self.pools_streamed_after = Some(head);
+                    }
+                    // The task ended before subscribing; `rx` reports it
+                    Err(_) => streaming = false,
+                },
+                pool = rx.recv(), if streaming => match pool {
+                    Some(Ok(pool_data)) if self.config.is_pool_excluded(&pool_data.pool_address) => {
+                        debug!("Skipping excluded pool {}", pool_data.pool_address);
+                    }
+                    Some(Ok(pool_data)) => {
+                        info!("New pool created: {}", pool_data);
+
+                        if let Err(e) = self.store_new_pool(&pool_data).await {
+                            error!("Error storing pool: {}", e);
+                        } else {
+                            self.pools_processed += 1;
+                        }
+                    }
+                    Some(Err(e)) => error!("Error parsing pool creation event: {}", e),
+                    None => {
+                        warn!("Pool creation subscription ended, falling back to polling");
+                        streaming = false;
+                        self.pools_streamed_after = None;
+                    }
+                },
+                _ = poll.tick() => {
+                    if let Err(e) = self.process_blocks().await {
+                        error!("Error processing blocks: {}", e);
+                        report_retryable_error(&e);
+                    }
+                    let period = Duration::from_millis(self.config.poll_interval_ms);
+                    if poll.period() != period {
+                        poll = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What the swap writer task receives, in order.
+enum WriterMessage {
+    Swaps(Vec<SwapEvent>),
+    /// Every swap of `[from_block, to_block]` was sent: committed to `indexing_stats`,
+    /// forwarded to the archiver, and the indexed cursor moved past it.
+    RangeDone { from_block: u64, to_block: u64, head_block: u64, new_pools: u64 },
+}
+
+/// The parts of range indexing that need no mutable indexer state, owned so parallel
+/// backfill chunks can each run one on their own task.
+struct RangeWorker<P> {
+    provider: Arc<Provider<P>>,
+    database: Database,
+    handler: Arc<MoonshotHandler<P>>,
+    scope: IndexingScope,
+    factory_address: String,
+    chain_id: i64,
+    batch_size: u64,
+    max_blocks_per_log_request: u64,
+    decode_workers: usize,
+    excluded_pools: Vec<String>,
+    budget: Arc<MemoryBudget>,
+    swap_writer: mpsc::UnboundedSender<WriterMessage>,
+    new_pools: EventBroadcaster<PoolData>,
+}
+
+// Derived `Clone` would needlessly require `P: Clone`
+impl<P> Clone for RangeWorker<P> {
+    fn clone(&self) -> Self {
+        Self {
+            provider: self.provider.clone(),
+            database: self.database.clone(),
+            handler: self.handler.clone(),
+            scope: self.scope.clone(),
+            factory_address: self.factory_address.clone(),
+            chain_id: self.chain_id,
+            batch_size: self.batch_size,
+            max_blocks_per_log_request: self.max_blocks_per_log_request,
+            decode_workers: self.decode_workers,
+            excluded_pools: self.excluded_pools.clone(),
+            budget: self.budget.clone(),
+            swap_writer: self.swap_writer.clone(),
+            new_pools: self.new_pools.clone(),
+        }
+    }
+}
+
+impl<P: JsonRpcClient + 'static> RangeWorker<P> {
+    /// Same as `Config::is_pool_excluded`, for the settings the worker was made with.
+    fn is_pool_excluded(&self, pool_address: &str) -> bool {
+        self.excluded_pools.iter().any(|excluded| excluded.eq_ignore_ascii_case(pool_address))
+    }
+
+    /// `[from_block, to_block]` in consecutive ranges of `batch_size` blocks.
+    fn batches(&self, from_block: u64, to_block: u64) -> Vec<(u64, u64)> {
+        let mut batches = Vec::new();
+        let mut start = from_block;
+        while start <= to_block {
+            let end = std::cmp::min(to_block, start + self.batch_size - 1);
+            batches.push((start, end));
+            start = end + 1;
+        }
+        batches
+    }
+
+    /// Logs matching `filter` over `[from_block, to_block]`, in requests no wider than
+    /// `max_blocks_per_log_request` blocks.
+    async fn get_logs(&self, filter: &Filter, from_block: u64, to_block: u64) -> Result<Vec<Log>> {
+        chain::get_logs_chunked(self.provider.as_ref(), filter, from_block, to_block, self.max_blocks_per_log_request)
+            .await
+    }
+
+    async fn block_timestamp(&self, block_number: u64) -> Result<u64> {
+        CachedHeaders::new(self.provider.as_ref(), &self.database, self.chain_id)
+            .block_timestamp(block_number)
+            .await
+    }
+
+    /// Records a newly created pool; only pools inside the indexing scope get polled for swaps.
+    async fn store_new_pool(&self, pool_data: &PoolData) -> Result<()> {
+        self.database.upsert_pool(pool_data).await?;
+
+        // Caches the creation block's timestamp, which places the pool in token timelines
+        if let Some(block_number) = pool_data.created_at_block {
+            if let Err(e) = self.block_timestamp(block_number as u64).await {
+                warn!("Error reading timestamp of block {}: {}", block_number, e);
+            }
+        }
+
+        let tracked = self.scope.is_tracked(pool_data);
+        if !tracked {
+            debug!("Pool {} is outside the indexing scope, not tracking swaps", pool_data.pool_address);
+        }
+        self.database.set_pool_tracked(&pool_data.pool_address, tracked).await?;
+        self.new_pools.send(pool_data.clone());
+        Ok(())
+    }
+
+    async fn process_pool_events(&self, from_block: u64, to_block: u64) -> Result<u64> {
+        let factory_address: Address = self.factory_address.parse()?;
+
+        let filter = Filter::new().address(factory_address).topic0(self.handler.decoder().pool_created_topic());
+
+        let logs = self.get_logs(&filter, from_block, to_block).await?;
+        let decoded = decode::decode_pool_created(self.handler.decoder(), logs, self.chain_id).await?;
+        let mut pools_processed = 0;
+
+        for pool_data in decoded {
+            let pool_data = match pool_data {
+                Ok(pool_data) if self.is_pool_excluded(&pool_data.pool_address) => {
+                    debug!("Skipping excluded pool {}", pool_data.pool_address);
+                    continue;
+                }
+                Ok(mut pool_data) => self.handler.fetch_pool_token_metadata(&mut pool_data).await.map(|()| pool_data),
+                Err(e) => Err(e),
+            };
+            match pool_data {
                 Ok(pool_data) => {
-                    info!("New pool created: {} (tokens: {} <-> {})", 
-                          pool_data.pool_address, pool_data.token0_symbol.as_deref().unwrap_or("Unknown"), 
-                          pool_data.token1_symbol.as_deref().unwrap_or("Unknown"));
+                    info!("New pool created: {}", pool_data);
                     
-                    if let Err(e) = self.database.upsert_pool(&pool_data).await {
+                    if let Err(e) = self.store_new_pool(&pool_data).await {
                         error!("Error storing pool: {}", e);
                     } else {
                         pools_processed += 1;
@@ -145,63 +1554,99 @@ impl Indexer {
         Ok(pools_processed)
     }
 
-    async fn process_swap_events(&self, from_block: u64, to_block: u64) -> Result<u64> {
-        // Get all known pools from database to filter swap events
-        let known_pools = self.database.get_all_pool_addresses().await?;
-        
-        if known_pools.is_empty() {
-            debug!("No known pools found, skipping swap processing");
-            return Ok(0);
+    /// Decoded, valid swaps of one pool with their block timestamps filled in.
+    async fn fetch_swaps(&self, pool_address: &str, from_block: u64, to_block: u64, log_swaps: bool) -> Result<Vec<SwapEvent>> {
+        let pool_addr: Address = pool_address.parse()?;
+
+        let filter = Filter::new().address(pool_addr).topic0(self.handler.decoder().swap_topic());
+
+        let logs = self.get_logs(&filter, from_block, to_block).await?;
+        let decoded = decode::decode_swaps(self.handler.decoder(), logs, self.chain_id).await?;
+        let swaps = runtime::decode_in_order(decoded, self.decode_workers, |(log, swap)| {
+            self.complete_swap(log, swap, log_swaps)
+        })
+        .await;
+
+        Ok(swaps.into_iter().flatten().collect())
+    }
+
+    /// A decoded swap log validated and with its block timestamp filled in.
+    async fn complete_swap(&self, log: Log, decoded: Result<SwapEvent>, log_swaps: bool) -> Option<SwapEvent> {
+        match decoded {
+            Ok(mut swap_event) => {
+                if log_swaps {
+                    debug!("Swap event: {}", swap_event);
+                }
+
+                if let Err(e) = swap_event.validate() {
+                    warn!("Dropping swap at {}:{}: {} (raw log: {:?})",
+                          swap_event.tx_hash, swap_event.log_index, e, log);
+                    return None;
+                }
+
+                match self.block_timestamp(swap_event.block_number as u64).await {
+                    Ok(timestamp) => swap_event.timestamp = timestamp as i64,
+                    Err(e) => warn!("Error reading timestamp of block {}: {}", swap_event.block_number, e),
+                }
+
+                Some(swap_event)
+            }
+            Err(e) => {
+                error!("Error parsing swap event: {}", e);
+                None
+            }
         }
+    }
 
+    /// Hands swaps to the writer task, charging them to the memory budget until written.
+    fn write_swaps(&self, swaps: Vec<SwapEvent>) -> Result<()> {
+        self.budget.charge(MemoryBudget::estimate_batch_bytes(&swaps));
+        self.swap_writer.send(WriterMessage::Swaps(swaps))?;
+        Ok(())
+    }
+
+    /// Indexes the swaps of `pools` across `[from_block, to_block]` in `batch_size` ranges,
+    /// without refreshing pool state.
+    async fn process_swap_events(&self, pools: &[String], from_block: u64, to_block: u64) -> Result<u64> {
         let mut swaps_processed = 0;
 
-        // Process swap events for each known pool
-        for pool_address in known_pools {
-            let pool_addr: Address = pool_address.parse()?;
-            
-            let filter = Filter::new()
-                .from_block(from_block)
-                .to_block(to_block)
-                .address(pool_addr)
-                .event("Swap(address,address,int256,int256,uint160,uint128,int24)");
-
-            let logs = self.provider.get_logs(&filter).await?;
-
-            for log in logs {
-                match self.handler.handle_swap(log, self.config.chain_id as i64).await {
-                    Ok(swap_event) => {
-                        debug!("Swap event: {} -> {} (amount: {})", 
-                            swap_event.token_in, swap_event.token_out, swap_event.amount_in);
-                        
-                        if let Err(e) = self.database.insert_swap(&swap_event).await {
-                            error!("Error storing swap: {}", e);
-                        } else {
-                            swaps_processed += 1;
-                        }
+        for (start, end) in self.batches(from_block, to_block) {
+            self.budget.wait_for_capacity().await;
 
-                        // Update pool state after swap
-                        if let Ok(pool_address) = swap_event.pool_address.parse::<Address>() {
-                            if let Ok(pool_data) = self.handler.update_pool_state(pool_address, self.config.chain_id as i64).await {
-                                if let Err(e) = self.database.upsert_pool(&pool_data).await {
-                                    warn!("Error updating pool state: {}", e);
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!("Error parsing swap event: {}", e);
-                    }
+            for pool_address in pools {
+                if self.is_pool_excluded(pool_address) {
+                    debug!("Skipping swaps of excluded pool {}", pool_address);
+                    continue;
+                }
+                let decoded = self.fetch_swaps(pool_address, start, end, false).await?;
+                if !decoded.is_empty() {
+                    swaps_processed += decoded.len() as u64;
+                    self.write_swaps(decoded)?;
                 }
             }
         }
 
         Ok(swaps_processed)
     }
+}
 
-    pub async fn get_stats(&self) -> Result<(u64, u64, u64)> {
-        let (total_pools, total_swaps) = self.database.get_stats().await?;
-        Ok((self.last_processed_block, total_pools, total_swaps))
+/// Adds up the per-chunk counts of parallel tasks, failing if any task did.
+fn sum_chunk_counts(counts: Vec<Result<u64>>) -> Result<u64> {
+    counts.into_iter().sum()
+}
+
+/// Reports an error the indexer recovers from by retrying; a no-op unless Sentry is set up.
+fn report_retryable_error(error: &anyhow::Error) {
+    sentry::capture_message(&format!("Error processing blocks: {}", error), sentry::Level::Error);
+}
+
+/// The part of `[from, to]` whose pool creations must be polled: all of it unless a
+/// subscription delivers those after `streamed_after`, in which case only blocks up to it.
+fn pool_poll_range(from: u64, to: u64, streamed_after: Option<u64>) -> Option<(u64, u64)> {
+    match streamed_after {
+        Some(streamed_after) if from > streamed_after => None,
+        Some(streamed_after) => Some((from, to.min(streamed_after))),
+        None => Some((from, to)),
     }
 }
 
@@ -210,6 +1655,17 @@ mod tests {
     use super::*;
 
     #[test]
+    fn test_pool_creations_before_the_subscription_are_polled() {
+        assert_eq!(pool_poll_range(100, 199, None), Some((100, 199)));
+        // Downtime and catch-up ranges below the subscription's start are polled in full
+        assert_eq!(pool_poll_range(100, 199, Some(500)), Some((100, 199)));
+        assert_eq!(pool_poll_range(450, 549, Some(500)), Some((450, 500)));
+        assert_eq!(pool_poll_range(500, 599, Some(500)), Some((500, 500)));
+        assert_eq!(pool_poll_range(501, 600, Some(500)), None);
+    }
+
+    #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn test_indexer_creation() {
         // This would require a real config and connections
         // For now, just test that the struct can be conceptualized
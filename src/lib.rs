@@ -1,13 +1,58 @@
+pub mod analytics;
+pub mod archive;
+pub mod api;
+pub mod batching;
+pub mod budget;
+pub mod candles;
+pub mod catchup;
+pub mod chain;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod config;
+pub mod coordination;
+pub mod correlation;
+pub mod db;
+pub mod dex;
+pub mod doctor;
+pub mod enrich;
+pub mod error;
+pub mod events;
+pub mod holders;
+pub mod indexer;
+pub mod lifecycle;
+pub mod maintenance;
+pub mod metrics;
+pub mod mev;
+pub mod native_price;
+pub mod nats;
+pub mod migration;
+pub mod nonstandard;
+pub mod pool_balances;
+pub mod pool_state;
+pub mod position;
+pub mod reload;
+pub mod replay;
+pub mod runtime;
+pub mod price;
 pub mod moonshot;
+pub mod scope;
+pub mod supply;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod types;
+pub mod wash;
+pub mod watchlist;
 
 pub use config::Config;
-pub use types::{IndexingStats, PoolData, SwapEvent, TokenData};
+pub use types::{
+    CurveTrade, HolderSnapshot, IndexingStats, IndexingStatsDiff, InvalidEventError, MissingDecimalsError, PoolData, SwapEvent, TokenData,
+    TickData, TokenMigration, TradeSide,
+};
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::dex::MOONSHOT;
 
     #[test]
     fn test_pool_data_creation() {
@@ -16,7 +61,7 @@ mod tests {
             "0xTokenA".to_string(),
             "0xTokenB".to_string(),
             8453,
-            "moonshot".to_string(),
+            MOONSHOT,
         );
 
         assert_eq!(
@@ -69,7 +114,12 @@ mod tests {
             sqrt_price_x96: Some("123456789".to_string()),
             tick: Some(1000),
             chain_id: 8453,
-            dex_name: "moonshot".to_string(),
+            dex_name: MOONSHOT,
+            created_at_block: None,
+            updated_at_block: None,
+            has_nonstandard_token: false,
+            created_at: None,
+            updated_at: None,
         };
 
         let json = serde_json::to_string(&pool).unwrap();
@@ -81,4 +131,113 @@ mod tests {
         assert_eq!(pool.chain_id, deserialized.chain_id);
         assert_eq!(pool.dex_name, deserialized.dex_name);
     }
+
+    #[test]
+    fn test_explorer_urls() {
+        let mut swap = SwapEvent::new(
+            "0xabc".to_string(),
+            "0xPoolAddress".to_string(),
+            "token0".to_string(),
+            "token1".to_string(),
+            1000,
+            950,
+            1640995200,
+            12345,
+            0,
+            1,
+        );
+        assert_eq!(swap.explorer_tx_url().as_deref(), Some("https://etherscan.io/tx/0xabc"));
+
+        swap.chain_id = 8453;
+        assert_eq!(swap.explorer_tx_url().as_deref(), Some("https://basescan.org/tx/0xabc"));
+
+        swap.chain_id = 999_999;
+        assert_eq!(swap.explorer_tx_url(), None);
+
+        let mut pool = PoolData::new(
+            "0xPoolAddress".to_string(),
+            "0xTokenA".to_string(),
+            "0xTokenB".to_string(),
+            1,
+            MOONSHOT,
+        );
+        assert_eq!(
+            pool.explorer_address_url().as_deref(),
+            Some("https://etherscan.io/address/0xPoolAddress")
+        );
+
+        pool.chain_id = 8453;
+        assert_eq!(
+            pool.explorer_address_url().as_deref(),
+            Some("https://basescan.org/address/0xPoolAddress")
+        );
+    }
+
+    #[test]
+    fn test_human_amounts() {
+        let mut pool = PoolData::new(
+            "0xPoolAddress".to_string(),
+            "0xTokenA".to_string(),
+            "0xTokenB".to_string(),
+            8453,
+            MOONSHOT,
+        );
+        pool.token0_decimals = Some(6);
+        pool.token1_decimals = Some(18);
+
+        // 2.5 of the 6-decimal token in, 1.25 of the 18-decimal token out
+        let swap = SwapEvent::new(
+            "0xabc".to_string(),
+            "0xPoolAddress".to_string(),
+            "token0".to_string(),
+            "token1".to_string(),
+            2_500_000,
+            1_250_000_000_000_000_000,
+            1640995200,
+            12345,
+            0,
+            8453,
+        );
+        assert_eq!(swap.human_amounts(&pool), (2.5, 1.25));
+
+        // Tokens given by address resolve the same way, in the other direction
+        let reverse = SwapEvent::new(
+            "0xabc".to_string(),
+            "0xPoolAddress".to_string(),
+            "0xtokenb".to_string(),
+            "0xtokena".to_string(),
+            1_250_000_000_000_000_000,
+            2_500_000,
+            1640995200,
+            12345,
+            1,
+            8453,
+        );
+        assert_eq!(reverse.human_amounts(&pool), (1.25, 2.5));
+    }
+
+    #[test]
+    fn test_implied_tick_round_trip() {
+        let mut pool = PoolData::new(
+            "0xPoolAddress".to_string(),
+            "0xTokenA".to_string(),
+            "0xTokenB".to_string(),
+            8453,
+            MOONSHOT,
+        );
+        pool.tick = Some(-73_136);
+
+        // Decimals are required
+        let err = pool.implied_tick_from_price(1.0).unwrap_err();
+        assert!(err.downcast_ref::<MissingDecimalsError>().is_some());
+
+        pool.token0_decimals = Some(6);
+        pool.token1_decimals = Some(18);
+        let tick = pool.tick.unwrap();
+        let price = price::tick_to_price(tick, 6, 18);
+
+        assert_eq!(pool.implied_tick_from_price(price).unwrap(), tick);
+        assert!(pool.is_price_in_range(price, tick - 60, tick + 60).unwrap());
+        assert!(!pool.is_price_in_range(price, tick + 1, tick + 60).unwrap());
+    }
 }
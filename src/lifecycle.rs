@@ -0,0 +1,219 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::Config;
+use crate::db::Database;
+
+/// Where a pool sits in the polling hierarchy.
+///
+/// Active pools are polled for swaps every cycle, stale pools in a slower secondary group,
+/// and archived pools not at all. Unresponsive pools stopped answering state calls
+/// (rugged, self-destructed or paused) and are only retried on a slow schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PoolStatus {
+    Active,
+    Stale,
+    Archived,
+    Unresponsive,
+}
+
+impl PoolStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PoolStatus::Active => "active",
+            PoolStatus::Stale => "stale",
+            PoolStatus::Archived => "archived",
+            PoolStatus::Unresponsive => "unresponsive",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Self> {
+        match status {
+            "active" => Some(PoolStatus::Active),
+            "stale" => Some(PoolStatus::Stale),
+            "archived" => Some(PoolStatus::Archived),
+            "unresponsive" => Some(PoolStatus::Unresponsive),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LifecyclePolicy {
+    pub stale_after_secs: i64,
+    pub archive_after_secs: i64,
+}
+
+impl LifecyclePolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            stale_after_secs: config.stale_after_hours as i64 * 3600,
+            archive_after_secs: config.archive_after_days as i64 * 86400,
+        }
+    }
+
+    /// Status a pool should have given the timestamp of its last activity.
+    pub fn status_for(&self, last_activity: i64, now: i64) -> PoolStatus {
+        let idle = now - last_activity;
+        if idle >= self.archive_after_secs {
+            PoolStatus::Archived
+        } else if idle >= self.stale_after_secs {
+            PoolStatus::Stale
+        } else {
+            PoolStatus::Active
+        }
+    }
+
+    /// Status after a maintenance pass. Time only ever demotes a pool; promotion back to
+    /// active happens when a swap is observed.
+    pub fn transition(&self, current: PoolStatus, last_activity: i64, now: i64) -> PoolStatus {
+        let by_age = self.status_for(last_activity, now);
+        if rank(by_age) > rank(current) {
+            by_age
+        } else {
+            current
+        }
+    }
+}
+
+fn rank(status: PoolStatus) -> u8 {
+    match status {
+        PoolStatus::Active => 0,
+        PoolStatus::Stale => 1,
+        PoolStatus::Archived => 2,
+        // Left to the state refresh retries, never changed by age
+        PoolStatus::Unresponsive => 3,
+    }
+}
+
+/// Status changes made since startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransitionCounts {
+    pub to_active: u64,
+    pub to_stale: u64,
+    pub to_archived: u64,
+    pub to_unresponsive: u64,
+}
+
+impl TransitionCounts {
+    pub fn record(&mut self, status: PoolStatus) {
+        match status {
+            PoolStatus::Active => self.to_active += 1,
+            PoolStatus::Stale => self.to_stale += 1,
+            PoolStatus::Archived => self.to_archived += 1,
+            PoolStatus::Unresponsive => self.to_unresponsive += 1,
+        }
+    }
+
+    pub fn add(&mut self, other: TransitionCounts) {
+        self.to_active += other.to_active;
+        self.to_stale += other.to_stale;
+        self.to_archived += other.to_archived;
+        self.to_unresponsive += other.to_unresponsive;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.to_active + self.to_stale + self.to_archived + self.to_unresponsive
+    }
+}
+
+/// Demotes idle pools of a chain; `now` is a unix timestamp, the chain time indexing has
+/// reached, so callers can simulate the clock.
+pub async fn run_maintenance(database: &Database, policy: &LifecyclePolicy, chain_id: i64, now: i64) -> Result<TransitionCounts> {
+    let mut counts = TransitionCounts::default();
+
+    for (pool_address, current, last_activity) in database.get_pool_activity(chain_id).await? {
+        let next = policy.transition(current, last_activity, now);
+        if next != current {
+            database.set_pool_status(&pool_address, next).await?;
+            info!("Pool {} is now {} (was {})", pool_address, next.as_str(), current.as_str());
+            counts.record(next);
+        }
+    }
+
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3600;
+    const DAY: i64 = 86400;
+
+    fn policy() -> LifecyclePolicy {
+        LifecyclePolicy {
+            stale_after_secs: 6 * HOUR,
+            archive_after_secs: 3 * DAY,
+        }
+    }
+
+    #[test]
+    fn test_status_round_trip() {
+        for status in [PoolStatus::Active, PoolStatus::Stale, PoolStatus::Archived, PoolStatus::Unresponsive] {
+            assert_eq!(PoolStatus::parse(status.as_str()), Some(status));
+        }
+        assert_eq!(PoolStatus::parse("dead"), None);
+    }
+
+    #[test]
+    fn test_transitions_over_simulated_clock() {
+        let policy = policy();
+        let last_swap = 1_000_000;
+        let mut status = PoolStatus::Active;
+
+        // Active until the stale threshold
+        status = policy.transition(status, last_swap, last_swap + 6 * HOUR - 1);
+        assert_eq!(status, PoolStatus::Active);
+
+        // Active -> stale
+        status = policy.transition(status, last_swap, last_swap + 6 * HOUR);
+        assert_eq!(status, PoolStatus::Stale);
+
+        // Stays stale until the archive threshold
+        status = policy.transition(status, last_swap, last_swap + 3 * DAY - 1);
+        assert_eq!(status, PoolStatus::Stale);
+
+        // Stale -> archived
+        status = policy.transition(status, last_swap, last_swap + 3 * DAY);
+        assert_eq!(status, PoolStatus::Archived);
+    }
+
+    #[test]
+    fn test_active_pool_can_be_archived_directly() {
+        let policy = policy();
+        assert_eq!(
+            policy.transition(PoolStatus::Active, 0, 4 * DAY),
+            PoolStatus::Archived
+        );
+    }
+
+    #[test]
+    fn test_maintenance_never_promotes() {
+        let policy = policy();
+        // A fresh swap timestamp alone does not reactivate; the indexer does that on a swap
+        assert_eq!(policy.transition(PoolStatus::Stale, 100, 100), PoolStatus::Stale);
+        assert_eq!(policy.transition(PoolStatus::Archived, 100, 100), PoolStatus::Archived);
+        assert_eq!(policy.transition(PoolStatus::Unresponsive, 0, 4 * DAY), PoolStatus::Unresponsive);
+    }
+
+    #[test]
+    fn test_transition_counts() {
+        let mut counts = TransitionCounts::default();
+        counts.record(PoolStatus::Stale);
+        counts.record(PoolStatus::Stale);
+        counts.record(PoolStatus::Active);
+
+        let mut total = TransitionCounts::default();
+        total.add(counts);
+        total.record(PoolStatus::Archived);
+
+        assert_eq!(
+            total,
+            TransitionCounts { to_active: 1, to_stale: 2, to_archived: 1, to_unresponsive: 0 }
+        );
+        assert_eq!(total.total(), 4);
+    }
+}
@@ -1,18 +1,186 @@
-use anyhow::Result;
-use std::env;
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use ethers::providers::{Provider, Ws};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::Arc;
 use tokio::signal;
-use tracing::{info, error, warn};
+use tracing::{info, error, info_span, warn, Instrument};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, reload};
 
-use moonshot_indexer::config::Config;
-use moonshot_indexer::indexer::Indexer;
+use moonshot_indexer::api;
+use moonshot_indexer::candles::{self, PriceAnchors, RebuildOptions};
+use moonshot_indexer::config::{redacted, Config};
+use moonshot_indexer::coordination;
+use moonshot_indexer::correlation;
+use moonshot_indexer::db::{Database, QueryLimits};
+use moonshot_indexer::dex::DexRegistry;
+use moonshot_indexer::doctor::{self, CheckResult};
+use moonshot_indexer::enrich::{self, EnrichPolicy};
+use moonshot_indexer::indexer::{self, Indexer};
+use moonshot_indexer::maintenance::{self, MaintenancePolicy};
+use moonshot_indexer::moonshot::LogDecoder;
+#[cfg(feature = "nats")]
+use moonshot_indexer::nats::{self, NatsSink};
+use moonshot_indexer::reload::ConfigReloader;
+use moonshot_indexer::replay::{self, JsonlSink, ReplayOptions, Sink, SinkOffsetStore, WebhookSink};
+use moonshot_indexer::runtime::RuntimeSettings;
+use moonshot_indexer::wash::{self, WashPolicy};
+
+#[derive(Parser)]
+#[command(about = "Moonshot indexer for Abstract chain")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Send a synthetic event to the configured Sentry DSN and exit
+    #[arg(long)]
+    test_sentry: bool,
+    /// Decode the pool creations and swaps of this block and print them as JSON, without
+    /// writing anything
+    #[arg(long)]
+    replay_block: Option<u64>,
+    /// Run even while another process indexes the same chain, or backfills overlapping
+    /// blocks; stored rows are still deduplicated
+    #[arg(long, global = true)]
+    allow_concurrent: bool,
+    /// Log a command and its database statements under this id, e.g. one taken from the
+    /// API request or ticket it follows up on; a fresh one when unset
+    #[arg(long, global = true)]
+    request_id: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Index a historical range, bounded by block numbers or unix timestamps
+    Backfill {
+        #[arg(long, conflicts_with = "from_time")]
+        from_block: Option<u64>,
+        #[arg(long, conflicts_with = "to_time")]
+        to_block: Option<u64>,
+        /// Starts at the first block at or after this timestamp
+        #[arg(long)]
+        from_time: Option<u64>,
+        /// Ends at the last block at or before this timestamp
+        #[arg(long)]
+        to_time: Option<u64>,
+    },
+    /// Add missing `tokens` rows for tokens referenced by indexed pools
+    SyncTokens,
+    /// Stream stored swaps and pool creations with `from_ts <= timestamp < to_ts` into a
+    /// sink, tagged `"replay": true`, without touching the chain
+    Replay {
+        #[arg(long)]
+        from_ts: i64,
+        #[arg(long)]
+        to_ts: i64,
+        #[arg(long, value_enum)]
+        sink: SinkKind,
+        /// File the `jsonl` sink writes to
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// URL the `webhook` sink POSTs each event to
+        #[arg(long)]
+        webhook_url: Option<String>,
+        /// Replay this many times faster than real time; full speed when unset
+        #[arg(long)]
+        speed: Option<f64>,
+        /// Remember delivered events under this name, so a rerun after a crash resumes
+        /// after the last acknowledged one
+        #[arg(long)]
+        sink_name: Option<String>,
+        /// Add token-unit amounts and explorer links to swaps and pool creations
+        #[arg(long)]
+        pretty: bool,
+    },
+    /// Flag suspected wash trades among stored swaps with `from_ts <= timestamp < to_ts`,
+    /// for ranges older than the background scorer looks at
+    ScoreWash {
+        #[arg(long)]
+        from_ts: i64,
+        #[arg(long)]
+        to_ts: i64,
+    },
+    /// Read the state of pools that are indexed but were never processed (NULL liquidity)
+    ProcessNewPools {
+        /// Process at most this many pools
+        #[arg(long, default_value_t = 100)]
+        max: usize,
+    },
+    /// Backfill USD amounts of stored swaps with `from_ts <= timestamp < to_ts` from
+    /// historical token prices; resumes where an interrupted run over the same range stopped
+    EnrichUsd {
+        #[arg(long)]
+        from_ts: i64,
+        #[arg(long)]
+        to_ts: i64,
+    },
+    /// Check the RPC endpoint, factory, database and clock, printing a pass/fail table;
+    /// exits non-zero if any check fails
+    Doctor {
+        /// Blocks before the head searched for a PoolCreated event of the factory
+        #[arg(long, default_value_t = doctor::DEFAULT_SAMPLE_BLOCKS)]
+        sample_blocks: u64,
+    },
+    /// Permanently delete a pool, or a token and every pool trading it, with everything
+    /// indexed for them
+    Purge {
+        #[arg(long, conflicts_with = "token", required_unless_present = "token")]
+        pool: Option<String>,
+        #[arg(long)]
+        token: Option<String>,
+        /// Confirm the deletion; nothing is deleted without it
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Replace the price buckets read from a pool, rebuilt from its recorded ticks, after a
+    /// change to how prices are derived; over the pool's whole history unless bounded
+    RebuildCandles {
+        #[arg(long)]
+        pool: String,
+        /// Bucket width; prices are only stored hourly
+        #[arg(long, default_value = candles::DEFAULT_INTERVAL, value_parser = candles::parse_interval)]
+        interval: i64,
+        #[arg(long)]
+        from_ts: Option<i64>,
+        #[arg(long)]
+        to_ts: Option<i64>,
+    },
+    /// Relabel every stored row of DEX `--from` as `--to`, after a rebrand
+    RenameDex {
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: String,
+        /// Chain whose rows are relabelled; CHAIN_ID when unset
+        #[arg(long)]
+        chain_id: Option<i64>,
+        /// Print the statements instead of running them
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SinkKind {
+    Jsonl,
+    Webhook,
+    Kafka,
+    Nats,
+}
+
+// The runtime is built by hand so its size follows the configuration
+fn main() -> Result<()> {
+    let cli = Cli::parse();
 
-#[tokio::main]
-async fn main() -> Result<()> {
     // Load environment variables
     dotenv::dotenv().ok();
     
-    // Initialize logging
-    tracing_subscriber::fmt::init();
+    // Initialize logging; the level follows LOG_LEVEL once the configuration is loaded
+    let (log_filter, log_filter_handle) = reload::Layer::new(LevelFilter::INFO);
+    tracing_subscriber::registry().with(log_filter).with(fmt::layer()).init();
     
     info!("🚀 Starting Moonshot Indexer on Abstract Chain");
     info!("==============================================");
@@ -22,7 +190,7 @@ async fn main() -> Result<()> {
         Ok(config) => {
             info!("Configuration loaded successfully");
             info!("Chain ID: {}", config.chain_id);
-            info!("RPC URL: {}", config.rpc_url);
+            info!("RPC URL: {}", redacted(&config.rpc_url));
             info!("Factory Address: {}", config.moonshot_factory_address);
             config
         }
@@ -32,10 +200,190 @@ async fn main() -> Result<()> {
         }
     };
 
+    let set_log_level = move |level: LevelFilter| Ok(log_filter_handle.reload(level)?);
+    set_log_level(config.log_level.parse()?)?;
+    let reloader = Arc::new(ConfigReloader::new(config.clone()).with_log_level(set_log_level));
+
+    let runtime = RuntimeSettings::from_config(&config);
+    info!("Runtime: {} worker threads, at most {} blocking threads, {} decode workers",
+          runtime.worker_threads, runtime.max_blocking_threads, runtime.decode_workers);
+    // Commands run under a request id; the indexer itself does not
+    let request_id = cli.command.is_some().then(|| correlation::accept(cli.request_id.as_deref()));
+    runtime.build_runtime()?.block_on(async move {
+        match request_id {
+            Some(request_id) => {
+                info!("Running command under request id {}", request_id);
+                let span = info_span!("command", request_id = %request_id);
+                correlation::scope(request_id, run(cli, config, reloader, runtime)).instrument(span).await
+            }
+            None => run(cli, config, reloader, runtime).await,
+        }
+    })
+}
+
+async fn run(cli: Cli, config: Config, reloader: Arc<ConfigReloader>, runtime: RuntimeSettings) -> Result<()> {
+    // Error reporting; the guard flushes pending events when main returns
+    let _sentry = config.sentry_dsn.as_deref().map(|dsn| {
+        let guard = sentry::init((dsn, sentry::ClientOptions {
+            release: sentry::release_name!(),
+            ..Default::default()
+        }));
+        sentry::configure_scope(|scope| {
+            scope.set_tag("chain_id", config.chain_id);
+        });
+        info!("Sentry error reporting enabled");
+        guard
+    });
+
+    if cli.test_sentry {
+        if config.sentry_dsn.is_none() {
+            bail!("--test-sentry needs SENTRY_DSN to be set");
+        }
+        let event_id = sentry::capture_message("Moonshot indexer Sentry test event", sentry::Level::Info);
+        info!("Sent Sentry test event {}", event_id);
+        return Ok(());
+    }
+
+    if let Some(Command::Replay { from_ts, to_ts, sink, output, webhook_url, speed, sink_name, pretty }) = cli.command {
+        let mut sink: Box<dyn Sink> = match (sink, output, webhook_url) {
+            (SinkKind::Jsonl, Some(path), _) => Box::new(JsonlSink::new(BufWriter::new(File::create(path)?))),
+            // Logs go to standard output, so the events need a file of their own
+            (SinkKind::Jsonl, None, _) => bail!("the jsonl sink needs --output"),
+            (SinkKind::Webhook, _, Some(url)) => Box::new(WebhookSink::new(url)),
+            (SinkKind::Webhook, _, None) => bail!("the webhook sink needs --webhook-url"),
+            (SinkKind::Kafka, _, _) => bail!("this build has no Kafka producer; replay into the jsonl or webhook sink"),
+            #[cfg(feature = "nats")]
+            (SinkKind::Nats, _, _) => Box::new(NatsSink::new(nats::connect(&config).await?, config.chain_id as i64)),
+            #[cfg(not(feature = "nats"))]
+            (SinkKind::Nats, _, _) => bail!("this build has no NATS client; rebuild with --features nats"),
+        };
+        let database = Database::new(&config.database_url).await?.with_limits(QueryLimits::from_config(&config));
+        let options = ReplayOptions { chain_id: config.chain_id as i64, from_ts, to_ts, speed, pretty };
+        let offsets = match sink_name {
+            Some(sink_name) => {
+                database.init_schema().await?;
+                Some(SinkOffsetStore::new(database.clone(), &sink_name, options.chain_id))
+            }
+            None => None,
+        };
+
+        let sent = replay::replay(&database, &options, sink.as_mut(), offsets.as_ref()).await?;
+        info!("Replayed {} events", sent);
+        return Ok(());
+    }
+
+    if let Some(Command::EnrichUsd { from_ts, to_ts }) = cli.command {
+        let database = Database::new(&config.database_url).await?.with_limits(QueryLimits::from_config(&config));
+        database.init_schema().await?;
+        let policy = EnrichPolicy::from_config(&config);
+
+        let counts = enrich::enrich_usd(&database, &policy, &enrich::cursor_name(from_ts, to_ts), from_ts, to_ts).await?;
+        info!("USD enrichment complete - {} priced, {} without a price", counts.priced, counts.unpriced);
+        return Ok(());
+    }
+
+    if let Some(Command::ScoreWash { from_ts, to_ts }) = cli.command {
+        let database = Database::new(&config.database_url).await?.with_limits(QueryLimits::from_config(&config));
+        database.init_schema().await?;
+
+        let flagged = wash::score_range(&database, &WashPolicy::from_config(&config), from_ts, to_ts).await?;
+        info!("Wash-trading scoring complete - {} swaps suspected", flagged);
+        return Ok(());
+    }
+
+    if let Some(Command::Doctor { sample_blocks }) = cli.command {
+        let mut results = Vec::new();
+        match Provider::<Ws>::connect(&config.rpc_url).await {
+            Ok(provider) => {
+                results.push(doctor::check_rpc(&provider, config.chain_id).await);
+                results.push(doctor::check_factory(&provider, &config, sample_blocks).await);
+                results.push(doctor::check_clock(&provider).await);
+            }
+            Err(e) => results.push(CheckResult::fail("rpc", format!("cannot connect to {}: {}", redacted(&config.rpc_url), e))),
+        }
+        match Database::new(&config.database_url).await {
+            Ok(database) => {
+                let database = database.with_limits(QueryLimits::from_config(&config));
+                results.push(doctor::check_database(&database).await);
+                results.push(doctor::check_write_permission(&database).await);
+            }
+            Err(e) => results.push(CheckResult::fail("database", format!("cannot connect: {}", e))),
+        }
+
+        print!("{}", doctor::format_report(&results));
+        let failed = results.iter().filter(|result| !result.passed).count();
+        if failed > 0 {
+            bail!("{} of {} checks failed", failed, results.len());
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Purge { pool, token, yes }) = cli.command {
+        if !yes {
+            bail!("purge deletes data permanently; rerun with --yes to confirm");
+        }
+        let database = Database::new(&config.database_url).await?.with_limits(QueryLimits::from_config(&config));
+        database.init_schema().await?;
+        let chain_id = config.chain_id as i64;
+
+        let counts = match (pool, token) {
+            (Some(pool), _) => database.delete_pool_data(&pool, chain_id).await?,
+            (None, Some(token)) => database.delete_token_data(&token, chain_id).await?,
+            (None, None) => bail!("purge needs --pool or --token"),
+        };
+        for (table, rows) in &counts.tables {
+            info!("Purged {} rows from {}", rows, table);
+        }
+        info!("Purge complete - {} rows deleted", counts.total());
+        return Ok(());
+    }
+
+    if let Some(Command::RebuildCandles { pool, interval, from_ts, to_ts }) = cli.command {
+        let database = Database::new(&config.database_url).await?.with_limits(QueryLimits::from_config(&config));
+        database.init_schema().await?;
+        let options = RebuildOptions { chain_id: config.chain_id as i64, pool_address: pool, interval_secs: interval, from_ts, to_ts };
+
+        let report = candles::rebuild(&database, &PriceAnchors::from_config(&config), &options).await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    if let Some(Command::RenameDex { from, to, chain_id, dry_run }) = cli.command {
+        let chain_id = chain_id.unwrap_or(config.chain_id as i64);
+        if dry_run {
+            println!("-- $1 = '{}', $2 = '{}', $3 = {}", from, to, chain_id);
+            for statement in Database::rename_dex_statements() {
+                println!("{};", statement);
+            }
+            return Ok(());
+        }
+        // The schema is not initialized first: it refuses to start while a stored name is
+        // not a DEX id, which is what this relabels
+        let database = Database::new(&config.database_url)
+            .await?
+            .with_limits(QueryLimits::from_config(&config))
+            .with_dexes(DexRegistry::from_config(&config)?);
+        let updated = database.rename_dex(&from, &to, chain_id).await?;
+        info!("Renamed DEX {} to {} - {} rows updated", from, to, updated);
+        return Ok(());
+    }
+
+    if let Some(block_number) = cli.replay_block {
+        // Only the node is read: no schema, scope or lock is touched
+        let provider = Provider::<Ws>::connect(&config.rpc_url).await?;
+        let decoder = LogDecoder::from_config(&config)?;
+        let report = indexer::replay_block(&provider, &decoder, &config, block_number).await?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
     // Create and start indexer
-    let mut indexer = match Indexer::new(config).await {
+    let stream_pool_creation = config.stream_pool_creation;
+    let mut indexer = match Indexer::new(config.clone()).await {
         Ok(indexer) => {
             info!("Indexer initialized successfully");
+            // Known once the decoder is checked against the registry
+            sentry::configure_scope(|scope| scope.set_tag("dex_name", indexer.dex()));
             indexer
         }
         Err(e) => {
@@ -44,6 +392,113 @@ async fn main() -> Result<()> {
         }
     };
 
+    // Serve the HTTP API alongside the indexer when configured
+    if let Some(bind_address) = config.api_bind_address.clone() {
+        // API queries go to the read replica when there is one
+        let database = Database::with_read_url(&config.database_url, config.database_read_url.as_deref())
+            .await?
+            .with_limits(QueryLimits::from_config(&config))
+            .with_dexes(DexRegistry::from_config(&config)?);
+        let provider = Arc::new(Provider::<Ws>::connect(&config.rpc_url).await?);
+        let state = api::ApiState::new(database, config.chain_id as i64)
+            .with_dex(indexer.dex())
+            .with_provider(provider)
+            .with_reloader(reloader.clone())
+            .with_runtime(runtime)
+            .with_progress(indexer.sync_progress())
+            .with_max_replica_lag(config.max_replica_lag_blocks)
+            .with_seconds_per_block(config.seconds_per_block)
+            .with_pool_watcher(indexer.pool_watcher())
+            .with_price_anchors(PriceAnchors::from_config(&config))
+            .with_stats_stale_after(config.stats_stale_after_secs);
+        tokio::spawn(async move {
+            if let Err(e) = api::serve(&bind_address, state).await {
+                error!("API server stopped: {}", e);
+            }
+        });
+    }
+
+    if let Some(Command::SyncTokens) = cli.command {
+        let added = indexer.sync_tokens_table().await?;
+        info!("Added {} tokens", added);
+        return Ok(());
+    }
+
+    if let Some(Command::ProcessNewPools { max }) = cli.command {
+        let pending = indexer.get_unprocessed_pools_count().await?;
+        let processed = indexer.process_unprocessed_pools(max).await?;
+        info!("Processed {} pools, {} left", processed, pending.saturating_sub(processed));
+        return Ok(());
+    }
+
+    if let Some(Command::Backfill { from_block, to_block, from_time, to_time }) = cli.command {
+        let from_block = match (from_block, from_time) {
+            (Some(block), _) => block,
+            (None, Some(timestamp)) => indexer.first_block_at_or_after_timestamp(timestamp).await?,
+            (None, None) => bail!("backfill needs --from-block or --from-time"),
+        };
+        let to_block = match (to_block, to_time) {
+            (Some(block), _) => block,
+            (None, Some(timestamp)) => indexer.block_for_timestamp(timestamp).await?,
+            (None, None) => bail!("backfill needs --to-block or --to-time"),
+        };
+
+        let _lock = if cli.allow_concurrent {
+            None
+        } else {
+            let database = Database::new(&config.database_url).await?;
+            let lock = coordination::lock_backfill(&database, config.chain_id, from_block, to_block).await;
+            Some(lock.context("another backfill covers these blocks; pass --allow-concurrent to run anyway")?)
+        };
+        tokio::select! {
+            result = indexer.backfill(from_block, to_block) => result?,
+            _ = signal::ctrl_c() => info!("Backfill interrupted"),
+        }
+        return Ok(());
+    }
+
+    // Held until the process exits; a second live indexer for the chain and DEX stops here
+    let _live_lock = if cli.allow_concurrent {
+        None
+    } else {
+        let database = Database::new(&config.database_url).await?;
+        let lock = coordination::lock_live(&database, config.chain_id, &indexer.dex()).await;
+        Some(lock.context("another indexer is live for this chain and DEX; pass --allow-concurrent to run anyway")?)
+    };
+
+    indexer.watch_config(reloader.subscribe());
+    if config.enrich_usd_interval_secs > 0 {
+        let database = Database::new(&config.database_url).await?.with_limits(QueryLimits::from_config(&config));
+        let interval = std::time::Duration::from_secs(config.enrich_usd_interval_secs);
+        enrich::spawn_worker(database, EnrichPolicy::from_config(&config), interval);
+    }
+    if config.detect_wash_trading {
+        let database = Database::new(&config.database_url).await?.with_limits(QueryLimits::from_config(&config));
+        let interval = std::time::Duration::from_secs(config.wash_scan_interval_secs);
+        wash::spawn_worker(database, WashPolicy::from_config(&config), interval);
+    }
+    if config.maintenance_interval_secs > 0 {
+        let database = Database::new(&config.database_url).await?.with_limits(QueryLimits::from_config(&config));
+        let interval = std::time::Duration::from_secs(config.maintenance_interval_secs);
+        maintenance::spawn_worker(database, MaintenancePolicy::from_config(&config), interval);
+    }
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        let mut hangups = match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => {
+                warn!("Config reload on SIGHUP unavailable: {}", e);
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            info!("SIGHUP received, reloading configuration");
+            if let Err(e) = reloader.reload() {
+                error!("Config reload failed, keeping the running configuration: {}", e);
+            }
+        }
+    });
+
     info!("Starting event processing...");
     info!("Press Ctrl+C to stop the indexer");
 
@@ -57,8 +512,18 @@ async fn main() -> Result<()> {
 
     // Run indexer until shutdown
     tokio::select! {
-        _ = indexer.start() => {
+        result = async {
+            if stream_pool_creation {
+                indexer.start_streaming().await
+            } else {
+                indexer.start().await
+            }
+        } => {
             error!("Indexer stopped unexpectedly");
+            if let Err(e) = result {
+                error!("Indexer error: {}", e);
+                sentry::capture_error(&*e);
+            }
         }
         _ = shutdown_signal => {
             info!("Shutting down gracefully...");
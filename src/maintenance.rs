@@ -0,0 +1,149 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::metrics;
+
+/// Tables churned by upserts and retention deletes, analyzed and reported on each pass.
+pub const HOT_TABLES: &[&str] = &["pools", "pool_snapshots", "swaps", "tick_history", "token_usd_prices", "tokens"];
+
+// Fewer dead tuples than this are not worth a warning, whatever the ratio
+const MIN_DEAD_TUPLES: i64 = 10_000;
+
+/// Size and dead-tuple counts of one table, from `pg_stat_user_tables`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TableHealth {
+    pub table_name: String,
+    /// Heap plus TOAST, without indexes.
+    pub table_bytes: i64,
+    /// All indexes of the table together.
+    pub index_bytes: i64,
+    pub live_tuples: i64,
+    pub dead_tuples: i64,
+    /// Unix time of the latest manual or automatic vacuum, None if never vacuumed.
+    pub last_vacuum_at: Option<i64>,
+}
+
+impl TableHealth {
+    /// Dead tuples over all tuples; 0 for an empty table.
+    pub fn dead_tuple_ratio(&self) -> f64 {
+        let total = self.live_tuples + self.dead_tuples;
+        if total == 0 {
+            0.0
+        } else {
+            self.dead_tuples as f64 / total as f64
+        }
+    }
+}
+
+/// One table's health as recorded in `maintenance_log`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TableReport {
+    #[serde(flatten)]
+    pub health: TableHealth,
+    pub needs_vacuum_full: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MaintenancePolicy {
+    /// Dead-tuple ratio from which a table is reported as bloated.
+    pub dead_tuple_ratio_threshold: f64,
+}
+
+impl MaintenancePolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self { dead_tuple_ratio_threshold: config.bloat_dead_tuple_ratio }
+    }
+
+    /// Whether the table carries more dead tuples than autovacuum keeps up with. Plain
+    /// vacuums only make the space reusable, so past this point a `VACUUM FULL` (or
+    /// pg_repack) is what gives it back.
+    pub fn needs_vacuum_full(&self, health: &TableHealth) -> bool {
+        health.dead_tuples >= MIN_DEAD_TUPLES && health.dead_tuple_ratio() >= self.dead_tuple_ratio_threshold
+    }
+}
+
+/// Analyzes each of `HOT_TABLES`, then records their sizes and dead tuples in the metrics
+/// registry and `maintenance_log`, warning about bloated ones. Never vacuums; that is left
+/// to autovacuum and, when reported as needed, to an operator.
+pub async fn run_maintenance(database: &Database, policy: &MaintenancePolicy) -> Result<Vec<TableReport>> {
+    for table in HOT_TABLES {
+        database.analyze_table(table).await?;
+    }
+
+    let reports: Vec<TableReport> = database
+        .get_table_health(HOT_TABLES)
+        .await?
+        .into_iter()
+        .map(|health| TableReport { needs_vacuum_full: policy.needs_vacuum_full(&health), health })
+        .collect();
+
+    for report in &reports {
+        let health = &report.health;
+        let table = health.table_name.as_str();
+        metrics::DB_TABLE_BYTES.with_label_values(&[table]).set(health.table_bytes as f64);
+        metrics::DB_INDEX_BYTES.with_label_values(&[table]).set(health.index_bytes as f64);
+        metrics::DB_DEAD_TUPLE_RATIO.with_label_values(&[table]).set(health.dead_tuple_ratio());
+        if report.needs_vacuum_full {
+            warn!(
+                "Table {} is {:.0}% dead tuples ({} of {}, {} MB with indexes); schedule a VACUUM FULL",
+                table,
+                health.dead_tuple_ratio() * 100.0,
+                health.dead_tuples,
+                health.live_tuples + health.dead_tuples,
+                (health.table_bytes + health.index_bytes) / (1024 * 1024)
+            );
+        }
+    }
+
+    let checked_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    database.insert_maintenance_log(checked_at, &reports).await?;
+    Ok(reports)
+}
+
+/// Runs maintenance on its own task every `interval`.
+pub fn spawn_worker(database: Database, policy: MaintenancePolicy, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            match run_maintenance(&database, &policy).await {
+                Ok(reports) => {
+                    let bloated = reports.iter().filter(|report| report.needs_vacuum_full).count();
+                    info!("Database maintenance - analyzed {} tables, {} need VACUUM FULL", reports.len(), bloated);
+                }
+                Err(e) => error!("Database maintenance failed: {}", e),
+            }
+            sleep(interval).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health(live_tuples: i64, dead_tuples: i64) -> TableHealth {
+        TableHealth {
+            table_name: "swaps".to_string(),
+            table_bytes: 8192,
+            index_bytes: 8192,
+            live_tuples,
+            dead_tuples,
+            last_vacuum_at: None,
+        }
+    }
+
+    #[test]
+    fn test_needs_vacuum_full() {
+        let policy = MaintenancePolicy { dead_tuple_ratio_threshold: 0.2 };
+        assert_eq!(health(0, 0).dead_tuple_ratio(), 0.0);
+        assert!(!policy.needs_vacuum_full(&health(90_000, 10_000)));
+        assert!(policy.needs_vacuum_full(&health(80_000, 20_000)));
+        // A high ratio on a small table is not worth the lock
+        assert!(!policy.needs_vacuum_full(&health(100, 9_000)));
+    }
+}
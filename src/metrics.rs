@@ -0,0 +1,386 @@
+use prometheus::{
+    register_gauge, register_gauge_vec, register_histogram, register_histogram_vec, register_int_counter_vec, Encoder, Gauge, GaugeVec,
+    Histogram, HistogramVec, IntCounterVec, TextEncoder,
+};
+use std::collections::VecDeque;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::types::{IndexingStats, IndexingStatsDiff};
+
+pub static PROCESSING_LATENCY_MS: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "moonshot_processing_latency_ms",
+        "Milliseconds from a swap's block timestamp to its insertion",
+        vec![500.0, 1_000.0, 2_000.0, 5_000.0, 10_000.0, 30_000.0, 60_000.0, 300_000.0]
+    )
+    .expect("metric registered once")
+});
+
+pub static SWAP_INSERTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "moonshot_swap_inserts_total",
+        "Swaps handed to the database, by whether this process stored them or found them already stored",
+        &["outcome"]
+    )
+    .expect("metric registered once")
+});
+
+pub static RPC_BATCH_FILL_RATIO: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "moonshot_rpc_batch_fill_ratio",
+        "Calls per JSON-RPC batch over RPC_BATCH_MAX_SIZE",
+        vec![0.1, 0.25, 0.5, 0.75, 0.9, 1.0]
+    )
+    .expect("metric registered once")
+});
+
+pub static RPC_BATCHES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!("moonshot_rpc_batches_total", "JSON-RPC batches, by whether they were sent or failed", &["outcome"])
+        .expect("metric registered once")
+});
+
+pub static SWAP_TOPIC_POOLS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "moonshot_swap_topic_pools_total",
+        "Contracts seen emitting Swap logs by SWAP_TOPIC_DISCOVERY, by whether the pool was known, discovered or not deployed by the factory (foreign)",
+        &["outcome"]
+    )
+    .expect("metric registered once")
+});
+
+pub static SWAP_USD_FLAGGED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "moonshot_swap_usd_flagged_total",
+        "Swaps whose USD amounts failed the enrichment sanity checks and were left out, by reason (price or tvl)",
+        &["reason"]
+    )
+    .expect("metric registered once")
+});
+
+pub static POOL_BALANCE_DROPS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "moonshot_pool_balance_drops_total",
+        "Pools that lost more than BALANCE_DROP_ALERT_PCT of a token balance within the window, by token (token0 or token1)",
+        &["token"]
+    )
+    .expect("metric registered once")
+});
+
+pub static EVENT_STREAM_SKIPPED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "moonshot_event_stream_skipped_total",
+        "Events embedder streams skipped after falling a whole buffer behind, by stream",
+        &["stream"]
+    )
+    .expect("metric registered once")
+});
+
+pub static SWAPS_PER_SECOND: LazyLock<Gauge> = LazyLock::new(|| {
+    register_gauge!("moonshot_swaps_per_second", "Swaps indexed per second over the throughput window")
+        .expect("metric registered once")
+});
+
+pub static POOLS_PER_SECOND: LazyLock<Gauge> = LazyLock::new(|| {
+    register_gauge!("moonshot_pools_per_second", "Pools indexed per second over the throughput window")
+        .expect("metric registered once")
+});
+
+pub static BLOCKS_PER_SECOND: LazyLock<Gauge> = LazyLock::new(|| {
+    register_gauge!("moonshot_blocks_per_second", "Blocks processed per second over the throughput window")
+        .expect("metric registered once")
+});
+
+pub static BUFFERED_SWAP_BYTES: LazyLock<GaugeVec> = LazyLock::new(|| {
+    register_gauge_vec!(
+        "moonshot_buffered_swap_bytes",
+        "Approximate bytes of decoded swaps buffered ahead of their consumer, by memory budget (writer or archive)",
+        &["budget"]
+    )
+    .expect("metric registered once")
+});
+
+pub static MEMORY_BUDGET_SUSPENDED: LazyLock<GaugeVec> = LazyLock::new(|| {
+    register_gauge_vec!(
+        "moonshot_memory_budget_suspended",
+        "1 while a memory budget holds its producer back, else 0, by memory budget (writer or archive)",
+        &["budget"]
+    )
+    .expect("metric registered once")
+});
+
+pub static DB_QUERY_SECONDS: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "moonshot_db_query_seconds",
+        "Seconds each Database method spent on its statements, timed-out calls included",
+        &["statement"],
+        vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 30.0]
+    )
+    .expect("metric registered once")
+});
+
+pub static DB_SLOW_QUERIES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "moonshot_db_slow_queries_total",
+        "Database method calls that took longer than SLOW_QUERY_THRESHOLD_MS",
+        &["statement"]
+    )
+    .expect("metric registered once")
+});
+
+pub static DB_TABLE_BYTES: LazyLock<GaugeVec> = LazyLock::new(|| {
+    register_gauge_vec!("moonshot_db_table_bytes", "Size of each maintained table without its indexes", &["table"])
+        .expect("metric registered once")
+});
+
+pub static DB_INDEX_BYTES: LazyLock<GaugeVec> = LazyLock::new(|| {
+    register_gauge_vec!("moonshot_db_index_bytes", "Size of all indexes of each maintained table", &["table"])
+        .expect("metric registered once")
+});
+
+pub static DB_DEAD_TUPLE_RATIO: LazyLock<GaugeVec> = LazyLock::new(|| {
+    register_gauge_vec!("moonshot_db_dead_tuple_ratio", "Dead tuples over all tuples of each maintained table", &["table"])
+        .expect("metric registered once")
+});
+
+// An average latency above this is logged as a warning, at most once per interval
+const LATENCY_WARN_THRESHOLD: Duration = Duration::from_secs(30);
+const LATENCY_WARN_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Everything registered with the default registry, in the Prometheus text format.
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+    // Encoding into a Vec only fails on malformed metric families, which the registry rejects
+    let _ = TextEncoder::new().encode(&prometheus::gather(), &mut buffer);
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+pub fn unix_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// Rolling average of block-to-insert latency over the most recent inserts.
+pub struct LatencyWindow {
+    capacity: usize,
+    samples: Mutex<VecDeque<i64>>,
+    last_warning: Mutex<Option<Instant>>,
+}
+
+impl LatencyWindow {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            samples: Mutex::new(VecDeque::with_capacity(capacity)),
+            last_warning: Mutex::new(None),
+        }
+    }
+
+    /// Records one insert; `block_timestamp` is in seconds, `indexed_at` in milliseconds.
+    pub fn record(&self, block_timestamp: i64, indexed_at: i64) {
+        let latency_ms = (indexed_at - block_timestamp * 1000).max(0);
+        PROCESSING_LATENCY_MS.observe(latency_ms as f64);
+
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() == self.capacity {
+            samples.pop_front();
+        }
+        samples.push_back(latency_ms);
+    }
+
+    pub fn average(&self) -> Duration {
+        let samples = self.samples.lock().unwrap();
+        if samples.is_empty() {
+            return Duration::ZERO;
+        }
+        Duration::from_millis((samples.iter().sum::<i64>() / samples.len() as i64) as u64)
+    }
+
+    /// Warns when the average is over 30s, at most once every five minutes, and returns
+    /// whether it did. Only call it while following the head: swaps of backfills and
+    /// catch-up ranges are late by design.
+    pub fn warn_if_slow(&self) -> bool {
+        let average = self.average();
+        if average <= LATENCY_WARN_THRESHOLD {
+            return false;
+        }
+
+        let mut last_warning = self.last_warning.lock().unwrap();
+        if last_warning.is_some_and(|warned| warned.elapsed() < LATENCY_WARN_INTERVAL) {
+            return false;
+        }
+        *last_warning = Some(Instant::now());
+        warn!("Swaps are stored {} ms after their block on average over the last {} inserts",
+              average.as_millis(), self.samples.lock().unwrap().len());
+        true
+    }
+}
+
+/// Indexing rates, per second.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Throughput {
+    pub swaps_per_second: f64,
+    pub pools_per_second: f64,
+    pub blocks_per_second: f64,
+}
+
+/// One line per chain and DEX with its cursor, blocks behind and update age, rows not
+/// updated within `stale_after_secs` of `now` (unix seconds) marked stale.
+pub fn stats_table(stats: &[IndexingStats], now: i64, stale_after_secs: u64) -> String {
+    let mut table = format!(
+        "{:>10} {:<12} {:>12} {:>8} {:>8} {:>12} {:>8}",
+        "chain", "dex", "block", "behind", "pools", "swaps", "age"
+    );
+    for row in stats {
+        let behind = row.blocks_behind().map_or("-".to_string(), |behind| behind.to_string());
+        table.push_str(&format!(
+            "\n{:>10} {:<12} {:>12} {:>8} {:>8} {:>12} {:>7}s",
+            row.chain_id, row.dex_name, row.last_processed_block, behind, row.total_pools_indexed, row.total_swaps_indexed,
+            row.update_age_secs(now)
+        ));
+        if row.is_stale(now, stale_after_secs) {
+            table.push_str(" STALE");
+        }
+    }
+    table
+}
+
+/// Diffs between consecutive `IndexingStats` samples over the last `span`, turned into
+/// throughput each time a sample is recorded.
+pub struct ThroughputWindow {
+    span: Duration,
+    last: Mutex<Option<IndexingStats>>,
+    // Each diff with the `updated_at` of the sample that ended it
+    diffs: Mutex<VecDeque<(i64, IndexingStatsDiff)>>,
+}
+
+impl ThroughputWindow {
+    pub fn new(span: Duration) -> Self {
+        Self { span, last: Mutex::new(None), diffs: Mutex::new(VecDeque::new()) }
+    }
+
+    /// Records a sample and exports the throughput since the start of the window.
+    pub fn record(&self, stats: IndexingStats) -> Throughput {
+        let mut last = self.last.lock().unwrap();
+        let mut diffs = self.diffs.lock().unwrap();
+        if let Some(last) = last.as_ref() {
+            diffs.push_back((stats.updated_at, stats.compare(last)));
+        }
+        let cutoff = stats.updated_at - self.span.as_secs() as i64;
+        while diffs.front().is_some_and(|(updated_at, _)| *updated_at <= cutoff) {
+            diffs.pop_front();
+        }
+        *last = Some(stats);
+
+        let total = diffs.iter().fold(IndexingStatsDiff::default(), |total, (_, diff)| IndexingStatsDiff {
+            new_pools: total.new_pools + diff.new_pools,
+            new_swaps: total.new_swaps + diff.new_swaps,
+            blocks_processed: total.blocks_processed + diff.blocks_processed,
+            elapsed_secs: total.elapsed_secs + diff.elapsed_secs,
+        });
+        let per_second = |count: i64| match total.elapsed_secs {
+            elapsed if elapsed > 0 => count as f64 / elapsed as f64,
+            _ => 0.0,
+        };
+        let throughput = Throughput {
+            swaps_per_second: per_second(total.new_swaps),
+            pools_per_second: per_second(total.new_pools),
+            blocks_per_second: per_second(total.blocks_processed),
+        };
+
+        SWAPS_PER_SECOND.set(throughput.swaps_per_second);
+        POOLS_PER_SECOND.set(throughput.pools_per_second);
+        BLOCKS_PER_SECOND.set(throughput.blocks_per_second);
+        throughput
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::{DexId, MOONSHOT};
+
+    #[test]
+    fn test_average_over_recent_inserts() {
+        let window = LatencyWindow::new(3);
+        assert_eq!(window.average(), Duration::ZERO);
+
+        window.record(100, 101_000);
+        window.record(100, 103_000);
+        assert_eq!(window.average(), Duration::from_millis(2_000));
+
+        // Only the last three samples count
+        window.record(100, 105_000);
+        window.record(100, 107_000);
+        assert_eq!(window.average(), Duration::from_millis(5_000));
+    }
+
+    #[test]
+    fn test_slow_average_warns_once_per_interval() {
+        let window = LatencyWindow::new(3);
+        window.record(100, 101_000);
+        window.record(100, 102_000);
+        assert!(!window.warn_if_slow());
+
+        // One late swap does not move the average past 30s, a run of them does
+        window.record(100, 150_000);
+        assert!(!window.warn_if_slow());
+        window.record(100, 200_000);
+        assert!(window.warn_if_slow());
+        window.record(100, 300_000);
+        assert!(!window.warn_if_slow());
+    }
+
+    #[test]
+    fn test_latency_is_exported() {
+        let window = LatencyWindow::new(10);
+        window.record(1, 2_500);
+
+        assert!(render().contains("moonshot_processing_latency_ms_bucket"));
+    }
+
+    fn stats(last_processed_block: i64, swaps: i64, updated_at: i64) -> IndexingStats {
+        IndexingStats {
+            last_processed_block,
+            total_pools_indexed: 1,
+            total_swaps_indexed: swaps,
+            chain_id: 2741,
+            dex_name: MOONSHOT,
+            updated_at,
+            head_block: None,
+        }
+    }
+
+    #[test]
+    fn test_stats_table_flags_stale_rows() {
+        let mut live = stats(130, 150, 1_000);
+        live.head_block = Some(140);
+        let mut stale = stats(90, 7, 400);
+        (stale.chain_id, stale.dex_name) = (8453, DexId::new("otherswap").unwrap());
+
+        let table = stats_table(&[live, stale], 1_030, 300);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].contains("2741") && lines[1].contains(" 10 ") && lines[1].ends_with("30s"));
+        assert!(lines[2].contains("otherswap") && lines[2].ends_with("630s STALE"));
+    }
+
+    #[test]
+    fn test_throughput_over_recent_diffs() {
+        let window = ThroughputWindow::new(Duration::from_secs(30));
+        assert_eq!(window.record(stats(100, 0, 1_000)), Throughput::default());
+
+        let throughput = window.record(stats(110, 50, 1_010));
+        assert_eq!((throughput.swaps_per_second, throughput.blocks_per_second), (5.0, 1.0));
+        let throughput = window.record(stats(130, 150, 1_030));
+        assert_eq!((throughput.swaps_per_second, throughput.blocks_per_second), (5.0, 1.0));
+
+        // The first diff ends at 1_010 and leaves the window at 1_040
+        let throughput = window.record(stats(130, 150, 1_040));
+        assert_eq!(throughput.swaps_per_second, 100.0 / 30.0);
+        assert!(render().contains("moonshot_swaps_per_second"));
+    }
+}
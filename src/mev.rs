@@ -0,0 +1,250 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tracing::debug;
+
+use crate::db::Database;
+use crate::types::{PoolData, SwapEvent};
+
+/// How a swap took part in a MEV pattern, as stored in `swaps.mev_type`.
+///
+/// Victims are ordinary trades that got sandwiched; the other kinds are the searcher's own
+/// swaps, which volume queries leave out when asked to exclude MEV.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MevType {
+    SandwichFront,
+    SandwichBack,
+    Victim,
+    Arb,
+}
+
+impl MevType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MevType::SandwichFront => "sandwich_front",
+            MevType::SandwichBack => "sandwich_back",
+            MevType::Victim => "victim",
+            MevType::Arb => "arb",
+        }
+    }
+
+    pub fn parse(mev_type: &str) -> Option<Self> {
+        match mev_type {
+            "sandwich_front" => Some(MevType::SandwichFront),
+            "sandwich_back" => Some(MevType::SandwichBack),
+            "victim" => Some(MevType::Victim),
+            "arb" => Some(MevType::Arb),
+            _ => None,
+        }
+    }
+}
+
+/// Address of a swap's token, resolving `token0`/`token1` through its pool. None for a
+/// label whose pool is unknown.
+fn token_address(swap: &SwapEvent, token: &str, pools: &HashMap<String, PoolData>) -> Option<String> {
+    let token = match pools.get(&swap.pool_address) {
+        Some(pool) => pool.resolve_token(token),
+        None if token == "token0" || token == "token1" => return None,
+        None => token,
+    };
+    Some(token.to_lowercase())
+}
+
+/// MEV tags for one block's swaps, given in log order; the result lines up with `swaps`.
+///
+/// A sandwich is a sender's swap followed, in the same pool, by someone else's swap in the
+/// same direction and then the first sender's swap back the other way. An arbitrage is a
+/// transaction swapping through two or more pools that ends in the token it started with.
+/// Sandwich tags win over arbitrage, and swaps without a sender are never sandwich legs.
+pub fn classify_block(swaps: &[SwapEvent], pools: &HashMap<String, PoolData>) -> Vec<Option<MevType>> {
+    let mut tags = vec![None; swaps.len()];
+    let direction = |swap: &SwapEvent| {
+        pools.get(&swap.pool_address).map_or(swap.token_in.to_lowercase(), |pool| pool.resolve_token(&swap.token_in).to_lowercase())
+    };
+
+    for (front, front_swap) in swaps.iter().enumerate() {
+        let Some(sender) = &front_swap.sender_address else { continue };
+        if tags[front].is_some() {
+            continue;
+        }
+        let front_direction = direction(front_swap);
+        let same_pool = |i: &usize| swaps[*i].pool_address == front_swap.pool_address;
+        let by_sender = |i: &usize| swaps[*i].sender_address.as_ref().is_some_and(|s| s.eq_ignore_ascii_case(sender));
+
+        let back = (front + 1..swaps.len())
+            .filter(same_pool)
+            .find(|i| by_sender(i) && tags[*i].is_none() && direction(&swaps[*i]) != front_direction);
+        let Some(back) = back else { continue };
+        let victims: Vec<usize> = (front + 1..back)
+            .filter(same_pool)
+            .filter(|i| !by_sender(i) && direction(&swaps[*i]) == front_direction)
+            .collect();
+        if victims.is_empty() {
+            continue;
+        }
+
+        tags[front] = Some(MevType::SandwichFront);
+        tags[back] = Some(MevType::SandwichBack);
+        for victim in victims {
+            tags[victim] = Some(MevType::Victim);
+        }
+    }
+
+    let mut transactions: Vec<(&str, Vec<usize>)> = Vec::new();
+    for (i, swap) in swaps.iter().enumerate() {
+        match transactions.iter_mut().find(|(tx_hash, _)| *tx_hash == swap.tx_hash) {
+            Some((_, indices)) => indices.push(i),
+            None => transactions.push((&swap.tx_hash, vec![i])),
+        }
+    }
+    for (_, indices) in transactions {
+        let pool_count = indices.iter().map(|i| &swaps[*i].pool_address).collect::<HashSet<_>>().len();
+        let (first, last) = (&swaps[indices[0]], &swaps[indices[indices.len() - 1]]);
+        let start = token_address(first, &first.token_in, pools);
+        let end = token_address(last, &last.token_out, pools);
+        if pool_count >= 2 && start.is_some() && start == end {
+            for i in indices {
+                tags[i].get_or_insert(MevType::Arb);
+            }
+        }
+    }
+
+    tags
+}
+
+/// Classifies the stored swaps of `[from_block, to_block]` block by block and stores the
+/// tags, clearing those of swaps no longer in a pattern. Returns how many swaps are tagged.
+pub async fn tag_blocks(database: &Database, chain_id: i64, from_block: i64, to_block: i64) -> Result<u64> {
+    let swaps = database.get_swaps_in_block_range(chain_id, from_block, to_block).await?;
+
+    let mut pools = HashMap::new();
+    for swap in &swaps {
+        if !pools.contains_key(&swap.pool_address) {
+            if let Some(pool) = database.get_pool(&swap.pool_address).await? {
+                pools.insert(swap.pool_address.clone(), pool);
+            }
+        }
+    }
+
+    let mut tags = Vec::with_capacity(swaps.len());
+    for block in swaps.chunk_by(|a, b| a.block_number == b.block_number) {
+        tags.extend(block.iter().zip(classify_block(block, &pools)));
+    }
+
+    let tagged = tags.iter().filter(|(_, tag)| tag.is_some()).count() as u64;
+    database.set_swap_mev_types(chain_id, &tags).await?;
+    debug!("Tagged {} MEV swaps in blocks {} to {}", tagged, from_block, to_block);
+    Ok(tagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::MOONSHOT;
+
+    const POOL_A: &str = "0x00000000000000000000000000000000000000aa";
+    const POOL_B: &str = "0x00000000000000000000000000000000000000bb";
+    const WETH: &str = "0x00000000000000000000000000000000000000e0";
+    const TOKEN: &str = "0x00000000000000000000000000000000000000e1";
+
+    fn pools() -> HashMap<String, PoolData> {
+        [(POOL_A, WETH, TOKEN), (POOL_B, TOKEN, WETH)]
+            .into_iter()
+            .map(|(pool, token0, token1)| {
+                let pool_data = PoolData::new(pool.to_string(), token0.to_string(), token1.to_string(), 2741, MOONSHOT);
+                (pool.to_string(), pool_data)
+            })
+            .collect()
+    }
+
+    fn swap(tx: u64, log_index: i32, pool: &str, token_in: &str, sender: &str) -> SwapEvent {
+        let token_out = if token_in == "token0" { "token1" } else { "token0" };
+        let mut swap = SwapEvent::new(
+            format!("0x{:064x}", tx),
+            pool.to_string(),
+            token_in.to_string(),
+            token_out.to_string(),
+            1000,
+            990,
+            1_700_000_000,
+            100,
+            log_index,
+            2741,
+        );
+        swap.sender_address = Some(sender.to_string());
+        swap
+    }
+
+    #[test]
+    fn test_sandwich_around_victims() {
+        let swaps = [
+            swap(1, 0, POOL_A, "token0", "0xbot"),
+            swap(2, 1, POOL_A, "token0", "0xvictim"),
+            // Other pool and opposite direction: not part of the sandwich
+            swap(3, 2, POOL_B, "token0", "0xother"),
+            swap(4, 3, POOL_A, "token1", "0xother"),
+            swap(5, 4, POOL_A, "token0", "0xvictim2"),
+            swap(6, 5, POOL_A, "token1", "0xBOT"),
+        ];
+
+        assert_eq!(
+            classify_block(&swaps, &pools()),
+            vec![
+                Some(MevType::SandwichFront),
+                Some(MevType::Victim),
+                None,
+                None,
+                Some(MevType::Victim),
+                Some(MevType::SandwichBack),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_sandwich_without_victim_or_back_run() {
+        // Round trip with nobody in between
+        let swaps = [swap(1, 0, POOL_A, "token0", "0xbot"), swap(2, 1, POOL_A, "token1", "0xbot")];
+        assert_eq!(classify_block(&swaps, &pools()), vec![None, None]);
+
+        // No swap back
+        let swaps = [swap(1, 0, POOL_A, "token0", "0xbot"), swap(2, 1, POOL_A, "token0", "0xuser")];
+        assert_eq!(classify_block(&swaps, &pools()), vec![None, None]);
+
+        // Senders unknown
+        let mut swaps = [
+            swap(1, 0, POOL_A, "token0", "0xbot"),
+            swap(2, 1, POOL_A, "token0", "0xuser"),
+            swap(3, 2, POOL_A, "token1", "0xbot"),
+        ];
+        swaps.iter_mut().for_each(|swap| swap.sender_address = None);
+        assert_eq!(classify_block(&swaps, &pools()), vec![None, None, None]);
+    }
+
+    #[test]
+    fn test_atomic_arbitrage_cycle() {
+        // WETH -> TOKEN in pool A, TOKEN -> WETH in pool B, one transaction
+        let swaps = [
+            swap(7, 0, POOL_A, "token0", "0xarb"),
+            swap(7, 1, POOL_B, "token0", "0xarb"),
+            swap(8, 2, POOL_A, "token0", "0xuser"),
+        ];
+        assert_eq!(classify_block(&swaps, &pools()), vec![Some(MevType::Arb), Some(MevType::Arb), None]);
+
+        // A route ending in another token is a plain multi-hop trade
+        let swaps = [swap(7, 0, POOL_A, "token0", "0xuser"), swap(7, 1, POOL_B, "token1", "0xuser")];
+        assert_eq!(classify_block(&swaps, &pools()), vec![None, None]);
+
+        // Pools unknown, so token0/token1 cannot be compared across them
+        let swaps = [swap(7, 0, POOL_A, "token0", "0xarb"), swap(7, 1, POOL_B, "token0", "0xarb")];
+        assert_eq!(classify_block(&swaps, &HashMap::new()), vec![None, None]);
+    }
+
+    #[test]
+    fn test_mev_type_round_trip() {
+        for mev_type in [MevType::SandwichFront, MevType::SandwichBack, MevType::Victim, MevType::Arb] {
+            assert_eq!(MevType::parse(mev_type.as_str()), Some(mev_type));
+        }
+        assert_eq!(MevType::parse("liquidation"), None);
+    }
+}
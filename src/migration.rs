@@ -0,0 +1,185 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::db::Database;
+use crate::dex::MOONSHOT;
+use crate::pool_state::{self, PoolStateReader};
+use crate::scope::IndexingScope;
+use crate::types::{CurveTrade, PoolData, SwapEvent, TokenData, TokenMigration};
+
+/// One trade in a token's history, from either side of its graduation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "phase", rename_all = "lowercase")]
+pub enum TimelineEntry {
+    Curve(CurveTrade),
+    Amm(SwapEvent),
+}
+
+impl TimelineEntry {
+    fn position(&self) -> (i64, i32) {
+        match self {
+            TimelineEntry::Curve(trade) => (trade.block_number, trade.log_index),
+            TimelineEntry::Amm(swap) => (swap.block_number, swap.log_index),
+        }
+    }
+}
+
+/// A token's metadata, graduation and trades across the curve and AMM phases.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenTimeline {
+    #[serde(flatten)]
+    pub token: Option<TokenData>,
+    pub migration: Option<TokenMigration>,
+    /// From the latest holder snapshot; absent until one is taken or without a known supply.
+    pub top10_concentration_pct: Option<f64>,
+    pub timeline: Vec<TimelineEntry>,
+}
+
+impl TokenTimeline {
+    pub fn is_empty(&self) -> bool {
+        self.token.is_none() && self.migration.is_none() && self.timeline.is_empty()
+    }
+}
+
+/// Curve trades followed by pool swaps, in chain order.
+pub fn merge_timeline(trades: Vec<CurveTrade>, swaps: Vec<SwapEvent>) -> Vec<TimelineEntry> {
+    let mut timeline: Vec<TimelineEntry> = trades
+        .into_iter()
+        .map(TimelineEntry::Curve)
+        .chain(swaps.into_iter().map(TimelineEntry::Amm))
+        .collect();
+    timeline.sort_by_key(TimelineEntry::position);
+    timeline
+}
+
+/// Loads a token's timeline; AMM swaps are limited to the latest `swap_limit` in its pool.
+pub async fn load_timeline(database: &Database, token_address: &str, chain_id: i64, swap_limit: i64) -> Result<TokenTimeline> {
+    let token = database.get_token(token_address, chain_id).await?;
+    let migration = database.get_token_migration(token_address, chain_id).await?;
+    let trades = database.get_curve_trades(token_address, chain_id).await?;
+    let top10_concentration_pct = database
+        .get_latest_holder_snapshot(token_address, chain_id)
+        .await?
+        .and_then(|snapshot| snapshot.top10_concentration_pct);
+    let swaps = match &migration {
+        Some(migration) => database.get_swaps_by_pool(&migration.pool_address, chain_id, swap_limit).await?,
+        None => Vec::new(),
+    };
+
+    Ok(TokenTimeline {
+        token,
+        migration,
+        top10_concentration_pct,
+        timeline: merge_timeline(trades, swaps),
+    })
+}
+
+/// Stores a graduation, taking the final curve price from the token's last indexed trade.
+///
+/// The destination pool is registered straight away from its on-chain state when it is not
+/// stored yet, e.g. because its `PoolCreated` log came from another factory or has not been
+/// processed. Returns the newly registered pool.
+pub async fn record_migration<R: PoolStateReader + ?Sized>(
+    database: &Database,
+    reader: &R,
+    scope: &IndexingScope,
+    migration: &mut TokenMigration,
+) -> Result<Option<PoolData>> {
+    if migration.final_curve_price.is_none() {
+        migration.final_curve_price = database
+            .get_last_curve_trade(&migration.token_address, migration.chain_id)
+            .await?
+            .and_then(|trade| trade.price());
+    }
+    database.insert_token_migration(migration).await?;
+
+    if database.get_pool(&migration.pool_address).await?.is_some() {
+        return Ok(None);
+    }
+
+    let mut skeleton = PoolData::new(
+        migration.pool_address.clone(),
+        String::new(),
+        String::new(),
+        migration.chain_id,
+        MOONSHOT,
+    );
+    skeleton.created_at_block = Some(migration.block_number);
+
+    let update = pool_state::refresh(reader, &skeleton).await?;
+    let pool = update.pool;
+    if pool.token0_address.is_empty() || pool.token1_address.is_empty() {
+        bail!("could not read the tokens of graduated pool {}: {:?}", migration.pool_address, update.errors);
+    }
+
+    database.upsert_pool(&pool).await?;
+    database.set_pool_tracked(&pool.pool_address, scope.is_tracked(&pool)).await?;
+    Ok(Some(pool))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(block_number: i64, log_index: i32) -> CurveTrade {
+        CurveTrade {
+            tx_hash: "0xabc".to_string(),
+            log_index,
+            block_number,
+            timestamp: block_number,
+            chain_id: 2741,
+            curve_address: "0x00000000000000000000000000000000000000c0".to_string(),
+            token_address: "0x00000000000000000000000000000000000000a0".to_string(),
+            trader: "0x000000000000000000000000000000000000beef".to_string(),
+            side: crate::types::TradeSide::Buy,
+            token_amount: "4000".to_string(),
+            collateral_amount: "10".to_string(),
+        }
+    }
+
+    fn swap(block_number: i64, log_index: i32) -> SwapEvent {
+        SwapEvent::new(
+            "0xdef".to_string(),
+            "0x0000000000000000000000000000000000001000".to_string(),
+            "token0".to_string(),
+            "token1".to_string(),
+            1000,
+            950,
+            block_number,
+            block_number,
+            log_index,
+            2741,
+        )
+    }
+
+    #[test]
+    fn test_timeline_is_in_chain_order() {
+        // The first swap can share the graduation block
+        let timeline = merge_timeline(vec![trade(12, 3), trade(10, 0)], vec![swap(12, 5), swap(13, 0)]);
+        let positions: Vec<_> = timeline.iter().map(TimelineEntry::position).collect();
+
+        assert_eq!(positions, vec![(10, 0), (12, 3), (12, 5), (13, 0)]);
+        assert!(matches!(timeline[1], TimelineEntry::Curve(_)));
+        assert!(matches!(timeline[2], TimelineEntry::Amm(_)));
+    }
+
+    #[test]
+    fn test_entries_are_tagged_by_phase() {
+        let timeline = merge_timeline(vec![trade(10, 0)], vec![swap(11, 0)]);
+        let json = serde_json::to_value(&timeline).unwrap();
+
+        assert_eq!(json[0]["phase"], "curve");
+        assert_eq!(json[0]["collateral_amount"], "10");
+        assert_eq!(json[1]["phase"], "amm");
+        assert_eq!(json[1]["amount_in"], 1000);
+    }
+
+    #[test]
+    fn test_curve_trade_price() {
+        assert_eq!(trade(1, 0).price(), Some(0.0025));
+
+        let mut empty = trade(1, 0);
+        empty.token_amount = "0".to_string();
+        assert_eq!(empty.price(), None);
+    }
+}
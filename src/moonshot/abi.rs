@@ -1,6 +1,6 @@
 use ethers::abi::Abi;
 
-// Moonshot Factory ABI - PoolCreated event
+// Moonshot Factory ABI - PoolCreated event and the getPool lookup
 pub const MOONSHOT_FACTORY_ABI: &str = r#"[
     {
         "anonymous": false,
@@ -38,6 +38,35 @@ pub const MOONSHOT_FACTORY_ABI: &str = r#"[
         ],
         "name": "PoolCreated",
         "type": "event"
+    },
+    {
+        "inputs": [
+            {
+                "internalType": "address",
+                "name": "tokenA",
+                "type": "address"
+            },
+            {
+                "internalType": "address",
+                "name": "tokenB",
+                "type": "address"
+            },
+            {
+                "internalType": "uint24",
+                "name": "fee",
+                "type": "uint24"
+            }
+        ],
+        "name": "getPool",
+        "outputs": [
+            {
+                "internalType": "address",
+                "name": "",
+                "type": "address"
+            }
+        ],
+        "stateMutability": "view",
+        "type": "function"
     }
 ]"#;
 
@@ -199,6 +228,230 @@ pub const MOONSHOT_POOL_ABI: &str = r#"[
         ],
         "stateMutability": "view",
         "type": "function"
+    },
+    {
+        "inputs": [
+            {
+                "internalType": "int24",
+                "name": "tick",
+                "type": "int24"
+            }
+        ],
+        "name": "ticks",
+        "outputs": [
+            {
+                "internalType": "uint128",
+                "name": "liquidityGross",
+                "type": "uint128"
+            },
+            {
+                "internalType": "int128",
+                "name": "liquidityNet",
+                "type": "int128"
+            },
+            {
+                "internalType": "uint256",
+                "name": "feeGrowthOutside0X128",
+                "type": "uint256"
+            },
+            {
+                "internalType": "uint256",
+                "name": "feeGrowthOutside1X128",
+                "type": "uint256"
+            },
+            {
+                "internalType": "int56",
+                "name": "tickCumulativeOutside",
+                "type": "int56"
+            },
+            {
+                "internalType": "uint160",
+                "name": "secondsPerLiquidityOutsideX128",
+                "type": "uint160"
+            },
+            {
+                "internalType": "uint32",
+                "name": "secondsOutside",
+                "type": "uint32"
+            },
+            {
+                "internalType": "bool",
+                "name": "initialized",
+                "type": "bool"
+            }
+        ],
+        "stateMutability": "view",
+        "type": "function"
+    },
+    {
+        "inputs": [],
+        "name": "feeGrowthGlobal0X128",
+        "outputs": [
+            {
+                "internalType": "uint256",
+                "name": "",
+                "type": "uint256"
+            }
+        ],
+        "stateMutability": "view",
+        "type": "function"
+    },
+    {
+        "inputs": [],
+        "name": "feeGrowthGlobal1X128",
+        "outputs": [
+            {
+                "internalType": "uint256",
+                "name": "",
+                "type": "uint256"
+            }
+        ],
+        "stateMutability": "view",
+        "type": "function"
+    },
+    {
+        "inputs": [
+            {
+                "internalType": "bytes32",
+                "name": "key",
+                "type": "bytes32"
+            }
+        ],
+        "name": "positions",
+        "outputs": [
+            {
+                "internalType": "uint128",
+                "name": "liquidity",
+                "type": "uint128"
+            },
+            {
+                "internalType": "uint256",
+                "name": "feeGrowthInside0LastX128",
+                "type": "uint256"
+            },
+            {
+                "internalType": "uint256",
+                "name": "feeGrowthInside1LastX128",
+                "type": "uint256"
+            },
+            {
+                "internalType": "uint128",
+                "name": "tokensOwed0",
+                "type": "uint128"
+            },
+            {
+                "internalType": "uint128",
+                "name": "tokensOwed1",
+                "type": "uint128"
+            }
+        ],
+        "stateMutability": "view",
+        "type": "function"
+    }
+]"#;
+
+// Moonshot bonding curve ABI - token launches, curve trades and graduation to a pool.
+// Deployments with different event names or signatures are handled by overrides from config.
+pub const MOONSHOT_CURVE_ABI: &str = r#"[
+    {
+        "anonymous": false,
+        "inputs": [
+            {
+                "indexed": true,
+                "internalType": "address",
+                "name": "token",
+                "type": "address"
+            },
+            {
+                "indexed": true,
+                "internalType": "address",
+                "name": "creator",
+                "type": "address"
+            }
+        ],
+        "name": "TokenCreated",
+        "type": "event"
+    },
+    {
+        "anonymous": false,
+        "inputs": [
+            {
+                "indexed": true,
+                "internalType": "address",
+                "name": "token",
+                "type": "address"
+            },
+            {
+                "indexed": true,
+                "internalType": "address",
+                "name": "buyer",
+                "type": "address"
+            },
+            {
+                "indexed": false,
+                "internalType": "uint256",
+                "name": "tokenAmount",
+                "type": "uint256"
+            },
+            {
+                "indexed": false,
+                "internalType": "uint256",
+                "name": "collateralAmount",
+                "type": "uint256"
+            }
+        ],
+        "name": "Buy",
+        "type": "event"
+    },
+    {
+        "anonymous": false,
+        "inputs": [
+            {
+                "indexed": true,
+                "internalType": "address",
+                "name": "token",
+                "type": "address"
+            },
+            {
+                "indexed": true,
+                "internalType": "address",
+                "name": "seller",
+                "type": "address"
+            },
+            {
+                "indexed": false,
+                "internalType": "uint256",
+                "name": "tokenAmount",
+                "type": "uint256"
+            },
+            {
+                "indexed": false,
+                "internalType": "uint256",
+                "name": "collateralAmount",
+                "type": "uint256"
+            }
+        ],
+        "name": "Sell",
+        "type": "event"
+    },
+    {
+        "anonymous": false,
+        "inputs": [
+            {
+                "indexed": true,
+                "internalType": "address",
+                "name": "token",
+                "type": "address"
+            },
+            {
+                "indexed": true,
+                "internalType": "address",
+                "name": "pool",
+                "type": "address"
+            }
+        ],
+        "name": "Graduated",
+        "type": "event"
     }
 ]"#;
 
@@ -255,6 +508,49 @@ pub const ERC20_ABI: &str = r#"[
         ],
         "stateMutability": "view",
         "type": "function"
+    },
+    {
+        "inputs": [
+            {
+                "internalType": "address",
+                "name": "account",
+                "type": "address"
+            }
+        ],
+        "name": "balanceOf",
+        "outputs": [
+            {
+                "internalType": "uint256",
+                "name": "",
+                "type": "uint256"
+            }
+        ],
+        "stateMutability": "view",
+        "type": "function"
+    },
+    {
+        "inputs": [
+            {
+                "internalType": "address",
+                "name": "owner",
+                "type": "address"
+            },
+            {
+                "internalType": "address",
+                "name": "spender",
+                "type": "address"
+            }
+        ],
+        "name": "allowance",
+        "outputs": [
+            {
+                "internalType": "uint256",
+                "name": "",
+                "type": "uint256"
+            }
+        ],
+        "stateMutability": "view",
+        "type": "function"
     }
 ]"#;
 
@@ -270,6 +566,10 @@ pub fn get_erc20_abi() -> Abi {
     serde_json::from_str(ERC20_ABI).expect("Invalid ERC20 ABI")
 }
 
+pub fn get_curve_abi() -> Abi {
+    serde_json::from_str(MOONSHOT_CURVE_ABI).expect("Invalid curve ABI")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -280,10 +580,20 @@ mod tests {
         let factory_abi = get_factory_abi();
         let pool_abi = get_pool_abi();
         let erc20_abi = get_erc20_abi();
+        let curve_abi = get_curve_abi();
 
         // Check that we have the expected events/functions
-        assert!(factory_abi.events().any(|(name, _)| name == "PoolCreated"));
-        assert!(pool_abi.events().any(|(name, _)| name == "Swap"));
-        assert!(erc20_abi.functions().any(|(name, _)| name == "symbol"));
+        assert!(factory_abi.events().any(|event| event.name == "PoolCreated"));
+        assert!(factory_abi.function("getPool").is_ok());
+        assert!(pool_abi.events().any(|event| event.name == "Swap"));
+        for name in ["ticks", "positions", "feeGrowthGlobal0X128", "feeGrowthGlobal1X128"] {
+            assert!(pool_abi.function(name).is_ok());
+        }
+        assert!(erc20_abi.functions().any(|function| function.name == "symbol"));
+        assert!(erc20_abi.functions().any(|function| function.name == "balanceOf"));
+        assert!(erc20_abi.functions().any(|function| function.name == "allowance"));
+        for name in ["TokenCreated", "Buy", "Sell", "Graduated"] {
+            assert!(curve_abi.event(name).is_ok());
+        }
     }
 }
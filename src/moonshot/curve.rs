@@ -0,0 +1,276 @@
+use anyhow::{anyhow, bail, Result};
+use ethers::abi::{Event, HumanReadableParser, ParamType, RawLog, Token};
+use ethers::types::{Address, Log, H256};
+
+use super::abi::get_curve_abi;
+use crate::config::Config;
+use crate::types::{CurveTrade, TokenMigration, TradeSide};
+
+// Leading parameters each curve event must have; overrides may append more
+const TOKEN_CREATED_LAYOUT: &[ParamType] = &[ParamType::Address, ParamType::Address];
+const TRADE_LAYOUT: &[ParamType] = &[ParamType::Address, ParamType::Address, ParamType::Uint(256), ParamType::Uint(256)];
+const GRADUATED_LAYOUT: &[ParamType] = &[ParamType::Address, ParamType::Address];
+
+/// A curve log decoded into what the indexer stores.
+#[derive(Debug, Clone)]
+pub enum CurveEvent {
+    /// A token launched on the curve, as (token, creator).
+    TokenCreated(Address, Address),
+    Trade(CurveTrade),
+    Graduated(TokenMigration),
+}
+
+/// Decodes logs of the Moonshot bonding curve contract.
+///
+/// Events are matched by role rather than by name, so deployments that name or extend
+/// them differently work as long as the leading parameters keep the built-in order.
+pub struct CurveHandler {
+    token_created: Event,
+    buy: Event,
+    sell: Event,
+    graduated: Event,
+}
+
+impl CurveHandler {
+    pub fn new() -> Self {
+        let abi = get_curve_abi();
+        let event = |name: &str| abi.event(name).expect("curve ABI event").clone();
+
+        Self {
+            token_created: event("TokenCreated"),
+            buy: event("Buy"),
+            sell: event("Sell"),
+            graduated: event("Graduated"),
+        }
+    }
+
+    /// The built-in events with any overrides from config applied.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut handler = Self::new();
+        let overrides = [
+            (&mut handler.token_created, &config.curve_token_created_event, TOKEN_CREATED_LAYOUT),
+            (&mut handler.buy, &config.curve_buy_event, TRADE_LAYOUT),
+            (&mut handler.sell, &config.curve_sell_event, TRADE_LAYOUT),
+            (&mut handler.graduated, &config.curve_graduated_event, GRADUATED_LAYOUT),
+        ];
+
+        for (event, definition, layout) in overrides {
+            if let Some(definition) = definition {
+                *event = parse_event(definition, layout)?;
+            }
+        }
+        Ok(handler)
+    }
+
+    /// Signatures of every curve event, for a log filter.
+    pub fn topics(&self) -> Vec<H256> {
+        [&self.token_created, &self.buy, &self.sell, &self.graduated]
+            .iter()
+            .map(|event| event.signature())
+            .collect()
+    }
+
+    /// Decodes a curve log; timestamps and the final curve price are left for the caller to fill in.
+    pub fn decode(&self, log: &Log, chain_id: i64) -> Result<CurveEvent> {
+        let topic = log.topics.first().ok_or_else(|| anyhow!("curve log without topics"))?;
+
+        if *topic == self.token_created.signature() {
+            let params = decode_params(&self.token_created, log)?;
+            Ok(CurveEvent::TokenCreated(address_param(&params, 0)?, address_param(&params, 1)?))
+        } else if *topic == self.buy.signature() {
+            self.decode_trade(&self.buy, TradeSide::Buy, log, chain_id).map(CurveEvent::Trade)
+        } else if *topic == self.sell.signature() {
+            self.decode_trade(&self.sell, TradeSide::Sell, log, chain_id).map(CurveEvent::Trade)
+        } else if *topic == self.graduated.signature() {
+            let params = decode_params(&self.graduated, log)?;
+            let block_number = log.block_number.unwrap_or_default().as_u64() as i64;
+            Ok(CurveEvent::Graduated(TokenMigration {
+                token_address: format!("{:?}", address_param(&params, 0)?),
+                curve_address: format!("{:?}", log.address),
+                pool_address: format!("{:?}", address_param(&params, 1)?),
+                tx_hash: format!("{:?}", log.transaction_hash.unwrap_or_default()),
+                block_number,
+                log_index: log.log_index.unwrap_or_default().as_u64() as i32,
+                timestamp: block_number,
+                final_curve_price: None,
+                chain_id,
+            }))
+        } else {
+            bail!("unknown curve event {:?}", topic)
+        }
+    }
+
+    fn decode_trade(&self, event: &Event, side: TradeSide, log: &Log, chain_id: i64) -> Result<CurveTrade> {
+        let params = decode_params(event, log)?;
+        let block_number = log.block_number.unwrap_or_default().as_u64() as i64;
+
+        Ok(CurveTrade {
+            tx_hash: format!("{:?}", log.transaction_hash.unwrap_or_default()),
+            log_index: log.log_index.unwrap_or_default().as_u64() as i32,
+            block_number,
+            timestamp: block_number,
+            chain_id,
+            curve_address: format!("{:?}", log.address),
+            token_address: format!("{:?}", address_param(&params, 0)?),
+            trader: format!("{:?}", address_param(&params, 1)?),
+            side,
+            token_amount: uint_param(&params, 2)?,
+            collateral_amount: uint_param(&params, 3)?,
+        })
+    }
+}
+
+impl Default for CurveHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_event(definition: &str, layout: &[ParamType]) -> Result<Event> {
+    let event = HumanReadableParser::parse_event(definition)
+        .map_err(|e| anyhow!("invalid curve event definition {:?}: {}", definition, e))?;
+
+    let kinds: Vec<&ParamType> = event.inputs.iter().map(|input| &input.kind).collect();
+    if kinds.len() < layout.len() || kinds.iter().zip(layout).any(|(kind, expected)| *kind != expected) {
+        bail!("curve event {:?} must start with parameters {:?}", definition, layout);
+    }
+    Ok(event)
+}
+
+fn decode_params(event: &Event, log: &Log) -> Result<Vec<Token>> {
+    let raw = RawLog { topics: log.topics.clone(), data: log.data.to_vec() };
+    Ok(event.parse_log(raw)?.params.into_iter().map(|param| param.value).collect())
+}
+
+fn address_param(params: &[Token], index: usize) -> Result<Address> {
+    params
+        .get(index)
+        .and_then(|token| token.clone().into_address())
+        .ok_or_else(|| anyhow!("curve event parameter {} is not an address", index))
+}
+
+fn uint_param(params: &[Token], index: usize) -> Result<String> {
+    params
+        .get(index)
+        .and_then(|token| token.clone().into_uint())
+        .map(|value| value.to_string())
+        .ok_or_else(|| anyhow!("curve event parameter {} is not an integer", index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::encode;
+    use ethers::types::{U256, U64};
+
+    const CURVE: u64 = 0xc0;
+    const TOKEN: u64 = 0xa0;
+    const TRADER: u64 = 0xbeef;
+    const POOL: u64 = 0x1000;
+
+    fn address(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    fn log(event: &Event, indexed: &[u64], data: &[Token]) -> Log {
+        let mut topics = vec![event.signature()];
+        topics.extend(indexed.iter().map(|n| H256::from(address(*n))));
+
+        Log {
+            address: address(CURVE),
+            topics,
+            data: encode(data).into(),
+            block_number: Some(U64::from(1_234)),
+            log_index: Some(7.into()),
+            transaction_hash: Some(H256::from_low_u64_be(0xabc)),
+            ..Default::default()
+        }
+    }
+
+    fn amounts(token_amount: u64, collateral_amount: u64) -> [Token; 2] {
+        [Token::Uint(U256::from(token_amount)), Token::Uint(U256::from(collateral_amount))]
+    }
+
+    #[test]
+    fn test_decodes_buys_and_sells() {
+        let handler = CurveHandler::new();
+
+        let buy = log(&handler.buy, &[TOKEN, TRADER], &amounts(5_000, 20));
+        let CurveEvent::Trade(trade) = handler.decode(&buy, 2741).unwrap() else {
+            panic!("expected a trade");
+        };
+        assert_eq!(trade.side, TradeSide::Buy);
+        assert_eq!(trade.token_address, format!("{:?}", address(TOKEN)));
+        assert_eq!(trade.trader, format!("{:?}", address(TRADER)));
+        assert_eq!(trade.curve_address, format!("{:?}", address(CURVE)));
+        assert_eq!((trade.token_amount.as_str(), trade.collateral_amount.as_str()), ("5000", "20"));
+        assert_eq!((trade.block_number, trade.log_index, trade.chain_id), (1_234, 7, 2741));
+
+        let sell = log(&handler.sell, &[TOKEN, TRADER], &amounts(4_000, 15));
+        let CurveEvent::Trade(trade) = handler.decode(&sell, 2741).unwrap() else {
+            panic!("expected a trade");
+        };
+        assert_eq!(trade.side, TradeSide::Sell);
+        assert_eq!((trade.token_amount.as_str(), trade.collateral_amount.as_str()), ("4000", "15"));
+    }
+
+    #[test]
+    fn test_decodes_amounts_beyond_u64() {
+        let handler = CurveHandler::new();
+        let amount = U256::from(10).pow(U256::from(30));
+
+        let buy = log(&handler.buy, &[TOKEN, TRADER], &[Token::Uint(amount), Token::Uint(U256::one())]);
+        let CurveEvent::Trade(trade) = handler.decode(&buy, 2741).unwrap() else {
+            panic!("expected a trade");
+        };
+        assert_eq!(trade.token_amount, amount.to_string());
+    }
+
+    #[test]
+    fn test_decodes_launch_and_graduation() {
+        let handler = CurveHandler::new();
+
+        let created = log(&handler.token_created, &[TOKEN, TRADER], &[]);
+        assert!(matches!(
+            handler.decode(&created, 2741).unwrap(),
+            CurveEvent::TokenCreated(token, creator) if token == address(TOKEN) && creator == address(TRADER)
+        ));
+
+        let graduated = log(&handler.graduated, &[TOKEN, POOL], &[]);
+        let CurveEvent::Graduated(graduation) = handler.decode(&graduated, 2741).unwrap() else {
+            panic!("expected a graduation");
+        };
+        assert_eq!(graduation.token_address, format!("{:?}", address(TOKEN)));
+        assert_eq!(graduation.pool_address, format!("{:?}", address(POOL)));
+        assert_eq!((graduation.block_number, graduation.log_index), (1_234, 7));
+    }
+
+    #[test]
+    fn test_overridden_events() {
+        let buy = parse_event(
+            "event TokensPurchased(address indexed token, address indexed buyer, uint256 amount, uint256 cost, uint256 price)",
+            TRADE_LAYOUT,
+        )
+        .unwrap();
+        let handler = CurveHandler { buy: buy.clone(), ..CurveHandler::new() };
+        assert!(handler.topics().contains(&buy.signature()));
+
+        let mut data = amounts(100, 3).to_vec();
+        data.push(Token::Uint(U256::from(30)));
+        let CurveEvent::Trade(trade) = handler.decode(&log(&buy, &[TOKEN, TRADER], &data), 2741).unwrap() else {
+            panic!("expected a trade");
+        };
+        assert_eq!((trade.side, trade.token_amount.as_str()), (TradeSide::Buy, "100"));
+
+        // The default Buy signature no longer matches anything
+        let default_buy = log(&CurveHandler::new().buy, &[TOKEN, TRADER], &amounts(1, 1));
+        assert!(handler.decode(&default_buy, 2741).is_err());
+    }
+
+    #[test]
+    fn test_rejects_overrides_with_another_layout() {
+        assert!(parse_event("event Buy(uint256 amount, address indexed token)", TRADE_LAYOUT).is_err());
+        assert!(parse_event("event Graduated(address indexed token)", GRADUATED_LAYOUT).is_err());
+        assert!(parse_event("not an event", GRADUATED_LAYOUT).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+use anyhow::{anyhow, bail, Result};
+use ethers::abi::{Abi, Event, RawLog, Token};
+use ethers::types::{Address, Log, H256, I256, U256};
+use std::sync::{Arc, OnceLock};
+
+use super::abi::{get_factory_abi, get_pool_abi};
+use super::generic_v3::GenericV3Schema;
+use crate::config::Config;
+use crate::dex::{DexId, DexRegistry, HandlerKind, MOONSHOT};
+use crate::runtime;
+use crate::types::{PoolData, SwapEvent};
+
+/// The fields of a pool `Swap` event, as emitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedSwap {
+    pub sender: Address,
+    pub recipient: Address,
+    pub amount0: i128,
+    pub amount1: i128,
+    pub sqrt_price_x96: U256,
+    pub liquidity: u128,
+    pub tick: i32,
+}
+
+fn swap_event() -> &'static Event {
+    static SWAP_EVENT: OnceLock<Event> = OnceLock::new();
+    SWAP_EVENT.get_or_init(|| get_pool_abi().event("Swap").expect("pool ABI has Swap").clone())
+}
+
+/// Decodes the topics and data of a pool `Swap` log. Malformed input of any shape is an
+/// error, never a panic.
+pub fn decode_swap(topics: &[H256], data: &[u8]) -> Result<DecodedSwap> {
+    let decoded = swap_event().parse_log(RawLog { topics: topics.to_vec(), data: data.to_vec() })?;
+    let param = |index: usize| decoded.params[index].value.clone();
+    let address = |index: usize| {
+        param(index).into_address().ok_or_else(|| anyhow!("Swap param {} is not an address", index))
+    };
+    // int256 words arrive in two's complement; one of the amounts is always negative
+    let signed = |index: usize| match param(index) {
+        Token::Int(raw) => Ok(I256::from_raw(raw)),
+        other => Err(anyhow!("Swap param {} is not an int: {:?}", index, other)),
+    };
+    let unsigned = |index: usize| param(index).into_uint().ok_or_else(|| anyhow!("Swap param {} is not a uint", index));
+
+    Ok(DecodedSwap {
+        sender: address(0)?,
+        recipient: address(1)?,
+        amount0: i128::try_from(signed(2)?)?,
+        amount1: i128::try_from(signed(3)?)?,
+        sqrt_price_x96: unsigned(4)?,
+        liquidity: u128::try_from(unsigned(5)?).map_err(|_| anyhow!("Swap liquidity exceeds 128 bits"))?,
+        tick: i32::try_from(signed(6)?)?,
+    })
+}
+
+/// Decodes factory and pool logs without any RPC calls, so batches can be decoded on
+/// blocking threads away from the I/O futures.
+///
+/// Moonshot's own events are decoded natively; a `GenericV3Schema` replaces them for
+/// Uniswap V3 forks that rename or reorder the events.
+pub struct LogDecoder {
+    factory_abi: Abi,
+    schema: Option<GenericV3Schema>,
+}
+
+impl LogDecoder {
+    pub fn new() -> Self {
+        Self {
+            factory_abi: get_factory_abi(),
+            schema: None,
+        }
+    }
+
+    /// A decoder for the events described by `schema` instead of Moonshot's.
+    pub fn generic(schema: GenericV3Schema) -> Self {
+        Self {
+            factory_abi: get_factory_abi(),
+            schema: Some(schema),
+        }
+    }
+
+    /// The generic decoder when `EVENT_SCHEMA_FILE` is set, failing on a descriptor that
+    /// does not check out; the native one otherwise. Fails as well when `check_dex` does
+    /// against the configured registry.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let decoder = match &config.event_schema_file {
+            Some(path) => Self::generic(GenericV3Schema::load(path)?),
+            None => Self::new(),
+        };
+        decoder.check_dex(&DexRegistry::from_config(config)?, config.chain_id as i64)?;
+        Ok(decoder)
+    }
+
+    /// Fails unless the decoder's DEX is in `dexes`, decoded the way this decoder does, and
+    /// deployed on `chain_id`.
+    pub fn check_dex(&self, dexes: &DexRegistry, chain_id: i64) -> Result<()> {
+        let dex = dexes.for_chain(self.dex().as_str(), chain_id)?;
+        let handler = if self.schema.is_some() { HandlerKind::GenericV3 } else { HandlerKind::Moonshot };
+        if dexes.get(&dex).map(|info| info.handler) != Some(handler) {
+            match handler {
+                HandlerKind::GenericV3 => bail!("DEX {} is not decoded with an event schema", dex),
+                HandlerKind::Moonshot => bail!("DEX {} is not decoded with the built-in ABI", dex),
+            }
+        }
+        Ok(())
+    }
+
+    /// DEX the decoded pools are stored under.
+    pub fn dex(&self) -> DexId {
+        self.schema.as_ref().map_or(MOONSHOT, |schema| schema.dex().clone())
+    }
+
+    /// Topic of the factory's pool creation event.
+    pub fn pool_created_topic(&self) -> H256 {
+        match &self.schema {
+            Some(schema) => schema.pool_created_topic(),
+            None => self.factory_abi.event("PoolCreated").expect("factory ABI has PoolCreated").signature(),
+        }
+    }
+
+    /// Topic of the pools' swap event.
+    pub fn swap_topic(&self) -> H256 {
+        match &self.schema {
+            Some(schema) => schema.swap_topic(),
+            None => swap_event().signature(),
+        }
+    }
+
+    /// Decodes a `PoolCreated` log; token metadata is left for the handler to fetch.
+    pub fn decode_pool_created(&self, log: &Log, chain_id: i64) -> Result<PoolData> {
+        if let Some(schema) = &self.schema {
+            return schema.decode_pool_created(log, chain_id);
+        }
+
+        let event = self.factory_abi.event("PoolCreated")?;
+        let decoded = event.parse_log(log.clone().into())?;
+        let param = |index: usize| decoded.params[index].value.clone();
+        let address = |index: usize| {
+            param(index).into_address().ok_or_else(|| anyhow!("PoolCreated param {} is not an address", index))
+        };
+
+        let token0 = address(0)?;
+        let token1 = address(1)?;
+        let fee = param(2).into_uint().ok_or_else(|| anyhow!("PoolCreated fee is not a uint"))?;
+        let fee = i32::try_from(fee).map_err(|_| anyhow!("PoolCreated fee {} exceeds 32 bits", fee))?;
+        let tick_spacing = param(3).into_int().ok_or_else(|| anyhow!("PoolCreated tick spacing is not an int"))?;
+        let tick_spacing = i32::try_from(I256::from_raw(tick_spacing))?;
+        let pool_address = address(4)?;
+
+        let mut pool = new_pool(log, pool_address, token0, token1, chain_id, MOONSHOT);
+        pool.fee_tier = Some(fee);
+        pool.tick_spacing = Some(tick_spacing);
+        Ok(pool)
+    }
+
+    pub fn decode_swap(&self, log: &Log, chain_id: i64) -> Result<SwapEvent> {
+        if let Some(schema) = &self.schema {
+            return schema.decode_swap(log, chain_id);
+        }
+
+        let swap = decode_swap(&log.topics, &log.data)?;
+        let mut swap_event = swap_from_amounts(log, swap.amount0, swap.amount1, chain_id)?;
+        swap_event.sender_address = Some(format!("{:?}", swap.sender));
+        swap_event.recipient_address = Some(format!("{:?}", swap.recipient));
+        swap_event.tick = Some(swap.tick);
+
+        Ok(swap_event)
+    }
+}
+
+/// A pool created by `log` with nothing but its tokens known.
+pub(crate) fn new_pool(log: &Log, pool_address: Address, token0: Address, token1: Address, chain_id: i64, dex: DexId) -> PoolData {
+    PoolData {
+        pool_address: format!("{:?}", pool_address),
+        token0_address: format!("{:?}", token0),
+        token1_address: format!("{:?}", token1),
+        token0_symbol: None,
+        token1_symbol: None,
+        token0_decimals: None,
+        token1_decimals: None,
+        fee_tier: None,
+        tick_spacing: None,
+        liquidity: Some(0),
+        sqrt_price_x96: None,
+        tick: None,
+        chain_id,
+        dex_name: dex,
+        created_at_block: log.block_number.map(|b| b.as_u64() as i64),
+        updated_at_block: None,
+        has_nonstandard_token: false,
+        created_at: None,
+        updated_at: None,
+    }
+}
+
+/// The swap of `log` from the pool's signed token deltas; sender, recipient and tick are
+/// left for the caller.
+pub(crate) fn swap_from_amounts(log: &Log, amount0: i128, amount1: i128, chain_id: i64) -> Result<SwapEvent> {
+    let tx_hash = log.transaction_hash.ok_or_else(|| anyhow!("Swap log has no transaction hash"))?;
+    let block_number = log.block_number.ok_or_else(|| anyhow!("Swap log has no block number"))?;
+    let log_index = log.log_index.ok_or_else(|| anyhow!("Swap log has no log index"))?;
+
+    // The pool pays out the negative amount; both must fit the stored 64-bit amounts
+    let too_large = |amount: i128| anyhow!("swap amount {} exceeds 64 bits", amount);
+    let amount_in = |amount: i128| i64::try_from(amount).map_err(|_| too_large(amount));
+    let amount_out = |amount: i128| {
+        amount.checked_neg().and_then(|out| i64::try_from(out).ok()).ok_or_else(|| too_large(amount))
+    };
+    let (token_in, token_out, amount_in, amount_out) = if amount0 > 0 {
+        ("token0", "token1", amount_in(amount0)?, amount_out(amount1)?)
+    } else {
+        ("token1", "token0", amount_in(amount1)?, amount_out(amount0)?)
+    };
+
+    Ok(SwapEvent::new(
+        format!("{:?}", tx_hash),
+        format!("{:?}", log.address),
+        token_in.to_string(),
+        token_out.to_string(),
+        amount_in,
+        amount_out,
+        block_number.as_u64() as i64,
+        block_number.as_u64() as i64,
+        i32::try_from(log_index).map_err(|_| anyhow!("log index {} exceeds 32 bits", log_index))?,
+        chain_id,
+    ))
+}
+
+impl Default for LogDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decodes a batch of `Swap` logs on the blocking pool, pairing each log with its result.
+pub async fn decode_swaps(decoder: Arc<LogDecoder>, logs: Vec<Log>, chain_id: i64) -> Result<Vec<(Log, Result<SwapEvent>)>> {
+    runtime::offload(logs, move |log| {
+        let swap = decoder.decode_swap(&log, chain_id);
+        (log, swap)
+    })
+    .await
+}
+
+/// Decodes a batch of `PoolCreated` logs on the blocking pool.
+pub async fn decode_pool_created(decoder: Arc<LogDecoder>, logs: Vec<Log>, chain_id: i64) -> Result<Vec<Result<PoolData>>> {
+    runtime::offload(logs, move |log| decoder.decode_pool_created(&log, chain_id)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::{encode, Token};
+    use ethers::types::{H256, U64};
+    use std::time::Instant;
+
+    fn swap_log(n: u64) -> Log {
+        let amount1 = I256::from(-(n as i64) - 1);
+
+        Log {
+            address: Address::from_low_u64_be(0xaa),
+            topics: vec![
+                swap_event().signature(),
+                H256::from(Address::from_low_u64_be(0xbeef)),
+                H256::from(Address::from_low_u64_be(0xcafe)),
+            ],
+            data: encode(&[
+                Token::Int(U256::from(1_000 + n)),
+                Token::Int(amount1.into_raw()),
+                Token::Uint(U256::one() << 96),
+                Token::Uint(U256::from(10u64.pow(18))),
+                Token::Int(I256::from(-60).into_raw()),
+            ])
+            .into(),
+            block_number: Some(U64::from(n / 10)),
+            log_index: Some((n % 10).into()),
+            transaction_hash: Some(H256::from_low_u64_be(n)),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_offloaded_batch_decodes_every_log() {
+        let decoder = Arc::new(LogDecoder::new());
+        let logs: Vec<Log> = (0..10_000).map(swap_log).collect();
+
+        let started = Instant::now();
+        let decoded = decode_swaps(decoder, logs, 2741).await.unwrap();
+        let elapsed = started.elapsed();
+
+        assert_eq!(decoded.len(), 10_000, "decoded in {:?}", elapsed);
+        for (n, (log, swap)) in decoded.iter().enumerate() {
+            let swap = swap.as_ref().unwrap();
+            assert_eq!(log.transaction_hash, Some(H256::from_low_u64_be(n as u64)));
+            assert_eq!((swap.amount_in, swap.amount_out), (1_000 + n as i64, n as i64 + 1));
+            assert_eq!((swap.block_number, swap.log_index), (n as i64 / 10, n as i32 % 10));
+            assert_eq!(swap.sender_address, Some(format!("{:?}", Address::from_low_u64_be(0xbeef))));
+            assert_eq!(swap.recipient_address, Some(format!("{:?}", Address::from_low_u64_be(0xcafe))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_undecodable_logs_fail_individually() {
+        let decoder = Arc::new(LogDecoder::new());
+        let mut broken = swap_log(1);
+        broken.data = Vec::new().into();
+
+        let decoded = decode_swaps(decoder.clone(), vec![swap_log(0), broken], 2741).await.unwrap();
+        assert!(decoded[0].1.is_ok());
+        assert!(decoded[1].1.is_err());
+    }
+}
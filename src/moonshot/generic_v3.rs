@@ -0,0 +1,487 @@
+use anyhow::{anyhow, bail, Context, Result};
+use ethers::abi::{Event, HumanReadableParser, ParamType, RawLog, Token};
+use ethers::types::{Address, Log, H256, I256};
+use serde::Deserialize;
+use std::path::Path;
+
+use super::decode::{new_pool, swap_from_amounts};
+use crate::dex::DexId;
+use crate::types::{PoolData, SwapEvent};
+
+// DEX of the pools decoded with a descriptor that names none
+const DEFAULT_DEX: &str = "uniswap-v3";
+
+/// Where a Uniswap V3 fork's factory and pool events keep the fields the indexer reads,
+/// as loaded from the `EVENT_SCHEMA_FILE` descriptor.
+///
+/// Each event is a human-readable definition, whose `indexed` parameters are the log's
+/// topics in order; the other keys give the zero-based position of a field among all of
+/// the event's parameters, indexed or not.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventSchemaDescriptor {
+    /// Registry id the fork's pools are stored under; `uniswap-v3` when unset.
+    #[serde(default)]
+    pub dex: Option<String>,
+    pub pool_created: PoolCreatedLayout,
+    pub swap: SwapLayout,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PoolCreatedLayout {
+    pub event: String,
+    pub token0: usize,
+    pub token1: usize,
+    pub pool: usize,
+    pub fee: Option<usize>,
+    pub tick_spacing: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SwapLayout {
+    pub event: String,
+    /// Signed token0 delta of the pool: positive when paid in, negative when paid out.
+    pub amount0: usize,
+    pub amount1: usize,
+    pub sender: Option<usize>,
+    pub recipient: Option<usize>,
+    pub tick: Option<usize>,
+}
+
+impl EventSchemaDescriptor {
+    pub fn from_toml(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        Ok(serde_json::from_str(text)?)
+    }
+}
+
+/// Solidity type family a mapped field must have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Address,
+    Uint,
+    Int,
+}
+
+impl Kind {
+    fn matches(self, param: &ParamType) -> bool {
+        matches!(
+            (self, param),
+            (Kind::Address, ParamType::Address) | (Kind::Uint, ParamType::Uint(_)) | (Kind::Int, ParamType::Int(_))
+        )
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Address => "an address",
+            Kind::Uint => "a uint",
+            Kind::Int => "an int",
+        }
+    }
+}
+
+/// Decodes the pool creation and swap events of a `EventSchemaDescriptor`, checked against
+/// the events' own definitions when built.
+#[derive(Debug, Clone)]
+pub struct GenericV3Schema {
+    dex: DexId,
+    pool_created: Event,
+    pool_created_layout: PoolCreatedLayout,
+    swap: Event,
+    swap_layout: SwapLayout,
+}
+
+impl GenericV3Schema {
+    /// Reads the descriptor at `path`: TOML when it ends in `.toml`, JSON otherwise.
+    pub fn load(path: &str) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("cannot read event schema {}", path))?;
+        let descriptor = if Path::new(path).extension().is_some_and(|extension| extension == "toml") {
+            EventSchemaDescriptor::from_toml(&text)
+        } else {
+            EventSchemaDescriptor::from_json(&text)
+        };
+        descriptor.and_then(Self::new).with_context(|| format!("invalid event schema {}", path))
+    }
+
+    /// Checks that the DEX id is well-formed and that every mapped position exists, has the
+    /// field's type and is used once. Whether the DEX is registered is checked by
+    /// `LogDecoder::check_dex`.
+    pub fn new(descriptor: EventSchemaDescriptor) -> Result<Self> {
+        let EventSchemaDescriptor { dex, pool_created: pool_created_layout, swap: swap_layout } = descriptor;
+
+        let dex = DexId::new(dex.as_deref().unwrap_or(DEFAULT_DEX))?;
+
+        let pool_created = parse_event("pool_created", &pool_created_layout.event)?;
+        check_fields(
+            "pool_created",
+            &pool_created,
+            &[
+                ("token0", Some(pool_created_layout.token0), Kind::Address),
+                ("token1", Some(pool_created_layout.token1), Kind::Address),
+                ("pool", Some(pool_created_layout.pool), Kind::Address),
+                ("fee", pool_created_layout.fee, Kind::Uint),
+                ("tick_spacing", pool_created_layout.tick_spacing, Kind::Int),
+            ],
+        )?;
+
+        let swap = parse_event("swap", &swap_layout.event)?;
+        check_fields(
+            "swap",
+            &swap,
+            &[
+                ("amount0", Some(swap_layout.amount0), Kind::Int),
+                ("amount1", Some(swap_layout.amount1), Kind::Int),
+                ("sender", swap_layout.sender, Kind::Address),
+                ("recipient", swap_layout.recipient, Kind::Address),
+                ("tick", swap_layout.tick, Kind::Int),
+            ],
+        )?;
+        if pool_created.signature() == swap.signature() {
+            bail!("pool_created and swap are the same event");
+        }
+
+        Ok(Self { dex, pool_created, pool_created_layout, swap, swap_layout })
+    }
+
+    /// DEX the decoded pools are stored under.
+    pub fn dex(&self) -> &DexId {
+        &self.dex
+    }
+
+    pub fn pool_created_topic(&self) -> H256 {
+        self.pool_created.signature()
+    }
+
+    pub fn swap_topic(&self) -> H256 {
+        self.swap.signature()
+    }
+
+    /// Decodes a pool creation log; token metadata is left for the handler to fetch.
+    pub fn decode_pool_created(&self, log: &Log, chain_id: i64) -> Result<PoolData> {
+        let params = decode_params(&self.pool_created, log)?;
+        let layout = &self.pool_created_layout;
+
+        let mut pool = new_pool(
+            log,
+            address_param(&params, layout.pool)?,
+            address_param(&params, layout.token0)?,
+            address_param(&params, layout.token1)?,
+            chain_id,
+            self.dex.clone(),
+        );
+        if let Some(index) = layout.fee {
+            let fee = params[index].clone().into_uint().ok_or_else(|| anyhow!("fee is not a uint"))?;
+            pool.fee_tier = Some(i32::try_from(fee).map_err(|_| anyhow!("fee {} exceeds 32 bits", fee))?);
+        }
+        if let Some(index) = layout.tick_spacing {
+            pool.tick_spacing = Some(i32::try_from(int_param(&params, index)?)?);
+        }
+        Ok(pool)
+    }
+
+    pub fn decode_swap(&self, log: &Log, chain_id: i64) -> Result<SwapEvent> {
+        let params = decode_params(&self.swap, log)?;
+        let layout = &self.swap_layout;
+
+        let amount0 = i128::try_from(int_param(&params, layout.amount0)?)?;
+        let amount1 = i128::try_from(int_param(&params, layout.amount1)?)?;
+        let mut swap = swap_from_amounts(log, amount0, amount1, chain_id)?;
+        if let Some(index) = layout.sender {
+            swap.sender_address = Some(format!("{:?}", address_param(&params, index)?));
+        }
+        if let Some(index) = layout.recipient {
+            swap.recipient_address = Some(format!("{:?}", address_param(&params, index)?));
+        }
+        if let Some(index) = layout.tick {
+            swap.tick = Some(i32::try_from(int_param(&params, index)?)?);
+        }
+        Ok(swap)
+    }
+}
+
+fn parse_event(name: &str, definition: &str) -> Result<Event> {
+    HumanReadableParser::parse_event(definition).map_err(|e| anyhow!("invalid {} event {:?}: {}", name, definition, e))
+}
+
+fn check_fields(name: &str, event: &Event, fields: &[(&str, Option<usize>, Kind)]) -> Result<()> {
+    let mapped: Vec<(&str, usize, Kind)> =
+        fields.iter().filter_map(|(field, index, kind)| index.map(|index| (*field, index, *kind))).collect();
+
+    for (i, (field, index, kind)) in mapped.iter().enumerate() {
+        let Some(input) = event.inputs.get(*index) else {
+            bail!("{}.{} is parameter {} but {} has {} parameters", name, field, index, event.name, event.inputs.len());
+        };
+        if !kind.matches(&input.kind) {
+            bail!("{}.{} must be {} parameter, but parameter {} of {} is {}", name, field, kind.as_str(), index, event.name, input.kind);
+        }
+        if let Some((other, _, _)) = mapped[..i].iter().find(|(_, other_index, _)| other_index == index) {
+            bail!("{}.{} and {}.{} are both parameter {}", name, other, name, field, index);
+        }
+    }
+    Ok(())
+}
+
+fn decode_params(event: &Event, log: &Log) -> Result<Vec<Token>> {
+    let raw = RawLog { topics: log.topics.clone(), data: log.data.to_vec() };
+    Ok(event.parse_log(raw)?.params.into_iter().map(|param| param.value).collect())
+}
+
+fn address_param(params: &[Token], index: usize) -> Result<Address> {
+    params
+        .get(index)
+        .and_then(|token| token.clone().into_address())
+        .ok_or_else(|| anyhow!("event parameter {} is not an address", index))
+}
+
+// int words arrive in two's complement
+fn int_param(params: &[Token], index: usize) -> Result<I256> {
+    params
+        .get(index)
+        .and_then(|token| token.clone().into_int())
+        .map(I256::from_raw)
+        .ok_or_else(|| anyhow!("event parameter {} is not an int", index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::{parse_dex_list, DexRegistry};
+    use crate::moonshot::LogDecoder;
+    use crate::testing::{pool_created_log, swap_log};
+    use ethers::abi::encode;
+    use ethers::types::{U256, U64};
+
+    const POOL: u64 = 0xaa;
+    const FACTORY: u64 = 0xfac;
+    const TOKEN0: u64 = 0x10;
+    const TOKEN1: u64 = 0x20;
+    const TRADER: u64 = 0xbeef;
+
+    // Moonshot's own layout, spelled out as a descriptor
+    const NATIVE_TOML: &str = r#"
+        [pool_created]
+        event = "event PoolCreated(address indexed token0, address indexed token1, uint24 fee, int24 tickSpacing, address indexed pool)"
+        token0 = 0
+        token1 = 1
+        fee = 2
+        tick_spacing = 3
+        pool = 4
+
+        [swap]
+        event = "event Swap(address indexed sender, address indexed recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick)"
+        sender = 0
+        recipient = 1
+        amount0 = 2
+        amount1 = 3
+        tick = 6
+    "#;
+
+    // A fork that renamed both events and moved every field
+    const FORK_JSON: &str = r#"{
+        "pool_created": {
+            "event": "event PairDeployed(address indexed pool, int24 spacing, address indexed tokenB, uint24 fee, address indexed tokenA)",
+            "pool": 0, "tick_spacing": 1, "token1": 2, "fee": 3, "token0": 4
+        },
+        "swap": {
+            "event": "event Swapped(int24 tick, int256 delta1, address indexed to, int256 delta0, address indexed from, uint128 liquidity)",
+            "tick": 0, "amount1": 1, "recipient": 2, "amount0": 3, "sender": 4
+        }
+    }"#;
+
+    fn address(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    fn fork() -> GenericV3Schema {
+        GenericV3Schema::new(EventSchemaDescriptor::from_json(FORK_JSON).unwrap()).unwrap()
+    }
+
+    fn located(mut log: Log) -> Log {
+        log.block_number = Some(U64::from(1_234));
+        log.log_index = Some(7.into());
+        log.transaction_hash = Some(H256::from_low_u64_be(0xabc));
+        log
+    }
+
+    fn fork_log(event: &Event, address: Address, indexed: &[Address], data: &[Token]) -> Log {
+        let mut topics = vec![event.signature()];
+        topics.extend(indexed.iter().map(|indexed| H256::from(*indexed)));
+        located(Log { address, topics, data: encode(data).into(), ..Default::default() })
+    }
+
+    fn descriptor_error(edit: impl FnOnce(&mut EventSchemaDescriptor)) -> String {
+        let mut descriptor = EventSchemaDescriptor::from_toml(NATIVE_TOML).unwrap();
+        edit(&mut descriptor);
+        GenericV3Schema::new(descriptor).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_toml_and_json_descriptors_parse_alike() {
+        let toml = EventSchemaDescriptor::from_toml(NATIVE_TOML).unwrap();
+        let json = EventSchemaDescriptor::from_json(&serde_json::json!({
+            "pool_created": {
+                "event": toml.pool_created.event,
+                "token0": 0, "token1": 1, "fee": 2, "tick_spacing": 3, "pool": 4
+            },
+            "swap": {
+                "event": toml.swap.event,
+                "sender": 0, "recipient": 1, "amount0": 2, "amount1": 3, "tick": 6
+            }
+        }).to_string())
+        .unwrap();
+
+        assert_eq!(toml, json);
+        assert_eq!((toml.swap.amount0, toml.swap.tick), (2, Some(6)));
+        assert_eq!(toml.pool_created.fee, Some(2));
+    }
+
+    #[test]
+    fn test_descriptor_rejects_unknown_and_missing_keys() {
+        assert!(EventSchemaDescriptor::from_toml(&NATIVE_TOML.replace("tick = 6", "tick = 6\nprice = 4")).is_err());
+        assert!(EventSchemaDescriptor::from_toml(&NATIVE_TOML.replace("amount1 = 3", "")).is_err());
+
+        // Optional fields may be left out
+        let minimal = NATIVE_TOML.replace("sender = 0", "").replace("fee = 2", "");
+        let descriptor = EventSchemaDescriptor::from_toml(&minimal).unwrap();
+        assert_eq!((descriptor.swap.sender, descriptor.pool_created.fee), (None, None));
+        assert!(GenericV3Schema::new(descriptor).is_ok());
+    }
+
+    #[test]
+    fn test_mapping_is_checked_against_the_event() {
+        let error = descriptor_error(|d| d.swap.tick = Some(7));
+        assert_eq!(error, "swap.tick is parameter 7 but Swap has 7 parameters");
+
+        let error = descriptor_error(|d| d.swap.amount0 = 0);
+        assert_eq!(error, "swap.amount0 must be an int parameter, but parameter 0 of Swap is address");
+
+        let error = descriptor_error(|d| d.pool_created.fee = Some(3));
+        assert_eq!(error, "pool_created.fee must be a uint parameter, but parameter 3 of PoolCreated is int24");
+
+        let error = descriptor_error(|d| d.pool_created.token1 = 0);
+        assert_eq!(error, "pool_created.token0 and pool_created.token1 are both parameter 0");
+
+        let error = descriptor_error(|d| d.swap.event = "event Swap(address indexed".to_string());
+        assert!(error.starts_with("invalid swap event"), "{}", error);
+
+    }
+
+    #[test]
+    fn test_native_descriptor_matches_the_native_decoder() {
+        let schema = GenericV3Schema::new(EventSchemaDescriptor::from_toml(NATIVE_TOML).unwrap()).unwrap();
+        let native = LogDecoder::new();
+        assert_eq!(schema.swap_topic(), native.swap_topic());
+        assert_eq!(schema.pool_created_topic(), native.pool_created_topic());
+
+        let swap = located(swap_log(address(POOL), address(TRADER), -950, 1_000, -60));
+        assert_eq!(schema.decode_swap(&swap, 2741).unwrap(), native.decode_swap(&swap, 2741).unwrap());
+
+        // Only the DEX the pool is stored under differs
+        let created = located(pool_created_log(address(FACTORY), address(TOKEN0), address(TOKEN1), 3000, 60, address(POOL)));
+        let mut expected = native.decode_pool_created(&created, 2741).unwrap();
+        expected.dex_name = DexId::new("uniswap-v3").unwrap();
+        assert_eq!(schema.decode_pool_created(&created, 2741).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_reordered_fork_events_decode_like_native_ones() {
+        let schema = fork();
+        let native = LogDecoder::new();
+        let (trader, recipient) = (address(TRADER), address(0xcafe));
+
+        // The same swap as the native fixture, with every field in another place
+        let mut expected = located(swap_log(address(POOL), trader, 1_000, -950, -60));
+        expected.topics[2] = H256::from(recipient);
+        let fork_swap = fork_log(
+            &schema.swap,
+            address(POOL),
+            &[recipient, trader],
+            &[
+                Token::Int(I256::from(-60).into_raw()),
+                Token::Int(I256::from(-950).into_raw()),
+                Token::Int(I256::from(1_000).into_raw()),
+                Token::Uint(U256::from(10u64.pow(18))),
+            ],
+        );
+        let decoded = schema.decode_swap(&fork_swap, 2741).unwrap();
+        assert_eq!(decoded, native.decode_swap(&expected, 2741).unwrap());
+        assert_eq!((decoded.token_in.as_str(), decoded.amount_in, decoded.amount_out), ("token0", 1_000, 950));
+        assert_eq!(decoded.recipient_address, Some(format!("{:?}", recipient)));
+
+        let fork_created = fork_log(
+            &schema.pool_created,
+            address(FACTORY),
+            &[address(POOL), address(TOKEN1), address(TOKEN0)],
+            &[Token::Int(I256::from(60).into_raw()), Token::Uint(U256::from(3000))],
+        );
+        let created = located(pool_created_log(address(FACTORY), address(TOKEN0), address(TOKEN1), 3000, 60, address(POOL)));
+        let mut expected = native.decode_pool_created(&created, 2741).unwrap();
+        expected.dex_name = DexId::new("uniswap-v3").unwrap();
+        assert_eq!(schema.decode_pool_created(&fork_created, 2741).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_descriptor_names_a_generic_dex() {
+        assert_eq!(fork().dex().as_str(), "uniswap-v3");
+        assert_eq!(&LogDecoder::generic(fork()).dex(), fork().dex());
+        assert_eq!(LogDecoder::new().dex().as_str(), "moonshot");
+
+        let named = EventSchemaDescriptor::from_toml(&format!("dex = \"Uniswap-V3\"\n{}", NATIVE_TOML)).unwrap();
+        assert_eq!(GenericV3Schema::new(named).unwrap().dex().as_str(), "uniswap-v3");
+
+        let error = descriptor_error(|d| d.dex = Some("sushi swap".to_string()));
+        assert!(error.starts_with("invalid DEX id \"sushi swap\""), "{}", error);
+
+        // The DEX must be registered, and decoded with a schema
+        let presets = DexRegistry::presets();
+        let decoder = |dex: &str| {
+            let mut descriptor = EventSchemaDescriptor::from_toml(NATIVE_TOML).unwrap();
+            descriptor.dex = Some(dex.to_string());
+            LogDecoder::generic(GenericV3Schema::new(descriptor).unwrap())
+        };
+        let error = decoder("moonshot").check_dex(&presets, 8453).unwrap_err().to_string();
+        assert_eq!(error, "DEX moonshot is not decoded with an event schema");
+        let error = decoder("sushiswap").check_dex(&presets, 8453).unwrap_err().to_string();
+        assert!(error.starts_with("unknown DEX \"sushiswap\"; known DEXes: "), "{}", error);
+        let extended = presets.extended(parse_dex_list("sushiswap:SushiSwap:8453:generic-v3").unwrap()).unwrap();
+        assert!(decoder("sushiswap").check_dex(&extended, 8453).is_ok());
+        assert!(decoder("sushiswap").check_dex(&extended, 1).is_err());
+    }
+
+    #[test]
+    fn test_generic_decoder_uses_the_schema() {
+        let decoder = LogDecoder::generic(fork());
+        assert_eq!(decoder.swap_topic(), fork().swap_topic());
+        assert_ne!(decoder.swap_topic(), LogDecoder::new().swap_topic());
+
+        // Native logs are another event to the fork's schema
+        let native_swap = located(swap_log(address(POOL), address(TRADER), 1_000, -950, -60));
+        assert!(decoder.decode_swap(&native_swap, 2741).is_err());
+    }
+
+    #[test]
+    fn test_load_picks_the_format_by_extension() {
+        let dir = std::env::temp_dir().join(format!("event-schema-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (toml_path, json_path) = (dir.join("native.toml"), dir.join("fork.json"));
+        std::fs::write(&toml_path, NATIVE_TOML).unwrap();
+        std::fs::write(&json_path, FORK_JSON).unwrap();
+
+        let native = GenericV3Schema::load(toml_path.to_str().unwrap()).unwrap();
+        assert_eq!(native.swap_topic(), LogDecoder::new().swap_topic());
+        assert_eq!(GenericV3Schema::load(json_path.to_str().unwrap()).unwrap().swap_topic(), fork().swap_topic());
+
+        // TOML is not read as JSON
+        std::fs::write(&json_path, NATIVE_TOML).unwrap();
+        let error = GenericV3Schema::load(json_path.to_str().unwrap()).unwrap_err();
+        assert!(format!("{:#}", error).starts_with("invalid event schema"), "{:#}", error);
+        assert!(GenericV3Schema::load(dir.join("missing.json").to_str().unwrap()).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,95 +1,72 @@
-use anyhow::Result;
-use ethers::abi::{Abi, Token};
-use ethers::contract::Contract;
-use ethers::providers::Provider;
-use ethers::types::{Address, Log, U256};
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use ethers::abi::{Abi, Detokenize, Token};
+use ethers::contract::{Contract, Multicall, MULTICALL_ADDRESS};
+use ethers::providers::{JsonRpcClient, Middleware, Provider, PubsubClient, Ws};
+use ethers::types::{Address, Bytes, Filter, Log, U256};
+use futures::{Stream, StreamExt};
 use std::sync::Arc;
+use tracing::debug;
 
 use super::abi::{get_erc20_abi, get_factory_abi, get_pool_abi};
-use crate::types::{PoolData, SwapEvent};
+use super::decode::LogDecoder;
+use crate::holders::HolderSource;
+use crate::position;
+use crate::price;
+use crate::pool_state::{self, CallErrorKind, CallResult, PoolStateReader, PoolStateReads, PoolStateUpdate};
+use crate::supply::SupplySource;
+use crate::types::{PoolData, PositionInfo, TickData, TokenData};
 
-pub struct MoonshotHandler {
-    factory_abi: Abi,
+// Most ticks `get_all_tick_data` reads in one go, one call each, to stay clear of RPC rate limits
+const MAX_TICKS_PER_QUERY: usize = 100;
+
+/// Pool and token reads over any JSON-RPC transport; WebSocket by default.
+pub struct MoonshotHandler<P = Ws> {
+    decoder: Arc<LogDecoder>,
     pool_abi: Abi,
     erc20_abi: Abi,
-    provider: Arc<Provider<ethers::providers::Ws>>,
+    provider: Arc<Provider<P>>,
 }
 
-impl MoonshotHandler {
-    pub fn new(provider: Arc<Provider<ethers::providers::Ws>>) -> Self {
+impl<P: JsonRpcClient + 'static> MoonshotHandler<P> {
+    pub fn new(provider: Arc<Provider<P>>) -> Self {
+        Self::with_decoder(provider, Arc::new(LogDecoder::new()))
+    }
+
+    /// A handler decoding logs with `decoder`, e.g. one built from an event schema.
+    pub fn with_decoder(provider: Arc<Provider<P>>, decoder: Arc<LogDecoder>) -> Self {
         Self {
-            factory_abi: get_factory_abi(),
+            decoder,
             pool_abi: get_pool_abi(),
             erc20_abi: get_erc20_abi(),
             provider,
         }
     }
 
-    pub async fn handle_pool_created(&self, log: Log, chain_id: i64) -> Result<PoolData> {
-        let event = self.factory_abi.event("PoolCreated")?;
-        let decoded = event.parse_log(log.clone().into())?;
-
-        let token0: Address = decoded.params[0].value.clone().into_address().unwrap();
-        let token1: Address = decoded.params[1].value.clone().into_address().unwrap();
-        let fee: u32 = decoded.params[2].value.clone().into_uint().unwrap().as_u32();
-        let tick_spacing: i32 = decoded.params[3].value.clone().into_int().unwrap().as_u32() as i32;
-        let pool_address: Address = decoded.params[4].value.clone().into_address().unwrap();
-
-        let (token0_symbol, token0_decimals) = self.get_token_metadata(token0).await?;
-        let (token1_symbol, token1_decimals) = self.get_token_metadata(token1).await?;
-
-        let pool_data = PoolData {
-            pool_address: format!("{:?}", pool_address),
-            token0_address: format!("{:?}", token0),
-            token1_address: format!("{:?}", token1),
-            token0_symbol,
-            token1_symbol,
-            token0_decimals: Some(token0_decimals as i32),
-            token1_decimals: Some(token1_decimals as i32),
-            fee_tier: Some(fee as i32),
-            tick_spacing: Some(tick_spacing),
-            liquidity: Some(0),
-            sqrt_price_x96: None,
-            tick: None,
-            chain_id,
-            dex_name: "moonshot".to_string(),
-        };
+    /// The RPC-free log decoding, shareable with blocking tasks.
+    pub fn decoder(&self) -> Arc<LogDecoder> {
+        self.decoder.clone()
+    }
 
+    pub async fn handle_pool_created(&self, log: Log, chain_id: i64) -> Result<PoolData> {
+        let mut pool_data = self.decoder.decode_pool_created(&log, chain_id)?;
+        self.fetch_pool_token_metadata(&mut pool_data).await?;
         Ok(pool_data)
     }
 
-    pub async fn handle_swap(&self, log: Log, chain_id: i64) -> Result<SwapEvent> {
-        let event = self.pool_abi.event("Swap")?;
-        let decoded = event.parse_log(log.clone().into())?;
-
-        let sender: Address = decoded.params[0].value.clone().into_address().unwrap();
-        let recipient: Address = decoded.params[1].value.clone().into_address().unwrap();
-        let amount0: i128 = decoded.params[2].value.clone().into_int().unwrap().as_u128() as i128;
-        let amount1: i128 = decoded.params[3].value.clone().into_int().unwrap().as_u128() as i128;
-        let sqrt_price_x96: U256 = decoded.params[4].value.clone().into_uint().unwrap();
-        let liquidity: u128 = decoded.params[5].value.clone().into_uint().unwrap().as_u128();
-        let tick: i32 = decoded.params[6].value.clone().into_int().unwrap().as_u32() as i32;
-
-        let (token_in, token_out, amount_in, amount_out) = if amount0 > 0 {
-            ("token0", "token1", amount0 as i64, -(amount1 as i64))
-        } else {
-            ("token1", "token0", amount1 as i64, -(amount0 as i64))
-        };
+    pub async fn fetch_pool_token_metadata(&self, pool_data: &mut PoolData) -> Result<()> {
+        let token0 = Address::from(pool_data.token0_canonical()?);
+        let token1 = Address::from(pool_data.token1_canonical()?);
 
-        let swap_event = SwapEvent::new(
-            format!("{:?}", log.transaction_hash.unwrap()),
-            format!("{:?}", log.address),
-            token_in.to_string(),
-            token_out.to_string(),
-            amount_in,
-            amount_out,
-            log.block_number.unwrap().as_u64() as i64,
-            log.block_number.unwrap().as_u64() as i64,
-            log.log_index.unwrap().as_u64() as i32,
-            chain_id,
-        );
+        let mut metadata = self.batch_get_token_metadata(&[token0, token1]).await?.into_iter();
+        let (token0_symbol, token0_decimals) = metadata.next().unwrap_or((None, 18));
+        let (token1_symbol, token1_decimals) = metadata.next().unwrap_or((None, 18));
 
-        Ok(swap_event)
+        pool_data.token0_symbol = token0_symbol;
+        pool_data.token1_symbol = token1_symbol;
+        pool_data.token0_decimals = Some(token0_decimals as i32);
+        pool_data.token1_decimals = Some(token1_decimals as i32);
+        Ok(())
     }
 
     async fn get_token_metadata(&self, token_address: Address) -> Result<(Option<String>, u8)> {
@@ -100,49 +77,331 @@ impl MoonshotHandler {
             Err(_) => return Ok((None, 18)),
         };
 
-        let decimals: u8 = match contract.method("decimals", ())?.call().await {
-            Ok(d) => d,
-            Err(_) => 18,
-        };
+        let decimals: u8 = contract.method("decimals", ())?.call().await.unwrap_or(18);
 
         Ok((Some(symbol), decimals))
     }
 
-    pub async fn update_pool_state(
+    /// Symbol and decimals of each of `tokens`, with every `symbol()` and `decimals()` call in
+    /// one Multicall3 `aggregate3` request. Falls back to a pair of calls per token when the
+    /// multicall itself fails, e.g. on a chain without Multicall3.
+    pub async fn batch_get_token_metadata(&self, tokens: &[Address]) -> Result<Vec<(Option<String>, u8)>> {
+        if tokens.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut multicall = Multicall::new(self.provider.clone(), Some(MULTICALL_ADDRESS)).await?;
+        for token in tokens {
+            let contract = Contract::new(*token, self.erc20_abi.clone(), self.provider.clone());
+            multicall.add_call(contract.method::<_, String>("symbol", ())?, true);
+            multicall.add_call(contract.method::<_, u8>("decimals", ())?, true);
+        }
+
+        match multicall.call_raw().await {
+            Ok(results) => Ok(Self::parse_multicall_result(results)),
+            Err(e) => {
+                debug!("Multicall for {} tokens failed, reading them one by one: {}", tokens.len(), e);
+                let mut metadata = Vec::with_capacity(tokens.len());
+                for token in tokens {
+                    metadata.push(self.get_token_metadata(*token).await?);
+                }
+                Ok(metadata)
+            }
+        }
+    }
+
+    /// (symbol, decimals) per token from `aggregate3` results laid out as `symbol()` then
+    /// `decimals()` for each token. Failed calls default like single reads do: no symbol
+    /// means 18 decimals, and so do missing or out-of-range decimals.
+    pub fn parse_multicall_result(results: Vec<std::result::Result<Token, Bytes>>) -> Vec<(Option<String>, u8)> {
+        results
+            .chunks(2)
+            .map(|pair| {
+                let symbol = pair[0].clone().ok().and_then(|symbol| symbol.into_string());
+                let decimals = pair
+                    .get(1)
+                    .and_then(|decimals| decimals.clone().ok()?.into_uint())
+                    .filter(|decimals| *decimals <= U256::from(u8::MAX))
+                    .map_or(18, |decimals| decimals.as_u32() as u8);
+                match symbol {
+                    Some(symbol) => (Some(symbol), decimals),
+                    None => (None, 18),
+                }
+            })
+            .collect()
+    }
+
+    /// Full ERC20 metadata for the `tokens` table; calls that revert are left as `None`.
+    pub async fn fetch_token_data(&self, token_address: Address, chain_id: i64) -> Result<TokenData> {
+        let contract = Contract::new(token_address, self.erc20_abi.clone(), self.provider.clone());
+
+        let name: Option<String> = contract.method("name", ())?.call().await.ok();
+        let symbol: Option<String> = contract.method("symbol", ())?.call().await.ok();
+        let decimals: Option<u8> = contract.method("decimals", ())?.call().await.ok();
+        let total_supply: Option<U256> = contract.method("totalSupply", ())?.call().await.ok();
+
+        Ok(TokenData {
+            address: format!("{:?}", token_address),
+            name,
+            symbol,
+            decimals: decimals.map(|d| d as i32),
+            total_supply: total_supply.map(|s| s.to_string()),
+            supply_updated_at: None,
+            is_fee_on_transfer: false,
+            is_rebasing: false,
+            creator_address: None,
+            chain_id,
+        })
+    }
+
+    /// `wallet`'s balance of the token, in its smallest unit.
+    pub async fn get_token_balance(&self, token_address: Address, wallet: Address) -> Result<U256> {
+        let contract = Contract::new(token_address, self.erc20_abi.clone(), self.provider.clone());
+        Ok(contract.method::<_, U256>("balanceOf", wallet)?.call().await?)
+    }
+
+    /// The pool's own (token0, token1) balances, read in one multicall; `None` where a
+    /// `balanceOf` call reverted. Without Multicall3 the tokens are read one at a time.
+    pub async fn get_pool_token_balances(&self, pool: &PoolData) -> Result<(Option<U256>, Option<U256>)> {
+        let pool_address: Address = pool.pool_address.parse()?;
+        let tokens = [Address::from(pool.token0_canonical()?), Address::from(pool.token1_canonical()?)];
+
+        let mut multicall = Multicall::new(self.provider.clone(), Some(MULTICALL_ADDRESS)).await?;
+        for token in tokens {
+            let contract = Contract::new(token, self.erc20_abi.clone(), self.provider.clone());
+            multicall.add_call(contract.method::<_, U256>("balanceOf", pool_address)?, true);
+        }
+
+        match multicall.call_raw().await {
+            Ok(results) => {
+                let mut balances = results.into_iter().map(|result| result.ok().and_then(Token::into_uint));
+                Ok((balances.next().flatten(), balances.next().flatten()))
+            }
+            Err(e) => {
+                debug!("Balance multicall for pool {} failed, reading tokens one by one: {}", pool.pool_address, e);
+                let token0 = self.get_token_balance(tokens[0], pool_address).await.ok();
+                let token1 = self.get_token_balance(tokens[1], pool_address).await.ok();
+                Ok((token0, token1))
+            }
+        }
+    }
+
+    /// How much of the token `spender` may still move on behalf of `owner`.
+    pub async fn get_token_allowance(&self, token: Address, owner: Address, spender: Address) -> Result<U256> {
+        let contract = Contract::new(token, self.erc20_abi.clone(), self.provider.clone());
+        Ok(contract.method::<_, U256>("allowance", (owner, spender))?.call().await?)
+    }
+
+    /// Reads one entry of the pool's `ticks` mapping; uninitialized ticks come back zeroed.
+    pub async fn get_tick_data(&self, pool_address: Address, tick: i32) -> Result<TickData> {
+        let contract = Contract::new(pool_address, self.pool_abi.clone(), self.provider.clone());
+        let (liquidity_gross, liquidity_net, fee_growth_outside_0, fee_growth_outside_1, _, _, _, _): (
+            u128,
+            i128,
+            U256,
+            U256,
+            i64,
+            U256,
+            u32,
+            bool,
+        ) = contract.method("ticks", tick)?.call().await?;
+
+        Ok(TickData {
+            tick_index: tick,
+            liquidity_gross,
+            liquidity_net,
+            fee_growth_outside_0,
+            fee_growth_outside_1,
+        })
+    }
+
+    /// Reads every tick at `tick_spacing` intervals in `[tick_lower, tick_upper]`. Ranges
+    /// spanning more than 100 ticks are rejected rather than read partially.
+    pub async fn get_all_tick_data(
         &self,
         pool_address: Address,
-        chain_id: i64,
-    ) -> Result<PoolData> {
+        tick_lower: i32,
+        tick_upper: i32,
+        tick_spacing: i32,
+    ) -> Result<Vec<TickData>> {
+        let ticks = price::spaced_ticks(tick_lower, tick_upper, tick_spacing)?;
+        if ticks.len() > MAX_TICKS_PER_QUERY {
+            bail!("{} ticks between {} and {}, at most {} can be read at once",
+                  ticks.len(), tick_lower, tick_upper, MAX_TICKS_PER_QUERY);
+        }
+
+        let mut tick_data = Vec::with_capacity(ticks.len());
+        for tick in ticks {
+            tick_data.push(self.get_tick_data(pool_address, tick).await?);
+        }
+        Ok(tick_data)
+    }
+
+    /// Reads `owner`'s position over `[tick_lower, tick_upper)` from the pool; a position
+    /// that was never opened comes back zeroed.
+    pub async fn get_position_info(
+        &self,
+        pool_address: Address,
+        owner: Address,
+        tick_lower: i32,
+        tick_upper: i32,
+    ) -> Result<PositionInfo> {
         let contract = Contract::new(pool_address, self.pool_abi.clone(), self.provider.clone());
+        let key = position::position_key(owner, tick_lower, tick_upper);
+        let (liquidity, fee_growth_inside_0_last, fee_growth_inside_1_last, tokens_owed_0, tokens_owed_1): (
+            u128,
+            U256,
+            U256,
+            u128,
+            u128,
+        ) = contract.method("positions", key)?.call().await?;
 
-        let token0: Address = contract.method("token0", ())?.call().await?;
-        let token1: Address = contract.method("token1", ())?.call().await?;
-        let fee: u32 = contract.method("fee", ())?.call().await?;
-        let tick_spacing: i32 = contract.method("tickSpacing", ())?.call().await?;
-        let liquidity: u128 = contract.method("liquidity", ())?.call().await?;
-        let slot0: (U256, i32, u16, u16, u16, u8, bool) =
-            contract.method("slot0", ())?.call().await?;
-        let sqrt_price_x96 = slot0.0;
-        let tick = slot0.1;
-
-        let (token0_symbol, token0_decimals) = self.get_token_metadata(token0).await?;
-        let (token1_symbol, token1_decimals) = self.get_token_metadata(token1).await?;
-
-        Ok(PoolData {
-            pool_address: format!("{:?}", pool_address),
-            token0_address: format!("{:?}", token0),
-            token1_address: format!("{:?}", token1),
-            token0_symbol,
-            token1_symbol,
-            token0_decimals: Some(token0_decimals as i32),
-            token1_decimals: Some(token1_decimals as i32),
-            fee_tier: Some(fee as i32),
-            tick_spacing: Some(tick_spacing),
-            liquidity: Some(liquidity as i64),
-            sqrt_price_x96: Some(format!("{:?}", sqrt_price_x96)),
-            tick: Some(tick),
-            chain_id,
-            dex_name: "moonshot".to_string(),
+        Ok(PositionInfo {
+            tick_lower,
+            tick_upper,
+            liquidity,
+            tokens_owed_0,
+            tokens_owed_1,
+            fee_growth_inside_0_last,
+            fee_growth_inside_1_last,
         })
     }
+
+    /// Fees the position could collect now, per token, from the pool's current fee growth
+    /// and the position's boundary ticks. `pool` must have its current tick.
+    pub async fn estimate_uncollected_fees(&self, position: &PositionInfo, pool: &PoolData) -> Result<(u128, u128)> {
+        let tick_current = match pool.tick {
+            Some(tick) => tick,
+            None => bail!("pool {} has no current tick yet", pool.pool_address),
+        };
+        let pool_address = Address::from(pool.canonical_address()?);
+        let contract = Contract::new(pool_address, self.pool_abi.clone(), self.provider.clone());
+
+        let fee_growth_global_0: U256 = contract.method("feeGrowthGlobal0X128", ())?.call().await?;
+        let fee_growth_global_1: U256 = contract.method("feeGrowthGlobal1X128", ())?.call().await?;
+        let lower = self.get_tick_data(pool_address, position.tick_lower).await?;
+        let upper = self.get_tick_data(pool_address, position.tick_upper).await?;
+
+        Ok(position::uncollected_fees(
+            position,
+            tick_current,
+            (fee_growth_global_0, fee_growth_global_1),
+            &lower,
+            &upper,
+        ))
+    }
+
+    /// The pool `factory` deployed for the token pair and fee; the zero address if none.
+    pub async fn factory_pool(&self, factory: Address, token0: Address, token1: Address, fee: u32) -> Result<Address> {
+        let contract = Contract::new(factory, get_factory_abi(), self.provider.clone());
+        Ok(contract.method::<_, Address>("getPool", (token0, token1, fee))?.call().await?)
+    }
+
+    /// Refreshes a pool's state. Calls that fail keep the `previous` value rather than
+    /// failing the refresh; token metadata is only fetched while still unknown.
+    pub async fn update_pool_state(&self, previous: &PoolData) -> Result<PoolStateUpdate> {
+        let mut update = pool_state::refresh(self, previous).await?;
+        if update.all_failed {
+            return Ok(update);
+        }
+
+        let pool = &mut update.pool;
+        let mut unknown = Vec::new();
+        if pool.token0_symbol.is_none() {
+            unknown.push(Address::from(pool.token0_canonical()?));
+        }
+        if pool.token1_symbol.is_none() {
+            unknown.push(Address::from(pool.token1_canonical()?));
+        }
+        let mut metadata = self.batch_get_token_metadata(&unknown).await?.into_iter();
+        if pool.token0_symbol.is_none() {
+            let (symbol, decimals) = metadata.next().unwrap_or((None, 18));
+            pool.token0_symbol = symbol;
+            pool.token0_decimals = Some(decimals as i32);
+        }
+        if pool.token1_symbol.is_none() {
+            let (symbol, decimals) = metadata.next().unwrap_or((None, 18));
+            pool.token1_symbol = symbol;
+            pool.token1_decimals = Some(decimals as i32);
+        }
+
+        Ok(update)
+    }
+}
+
+impl<P: PubsubClient + 'static> MoonshotHandler<P> {
+    /// Subscribes to `PoolCreated` logs from the factory; each item is decoded and has its
+    /// token metadata fetched. The stream ends when the WebSocket subscription closes.
+    pub async fn subscribe_pool_created(
+        &self,
+        factory_address: Address,
+        chain_id: i64,
+    ) -> Result<impl Stream<Item = Result<PoolData>> + '_> {
+        let filter = Filter::new().address(factory_address).topic0(self.decoder.pool_created_topic());
+
+        let logs = self.provider.subscribe_logs(&filter).await?;
+
+        Ok(logs.then(move |log| async move { self.handle_pool_created(log, chain_id).await }))
+    }
+}
+
+async fn call_pool<P: JsonRpcClient, T: Detokenize>(contract: &Contract<Provider<P>>, name: &str) -> CallResult<T> {
+    let call = contract.method::<_, T>(name, ()).map_err(|_| CallErrorKind::MissingContract)?;
+    call.call().await.map_err(|e| CallErrorKind::classify(&e))
+}
+
+#[async_trait]
+impl<P: JsonRpcClient + 'static> PoolStateReader for MoonshotHandler<P> {
+    async fn read_pool_state(&self, pool_address: Address) -> PoolStateReads {
+        let contract = Contract::new(pool_address, self.pool_abi.clone(), self.provider.clone());
+        let slot0: CallResult<(U256, i32, u16, u16, u16, u8, bool)> = call_pool(&contract, "slot0").await;
+
+        PoolStateReads {
+            token0: call_pool(&contract, "token0").await,
+            token1: call_pool(&contract, "token1").await,
+            fee: call_pool(&contract, "fee").await,
+            tick_spacing: call_pool(&contract, "tickSpacing").await,
+            liquidity: call_pool(&contract, "liquidity").await,
+            slot0: slot0.map(|slot0| (slot0.0, slot0.1)),
+        }
+    }
+}
+
+/// Reads supplies through Multicall3 at its canonical address, one request per batch.
+#[async_trait]
+impl<P: JsonRpcClient + 'static> SupplySource for MoonshotHandler<P> {
+    async fn total_supplies(&self, tokens: &[Address]) -> Result<Vec<Option<U256>>> {
+        let mut multicall = Multicall::new(self.provider.clone(), Some(MULTICALL_ADDRESS)).await?;
+
+        for token in tokens {
+            let contract = Contract::new(*token, self.erc20_abi.clone(), self.provider.clone());
+            multicall.add_call(contract.method::<_, U256>("totalSupply", ())?, true);
+        }
+
+        Ok(multicall
+            .call_raw()
+            .await?
+            .into_iter()
+            .map(|result| result.ok().and_then(|token| token.into_uint()))
+            .collect())
+    }
+}
+
+/// Reads candidate balances through Multicall3; candidates whose call fails are left out.
+#[async_trait]
+impl<P: JsonRpcClient + 'static> HolderSource for MoonshotHandler<P> {
+    async fn holder_balances(&self, token: Address, candidates: &[Address]) -> Result<Vec<(Address, U256)>> {
+        let mut multicall = Multicall::new(self.provider.clone(), Some(MULTICALL_ADDRESS)).await?;
+        let contract = Contract::new(token, self.erc20_abi.clone(), self.provider.clone());
+
+        for candidate in candidates {
+            multicall.add_call(contract.method::<_, U256>("balanceOf", *candidate)?, true);
+        }
+
+        Ok(candidates
+            .iter()
+            .zip(multicall.call_raw().await?)
+            .filter_map(|(candidate, result)| Some((*candidate, result.ok()?.into_uint()?)))
+            .collect())
+    }
 }
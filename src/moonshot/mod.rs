@@ -1,5 +1,14 @@
+/// `dex_name` of the pools and stats rows this crate writes.
+pub const DEX_NAME: &str = "moonshot";
+
 pub mod abi;
+pub mod curve;
+pub mod decode;
+pub mod generic_v3;
 pub mod handler;
 
+pub use curve::{CurveEvent, CurveHandler};
+pub use decode::LogDecoder;
+pub use generic_v3::{EventSchemaDescriptor, GenericV3Schema};
 pub use handler::MoonshotHandler;
-pub use abi::{get_factory_abi, get_pool_abi, get_erc20_abi};
+pub use abi::{get_curve_abi, get_factory_abi, get_pool_abi, get_erc20_abi};
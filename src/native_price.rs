@@ -0,0 +1,201 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::price;
+use crate::types::{normalize_address, PoolData};
+
+// How often the deepest wrapped-native/stablecoin pool is chosen again
+const POOL_SELECTION_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// A USD price of the native token and where it was read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NativePrice {
+    pub price_usd: f64,
+    pub pool_address: String,
+    /// Block whose pool state the price was read from.
+    pub block_number: i64,
+    pub timestamp: i64,
+}
+
+/// The deepest pool pairing `wrapped_native` with one of `stablecoins` whose state is known
+/// well enough to price from: liquidity, tick and both decimals. None if there is none.
+pub fn select_pool<'a>(candidates: &'a [PoolData], wrapped_native: &str, stablecoins: &[String]) -> Option<&'a PoolData> {
+    let is_stablecoin = |token: &str| stablecoins.iter().any(|stablecoin| stablecoin.eq_ignore_ascii_case(token));
+    candidates
+        .iter()
+        .filter(|pool| {
+            (pool.token0_address.eq_ignore_ascii_case(wrapped_native) && is_stablecoin(&pool.token1_address))
+                || (pool.token1_address.eq_ignore_ascii_case(wrapped_native) && is_stablecoin(&pool.token0_address))
+        })
+        .filter(|pool| pool.tick.is_some() && pool.token0_decimals.is_some() && pool.token1_decimals.is_some())
+        .filter(|pool| pool.liquidity.is_some_and(|liquidity| liquidity > 0))
+        .max_by_key(|pool| pool.liquidity)
+}
+
+/// USD price of `wrapped_native` from a wrapped-native/stablecoin pool's current tick,
+/// taking the stablecoin at one dollar.
+pub fn pool_native_price(pool: &PoolData, wrapped_native: &str) -> Option<f64> {
+    let token0_price = price::tick_to_price(pool.tick?, pool.token0_decimals?, pool.token1_decimals?);
+    let price_usd = match pool.token0_address.eq_ignore_ascii_case(wrapped_native) {
+        true => token0_price,
+        false => 1.0 / token0_price,
+    };
+    (price_usd.is_finite() && price_usd > 0.0).then_some(price_usd)
+}
+
+/// Keeps the native token's USD price, the root every other USD amount is derived from.
+///
+/// The price comes from the deepest wrapped-native/stablecoin pool among the indexed
+/// pools, chosen again every hour, and each reading is recorded in `native_prices`. Without
+/// a usable pool the price is unavailable; no stale or fixed price stands in for it.
+pub struct NativePriceTracker {
+    chain_id: i64,
+    wrapped_native: String,
+    stablecoins: Vec<String>,
+    reselect_every: Duration,
+    selected: Option<(String, Instant)>,
+    latest: Option<NativePrice>,
+}
+
+impl NativePriceTracker {
+    pub fn new(chain_id: i64, wrapped_native: &str, stablecoins: &[String], reselect_every: Duration) -> Self {
+        Self {
+            chain_id,
+            wrapped_native: normalize_address(wrapped_native),
+            stablecoins: stablecoins.iter().map(|stablecoin| normalize_address(stablecoin)).collect(),
+            reselect_every,
+            selected: None,
+            latest: None,
+        }
+    }
+
+    /// None unless `NATIVE_WRAPPED_TOKEN` is set.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let wrapped_native = config.native_wrapped_token.as_deref()?;
+        Some(Self::new(config.chain_id as i64, wrapped_native, &config.native_stablecoins, POOL_SELECTION_INTERVAL))
+    }
+
+    pub fn wrapped_native(&self) -> &str {
+        &self.wrapped_native
+    }
+
+    /// The latest reading, None while no pool can price the native token.
+    pub fn native_usd_price(&self) -> Option<&NativePrice> {
+        self.latest.as_ref()
+    }
+
+    /// Tokens with a known USD price to route other prices to: the stablecoins at one dollar
+    /// and the native token while it has a price.
+    pub fn usd_anchors(&self) -> HashMap<String, f64> {
+        let mut anchors: HashMap<String, f64> = self.stablecoins.iter().map(|stablecoin| (stablecoin.clone(), 1.0)).collect();
+        if let Some(latest) = &self.latest {
+            anchors.insert(self.wrapped_native.clone(), latest.price_usd);
+        }
+        anchors
+    }
+
+    /// Reads the price from the selected pool as of `block_number`, choosing the pool again
+    /// when the choice is due or the pool can no longer price, and records the reading.
+    pub async fn refresh(&mut self, database: &Database, block_number: i64, timestamp: i64) -> Result<Option<&NativePrice>> {
+        let mut pool = match &self.selected {
+            Some((pool_address, selected_at)) if selected_at.elapsed() < self.reselect_every => {
+                database.get_pool(pool_address).await?
+            }
+            _ => None,
+        };
+        if pool.as_ref().and_then(|pool| pool_native_price(pool, &self.wrapped_native)).is_none() {
+            pool = self.select(database).await?;
+        }
+
+        let reading = pool.and_then(|pool| {
+            let price_usd = pool_native_price(&pool, &self.wrapped_native)?;
+            Some(NativePrice { price_usd, pool_address: pool.pool_address, block_number, timestamp })
+        });
+        if let Some(reading) = &reading {
+            database.insert_native_price(self.chain_id, reading).await?;
+            debug!("Native token at ${:.2} from pool {} at block {}", reading.price_usd, reading.pool_address, block_number);
+        }
+        self.latest = reading;
+        Ok(self.latest.as_ref())
+    }
+
+    async fn select(&mut self, database: &Database) -> Result<Option<PoolData>> {
+        let mut candidates = Vec::new();
+        for stablecoin in &self.stablecoins {
+            candidates.extend(database.get_pools_by_tokens(&self.wrapped_native, stablecoin).await?);
+        }
+        candidates.retain(|pool| pool.chain_id == self.chain_id);
+
+        let pool = select_pool(&candidates, &self.wrapped_native, &self.stablecoins).cloned();
+        let pool_address = pool.as_ref().map(|pool| pool.pool_address.clone());
+        if pool_address != self.selected.as_ref().map(|(pool_address, _)| pool_address.clone()) {
+            match &pool_address {
+                Some(pool_address) => info!("Pricing the native token from pool {}", pool_address),
+                None => warn!("No pool of {} and a stablecoin can price the native token", self.wrapped_native),
+            }
+        }
+        self.selected = pool_address.map(|pool_address| (pool_address, Instant::now()));
+        Ok(pool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::MOONSHOT;
+
+    const WETH: &str = "0x00000000000000000000000000000000000000e0";
+    const USDC: &str = "0x00000000000000000000000000000000000000c0";
+    const USDT: &str = "0x00000000000000000000000000000000000000c1";
+
+    fn pool(pool_address: &str, token0: &str, token1: &str, liquidity: Option<i64>) -> PoolData {
+        let mut pool = PoolData::new(pool_address.to_string(), token0.to_string(), token1.to_string(), 8453, MOONSHOT);
+        pool.liquidity = liquidity;
+        pool.tick = Some(0);
+        pool.token0_decimals = Some(18);
+        pool.token1_decimals = Some(6);
+        pool
+    }
+
+    #[test]
+    fn test_selects_deepest_stablecoin_pool() {
+        let stablecoins = vec![USDC.to_string(), USDT.to_string()];
+        let mut unpriceable = pool("0xd0", WETH, USDC, Some(9_000));
+        unpriceable.token1_decimals = None;
+        let candidates = vec![
+            pool("0xa0", WETH, USDC, Some(1_000)),
+            pool("0xa1", USDT, WETH, Some(5_000)),
+            // Deeper, but not a stablecoin pair or not priceable
+            pool("0xb0", WETH, "0x00000000000000000000000000000000000000ff", Some(50_000)),
+            unpriceable,
+            pool("0xc0", WETH, USDC, None),
+        ];
+
+        let selected = select_pool(&candidates, WETH, &stablecoins).unwrap();
+        assert_eq!(selected.pool_address, "0xa1");
+        assert!(select_pool(&candidates[2..], WETH, &stablecoins).is_none());
+    }
+
+    #[test]
+    fn test_price_either_side_of_the_pool() {
+        // 18-decimal WETH against 6-decimal USDC at $2,000
+        let tick = price::price_to_tick(2_000.0, 18, 6).unwrap();
+        let mut weth_usdc = pool("0xa0", WETH, USDC, Some(1_000));
+        weth_usdc.tick = Some(tick);
+        let price_usd = pool_native_price(&weth_usdc, WETH).unwrap();
+        assert!((price_usd - 2_000.0).abs() < 0.5, "{}", price_usd);
+
+        let mut usdc_weth = pool("0xa1", USDC, WETH, Some(1_000));
+        (usdc_weth.token0_decimals, usdc_weth.token1_decimals) = (Some(6), Some(18));
+        usdc_weth.tick = Some(price::price_to_tick(1.0 / 2_000.0, 6, 18).unwrap());
+        let price_usd = pool_native_price(&usdc_weth, WETH).unwrap();
+        assert!((price_usd - 2_000.0).abs() < 0.5, "{}", price_usd);
+
+        weth_usdc.tick = None;
+        assert_eq!(pool_native_price(&weth_usdc, WETH), None);
+    }
+}
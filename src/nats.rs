@@ -0,0 +1,247 @@
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use serde_json::Value;
+
+#[cfg(feature = "nats")]
+use crate::config::Config;
+use crate::replay::Sink;
+use crate::types::normalize_address;
+
+/// First token of every subject the sink publishes to.
+pub const SUBJECT_PREFIX: &str = "moonshot";
+
+/// Headers carrying an event's position, for consumers that drop duplicates themselves.
+pub const BLOCK_NUMBER_HEADER: &str = "Moonshot-Block-Number";
+pub const LOG_INDEX_HEADER: &str = "Moonshot-Log-Index";
+/// JetStream drops a message whose id it saw within the stream's duplicate window.
+pub const MESSAGE_ID_HEADER: &str = "Nats-Msg-Id";
+
+// Publishes awaiting their acknowledgement before the sink waits for them
+const MAX_PENDING_ACKS: usize = 256;
+
+/// An event as published: subject, headers and JSON body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NatsMessage {
+    pub subject: String,
+    pub headers: Vec<(&'static str, String)>,
+    pub payload: Vec<u8>,
+}
+
+/// Subject of a replay payload: `moonshot.<chain_id>.swaps.<pool_address>` for swaps,
+/// `moonshot.<chain_id>.pools` for pool creations, `curve_trades` and `migrations` likewise.
+pub fn subject(chain_id: i64, payload: &Value) -> Result<String> {
+    let kind = payload["type"].as_str().ok_or_else(|| anyhow!("payload has no event type"))?;
+    let subject = match kind {
+        "swap" => {
+            let pool_address = payload["event"]["pool_address"].as_str().ok_or_else(|| anyhow!("swap has no pool address"))?;
+            let pool_address = normalize_address(pool_address);
+            // Subject tokens are separated by dots and may not hold wildcards or whitespace
+            if pool_address.contains(['.', '*', '>']) || pool_address.contains(char::is_whitespace) {
+                bail!("pool address {:?} cannot be a subject token", pool_address);
+            }
+            format!("{}.{}.swaps.{}", SUBJECT_PREFIX, chain_id, pool_address)
+        }
+        "pool_created" => format!("{}.{}.pools", SUBJECT_PREFIX, chain_id),
+        "curve_trade" => format!("{}.{}.curve_trades", SUBJECT_PREFIX, chain_id),
+        "migration" => format!("{}.{}.migrations", SUBJECT_PREFIX, chain_id),
+        other => bail!("no subject for {} events", other),
+    };
+    Ok(subject)
+}
+
+/// The message for a replay payload. Events with a log carry its block and index, and a
+/// message id unique to the event so JetStream drops redeliveries.
+pub fn message(chain_id: i64, payload: &Value) -> Result<NatsMessage> {
+    let subject = subject(chain_id, payload)?;
+    let event = &payload["event"];
+    let block_number = event["block_number"].as_i64().or_else(|| event["created_at_block"].as_i64());
+    let log_index = event["log_index"].as_i64();
+
+    let mut headers = Vec::new();
+    if let Some(block_number) = block_number {
+        headers.push((BLOCK_NUMBER_HEADER, block_number.to_string()));
+    }
+    if let Some(log_index) = log_index {
+        headers.push((LOG_INDEX_HEADER, log_index.to_string()));
+    }
+    let kind = payload["type"].as_str().unwrap_or_default();
+    let message_id = match (block_number, log_index) {
+        (Some(block_number), Some(log_index)) => Some(format!("{}:{}:{}:{}", chain_id, block_number, log_index, kind)),
+        // Pool creations are stored without their log; a pool is created once
+        _ => event["pool_address"].as_str().map(|pool_address| format!("{}:pool:{}", chain_id, normalize_address(pool_address))),
+    };
+    if let Some(message_id) = message_id {
+        headers.push((MESSAGE_ID_HEADER, message_id));
+    }
+
+    Ok(NatsMessage { subject, headers, payload: serde_json::to_vec(payload)? })
+}
+
+/// A JetStream context, or a stand-in for one in tests.
+#[async_trait]
+pub trait JetStreamPublisher: Send + Sync {
+    /// Sends the message; the returned future resolves once the stream acknowledged it.
+    async fn publish(&self, message: NatsMessage) -> Result<BoxFuture<'static, Result<()>>>;
+}
+
+/// Publishes replayed events to JetStream. An event counts as delivered once its publish
+/// is acknowledged, which `flush` waits for, so `SinkOffsetStore` only moves past acknowledged
+/// events and a crash redelivers at most `ack_every` of them.
+pub struct NatsSink<J> {
+    publisher: J,
+    chain_id: i64,
+    pending: Vec<BoxFuture<'static, Result<()>>>,
+}
+
+impl<J: JetStreamPublisher> NatsSink<J> {
+    pub fn new(publisher: J, chain_id: i64) -> Self {
+        Self { publisher, chain_id, pending: Vec::new() }
+    }
+
+    async fn await_acks(&mut self) -> Result<()> {
+        for ack in self.pending.drain(..) {
+            ack.await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<J: JetStreamPublisher> Sink for NatsSink<J> {
+    async fn send(&mut self, payload: &Value) -> Result<()> {
+        if self.pending.len() >= MAX_PENDING_ACKS {
+            self.await_acks().await?;
+        }
+        let ack = self.publisher.publish(message(self.chain_id, payload)?).await?;
+        self.pending.push(ack);
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.await_acks().await
+    }
+}
+
+#[cfg(feature = "nats")]
+#[async_trait]
+impl JetStreamPublisher for async_nats::jetstream::Context {
+    async fn publish(&self, message: NatsMessage) -> Result<BoxFuture<'static, Result<()>>> {
+        let mut headers = async_nats::HeaderMap::new();
+        for (name, value) in message.headers {
+            headers.insert(name, value);
+        }
+        let ack = self.publish_with_headers(message.subject, headers, message.payload.into()).await?;
+        Ok(Box::pin(async move {
+            ack.await?;
+            Ok(())
+        }))
+    }
+}
+
+/// Connects to `NATS_URL` with the configured credentials and TLS. The stream capturing
+/// `moonshot.>` must exist already.
+#[cfg(feature = "nats")]
+pub async fn connect(config: &Config) -> Result<async_nats::jetstream::Context> {
+    let url = config.nats_url.as_deref().ok_or_else(|| anyhow!("the nats sink needs NATS_URL"))?;
+    let mut options = async_nats::ConnectOptions::new().require_tls(config.nats_tls_required);
+    if let Some(path) = &config.nats_credentials_file {
+        options = options.credentials_file(path).await?;
+    }
+    if let Some(path) = &config.nats_tls_ca_file {
+        options = options.add_root_certificates(path.into());
+    }
+    Ok(async_nats::jetstream::new(options.connect(url).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::MOONSHOT;
+    use crate::replay::payload;
+    use crate::types::{PoolData, SwapEvent, TokenEvent};
+    use std::sync::{Arc, Mutex};
+
+    fn swap() -> SwapEvent {
+        SwapEvent::new(
+            "0x01".to_string(),
+            "0x00000000000000000000000000000000000000AA".to_string(),
+            "token0".to_string(),
+            "token1".to_string(),
+            1000,
+            950,
+            1640995200,
+            100,
+            7,
+            2741,
+        )
+    }
+
+    #[test]
+    fn test_subjects_and_headers() {
+        let swap_message = message(2741, &payload(&TokenEvent::Swap(swap())).unwrap()).unwrap();
+        assert_eq!(swap_message.subject, "moonshot.2741.swaps.0x00000000000000000000000000000000000000aa");
+        assert_eq!(
+            swap_message.headers,
+            vec![
+                (BLOCK_NUMBER_HEADER, "100".to_string()),
+                (LOG_INDEX_HEADER, "7".to_string()),
+                (MESSAGE_ID_HEADER, "2741:100:7:swap".to_string()),
+            ]
+        );
+        assert_eq!(serde_json::from_slice::<Value>(&swap_message.payload).unwrap()["event"]["amount_in"], 1000);
+
+        let mut pool = PoolData::new("0xPOOL".to_string(), "0xa".to_string(), "0xb".to_string(), 2741, MOONSHOT);
+        pool.created_at_block = Some(90);
+        let pool_message = message(2741, &payload(&TokenEvent::PoolCreated(pool)).unwrap()).unwrap();
+        assert_eq!(pool_message.subject, "moonshot.2741.pools");
+        assert_eq!(
+            pool_message.headers,
+            vec![(BLOCK_NUMBER_HEADER, "90".to_string()), (MESSAGE_ID_HEADER, "2741:pool:0xpool".to_string())]
+        );
+
+        let mut wildcard = swap();
+        wildcard.pool_address = "0xaa.>".to_string();
+        assert!(subject(2741, &payload(&TokenEvent::Swap(wildcard)).unwrap()).is_err());
+    }
+
+    // Records publishes; acknowledges all but the subjects it is told to reject
+    #[derive(Clone, Default)]
+    struct MockJetStream {
+        published: Arc<Mutex<Vec<String>>>,
+        rejected: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl JetStreamPublisher for MockJetStream {
+        async fn publish(&self, message: NatsMessage) -> Result<BoxFuture<'static, Result<()>>> {
+            self.published.lock().unwrap().push(message.subject.clone());
+            let rejected = self.rejected.lock().unwrap().contains(&message.subject);
+            Ok(Box::pin(async move {
+                if rejected {
+                    bail!("no stream for {}", message.subject);
+                }
+                Ok(())
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flush_waits_for_every_ack() {
+        let jetstream = MockJetStream::default();
+        let mut sink = NatsSink::new(jetstream.clone(), 2741);
+
+        sink.send(&payload(&TokenEvent::Swap(swap())).unwrap()).await.unwrap();
+        sink.flush().await.unwrap();
+        assert_eq!(jetstream.published.lock().unwrap().len(), 1);
+
+        // A rejected publish only surfaces once its ack is awaited
+        jetstream.rejected.lock().unwrap().push("moonshot.2741.pools".to_string());
+        let pool = PoolData::new("0xpool".to_string(), "0xa".to_string(), "0xb".to_string(), 2741, MOONSHOT);
+        sink.send(&payload(&TokenEvent::PoolCreated(pool)).unwrap()).await.unwrap();
+        sink.send(&payload(&TokenEvent::Swap(swap())).unwrap()).await.unwrap();
+        assert!(sink.flush().await.unwrap_err().to_string().contains("no stream"));
+        assert_eq!(jetstream.published.lock().unwrap().len(), 3);
+        sink.flush().await.unwrap();
+    }
+}
@@ -0,0 +1,222 @@
+use ethers::types::{Address, Log, H256, I256, U256};
+use ethers::utils::keccak256;
+
+use crate::moonshot::get_pool_abi;
+
+// Share-based rebasing tokens round each transfer by a wei or two
+const REBASE_ROUNDING_WEI: u64 = 2;
+
+/// Ways a token's transfers can deviate from the amounts a pool accounts for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenBehavior {
+    /// Transfers deliver less than the nominal amount.
+    FeeOnTransfer,
+    /// Delivered amounts drift from the nominal amount without a fee, as balances rebase.
+    Rebasing,
+}
+
+struct Transfer {
+    token: Address,
+    from: Address,
+    to: Address,
+    value: U256,
+}
+
+fn transfer_topic() -> H256 {
+    H256::from(keccak256("Transfer(address,address,uint256)"))
+}
+
+fn topic_address(topic: &H256) -> Address {
+    Address::from_slice(&topic.as_bytes()[12..])
+}
+
+fn decode_transfer(log: &Log) -> Option<Transfer> {
+    if log.topics.len() != 3 || log.topics[0] != transfer_topic() || log.data.len() != 32 {
+        return None;
+    }
+
+    Some(Transfer {
+        token: log.address,
+        from: topic_address(&log.topics[1]),
+        to: topic_address(&log.topics[2]),
+        value: U256::from_big_endian(&log.data),
+    })
+}
+
+/// Compares every swap of `pool` in a transaction receipt against the ERC20 `Transfer`
+/// logs around it and returns the tokens whose transfers did not match the swap amounts.
+///
+/// Tokens paid in are checked against everything transferred to the pool; tokens paid
+/// out against what the pool transferred to the swap recipient. Sides with no matching
+/// transfers at all are skipped, since routers and callbacks can move funds in ways the
+/// receipt alone does not show.
+pub fn detect_in_receipt(pool: Address, token0: Address, token1: Address, logs: &[Log]) -> Vec<(Address, TokenBehavior)> {
+    let swap_topic = match get_pool_abi().event("Swap") {
+        Ok(event) => event.signature(),
+        Err(_) => return Vec::new(),
+    };
+    let transfers: Vec<Transfer> = logs.iter().filter_map(decode_transfer).collect();
+    let mut detected = Vec::new();
+
+    for log in logs {
+        if log.address != pool || log.topics.len() != 3 || log.topics[0] != swap_topic || log.data.len() < 64 {
+            continue;
+        }
+        let recipient = topic_address(&log.topics[2]);
+        let amount0 = I256::from_raw(U256::from_big_endian(&log.data[0..32]));
+        let amount1 = I256::from_raw(U256::from_big_endian(&log.data[32..64]));
+
+        for (token, amount) in [(token0, amount0), (token1, amount1)] {
+            let delivered: U256 = if amount.is_positive() {
+                transfers
+                    .iter()
+                    .filter(|t| t.token == token && t.to == pool)
+                    .fold(U256::zero(), |sum, t| sum + t.value)
+            } else if amount.is_negative() {
+                transfers
+                    .iter()
+                    .filter(|t| t.token == token && t.from == pool && t.to == recipient)
+                    .fold(U256::zero(), |sum, t| sum + t.value)
+            } else {
+                continue;
+            };
+
+            if delivered.is_zero() {
+                continue;
+            }
+            if let Some(behavior) = classify(amount.unsigned_abs(), delivered) {
+                if !detected.contains(&(token, behavior)) {
+                    detected.push((token, behavior));
+                }
+            }
+        }
+    }
+
+    detected
+}
+
+fn classify(expected: U256, delivered: U256) -> Option<TokenBehavior> {
+    if delivered == expected {
+        None
+    } else if delivered < expected && expected - delivered > U256::from(REBASE_ROUNDING_WEI) {
+        Some(TokenBehavior::FeeOnTransfer)
+    } else {
+        Some(TokenBehavior::Rebasing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::{encode, Token};
+
+    fn address(n: u64) -> Address {
+        Address::from_low_u64_be(n)
+    }
+
+    fn topic(address: Address) -> H256 {
+        H256::from(address)
+    }
+
+    const POOL: u64 = 0x1000;
+    const TOKEN0: u64 = 0xa0;
+    const TOKEN1: u64 = 0xa1;
+    const TRADER: u64 = 0xbeef;
+    const FEE_COLLECTOR: u64 = 0xfee;
+
+    fn swap_log(amount0: i64, amount1: i64) -> Log {
+        let data = encode(&[
+            Token::Int(I256::from(amount0).into_raw()),
+            Token::Int(I256::from(amount1).into_raw()),
+            Token::Uint(U256::one() << 96),
+            Token::Uint(U256::from(1_000_000)),
+            Token::Int(U256::zero()),
+        ]);
+
+        Log {
+            address: address(POOL),
+            topics: vec![
+                get_pool_abi().event("Swap").unwrap().signature(),
+                topic(address(TRADER)),
+                topic(address(TRADER)),
+            ],
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    fn transfer_log(token: u64, from: u64, to: u64, value: u64) -> Log {
+        Log {
+            address: address(token),
+            topics: vec![transfer_topic(), topic(address(from)), topic(address(to))],
+            data: encode(&[Token::Uint(U256::from(value))]).into(),
+            ..Default::default()
+        }
+    }
+
+    fn detect(logs: &[Log]) -> Vec<(Address, TokenBehavior)> {
+        detect_in_receipt(address(POOL), address(TOKEN0), address(TOKEN1), logs)
+    }
+
+    #[test]
+    fn test_standard_tokens_are_not_flagged() {
+        let receipt = [
+            transfer_log(TOKEN0, TRADER, POOL, 1_000),
+            transfer_log(TOKEN1, POOL, TRADER, 950),
+            swap_log(1_000, -950),
+        ];
+
+        assert!(detect(&receipt).is_empty());
+    }
+
+    #[test]
+    fn test_fee_on_output_token() {
+        // The pool pays 950 but the token skims 5% on the way to the trader
+        let receipt = [
+            transfer_log(TOKEN0, TRADER, POOL, 1_000),
+            transfer_log(TOKEN1, POOL, TRADER, 903),
+            transfer_log(TOKEN1, POOL, FEE_COLLECTOR, 47),
+            swap_log(1_000, -950),
+        ];
+
+        assert_eq!(detect(&receipt), vec![(address(TOKEN1), TokenBehavior::FeeOnTransfer)]);
+    }
+
+    #[test]
+    fn test_fee_on_input_token() {
+        let receipt = [
+            transfer_log(TOKEN0, TRADER, POOL, 990),
+            transfer_log(TOKEN0, TRADER, FEE_COLLECTOR, 10),
+            transfer_log(TOKEN1, POOL, TRADER, 950),
+            swap_log(1_000, -950),
+        ];
+
+        assert_eq!(detect(&receipt), vec![(address(TOKEN0), TokenBehavior::FeeOnTransfer)]);
+    }
+
+    #[test]
+    fn test_rounding_drift_is_rebasing() {
+        let receipt = [
+            transfer_log(TOKEN0, TRADER, POOL, 1_000),
+            transfer_log(TOKEN1, POOL, TRADER, 949),
+            swap_log(1_000, -950),
+        ];
+
+        assert_eq!(detect(&receipt), vec![(address(TOKEN1), TokenBehavior::Rebasing)]);
+    }
+
+    #[test]
+    fn test_other_pools_and_missing_transfers_are_ignored() {
+        let mut other_pool_swap = swap_log(1_000, -950);
+        other_pool_swap.address = address(0x2000);
+
+        let receipt = [
+            transfer_log(TOKEN1, POOL, TRADER, 900),
+            other_pool_swap,
+            // No token0 transfer to the pool at all, e.g. paid through a callback
+            swap_log(1_000, -950),
+        ];
+
+        assert_eq!(detect(&receipt), vec![(address(TOKEN1), TokenBehavior::FeeOnTransfer)]);
+    }
+}
@@ -0,0 +1,222 @@
+use ethers::types::{U256, U512};
+
+use crate::config::Config;
+use crate::types::{PoolBalanceSnapshot, PoolData};
+
+#[derive(Debug, Clone, Copy)]
+pub struct BalanceDropPolicy {
+    /// Loss of a token balance, in percent of its recent peak, that raises an alert.
+    pub drop_pct: f64,
+    /// How far back the peak is looked for, in blocks.
+    pub window_blocks: i64,
+}
+
+impl BalanceDropPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            drop_pct: config.balance_drop_alert_pct,
+            window_blocks: (config.balance_drop_window_secs as f64 / config.seconds_per_block).ceil().max(1.0) as i64,
+        }
+    }
+}
+
+/// A token balance of a pool that fell more than the policy allows within its window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BalanceDrop {
+    pub block_number: i64,
+    /// 0 for token0, 1 for token1.
+    pub token: usize,
+    /// Largest balance in the window before the drop.
+    pub peak: U256,
+    pub balance: U256,
+    /// `balance_change_pct` from `peak` to `balance`; negative.
+    pub change_pct: f64,
+}
+
+impl BalanceDrop {
+    /// Peak and balance scaled by the token's decimals in `pool` (18 when unknown).
+    pub fn human_amounts(&self, pool: &PoolData) -> (f64, f64) {
+        let decimals = if self.token == 0 { pool.token0_decimals } else { pool.token1_decimals };
+        let scale = 10f64.powi(decimals.unwrap_or(18));
+        // U256 has no lossy float conversion; its decimal string parses into the nearest f64
+        let human = |amount: U256| amount.to_string().parse::<f64>().unwrap_or(f64::MAX) / scale;
+        (human(self.peak), human(self.balance))
+    }
+
+    /// The alert for this drop, in token units with the pool's explorer link when known.
+    pub fn alert_message(&self, pool: &PoolData, window_secs: u64) -> String {
+        let symbol = if self.token == 0 { &pool.token0_symbol } else { &pool.token1_symbol };
+        let symbol = symbol.as_deref().unwrap_or(if self.token == 0 { "token0" } else { "token1" });
+        let (peak, balance) = self.human_amounts(pool);
+        let mut message = format!(
+            "Pool {} lost {:.1}% of its {} balance ({} -> {}) within {}s at block {}",
+            pool, -self.change_pct, symbol, peak, balance, window_secs, self.block_number
+        );
+        if let Some(url) = pool.explorer_address_url() {
+            message.push_str(&format!(": {}", url));
+        }
+        message
+    }
+}
+
+/// Change from `from` to `to` in percent of `from`, to a hundredth of a basis point;
+/// None when `from` is zero.
+pub fn balance_change_pct(from: U256, to: U256) -> Option<f64> {
+    if from.is_zero() {
+        return None;
+    }
+
+    let diff = if to > from { to - from } else { from - to };
+    let millionths = (diff.full_mul(U256::from(1_000_000)) / U512::from(from)).min(U512::from(u128::MAX));
+    let pct = millionths.low_u128() as f64 / 10_000.0;
+    Some(if to < from { -pct } else { pct })
+}
+
+fn balance(snapshot: &PoolBalanceSnapshot, token: usize) -> Option<U256> {
+    let balance = if token == 0 { &snapshot.token0_balance } else { &snapshot.token1_balance };
+    balance.as_deref().and_then(|balance| U256::from_dec_str(balance).ok())
+}
+
+/// Balance drops over consecutive `snapshots` of one pool, oldest first.
+///
+/// A token is drained at a snapshot when its balance is more than `drop_pct` below the
+/// largest balance of the earlier snapshots within `window_blocks`. An alert is raised
+/// when a token becomes drained, so a drop is reported once however many snapshots
+/// follow it; once the window no longer reaches the old peak, a further drop is a new event.
+pub fn detect_balance_drops(snapshots: &[PoolBalanceSnapshot], policy: &BalanceDropPolicy) -> Vec<BalanceDrop> {
+    let mut drops = Vec::new();
+
+    for token in 0..2 {
+        let mut drained = false;
+        for (i, snapshot) in snapshots.iter().enumerate() {
+            let Some(current) = balance(snapshot, token) else { continue };
+            let window_start = snapshot.block_number - policy.window_blocks;
+            let peak = snapshots[..i]
+                .iter()
+                .filter(|earlier| earlier.block_number >= window_start)
+                .filter_map(|earlier| balance(earlier, token))
+                .max();
+
+            let change_pct = peak.and_then(|peak| balance_change_pct(peak, current));
+            let now_drained = change_pct.is_some_and(|change| change < -policy.drop_pct);
+            if now_drained && !drained {
+                drops.push(BalanceDrop {
+                    block_number: snapshot.block_number,
+                    token,
+                    peak: peak.unwrap_or_default(),
+                    balance: current,
+                    change_pct: change_pct.unwrap_or_default(),
+                });
+            }
+            drained = now_drained;
+        }
+    }
+
+    drops.sort_by_key(|drop| (drop.block_number, drop.token));
+    drops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::MOONSHOT;
+
+    const POLICY: BalanceDropPolicy = BalanceDropPolicy { drop_pct: 80.0, window_blocks: 300 };
+
+    fn snapshots(balances: &[(i64, u64, u64)]) -> Vec<PoolBalanceSnapshot> {
+        balances
+            .iter()
+            .map(|(block_number, token0, token1)| PoolBalanceSnapshot {
+                block_number: *block_number,
+                token0_balance: Some(token0.to_string()),
+                token1_balance: Some(token1.to_string()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_balance_change_pct() {
+        assert_eq!(balance_change_pct(U256::from(1_000), U256::from(150)), Some(-85.0));
+        assert_eq!(balance_change_pct(U256::from(1_000), U256::from(1_500)), Some(50.0));
+        assert_eq!(balance_change_pct(U256::zero(), U256::from(1_500)), None);
+    }
+
+    #[test]
+    fn test_drop_is_reported_once() {
+        // token0 is pulled at block 120 and stays low; token1 only drifts
+        let drops = detect_balance_drops(
+            &snapshots(&[(100, 1_000, 500), (110, 900, 480), (120, 100, 470), (130, 90, 460), (140, 50, 450)]),
+            &POLICY,
+        );
+
+        assert_eq!(drops.len(), 1);
+        assert_eq!((drops[0].block_number, drops[0].token), (120, 0));
+        assert_eq!((drops[0].peak, drops[0].balance), (U256::from(1_000), U256::from(100)));
+        assert_eq!(drops[0].change_pct, -90.0);
+    }
+
+    #[test]
+    fn test_threshold_is_exclusive() {
+        assert!(detect_balance_drops(&snapshots(&[(100, 1_000, 1_000), (110, 200, 1_000)]), &POLICY).is_empty());
+        assert_eq!(detect_balance_drops(&snapshots(&[(100, 1_000, 1_000), (110, 199, 1_000)]), &POLICY).len(), 1);
+    }
+
+    #[test]
+    fn test_gradual_drop_within_the_window() {
+        // No single step loses 80%, but the window's peak is 1_000
+        let drops = detect_balance_drops(
+            &snapshots(&[(100, 1_000, 1_000), (150, 500, 1_000), (200, 250, 1_000), (250, 150, 1_000)]),
+            &POLICY,
+        );
+        assert_eq!(drops.iter().map(|drop| drop.block_number).collect::<Vec<_>>(), vec![250]);
+
+        // The same decline spread past the window never is
+        let slow = snapshots(&[(100, 1_000, 1_000), (300, 500, 1_000), (500, 250, 1_000), (700, 150, 1_000)]);
+        assert!(detect_balance_drops(&slow, &POLICY).is_empty());
+    }
+
+    #[test]
+    fn test_refill_and_second_drop_are_separate_events() {
+        let drops = detect_balance_drops(
+            &snapshots(&[(100, 1_000, 1_000), (110, 100, 1_000), (120, 1_000, 1_000), (130, 100, 50)]),
+            &POLICY,
+        );
+
+        let events: Vec<_> = drops.iter().map(|drop| (drop.block_number, drop.token)).collect();
+        assert_eq!(events, vec![(110, 0), (130, 0), (130, 1)]);
+    }
+
+    #[test]
+    fn test_alert_message_uses_token_units() {
+        let mut pool = PoolData::new(
+            "0x00000000000000000000000000000000000000aa".to_string(),
+            "0x00000000000000000000000000000000000000a0".to_string(),
+            "0x00000000000000000000000000000000000000a1".to_string(),
+            8453,
+            MOONSHOT,
+        );
+        (pool.token1_symbol, pool.token1_decimals) = (Some("USDC".to_string()), Some(6));
+        let drop = BalanceDrop {
+            block_number: 120,
+            token: 1,
+            peak: U256::from(2_500_000_000u64),
+            balance: U256::from(250_000_000u64),
+            change_pct: -90.0,
+        };
+
+        assert_eq!(drop.human_amounts(&pool), (2_500.0, 250.0));
+        let message = drop.alert_message(&pool, 3600);
+        assert!(message.contains("lost 90.0% of its USDC balance (2500 -> 250) within 3600s at block 120"));
+        assert!(message.ends_with(": https://basescan.org/address/0x00000000000000000000000000000000000000aa"));
+    }
+
+    #[test]
+    fn test_missing_balances_are_skipped() {
+        let mut sampled = snapshots(&[(100, 1_000, 1_000), (110, 0, 0), (120, 100, 1_000)]);
+        sampled[1].token0_balance = None;
+        sampled[1].token1_balance = None;
+
+        let drops = detect_balance_drops(&sampled, &POLICY);
+        assert_eq!(drops.iter().map(|drop| (drop.block_number, drop.token)).collect::<Vec<_>>(), vec![(120, 0)]);
+    }
+}
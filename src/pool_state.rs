@@ -0,0 +1,279 @@
+use async_trait::async_trait;
+use ethers::contract::ContractError;
+use ethers::providers::Middleware;
+use ethers::types::{Address, U256};
+use std::collections::HashMap;
+
+use crate::types::PoolData;
+
+/// Why a pool contract call failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallErrorKind {
+    /// The contract exists but reverted, e.g. while paused.
+    Revert,
+    /// Nothing decodable came back, as with a self-destructed or never-deployed pool.
+    MissingContract,
+    /// The RPC itself failed; says nothing about the pool.
+    Transport,
+}
+
+impl CallErrorKind {
+    pub fn classify<M: Middleware>(error: &ContractError<M>) -> Self {
+        match error {
+            ContractError::Revert(_) => CallErrorKind::Revert,
+            ContractError::MiddlewareError { .. } | ContractError::ProviderError { .. } => CallErrorKind::Transport,
+            // Calls to an address without code succeed with empty return data, which fails to decode
+            _ => CallErrorKind::MissingContract,
+        }
+    }
+}
+
+// Number of calls in `PoolStateReads`
+const STATE_CALLS: usize = 6;
+
+pub type CallResult<T> = std::result::Result<T, CallErrorKind>;
+
+/// Raw results of the pool's state calls; each one may fail independently.
+#[derive(Debug, Clone)]
+pub struct PoolStateReads {
+    pub token0: CallResult<Address>,
+    pub token1: CallResult<Address>,
+    pub fee: CallResult<u32>,
+    pub tick_spacing: CallResult<i32>,
+    pub liquidity: CallResult<u128>,
+    /// `slot0()` as (sqrtPriceX96, tick).
+    pub slot0: CallResult<(U256, i32)>,
+}
+
+impl PoolStateReads {
+    pub fn errors(&self) -> Vec<CallErrorKind> {
+        [
+            self.token0.as_ref().err(),
+            self.token1.as_ref().err(),
+            self.fee.as_ref().err(),
+            self.tick_spacing.as_ref().err(),
+            self.liquidity.as_ref().err(),
+            self.slot0.as_ref().err(),
+        ]
+        .into_iter()
+        .flatten()
+        .copied()
+        .collect()
+    }
+}
+
+#[async_trait]
+pub trait PoolStateReader: Send + Sync {
+    async fn read_pool_state(&self, pool_address: Address) -> PoolStateReads;
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolStateUpdate {
+    /// The previous state with every successfully read field replaced.
+    pub pool: PoolData,
+    pub errors: Vec<CallErrorKind>,
+    pub all_failed: bool,
+}
+
+/// Merges fresh reads into `previous`, keeping the stored value for any call that failed.
+pub fn merge(previous: &PoolData, reads: &PoolStateReads) -> PoolStateUpdate {
+    let mut pool = previous.clone();
+
+    if let Ok(token0) = reads.token0 {
+        pool.token0_address = format!("{:?}", token0);
+    }
+    if let Ok(token1) = reads.token1 {
+        pool.token1_address = format!("{:?}", token1);
+    }
+    if let Ok(fee) = reads.fee {
+        pool.fee_tier = Some(fee as i32);
+    }
+    if let Ok(tick_spacing) = reads.tick_spacing {
+        pool.tick_spacing = Some(tick_spacing);
+    }
+    if let Ok(liquidity) = reads.liquidity {
+        pool.liquidity = Some(liquidity as i64);
+    }
+    if let Ok((sqrt_price_x96, tick)) = reads.slot0 {
+        pool.sqrt_price_x96 = Some(format!("{:?}", sqrt_price_x96));
+        pool.tick = Some(tick);
+    }
+
+    let errors = reads.errors();
+    PoolStateUpdate {
+        pool,
+        all_failed: errors.len() == STATE_CALLS,
+        errors,
+    }
+}
+
+pub async fn refresh<R: PoolStateReader + ?Sized>(reader: &R, previous: &PoolData) -> anyhow::Result<PoolStateUpdate> {
+    let reads = reader.read_pool_state(previous.pool_address.parse()?).await;
+    Ok(merge(previous, &reads))
+}
+
+/// Failed state calls since startup, by kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CallErrorCounts {
+    pub revert: u64,
+    pub missing_contract: u64,
+    pub transport: u64,
+}
+
+impl CallErrorCounts {
+    pub fn record(&mut self, kind: CallErrorKind) {
+        match kind {
+            CallErrorKind::Revert => self.revert += 1,
+            CallErrorKind::MissingContract => self.missing_contract += 1,
+            CallErrorKind::Transport => self.transport += 1,
+        }
+    }
+}
+
+/// Counts consecutive refreshes in which every call of a pool failed.
+#[derive(Debug, Clone)]
+pub struct FailureTracker {
+    threshold: u32,
+    consecutive: HashMap<String, u32>,
+}
+
+impl FailureTracker {
+    pub fn new(threshold: u32) -> Self {
+        Self { threshold, consecutive: HashMap::new() }
+    }
+
+    /// Records a refresh and returns true when it takes the pool to the threshold.
+    /// Transport errors only ever count as failures alongside pool-level ones, since a
+    /// flaky RPC should not mark healthy pools unresponsive.
+    pub fn record(&mut self, pool_address: &str, update: &PoolStateUpdate) -> bool {
+        let pool_failed = update.all_failed && update.errors.iter().any(|kind| *kind != CallErrorKind::Transport);
+        if !pool_failed {
+            self.consecutive.remove(pool_address);
+            return false;
+        }
+
+        let failures = self.consecutive.entry(pool_address.to_string()).or_insert(0);
+        *failures += 1;
+        if *failures >= self.threshold {
+            self.consecutive.remove(pool_address);
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::MOONSHOT;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    /// Answers with fixed values except for the calls listed in `failing`.
+    struct MockPool {
+        failing: Mutex<HashSet<&'static str>>,
+        kind: CallErrorKind,
+    }
+
+    impl MockPool {
+        fn failing(calls: &[&'static str], kind: CallErrorKind) -> Self {
+            Self { failing: Mutex::new(calls.iter().copied().collect()), kind }
+        }
+
+        fn call<T>(&self, name: &str, value: T) -> CallResult<T> {
+            if self.failing.lock().unwrap().contains(name) {
+                Err(self.kind)
+            } else {
+                Ok(value)
+            }
+        }
+    }
+
+    const ALL_CALLS: [&str; 6] = ["token0", "token1", "fee", "tickSpacing", "liquidity", "slot0"];
+
+    #[async_trait]
+    impl PoolStateReader for MockPool {
+        async fn read_pool_state(&self, _pool_address: Address) -> PoolStateReads {
+            PoolStateReads {
+                token0: self.call("token0", Address::from_low_u64_be(0xa0)),
+                token1: self.call("token1", Address::from_low_u64_be(0xa1)),
+                fee: self.call("fee", 3000),
+                tick_spacing: self.call("tickSpacing", 60),
+                liquidity: self.call("liquidity", 777),
+                slot0: self.call("slot0", (U256::from(42), -10)),
+            }
+        }
+    }
+
+    fn previous() -> PoolData {
+        let mut pool = PoolData::new(
+            "0x0000000000000000000000000000000000001000".to_string(),
+            "0x00000000000000000000000000000000000000a0".to_string(),
+            "0x00000000000000000000000000000000000000a1".to_string(),
+            8453,
+            MOONSHOT,
+        );
+        pool.liquidity = Some(100);
+        pool.sqrt_price_x96 = Some("7".to_string());
+        pool.tick = Some(5);
+        pool
+    }
+
+    #[tokio::test]
+    async fn test_partial_update_keeps_previous_values() {
+        let mock = MockPool::failing(&["liquidity", "slot0"], CallErrorKind::Revert);
+        let update = refresh(&mock, &previous()).await.unwrap();
+
+        assert!(!update.all_failed);
+        assert_eq!(update.errors, vec![CallErrorKind::Revert, CallErrorKind::Revert]);
+        assert_eq!(update.pool.fee_tier, Some(3000));
+        assert_eq!(update.pool.tick_spacing, Some(60));
+        // Failed reads keep the stored state
+        assert_eq!(update.pool.liquidity, Some(100));
+        assert_eq!(update.pool.sqrt_price_x96.as_deref(), Some("7"));
+        assert_eq!(update.pool.tick, Some(5));
+    }
+
+    #[tokio::test]
+    async fn test_unresponsive_after_consecutive_full_failures() {
+        let mock = MockPool::failing(&ALL_CALLS, CallErrorKind::MissingContract);
+        let pool = previous();
+        let mut tracker = FailureTracker::new(3);
+
+        let update = refresh(&mock, &pool).await.unwrap();
+        assert!(update.all_failed);
+        assert!(!tracker.record(&pool.pool_address, &update));
+        assert!(!tracker.record(&pool.pool_address, &update));
+
+        // One partially successful refresh resets the count
+        mock.failing.lock().unwrap().remove("fee");
+        let partial = refresh(&mock, &pool).await.unwrap();
+        assert!(!tracker.record(&pool.pool_address, &partial));
+
+        mock.failing.lock().unwrap().insert("fee");
+        assert!(!tracker.record(&pool.pool_address, &update));
+        assert!(!tracker.record(&pool.pool_address, &update));
+        assert!(tracker.record(&pool.pool_address, &update));
+    }
+
+    #[tokio::test]
+    async fn test_transport_failures_do_not_mark_unresponsive() {
+        let mock = MockPool::failing(&ALL_CALLS, CallErrorKind::Transport);
+        let pool = previous();
+        let mut tracker = FailureTracker::new(1);
+
+        let update = refresh(&mock, &pool).await.unwrap();
+        assert!(update.all_failed);
+        assert!(!tracker.record(&pool.pool_address, &update));
+    }
+
+    #[test]
+    fn test_error_counts() {
+        let mut counts = CallErrorCounts::default();
+        for kind in [CallErrorKind::Revert, CallErrorKind::Revert, CallErrorKind::Transport] {
+            counts.record(kind);
+        }
+
+        assert_eq!(counts, CallErrorCounts { revert: 2, missing_contract: 0, transport: 1 });
+    }
+}
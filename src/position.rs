@@ -0,0 +1,136 @@
+use ethers::types::{Address, H256, U256};
+use ethers::utils::keccak256;
+
+use crate::types::{PositionInfo, TickData};
+
+/// Key of a position in the pool's `positions` mapping:
+/// `keccak256(abi.encodePacked(owner, tickLower, tickUpper))`, the ticks packed as int24.
+pub fn position_key(owner: Address, tick_lower: i32, tick_upper: i32) -> H256 {
+    let mut packed = Vec::with_capacity(26);
+    packed.extend_from_slice(owner.as_bytes());
+    // int24 in two's complement is the low three bytes of the i32
+    packed.extend_from_slice(&tick_lower.to_be_bytes()[1..]);
+    packed.extend_from_slice(&tick_upper.to_be_bytes()[1..]);
+    H256::from(keccak256(packed))
+}
+
+/// Fee growth per unit of liquidity inside `[lower, upper)` as the pool tracks it. Like the
+/// contract's, the arithmetic wraps: only differences between readings are meaningful.
+pub fn fee_growth_inside(
+    tick_current: i32,
+    fee_growth_global: U256,
+    lower: (i32, U256),
+    upper: (i32, U256),
+) -> U256 {
+    let ((tick_lower, outside_lower), (tick_upper, outside_upper)) = (lower, upper);
+    let other_side = |outside: U256| fee_growth_global.overflowing_sub(outside).0;
+    let below = if tick_current >= tick_lower { outside_lower } else { other_side(outside_lower) };
+    let above = if tick_current < tick_upper { outside_upper } else { other_side(outside_upper) };
+    fee_growth_global.overflowing_sub(below).0.overflowing_sub(above).0
+}
+
+/// Fees the position could collect now, per token: those already owed plus those accrued
+/// since it was last touched, computed like the pool does when the position is poked.
+/// `lower` and `upper` are the position's boundary ticks read at the same time as
+/// `fee_growth_global`. Amounts beyond `u128` saturate.
+pub fn uncollected_fees(
+    position: &PositionInfo,
+    tick_current: i32,
+    fee_growth_global: (U256, U256),
+    lower: &TickData,
+    upper: &TickData,
+) -> (u128, u128) {
+    let accrued = |global: U256, outside_lower: U256, outside_upper: U256, last: U256| {
+        let inside = fee_growth_inside(
+            tick_current,
+            global,
+            (position.tick_lower, outside_lower),
+            (position.tick_upper, outside_upper),
+        );
+        let fees = inside.overflowing_sub(last).0.full_mul(U256::from(position.liquidity)) >> 128;
+        U256::try_from(fees).ok().and_then(|fees| u128::try_from(fees).ok()).unwrap_or(u128::MAX)
+    };
+
+    let fees_0 = accrued(
+        fee_growth_global.0,
+        lower.fee_growth_outside_0,
+        upper.fee_growth_outside_0,
+        position.fee_growth_inside_0_last,
+    );
+    let fees_1 = accrued(
+        fee_growth_global.1,
+        lower.fee_growth_outside_1,
+        upper.fee_growth_outside_1,
+        position.fee_growth_inside_1_last,
+    );
+    (position.tokens_owed_0.saturating_add(fees_0), position.tokens_owed_1.saturating_add(fees_1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One token of fees per unit of liquidity, in Q128
+    fn per_liquidity(amount: u64) -> U256 {
+        U256::from(amount) << 128
+    }
+
+    fn tick(tick_index: i32, outside_0: U256, outside_1: U256) -> TickData {
+        TickData {
+            tick_index,
+            liquidity_gross: 1,
+            liquidity_net: 1,
+            fee_growth_outside_0: outside_0,
+            fee_growth_outside_1: outside_1,
+        }
+    }
+
+    fn position(last_0: U256, last_1: U256) -> PositionInfo {
+        PositionInfo {
+            tick_lower: -60,
+            tick_upper: 60,
+            liquidity: 1_000,
+            tokens_owed_0: 7,
+            tokens_owed_1: 0,
+            fee_growth_inside_0_last: last_0,
+            fee_growth_inside_1_last: last_1,
+        }
+    }
+
+    #[test]
+    fn test_position_key_packs_ticks_as_int24() {
+        let owner = Address::from_low_u64_be(0xbeef);
+        let mut packed = owner.as_bytes().to_vec();
+        packed.extend_from_slice(&[0xff, 0xff, 0xc4, 0x00, 0x00, 0x3c]);
+
+        assert_eq!(position_key(owner, -60, 60), H256::from(keccak256(packed)));
+        assert_ne!(position_key(owner, -60, 60), position_key(owner, -60, 120));
+    }
+
+    #[test]
+    fn test_fees_accrue_inside_the_range() {
+        let (lower, upper) = (tick(-60, per_liquidity(1), U256::zero()), tick(60, per_liquidity(2), U256::zero()));
+        let global = (per_liquidity(10), per_liquidity(4));
+
+        // In range: everything not outside either boundary, 10 - 1 - 2 = 7 per liquidity
+        let fees = uncollected_fees(&position(per_liquidity(3), U256::zero()), 0, global, &lower, &upper);
+        assert_eq!(fees, (7 + 4 * 1_000, 4 * 1_000));
+
+        // Below the range only what grew while the price was inside counts: 1 - 2 wraps
+        // below zero, and the last reading wrapped the same way
+        let wrapped = U256::zero().overflowing_sub(per_liquidity(1)).0;
+        let fees = uncollected_fees(&position(wrapped, U256::zero()), -100, global, &lower, &upper);
+        assert_eq!(fees, (7, 0));
+    }
+
+    #[test]
+    fn test_fees_saturate() {
+        let mut position = position(U256::zero(), U256::zero());
+        position.liquidity = u128::MAX;
+        position.tokens_owed_0 = u128::MAX;
+        let ticks = (tick(-60, U256::zero(), U256::zero()), tick(60, U256::zero(), U256::zero()));
+
+        let fees = uncollected_fees(&position, 0, (U256::MAX, per_liquidity(1)), &ticks.0, &ticks.1);
+        assert_eq!(fees, (u128::MAX, u128::MAX));
+    }
+}
@@ -0,0 +1,443 @@
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::types::{normalize_address, PoolData};
+
+// Each tick moves the raw price by one basis point
+const TICK_BASE: f64 = 1.0001;
+
+// How far, in basis points, a routed pool's liquidity may move before its routes are dropped
+const ROUTE_LIQUIDITY_CHANGE_BPS: i128 = 2_000;
+
+// Bounds on one route search; the best route found so far is kept when either runs out
+const MAX_ROUTE_EXPANSIONS: usize = 10_000;
+const ROUTE_SEARCH_BUDGET: Duration = Duration::from_millis(50);
+
+pub const MIN_TICK: i32 = -887272;
+pub const MAX_TICK: i32 = 887272;
+
+/// Human-readable price of token0 in token1 at `tick`.
+pub fn tick_to_price(tick: i32, token0_decimals: i32, token1_decimals: i32) -> f64 {
+    (tick as f64 * TICK_BASE.ln()).exp() * 10f64.powi(token0_decimals - token1_decimals)
+}
+
+/// The tick whose range contains `price` (token0 in token1, decimal adjusted), rounding
+/// down like the pool contracts do.
+pub fn price_to_tick(price: f64, token0_decimals: i32, token1_decimals: i32) -> Result<i32> {
+    if !price.is_finite() || price <= 0.0 {
+        bail!("price must be positive and finite, got {}", price);
+    }
+
+    let raw_price = price * 10f64.powi(token1_decimals - token0_decimals);
+    // Nudge before flooring so a price computed from a tick maps back to that tick
+    let tick = (raw_price.ln() / TICK_BASE.ln() + 1e-6).floor();
+    if tick < MIN_TICK as f64 || tick > MAX_TICK as f64 {
+        bail!("price {} is outside the tick range", price);
+    }
+
+    Ok(tick as i32)
+}
+
+/// Whether a position over `[lower_tick, upper_tick)` is active at `tick`.
+pub fn within_tick_range(tick: i32, lower_tick: i32, upper_tick: i32) -> bool {
+    lower_tick <= tick && tick < upper_tick
+}
+
+/// The initializable ticks in `[tick_lower, tick_upper]`, i.e. the multiples of `tick_spacing`
+/// within the valid tick range.
+pub fn spaced_ticks(tick_lower: i32, tick_upper: i32, tick_spacing: i32) -> Result<Vec<i32>> {
+    if tick_spacing <= 0 {
+        bail!("tick spacing must be positive, got {}", tick_spacing);
+    }
+    if tick_lower > tick_upper {
+        bail!("lower tick {} is above upper tick {}", tick_lower, tick_upper);
+    }
+
+    let (tick_lower, tick_upper) = (tick_lower.max(MIN_TICK), tick_upper.min(MAX_TICK));
+    let mut tick = tick_lower.div_euclid(tick_spacing) * tick_spacing;
+    if tick < tick_lower {
+        tick += tick_spacing;
+    }
+
+    let mut ticks = Vec::new();
+    while tick <= tick_upper {
+        ticks.push(tick);
+        tick += tick_spacing;
+    }
+    Ok(ticks)
+}
+
+/// Impermanent loss of a full-range position after its price moved by `price_ratio`
+/// (current / initial), as a fraction of holding the tokens: `-0.05` is 5% worse.
+///
+/// Assumes the position started as a 50/50 split by value and that no fees were earned or
+/// reinvested; concentrated positions lose more within their range.
+pub fn impermanent_loss(price_ratio: f64) -> f64 {
+    2.0 * price_ratio.sqrt() / (1.0 + price_ratio) - 1.0
+}
+
+/// Pools leading from a token to one with a known USD price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceRoute {
+    /// In order from the priced token.
+    pub pools: Vec<String>,
+    /// Every token along the route, the priced token first and the anchor last.
+    pub tokens: Vec<String>,
+    /// The smallest liquidity among the route's pools.
+    pub min_liquidity: i64,
+}
+
+impl PriceRoute {
+    pub fn anchor(&self) -> &str {
+        &self.tokens[self.tokens.len() - 1]
+    }
+}
+
+/// Price of `from` in the pool's other token at the pool's current tick.
+fn hop_price(pool: &PoolData, from: &str) -> Option<f64> {
+    let token0_price = tick_to_price(pool.tick?, pool.token0_decimals?, pool.token1_decimals?);
+    let price = if pool.token0_address.eq_ignore_ascii_case(from) { token0_price } else { 1.0 / token0_price };
+    (price.is_finite() && price > 0.0).then_some(price)
+}
+
+fn routable(pool: &PoolData) -> bool {
+    pool.liquidity.is_some_and(|liquidity| liquidity > 0)
+        && pool.tick.is_some()
+        && pool.token0_decimals.is_some()
+        && pool.token1_decimals.is_some()
+}
+
+fn liquidity_moved(before: Option<i64>, after: Option<i64>) -> bool {
+    let (before, after) = (before.unwrap_or(0) as i128, after.unwrap_or(0) as i128);
+    (after - before).abs() * 10_000 > before * ROUTE_LIQUIDITY_CHANGE_BPS
+}
+
+/// Resolves USD prices for tokens that are not paired with a priced token directly, by
+/// walking the graph of known pools through up to `max_hops` pools to an anchor: a token
+/// whose USD price is given, such as the wrapped native token or a stablecoin.
+///
+/// Among the routes found, the one whose shallowest pool holds the most liquidity wins,
+/// then the shorter one. Routes never visit a token twice, and each search stops after a
+/// bounded number of steps or time. Routes are memoized per token and dropped when one of
+/// their pools leaves, stops being routable or moves its liquidity by more than 20%, or a
+/// pool touching one of their tokens appears; prices always come from current pool ticks.
+pub struct PriceRouter {
+    max_hops: usize,
+    pools: HashMap<String, PoolData>,
+    // Routable pools by each of their tokens
+    pools_by_token: HashMap<String, Vec<String>>,
+    routes: HashMap<String, PriceRoute>,
+}
+
+impl PriceRouter {
+    pub fn new(max_hops: usize) -> Self {
+        Self { max_hops: max_hops.max(1), pools: HashMap::new(), pools_by_token: HashMap::new(), routes: HashMap::new() }
+    }
+
+    /// Replaces the known pools with `pools`, dropping memoized routes they invalidate.
+    pub fn update_pools(&mut self, pools: impl IntoIterator<Item = PoolData>) {
+        let mut updated = HashMap::new();
+        for mut pool in pools {
+            pool.pool_address = normalize_address(&pool.pool_address);
+            pool.token0_address = normalize_address(&pool.token0_address);
+            pool.token1_address = normalize_address(&pool.token1_address);
+            updated.insert(pool.pool_address.clone(), pool);
+        }
+
+        let mut changed_pools = HashSet::new();
+        let mut new_tokens = HashSet::new();
+        for (pool_address, pool) in &self.pools {
+            match updated.get(pool_address) {
+                Some(now) if routable(now) && !liquidity_moved(pool.liquidity, now.liquidity) => {}
+                _ => {
+                    changed_pools.insert(pool_address.clone());
+                }
+            }
+        }
+        for (pool_address, pool) in &updated {
+            let was_routable = self.pools.get(pool_address).is_some_and(routable);
+            if routable(pool) && !was_routable {
+                new_tokens.insert(pool.token0_address.clone());
+                new_tokens.insert(pool.token1_address.clone());
+            }
+        }
+        self.routes.retain(|_, route| {
+            !route.pools.iter().any(|pool_address| changed_pools.contains(pool_address))
+                && !route.tokens.iter().any(|token| new_tokens.contains(token))
+        });
+
+        self.pools_by_token.clear();
+        for pool in updated.values().filter(|pool| routable(pool)) {
+            for token in [&pool.token0_address, &pool.token1_address] {
+                self.pools_by_token.entry(token.clone()).or_default().push(pool.pool_address.clone());
+            }
+        }
+        for pool_addresses in self.pools_by_token.values_mut() {
+            pool_addresses.sort();
+        }
+        self.pools = updated;
+    }
+
+    /// USD price of `token` given the USD prices of the anchor tokens, None when no route
+    /// within `max_hops` reaches one.
+    pub fn usd_price(&mut self, token: &str, anchors: &HashMap<String, f64>) -> Option<f64> {
+        let token = normalize_address(token);
+        if let Some(price_usd) = anchors.get(&token) {
+            return Some(*price_usd);
+        }
+
+        if let Some(price_usd) = self.routes.get(&token).and_then(|route| self.route_price(route, anchors)) {
+            return Some(price_usd);
+        }
+        self.routes.remove(&token);
+
+        let route = self.find_route(&token, anchors)?;
+        let price_usd = self.route_price(&route, anchors)?;
+        self.routes.insert(token, route);
+        Some(price_usd)
+    }
+
+    /// The route `usd_price` last priced `token` along, None for anchors and tokens it could
+    /// not price.
+    pub fn route(&self, token: &str) -> Option<&PriceRoute> {
+        self.routes.get(&normalize_address(token))
+    }
+
+    /// USD price along `route` from its pools' current ticks.
+    pub fn route_price(&self, route: &PriceRoute, anchors: &HashMap<String, f64>) -> Option<f64> {
+        let mut price_usd = *anchors.get(route.anchor())?;
+        for (pool_address, from) in route.pools.iter().zip(&route.tokens) {
+            let pool = self.pools.get(pool_address).filter(|pool| routable(pool))?;
+            price_usd *= hop_price(pool, from)?;
+        }
+        (price_usd.is_finite() && price_usd > 0.0).then_some(price_usd)
+    }
+
+    /// The best route from `token` to any anchor, searched depth first.
+    pub fn find_route(&self, token: &str, anchors: &HashMap<String, f64>) -> Option<PriceRoute> {
+        let token = normalize_address(token);
+        let started = Instant::now();
+        let mut best: Option<PriceRoute> = None;
+        let mut stack = vec![PriceRoute { pools: Vec::new(), tokens: vec![token.clone()], min_liquidity: i64::MAX }];
+        let mut expansions = 0;
+
+        while let Some(path) = stack.pop() {
+            expansions += 1;
+            if expansions > MAX_ROUTE_EXPANSIONS || started.elapsed() > ROUTE_SEARCH_BUDGET {
+                debug!("Route search for {} stopped after {} steps", token, expansions - 1);
+                break;
+            }
+
+            let at = &path.tokens[path.tokens.len() - 1];
+            for pool_address in self.pools_by_token.get(at).into_iter().flatten() {
+                let pool = &self.pools[pool_address];
+                let next = if &pool.token0_address == at { &pool.token1_address } else { &pool.token0_address };
+                if path.tokens.contains(next) {
+                    continue;
+                }
+
+                let mut route = path.clone();
+                route.pools.push(pool_address.clone());
+                route.tokens.push(next.clone());
+                route.min_liquidity = route.min_liquidity.min(pool.liquidity.unwrap_or(0));
+
+                // Liquidity only shrinks along a path, so one no better than the best is done
+                let beats_best = |best: &Option<PriceRoute>| {
+                    best.as_ref().is_none_or(|best| {
+                        (route.min_liquidity, std::cmp::Reverse(route.pools.len()))
+                            > (best.min_liquidity, std::cmp::Reverse(best.pools.len()))
+                    })
+                };
+                if !beats_best(&best) {
+                    continue;
+                }
+                if anchors.contains_key(next) {
+                    best = Some(route);
+                } else if route.pools.len() < self.max_hops {
+                    stack.push(route);
+                }
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::MOONSHOT;
+
+    #[test]
+    fn test_tick_zero_is_unit_price() {
+        assert_eq!(price_to_tick(1.0, 18, 18).unwrap(), 0);
+        assert_eq!(tick_to_price(0, 18, 18), 1.0);
+    }
+
+    #[test]
+    fn test_round_trip_across_ticks_and_decimals() {
+        for (decimals0, decimals1) in [(18, 18), (6, 18), (18, 6)] {
+            for tick in [-200_000, -60, -1, 0, 1, 60, 12_345, 200_000] {
+                let price = tick_to_price(tick, decimals0, decimals1);
+                assert_eq!(price_to_tick(price, decimals0, decimals1).unwrap(), tick);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rounds_down_between_ticks() {
+        let between = (tick_to_price(10, 18, 18) + tick_to_price(11, 18, 18)) / 2.0;
+        assert_eq!(price_to_tick(between, 18, 18).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_invalid_prices() {
+        assert!(price_to_tick(0.0, 18, 18).is_err());
+        assert!(price_to_tick(-1.0, 18, 18).is_err());
+        assert!(price_to_tick(f64::NAN, 18, 18).is_err());
+        assert!(price_to_tick(1e300, 18, 18).is_err());
+    }
+
+    #[test]
+    fn test_spaced_ticks_align_to_spacing() {
+        assert_eq!(spaced_ticks(-130, 130, 60).unwrap(), vec![-120, -60, 0, 60, 120]);
+        assert_eq!(spaced_ticks(-120, 120, 60).unwrap(), vec![-120, -60, 0, 60, 120]);
+        assert_eq!(spaced_ticks(1, 59, 60).unwrap(), Vec::<i32>::new());
+        assert!(spaced_ticks(0, 60, 0).is_err());
+        assert!(spaced_ticks(60, 0, 60).is_err());
+    }
+
+    #[test]
+    fn test_impermanent_loss() {
+        assert_eq!(impermanent_loss(1.0), 0.0);
+        assert!((impermanent_loss(2.0) - -0.0572).abs() < 1e-4);
+        // A halving loses as much as a doubling
+        assert!((impermanent_loss(0.5) - impermanent_loss(2.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_within_tick_range_excludes_upper() {
+        assert!(within_tick_range(-60, -60, 60));
+        assert!(within_tick_range(59, -60, 60));
+        assert!(!within_tick_range(60, -60, 60));
+        assert!(!within_tick_range(-61, -60, 60));
+    }
+
+    // Token addresses for the synthetic pool graphs
+    const USDC: &str = "0x00000000000000000000000000000000000000c0";
+    const WETH: &str = "0x00000000000000000000000000000000000000e0";
+    const A: &str = "0x00000000000000000000000000000000000000a1";
+    const B: &str = "0x00000000000000000000000000000000000000a2";
+    const C: &str = "0x00000000000000000000000000000000000000a3";
+    const D: &str = "0x00000000000000000000000000000000000000a4";
+
+    // A pool where one `token0` is worth `price` of `token1`, both with 18 decimals
+    fn graph_pool(pool_address: &str, token0: &str, token1: &str, liquidity: i64, price: f64) -> PoolData {
+        let mut pool = PoolData::new(pool_address.to_string(), token0.to_string(), token1.to_string(), 8453, MOONSHOT);
+        pool.liquidity = Some(liquidity);
+        pool.tick = Some(price_to_tick(price, 18, 18).unwrap());
+        pool.token0_decimals = Some(18);
+        pool.token1_decimals = Some(18);
+        pool
+    }
+
+    fn anchors() -> HashMap<String, f64> {
+        HashMap::from([(USDC.to_string(), 1.0), (WETH.to_string(), 2_000.0)])
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.unwrap();
+        assert!((actual / expected - 1.0).abs() < 1e-3, "{} vs {}", actual, expected);
+    }
+
+    #[test]
+    fn test_price_composes_across_hops() {
+        let mut router = PriceRouter::new(2);
+        // A -> B -> WETH, with B on the token1 side of its pool
+        router.update_pools([graph_pool("0xp1", A, B, 1_000, 0.5), graph_pool("0xp2", WETH, B, 1_000, 1_000.0)]);
+
+        // 1 A = 0.5 B, 1 B = 0.001 WETH = $2
+        assert_close(router.usd_price(A, &anchors()), 1.0);
+        assert_close(router.usd_price(B, &anchors()), 2.0);
+        assert_eq!(router.usd_price(WETH, &anchors()), Some(2_000.0));
+        assert_eq!(router.find_route(A, &anchors()).unwrap().tokens, vec![A, B, WETH]);
+    }
+
+    #[test]
+    fn test_prefers_highest_minimum_liquidity() {
+        let mut router = PriceRouter::new(2);
+        router.update_pools([
+            // Direct but shallow
+            graph_pool("0xp1", C, WETH, 100, 0.001),
+            // Two deep hops
+            graph_pool("0xp2", C, D, 10_000, 4.0),
+            graph_pool("0xp3", D, USDC, 5_000, 0.5),
+            // Equally deep, but longer
+            graph_pool("0xp4", D, A, 5_000, 1.0),
+        ]);
+
+        let route = router.find_route(C, &anchors()).unwrap();
+        assert_eq!(route.pools, vec!["0xp2", "0xp3"]);
+        assert_eq!(route.min_liquidity, 5_000);
+        assert_close(router.usd_price(C, &anchors()), 2.0);
+
+        // With only one hop allowed the shallow direct pool is all there is
+        let mut one_hop = PriceRouter::new(1);
+        one_hop.update_pools(router.pools.values().cloned().collect::<Vec<_>>());
+        assert_eq!(one_hop.find_route(C, &anchors()).unwrap().pools, vec!["0xp1"]);
+    }
+
+    #[test]
+    fn test_unpriceable_tokens() {
+        let mut router = PriceRouter::new(2);
+        router.update_pools([
+            // A -> B -> C -> WETH is one hop too many
+            graph_pool("0xp1", A, B, 1_000, 1.0),
+            graph_pool("0xp2", B, C, 1_000, 1.0),
+            graph_pool("0xp3", C, WETH, 1_000, 1.0),
+            // A cycle with no way out
+            graph_pool("0xp4", D, "0x00000000000000000000000000000000000000a5", 1_000, 1.0),
+            graph_pool("0xp5", "0x00000000000000000000000000000000000000a5", "0x00000000000000000000000000000000000000a6", 1_000, 1.0),
+            graph_pool("0xp6", "0x00000000000000000000000000000000000000a6", D, 1_000, 1.0),
+        ]);
+        assert_eq!(router.usd_price(A, &anchors()), None);
+        assert_eq!(router.usd_price(D, &anchors()), None);
+        assert_eq!(router.usd_price("0x00000000000000000000000000000000000000ff", &anchors()), None);
+
+        // Without its anchor's price a routed token is unpriceable too
+        assert_close(router.usd_price(C, &anchors()), 2_000.0);
+        assert_eq!(router.usd_price(C, &HashMap::from([(USDC.to_string(), 1.0)])), None);
+    }
+
+    #[test]
+    fn test_routes_are_memoized_until_liquidity_moves() {
+        let mut router = PriceRouter::new(2);
+        let direct = graph_pool("0xp1", C, WETH, 1_000, 0.001);
+        let mut via_d = graph_pool("0xp2", C, D, 10_000, 4.0);
+        let mut d_usdc = graph_pool("0xp3", D, USDC, 5_000, 0.5);
+        router.update_pools([direct.clone(), via_d.clone(), d_usdc.clone()]);
+        assert_close(router.usd_price(C, &anchors()), 2.0);
+        assert_eq!(router.routes[C].pools, vec!["0xp2", "0xp3"]);
+
+        // A small liquidity change keeps the route; its price follows the new tick
+        d_usdc.liquidity = Some(4_500);
+        d_usdc.tick = Some(price_to_tick(0.75, 18, 18).unwrap());
+        router.update_pools([direct.clone(), via_d.clone(), d_usdc.clone()]);
+        assert!(router.routes.contains_key(C));
+        assert_close(router.usd_price(C, &anchors()), 3.0);
+
+        // Draining the routed pool drops the route and the direct pool takes over
+        d_usdc.liquidity = Some(50);
+        router.update_pools([direct.clone(), via_d.clone(), d_usdc.clone()]);
+        assert!(!router.routes.contains_key(C));
+        assert_close(router.usd_price(C, &anchors()), 2.0);
+        assert_eq!(router.routes[C].pools, vec!["0xp1"]);
+
+        // A pool that stops being routable drops the routes through it
+        via_d.tick = None;
+        router.update_pools([direct, via_d, d_usdc]);
+        assert_eq!(router.routes[C].pools, vec!["0xp1"]);
+    }
+}
@@ -0,0 +1,356 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::db::{Database, TimelineCursor};
+use crate::types::{PoolData, TokenEvent};
+
+// Events read from the database per round trip
+const REPLAY_PAGE_SIZE: i64 = 1000;
+
+// Events sent between acknowledgements unless a store asks for another cadence
+const DEFAULT_ACK_EVERY: u64 = 100;
+
+/// Where replayed events go.
+///
+/// Delivery contract: payloads of one chain arrive one at a time in timeline order, i.e. by
+/// (block, log index), and `replay` fails rather than send one out of order. Delivery is at
+/// least once: after a crash, events sent since the last acknowledged one are sent again, so
+/// a sink must either be idempotent (e.g. upsert by tx hash and log index) or drop
+/// duplicates itself. An event counts as delivered once a later `flush` returns `Ok`.
+#[async_trait]
+pub trait Sink: Send {
+    async fn send(&mut self, payload: &Value) -> Result<()>;
+
+    /// Makes everything sent so far durable at the destination.
+    async fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Remembers the last event a named sink acknowledged on a chain, in `sink_offsets`, so a
+/// restarted replay into a non-transactional sink (Kafka, webhook, S3) resumes after it
+/// instead of sending the whole range again. Only events sent after the last
+/// acknowledgement, at most `ack_every`, can be delivered twice.
+#[derive(Clone)]
+pub struct SinkOffsetStore {
+    database: Database,
+    sink_name: String,
+    chain_id: i64,
+    ack_every: u64,
+}
+
+impl SinkOffsetStore {
+    pub fn new(database: Database, sink_name: &str, chain_id: i64) -> Self {
+        Self { database, sink_name: sink_name.to_string(), chain_id, ack_every: DEFAULT_ACK_EVERY }
+    }
+
+    /// Flushes the sink and acknowledges every `ack_every` events; fewer narrows the window
+    /// of duplicates after a crash at the cost of more flushes.
+    pub fn with_ack_every(mut self, ack_every: u64) -> Self {
+        self.ack_every = ack_every.max(1);
+        self
+    }
+
+    pub async fn last_acknowledged(&self) -> Result<Option<TimelineCursor>> {
+        self.database.get_sink_offset(&self.sink_name, self.chain_id).await
+    }
+
+    pub async fn acknowledge(&self, position: &TimelineCursor) -> Result<()> {
+        self.database.set_sink_offset(&self.sink_name, self.chain_id, position).await
+    }
+}
+
+/// Writes one JSON payload per line.
+pub struct JsonlSink<W> {
+    writer: W,
+}
+
+impl<W: Write + Send> JsonlSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[async_trait]
+impl<W: Write + Send> Sink for JsonlSink<W> {
+    async fn send(&mut self, payload: &Value) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, payload)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// POSTs each payload as a JSON body, failing the replay on a non-success status.
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookSink {
+    pub fn new(url: String) -> Self {
+        Self { client: reqwest::Client::new(), url }
+    }
+}
+
+#[async_trait]
+impl Sink for WebhookSink {
+    async fn send(&mut self, payload: &Value) -> Result<()> {
+        self.client.post(&self.url).json(payload).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Stored events to replay: swaps and pool creations of `chain_id` with
+/// `from_ts <= timestamp < to_ts`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayOptions {
+    pub chain_id: i64,
+    pub from_ts: i64,
+    pub to_ts: i64,
+    /// Replays `speed` times faster than the events happened; `None` replays at full speed.
+    pub speed: Option<f64>,
+    /// Adds a `pretty` object to swaps and pool creations: amounts in token units and
+    /// explorer links, for people reading the output rather than programs.
+    pub pretty: bool,
+}
+
+/// The event as consumers receive it live, tagged `"replay": true`.
+pub fn payload(event: &TokenEvent) -> Result<Value> {
+    let mut payload = match event {
+        // Row times belong to the database; a live pool creation has none
+        TokenEvent::PoolCreated(pool) => {
+            serde_json::to_value(TokenEvent::PoolCreated(PoolData { created_at: None, updated_at: None, ..pool.clone() }))?
+        }
+        _ => serde_json::to_value(event)?,
+    };
+    if let Value::Object(fields) = &mut payload {
+        fields.insert("replay".to_string(), Value::Bool(true));
+    }
+    Ok(payload)
+}
+
+/// Adds the `pretty` object to a swap or pool creation payload; `pool` is the swap's pool,
+/// whose decimals scale the amounts (18 when unknown or unindexed).
+pub fn add_pretty_fields(payload: &mut Value, event: &TokenEvent, pool: Option<&PoolData>) {
+    let pretty = match event {
+        TokenEvent::Swap(swap) => {
+            let (amount_in, amount_out) = match pool {
+                Some(pool) => swap.human_amounts(pool),
+                None => (swap.amount_in as f64 / 1e18, swap.amount_out as f64 / 1e18),
+            };
+            json!({ "amount_in": amount_in, "amount_out": amount_out, "explorer_url": swap.explorer_tx_url() })
+        }
+        TokenEvent::PoolCreated(pool) => json!({ "explorer_url": pool.explorer_address_url() }),
+        TokenEvent::CurveTrade(_) | TokenEvent::Migration(_) => return,
+    };
+    if let Value::Object(fields) = payload {
+        fields.insert("pretty".to_string(), pretty);
+    }
+}
+
+/// How long to wait before an event at `timestamp` when the previous one was at `previous`.
+/// Events without a timestamp (pool creations) and events out of time order go out at once.
+pub fn pacing_delay(previous: Option<i64>, timestamp: Option<i64>, speed: f64) -> Duration {
+    match (previous, timestamp) {
+        (Some(previous), Some(timestamp)) if timestamp > previous => {
+            Duration::from_secs_f64((timestamp - previous) as f64 / speed)
+        }
+        _ => Duration::ZERO,
+    }
+}
+
+fn event_timestamp(event: &TokenEvent) -> Option<i64> {
+    match event {
+        TokenEvent::Swap(swap) => Some(swap.timestamp),
+        TokenEvent::CurveTrade(trade) => Some(trade.timestamp),
+        TokenEvent::Migration(migration) => Some(migration.timestamp),
+        TokenEvent::PoolCreated(_) => None,
+    }
+}
+
+/// Streams the stored events in (block, log index) order into `sink` and returns how many
+/// were sent. Reads nothing from the chain, so the same data always replays the same way.
+///
+/// With `offsets`, the replay starts after the sink's last acknowledged event and
+/// acknowledges as it goes; see `SinkOffsetStore`.
+pub async fn replay(
+    database: &Database,
+    options: &ReplayOptions,
+    sink: &mut dyn Sink,
+    offsets: Option<&SinkOffsetStore>,
+) -> Result<u64> {
+    if options.from_ts >= options.to_ts {
+        bail!("replay window is empty: --from-ts {} is not before --to-ts {}", options.from_ts, options.to_ts);
+    }
+    if let Some(speed) = options.speed.filter(|speed| !(speed.is_finite() && *speed > 0.0)) {
+        bail!("--speed must be positive, got {}", speed);
+    }
+
+    let mut cursor = match offsets {
+        Some(offsets) => offsets.last_acknowledged().await?,
+        None => None,
+    };
+    let mut previous_timestamp = None;
+    let mut previous_position = cursor.clone();
+    let mut unacknowledged = 0;
+    let mut sent = 0;
+    // Pools of replayed swaps, looked up once for `pretty` amounts
+    let mut pools: HashMap<String, Option<PoolData>> = HashMap::new();
+    loop {
+        let page = database
+            .get_chain_timeline(options.chain_id, options.from_ts, options.to_ts, REPLAY_PAGE_SIZE, cursor.as_ref())
+            .await?;
+
+        for event in &page.events {
+            let position = TimelineCursor::of(event);
+            if let Some(previous) = previous_position.as_ref().filter(|previous| position <= **previous) {
+                bail!("event at {} would be sent out of order, after {}", position, previous);
+            }
+
+            let timestamp = event_timestamp(event);
+            if let Some(speed) = options.speed {
+                sleep(pacing_delay(previous_timestamp, timestamp, speed)).await;
+            }
+            previous_timestamp = timestamp.or(previous_timestamp);
+
+            let mut event_payload = payload(event)?;
+            if options.pretty {
+                let pool = match event {
+                    TokenEvent::Swap(swap) => match pools.get(&swap.pool_address) {
+                        Some(pool) => pool.as_ref(),
+                        None => {
+                            let pool = database.get_pool(&swap.pool_address).await?;
+                            pools.entry(swap.pool_address.clone()).or_insert(pool).as_ref()
+                        }
+                    },
+                    _ => None,
+                };
+                add_pretty_fields(&mut event_payload, event, pool);
+            }
+            sink.send(&event_payload).await?;
+            sent += 1;
+
+            if let Some(offsets) = offsets {
+                unacknowledged += 1;
+                if unacknowledged >= offsets.ack_every {
+                    sink.flush().await?;
+                    offsets.acknowledge(&position).await?;
+                    unacknowledged = 0;
+                }
+            }
+            previous_position = Some(position);
+        }
+
+        match page.next_cursor {
+            Some(next) => cursor = Some(next.parse()?),
+            None => break,
+        }
+    }
+
+    sink.flush().await?;
+    if let (Some(offsets), Some(position)) = (offsets, &previous_position) {
+        if unacknowledged > 0 {
+            offsets.acknowledge(position).await?;
+        }
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::MOONSHOT;
+    use crate::types::SwapEvent;
+
+    #[test]
+    fn test_payload_is_tagged_as_replay() {
+        let swap = SwapEvent::new(
+            "0x01".to_string(),
+            "0x00000000000000000000000000000000000000aa".to_string(),
+            "token0".to_string(),
+            "token1".to_string(),
+            1000,
+            950,
+            1640995200,
+            100,
+            0,
+            2741,
+        );
+
+        let payload = payload(&TokenEvent::Swap(swap)).unwrap();
+        assert_eq!(payload["replay"], Value::Bool(true));
+        assert_eq!(payload["type"], "swap");
+        assert_eq!(payload["event"]["amount_in"], 1000);
+    }
+
+    #[test]
+    fn test_pretty_fields_use_pool_decimals() {
+        let mut swap = SwapEvent::new(
+            "0x01".to_string(),
+            "0x00000000000000000000000000000000000000aa".to_string(),
+            "token0".to_string(),
+            "token1".to_string(),
+            2_500_000_000_000_000_000,
+            1_250_000,
+            1640995200,
+            100,
+            0,
+            8453,
+        );
+        let mut pool = PoolData::new(
+            swap.pool_address.clone(),
+            "0x00000000000000000000000000000000000000a0".to_string(),
+            "0x00000000000000000000000000000000000000a1".to_string(),
+            8453,
+            MOONSHOT,
+        );
+        (pool.token0_decimals, pool.token1_decimals) = (Some(18), Some(6));
+
+        let event = TokenEvent::Swap(swap.clone());
+        let mut swap_payload = payload(&event).unwrap();
+        add_pretty_fields(&mut swap_payload, &event, Some(&pool));
+        assert_eq!(swap_payload["pretty"]["amount_in"], 2.5);
+        assert_eq!(swap_payload["pretty"]["amount_out"], 1.25);
+        assert_eq!(swap_payload["pretty"]["explorer_url"], "https://basescan.org/tx/0x01");
+        // The raw event is left as it was
+        assert_eq!(swap_payload["event"]["amount_out"], 1_250_000);
+
+        let event = TokenEvent::PoolCreated(pool.clone());
+        let mut pool_payload = payload(&event).unwrap();
+        add_pretty_fields(&mut pool_payload, &event, None);
+        assert_eq!(
+            pool_payload["pretty"]["explorer_url"],
+            "https://basescan.org/address/0x00000000000000000000000000000000000000aa"
+        );
+
+        // Chains without a known explorer get a null link
+        swap.chain_id = 1_234_567;
+        let event = TokenEvent::Swap(swap);
+        let mut swap_payload = payload(&event).unwrap();
+        add_pretty_fields(&mut swap_payload, &event, None);
+        assert_eq!(swap_payload["pretty"]["amount_out"], 1.25e-12);
+        assert_eq!(swap_payload["pretty"]["explorer_url"], Value::Null);
+    }
+
+    #[test]
+    fn test_pacing_follows_event_time() {
+        assert_eq!(pacing_delay(Some(100), Some(110), 1.0), Duration::from_secs(10));
+        assert_eq!(pacing_delay(Some(100), Some(110), 4.0), Duration::from_millis(2500));
+        assert_eq!(pacing_delay(None, Some(110), 1.0), Duration::ZERO);
+        assert_eq!(pacing_delay(Some(110), None, 1.0), Duration::ZERO);
+        assert_eq!(pacing_delay(Some(110), Some(100), 1.0), Duration::ZERO);
+    }
+}
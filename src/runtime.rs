@@ -0,0 +1,146 @@
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::future::Future;
+use std::thread;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::config::Config;
+
+/// Effective sizing of the tokio runtime and the swap decode pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RuntimeSettings {
+    pub worker_threads: usize,
+    pub max_blocking_threads: usize,
+    pub decode_workers: usize,
+}
+
+impl RuntimeSettings {
+    /// `WORKER_THREADS=0` resolves to one worker per available CPU, as tokio does by default.
+    pub fn from_config(config: &Config) -> Self {
+        let worker_threads = match config.worker_threads {
+            0 => thread::available_parallelism().map(usize::from).unwrap_or(1),
+            threads => threads,
+        };
+
+        Self {
+            worker_threads,
+            max_blocking_threads: config.max_blocking_threads,
+            decode_workers: config.decode_workers.max(1),
+        }
+    }
+
+    pub fn build_runtime(&self) -> std::io::Result<Runtime> {
+        Builder::new_multi_thread()
+            .worker_threads(self.worker_threads)
+            .max_blocking_threads(self.max_blocking_threads)
+            .enable_all()
+            .build()
+    }
+}
+
+/// Runs `f` over `items` on tokio's blocking pool, keeping CPU-bound work off the threads
+/// that drive I/O. The batch moves to the pool whole: a cancelled caller stops waiting, but
+/// the batch still runs to completion and runtime shutdown waits for it.
+pub async fn offload<T, R, F>(items: Vec<T>, f: F) -> Result<Vec<R>>
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> R + Send + 'static,
+{
+    Ok(tokio::task::spawn_blocking(move || items.into_iter().map(f).collect()).await?)
+}
+
+/// Runs `decode` over `items` with at most `workers` calls in flight, keeping input order.
+pub async fn decode_in_order<T, U, F, Fut>(items: Vec<T>, workers: usize, decode: F) -> Vec<U>
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = U>,
+{
+    stream::iter(items).map(decode).buffered(workers.max(1)).collect().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catchup;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_zero_worker_threads_uses_every_cpu() {
+        let config = Config::from_lookup(|name| match name {
+            "RPC_URL" => Some("wss://rpc.example.com".to_string()),
+            "DATABASE_URL" => Some("postgresql://localhost:5432/test".to_string()),
+            "WORKER_THREADS" => Some("0".to_string()),
+            _ => None,
+        })
+        .unwrap();
+
+        let settings = RuntimeSettings::from_config(&config);
+        assert_eq!(settings.worker_threads, thread::available_parallelism().map(usize::from).unwrap_or(1));
+        assert_eq!((settings.max_blocking_threads, settings.decode_workers), (512, 4));
+    }
+
+    #[tokio::test]
+    async fn test_decoding_keeps_order_and_bounds_concurrency() {
+        let (in_flight, peak) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+
+        let decoded = decode_in_order((0..20u64).collect(), 3, |n| {
+            let (in_flight, peak) = (in_flight.clone(), peak.clone());
+            async move {
+                peak.fetch_max(in_flight.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                // Later items finish first
+                tokio::time::sleep(Duration::from_millis(20 - n)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                n * 2
+            }
+        })
+        .await;
+
+        assert_eq!(decoded, (0..20).map(|n| n * 2).collect::<Vec<_>>());
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_cancelled_offload_drains_on_shutdown() {
+        let runtime = RuntimeSettings { worker_threads: 1, max_blocking_threads: 1, decode_workers: 1 }
+            .build_runtime()
+            .unwrap();
+        let processed = Arc::new(AtomicUsize::new(0));
+
+        let counter = processed.clone();
+        let task = runtime.spawn(offload((0..10_000).collect(), move |n: u64| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            n
+        }));
+        // Cancel the caller as if shutting down, then shut the runtime down
+        task.abort();
+        runtime.shutdown_timeout(Duration::from_secs(10));
+
+        // Either the batch never started or it ran to the end; it is never cut short
+        assert!([0, 10_000].contains(&processed.load(Ordering::SeqCst)));
+    }
+
+    #[test]
+    fn test_single_worker_runtime_completes_a_range() {
+        let settings = RuntimeSettings { worker_threads: 1, max_blocking_threads: 1, decode_workers: 2 };
+        let runtime = settings.build_runtime().unwrap();
+
+        // Chunks on their own tasks, each decoding its blocks, like a parallel backfill
+        let decoded = runtime.block_on(async {
+            let tasks = catchup::split_range(1_000, 1_999, 4).into_iter().map(|(from, to)| {
+                tokio::spawn(async move {
+                    let blocks = decode_in_order((from..=to).collect(), settings.decode_workers, |block| async move {
+                        tokio::task::spawn_blocking(move || block).await.unwrap()
+                    });
+                    blocks.await.len()
+                })
+            });
+            futures::future::try_join_all(tasks).await.unwrap().into_iter().sum::<usize>()
+        });
+
+        assert_eq!(decoded, 1_000);
+    }
+}
@@ -0,0 +1,173 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use tracing::{info, warn};
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::types::{normalize_address, PoolData};
+
+/// Which pools enter the swap registry. Every pool is still recorded; untracked pools are
+/// just never polled for swaps. With both filters set a pool must satisfy both. Watched
+/// pools are tracked whatever the filters say.
+#[derive(Debug, Clone, Default)]
+pub struct IndexingScope {
+    pool_allowlist: Option<HashSet<String>>,
+    track_token: Option<String>,
+    watched_pools: HashSet<String>,
+}
+
+impl IndexingScope {
+    pub fn new(pool_allowlist: Vec<String>, track_token: Option<String>) -> Self {
+        let pool_allowlist = if pool_allowlist.is_empty() {
+            None
+        } else {
+            Some(pool_allowlist.iter().map(|a| normalize_address(a)).collect())
+        };
+
+        Self {
+            pool_allowlist,
+            track_token: track_token.map(|t| normalize_address(&t)),
+            watched_pools: HashSet::new(),
+        }
+    }
+
+    /// Pools added to the watchlist at runtime.
+    pub fn with_watched_pools(mut self, pools: impl IntoIterator<Item = String>) -> Self {
+        self.watched_pools.extend(pools.into_iter().map(|pool| normalize_address(&pool)));
+        self
+    }
+
+    pub fn watch_pool(&mut self, pool_address: &str) {
+        self.watched_pools.insert(normalize_address(pool_address));
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.pool_allowlist.clone(), config.track_token.clone())
+    }
+
+    pub fn is_unrestricted(&self) -> bool {
+        self.pool_allowlist.is_none() && self.track_token.is_none()
+    }
+
+    pub fn is_tracked(&self, pool: &PoolData) -> bool {
+        if self.watched_pools.contains(&normalize_address(&pool.pool_address)) {
+            return true;
+        }
+
+        let allowed = self
+            .pool_allowlist
+            .as_ref()
+            .is_none_or(|allowlist| allowlist.contains(&normalize_address(&pool.pool_address)));
+
+        let has_token = self.track_token.as_ref().is_none_or(|token| {
+            normalize_address(&pool.token0_address) == *token || normalize_address(&pool.token1_address) == *token
+        });
+
+        allowed && has_token
+    }
+}
+
+/// Re-evaluates `pools.tracked` for every stored pool so scope changes apply retroactively.
+/// Returns the pools that just became tracked.
+pub async fn apply_to_existing_pools(database: &Database, scope: &IndexingScope) -> Result<Vec<PoolData>> {
+    let untracked: HashSet<String> = database.get_untracked_pool_addresses().await?.into_iter().collect();
+    let mut newly_tracked = Vec::new();
+
+    for pool in database.get_all_pools().await? {
+        let tracked = scope.is_tracked(&pool);
+        let was_tracked = !untracked.contains(&pool.pool_address);
+        if tracked == was_tracked {
+            continue;
+        }
+
+        database.set_pool_tracked(&pool.pool_address, tracked).await?;
+        if tracked {
+            newly_tracked.push(pool);
+        }
+    }
+
+    for pool in &newly_tracked {
+        match pool.created_at_block {
+            Some(block) => warn!(
+                "Pool {} is now tracked; swaps before this run are missing, backfill from block {} to fill them",
+                pool.pool_address, block
+            ),
+            None => warn!("Pool {} is now tracked; earlier swaps are missing until backfilled", pool.pool_address),
+        }
+    }
+    if !scope.is_unrestricted() {
+        info!("Indexing scope restricted by allowlist/token filter");
+    }
+
+    Ok(newly_tracked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::MOONSHOT;
+
+    const TOKEN_A: &str = "0x000000000000000000000000000000000000000A";
+    const TOKEN_B: &str = "0x000000000000000000000000000000000000000B";
+    const TOKEN_C: &str = "0x000000000000000000000000000000000000000C";
+
+    fn pool(address: &str, token0: &str, token1: &str) -> PoolData {
+        PoolData::new(
+            address.to_string(),
+            token0.to_string(),
+            token1.to_string(),
+            8453,
+            MOONSHOT,
+        )
+    }
+
+    #[test]
+    fn test_unrestricted_scope_tracks_everything() {
+        let scope = IndexingScope::new(vec![], None);
+        assert!(scope.is_unrestricted());
+        assert!(scope.is_tracked(&pool("0x01", TOKEN_A, TOKEN_B)));
+    }
+
+    #[test]
+    fn test_filter_by_pool_list() {
+        let scope = IndexingScope::new(vec!["0xAbC1".to_string(), "0x02".to_string()], None);
+
+        // Case-insensitive match on the pool address
+        assert!(scope.is_tracked(&pool("0xabc1", TOKEN_A, TOKEN_B)));
+        assert!(scope.is_tracked(&pool("0x02", TOKEN_B, TOKEN_C)));
+        assert!(!scope.is_tracked(&pool("0x03", TOKEN_A, TOKEN_B)));
+    }
+
+    #[test]
+    fn test_filter_by_token() {
+        let scope = IndexingScope::new(vec![], Some(TOKEN_A.to_lowercase()));
+
+        assert!(scope.is_tracked(&pool("0x01", TOKEN_A, TOKEN_B)));
+        assert!(scope.is_tracked(&pool("0x02", TOKEN_C, TOKEN_A)));
+        assert!(!scope.is_tracked(&pool("0x03", TOKEN_B, TOKEN_C)));
+    }
+
+    #[test]
+    fn test_pool_list_and_token_combined() {
+        let scope = IndexingScope::new(
+            vec!["0x01".to_string(), "0x03".to_string()],
+            Some(TOKEN_A.to_string()),
+        );
+
+        // Must be allowlisted and contain the token
+        assert!(scope.is_tracked(&pool("0x01", TOKEN_A, TOKEN_B)));
+        assert!(!scope.is_tracked(&pool("0x02", TOKEN_A, TOKEN_B)));
+        assert!(!scope.is_tracked(&pool("0x03", TOKEN_B, TOKEN_C)));
+    }
+
+    #[test]
+    fn test_watched_pools_bypass_filters() {
+        let mut scope = IndexingScope::new(vec!["0x01".to_string()], Some(TOKEN_A.to_string()))
+            .with_watched_pools(vec!["0xAB".to_string()]);
+        scope.watch_pool("0x03");
+
+        assert!(scope.is_tracked(&pool("0xab", TOKEN_B, TOKEN_C)));
+        assert!(scope.is_tracked(&pool("0x03", TOKEN_B, TOKEN_C)));
+        assert!(!scope.is_tracked(&pool("0x02", TOKEN_A, TOKEN_B)));
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use ethers::types::{Address, U256};
+use tracing::{debug, warn};
+
+use crate::db::Database;
+
+// Tokens per multicall request
+pub const SUPPLY_BATCH_SIZE: usize = 100;
+
+/// Batched `totalSupply()` reads.
+#[async_trait]
+pub trait SupplySource: Send + Sync {
+    /// Supplies in the order of `tokens`; `None` where the call reverted.
+    async fn total_supplies(&self, tokens: &[Address]) -> Result<Vec<Option<U256>>>;
+}
+
+/// True when `current` differs from `previous` by more than `threshold_bps` of `previous`.
+pub fn exceeds_threshold(previous: U256, current: U256, threshold_bps: u32) -> bool {
+    if previous.is_zero() {
+        return !current.is_zero();
+    }
+
+    let diff = if current > previous { current - previous } else { previous - current };
+    // diff / previous > bps / 10_000, in 512-bit arithmetic so large supplies cannot overflow
+    diff.full_mul(U256::from(10_000)) > previous.full_mul(U256::from(threshold_bps))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SupplyRefreshCounts {
+    pub refreshed: u64,
+    pub recorded: u64,
+    pub failed: u64,
+}
+
+/// Refreshes `total_supply` for every token in a tracked pool and appends a
+/// `token_supply_history` row for the first reading and for changes above the threshold.
+/// `now` is a unix timestamp so callers can simulate the clock.
+pub async fn refresh_supplies<S: SupplySource + ?Sized>(
+    database: &Database,
+    source: &S,
+    chain_id: i64,
+    threshold_bps: u32,
+    now: i64,
+) -> Result<SupplyRefreshCounts> {
+    let mut counts = SupplyRefreshCounts::default();
+    let tokens = database.get_tracked_pool_token_addresses(chain_id).await?;
+
+    for batch in tokens.chunks(SUPPLY_BATCH_SIZE) {
+        let addresses = batch.iter().map(|a| a.parse()).collect::<Result<Vec<Address>, _>>()?;
+        let supplies = source.total_supplies(&addresses).await?;
+
+        for (token_address, supply) in batch.iter().zip(supplies) {
+            let supply = match supply {
+                Some(supply) => supply,
+                None => {
+                    warn!("totalSupply() failed for token {}", token_address);
+                    counts.failed += 1;
+                    continue;
+                }
+            };
+
+            let previous = database
+                .get_token(token_address, chain_id)
+                .await?
+                .and_then(|token| token.total_supply)
+                .and_then(|supply| U256::from_dec_str(&supply).ok());
+
+            let changed = previous.is_none_or(|previous| exceeds_threshold(previous, supply, threshold_bps));
+            let supply = supply.to_string();
+
+            database.update_token_supply(token_address, chain_id, &supply, now).await?;
+            counts.refreshed += 1;
+
+            if changed {
+                database.insert_token_supply_history(token_address, chain_id, &supply, now).await?;
+                debug!("Token {} supply is now {}", token_address, supply);
+                counts.recorded += 1;
+            }
+        }
+    }
+
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold_is_exclusive() {
+        let previous = U256::from(1_000_000);
+
+        // 100 bps of 1_000_000 is exactly 10_000
+        assert!(!exceeds_threshold(previous, U256::from(1_010_000), 100));
+        assert!(exceeds_threshold(previous, U256::from(1_010_001), 100));
+        assert!(!exceeds_threshold(previous, U256::from(990_000), 100));
+        assert!(exceeds_threshold(previous, U256::from(989_999), 100));
+    }
+
+    #[test]
+    fn test_zero_threshold_records_any_change() {
+        let previous = U256::from(500);
+
+        assert!(!exceeds_threshold(previous, previous, 0));
+        assert!(exceeds_threshold(previous, U256::from(501), 0));
+    }
+
+    #[test]
+    fn test_zero_previous_supply() {
+        assert!(!exceeds_threshold(U256::zero(), U256::zero(), 100));
+        assert!(exceeds_threshold(U256::zero(), U256::one(), 100));
+    }
+
+    #[test]
+    fn test_large_supplies_do_not_overflow() {
+        let previous = U256::MAX / 2;
+        let current = previous + previous / 50; // +2%
+
+        assert!(exceeds_threshold(previous, current, 100));
+        assert!(!exceeds_threshold(previous, current, 300));
+    }
+}
@@ -0,0 +1,322 @@
+//! A scripted in-memory chain for running the indexer without a node.
+//!
+//! `MockChain` is a JSON-RPC transport: wrapped in a `Provider` it answers the calls the
+//! indexer makes from blocks, logs and `eth_call` results registered up front, e.g.
+//!
+//! ```ignore
+//! let chain = MockChain::new(100);
+//! chain.add_log(50, pool_created_log(factory, token0, token1, 3000, 60, pool));
+//! chain.on_call(token0, "symbol()", vec![Token::String("MOON".into())]);
+//! let indexer = Indexer::with_provider(config, chain.provider()).await?;
+//! ```
+
+use async_trait::async_trait;
+use ethers::abi::{decode, encode, ParamType, Token};
+use ethers::contract::MULTICALL_ADDRESS;
+use ethers::providers::{JsonRpcClient, JsonRpcError, MockError, Provider};
+use ethers::types::{
+    Address, Block, BlockNumber, Bytes, Filter, Log, TransactionReceipt, ValueOrArray, H256, I256, U256, U64,
+};
+use ethers::utils::{keccak256, serialize};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::moonshot::{get_factory_abi, get_pool_abi};
+
+/// Timestamp of block 0; each later block is `BLOCK_TIME_SECS` after the previous one.
+pub const GENESIS_TIMESTAMP: u64 = 1_700_000_000;
+pub const BLOCK_TIME_SECS: u64 = 2;
+/// Every block uses half of this gas limit.
+pub const BLOCK_GAS_LIMIT: u64 = 30_000_000;
+
+const AGGREGATE3: &str = "aggregate3((address,bool,bytes)[])";
+
+#[derive(Debug, Default)]
+struct ChainState {
+    head: u64,
+    chain_id: u64,
+    code: HashMap<Address, Bytes>,
+    logs: BTreeMap<u64, Vec<Log>>,
+    calls: HashMap<(Address, [u8; 4]), Bytes>,
+    receipts: HashMap<H256, TransactionReceipt>,
+    // Set once Multicall3 answers `aggregate3` from the registered calls
+    multicall: bool,
+    // Requests of a method that fail before it answers again
+    failures: HashMap<String, usize>,
+    requests: Vec<String>,
+    // Added to every response, like the round trip to a remote node
+    latency: Duration,
+}
+
+impl ChainState {
+    /// Output of Multicall3's `aggregate3` on `input`, None if a call that may not fail did.
+    fn aggregate3(&self, input: &[u8]) -> Option<Bytes> {
+        let call = ParamType::Tuple(vec![ParamType::Address, ParamType::Bool, ParamType::Bytes]);
+        let calls = decode(&[ParamType::Array(Box::new(call))], input).ok()?.pop()?.into_array()?;
+
+        let mut results = Vec::with_capacity(calls.len());
+        for call in calls {
+            let call = call.into_tuple()?;
+            let [Token::Address(target), Token::Bool(allow_failure), Token::Bytes(data)] = &call[..] else {
+                return None;
+            };
+            let answer = data.get(..4).and_then(|selector| self.calls.get(&(*target, selector.try_into().unwrap())));
+            match answer {
+                Some(output) => results.push(Token::Tuple(vec![Token::Bool(true), Token::Bytes(output.to_vec())])),
+                None if *allow_failure => results.push(Token::Tuple(vec![Token::Bool(false), Token::Bytes(Vec::new())])),
+                None => return None,
+            }
+        }
+        Some(encode(&[Token::Array(results)]).into())
+    }
+}
+
+/// A chain of blocks `0..=head` whose logs, call results and failures tests script.
+/// Clones share the same chain, so a test can keep scripting after handing it out.
+#[derive(Debug, Clone, Default)]
+pub struct MockChain {
+    state: Arc<Mutex<ChainState>>,
+}
+
+impl MockChain {
+    pub fn new(head: u64) -> Self {
+        let chain = Self::default();
+        chain.set_head(head);
+        chain
+    }
+
+    pub fn provider(&self) -> Arc<Provider<MockChain>> {
+        Arc::new(Provider::new(self.clone()))
+    }
+
+    pub fn set_head(&self, head: u64) {
+        self.state.lock().unwrap().head = head;
+    }
+
+    pub fn block_timestamp(block_number: u64) -> u64 {
+        GENESIS_TIMESTAMP + block_number * BLOCK_TIME_SECS
+    }
+
+    /// One gwei plus a wei per block, so each block's base fee is distinct.
+    pub fn block_base_fee(block_number: u64) -> u64 {
+        1_000_000_000 + block_number
+    }
+
+    /// Adds `log` to `block_number`, filling in its position: block, transaction hash when
+    /// unset, and the next log index in the block.
+    pub fn add_log(&self, block_number: u64, mut log: Log) -> Log {
+        let mut state = self.state.lock().unwrap();
+        let block_logs = state.logs.entry(block_number).or_default();
+
+        log.block_number = Some(U64::from(block_number));
+        log.block_hash = Some(block_hash(block_number));
+        log.log_index = Some(U256::from(block_logs.len()));
+        log.transaction_hash = log
+            .transaction_hash
+            .or_else(|| Some(H256::from(keccak256(format!("tx {} {}", block_number, block_logs.len())))));
+        log.removed = Some(false);
+        block_logs.push(log.clone());
+        log
+    }
+
+    /// Answers `eth_call`s of `signature` (e.g. `"symbol()"`) on `to` with `output`,
+    /// whatever the arguments. Calls without a registered answer revert.
+    pub fn on_call(&self, to: Address, signature: &str, output: Vec<Token>) {
+        let selector = selector(signature);
+        self.state.lock().unwrap().calls.insert((to, selector), encode(&output).into());
+    }
+
+    /// Deploys Multicall3 at its canonical address, answering each call of an `aggregate3`
+    /// like `eth_call` would. Without it, multicalls revert.
+    pub fn deploy_multicall(&self) {
+        self.state.lock().unwrap().multicall = true;
+    }
+
+    /// Answered by `eth_chainId`; 0 until set.
+    pub fn set_chain_id(&self, chain_id: u64) {
+        self.state.lock().unwrap().chain_id = chain_id;
+    }
+
+    /// Bytecode `eth_getCode` returns for `address`; other addresses have none.
+    pub fn deploy_code(&self, address: Address, code: Vec<u8>) {
+        self.state.lock().unwrap().code.insert(address, code.into());
+    }
+
+    pub fn add_receipt(&self, receipt: TransactionReceipt) {
+        self.state.lock().unwrap().receipts.insert(receipt.transaction_hash, receipt);
+    }
+
+    /// Fails the next `times` requests of `method` (e.g. `"eth_getLogs"`) with a JSON-RPC error.
+    pub fn fail_next(&self, method: &str, times: usize) {
+        self.state.lock().unwrap().failures.insert(method.to_string(), times);
+    }
+
+    /// Delays every response by `latency`, so concurrency shows up in timings as it
+    /// would against a remote node.
+    pub fn set_latency(&self, latency: Duration) {
+        self.state.lock().unwrap().latency = latency;
+    }
+
+    /// Methods requested so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    fn respond(&self, method: &str, params: Value) -> Result<Value, MockError> {
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(method.to_string());
+        if let Some(remaining) = state.failures.get_mut(method).filter(|remaining| **remaining > 0) {
+            *remaining -= 1;
+            return Err(rpc_error(-32000, &format!("scripted {} failure", method)));
+        }
+
+        match method {
+            "eth_blockNumber" => Ok(serialize(&U64::from(state.head))),
+            "eth_chainId" => Ok(serialize(&U64::from(state.chain_id))),
+            "eth_getCode" => {
+                let address: Address = serde_json::from_value(params[0].clone())?;
+                Ok(serde_json::to_value(state.code.get(&address).cloned().unwrap_or_default())?)
+            }
+            "eth_getBlockByNumber" => {
+                let number = match serde_json::from_value::<BlockNumber>(params[0].clone())? {
+                    BlockNumber::Number(number) => number.as_u64(),
+                    BlockNumber::Earliest => 0,
+                    _ => state.head,
+                };
+                if number > state.head {
+                    return Ok(Value::Null);
+                }
+                let block = Block::<H256> {
+                    hash: Some(block_hash(number)),
+                    parent_hash: block_hash(number.saturating_sub(1)),
+                    number: Some(U64::from(number)),
+                    timestamp: U256::from(Self::block_timestamp(number)),
+                    base_fee_per_gas: Some(U256::from(Self::block_base_fee(number))),
+                    gas_used: U256::from(BLOCK_GAS_LIMIT / 2),
+                    gas_limit: U256::from(BLOCK_GAS_LIMIT),
+                    ..Default::default()
+                };
+                Ok(serde_json::to_value(block)?)
+            }
+            "eth_getLogs" => {
+                let filter: Filter = serde_json::from_value(params[0].clone())?;
+                let from = filter.get_from_block().map_or(0, |block| block.as_u64());
+                let to = filter.get_to_block().map_or(state.head, |block| block.as_u64()).min(state.head);
+                if from > to {
+                    return Ok(Value::Array(Vec::new()));
+                }
+                let logs: Vec<&Log> = state
+                    .logs
+                    .range(from..=to)
+                    .flat_map(|(_, logs)| logs)
+                    .filter(|log| matches_filter(&filter, log))
+                    .collect();
+                Ok(serde_json::to_value(logs)?)
+            }
+            "eth_call" => {
+                let to: Option<Address> = serde_json::from_value(params[0]["to"].clone())?;
+                let data = params[0].get("input").or_else(|| params[0].get("data")).cloned().unwrap_or_default();
+                let data: Bytes = serde_json::from_value(data)?;
+                if state.multicall && to == Some(MULTICALL_ADDRESS) && data.get(..4) == Some(&selector(AGGREGATE3)[..]) {
+                    return match state.aggregate3(&data[4..]) {
+                        Some(output) => Ok(serde_json::to_value(output)?),
+                        None => Err(rpc_error(3, "execution reverted")),
+                    };
+                }
+                let answer = to
+                    .zip(data.get(..4))
+                    .and_then(|(to, selector)| state.calls.get(&(to, selector.try_into().unwrap())));
+                match answer {
+                    Some(output) => Ok(serde_json::to_value(output)?),
+                    None => Err(rpc_error(3, "execution reverted")),
+                }
+            }
+            "eth_getTransactionReceipt" => {
+                let hash: H256 = serde_json::from_value(params[0].clone())?;
+                Ok(serde_json::to_value(state.receipts.get(&hash))?)
+            }
+            _ => Err(rpc_error(-32601, &format!("MockChain does not support {}", method))),
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for MockChain {
+    type Error = MockError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, MockError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let params = serde_json::to_value(params)?;
+        let latency = self.state.lock().unwrap().latency;
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        Ok(serde_json::from_value(self.respond(method, params)?)?)
+    }
+}
+
+fn rpc_error(code: i64, message: &str) -> MockError {
+    MockError::JsonRpcError(JsonRpcError { code, message: message.to_string(), data: None })
+}
+
+fn block_hash(block_number: u64) -> H256 {
+    H256::from(keccak256(format!("block {}", block_number)))
+}
+
+fn selector(signature: &str) -> [u8; 4] {
+    keccak256(signature)[..4].try_into().unwrap()
+}
+
+fn matches_filter(filter: &Filter, log: &Log) -> bool {
+    let address_matches = match &filter.address {
+        Some(ValueOrArray::Value(address)) => log.address == *address,
+        Some(ValueOrArray::Array(addresses)) => addresses.is_empty() || addresses.contains(&log.address),
+        None => true,
+    };
+
+    address_matches
+        && filter.topics.iter().enumerate().all(|(i, topic)| match topic {
+            None | Some(ValueOrArray::Value(None)) => true,
+            Some(ValueOrArray::Value(Some(topic))) => log.topics.get(i) == Some(topic),
+            Some(ValueOrArray::Array(topics)) => {
+                topics.iter().any(|topic| topic.is_none() || log.topics.get(i) == topic.as_ref())
+            }
+        })
+}
+
+/// A factory `PoolCreated` log.
+pub fn pool_created_log(factory: Address, token0: Address, token1: Address, fee: u32, tick_spacing: i32, pool: Address) -> Log {
+    let event = get_factory_abi().event("PoolCreated").expect("factory ABI has PoolCreated").clone();
+    Log {
+        address: factory,
+        topics: vec![event.signature(), H256::from(token0), H256::from(token1), H256::from(pool)],
+        data: encode(&[Token::Uint(U256::from(fee)), Token::Int(I256::from(tick_spacing).into_raw())]).into(),
+        ..Default::default()
+    }
+}
+
+/// A pool `Swap` log; the positive amount is the one paid into the pool.
+pub fn swap_log(pool: Address, sender: Address, amount0: i64, amount1: i64, tick: i32) -> Log {
+    let event = get_pool_abi().event("Swap").expect("pool ABI has Swap").clone();
+    Log {
+        address: pool,
+        topics: vec![event.signature(), H256::from(sender), H256::from(sender)],
+        data: encode(&[
+            Token::Int(I256::from(amount0).into_raw()),
+            Token::Int(I256::from(amount1).into_raw()),
+            Token::Uint(U256::one() << 96),
+            Token::Uint(U256::from(10u64.pow(18))),
+            Token::Int(I256::from(tick).into_raw()),
+        ])
+        .into(),
+        ..Default::default()
+    }
+}
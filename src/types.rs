@@ -1,6 +1,15 @@
-use serde::{Deserialize, Serialize};
+use ethers::types::U256;
+use ethers::utils::hex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::ops::Deref;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::chain::chain_info;
+use crate::db::Database;
+use crate::dex::{DexId, MOONSHOT};
+use crate::price;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SwapEvent {
     pub tx_hash: String,
     pub pool_address: String,
@@ -14,9 +23,91 @@ pub struct SwapEvent {
     pub block_number: i64,
     pub log_index: i32,
     pub chain_id: i64,
+    #[serde(default)]
+    pub sender_address: Option<String>,
+    /// Who received the swap's output, as emitted in the `Swap` event.
+    #[serde(default)]
+    pub recipient_address: Option<String>,
+    /// Unix milliseconds at which the swap was written to the database.
+    #[serde(default)]
+    pub indexed_at: Option<i64>,
+    /// Input data of the swap's transaction, when an extension fetched it.
+    #[serde(default)]
+    pub calldata: Option<HexBytes>,
+    /// Pool tick after the swap, as emitted. Stored in `tick_history`, not read back with
+    /// the swap.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tick: Option<i32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Raw bytes that read and print as `0x`-prefixed hex.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct HexBytes(Vec<u8>);
+
+impl HexBytes {
+    pub fn from_bytes(b: &[u8]) -> HexBytes {
+        HexBytes(b.to_vec())
+    }
+
+    pub fn to_hex_string(&self) -> String {
+        format!("0x{}", hex::encode(&self.0))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<u8>> for HexBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        HexBytes(bytes)
+    }
+}
+
+impl TryFrom<&str> for HexBytes {
+    type Error = InvalidHexError;
+
+    /// Accepts hex with or without a `0x` prefix; `"0x"` alone is the empty byte string.
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).unwrap_or(value);
+        hex::decode(digits).map(HexBytes).map_err(|e| InvalidHexError {
+            reason: format!("{:?} is not valid hex: {}", value, e),
+        })
+    }
+}
+
+impl Deref for HexBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Display for HexBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex_string())
+    }
+}
+
+impl Serialize for HexBytes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for HexBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        HexBytes::try_from(value.as_str()).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PoolData {
     pub pool_address: String,
     pub token0_address: String,
@@ -31,17 +122,113 @@ pub struct PoolData {
     pub sqrt_price_x96: Option<String>,
     pub tick: Option<i32>,
     pub chain_id: i64,
-    pub dex_name: String,
+    pub dex_name: DexId,
+    #[serde(default)]
+    pub created_at_block: Option<i64>,
+    /// Block of the swap whose state refresh last changed the row, or the creation block.
+    /// Set by the indexer before `upsert_pool`; kept when the row is left unchanged.
+    #[serde(default)]
+    pub updated_at_block: Option<i64>,
+    /// Set once a pool token is seen charging transfer fees or rebasing.
+    #[serde(default)]
+    pub has_nonstandard_token: bool,
+    /// Unix seconds the row was first stored and last changed; read back from the database
+    /// and ignored by `upsert_pool`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<i64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A pool with its swap totals, as `Database::query_pool_stats_view` reads them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolStats {
+    #[serde(flatten)]
+    pub pool: PoolData,
+    pub swap_count: i64,
+    pub last_swap_ts: Option<i64>,
+    /// Sum over the swaps with a USD amount; `None` without any.
+    pub volume_usd: Option<f64>,
+}
+
+/// A pool with its age as of some block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolSummary {
+    #[serde(flatten)]
+    pub pool: PoolData,
+    pub age_blocks: Option<u64>,
+    pub age_days: Option<f64>,
+}
+
+impl PoolSummary {
+    pub fn new(pool: PoolData, current_block: u64, seconds_per_block: f64) -> Self {
+        let age_blocks = pool.age_blocks(current_block);
+        let age_days = pool.age_days(current_block, seconds_per_block);
+        Self { pool, age_blocks, age_days }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TokenData {
     pub address: String,
     pub name: Option<String>,
     pub symbol: Option<String>,
     pub decimals: Option<i32>,
     pub total_supply: Option<String>,
+    /// Unix time of the last supply refresh.
+    #[serde(default)]
+    pub supply_updated_at: Option<i64>,
+    #[serde(default)]
+    pub is_fee_on_transfer: bool,
+    #[serde(default)]
+    pub is_rebasing: bool,
+    /// Deployer, for tokens launched on the bonding curve.
+    #[serde(default)]
+    pub creator_address: Option<String>,
+    pub chain_id: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HolderBalance {
+    pub holder_address: String,
+    /// Raw balance as a decimal string.
+    pub balance: String,
+}
+
+/// The largest known holders of a token at a point in time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HolderSnapshot {
+    pub token_address: String,
     pub chain_id: i64,
+    pub taken_at: i64,
+    /// Largest balances first.
+    pub top_holders: Vec<HolderBalance>,
+    /// Share of the total supply held by the ten largest holders; None while the supply is unknown.
+    pub top10_concentration_pct: Option<f64>,
+}
+
+/// State of one initialized tick in a pool's `ticks` mapping.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TickData {
+    pub tick_index: i32,
+    pub liquidity_gross: u128,
+    /// Liquidity added (or, when negative, removed) when the price crosses the tick upwards.
+    pub liquidity_net: i128,
+    pub fee_growth_outside_0: U256,
+    pub fee_growth_outside_1: U256,
+}
+
+/// A liquidity position in a pool's `positions` mapping, with the range it was read for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PositionInfo {
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    /// Fees credited to the position when it was last touched, not yet collected.
+    pub tokens_owed_0: u128,
+    pub tokens_owed_1: u128,
+    pub fee_growth_inside_0_last: U256,
+    pub fee_growth_inside_1_last: U256,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,11 +237,406 @@ pub struct IndexingStats {
     pub total_pools_indexed: i64,
     pub total_swaps_indexed: i64,
     pub chain_id: i64,
-    pub dex_name: String,
+    pub dex_name: DexId,
+    /// Unix seconds.
     pub updated_at: i64,
+    /// Chain head when the row was last updated; None for samples taken without one.
+    #[serde(default)]
+    pub head_block: Option<i64>,
+}
+
+/// What changed between two `IndexingStats` samples. Deltas go negative if the database
+/// was reset in between.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexingStatsDiff {
+    pub new_pools: i64,
+    pub new_swaps: i64,
+    pub blocks_processed: i64,
+    pub elapsed_secs: i64,
+}
+
+impl IndexingStats {
+    /// Change from the earlier sample `other` to this one.
+    pub fn compare(&self, other: &IndexingStats) -> IndexingStatsDiff {
+        IndexingStatsDiff {
+            new_pools: self.total_pools_indexed - other.total_pools_indexed,
+            new_swaps: self.total_swaps_indexed - other.total_swaps_indexed,
+            blocks_processed: self.last_processed_block - other.last_processed_block,
+            elapsed_secs: self.updated_at - other.updated_at,
+        }
+    }
+
+    pub fn blocks_behind(&self) -> Option<i64> {
+        self.head_block.map(|head_block| (head_block - self.last_processed_block).max(0))
+    }
+
+    /// Seconds since the row was last updated, as of `now` (unix seconds).
+    pub fn update_age_secs(&self, now: i64) -> i64 {
+        (now - self.updated_at).max(0)
+    }
+
+    /// Whether the indexer behind the row went `stale_after_secs` without committing a range.
+    pub fn is_stale(&self, now: i64, stale_after_secs: u64) -> bool {
+        self.update_age_secs(now) > stale_after_secs as i64
+    }
+}
+
+/// Blocks without a swap after which a pool is likely dead.
+pub const LIKELY_DEAD_GAP_BLOCKS: i64 = 10_000;
+
+/// Gas market of one block, from its header.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GasStats {
+    pub chain_id: i64,
+    pub block_number: i64,
+    pub timestamp: i64,
+    /// In wei; None on chains without an EIP-1559 base fee.
+    pub base_fee_per_gas: Option<i64>,
+    /// Gas used over the block's gas limit.
+    pub gas_used_ratio: f64,
+    /// In wei, over the block's transactions; only receipt enrichment fills it in.
+    pub median_priority_fee: Option<i64>,
+}
+
+/// Swap activity of one block; blocks without swaps have zero counts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockStats {
+    pub block_number: i64,
+    pub swap_count: u64,
+    /// Pools with at least one swap in the block.
+    pub pool_count: u64,
+    /// None when no swap of the block has a USD amount.
+    pub volume_usd: Option<f64>,
+}
+
+/// The pool's own token balances as sampled with a snapshot, as decimal strings in the
+/// tokens' smallest units; `None` where the read failed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolBalanceSnapshot {
+    pub block_number: i64,
+    pub token0_balance: Option<String>,
+    pub token1_balance: Option<String>,
+}
+
+/// A pool's price as of a bucket boundary, from the last swap at or before it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PricePoint {
+    pub timestamp: i64,
+    pub tick: i32,
+    /// Token1 per token0, in whole tokens.
+    pub price: f64,
+}
+
+/// The last swap a pool made before a bucket ends, with the native token's USD price read
+/// before then, if any.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BucketSwap {
+    pub bucket_start: i64,
+    pub token_in: String,
+    pub amount_in: f64,
+    pub amount_out: f64,
+    pub block_number: i64,
+    /// Of the swap, which is in an earlier bucket when the pool had none in this one.
+    pub timestamp: i64,
+    pub native_price_usd: Option<f64>,
+}
+
+/// Blocks between two consecutive swaps of a pool, from the earlier swap's block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockGap {
+    pub from_block: i64,
+    pub to_block: i64,
+    pub gap_blocks: i64,
+}
+
+/// Swap totals for a pool up to and including a block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CumulativeVolume {
+    pub pool_address: String,
+    pub up_to_block: i64,
+    pub swap_count: i64,
+    pub token0_in: String,
+    pub token1_in: String,
+    /// Sum of `SwapEvent::volume_usd`; unpriced swaps count as nothing.
+    pub volume_usd: f64,
+    /// USD volume without swaps flagged as suspected wash trades.
+    pub volume_excluding_wash: f64,
+}
+
+/// A pool as of a block, with its swap totals up to it; what `/pools/:address/at/:block`
+/// returns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolAtBlock {
+    pub pool: PoolSummary,
+    pub cumulative_volume: CumulativeVolume,
+}
+
+/// Raw swap volume of a pool over a time window, per token and direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeBreakdown {
+    pub token0_as_input: i64,
+    pub token0_as_output: i64,
+    pub token1_as_input: i64,
+    pub token1_as_output: i64,
+    pub total_swap_count: u64,
+}
+
+/// LP fees a pool earned over a time window, per input token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolFeeRevenue {
+    pub pool_address: String,
+    pub fee_revenue_token0_raw: i64,
+    pub fee_revenue_token1_raw: i64,
+    /// None when none of the pool's swaps in the window have USD amounts.
+    pub fee_revenue_usd: Option<f64>,
+}
+
+/// What `Database::get_pool_ranking` orders pools by. The 24-hour metrics aggregate the
+/// swaps of the last day; `Liquidity` is the pool's current in-range liquidity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolRankingMetric {
+    Volume24h,
+    SwapCount24h,
+    Liquidity,
+    FeeRevenue24h,
+    UniqueTraders24h,
+}
+
+/// A pool's place in a ranking, 1 being the highest `metric_value`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolRank {
+    pub rank: u32,
+    pub pool_address: String,
+    pub metric_value: f64,
+    pub token0_symbol: Option<String>,
+    pub token1_symbol: Option<String>,
+    pub fee_tier: Option<i32>,
+}
+
+/// Percentiles and mean of a pool's swap input sizes in USD.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SwapSizeDistribution {
+    pub p10: f64,
+    pub p25: f64,
+    pub p50: f64,
+    pub p75: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub mean: f64,
+}
+
+/// A wallet's USD flows through indexed swaps.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WalletPnL {
+    pub total_spent_usd: f64,
+    pub total_received_usd: f64,
+    pub net_pnl_usd: f64,
+    pub swap_count: u64,
+}
+
+/// A sender's swaps over a time window.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SenderActivity {
+    pub sender_address: String,
+    pub swap_count: u64,
+    /// `None` when none of the swaps has a USD amount.
+    pub total_volume_usd: Option<f64>,
+    pub unique_pools: u64,
+}
+
+/// Chain-wide totals for the dashboard.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProtocolStats {
+    pub total_pools: u64,
+    pub total_swaps: u64,
+    /// None when no swap has a USD amount.
+    pub total_volume_usd: Option<f64>,
+    /// Pools with a swap in the last 24 hours.
+    pub active_pools_24h: u64,
+    /// Pools indexed in the last 24 hours.
+    pub new_pools_24h: u64,
+    /// Distinct tokens across all pools.
+    pub unique_tokens: u64,
+    /// Highest block with an indexed swap or pool creation; 0 before any.
+    pub last_block: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+impl CurveTrade {
+    /// Collateral paid or received per token, in raw units.
+    pub fn price(&self) -> Option<f64> {
+        let token_amount: f64 = self.token_amount.parse().ok()?;
+        let collateral_amount: f64 = self.collateral_amount.parse().ok()?;
+        (token_amount > 0.0).then(|| collateral_amount / token_amount)
+    }
+}
+
+impl TradeSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TradeSide::Buy => "buy",
+            TradeSide::Sell => "sell",
+        }
+    }
+
+    pub fn parse(side: &str) -> Option<Self> {
+        match side {
+            "buy" => Some(TradeSide::Buy),
+            "sell" => Some(TradeSide::Sell),
+            _ => None,
+        }
+    }
+}
+
+/// A buy or sell against a Moonshot bonding curve, before the token graduates to a pool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CurveTrade {
+    pub tx_hash: String,
+    pub log_index: i32,
+    pub block_number: i64,
+    pub timestamp: i64,
+    pub chain_id: i64,
+    pub curve_address: String,
+    pub token_address: String,
+    pub trader: String,
+    pub side: TradeSide,
+    /// Raw token amount bought or sold, as a decimal string.
+    pub token_amount: String,
+    /// Raw collateral amount paid or received, as a decimal string.
+    pub collateral_amount: String,
+}
+
+/// A curve token graduating: its liquidity migrates from the curve into an AMM pool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenMigration {
+    pub token_address: String,
+    /// The curve contract the token traded on before graduating.
+    pub curve_address: String,
+    pub pool_address: String,
+    pub tx_hash: String,
+    pub block_number: i64,
+    #[serde(default)]
+    pub log_index: i32,
+    pub timestamp: i64,
+    /// Collateral per token (raw units) of the last curve trade, if any were indexed.
+    pub final_curve_price: Option<f64>,
+    pub chain_id: i64,
+}
+
+/// Anything that happened to a token, for its timeline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "event", rename_all = "snake_case")]
+pub enum TokenEvent {
+    CurveTrade(CurveTrade),
+    Swap(SwapEvent),
+    /// A pool containing the token, placed at the start of its creation block.
+    PoolCreated(PoolData),
+    Migration(TokenMigration),
+}
+
+/// One page of a token timeline; `next_cursor` is set while more events follow.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenTimelinePage {
+    pub events: Vec<TokenEvent>,
+    pub next_cursor: Option<String>,
+}
+
+/// One page of the pools changed since some time, oldest change first; `next_cursor` is set
+/// while more pools follow.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolUpdatesPage {
+    pub pools: Vec<PoolData>,
+    pub next_cursor: Option<String>,
+}
+
+/// A decoded event that cannot have come from a well-formed log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidEventError {
+    pub reason: String,
+}
+
+impl fmt::Display for InvalidEventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid event: {}", self.reason)
+    }
+}
+
+impl std::error::Error for InvalidEventError {}
+
+/// A price conversion needed token decimals the pool does not have yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingDecimalsError {
+    pub pool_address: String,
+}
+
+impl fmt::Display for MissingDecimalsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pool {} is missing token decimals", self.pool_address)
+    }
 }
 
+impl std::error::Error for MissingDecimalsError {}
+
+/// A string that does not decode to `HexBytes`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidHexError {
+    pub reason: String,
+}
+
+impl fmt::Display for InvalidHexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid hex: {}", self.reason)
+    }
+}
+
+impl std::error::Error for InvalidHexError {}
+
+/// A string that is not a `0x`-prefixed 20-byte hex address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressParseError {
+    pub address: String,
+    pub reason: String,
+}
+
+impl fmt::Display for AddressParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid address {:?}: {}", self.address, self.reason)
+    }
+}
+
+impl std::error::Error for AddressParseError {}
+
 impl SwapEvent {
+    /// True when both sides of the swap are the same token. Impossible in a valid pool,
+    /// so this only shows up with corrupted logs.
+    pub fn is_self_loop(&self, pool: &PoolData) -> bool {
+        pool.resolve_token(&self.token_in).to_lowercase() == pool.resolve_token(&self.token_out).to_lowercase()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+
+    pub fn validate(&self) -> std::result::Result<(), InvalidEventError> {
+        let reason = if self.token_in.to_lowercase() == self.token_out.to_lowercase() {
+            format!("self-loop swap of {} in pool {}", self.token_in, self.pool_address)
+        } else if self.amount_in < 0 || self.amount_out < 0 {
+            format!("negative amounts {} -> {}", self.amount_in, self.amount_out)
+        } else {
+            return Ok(());
+        };
+
+        Err(InvalidEventError { reason })
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         tx_hash: String,
         pool_address: String,
@@ -80,8 +662,83 @@ impl SwapEvent {
             block_number,
             log_index,
             chain_id,
+            sender_address: None,
+            recipient_address: None,
+            indexed_at: None,
+            calldata: None,
+            tick: None,
+        }
+    }
+}
+
+impl SwapEvent {
+    pub fn explorer_tx_url(&self) -> Option<String> {
+        chain_info(self.chain_id).map(|chain| format!("{}/tx/{}", chain.explorer_url, self.tx_hash))
+    }
+
+    /// Amounts scaled by the pool's token decimals (18 when unknown), as (in, out).
+    pub fn human_amounts(&self, pool: &PoolData) -> (f64, f64) {
+        let decimals_in = pool.decimals_for(&self.token_in).unwrap_or(18);
+        let decimals_out = pool.decimals_for(&self.token_out).unwrap_or(18);
+
+        (
+            self.amount_in as f64 / 10f64.powi(decimals_in),
+            self.amount_out as f64 / 10f64.powi(decimals_out),
+        )
+    }
+
+    /// Price impact in basis points against the pool price at `tick_before`, the tick left
+    /// by the pool's previous swap: `(actual / expected - 1) * 10_000`, both prices in the
+    /// output token per input token and decimal adjusted. Large positive values mean the swap
+    /// executed better than the pool price; negative ones are slippage, the fee included.
+    pub fn compute_price_impact_bps(&self, pool: &PoolData, tick_before: i32) -> anyhow::Result<i64> {
+        if self.amount_in <= 0 || self.amount_out <= 0 {
+            anyhow::bail!("swap {}:{} has no amounts to price", self.tx_hash, self.log_index);
+        }
+        // tick_to_price quotes token0 in token1
+        let pool_price = pool.price_at_tick(tick_before)?;
+        let expected_price = if self.token_in == "token0" || self.token_in.eq_ignore_ascii_case(&pool.token0_address) {
+            pool_price
+        } else if self.token_in == "token1" || self.token_in.eq_ignore_ascii_case(&pool.token1_address) {
+            1.0 / pool_price
+        } else {
+            anyhow::bail!("token {} is not in pool {}", self.token_in, pool.pool_address);
+        };
+
+        let (amount_in, amount_out) = self.human_amounts(pool);
+        let actual_price = amount_out / amount_in;
+        Ok(((actual_price / expected_price - 1.0) * 10_000.0).round() as i64)
+    }
+
+    /// USD value of the swap: the mean of both sides when both are priced, otherwise
+    /// whichever side is. Volume queries use the same rule in SQL.
+    pub fn volume_usd(&self) -> Option<f64> {
+        match (self.amount_in_usd, self.amount_out_usd) {
+            (Some(amount_in_usd), Some(amount_out_usd)) => Some((amount_in_usd + amount_out_usd) / 2.0),
+            (amount_in_usd, amount_out_usd) => amount_in_usd.or(amount_out_usd),
         }
     }
+
+    pub fn has_usd_data(&self) -> bool {
+        self.volume_usd().is_some()
+    }
+}
+
+impl fmt::Display for SwapEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "swap {} {} -> {} {} in pool {} (block {}, tx {}:{})",
+            self.amount_in,
+            self.token_in,
+            self.amount_out,
+            self.token_out,
+            self.pool_address,
+            self.block_number,
+            self.tx_hash,
+            self.log_index
+        )
+    }
 }
 
 impl PoolData {
@@ -90,7 +747,7 @@ impl PoolData {
         token0_address: String,
         token1_address: String,
         chain_id: i64,
-        dex_name: String,
+        dex_name: DexId,
     ) -> Self {
         Self {
             pool_address,
@@ -107,6 +764,558 @@ impl PoolData {
             tick: None,
             chain_id,
             dex_name,
+            created_at_block: None,
+            updated_at_block: None,
+            has_nonstandard_token: false,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    /// A pool built field by field; see `PoolDataBuilder::build` for what is required.
+    pub fn builder() -> PoolDataBuilder {
+        PoolDataBuilder::default()
+    }
+}
+
+/// Builds a `PoolData` without spelling out every optional field.
+#[derive(Debug, Clone, Default)]
+pub struct PoolDataBuilder {
+    pool_address: Option<String>,
+    token0_address: Option<String>,
+    token1_address: Option<String>,
+    chain_id: Option<i64>,
+    dex_name: Option<String>,
+    fee_tier: Option<i32>,
+    tick_spacing: Option<i32>,
+    liquidity: Option<i64>,
+    token0_symbol: Option<String>,
+    token1_symbol: Option<String>,
+    token0_decimals: Option<i32>,
+    token1_decimals: Option<i32>,
+}
+
+impl PoolDataBuilder {
+    pub fn pool_address(mut self, address: &str) -> Self {
+        self.pool_address = Some(address.to_string());
+        self
+    }
+
+    pub fn token0(mut self, address: &str) -> Self {
+        self.token0_address = Some(address.to_string());
+        self
+    }
+
+    pub fn token1(mut self, address: &str) -> Self {
+        self.token1_address = Some(address.to_string());
+        self
+    }
+
+    pub fn chain_id(mut self, chain_id: i64) -> Self {
+        self.chain_id = Some(chain_id);
+        self
+    }
+
+    pub fn dex_name(mut self, dex_name: &str) -> Self {
+        self.dex_name = Some(dex_name.to_string());
+        self
+    }
+
+    pub fn fee_tier(mut self, fee_tier: i32) -> Self {
+        self.fee_tier = Some(fee_tier);
+        self
+    }
+
+    pub fn tick_spacing(mut self, tick_spacing: i32) -> Self {
+        self.tick_spacing = Some(tick_spacing);
+        self
+    }
+
+    pub fn liquidity(mut self, liquidity: i64) -> Self {
+        self.liquidity = Some(liquidity);
+        self
+    }
+
+    pub fn token0_symbol(mut self, symbol: &str) -> Self {
+        self.token0_symbol = Some(symbol.to_string());
+        self
+    }
+
+    pub fn token1_symbol(mut self, symbol: &str) -> Self {
+        self.token1_symbol = Some(symbol.to_string());
+        self
+    }
+
+    pub fn token0_decimals(mut self, decimals: i32) -> Self {
+        self.token0_decimals = Some(decimals);
+        self
+    }
+
+    pub fn token1_decimals(mut self, decimals: i32) -> Self {
+        self.token1_decimals = Some(decimals);
+        self
+    }
+
+    /// The pool, once its address, both tokens and chain are set. The addresses must be
+    /// well-formed, the tokens distinct and the DEX id canonical; it defaults to `moonshot`.
+    pub fn build(self) -> anyhow::Result<PoolData> {
+        let pool_address = self.pool_address.ok_or_else(|| anyhow::anyhow!("pool address is required"))?;
+        let token0_address = self.token0_address.ok_or_else(|| anyhow::anyhow!("token0 is required"))?;
+        let token1_address = self.token1_address.ok_or_else(|| anyhow::anyhow!("token1 is required"))?;
+        let chain_id = self.chain_id.ok_or_else(|| anyhow::anyhow!("chain id is required"))?;
+
+        parse_address(&pool_address)?;
+        if parse_address(&token0_address)? == parse_address(&token1_address)? {
+            anyhow::bail!("token0 and token1 are both {}", token0_address);
+        }
+        if chain_id <= 0 {
+            anyhow::bail!("chain id must be positive, got {}", chain_id);
+        }
+        if let Some(fee_tier) = self.fee_tier.filter(|fee_tier| *fee_tier < 0) {
+            anyhow::bail!("fee tier must not be negative, got {}", fee_tier);
+        }
+        if let Some(tick_spacing) = self.tick_spacing.filter(|tick_spacing| *tick_spacing <= 0) {
+            anyhow::bail!("tick spacing must be positive, got {}", tick_spacing);
         }
+        // Amounts are U256, whose largest power of ten is 10^77
+        for decimals in [self.token0_decimals, self.token1_decimals].into_iter().flatten() {
+            if !(0..=77).contains(&decimals) {
+                anyhow::bail!("token decimals must be between 0 and 77, got {}", decimals);
+            }
+        }
+
+        let dex_name = self.dex_name.as_deref().map(DexId::new).transpose()?.unwrap_or(MOONSHOT);
+        let mut pool = PoolData::new(pool_address, token0_address, token1_address, chain_id, dex_name);
+        pool.fee_tier = self.fee_tier;
+        pool.tick_spacing = self.tick_spacing;
+        pool.liquidity = self.liquidity;
+        pool.token0_symbol = self.token0_symbol;
+        pool.token1_symbol = self.token1_symbol;
+        pool.token0_decimals = self.token0_decimals;
+        pool.token1_decimals = self.token1_decimals;
+        Ok(pool)
+    }
+}
+
+impl PoolData {
+    /// Whether the pool ever went `gap_threshold` blocks or more without a swap;
+    /// `LIKELY_DEAD_GAP_BLOCKS` is the usual threshold.
+    pub async fn is_likely_dead(&self, db: &Database, gap_threshold: i64) -> anyhow::Result<bool> {
+        let gaps = db.get_consecutive_no_swap_blocks(&self.pool_address, self.chain_id, gap_threshold).await?;
+        Ok(!gaps.is_empty())
+    }
+
+    /// Blocks since the pool was created; None when its creation block is unknown or after
+    /// `current_block`.
+    pub fn age_blocks(&self, current_block: u64) -> Option<u64> {
+        let created_at_block = u64::try_from(self.created_at_block?).ok()?;
+        current_block.checked_sub(created_at_block)
+    }
+
+    /// `age_blocks` in days at `seconds_per_block`.
+    pub fn age_days(&self, current_block: u64, seconds_per_block: f64) -> Option<f64> {
+        self.age_blocks(current_block).map(|blocks| blocks as f64 * seconds_per_block / 86_400.0)
+    }
+
+    pub fn explorer_address_url(&self) -> Option<String> {
+        chain_info(self.chain_id)
+            .map(|chain| format!("{}/address/{}", chain.explorer_url, self.pool_address))
+    }
+
+    pub fn canonical_address(&self) -> std::result::Result<[u8; 20], AddressParseError> {
+        parse_address(&self.pool_address)
+    }
+
+    pub fn token0_canonical(&self) -> std::result::Result<[u8; 20], AddressParseError> {
+        parse_address(&self.token0_address)
+    }
+
+    pub fn token1_canonical(&self) -> std::result::Result<[u8; 20], AddressParseError> {
+        parse_address(&self.token1_address)
+    }
+
+    fn decimals(&self) -> std::result::Result<(i32, i32), MissingDecimalsError> {
+        match (self.token0_decimals, self.token1_decimals) {
+            (Some(decimals0), Some(decimals1)) => Ok((decimals0, decimals1)),
+            _ => Err(MissingDecimalsError { pool_address: self.pool_address.clone() }),
+        }
+    }
+
+    /// `price::tick_to_price` using the pool's own decimals.
+    pub fn price_at_tick(&self, tick: i32) -> anyhow::Result<f64> {
+        let (decimals0, decimals1) = self.decimals()?;
+        Ok(price::tick_to_price(tick, decimals0, decimals1))
+    }
+
+    /// `price::price_to_tick` using the pool's own decimals.
+    pub fn implied_tick_from_price(&self, price: f64) -> anyhow::Result<i32> {
+        let (decimals0, decimals1) = self.decimals()?;
+        price::price_to_tick(price, decimals0, decimals1)
+    }
+
+    /// Price of token0 in token1 at the pool's current tick, adjusted for decimals.
+    pub fn current_price(&self) -> anyhow::Result<f64> {
+        let tick = self.tick.ok_or_else(|| anyhow::anyhow!("pool {} has no current tick", self.pool_address))?;
+        self.price_at_tick(tick)
+    }
+
+    /// How far `current_price` is from an external `oracle_price` of token0 in token1, as a
+    /// fraction of the oracle price: 0.01 is 1% above it, -0.01 1% below.
+    pub fn spot_price_deviation_from_oracle(&self, oracle_price: f64) -> anyhow::Result<f64> {
+        if !(oracle_price.is_finite() && oracle_price > 0.0) {
+            anyhow::bail!("oracle price must be a positive number, got {}", oracle_price);
+        }
+        Ok(self.current_price()? / oracle_price - 1.0)
+    }
+
+    /// Whether the spot price deviates from `oracle_price` by more than `threshold`, a
+    /// fraction like `spot_price_deviation_from_oracle`'s, in either direction.
+    pub fn is_arbitrageable(&self, oracle_price: f64, threshold: f64) -> anyhow::Result<bool> {
+        Ok(self.spot_price_deviation_from_oracle(oracle_price)?.abs() > threshold)
+    }
+
+    pub fn is_price_in_range(&self, price: f64, lower_tick: i32, upper_tick: i32) -> anyhow::Result<bool> {
+        let tick = self.implied_tick_from_price(price)?;
+        Ok(price::within_tick_range(tick, lower_tick, upper_tick))
+    }
+
+    /// Whether a position over `[lower_tick, upper_tick)` earns fees at the pool's current
+    /// tick; false while the tick is unknown. Same as `price::within_tick_range`.
+    pub fn is_concentrated_in_range(&self, lower_tick: i32, upper_tick: i32) -> bool {
+        self.tick.is_some_and(|tick| price::within_tick_range(tick, lower_tick, upper_tick))
+    }
+
+    /// How far the current price is from the nearest bound of `[lower_tick, upper_tick)`,
+    /// as a percentage of the range's width in price: 0 at a bound, 50 at the midpoint
+    /// price. Measured from outside just the same for a price out of range.
+    pub fn distance_to_range_pct(&self, lower_tick: i32, upper_tick: i32, token0_decimals: u8, token1_decimals: u8) -> anyhow::Result<f64> {
+        if lower_tick >= upper_tick {
+            anyhow::bail!("lower tick {} must be below upper tick {}", lower_tick, upper_tick);
+        }
+        let tick = self.tick.ok_or_else(|| anyhow::anyhow!("pool {} has no current tick", self.pool_address))?;
+
+        let to_price = |tick| price::tick_to_price(tick, token0_decimals as i32, token1_decimals as i32);
+        let (current, lower, upper) = (to_price(tick), to_price(lower_tick), to_price(upper_tick));
+        let distance = (current - lower).abs().min((upper - current).abs());
+        Ok(distance / (upper - lower) * 100.0)
+    }
+
+    /// Address for a token given as "token0"/"token1"; anything else is returned as is.
+    pub fn resolve_token<'a>(&'a self, token: &'a str) -> &'a str {
+        match token {
+            "token0" => &self.token0_address,
+            "token1" => &self.token1_address,
+            _ => token,
+        }
+    }
+
+    /// Decimals for a token given as "token0"/"token1" (as decoded from swaps) or an address.
+    pub fn decimals_for(&self, token: &str) -> Option<i32> {
+        if token == "token0" || token.eq_ignore_ascii_case(&self.token0_address) {
+            self.token0_decimals
+        } else if token == "token1" || token.eq_ignore_ascii_case(&self.token1_address) {
+            self.token1_decimals
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for PoolData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} {} pool {}",
+            self.token0_symbol.as_deref().unwrap_or("Unknown"),
+            self.token1_symbol.as_deref().unwrap_or("Unknown"),
+            self.dex_name,
+            self.pool_address
+        )?;
+        if let Some(fee_tier) = self.fee_tier {
+            write!(f, " (fee {})", fee_tier)?;
+        }
+        Ok(())
+    }
+}
+
+/// Percentage label of a fee tier in hundredths of a bip, e.g. `"0.05%"` for 500; `"other"`
+/// for tiers outside the standard ones.
+pub fn fee_tier_pct_label(fee_tier: i32) -> &'static str {
+    match fee_tier {
+        100 => "0.01%",
+        500 => "0.05%",
+        2500 => "0.25%",
+        3000 => "0.3%",
+        10000 => "1%",
+        _ => "other",
+    }
+}
+
+/// Canonical spelling of an address for comparisons: trimmed, lowercase, `0x`-prefixed.
+/// The hex itself is not validated.
+pub fn normalize_address(address: &str) -> String {
+    let address = address.trim();
+    let digits = address.strip_prefix("0x").or_else(|| address.strip_prefix("0X")).unwrap_or(address);
+    format!("0x{}", digits.to_ascii_lowercase())
+}
+
+/// The 20 bytes of a `0x`-prefixed hex address, in either case.
+pub fn parse_address(address: &str) -> std::result::Result<[u8; 20], AddressParseError> {
+    let error = |reason: String| AddressParseError { address: address.to_string(), reason };
+    let digits = address
+        .strip_prefix("0x")
+        .or_else(|| address.strip_prefix("0X"))
+        .ok_or_else(|| error("missing 0x prefix".to_string()))?;
+    if digits.len() != 40 {
+        return Err(error(format!("expected 40 hex digits, got {}", digits.len())));
+    }
+
+    let mut bytes = [0u8; 20];
+    hex::decode_to_slice(digits, &mut bytes).map_err(|e| error(e.to_string()))?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(last_processed_block: i64, pools: i64, swaps: i64, updated_at: i64) -> IndexingStats {
+        IndexingStats {
+            last_processed_block,
+            total_pools_indexed: pools,
+            total_swaps_indexed: swaps,
+            chain_id: 2741,
+            dex_name: MOONSHOT,
+            updated_at,
+            head_block: None,
+        }
+    }
+
+    #[test]
+    fn test_indexing_stats_compare() {
+        let earlier = stats(1_000, 10, 500, 1_700_000_000);
+        let later = stats(1_015, 12, 620, 1_700_000_030);
+        let diff = later.compare(&earlier);
+        assert_eq!(diff, IndexingStatsDiff { new_pools: 2, new_swaps: 120, blocks_processed: 15, elapsed_secs: 30 });
+        assert_eq!(earlier.compare(&earlier), IndexingStatsDiff::default());
+
+        // After a database reset the totals go backwards
+        let reset = stats(1_016, 0, 3, 1_700_000_031);
+        assert_eq!(reset.compare(&later), IndexingStatsDiff { new_pools: -12, new_swaps: -617, blocks_processed: 1, elapsed_secs: 1 });
+    }
+
+    #[test]
+    fn test_hex_bytes_round_trip() {
+        for bytes in [Vec::new(), vec![0xab], (0..32).collect::<Vec<u8>>()] {
+            let value = HexBytes::from_bytes(&bytes);
+            let text = value.to_hex_string();
+            assert_eq!(text.len(), 2 + 2 * bytes.len());
+            assert_eq!(HexBytes::try_from(text.as_str()).unwrap(), value);
+
+            let json = serde_json::to_string(&value).unwrap();
+            assert_eq!(json, format!("\"{}\"", text));
+            assert_eq!(serde_json::from_str::<HexBytes>(&json).unwrap(), value);
+
+            assert_eq!(&*value, bytes.as_slice());
+            assert_eq!((value.len(), value.is_empty()), (bytes.len(), bytes.is_empty()));
+        }
+
+        assert_eq!(HexBytes::from_bytes(&[0xab]).to_string(), "0xab");
+    }
+
+    #[test]
+    fn test_hex_bytes_rejects_invalid_hex() {
+        assert_eq!(HexBytes::try_from("ABCD").unwrap(), HexBytes::from_bytes(&[0xab, 0xcd]));
+        assert!(HexBytes::try_from("0xabc").is_err());
+        assert!(HexBytes::try_from("0xzz").is_err());
+        assert!(serde_json::from_str::<HexBytes>("\"0x1\"").is_err());
+    }
+
+    #[test]
+    fn test_normalize_address() {
+        let expected = "0x00000000000000000000000000000000000000ab";
+        assert_eq!(normalize_address("0x00000000000000000000000000000000000000AB"), expected);
+        assert_eq!(normalize_address(" 0X00000000000000000000000000000000000000ab\n"), expected);
+        assert_eq!(normalize_address("00000000000000000000000000000000000000Ab"), expected);
+    }
+
+    #[test]
+    fn test_canonical_addresses() {
+        let mut pool = PoolData::new(
+            "0x0000000000000000000000000000000000000000".to_string(),
+            "0x00000000000000000000000000000000000000aB".to_string(),
+            "0x4200000000000000000000000000000000000006".to_string(),
+            2741,
+            MOONSHOT,
+        );
+        assert_eq!(pool.canonical_address().unwrap(), [0u8; 20]);
+
+        let mut token0 = [0u8; 20];
+        token0[19] = 0xab;
+        assert_eq!(pool.token0_canonical().unwrap(), token0);
+        let token1 = pool.token1_canonical().unwrap();
+        assert_eq!((token1[0], token1[19]), (0x42, 0x06));
+
+        for malformed in ["", "0x", "4200000000000000000000000000000000000006", "0x42", "0xzz00000000000000000000000000000000000006"] {
+            pool.pool_address = malformed.to_string();
+            let error = pool.canonical_address().unwrap_err();
+            assert_eq!(error.address, malformed);
+        }
+    }
+
+    #[test]
+    fn test_pool_age() {
+        let mut pool = PoolData::new(
+            "0x00000000000000000000000000000000000000aa".to_string(),
+            "0x00000000000000000000000000000000000000a0".to_string(),
+            "0x00000000000000000000000000000000000000a1".to_string(),
+            1,
+            MOONSHOT,
+        );
+        assert_eq!(pool.age_blocks(2_000), None);
+
+        pool.created_at_block = Some(1_000);
+        assert_eq!(pool.age_blocks(2_000), Some(1_000));
+        let age_days = pool.age_days(2_000, 12.0).unwrap();
+        assert!((age_days - 0.139).abs() < 0.001, "{}", age_days);
+        assert_eq!(pool.age_blocks(999), None);
+
+        let summary = PoolSummary::new(pool, 2_000, 12.0);
+        assert_eq!((summary.age_blocks, summary.age_days), (Some(1_000), Some(age_days)));
+    }
+
+    #[test]
+    fn test_price_impact_bps() {
+        let mut pool = PoolData::new(
+            "0x00000000000000000000000000000000000000aa".to_string(),
+            "0x00000000000000000000000000000000000000a0".to_string(),
+            "0x00000000000000000000000000000000000000a1".to_string(),
+            1,
+            MOONSHOT,
+        );
+        let swap = |token_in: &str, token_out: &str, amount_in: i64, amount_out: i64| {
+            SwapEvent::new("0xabc".to_string(), pool.pool_address.clone(), token_in.to_string(), token_out.to_string(), amount_in, amount_out, 0, 1, 0, 1)
+        };
+        let sell = swap("token0", "token1", 1_000, 990);
+        let buy = swap("0x00000000000000000000000000000000000000A1", "token0", 2_000, 1_000);
+        let stranger = swap("0x00000000000000000000000000000000000000ff", "token0", 1_000, 1_000);
+
+        // Decimals are required
+        assert!(sell.compute_price_impact_bps(&pool, 0).is_err());
+        pool.token0_decimals = Some(18);
+        pool.token1_decimals = Some(18);
+
+        // 1% short of a price of 1 is 100 bps of slippage; at tick 6932 token0 costs 2 token1
+        assert_eq!(sell.compute_price_impact_bps(&pool, 0).unwrap(), -100);
+        assert_eq!(swap("token1", "token0", 1_000, 1_010).compute_price_impact_bps(&pool, 0).unwrap(), 100);
+        assert_eq!(buy.compute_price_impact_bps(&pool, 6932).unwrap(), 0);
+        assert!(stranger.compute_price_impact_bps(&pool, 0).is_err());
+    }
+
+    #[test]
+    fn test_range_checks_use_the_current_tick() {
+        let mut pool = PoolData::new("0xpool".to_string(), "0xa".to_string(), "0xb".to_string(), 8453, MOONSHOT);
+        assert!(!pool.is_concentrated_in_range(-60, 60));
+        assert!(pool.distance_to_range_pct(-60, 60, 18, 18).is_err());
+
+        pool.tick = Some(0);
+        assert!(pool.is_concentrated_in_range(-60, 60));
+        assert!(!pool.is_concentrated_in_range(60, 120));
+        assert!(pool.distance_to_range_pct(60, -60, 18, 18).is_err());
+
+        // The tick midpoint sits a little below the price midpoint of a wide range
+        let distance = pool.distance_to_range_pct(-1_000, 1_000, 18, 18).unwrap();
+        assert!((distance - 50.0).abs() < 3.0, "{}", distance);
+        assert!(pool.distance_to_range_pct(0, 1_000, 18, 6).unwrap().abs() < 1e-9);
+        // Outside the range the distance is to the nearer bound
+        let below = pool.distance_to_range_pct(1_000, 2_000, 18, 18).unwrap();
+        assert!(below > 90.0 && below < 100.0, "{}", below);
+    }
+
+    #[test]
+    fn test_spot_price_deviation_from_oracle() {
+        let mut pool = PoolData::new("0xpool".to_string(), "0xa".to_string(), "0xb".to_string(), 8453, MOONSHOT);
+        pool.tick = Some(-887);
+
+        // Decimals and the current tick are both needed
+        assert!(pool.spot_price_deviation_from_oracle(1.0).is_err());
+        pool.token0_decimals = Some(18);
+        pool.token1_decimals = Some(18);
+        let spot = pool.current_price().unwrap();
+        assert!((spot - 1.0001f64.powi(-887)).abs() < 1e-12);
+
+        // 2% above the oracle trips a 1% threshold but not a 3% one
+        let oracle = spot / 1.02;
+        assert!((pool.spot_price_deviation_from_oracle(oracle).unwrap() - 0.02).abs() < 1e-12);
+        assert!(pool.is_arbitrageable(oracle, 0.01).unwrap());
+        assert!(!pool.is_arbitrageable(oracle, 0.03).unwrap());
+
+        // 2% below counts the same
+        let oracle = spot / 0.98;
+        assert!((pool.spot_price_deviation_from_oracle(oracle).unwrap() + 0.02).abs() < 1e-12);
+        assert!(pool.is_arbitrageable(oracle, 0.01).unwrap());
+
+        for oracle in [0.0, -1.0, f64::NAN] {
+            assert!(pool.spot_price_deviation_from_oracle(oracle).is_err(), "{} was accepted", oracle);
+        }
+        pool.tick = None;
+        assert!(pool.is_arbitrageable(spot, 0.01).is_err());
+    }
+
+    #[test]
+    fn test_volume_usd() {
+        let mut swap = SwapEvent::new(
+            "0xabc".to_string(),
+            "0x00000000000000000000000000000000000000aa".to_string(),
+            "token0".to_string(),
+            "token1".to_string(),
+            1000,
+            950,
+            1640995200,
+            12345,
+            0,
+            8453,
+        );
+        assert_eq!(swap.volume_usd(), None);
+        assert!(!swap.has_usd_data());
+
+        swap.amount_in_usd = Some(100.0);
+        assert_eq!(swap.volume_usd(), Some(100.0));
+        swap.amount_out_usd = Some(98.0);
+        assert_eq!(swap.volume_usd(), Some(99.0));
+        swap.amount_in_usd = None;
+        assert_eq!(swap.volume_usd(), Some(98.0));
+        assert!(swap.has_usd_data());
+    }
+
+    #[test]
+    fn test_fee_tier_labels() {
+        assert_eq!(fee_tier_pct_label(500), "0.05%");
+        assert_eq!(fee_tier_pct_label(3000), "0.3%");
+        assert_eq!(fee_tier_pct_label(10000), "1%");
+        assert_eq!(fee_tier_pct_label(1234), "other");
+    }
+
+    #[test]
+    fn test_pool_builder() {
+        let builder = PoolData::builder()
+            .pool_address("0x00000000000000000000000000000000000000aa")
+            .token0("0x00000000000000000000000000000000000000a0")
+            .token1("0x00000000000000000000000000000000000000a1")
+            .chain_id(2741);
+        let pool = builder.clone().fee_tier(3000).tick_spacing(60).token0_symbol("MOON").token1_decimals(18).build().unwrap();
+        assert_eq!(pool.dex_name, "moonshot");
+        assert_eq!((pool.fee_tier, pool.tick_spacing, pool.liquidity), (Some(3000), Some(60), None));
+        assert_eq!((pool.token0_symbol.as_deref(), pool.token1_decimals), (Some("MOON"), Some(18)));
+        assert_eq!(builder.clone().dex_name("OtherSwap").build().unwrap().dex_name, "otherswap");
+
+        let error = |builder: PoolDataBuilder| builder.build().unwrap_err().to_string();
+        assert_eq!(error(PoolData::builder().chain_id(2741)), "pool address is required");
+        assert!(error(builder.clone().token1("0x00000000000000000000000000000000000000A0")).contains("both"));
+        assert!(error(builder.clone().pool_address("0xaa")).contains("invalid address"));
+        assert!(error(builder.clone().chain_id(0)).contains("chain id"));
+        assert!(error(builder.clone().tick_spacing(0)).contains("tick spacing"));
+        assert!(error(builder.clone().dex_name("other swap")).contains("invalid DEX id"));
+        assert!(error(builder.token0_decimals(78)).contains("decimals"));
     }
 }
@@ -0,0 +1,245 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tracing::{debug, error, info};
+
+use crate::config::Config;
+use crate::db::Database;
+use crate::types::{PoolData, SwapEvent};
+
+// Most wallets trading back and forth together that still look like one operator
+const MAX_CLUSTER_WALLETS: usize = 3;
+
+#[derive(Debug, Clone, Copy)]
+pub struct WashPolicy {
+    pub chain_id: i64,
+    pub window_secs: i64,
+    pub net_threshold_bps: u32,
+}
+
+impl WashPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            chain_id: config.chain_id as i64,
+            window_secs: config.wash_window_secs as i64,
+            net_threshold_bps: config.wash_net_threshold_bps,
+        }
+    }
+}
+
+/// The wallet a swap trades for: its recipient, else its sender.
+fn wallet(swap: &SwapEvent) -> Option<String> {
+    swap.recipient_address.as_ref().or(swap.sender_address.as_ref()).map(|wallet| wallet.to_lowercase())
+}
+
+/// How much token0 the swap's wallet gained (negative when it sold token0).
+fn token0_delta(swap: &SwapEvent, pool: Option<&PoolData>) -> Option<i128> {
+    let is_token0 = |token: &str| token == "token0" || pool.is_some_and(|pool| token.eq_ignore_ascii_case(&pool.token0_address));
+    if is_token0(&swap.token_in) {
+        Some(-(swap.amount_in as i128))
+    } else if is_token0(&swap.token_out) {
+        Some(swap.amount_out as i128)
+    } else {
+        None
+    }
+}
+
+/// Suspected wash trades among one pool's swaps, given in log order; the result lines up
+/// with `swaps`.
+///
+/// A swap is a self-trade when its sender is its recipient and it is the only swap of its
+/// transaction (multi-hop routes send intermediate output back to the router). Otherwise the
+/// swaps are cut into sessions of `window_secs` from the first swap of each, and within a
+/// session the wallets that both bought and sold token0 and ended within
+/// `net_threshold_bps` of where they started are a cluster. A cluster of at most three
+/// wallets has all of its session's swaps flagged; one-way traders never are.
+pub fn score_pool_swaps(swaps: &[SwapEvent], pool: Option<&PoolData>, policy: &WashPolicy) -> Vec<bool> {
+    let mut flags = vec![false; swaps.len()];
+
+    let mut swaps_per_tx: HashMap<&str, usize> = HashMap::new();
+    for swap in swaps {
+        *swaps_per_tx.entry(&swap.tx_hash).or_default() += 1;
+    }
+    for (flag, swap) in flags.iter_mut().zip(swaps) {
+        if let (Some(sender), Some(recipient)) = (&swap.sender_address, &swap.recipient_address) {
+            *flag = sender.eq_ignore_ascii_case(recipient) && swaps_per_tx[swap.tx_hash.as_str()] == 1;
+        }
+    }
+
+    let mut start = 0;
+    while start < swaps.len() {
+        let window_end = swaps[start].timestamp.saturating_add(policy.window_secs);
+        let end = (start..swaps.len()).find(|i| swaps[*i].timestamp >= window_end).unwrap_or(swaps.len());
+
+        // Per wallet: (net token0 change, gross token0 traded, bought, sold)
+        let mut positions: HashMap<String, (i128, i128, bool, bool)> = HashMap::new();
+        for swap in &swaps[start..end] {
+            let (Some(wallet), Some(delta)) = (wallet(swap), token0_delta(swap, pool)) else { continue };
+            let position = positions.entry(wallet).or_default();
+            position.0 += delta;
+            position.1 += delta.abs();
+            position.2 |= delta > 0;
+            position.3 |= delta < 0;
+        }
+        let cluster: Vec<&String> = positions
+            .iter()
+            .filter(|(_, (net, gross, bought, sold))| {
+                *bought && *sold && net.abs() * 10_000 <= *gross * policy.net_threshold_bps as i128
+            })
+            .map(|(wallet, _)| wallet)
+            .collect();
+
+        if !cluster.is_empty() && cluster.len() <= MAX_CLUSTER_WALLETS {
+            for i in start..end {
+                if wallet(&swaps[i]).is_some_and(|wallet| cluster.contains(&&wallet)) {
+                    flags[i] = true;
+                }
+            }
+        }
+        start = end;
+    }
+
+    flags
+}
+
+/// Scores swaps with `from_ts <= timestamp < to_ts` pool by pool and stores the flags,
+/// clearing those no longer suspected. Swaps up to a window earlier are read as context so
+/// clusters straddling `from_ts` are seen whole. Returns how many swaps are flagged.
+pub async fn score_range(database: &Database, policy: &WashPolicy, from_ts: i64, to_ts: i64) -> Result<u64> {
+    let swaps = database
+        .get_swaps_in_time_range(policy.chain_id, from_ts.saturating_sub(policy.window_secs), to_ts)
+        .await?;
+
+    let mut by_pool: HashMap<String, Vec<SwapEvent>> = HashMap::new();
+    for swap in swaps {
+        by_pool.entry(swap.pool_address.clone()).or_default().push(swap);
+    }
+
+    let mut flags = Vec::new();
+    for (pool_address, pool_swaps) in &by_pool {
+        let pool = database.get_pool(pool_address).await?;
+        let scored = score_pool_swaps(pool_swaps, pool.as_ref(), policy);
+        flags.extend(pool_swaps.iter().zip(scored).filter(|(swap, _)| swap.timestamp >= from_ts));
+    }
+
+    let flagged = flags.iter().filter(|(_, flag)| *flag).count() as u64;
+    database.set_swap_wash_flags(policy.chain_id, &flags).await?;
+    debug!("Flagged {} suspected wash swaps with timestamps {} to {}", flagged, from_ts, to_ts);
+    Ok(flagged)
+}
+
+/// Scores recent swaps on its own task every `interval`, away from the indexing loop. Each
+/// pass covers the last two intervals so swaps indexed late in one pass are scored again
+/// with their neighbours; older ranges are scored with the `score-wash` command.
+pub fn spawn_worker(database: Database, policy: WashPolicy, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+            let from_ts = now - 2 * interval.as_secs() as i64;
+            match score_range(&database, &policy, from_ts, now + 1).await {
+                Ok(flagged) if flagged > 0 => info!("Wash-trading pass - {} swaps suspected", flagged),
+                Ok(_) => {}
+                Err(e) => error!("Wash-trading pass failed: {}", e),
+            }
+            sleep(interval).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dex::MOONSHOT;
+
+    const POLICY: WashPolicy = WashPolicy { chain_id: 2741, window_secs: 300, net_threshold_bps: 100 };
+
+    // `wallet` buys (gains token0) or sells `amount` of token0 at `timestamp`
+    fn swap(tx: u64, timestamp: i64, wallet: &str, buys: bool, amount: i64) -> SwapEvent {
+        let (token_in, token_out) = if buys { ("token1", "token0") } else { ("token0", "token1") };
+        let mut swap = SwapEvent::new(
+            format!("0x{:064x}", tx),
+            "0x00000000000000000000000000000000000000aa".to_string(),
+            token_in.to_string(),
+            token_out.to_string(),
+            amount,
+            amount,
+            timestamp,
+            timestamp,
+            0,
+            2741,
+        );
+        swap.sender_address = Some("0xrouter".to_string());
+        swap.recipient_address = Some(wallet.to_string());
+        swap
+    }
+
+    #[test]
+    fn test_self_trade() {
+        let mut self_trade = swap(1, 1_000, "0xa", true, 500);
+        self_trade.sender_address = Some("0xA".to_string());
+        let mut hop = swap(2, 1_000, "0xrouter", true, 500);
+        hop.sender_address = Some("0xrouter".to_string());
+        let mut next_hop = hop.clone();
+        next_hop.log_index = 1;
+
+        let flags = score_pool_swaps(&[self_trade, hop, next_hop], None, &POLICY);
+        assert_eq!(flags, vec![true, false, false]);
+    }
+
+    #[test]
+    fn test_two_wallets_trading_back_and_forth() {
+        let swaps = [
+            swap(1, 1_000, "0xa", true, 1_000),
+            swap(2, 1_010, "0xb", false, 1_000),
+            swap(3, 1_020, "0xa", false, 1_000),
+            swap(4, 1_030, "0xb", true, 995),
+            // A genuine buyer in the same window is left alone
+            swap(5, 1_040, "0xc", true, 400),
+        ];
+        assert_eq!(score_pool_swaps(&swaps, None, &POLICY), vec![true, true, true, true, false]);
+
+        // Same pattern spread over more than the window
+        let mut spread = swaps.clone();
+        spread[2].timestamp = 1_400;
+        spread[3].timestamp = 1_410;
+        spread[4].timestamp = 1_420;
+        assert_eq!(score_pool_swaps(&spread, None, &POLICY), vec![false; 5]);
+    }
+
+    #[test]
+    fn test_genuine_two_party_trading() {
+        // One buys what the other sells
+        let swaps = [swap(1, 1_000, "0xa", true, 1_000), swap(2, 1_010, "0xb", false, 1_000)];
+        assert_eq!(score_pool_swaps(&swaps, None, &POLICY), vec![false, false]);
+
+        // A trader takes partial profit: both directions, but far from flat
+        let swaps = [
+            swap(1, 1_000, "0xa", true, 1_000),
+            swap(2, 1_010, "0xb", true, 2_000),
+            swap(3, 1_020, "0xa", false, 600),
+        ];
+        assert_eq!(score_pool_swaps(&swaps, None, &POLICY), vec![false, false, false]);
+    }
+
+    #[test]
+    fn test_tokens_by_address_need_the_pool() {
+        let pool = PoolData::new(
+            "0x00000000000000000000000000000000000000aa".to_string(),
+            "0x00000000000000000000000000000000000000e0".to_string(),
+            "0x00000000000000000000000000000000000000e1".to_string(),
+            2741,
+            MOONSHOT,
+        );
+        let by_address = |mut swap: SwapEvent| {
+            swap.token_in = pool.resolve_token(&swap.token_in).to_string();
+            swap.token_out = pool.resolve_token(&swap.token_out).to_string();
+            swap
+        };
+        let swaps = [by_address(swap(1, 1_000, "0xa", true, 1_000)), by_address(swap(2, 1_010, "0xa", false, 1_000))];
+
+        assert_eq!(score_pool_swaps(&swaps, Some(&pool), &POLICY), vec![true, true]);
+        assert_eq!(score_pool_swaps(&swaps, None, &POLICY), vec![false, false]);
+    }
+}
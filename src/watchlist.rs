@@ -0,0 +1,126 @@
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+use crate::correlation;
+use crate::db::Database;
+use crate::dex::MOONSHOT;
+use crate::error::IndexerError;
+use crate::lifecycle::PoolStatus;
+use crate::pool_state::{self, PoolStateReader};
+use crate::types::{normalize_address, parse_address, PoolData};
+
+// Watch requests that can wait for the indexer's next poll
+const WATCH_QUEUE_CAPACITY: usize = 100;
+
+/// A pool queued to watch, with the id of the request that asked for it so the indexer
+/// logs its handling under the same id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedPool {
+    pub pool_address: String,
+    pub request_id: Option<String>,
+}
+
+/// Queues pools for a running indexer to watch. Clones share the queue and the set of
+/// watched pools, so each pool is queued once however many callers ask for it.
+#[derive(Debug, Clone)]
+pub struct PoolWatcher {
+    sender: mpsc::Sender<QueuedPool>,
+    watched: Arc<Mutex<HashSet<String>>>,
+}
+
+impl PoolWatcher {
+    /// A watcher already watching `watched`, and the receiving end of its queue.
+    pub fn new(watched: impl IntoIterator<Item = String>) -> (Self, mpsc::Receiver<QueuedPool>) {
+        let (sender, receiver) = mpsc::channel(WATCH_QUEUE_CAPACITY);
+        let watched = watched.into_iter().map(|pool| normalize_address(&pool)).collect();
+        (Self { sender, watched: Arc::new(Mutex::new(watched)) }, receiver)
+    }
+
+    /// Queues the pool unless it is watched already, which fails with
+    /// `IndexerError::AlreadyWatched`; a malformed address fails with `AddressParseError`.
+    /// The pool carries the caller's request id, if any.
+    pub fn watch(&self, pool_address: &str) -> Result<()> {
+        parse_address(pool_address)?;
+        let pool_address = normalize_address(pool_address);
+
+        let mut watched = self.watched.lock().unwrap();
+        if watched.contains(&pool_address) {
+            return Err(IndexerError::AlreadyWatched { pool_address }.into());
+        }
+        self.sender
+            .try_send(QueuedPool { pool_address: pool_address.clone(), request_id: correlation::current() })
+            .map_err(|e| anyhow!("cannot queue pool {}: {}", pool_address, e))?;
+        watched.insert(pool_address);
+        Ok(())
+    }
+
+    pub fn is_watched(&self, pool_address: &str) -> bool {
+        self.watched.lock().unwrap().contains(&normalize_address(pool_address))
+    }
+
+    /// Every watched pool, including those still queued.
+    pub fn watched_pools(&self) -> Vec<String> {
+        self.watched.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Puts the pool on the watchlist and in the swap registry, active again if it had gone
+/// stale. A pool the indexer has not seen is registered from its on-chain state first;
+/// that pool is returned.
+pub async fn add_pool<R: PoolStateReader + ?Sized>(
+    database: &Database,
+    reader: &R,
+    chain_id: i64,
+    pool_address: &str,
+    added_at: i64,
+) -> Result<Option<PoolData>> {
+    database.insert_watched_pool(pool_address, chain_id, added_at).await?;
+
+    if database.get_pool(pool_address).await?.is_some() {
+        database.set_pool_tracked(pool_address, true).await?;
+        database.set_pool_status(pool_address, PoolStatus::Active).await?;
+        return Ok(None);
+    }
+
+    let skeleton = PoolData::new(pool_address.to_string(), String::new(), String::new(), chain_id, MOONSHOT);
+    let update = pool_state::refresh(reader, &skeleton).await?;
+    let pool = update.pool;
+    if pool.token0_address.is_empty() || pool.token1_address.is_empty() {
+        bail!("could not read the tokens of watched pool {}: {:?}", pool_address, update.errors);
+    }
+
+    database.upsert_pool(&pool).await?;
+    database.set_pool_tracked(&pool.pool_address, true).await?;
+    Ok(Some(pool))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AddressParseError;
+
+    const POOL: &str = "0x00000000000000000000000000000000000000AB";
+
+    #[tokio::test]
+    async fn test_watch_queues_each_pool_once() {
+        let (watcher, mut receiver) = PoolWatcher::new(vec!["0x00000000000000000000000000000000000000cd".to_string()]);
+
+        correlation::scope("watch-1".to_string(), async { watcher.watch(POOL) }).await.unwrap();
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            QueuedPool { pool_address: POOL.to_lowercase(), request_id: Some("watch-1".to_string()) }
+        );
+        assert!(watcher.is_watched(POOL));
+
+        let err = watcher.clone().watch(&POOL.to_lowercase()).unwrap_err();
+        assert!(matches!(err.downcast_ref::<IndexerError>(), Some(IndexerError::AlreadyWatched { .. })));
+        let err = watcher.watch("0x00000000000000000000000000000000000000CD").unwrap_err();
+        assert!(err.downcast_ref::<IndexerError>().is_some());
+
+        let err = watcher.watch("0xnot-a-pool").unwrap_err();
+        assert!(err.downcast_ref::<AddressParseError>().is_some());
+        assert!(receiver.try_recv().is_err());
+    }
+}
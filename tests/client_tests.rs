@@ -0,0 +1,229 @@
+use futures::TryStreamExt;
+use moonshot_indexer::{
+    api::{self, ApiState},
+    client::{ClientError, IndexerClient, TimelineFilter},
+    correlation::REQUEST_ID_HEADER,
+    db::Database,
+    dex::MOONSHOT,
+    migration::TimelineEntry,
+    types::{PoolData, SwapEvent, TokenEvent, TokenMigration},
+    watchlist::PoolWatcher,
+};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+fn unique_id() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos()
+}
+
+/// Serves the API over the test database on a free local port, returning its base URL.
+async fn serve(state: ApiState) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, api::router(state)).await.unwrap() });
+    format!("http://{}", address)
+}
+
+fn client_error(err: &anyhow::Error) -> &ClientError {
+    err.downcast_ref::<ClientError>().expect("an API error")
+}
+
+#[tokio::test]
+async fn test_client_against_the_api() {
+    dotenv::dotenv().ok();
+    let database = Database::new(&env::var("DATABASE_URL").expect("DATABASE_URL must be set")).await.unwrap();
+    database.init_schema().await.unwrap();
+
+    // A launch token graduating into a pool in block 10, with five swaps, one per block
+    let chain_id = 27_000_000 + (unique_id() % 1_000_000) as i64;
+    let token = format!("0x{:040x}", unique_id());
+    let mut pool = PoolData::new(
+        format!("0x{:040x}", unique_id()),
+        token.clone(),
+        "0x00000000000000000000000000000000000000a1".to_string(),
+        chain_id,
+        MOONSHOT,
+    );
+    pool.created_at_block = Some(10);
+    database.upsert_pool(&pool).await.unwrap();
+    database.upsert_block(10, 1_700_000_000, chain_id).await.unwrap();
+    database
+        .insert_token_migration(&TokenMigration {
+            token_address: token.clone(),
+            curve_address: format!("0x{:040x}", unique_id()),
+            pool_address: pool.pool_address.clone(),
+            tx_hash: format!("0x{:064x}", unique_id()),
+            block_number: 10,
+            log_index: 0,
+            timestamp: 1_700_000_000,
+            final_curve_price: None,
+            chain_id,
+        })
+        .await
+        .unwrap();
+    for block_number in 10..15 {
+        let mut swap = SwapEvent::new(
+            format!("0x{:064x}", unique_id()),
+            pool.pool_address.clone(),
+            token.clone(),
+            pool.token1_address.clone(),
+            1000,
+            950,
+            1_700_000_000 + block_number,
+            block_number,
+            1,
+            chain_id,
+        );
+        swap.amount_in_usd = Some(2.0);
+        database.insert_swap(&swap).await.unwrap();
+    }
+
+    let (watcher, _watch_requests) = PoolWatcher::new(Vec::new());
+    let base_url = serve(ApiState::new(database, chain_id).with_pool_watcher(watcher)).await;
+    let client = IndexerClient::new(&format!("{}/", base_url), Some("secret"));
+
+    let at_block = client.pool_at_block(&pool.pool_address, 12, false).await.unwrap();
+    assert_eq!(at_block.pool.pool.pool_address, pool.pool_address);
+    assert_eq!(at_block.pool.age_blocks, Some(2));
+    assert_eq!(at_block.cumulative_volume.swap_count, 3);
+
+    let err = client.pool_at_block("0x00000000000000000000000000000000000000ff", 12, false).await.unwrap_err();
+    assert!(client_error(&err).is_not_found());
+
+    // The stream pages through the whole timeline, two events at a time
+    let filter = TimelineFilter { limit: Some(2), ..Default::default() };
+    let events: Vec<TokenEvent> = client.token_timeline(&token, filter).try_collect().await.unwrap();
+    assert_eq!(events.len(), 7);
+    assert!(matches!(&events[0], TokenEvent::PoolCreated(created) if created.pool_address == pool.pool_address));
+    assert!(matches!(&events[1], TokenEvent::Migration(_)));
+    assert!(events[2..].iter().all(|event| matches!(event, TokenEvent::Swap(_))));
+    let page = client.token_timeline_page(&token, &TimelineFilter::default(), None).await.unwrap();
+    assert_eq!(page.events.len(), 7);
+    assert!(page.next_cursor.is_none());
+
+    let err = client.token_timeline_page(&token, &TimelineFilter::default(), Some("not-a-cursor")).await.unwrap_err();
+    assert!(client_error(&err).is_client_error());
+
+    let timeline = client.token(&token).await.unwrap();
+    assert_eq!(timeline.migration.unwrap().pool_address, pool.pool_address);
+    assert_eq!(timeline.timeline.len(), 5);
+    assert!(timeline.timeline.iter().all(|entry| matches!(entry, TimelineEntry::Amm(_))));
+
+    let stats = client.stats().await.unwrap();
+    assert_eq!((stats.total_pools, stats.total_swaps, stats.last_block), (1, 5, 14));
+    assert_eq!(stats.total_volume_usd, Some(10.0));
+
+    // Error bodies come back as the API's message
+    let err = client.gas(20, 10).await.unwrap_err();
+    assert_eq!(client_error(&err), &ClientError { status: 400, message: "from_ts must be before to_ts".to_string() });
+
+    client.watch_pool(&pool.pool_address).await.unwrap();
+    let err = client.watch_pool(&pool.pool_address).await.unwrap_err();
+    assert_eq!(client_error(&err).status, 409);
+}
+
+// Name and `request_id` of a span
+type CapturedSpan = (String, Option<String>);
+
+/// Records every span opened.
+#[derive(Clone, Default)]
+struct SpanCapture {
+    spans: Arc<Mutex<Vec<CapturedSpan>>>,
+}
+
+impl SpanCapture {
+    fn with_request_id(&self, request_id: &str) -> Vec<String> {
+        let spans = self.spans.lock().unwrap();
+        spans.iter().filter(|(_, id)| id.as_deref() == Some(request_id)).map(|(name, _)| name.clone()).collect()
+    }
+}
+
+struct RequestIdVisitor(Option<String>);
+
+impl Visit for RequestIdVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "request_id" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "request_id" {
+            self.0 = Some(format!("{:?}", value));
+        }
+    }
+}
+
+impl<S: tracing::Subscriber> Layer<S> for SpanCapture {
+    fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+        let mut visitor = RequestIdVisitor(None);
+        attrs.record(&mut visitor);
+        self.spans.lock().unwrap().push((attrs.metadata().name().to_string(), visitor.0));
+    }
+}
+
+#[tokio::test]
+async fn test_request_ids_reach_headers_spans_and_errors() {
+    // The test runtime has one thread, so the server's tasks report to this subscriber too
+    let capture = SpanCapture::default();
+    let _subscriber = tracing::subscriber::set_default(tracing_subscriber::registry().with(capture.clone()));
+
+    dotenv::dotenv().ok();
+    let database = Database::new(&env::var("DATABASE_URL").expect("DATABASE_URL must be set")).await.unwrap();
+    let chain_id = 35_000_000 + (unique_id() % 1_000_000) as i64;
+    let base_url = serve(ApiState::new(database, chain_id)).await;
+    let http = reqwest::Client::new();
+
+    // An incoming id is echoed and carried down to the database statements
+    let response = http
+        .get(format!("{}/gas?from_ts=0&to_ts=10", base_url))
+        .header(REQUEST_ID_HEADER, "gas-lookup-1")
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()[REQUEST_ID_HEADER], "gas-lookup-1");
+    let spans = capture.with_request_id("gas-lookup-1");
+    assert!(spans.contains(&"request".to_string()) && spans.contains(&"db".to_string()), "spans: {:?}", spans);
+
+    // A failing request without an id gets a fresh one, in the header and the error body
+    let response = http.get(format!("{}/gas?from_ts=20&to_ts=10", base_url)).send().await.unwrap();
+    assert_eq!(response.status(), 400);
+    let request_id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap().to_string();
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "from_ts must be before to_ts");
+    assert_eq!(body["request_id"], request_id.as_str());
+    assert_eq!(capture.with_request_id(&request_id), vec!["request".to_string()]);
+
+    // Ids that cannot be logged or echoed safely are replaced
+    let response = http.get(format!("{}/health", base_url)).header(REQUEST_ID_HEADER, "two words").send().await.unwrap();
+    assert_ne!(response.headers()[REQUEST_ID_HEADER], "two words");
+}
+
+#[tokio::test]
+async fn test_updated_pools_dex_filter() {
+    dotenv::dotenv().ok();
+    let database = Database::new(&env::var("DATABASE_URL").expect("DATABASE_URL must be set")).await.unwrap();
+    database.init_schema().await.unwrap();
+    let chain_id = 42_000_000 + (unique_id() % 1_000_000) as i64;
+    let mut pool = PoolData::new(format!("0x{:040x}", unique_id()), "0xa0".to_string(), "0xa1".to_string(), chain_id, MOONSHOT);
+    pool.liquidity = Some(0);
+    database.upsert_pool(&pool).await.unwrap();
+    let base_url = serve(ApiState::new(database, chain_id)).await;
+    let http = reqwest::Client::new();
+
+    let page = |dex: &'static str| http.get(format!("{}/pools/updated?since=0&dex={}", base_url, dex)).send();
+    let body: serde_json::Value = page("MoonShot").await.unwrap().json().await.unwrap();
+    assert_eq!(body["pools"][0]["pool_address"], pool.pool_address.as_str());
+    let body: serde_json::Value = page("uniswap-v3").await.unwrap().json().await.unwrap();
+    assert_eq!(body["pools"], serde_json::json!([]));
+
+    let response = page("sushiswap").await.unwrap();
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "unknown DEX \"sushiswap\"; known DEXes: moonshot, uniswap-v3");
+}
@@ -0,0 +1,2783 @@
+use ethers::abi::{encode, Token};
+use ethers::types::{Address, Log, H256, U256, U64};
+use async_trait::async_trait;
+use flate2::read::GzDecoder;
+use moonshot_indexer::{
+    archive::{ArchivePolicy, Archiver, ObjectStore},
+    budget::MemoryBudget,
+    candles::{self, PriceAnchors, RebuildOptions},
+    chain::{BlockHeader, BlockHeaders, CachedHeaders},
+    coordination,
+    db::{Database, PoolUpdateCursor, QueryCounts, QueryLimits, TimelineCursor},
+    dex::{self, DexRegistry, MOONSHOT},
+    doctor,
+    error::IndexerError,
+    enrich::{self, EnrichCounts, EnrichPolicy},
+    holders::{self, HolderSnapshotCounts, HolderSnapshotPolicy, HolderSource},
+    lifecycle::{self, LifecyclePolicy, PoolStatus},
+    maintenance::{self, MaintenancePolicy},
+    metrics,
+    mev::{self, MevType},
+    native_price::NativePriceTracker,
+    price,
+    wash::{self, WashPolicy},
+    nonstandard::TokenBehavior,
+    scope::{self, IndexingScope},
+    supply::{self, SupplyRefreshCounts, SupplySource},
+    migration::{self, TimelineEntry},
+    moonshot::{get_curve_abi, CurveEvent, CurveHandler},
+    pool_state::{CallErrorKind, PoolStateReader, PoolStateReads},
+    replay::{self, JsonlSink, ReplayOptions, Sink, SinkOffsetStore},
+    types::{
+        BlockGap, BlockStats, CurveTrade, GasStats, HexBytes, InvalidEventError, MissingDecimalsError, PoolData, PoolRank, PoolRankingMetric, ProtocolStats, SwapEvent, SwapSizeDistribution, TickData, TokenData, TokenEvent, TokenMigration,
+        SenderActivity, TradeSide, VolumeBreakdown, WalletPnL, LIKELY_DEAD_GAP_BLOCKS,
+    },
+};
+use proptest::prelude::*;
+use proptest::test_runner::{TestCaseError, TestRunner};
+use std::env;
+use std::io::Read;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+mod strategies;
+
+// Concurrent CREATE TABLE IF NOT EXISTS can race in Postgres, so tests initialise one at a time
+static SCHEMA_LOCK: Mutex<()> = Mutex::const_new(());
+
+async fn test_database() -> Database {
+    dotenv::dotenv().ok();
+
+    let db_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let database = Database::new(&db_url).await.expect("Should connect to database");
+
+    let _guard = SCHEMA_LOCK.lock().await;
+    database.init_schema().await.expect("Should initialise schema");
+    database
+}
+
+fn unique_id() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos()
+}
+
+fn swap(tx_hash: &str, log_index: i32, block_number: i64) -> SwapEvent {
+    SwapEvent::new(
+        tx_hash.to_string(),
+        "0x00000000000000000000000000000000000000aa".to_string(),
+        "token0".to_string(),
+        "token1".to_string(),
+        1000,
+        950,
+        1640995200,
+        block_number,
+        log_index,
+        8453,
+    )
+}
+
+fn pool(pool_address: &str, created_at_block: i64) -> PoolData {
+    let mut pool = PoolData::new(
+        pool_address.to_string(),
+        "0x00000000000000000000000000000000000000a0".to_string(),
+        "0x00000000000000000000000000000000000000a1".to_string(),
+        8453,
+        MOONSHOT,
+    );
+    pool.liquidity = Some(0);
+    pool.created_at_block = Some(created_at_block);
+    pool
+}
+
+#[tokio::test]
+async fn test_reads_and_writes_use_their_own_pools() {
+    let database = test_database().await;
+    let db_url = env::var("DATABASE_URL").unwrap();
+    // Both sides on the test database stand in for a primary and its replica
+    let split = Database::with_read_url(&db_url, Some(&db_url)).await.unwrap();
+    let pool_address = format!("0x{:040x}", unique_id());
+    let chain_id = (unique_id() % 1_000_000_000) as i64;
+
+    assert_eq!(split.query_counts(), QueryCounts::default());
+    split.upsert_pool(&pool(&pool_address, 1_000)).await.unwrap();
+    assert_eq!(split.query_counts(), QueryCounts { reads: 0, writes: 1 });
+    assert!(split.get_pool(&pool_address).await.unwrap().is_some());
+    assert!(split.get_swaps_in_time_range(chain_id, 0, 1).await.unwrap().is_empty());
+    assert_eq!(split.query_counts(), QueryCounts { reads: 2, writes: 1 });
+
+    // Swaps written through one handle are read back through the other's replica
+    let mut indexed = swap(&format!("0x{:064x}", unique_id()), 0, 4_200);
+    indexed.chain_id = chain_id;
+    database.insert_swap(&indexed).await.unwrap();
+    let lag = split.replica_lag(chain_id).await.unwrap();
+    assert_eq!((lag.primary_block, lag.replica_block, lag.blocks()), (4_200, 4_200, 0));
+    assert_eq!(split.query_counts(), QueryCounts { reads: 3, writes: 2 });
+
+    // Without a read URL both sides share one pool but are still counted apart
+    let single = Database::new(&db_url).await.unwrap();
+    single.get_pool(&pool_address).await.unwrap();
+    assert_eq!(single.query_counts(), QueryCounts { reads: 1, writes: 0 });
+}
+
+/// Holds the row lock of `sink_name`'s offset for `seconds`, stalling writes to it.
+async fn hold_sink_offset(sink_name: &str, seconds: f64) -> tokio::task::JoinHandle<()> {
+    let pool = sqlx::PgPool::connect(&env::var("DATABASE_URL").unwrap()).await.unwrap();
+    let mut tx = pool.begin().await.unwrap();
+    sqlx::query("SELECT 1 FROM sink_offsets WHERE sink_name = $1 FOR UPDATE")
+        .bind(sink_name)
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+    tokio::spawn(async move {
+        sqlx::query("SELECT pg_sleep($1)").bind(seconds).execute(&mut *tx).await.unwrap();
+        tx.commit().await.unwrap();
+    })
+}
+
+#[tokio::test]
+async fn test_slow_and_timed_out_statements() {
+    let database = test_database().await;
+    let sink_name = format!("slow_{}", unique_id());
+    let cursor = |block_number| TimelineCursor::of(&TokenEvent::Swap(swap("0x5104", 0, block_number)));
+    database.set_sink_offset(&sink_name, 8453, &cursor(100)).await.unwrap();
+
+    let slow_count = || {
+        metrics::render()
+            .lines()
+            .find(|line| line.starts_with("moonshot_db_slow_queries_total{statement=\"set_sink_offset\"}"))
+            .and_then(|line| line.rsplit(' ').next()?.parse::<u64>().ok())
+            .unwrap_or(0)
+    };
+
+    // A stalled write that finishes in time is only logged as slow
+    let slow_before = slow_count();
+    let limits = QueryLimits { slow_query_threshold: Duration::from_millis(100), ..QueryLimits::default() };
+    let patient = database.clone().with_limits(limits);
+    let release = hold_sink_offset(&sink_name, 0.4).await;
+    patient.set_sink_offset(&sink_name, 8453, &cursor(101)).await.unwrap();
+    release.await.unwrap();
+    assert!(slow_count() > slow_before);
+    assert!(metrics::render().contains("moonshot_db_query_seconds_count{statement=\"set_sink_offset\"}"));
+
+    // One that does not fails with a retryable timeout
+    let limits = QueryLimits { write_timeout: Duration::from_millis(100), ..QueryLimits::default() };
+    let impatient = database.clone().with_limits(limits);
+    let release = hold_sink_offset(&sink_name, 1.5).await;
+    let started = Instant::now();
+    let err = impatient.set_sink_offset(&sink_name, 8453, &cursor(102)).await.unwrap_err();
+    let elapsed = started.elapsed();
+    release.await.unwrap();
+    let err = err.downcast_ref::<IndexerError>().unwrap();
+    assert_eq!(
+        err,
+        &IndexerError::Database { statement: "set_sink_offset", timeout: Duration::from_millis(100), retryable: true }
+    );
+
+    // Postgres cancelled the statement itself: well before the client-side backstop, and it
+    // did not go on to write once the lock was released
+    assert!(elapsed < Duration::from_secs(1), "took {:?}", elapsed);
+    assert_eq!(database.get_sink_offset(&sink_name, 8453).await.unwrap(), Some(cursor(101)));
+}
+
+#[tokio::test]
+async fn test_get_swap_by_tx_hash_returns_all_log_indices() {
+    let database = test_database().await;
+    let tx_hash = "0x1124000000000000000000000000000000000000000000000000000000000001";
+
+    // Insert out of order to check the ordering
+    database.insert_swap(&swap(tx_hash, 7, 1124)).await.unwrap();
+    database.insert_swap(&swap(tx_hash, 3, 1124)).await.unwrap();
+
+    let swaps = database.get_swap_by_tx_hash(tx_hash, 8453).await.unwrap();
+    assert_eq!(swaps.len(), 2);
+    assert_eq!(swaps[0].log_index, 3);
+    assert_eq!(swaps[1].log_index, 7);
+    assert!(swaps.iter().all(|s| s.tx_hash == tx_hash && s.amount_in == 1000));
+
+    // Other chains are not mixed in
+    let other_chain = database.get_swap_by_tx_hash(tx_hash, 1).await.unwrap();
+    assert!(other_chain.is_empty());
+}
+
+#[tokio::test]
+async fn test_get_swaps_by_block_and_sender() {
+    let database = test_database().await;
+    let sender = "0x0000000000000000000000000000000000001124";
+
+    let mut first = swap("0x1124000000000000000000000000000000000000000000000000000000000002", 0, 112400);
+    first.sender_address = Some(sender.to_string());
+    let mut second = swap("0x1124000000000000000000000000000000000000000000000000000000000003", 1, 112400);
+    second.sender_address = Some(sender.to_string());
+    let other_sender = swap("0x1124000000000000000000000000000000000000000000000000000000000004", 2, 112400);
+
+    database.insert_swap(&first).await.unwrap();
+    database.insert_swap(&second).await.unwrap();
+    database.insert_swap(&other_sender).await.unwrap();
+
+    let swaps = database
+        .get_swaps_by_block_and_sender(112400, sender, 8453)
+        .await
+        .unwrap();
+    assert_eq!(swaps.len(), 2);
+    assert_eq!(swaps[0].tx_hash, first.tx_hash);
+    assert_eq!(swaps[1].tx_hash, second.tx_hash);
+}
+
+#[tokio::test]
+async fn test_get_pool_at_block_selects_latest_snapshot() {
+    let database = test_database().await;
+    let pool_address = "0x0000000000000000000000000000000000011242";
+    let created = pool(pool_address, 50);
+    database.upsert_pool(&created).await.unwrap();
+
+    for (block, liquidity) in [(100, 1_000), (200, 2_000), (300, 3_000)] {
+        let mut state = created.clone();
+        state.liquidity = Some(liquidity);
+        state.tick = Some(block as i32);
+        database.insert_pool_snapshot(&state, block).await.unwrap();
+    }
+
+    let liquidity_at = |block: i64| {
+        let database = database.clone();
+        async move {
+            database
+                .get_pool_at_block(pool_address, block)
+                .await
+                .unwrap()
+                .map(|p| p.liquidity)
+        }
+    };
+
+    // Before creation the pool did not exist
+    assert_eq!(liquidity_at(49).await, None);
+    // Existed but no snapshot yet: creation-time defaults
+    assert_eq!(liquidity_at(50).await, Some(Some(0)));
+    assert_eq!(liquidity_at(99).await, Some(Some(0)));
+    // Boundaries select the snapshot at or before the block
+    assert_eq!(liquidity_at(100).await, Some(Some(1_000)));
+    assert_eq!(liquidity_at(199).await, Some(Some(1_000)));
+    assert_eq!(liquidity_at(200).await, Some(Some(2_000)));
+    assert_eq!(liquidity_at(299).await, Some(Some(2_000)));
+    assert_eq!(liquidity_at(300).await, Some(Some(3_000)));
+    assert_eq!(liquidity_at(1_000_000).await, Some(Some(3_000)));
+
+    let at_250 = database.get_pool_at_block(pool_address, 250).await.unwrap().unwrap();
+    assert_eq!(at_250.tick, Some(200));
+    assert_eq!(at_250.token0_address, created.token0_address);
+
+    assert!(database
+        .get_pool_at_block("0x0000000000000000000000000000000000000bad", 100)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_get_cumulative_volume_up_to_block() {
+    let database = test_database().await;
+    let pool_address = "0x0000000000000000000000000000000000011243";
+
+    for (i, block) in [100, 200, 300].into_iter().enumerate() {
+        let mut event = swap(
+            &format!("0x11243000000000000000000000000000000000000000000000000000000000{:02}", i),
+            0,
+            block,
+        );
+        event.pool_address = pool_address.to_string();
+        event.amount_in_usd = Some(10.0);
+        database.insert_swap(&event).await.unwrap();
+    }
+    // The same address on another chain is another pool
+    let mut other_chain = swap("0x1124300000000000000000000000000000000000000000000000000000000099", 0, 100);
+    (other_chain.pool_address, other_chain.chain_id, other_chain.amount_in_usd) = (pool_address.to_string(), 2741, Some(50.0));
+    database.insert_swap(&other_chain).await.unwrap();
+
+    let volume = database.get_cumulative_volume(pool_address, 8453, 199, false).await.unwrap();
+    assert_eq!(volume.swap_count, 1);
+    assert_eq!(volume.token0_in, "1000");
+    assert_eq!(volume.volume_usd, 10.0);
+
+    let volume = database.get_cumulative_volume(pool_address, 8453, 300, false).await.unwrap();
+    assert_eq!(volume.swap_count, 3);
+    assert_eq!(volume.token0_in, "3000");
+    assert_eq!(volume.token1_in, "0");
+    assert_eq!(volume.volume_usd, 30.0);
+}
+
+#[tokio::test]
+async fn test_volume_counts_whichever_side_is_priced() {
+    let database = test_database().await;
+    let pool_address = format!("0x{:040x}", unique_id());
+
+    let mut expected = 0.0;
+    for (log_index, (amount_in_usd, amount_out_usd)) in [(Some(100.0), Some(98.0)), (Some(10.0), None), (None, Some(4.0)), (None, None)].into_iter().enumerate() {
+        let mut event = swap(&format!("0x{:064x}", unique_id()), log_index as i32, 100);
+        (event.pool_address, event.amount_in_usd, event.amount_out_usd) = (pool_address.clone(), amount_in_usd, amount_out_usd);
+        expected += event.volume_usd().unwrap_or_default();
+        database.insert_swap(&event).await.unwrap();
+    }
+
+    let volume = database.get_cumulative_volume(&pool_address, 8453, 100, false).await.unwrap();
+    assert_eq!((volume.swap_count, volume.volume_usd), (4, 113.0));
+    assert_eq!(volume.volume_usd, expected);
+}
+
+#[tokio::test]
+async fn test_pool_volume_breakdown() {
+    let database = test_database().await;
+    let chain_id = 20_000_000 + (unique_id() % 1_000_000) as i64;
+    let pool_address = format!("0x{:040x}", unique_id());
+    let mut pool_data = pool(&pool_address, 1);
+    pool_data.chain_id = chain_id;
+    pool_data.token0_address = format!("0x{:040x}", unique_id() + 1);
+    pool_data.token1_address = format!("0x{:040x}", unique_id() + 2);
+    database.upsert_pool(&pool_data).await.unwrap();
+
+    let insert = |token_in: String, token_out: String, amount_in: i64, amount_out: i64, timestamp: i64| {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), 0, 1148);
+        swap_event.pool_address = pool_address.clone();
+        swap_event.chain_id = chain_id;
+        (swap_event.token_in, swap_event.token_out) = (token_in, token_out);
+        (swap_event.amount_in, swap_event.amount_out) = (amount_in, amount_out);
+        swap_event.timestamp = timestamp;
+        let database = database.clone();
+        async move { database.insert_swap(&swap_event).await.unwrap() }
+    };
+
+    insert("token0".to_string(), "token1".to_string(), 1_000, 400, 1_000).await;
+    insert("token1".to_string(), "token0".to_string(), 250, 600, 1_100).await;
+    // Raw addresses, in a different case than the pool's
+    let (token0, token1) = (pool_data.token0_address.to_uppercase().replace("0X", "0x"), pool_data.token1_address.clone());
+    insert(token0, token1, 3_000, 1_200, 1_200).await;
+    // Outside the window
+    insert("token0".to_string(), "token1".to_string(), 99_999, 99_999, 2_000).await;
+
+    let breakdown = database.get_pool_volume_breakdown(&pool_address, chain_id, 1_000, 2_000, false).await.unwrap();
+    assert_eq!(
+        breakdown,
+        VolumeBreakdown {
+            token0_as_input: 4_000,
+            token0_as_output: 600,
+            token1_as_input: 250,
+            token1_as_output: 1_600,
+            total_swap_count: 3,
+        }
+    );
+
+    let empty = database.get_pool_volume_breakdown(&pool_address, chain_id, 0, 1_000, false).await.unwrap();
+    assert_eq!(empty.total_swap_count, 0);
+    assert_eq!(empty.token0_as_input, 0);
+    assert!(database.get_pool_volume_breakdown(&pool_address, chain_id + 1, 0, 2_000, false).await.is_err());
+}
+
+#[tokio::test]
+async fn test_get_swaps_by_amount_range() {
+    let database = test_database().await;
+    let chain_id = 23_000_000 + (unique_id() % 1_000_000) as i64;
+    let pool_address = format!("0x{:040x}", unique_id());
+    let usdc = "0x00000000000000000000000000000000000000a1";
+    let mut pool_data = pool(&pool_address, 1);
+    pool_data.chain_id = chain_id;
+    database.upsert_pool(&pool_data).await.unwrap();
+
+    // USDC (token1, 6 decimals) sold for $100 and $50,000, bought for $2,000, and a large
+    // amount of the other token sold for dust
+    let mut swaps = Vec::new();
+    for (log_index, token_in, token_out, amount_in, amount_out) in [
+        (0, "token1", "token0", 100_000_000, 7),
+        (1, "token1", "token0", 50_000_000_000, 3_500),
+        (2, "token0", "token1", 140, 2_000_000_000),
+        (3, "token0", "token1", 9_000_000_000, 5),
+    ] {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), log_index, 1150);
+        swap_event.pool_address = pool_address.clone();
+        swap_event.chain_id = chain_id;
+        swap_event.token_in = token_in.to_string();
+        swap_event.token_out = token_out.to_string();
+        swap_event.amount_in = amount_in;
+        swap_event.amount_out = amount_out;
+        swap_event.timestamp = 2_000;
+        database.insert_swap(&swap_event).await.unwrap();
+        swaps.push(swap_event);
+    }
+    // Tokens given by address match as well
+    let mut by_address = swap(&format!("0x{:064x}", unique_id()), 4, 1150);
+    by_address.pool_address = pool_address.clone();
+    by_address.chain_id = chain_id;
+    by_address.token_in = usdc.to_uppercase().replacen("0X", "0x", 1);
+    by_address.amount_in = 1_500_000_000;
+    by_address.timestamp = 2_000;
+    database.insert_swap(&by_address).await.unwrap();
+
+    let large = database
+        .get_swaps_by_amount_range(chain_id, usdc, 1_000_000_000, i64::MAX, 0, 3_000)
+        .await
+        .unwrap();
+    let log_indices: Vec<i32> = large.iter().map(|swap_event| swap_event.log_index).collect();
+    assert_eq!(log_indices, vec![1, 2, 4]);
+
+    let small = database
+        .get_swaps_by_amount_range(chain_id, usdc, 0, 1_000_000_000, 0, 3_000)
+        .await
+        .unwrap();
+    let log_indices: Vec<i32> = small.iter().map(|swap_event| swap_event.log_index).collect();
+    assert_eq!(log_indices, vec![0, 3, 4]);
+    assert_eq!(small[0].tx_hash, swaps[0].tx_hash);
+
+    // The window is half-open
+    assert!(database
+        .get_swaps_by_amount_range(chain_id, usdc, 0, i64::MAX, 0, 2_000)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_wash_scoring_and_volume_excluding_wash() {
+    let database = test_database().await;
+    let chain_id = 22_000_000 + (unique_id() % 1_000_000) as i64;
+    let pool_address = format!("0x{:040x}", unique_id());
+    let mut pool_data = pool(&pool_address, 1);
+    pool_data.chain_id = chain_id;
+    database.upsert_pool(&pool_data).await.unwrap();
+
+    // Wallet A buys and sells back the same amount; B only buys
+    let mut swaps = Vec::new();
+    for (log_index, wallet, buys) in [(0, "0xa", true), (1, "0xb", true), (2, "0xa", false)] {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), log_index, 1150);
+        swap_event.pool_address = pool_address.clone();
+        swap_event.chain_id = chain_id;
+        if buys {
+            swap_event.token_in = "token1".to_string();
+            swap_event.token_out = "token0".to_string();
+            swap_event.amount_out = 1_000;
+        }
+        swap_event.amount_in_usd = Some(10.0);
+        swap_event.recipient_address = Some(wallet.to_string());
+        swap_event.timestamp = 5_000 + log_index as i64;
+        database.insert_swap(&swap_event).await.unwrap();
+        swaps.push(swap_event);
+    }
+
+    let policy = WashPolicy { chain_id, window_secs: 300, net_threshold_bps: 100 };
+    assert_eq!(wash::score_range(&database, &policy, 0, 10_000).await.unwrap(), 2);
+    let mut flags = Vec::new();
+    for swap_event in &swaps {
+        flags.push(database.is_suspected_wash(&swap_event.tx_hash, swap_event.log_index, chain_id).await.unwrap());
+    }
+    assert_eq!(flags, vec![Some(true), Some(false), Some(true)]);
+
+    let volume = database.get_cumulative_volume(&pool_address, chain_id, 1150, false).await.unwrap();
+    assert_eq!((volume.volume_usd, volume.volume_excluding_wash), (30.0, 10.0));
+
+    // Rescoring with a tighter window than the round trip clears the flags
+    let policy = WashPolicy { window_secs: 1, ..policy };
+    assert_eq!(wash::score_range(&database, &policy, 0, 10_000).await.unwrap(), 0);
+    let volume = database.get_cumulative_volume(&pool_address, chain_id, 1150, false).await.unwrap();
+    assert_eq!(volume.volume_excluding_wash, 30.0);
+}
+
+#[tokio::test]
+async fn test_mev_tags_and_volume_exclusion() {
+    let database = test_database().await;
+    let chain_id = 21_000_000 + (unique_id() % 1_000_000) as i64;
+    let pool_address = format!("0x{:040x}", unique_id());
+    let mut pool_data = pool(&pool_address, 1);
+    pool_data.chain_id = chain_id;
+    database.upsert_pool(&pool_data).await.unwrap();
+
+    // A bot buys ahead of a user and sells right after, then an unrelated swap
+    let mut swaps = Vec::new();
+    for (log_index, token_in, sender, amount_in) in [
+        (0, "token0", "0xbot", 5_000),
+        (1, "token0", "0xuser", 1_000),
+        (2, "token1", "0xbot", 4_000),
+        (3, "token1", "0xother", 300),
+    ] {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), log_index, 1149);
+        swap_event.pool_address = pool_address.clone();
+        swap_event.chain_id = chain_id;
+        swap_event.token_in = token_in.to_string();
+        swap_event.token_out = if token_in == "token0" { "token1" } else { "token0" }.to_string();
+        swap_event.amount_in = amount_in;
+        swap_event.sender_address = Some(sender.to_string());
+        swap_event.timestamp = 1_000 + log_index as i64;
+        database.insert_swap(&swap_event).await.unwrap();
+        swaps.push(swap_event);
+    }
+
+    assert_eq!(mev::tag_blocks(&database, chain_id, 1149, 1149).await.unwrap(), 3);
+    let mut tags = Vec::new();
+    for swap_event in &swaps {
+        tags.push(database.get_swap_mev_type(&swap_event.tx_hash, swap_event.log_index, chain_id).await.unwrap());
+    }
+    assert_eq!(tags, vec![Some(MevType::SandwichFront), Some(MevType::Victim), Some(MevType::SandwichBack), None]);
+
+    let all = database.get_pool_volume_breakdown(&pool_address, chain_id, 0, 2_000, false).await.unwrap();
+    assert_eq!((all.token0_as_input, all.token1_as_input, all.total_swap_count), (6_000, 4_300, 4));
+    let organic = database.get_pool_volume_breakdown(&pool_address, chain_id, 0, 2_000, true).await.unwrap();
+    assert_eq!((organic.token0_as_input, organic.token1_as_input, organic.total_swap_count), (1_000, 300, 2));
+    let volume = database.get_cumulative_volume(&pool_address, chain_id, 1149, true).await.unwrap();
+    assert_eq!((volume.swap_count, volume.token0_in.as_str()), (2, "1000"));
+}
+
+#[tokio::test]
+async fn test_block_header_cache_roundtrip() {
+    let database = test_database().await;
+
+    assert_eq!(database.get_block_timestamp(1125, 1).await.unwrap(), None);
+
+    database.upsert_block(1125, 1_600_000_000, 1125).await.unwrap();
+    database.upsert_block(1125, 1_700_000_000, 1125).await.unwrap();
+    assert_eq!(
+        database.get_block_timestamp(1125, 1125).await.unwrap(),
+        Some(1_700_000_000)
+    );
+}
+
+/// Headers of blocks 0..=10 mined every 12s, filling `block / 10` of the gas limit.
+struct FixtureHeaders;
+
+#[async_trait]
+impl BlockHeaders for FixtureHeaders {
+    async fn head_block_number(&self) -> anyhow::Result<u64> {
+        Ok(10)
+    }
+
+    async fn block_timestamp(&self, block_number: u64) -> anyhow::Result<u64> {
+        Ok(self.block_header(block_number).await?.timestamp)
+    }
+
+    async fn block_header(&self, block_number: u64) -> anyhow::Result<BlockHeader> {
+        Ok(BlockHeader {
+            timestamp: 1_000 + 12 * block_number,
+            base_fee_per_gas: (block_number != 7).then_some(2_000_000_000 + block_number as i64),
+            gas_used_ratio: Some(block_number as f64 / 10.0),
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_consecutive_no_swap_blocks() {
+    let database = test_database().await;
+    let pool_address = format!("0x{:040x}", unique_id());
+    let pool_data = pool(&pool_address, 1);
+    database.upsert_pool(&pool_data).await.unwrap();
+
+    // Two swaps share block 100
+    for (i, block_number) in [100, 100, 150, 20_150, 20_160].into_iter().enumerate() {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), i as i32, block_number);
+        swap_event.pool_address = pool_address.clone();
+        database.insert_swap(&swap_event).await.unwrap();
+    }
+
+    let gaps = database.get_consecutive_no_swap_blocks(&pool_address, 8453, 10).await.unwrap();
+    assert_eq!(
+        gaps,
+        vec![
+            BlockGap { from_block: 150, to_block: 20_150, gap_blocks: 20_000 },
+            BlockGap { from_block: 100, to_block: 150, gap_blocks: 50 },
+            BlockGap { from_block: 20_150, to_block: 20_160, gap_blocks: 10 },
+        ]
+    );
+    assert!(database.get_consecutive_no_swap_blocks(&pool_address, 1, 10).await.unwrap().is_empty());
+
+    assert!(pool_data.is_likely_dead(&database, LIKELY_DEAD_GAP_BLOCKS).await.unwrap());
+    assert!(!pool_data.is_likely_dead(&database, 20_001).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_swap_impact_on_tick() {
+    let database = test_database().await;
+    let pool_address = format!("0x{:040x}", unique_id());
+
+    // Two swaps within one tick, then one crossing 60 ticks down
+    let mut swaps = Vec::new();
+    for (block_number, log_index, tick) in [(10, 4, -200), (10, 9, -200), (12, 0, -260)] {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), log_index, block_number);
+        swap_event.pool_address = pool_address.clone();
+        swap_event.tick = Some(tick);
+        database.insert_swap(&swap_event).await.unwrap();
+        swaps.push(swap_event);
+    }
+    // Ticks of other pools are not mixed in
+    let mut other_pool = swap(&format!("0x{:064x}", unique_id()), 0, 11);
+    other_pool.tick = Some(5_000);
+    database.insert_swap(&other_pool).await.unwrap();
+
+    let impact = |i: usize| database.get_swap_impact_on_tick(&pool_address, 8453, &swaps[i].tx_hash, swaps[i].log_index);
+    assert_eq!(impact(1).await.unwrap(), 0);
+    assert_eq!(impact(2).await.unwrap(), -60);
+    // Nothing before the first swap, and nothing at all for an unknown one
+    assert!(impact(0).await.is_err());
+    assert!(database.get_swap_impact_on_tick(&pool_address, 8453, &swaps[2].tx_hash, 1).await.is_err());
+
+    // Priced against the tick the second swap left
+    let mut pool_data = pool(&pool_address, 1);
+    pool_data.token0_decimals = Some(18);
+    pool_data.token1_decimals = Some(18);
+    let price_impact = database.get_swap_price_impact_bps(&swaps[2], &pool_data).await.unwrap();
+    assert_eq!(price_impact, swaps[2].compute_price_impact_bps(&pool_data, -200).unwrap());
+    assert!(database.get_swap_price_impact_bps(&swaps[0], &pool_data).await.is_err());
+}
+
+#[tokio::test]
+async fn test_price_sampled_at_regular_intervals() {
+    let database = test_database().await;
+    let pool_address = format!("0x{:040x}", unique_id());
+    let mut pool_data = pool(&pool_address, 1);
+    (pool_data.token0_decimals, pool_data.token1_decimals) = (Some(18), Some(6));
+    database.upsert_pool(&pool_data).await.unwrap();
+
+    // One swap before the hour, two in its third bucket and one in its last
+    let from_ts = 1_700_000_000;
+    for (block_number, timestamp, tick) in [(10, from_ts - 50, -200_000), (20, from_ts + 600, -199_000), (21, from_ts + 610, -198_000), (30, from_ts + 3_590, -197_000)] {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), 0, block_number);
+        (swap_event.pool_address, swap_event.timestamp, swap_event.tick) = (pool_address.clone(), timestamp, Some(tick));
+        database.insert_swap(&swap_event).await.unwrap();
+    }
+
+    let points = database.get_price_at_timestamp_range(&pool_address, 8453, from_ts, from_ts + 3_600, 300).await.unwrap();
+    assert_eq!(points.len(), 12);
+    assert_eq!(points.iter().map(|point| point.timestamp).collect::<Vec<_>>(), (0..12).map(|i| from_ts + i * 300).collect::<Vec<_>>());
+    let ticks: Vec<i32> = points.iter().map(|point| point.tick).collect();
+    assert_eq!(ticks[..3], [-200_000, -200_000, -199_000]);
+    assert!(ticks[3..].iter().all(|tick| *tick == -198_000));
+    assert_eq!(points[0].price, price::tick_to_price(-200_000, 18, 6));
+
+    // Boundaries before the first swap have no price
+    let points = database.get_price_at_timestamp_range(&pool_address, 8453, from_ts - 600, from_ts + 1, 300).await.unwrap();
+    assert_eq!(points.iter().map(|point| point.timestamp).collect::<Vec<_>>(), vec![from_ts]);
+
+    assert!(database.get_price_at_timestamp_range(&pool_address, 8453, from_ts, from_ts + 3_600, 0).await.is_err());
+    // Ticks alone are not prices
+    let no_decimals = format!("0x{:040x}", unique_id());
+    database.upsert_pool(&pool(&no_decimals, 1)).await.unwrap();
+    let mut swap_event = swap(&format!("0x{:064x}", unique_id()), 0, 10);
+    (swap_event.pool_address, swap_event.timestamp, swap_event.tick) = (no_decimals.clone(), from_ts, Some(0));
+    database.insert_swap(&swap_event).await.unwrap();
+    let err = database.get_price_at_timestamp_range(&no_decimals, 8453, from_ts, from_ts + 3_600, 300).await.unwrap_err();
+    assert!(err.downcast_ref::<MissingDecimalsError>().is_some());
+}
+
+#[tokio::test]
+async fn test_maintenance_reports_table_health() {
+    let database = test_database().await;
+    database.insert_swap(&swap(&format!("0x{:064x}", unique_id()), 0, 1)).await.unwrap();
+
+    // Unknown tables are left out rather than failing the report
+    let health = database.get_table_health(&["swaps", "pools", "no_such_table"]).await.unwrap();
+    let names: Vec<&str> = health.iter().map(|table| table.table_name.as_str()).collect();
+    assert_eq!(names, vec!["pools", "swaps"]);
+    for table in &health {
+        assert!(table.table_bytes > 0 && table.index_bytes > 0, "{:?}", table);
+        assert!(table.live_tuples >= 0 && table.dead_tuples >= 0, "{:?}", table);
+        assert!((0.0..=1.0).contains(&table.dead_tuple_ratio()), "{:?}", table);
+    }
+
+    let policy = MaintenancePolicy { dead_tuple_ratio_threshold: 0.2 };
+    let reports = maintenance::run_maintenance(&database, &policy).await.unwrap();
+    assert_eq!(reports.len(), maintenance::HOT_TABLES.len());
+    // Analyzed just now, so the swap inserted above is counted
+    let swaps = reports.iter().find(|report| report.health.table_name == "swaps").unwrap();
+    assert!(swaps.health.live_tuples > 0);
+
+    let (_, logged) = database.get_latest_maintenance_report("swaps").await.unwrap().unwrap();
+    assert_eq!(logged.health.table_name, "swaps");
+    assert_eq!(logged.needs_vacuum_full, policy.needs_vacuum_full(&logged.health));
+    assert!(database.get_latest_maintenance_report("no_such_table").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_purge_removes_only_the_targeted_pool_and_token() {
+    let database = test_database().await;
+    let chain_id = 17_000_000 + (unique_id() % 1_000_000) as i64;
+    let weth = format!("0x{:040x}", unique_id());
+
+    // Two pools, each pairing its own launch token with WETH, with data in every table
+    let mut seeded = Vec::new();
+    for _ in 0..2 {
+        let (pool_address, token) = (format!("0x{:040x}", unique_id()), format!("0x{:040x}", unique_id()));
+        let mut pool_data = pool(&pool_address, 100);
+        (pool_data.token0_address, pool_data.token1_address, pool_data.chain_id) = (token.clone(), weth.clone(), chain_id);
+        database.upsert_pool(&pool_data).await.unwrap();
+        database.insert_pool_snapshot(&pool_data, 110).await.unwrap();
+        database.insert_watched_pool(&pool_address, chain_id, 1_000).await.unwrap();
+        let tick = TickData { tick_index: 60, liquidity_gross: 1, liquidity_net: 1, fee_growth_outside_0: U256::zero(), fee_growth_outside_1: U256::zero() };
+        database.upsert_tick_data(&pool_address, chain_id, &[tick], 110).await.unwrap();
+        for log_index in 0..3 {
+            let mut swap_event = swap(&format!("0x{:064x}", unique_id()), log_index, 120);
+            (swap_event.pool_address, swap_event.chain_id, swap_event.tick) = (pool_address.clone(), chain_id, Some(60));
+            database.insert_swap(&swap_event).await.unwrap();
+        }
+
+        database.update_token_supply(&token, chain_id, "1000", 1_000).await.unwrap();
+        database.insert_token_supply_history(&token, chain_id, "1000", 1_000).await.unwrap();
+        database.upsert_token_usd_price(&token, chain_id, 0, 1.5, None).await.unwrap();
+        database
+            .insert_curve_trade(&CurveTrade {
+                tx_hash: format!("0x{:064x}", unique_id()),
+                log_index: 0,
+                block_number: 90,
+                timestamp: 1_000,
+                chain_id,
+                curve_address: "0x00000000000000000000000000000000000000c0".to_string(),
+                token_address: token.clone(),
+                trader: "0x000000000000000000000000000000000000beef".to_string(),
+                side: TradeSide::Buy,
+                token_amount: "1000".to_string(),
+                collateral_amount: "1".to_string(),
+            })
+            .await
+            .unwrap();
+        seeded.push((pool_address, token));
+    }
+    let [(scam_pool, scam_token), (kept_pool, kept_token)] = <[_; 2]>::try_from(seeded).unwrap();
+
+    // Addresses are matched however they are written
+    let counts = database.delete_token_data(&scam_token.to_uppercase().replace("0X", "0x"), chain_id).await.unwrap();
+    for (table, rows) in [("swaps", 3), ("tick_history", 3), ("pool_snapshots", 1), ("tick_data", 1), ("watchlist", 1), ("pools", 1)] {
+        assert_eq!(counts.get(table), rows, "{}", table);
+    }
+    for table in ["tokens", "token_supply_history", "token_usd_prices", "curve_trades"] {
+        assert_eq!(counts.get(table), 1, "{}", table);
+    }
+    assert_eq!(counts.total(), 14);
+
+    let exists = |pool_address: String, token: String| {
+        let database = database.clone();
+        async move {
+            [
+                database.get_pool(&pool_address).await.unwrap().is_some(),
+                !database.get_swaps_by_pool(&pool_address, chain_id, 10).await.unwrap().is_empty(),
+                database.get_pool_at_block(&pool_address, 110).await.unwrap().is_some(),
+                !database.get_tick_data(&pool_address, chain_id, 0, 60).await.unwrap().is_empty(),
+                database.get_watched_pools(chain_id).await.unwrap().contains(&pool_address),
+                database.get_token(&token, chain_id).await.unwrap().is_some(),
+                !database.get_token_supply_history(&token, chain_id).await.unwrap().is_empty(),
+                database.get_token_usd_price_near(&token, chain_id, 0, 60).await.unwrap().is_some(),
+                !database.get_curve_trades(&token, chain_id).await.unwrap().is_empty(),
+            ]
+        }
+    };
+    assert_eq!(exists(scam_pool.clone(), scam_token.clone()).await, [false; 9]);
+    assert_eq!(exists(kept_pool.clone(), kept_token.clone()).await, [true; 9]);
+
+    // Removing a pool leaves its tokens, and a second run finds nothing left
+    let counts = database.delete_pool_data(&kept_pool, chain_id).await.unwrap();
+    assert_eq!((counts.get("swaps"), counts.get("pools")), (3, 1));
+    assert!(database.get_token(&kept_token, chain_id).await.unwrap().is_some());
+    assert_eq!(database.delete_pool_data(&kept_pool, chain_id).await.unwrap().total(), 0);
+}
+
+#[tokio::test]
+async fn test_doctor_database_checks() {
+    let database = test_database().await;
+    assert!(doctor::check_database(&database).await.passed);
+    assert!(doctor::check_write_permission(&database).await.passed);
+    assert_eq!(database.get_missing_tables(&["pools", "no_such_table", "swaps"]).await.unwrap(), vec!["no_such_table"]);
+}
+
+#[tokio::test]
+async fn test_processes_contend_for_indexing_locks() {
+    let first = test_database().await;
+    let second = test_database().await;
+    let chain_id = 25_000_000 + (unique_id() % 1_000_000) as u64;
+
+    let live = coordination::lock_live(&first, chain_id, &MOONSHOT).await.unwrap();
+    let err = coordination::lock_live(&second, chain_id, &MOONSHOT).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<IndexerError>(), Some(IndexerError::Locked { .. })));
+    // Other chains, other DEXes of the chain and backfills are not held up by the live lock
+    coordination::lock_live(&second, chain_id + 1, &MOONSHOT).await.unwrap().release().await.unwrap();
+    let uniswap = "uniswap-v3".parse().unwrap();
+    coordination::lock_live(&second, chain_id, &uniswap).await.unwrap().release().await.unwrap();
+    let backfill = coordination::lock_backfill(&second, chain_id, 150_000, 250_000).await.unwrap();
+
+    // Backfills conflict only when their ranges share a lock span
+    let err = coordination::lock_backfill(&first, chain_id, 240_000, 260_000).await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<IndexerError>(), Some(IndexerError::Locked { .. })));
+    coordination::lock_backfill(&first, chain_id, 300_000, 350_000).await.unwrap().release().await.unwrap();
+
+    live.release().await.unwrap();
+    coordination::lock_live(&second, chain_id, &MOONSHOT).await.unwrap().release().await.unwrap();
+
+    // Dropping a lock closes its connection, which releases it server side
+    drop(backfill);
+    let mut relocked = None;
+    for _ in 0..50 {
+        if let Ok(lock) = coordination::lock_backfill(&first, chain_id, 240_000, 260_000).await {
+            relocked = Some(lock);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    relocked.expect("lock released on drop").release().await.unwrap();
+
+    // Only the process that stored a swap sees it as new
+    let swap_event = swap(&format!("0x{:064x}", unique_id()), 0, 1130);
+    assert!(first.insert_swap(&swap_event).await.unwrap());
+    assert!(!second.insert_swap(&swap_event).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_rename_dex_only_touches_the_chain() {
+    let database = test_database().await;
+    let chain_id = 26_000_000 + (unique_id() % 1_000_000) as i64;
+
+    let mut pools = Vec::new();
+    for pool_chain_id in [chain_id, chain_id, chain_id + 1] {
+        let mut pool_data = pool(&format!("0x{:040x}", unique_id()), 100);
+        pool_data.chain_id = pool_chain_id;
+        database.upsert_pool(&pool_data).await.unwrap();
+        pools.push(pool_data.pool_address);
+    }
+
+    assert_eq!(database.rename_dex("moonshot", "Uniswap-V3", chain_id).await.unwrap(), 2);
+    assert_eq!(database.get_pool(&pools[0]).await.unwrap().unwrap().dex_name, "uniswap-v3");
+    assert_eq!(database.get_pool(&pools[1]).await.unwrap().unwrap().dex_name, "uniswap-v3");
+    assert_eq!(database.get_pool(&pools[2]).await.unwrap().unwrap().dex_name, "moonshot");
+    assert_eq!(database.rename_dex("moonshot", "uniswap-v3", chain_id).await.unwrap(), 0);
+
+    // Rows are only relabelled as a DEX of the registry
+    assert!(database.rename_dex("uniswap-v3", "newswap", chain_id).await.is_err());
+    assert_eq!(database.get_pool(&pools[0]).await.unwrap().unwrap().dex_name, "uniswap-v3");
+}
+
+#[tokio::test]
+async fn test_dex_names_are_checked_against_the_registry() {
+    let database = test_database().await;
+    let chain_id = 41_000_000 + (unique_id() % 1_000_000) as i64;
+
+    // Stored under the canonical id whatever the case
+    let mut moonshot = pool(&format!("0x{:040x}", unique_id()), 100);
+    (moonshot.chain_id, moonshot.dex_name) = (chain_id, " MoonShot".parse().unwrap());
+    database.upsert_pool(&moonshot).await.unwrap();
+    assert_eq!(database.get_pool(&moonshot.pool_address).await.unwrap().unwrap().dex_name, "moonshot");
+
+    let mut uniswap = pool(&format!("0x{:040x}", unique_id()), 100);
+    (uniswap.chain_id, uniswap.dex_name) = (chain_id, "uniswap-v3".parse().unwrap());
+    database.upsert_pool(&uniswap).await.unwrap();
+
+    // Unknown DEXes and known chains the DEX is not deployed on are rejected
+    let mut typo = pool(&format!("0x{:040x}", unique_id()), 100);
+    (typo.chain_id, typo.dex_name) = (chain_id, "moonshott".parse().unwrap());
+    let error = database.upsert_pool(&typo).await.unwrap_err().to_string();
+    assert!(error.starts_with("unknown DEX \"moonshott\"; known DEXes: moonshot"), "{}", error);
+    let mut elsewhere = pool(&format!("0x{:040x}", unique_id()), 100);
+    elsewhere.chain_id = 1;
+    assert!(database.upsert_pool(&elsewhere).await.is_err());
+    assert!(database.get_pool(&typo.pool_address).await.unwrap().is_none());
+    assert!(database.get_pool(&elsewhere.pool_address).await.unwrap().is_none());
+
+    assert!(database.record_range_stats(chain_id, &MOONSHOT, 10, 20, 1, 0).await.is_ok());
+    assert!(database.record_range_stats(chain_id, &"otherswap".parse().unwrap(), 10, 20, 1, 0).await.is_err());
+    let stats = database.get_all_stats().await.unwrap();
+    let dexes: Vec<&str> = stats.iter().filter(|row| row.chain_id == chain_id).map(|row| row.dex_name.as_str()).collect();
+    assert_eq!(dexes, vec!["moonshot"]);
+
+    // The updated pools feed filters on the canonical id
+    let dex = Some(database.dexes().parse("Uniswap-V3").unwrap());
+    let page = database.get_pools_updated_since(chain_id, 0, dex, 10, None).await.unwrap();
+    assert_eq!(page.pools.iter().map(|p| p.pool_address.as_str()).collect::<Vec<_>>(), vec![uniswap.pool_address.as_str()]);
+    assert_eq!(database.get_pools_updated_since(chain_id, 0, None, 10, None).await.unwrap().pools.len(), 2);
+}
+
+#[tokio::test]
+async fn test_configured_dexes_extend_the_presets() {
+    let database = test_database().await;
+    let chain_id = 42_000_000 + (unique_id() % 1_000_000) as i64;
+
+    let mut sushi = pool(&format!("0x{:040x}", unique_id()), 100);
+    (sushi.chain_id, sushi.dex_name) = (chain_id, "sushi-v3".parse().unwrap());
+    let error = database.upsert_pool(&sushi).await.unwrap_err().to_string();
+    assert!(error.starts_with("unknown DEX \"sushi-v3\""), "{}", error);
+
+    let extra = dex::parse_dex_list("sushi-v3:SushiSwap V3:1,8453:generic-v3").unwrap();
+    let database = database.with_dexes(DexRegistry::presets().extended(extra).unwrap());
+    database.upsert_pool(&sushi).await.unwrap();
+    assert_eq!(database.get_pool(&sushi.pool_address).await.unwrap().unwrap().dex_name, "sushi-v3");
+    database.record_range_stats(chain_id, &sushi.dex_name, 10, 20, 1, 0).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_dex_name_columns_are_constrained() {
+    let database = test_database().await;
+    let raw = sqlx::PgPool::connect(&env::var("DATABASE_URL").unwrap()).await.unwrap();
+    let chain_id = 43_000_000 + (unique_id() % 1_000_000) as i64;
+    let address = format!("0x{:040x}", unique_id());
+    let insert_pool = |dex_name: &'static str| {
+        sqlx::query("INSERT INTO pools (pool_address, token0_address, token1_address, chain_id, dex_name) VALUES ($1, '0xa0', '0xa1', $2, $3)")
+            .bind(address.clone())
+            .bind(chain_id)
+            .bind(dex_name)
+    };
+
+    // Writes bypassing the registry still only store canonical ids
+    for dex_name in ["MoonShot", "uni swap", "", "a-dex-id-well-over-thirty-two-chars"] {
+        let error = insert_pool(dex_name).execute(&raw).await.unwrap_err().to_string();
+        assert!(error.contains("pools_dex_name_check"), "{}", error);
+    }
+
+    let _guard = SCHEMA_LOCK.lock().await;
+    // Rows stored before the constraints existed are lowercased, and stats rows that differed
+    // only in case are merged
+    sqlx::query("ALTER TABLE pools DROP CONSTRAINT pools_dex_name_check").execute(&raw).await.unwrap();
+    sqlx::query("ALTER TABLE indexing_stats DROP CONSTRAINT indexing_stats_dex_name_check").execute(&raw).await.unwrap();
+    insert_pool(" MoonShot").execute(&raw).await.unwrap();
+    for (dex_name, last_processed_block, pools) in [("MoonShot", 30, 2), ("moonshot", 20, 3)] {
+        sqlx::query(
+            "INSERT INTO indexing_stats (chain_id, dex_name, last_processed_block, total_pools_indexed, updated_at) VALUES ($1, $2, $3, $4, 0)",
+        )
+        .bind(chain_id)
+        .bind(dex_name)
+        .bind(last_processed_block)
+        .bind(pools)
+        .execute(&raw)
+        .await
+        .unwrap();
+    }
+    database.init_schema().await.unwrap();
+
+    assert_eq!(database.get_pool(&address).await.unwrap().unwrap().dex_name, MOONSHOT);
+    let stats = database.get_all_stats().await.unwrap();
+    let stats: Vec<_> = stats.iter().filter(|row| row.chain_id == chain_id).collect();
+    assert_eq!(stats.len(), 1);
+    assert_eq!((stats[0].dex_name.as_str(), stats[0].last_processed_block, stats[0].total_pools_indexed), ("moonshot", 30, 5));
+    assert!(insert_pool("MoonShot").execute(&raw).await.unwrap_err().to_string().contains("pools_dex_name_check"));
+
+    // A name that is no DEX id stops the migration until it is relabelled
+    sqlx::query("ALTER TABLE pools DROP CONSTRAINT pools_dex_name_check").execute(&raw).await.unwrap();
+    sqlx::query("UPDATE pools SET dex_name = 'Moon Shot' WHERE pool_address = $1").bind(&address).execute(&raw).await.unwrap();
+    let error = database.init_schema().await.unwrap_err().to_string();
+    assert!(error.contains("\"Moon Shot\"") && error.contains("rename-dex"), "{}", error);
+    assert_eq!(database.rename_dex("Moon Shot", "moonshot", chain_id).await.unwrap(), 1);
+    database.init_schema().await.unwrap();
+    assert!(insert_pool("Moon Shot").execute(&raw).await.is_err());
+}
+
+#[tokio::test]
+async fn test_gas_stats_from_fetched_headers() {
+    let database = test_database().await;
+    let chain_id = 24_000_000 + (unique_id() % 1_000_000) as i64;
+    let headers = CachedHeaders::new(&FixtureHeaders, &database, chain_id);
+
+    for block_number in [3, 5, 7, 9] {
+        headers.block_timestamp(block_number).await.unwrap();
+    }
+
+    // Blocks 5 and 7 are mined at 1_060 and 1_084; the window is half-open
+    let stats = database.get_gas_stats(chain_id, 1_060, 1_108).await.unwrap();
+    assert_eq!(
+        stats,
+        vec![
+            GasStats {
+                chain_id,
+                block_number: 5,
+                timestamp: 1_060,
+                base_fee_per_gas: Some(2_000_000_005),
+                gas_used_ratio: 0.5,
+                median_priority_fee: None,
+            },
+            GasStats {
+                chain_id,
+                block_number: 7,
+                timestamp: 1_084,
+                base_fee_per_gas: None,
+                gas_used_ratio: 0.7,
+                median_priority_fee: None,
+            },
+        ]
+    );
+
+    // Rewriting a header keeps an enriched priority fee
+    let mut enriched = stats[0].clone();
+    enriched.median_priority_fee = Some(1_500_000);
+    database.upsert_gas_stats(&enriched).await.unwrap();
+    enriched.median_priority_fee = None;
+    enriched.gas_used_ratio = 0.55;
+    database.upsert_gas_stats(&enriched).await.unwrap();
+    let stored = database.get_gas_stats(chain_id, 1_060, 1_061).await.unwrap();
+    assert_eq!((stored[0].gas_used_ratio, stored[0].median_priority_fee), (0.55, Some(1_500_000)));
+}
+
+#[tokio::test]
+async fn test_pool_active_hours_and_days() {
+    let database = test_database().await;
+    let pool_address = "0x0000000000000000000000000000000000011260";
+    // 2022-01-02 00:00:00 UTC, a Sunday
+    let sunday_midnight = 1_641_081_600;
+
+    let offsets = [
+        0,                       // Sunday 00:xx
+        1_800,                   // Sunday 00:xx
+        13 * 3_600 + 59,         // Sunday 13:xx
+        86_400 + 13 * 3_600,     // Monday 13:xx
+        6 * 86_400 + 23 * 3_600, // Saturday 23:xx
+    ];
+    for (i, offset) in offsets.into_iter().enumerate() {
+        let mut event = swap(
+            &format!("0x11260000000000000000000000000000000000000000000000000000000000{:02}", i),
+            0,
+            1126,
+        );
+        event.pool_address = pool_address.to_string();
+        event.timestamp = sunday_midnight + offset;
+        database.insert_swap(&event).await.unwrap();
+    }
+
+    let hours = database.get_pool_active_hours(pool_address, 8453).await.unwrap();
+    assert_eq!(hours.len(), 24);
+    assert_eq!(hours[0], 2);
+    assert_eq!(hours[13], 2);
+    assert_eq!(hours[23], 1);
+    assert_eq!(hours.iter().sum::<u32>(), 5);
+
+    let days = database.get_pool_active_days_of_week(pool_address, 8453).await.unwrap();
+    assert_eq!(days, vec![3, 1, 0, 0, 0, 0, 1]);
+
+    let empty = database.get_pool_active_hours(pool_address, 1).await.unwrap();
+    assert_eq!(empty, vec![0; 24]);
+}
+
+#[tokio::test]
+async fn test_pool_lifecycle_maintenance_with_simulated_clock() {
+    let database = test_database().await;
+    let policy = LifecyclePolicy {
+        stale_after_secs: 6 * 3600,
+        archive_after_secs: 3 * 86400,
+    };
+    let t0 = 1_900_000_000;
+    // Swaps persist between runs and would shift last activity, so use fresh pools each run
+    let run = unique_id();
+    let quiet = &format!("0x{:040x}", run);
+    let busy = &format!("0x{:040x}", run + 1);
+
+    for (i, (pool_address, last_swap)) in [(quiet, t0), (busy, t0 + 20 * 3600)].into_iter().enumerate() {
+        database.upsert_pool(&pool(pool_address, 1127)).await.unwrap();
+
+        let mut event = swap(&format!("0x{:064x}", run + i as u128), 0, 1127);
+        event.pool_address = pool_address.to_string();
+        event.timestamp = last_swap;
+        database.insert_swap(&event).await.unwrap();
+    }
+
+    let status = |pool_address: &str| {
+        let pool_address = pool_address.to_string();
+        let database = database.clone();
+        async move { database.get_pool_status(&pool_address).await.unwrap().unwrap() }
+    };
+
+    // One day in: the quiet pool goes stale, the busy one is still active
+    lifecycle::run_maintenance(&database, &policy, 8453, t0 + 86400).await.unwrap();
+    assert_eq!(status(quiet).await, PoolStatus::Stale);
+    assert_eq!(status(busy).await, PoolStatus::Active);
+
+    let active = database.get_pool_addresses_by_status(PoolStatus::Active).await.unwrap();
+    let stale = database.get_pool_addresses_by_status(PoolStatus::Stale).await.unwrap();
+    assert!(active.contains(busy) && !active.contains(quiet));
+    assert!(stale.contains(quiet));
+
+    // Three days in: quiet is archived, busy has gone stale
+    lifecycle::run_maintenance(&database, &policy, 8453, t0 + 3 * 86400).await.unwrap();
+    assert_eq!(status(quiet).await, PoolStatus::Archived);
+    assert_eq!(status(busy).await, PoolStatus::Stale);
+
+    // A swap observed on the stale pool reactivates it, and maintenance leaves it active
+    database.set_pool_status(busy, PoolStatus::Active).await.unwrap();
+    let mut event = swap(&format!("0x{:064x}", run + 2), 0, 1128);
+    event.pool_address = busy.to_string();
+    event.timestamp = t0 + 3 * 86400;
+    database.insert_swap(&event).await.unwrap();
+
+    lifecycle::run_maintenance(&database, &policy, 8453, t0 + 3 * 86400 + 60).await.unwrap();
+    assert_eq!(status(busy).await, PoolStatus::Active);
+    assert_eq!(status(quiet).await, PoolStatus::Archived);
+}
+
+#[tokio::test]
+async fn test_missing_token_addresses_and_upsert() {
+    let database = test_database().await;
+    let chain_id = 11270;
+    let token0 = "0x00000000000000000000000000000000001127a0";
+    let token1 = "0x00000000000000000000000000000000001127a1";
+
+    let mut pool_data = pool("0x0000000000000000000000000000000000011273", 1127);
+    pool_data.token0_address = token0.to_string();
+    pool_data.token1_address = token1.to_string();
+    pool_data.chain_id = chain_id;
+    database.upsert_pool(&pool_data).await.unwrap();
+
+    let token = TokenData {
+        address: token0.to_string(),
+        name: Some("Token Zero".to_string()),
+        symbol: Some("TZERO".to_string()),
+        decimals: Some(18),
+        total_supply: Some("1000000000000000000000000".to_string()),
+        supply_updated_at: None,
+        is_fee_on_transfer: false,
+        is_rebasing: false,
+        creator_address: None,
+        chain_id,
+    };
+    database.upsert_token(&token).await.unwrap();
+
+    let missing = database.get_missing_token_addresses(chain_id).await.unwrap();
+    assert_eq!(missing, vec![token1.to_string()]);
+
+    let stored = database.get_token(token0, chain_id).await.unwrap().unwrap();
+    assert_eq!(stored.symbol.as_deref(), Some("TZERO"));
+    assert_eq!(stored.total_supply, token.total_supply);
+}
+
+#[tokio::test]
+async fn test_scope_change_retroactively_tracks_pools() {
+    let database = test_database().await;
+    let run = unique_id();
+    let wanted = format!("0x{:040x}", run);
+    let other = format!("0x{:040x}", run + 1);
+
+    for address in [&wanted, &other] {
+        database.upsert_pool(&pool(address, 1127)).await.unwrap();
+        database.set_pool_tracked(address, false).await.unwrap();
+    }
+
+    // Restarting with the pool allowlisted tracks it again and reports it for backfill
+    let scope = IndexingScope::new(vec![wanted.clone()], None);
+    let newly_tracked = scope::apply_to_existing_pools(&database, &scope).await.unwrap();
+    assert!(newly_tracked.iter().any(|p| p.pool_address == wanted));
+    assert!(!newly_tracked.iter().any(|p| p.pool_address == other));
+
+    let untracked = database.get_untracked_pool_addresses().await.unwrap();
+    assert!(!untracked.contains(&wanted));
+    assert!(untracked.contains(&other));
+}
+
+#[tokio::test]
+async fn test_pools_created_in_block_range_and_creation_rate() {
+    let database = test_database().await;
+    let chain_id = 11280;
+    let run = unique_id();
+
+    for (i, block) in [300, 100, 200, 400].into_iter().enumerate() {
+        let mut created = pool(&format!("0x{:040x}", run + i as u128), block);
+        created.chain_id = chain_id;
+        database.upsert_pool(&created).await.unwrap();
+    }
+
+    let pools = database
+        .get_pools_created_in_block_range(chain_id, 100, 300)
+        .await
+        .unwrap();
+    let blocks: Vec<i64> = pools
+        .iter()
+        .filter(|p| p.pool_address >= format!("0x{:040x}", run))
+        .map(|p| p.created_at_block.unwrap())
+        .collect();
+    assert_eq!(blocks, vec![100, 200, 300]);
+
+    // At least this run's four pools were indexed within the last two hours
+    let rate = database.get_pool_creation_rate(chain_id, 2).await.unwrap();
+    assert!(rate >= 2.0);
+    assert_eq!(database.get_pool_creation_rate(chain_id, 0).await.unwrap(), 0.0);
+}
+
+#[tokio::test]
+async fn test_pools_sorted_by_recent_activity() {
+    let database = test_database().await;
+    let chain_id = 28_000_000 + (unique_id() % 1_000_000) as i64;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+
+    // Swapped at T, T-1h and T-30min, one only before the lookback and one never
+    let run = unique_id();
+    let addresses: Vec<String> = (0..5).map(|i| format!("0x{:040x}", run + i)).collect();
+    for address in &addresses {
+        let mut created = pool(address, 100);
+        created.chain_id = chain_id;
+        database.upsert_pool(&created).await.unwrap();
+    }
+    let swapped_at = [now, now - 3600, now - 1800, now - 3 * 3600];
+    for (address, timestamp) in addresses.iter().zip(swapped_at) {
+        let mut swapped = swap(&format!("0x{:064x}", unique_id()), 0, 200);
+        swapped.pool_address = address.clone();
+        swapped.timestamp = timestamp;
+        swapped.chain_id = chain_id;
+        database.insert_swap(&swapped).await.unwrap();
+    }
+
+    let pools = database.get_pools_sorted_by_recent_activity(chain_id, 2, 10).await.unwrap();
+    let order: Vec<&str> = pools.iter().map(|p| p.pool_address.as_str()).collect();
+    assert_eq!(order, vec![&addresses[0], &addresses[2], &addresses[1], &addresses[3], &addresses[4]]);
+
+    let pools = database.get_pools_sorted_by_recent_activity(chain_id, 2, 2).await.unwrap();
+    assert_eq!(pools.len(), 2);
+    assert_eq!(pools[1].pool_address, addresses[2]);
+}
+
+#[tokio::test]
+async fn test_pools_updated_since() {
+    let database = test_database().await;
+    let raw = sqlx::PgPool::connect(&env::var("DATABASE_URL").unwrap()).await.unwrap();
+    let chain_id = 30_000_000 + (unique_id() % 1_000_000) as i64;
+
+    // Last changed at 1000, 2000 and 2000 seconds, the tie broken by address
+    let run = unique_id();
+    let addresses: Vec<String> = (0..3).map(|i| format!("0x{:040x}", run + i)).collect();
+    for (address, updated_at) in addresses.iter().zip([1_000_i64, 2_000, 2_000]) {
+        let mut created = pool(address, 100);
+        created.chain_id = chain_id;
+        // Ignored on writes
+        created.updated_at = Some(5);
+        database.upsert_pool(&created).await.unwrap();
+        sqlx::query("UPDATE pools SET updated_at = TIMESTAMP 'epoch' + $2 * INTERVAL '1 second' WHERE pool_address = $1")
+            .bind(address)
+            .bind(updated_at)
+            .execute(&raw)
+            .await
+            .unwrap();
+    }
+
+    let page = database.get_pools_updated_since(chain_id, 0, None, 10, None).await.unwrap();
+    let order: Vec<&str> = page.pools.iter().map(|p| p.pool_address.as_str()).collect();
+    assert_eq!(order, vec![&addresses[0], &addresses[1], &addresses[2]]);
+    assert_eq!(page.pools[0].updated_at, Some(1_000));
+    assert!(page.pools[0].created_at.is_some_and(|at| at > 1_600_000_000));
+    assert!(page.next_cursor.is_none());
+
+    // Paging one pool at a time from 1500 on
+    let first = database.get_pools_updated_since(chain_id, 1_500, None, 1, None).await.unwrap();
+    assert_eq!(first.pools[0].pool_address, addresses[1]);
+    let cursor: PoolUpdateCursor = first.next_cursor.unwrap().parse().unwrap();
+    let second = database.get_pools_updated_since(chain_id, 1_500, None, 1, Some(&cursor)).await.unwrap();
+    assert_eq!(second.pools[0].pool_address, addresses[2]);
+    assert!(second.next_cursor.is_none());
+    assert!("2000".parse::<PoolUpdateCursor>().is_err());
+
+    // Refreshing the same state is a no-op; a new tick moves the pool to the end of the feed
+    let mut refreshed = pool(&addresses[0], 100);
+    refreshed.chain_id = chain_id;
+    database.upsert_pool(&refreshed).await.unwrap();
+    assert_eq!(database.get_pool(&addresses[0]).await.unwrap().unwrap().updated_at, Some(1_000));
+    refreshed.tick = Some(42);
+    database.upsert_pool(&refreshed).await.unwrap();
+    let page = database.get_pools_updated_since(chain_id, 0, None, 10, None).await.unwrap();
+    let order: Vec<&str> = page.pools.iter().map(|p| p.pool_address.as_str()).collect();
+    assert_eq!(order, vec![&addresses[1], &addresses[2], &addresses[0]]);
+    assert!(page.pools[2].updated_at.is_some_and(|at| at > 1_600_000_000));
+}
+
+#[tokio::test]
+async fn test_pool_stats_view() {
+    let database = test_database().await;
+    let chain_id = 31_000_000 + (unique_id() % 1_000_000) as i64;
+
+    // Two priced swaps in the first pool, none in the second
+    let run = unique_id();
+    let addresses: Vec<String> = (0..2).map(|i| format!("0x{:040x}", run + i)).collect();
+    for address in &addresses {
+        let mut created = pool(address, 100);
+        created.chain_id = chain_id;
+        database.upsert_pool(&created).await.unwrap();
+    }
+    for (log_index, timestamp) in [(0, 1_700_000_000), (1, 1_700_000_012)] {
+        let mut swapped = swap(&format!("0x{:064x}", unique_id()), log_index, 200);
+        swapped.pool_address = addresses[0].clone();
+        swapped.timestamp = timestamp;
+        swapped.chain_id = chain_id;
+        swapped.amount_in_usd = Some(2.5);
+        database.insert_swap(&swapped).await.unwrap();
+    }
+
+    let stats = database.query_pool_stats_view(chain_id, 10).await.unwrap();
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[0].pool.pool_address, addresses[0]);
+    assert_eq!((stats[0].swap_count, stats[0].last_swap_ts, stats[0].volume_usd), (2, Some(1_700_000_012), Some(5.0)));
+    assert_eq!((stats[1].swap_count, stats[1].last_swap_ts, stats[1].volume_usd), (0, None, None));
+    assert_eq!(database.query_pool_stats_view(chain_id, 1).await.unwrap().len(), 1);
+
+    // Recreating the view is idempotent
+    database.create_views().await.unwrap();
+    assert_eq!(database.query_pool_stats_view(chain_id, 10).await.unwrap(), stats);
+}
+
+#[tokio::test]
+async fn test_insert_swap_rejects_self_loop() {
+    let database = test_database().await;
+    let tx_hash = format!("0x{:064x}", unique_id());
+    let pool = pool("0x00000000000000000000000000000000000000aa", 1129);
+
+    let mut self_loop = swap(&tx_hash, 0, 1129);
+    self_loop.token_out = "token0".to_string();
+    assert!(self_loop.is_self_loop(&pool));
+    assert!(!self_loop.is_valid());
+
+    let err = database.insert_swap(&self_loop).await.unwrap_err();
+    assert!(err.downcast_ref::<InvalidEventError>().is_some());
+    assert!(database.get_swap_by_tx_hash(&tx_hash, 8453).await.unwrap().is_empty());
+
+    // A label and the address it resolves to are the same token
+    let mut resolved_loop = swap(&tx_hash, 1, 1129);
+    resolved_loop.token_out = pool.token0_address.to_uppercase();
+    assert!(resolved_loop.is_self_loop(&pool));
+    assert!(!swap(&tx_hash, 2, 1129).is_self_loop(&pool));
+
+    // Valid swaps go through, stamped with the insert time when the caller leaves it unset
+    let mut valid = swap(&tx_hash, 2, 1129);
+    valid.calldata = Some(HexBytes::try_from("0x128acb08").unwrap());
+    database.insert_swap(&valid).await.unwrap();
+    let stored = database.get_swap_by_tx_hash(&tx_hash, 8453).await.unwrap();
+    assert_eq!(stored.len(), 1);
+    assert!(stored[0].indexed_at.is_some_and(|at| at > 1640995200 * 1000));
+    assert_eq!(stored[0].calldata, valid.calldata);
+}
+
+struct MockSupplies(std::collections::HashMap<Address, U256>);
+
+#[async_trait::async_trait]
+impl SupplySource for MockSupplies {
+    async fn total_supplies(&self, tokens: &[Address]) -> anyhow::Result<Vec<Option<U256>>> {
+        Ok(tokens.iter().map(|token| self.0.get(token).copied()).collect())
+    }
+}
+
+#[tokio::test]
+async fn test_supply_refresh_records_changes_above_threshold() {
+    let database = test_database().await;
+    // Chain id unique to this run so only this test's pool is refreshed
+    let chain_id = 1_000_000 + (unique_id() % 1_000_000) as i64;
+    let token0 = "0x00000000000000000000000000000000011292a0";
+    let token1 = "0x00000000000000000000000000000000011292a1";
+
+    let mut pool_data = pool(&format!("0x{:040x}", unique_id()), 1129);
+    pool_data.token0_address = token0.to_string();
+    pool_data.token1_address = token1.to_string();
+    pool_data.chain_id = chain_id;
+    database.upsert_pool(&pool_data).await.unwrap();
+
+    // token1 reverts on totalSupply()
+    let mut supplies = MockSupplies([(token0.parse().unwrap(), U256::from(1_000_000))].into());
+
+    // First reading is always recorded
+    let counts = supply::refresh_supplies(&database, &supplies, chain_id, 100, 1_000).await.unwrap();
+    assert_eq!(counts, SupplyRefreshCounts { refreshed: 1, recorded: 1, failed: 1 });
+
+    // Exactly 100 bps is not "more than" the threshold
+    supplies.0.insert(token0.parse().unwrap(), U256::from(1_010_000));
+    let counts = supply::refresh_supplies(&database, &supplies, chain_id, 100, 2_000).await.unwrap();
+    assert_eq!(counts.recorded, 0);
+
+    // Compared against the stored value, not the last recorded one
+    supplies.0.insert(token0.parse().unwrap(), U256::from(1_030_000));
+    let counts = supply::refresh_supplies(&database, &supplies, chain_id, 100, 3_000).await.unwrap();
+    assert_eq!(counts.recorded, 1);
+
+    let token = database.get_token(token0, chain_id).await.unwrap().unwrap();
+    assert_eq!(token.total_supply.as_deref(), Some("1030000"));
+    assert_eq!(token.supply_updated_at, Some(3_000));
+
+    let history = database.get_token_supply_history(token0, chain_id).await.unwrap();
+    assert_eq!(history, vec![("1000000".to_string(), 1_000), ("1030000".to_string(), 3_000)]);
+}
+
+#[tokio::test]
+async fn test_flag_nonstandard_token_propagates_to_pools() {
+    let database = test_database().await;
+    let chain_id = 2_000_000 + (unique_id() % 1_000_000) as i64;
+    let fee_token = "0x00000000000000000000000000000000011302a0";
+
+    let mut flagged = pool(&format!("0x{:040x}", unique_id()), 1130);
+    flagged.token1_address = fee_token.to_string();
+    flagged.chain_id = chain_id;
+    database.upsert_pool(&flagged).await.unwrap();
+
+    let mut clean = pool(&format!("0x{:040x}", unique_id()), 1130);
+    clean.chain_id = chain_id;
+    database.upsert_pool(&clean).await.unwrap();
+
+    database.flag_nonstandard_token(fee_token, chain_id, TokenBehavior::FeeOnTransfer).await.unwrap();
+    // A later rebasing verdict adds to, rather than replaces, the fee flag
+    database.flag_nonstandard_token(fee_token, chain_id, TokenBehavior::Rebasing).await.unwrap();
+
+    let token = database.get_token(fee_token, chain_id).await.unwrap().unwrap();
+    assert!(token.is_fee_on_transfer);
+    assert!(token.is_rebasing);
+
+    assert!(database.get_pool(&flagged.pool_address).await.unwrap().unwrap().has_nonstandard_token);
+    assert!(!database.get_pool(&clean.pool_address).await.unwrap().unwrap().has_nonstandard_token);
+}
+
+#[tokio::test]
+async fn test_get_pools_by_liquidity_range() {
+    let database = test_database().await;
+    let chain_id = 3_000_000 + (unique_id() % 1_000_000) as i64;
+
+    let mut addresses = Vec::new();
+    for liquidity in [Some(100), Some(5_000), Some(1_000_000), None] {
+        let mut pool_data = pool(&format!("0x{:040x}", unique_id()), 1131);
+        pool_data.chain_id = chain_id;
+        pool_data.liquidity = liquidity;
+        database.upsert_pool(&pool_data).await.unwrap();
+        addresses.push(pool_data.pool_address);
+    }
+
+    let liquidities = |pools: Vec<PoolData>| pools.iter().map(|p| p.liquidity).collect::<Vec<_>>();
+
+    // Bounds are inclusive and results are deepest first
+    let pools = database.get_pools_by_liquidity_range(chain_id, 100, 1_000_000).await.unwrap();
+    assert_eq!(liquidities(pools), vec![Some(1_000_000), Some(5_000), Some(100)]);
+
+    let pools = database.get_pools_by_liquidity_range(chain_id, 101, 999_999).await.unwrap();
+    assert_eq!(liquidities(pools), vec![Some(5_000)]);
+
+    // NULL liquidity never matches a range
+    let pools = database.get_pools_by_liquidity_range(chain_id, 0, i64::MAX).await.unwrap();
+    assert_eq!(pools.len(), 3);
+    assert_eq!(database.get_pools_with_null_liquidity(chain_id).await.unwrap(), vec![addresses[3].clone()]);
+    assert_eq!(database.count_pools_with_null_liquidity(chain_id).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_curve_trades_and_migration_round_trip() {
+    let database = test_database().await;
+    let chain_id = 4_000_000 + (unique_id() % 1_000_000) as i64;
+    let token = format!("0x{:040x}", unique_id());
+    let tx_hash = format!("0x{:064x}", unique_id());
+
+    let trade = |log_index: i32, side: TradeSide, token_amount: &str| CurveTrade {
+        tx_hash: tx_hash.clone(),
+        log_index,
+        block_number: 1133,
+        timestamp: 1640995200,
+        chain_id,
+        curve_address: "0x00000000000000000000000000000000000000c0".to_string(),
+        token_address: token.clone(),
+        trader: "0x000000000000000000000000000000000000beef".to_string(),
+        side,
+        token_amount: token_amount.to_string(),
+        collateral_amount: "20".to_string(),
+    };
+
+    // Curve supplies are 18-decimal and routinely exceed i64
+    database.insert_curve_trade(&trade(1, TradeSide::Sell, "4000")).await.unwrap();
+    database.insert_curve_trade(&trade(0, TradeSide::Buy, "1000000000000000000000000")).await.unwrap();
+    database.insert_curve_trade(&trade(0, TradeSide::Buy, "1000000000000000000000000")).await.unwrap();
+
+    let trades = database.get_curve_trades(&token, chain_id).await.unwrap();
+    assert_eq!(trades.len(), 2);
+    assert_eq!((trades[0].side, trades[0].token_amount.as_str()), (TradeSide::Buy, "1000000000000000000000000"));
+    assert_eq!((trades[1].side, trades[1].token_amount.as_str()), (TradeSide::Sell, "4000"));
+    assert_eq!(trades[1].collateral_amount, "20");
+
+    // The migration only resolves to a pool once the pool is indexed
+    let pool_data = pool(&format!("0x{:040x}", unique_id()), 1133);
+    let migration = TokenMigration {
+        token_address: token.clone(),
+        curve_address: "0x00000000000000000000000000000000000000c0".to_string(),
+        pool_address: pool_data.pool_address.clone(),
+        tx_hash: tx_hash.clone(),
+        block_number: 1133,
+        log_index: 2,
+        timestamp: 1640995200,
+        final_curve_price: Some(0.005),
+        chain_id,
+    };
+    database.insert_token_migration(&migration).await.unwrap();
+    assert!(database.get_graduated_pool(&token, chain_id).await.unwrap().is_none());
+
+    database.upsert_pool(&pool_data).await.unwrap();
+    let graduated = database.get_graduated_pool(&token, chain_id).await.unwrap().unwrap();
+    assert_eq!(graduated.pool_address, pool_data.pool_address);
+}
+
+#[tokio::test]
+async fn test_swap_fee_revenue_by_pool() {
+    let database = test_database().await;
+    let chain_id = 5_000_000 + (unique_id() % 1_000_000) as i64;
+
+    let mut pools = Vec::new();
+    for fee_tier in [Some(3000), Some(10000), Some(500), None] {
+        let mut pool_data = pool(&format!("0x{:040x}", unique_id()), 1134);
+        pool_data.fee_tier = fee_tier;
+        database.upsert_pool(&pool_data).await.unwrap();
+        pools.push(pool_data.pool_address);
+    }
+
+    let insert = |pool_address: String, token_in: &'static str, amount_in: i64, usd: Option<f64>, timestamp: i64| {
+        let database = database.clone();
+        async move {
+            let mut swap_event = swap(&format!("0x{:064x}", unique_id()), 0, 1134);
+            swap_event.pool_address = pool_address;
+            swap_event.chain_id = chain_id;
+            swap_event.timestamp = timestamp;
+            if token_in == "token1" {
+                swap_event.token_in = "token1".to_string();
+                swap_event.token_out = "token0".to_string();
+            }
+            swap_event.amount_in = amount_in;
+            swap_event.amount_in_usd = usd;
+            database.insert_swap(&swap_event).await.unwrap();
+        }
+    };
+
+    // 0.3% pool: 1_000_000 of token0 and 2_000_000 of token1 in, $3000 of volume
+    insert(pools[0].clone(), "token0", 1_000_000, Some(1000.0), 1_000).await;
+    insert(pools[0].clone(), "token1", 2_000_000, Some(2000.0), 1_500).await;
+    // Outside the window
+    insert(pools[0].clone(), "token0", 9_000_000, Some(9000.0), 2_000).await;
+    // 1% pool with $5000 of volume earns more despite the smaller raw amount
+    insert(pools[1].clone(), "token0", 500_000, Some(5000.0), 1_000).await;
+    // Dust pool below the volume floor
+    insert(pools[2].clone(), "token0", 100, Some(0.5), 1_000).await;
+    // No fee tier, skipped
+    insert(pools[3].clone(), "token0", 1_000_000, Some(1000.0), 1_000).await;
+
+    let revenue = database.get_swap_fee_revenue_by_pool(chain_id, 1_000, 2_000, 1.0, false).await.unwrap();
+    let summary: Vec<_> = revenue
+        .iter()
+        .map(|r| (r.pool_address.clone(), r.fee_revenue_token0_raw, r.fee_revenue_token1_raw, r.fee_revenue_usd))
+        .collect();
+
+    assert_eq!(
+        summary,
+        vec![
+            (pools[1].clone(), 5_000, 0, Some(50.0)),
+            (pools[0].clone(), 3_000, 6_000, Some(9.0)),
+        ]
+    );
+
+    let with_dust = database.get_swap_fee_revenue_by_pool(chain_id, 1_000, 2_000, 0.0, false).await.unwrap();
+    assert_eq!(with_dust.len(), 3);
+}
+
+/// A freshly deployed pool between the graduated token and WETH.
+struct GraduatedPool {
+    token: Address,
+}
+
+#[async_trait::async_trait]
+impl PoolStateReader for GraduatedPool {
+    async fn read_pool_state(&self, _pool_address: Address) -> PoolStateReads {
+        PoolStateReads {
+            token0: Ok(self.token),
+            token1: Ok(Address::from_low_u64_be(0xe7)),
+            fee: Ok(10000),
+            tick_spacing: Ok(200),
+            liquidity: Ok(5_000_000),
+            slot0: Err(CallErrorKind::Revert),
+        }
+    }
+}
+
+fn curve_log(event: &str, indexed: &[Address], data: &[Token], block_number: u64, log_index: u64) -> Log {
+    let mut topics = vec![get_curve_abi().event(event).unwrap().signature()];
+    topics.extend(indexed.iter().map(|address| H256::from(*address)));
+
+    Log {
+        address: Address::from_low_u64_be(0xc0),
+        topics,
+        data: encode(data).into(),
+        block_number: Some(U64::from(block_number)),
+        log_index: Some(log_index.into()),
+        transaction_hash: Some(H256::from_low_u64_be(unique_id() as u64)),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_replayed_graduation_links_curve_and_pool_phases() {
+    let database = test_database().await;
+    let chain_id = 6_000_000 + (unique_id() % 1_000_000) as i64;
+    let token = Address::from_low_u64_be(unique_id() as u64);
+    let pool_address = Address::from_low_u64_be(unique_id() as u64);
+    let trader = Address::from_low_u64_be(0xbeef);
+    let amounts = |token_amount: u64, collateral_amount: u64| {
+        [Token::Uint(U256::from(token_amount)), Token::Uint(U256::from(collateral_amount))]
+    };
+
+    // create -> buys -> graduate, then the first swap in the graduation block
+    let logs = [
+        curve_log("TokenCreated", &[token, trader], &[], 100, 0),
+        curve_log("Buy", &[token, trader], &amounts(1_000, 1), 101, 0),
+        curve_log("Buy", &[token, trader], &amounts(2_000, 5), 102, 3),
+        curve_log("Graduated", &[token, pool_address], &[], 103, 1),
+    ];
+
+    let handler = CurveHandler::new();
+    let scope = IndexingScope::default();
+    let mut registered = None;
+    for log in &logs {
+        match handler.decode(log, chain_id).unwrap() {
+            CurveEvent::TokenCreated(created, _) => assert_eq!(created, token),
+            CurveEvent::Trade(trade) => database.insert_curve_trade(&trade).await.unwrap(),
+            CurveEvent::Graduated(mut migration) => {
+                let reader = GraduatedPool { token };
+                registered = migration::record_migration(&database, &reader, &scope, &mut migration).await.unwrap();
+            }
+        }
+    }
+
+    // The destination pool is tracked before any PoolCreated log was processed
+    let registered = registered.expect("graduated pool registered");
+    let pool_key = format!("{:?}", pool_address);
+    assert_eq!(registered.pool_address, pool_key);
+    assert_eq!(registered.token0_address, format!("{:?}", token));
+    assert_eq!(registered.created_at_block, Some(103));
+    assert!(database.get_pool_addresses_by_status(PoolStatus::Active).await.unwrap().contains(&pool_key));
+
+    let mut first_swap = swap(&format!("0x{:064x}", unique_id()), 4, 103);
+    first_swap.pool_address = pool_key.clone();
+    first_swap.chain_id = chain_id;
+    database.insert_swap(&first_swap).await.unwrap();
+
+    let token_key = format!("{:?}", token);
+    let timeline = migration::load_timeline(&database, &token_key, chain_id, 100).await.unwrap();
+    let migration = timeline.migration.expect("migration recorded");
+    assert_eq!(migration.pool_address, pool_key);
+    assert_eq!(migration.final_curve_price, Some(0.0025));
+
+    let phases: Vec<_> = timeline
+        .timeline
+        .iter()
+        .map(|entry| match entry {
+            TimelineEntry::Curve(trade) => ("curve", trade.block_number),
+            TimelineEntry::Amm(swap) => ("amm", swap.block_number),
+        })
+        .collect();
+    assert_eq!(phases, vec![("curve", 101), ("curve", 102), ("amm", 103)]);
+
+    assert_eq!(database.get_graduated_pool(&token_key, chain_id).await.unwrap().unwrap().pool_address, pool_key);
+}
+
+#[tokio::test]
+async fn test_tick_data_round_trip() {
+    let database = test_database().await;
+    let pool_address = format!("0x{:040x}", unique_id());
+    let tick = |tick_index: i32, liquidity_net: i128| TickData {
+        tick_index,
+        liquidity_gross: u128::MAX,
+        liquidity_net,
+        fee_growth_outside_0: U256::MAX,
+        fee_growth_outside_1: U256::from(7),
+    };
+
+    database
+        .upsert_tick_data(&pool_address, 8453, &[tick(60, i128::MIN), tick(-60, 500)], 1135)
+        .await
+        .unwrap();
+    // A later reading replaces the stored tick
+    database.upsert_tick_data(&pool_address, 8453, &[tick(60, -1_000)], 1136).await.unwrap();
+
+    let stored = database.get_tick_data(&pool_address, 8453, -60, 60).await.unwrap();
+    assert_eq!(stored, vec![tick(-60, 500), tick(60, -1_000)]);
+    assert_eq!(database.get_tick_data(&pool_address, 8453, 0, 60).await.unwrap(), vec![tick(60, -1_000)]);
+}
+
+#[tokio::test]
+async fn test_token_timeline_interleaves_and_paginates() {
+    let database = test_database().await;
+    let chain_id = 7_000_000 + (unique_id() % 1_000_000) as i64;
+    let token = format!("0x{:040x}", unique_id());
+    let curve = "0x00000000000000000000000000000000000000c0".to_string();
+    // Blocks are 2 seconds apart
+    let timestamp = |block_number: i64| 1_700_000_000 + block_number * 2;
+
+    for (block_number, log_index) in [(10, 0), (11, 1), (12, 2)] {
+        database
+            .insert_curve_trade(&CurveTrade {
+                tx_hash: format!("0x{:064x}", unique_id()),
+                log_index,
+                block_number,
+                timestamp: timestamp(block_number),
+                chain_id,
+                curve_address: curve.clone(),
+                token_address: token.clone(),
+                trader: "0x000000000000000000000000000000000000beef".to_string(),
+                side: TradeSide::Buy,
+                token_amount: "1000".to_string(),
+                collateral_amount: "1".to_string(),
+            })
+            .await
+            .unwrap();
+    }
+
+    // Graduation in block 12 creates the pool, migrates, and the first swaps follow
+    let mut pool_data = pool(&format!("0x{:040x}", unique_id()), 12);
+    pool_data.token0_address = token.clone();
+    pool_data.chain_id = chain_id;
+    database.upsert_pool(&pool_data).await.unwrap();
+    database.upsert_block(12, timestamp(12), chain_id).await.unwrap();
+
+    database
+        .insert_token_migration(&TokenMigration {
+            token_address: token.clone(),
+            curve_address: curve.clone(),
+            pool_address: pool_data.pool_address.clone(),
+            tx_hash: format!("0x{:064x}", unique_id()),
+            block_number: 12,
+            log_index: 3,
+            timestamp: timestamp(12),
+            final_curve_price: Some(0.001),
+            chain_id,
+        })
+        .await
+        .unwrap();
+
+    for (block_number, log_index) in [(12, 4), (13, 0), (13, 1)] {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), log_index, block_number);
+        swap_event.pool_address = pool_data.pool_address.clone();
+        swap_event.chain_id = chain_id;
+        swap_event.timestamp = timestamp(block_number);
+        database.insert_swap(&swap_event).await.unwrap();
+    }
+
+    let describe = |events: &[TokenEvent]| -> Vec<(&'static str, i64)> {
+        events
+            .iter()
+            .map(|event| match event {
+                TokenEvent::CurveTrade(trade) => ("curve_trade", trade.block_number),
+                TokenEvent::PoolCreated(pool) => ("pool_created", pool.created_at_block.unwrap()),
+                TokenEvent::Migration(migration) => ("migration", migration.block_number),
+                TokenEvent::Swap(swap) => ("swap", swap.block_number),
+            })
+            .collect()
+    };
+
+    let full = database.get_token_timeline(&token, chain_id, 0, i64::MAX, 100, None).await.unwrap();
+    assert!(full.next_cursor.is_none());
+    assert_eq!(
+        describe(&full.events),
+        vec![
+            ("curve_trade", 10),
+            ("curve_trade", 11),
+            ("pool_created", 12),
+            ("curve_trade", 12),
+            ("migration", 12),
+            ("swap", 12),
+            ("swap", 13),
+            ("swap", 13),
+        ]
+    );
+
+    // Pages of three continue across the curve/pool boundary without gaps or repeats
+    let mut paged = Vec::new();
+    let mut cursor: Option<TimelineCursor> = None;
+    let mut pages = 0;
+    loop {
+        let page = database
+            .get_token_timeline(&token, chain_id, 0, i64::MAX, 3, cursor.as_ref())
+            .await
+            .unwrap();
+        pages += 1;
+        paged.extend(page.events);
+        match page.next_cursor {
+            Some(next) => cursor = Some(next.parse().unwrap()),
+            None => break,
+        }
+    }
+    assert_eq!(pages, 3);
+    assert_eq!(describe(&paged), describe(&full.events));
+
+    // The time window applies to every kind of event
+    let window = database
+        .get_token_timeline(&token, chain_id, timestamp(11), timestamp(13), 100, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        describe(&window.events),
+        vec![("curve_trade", 11), ("pool_created", 12), ("curve_trade", 12), ("migration", 12), ("swap", 12)]
+    );
+}
+
+// Stands in for a Transfer-event index: returns every holder whatever the candidates
+struct MockHolders {
+    balances: Vec<(Address, U256)>,
+    candidates: std::sync::Mutex<Vec<Address>>,
+}
+
+#[async_trait::async_trait]
+impl HolderSource for MockHolders {
+    async fn holder_balances(&self, _token: Address, candidates: &[Address]) -> anyhow::Result<Vec<(Address, U256)>> {
+        *self.candidates.lock().unwrap() = candidates.to_vec();
+        Ok(self.balances.clone())
+    }
+}
+
+#[tokio::test]
+async fn test_holder_snapshots_follow_interval() {
+    let database = test_database().await;
+    let chain_id = 8_000_000 + (unique_id() % 1_000_000) as i64;
+    let token = format!("0x{:040x}", unique_id());
+    let creator: Address = "0x000000000000000000000000000000000000beef".parse().unwrap();
+
+    database.update_token_supply(&token, chain_id, "200000", 1_000).await.unwrap();
+    database.set_token_creator(&token, chain_id, &format!("{:?}", creator)).await.unwrap();
+    database
+        .insert_curve_trade(&CurveTrade {
+            tx_hash: format!("0x{:064x}", unique_id()),
+            log_index: 0,
+            block_number: 10,
+            timestamp: 1_000,
+            chain_id,
+            curve_address: "0x00000000000000000000000000000000000000c0".to_string(),
+            token_address: token.clone(),
+            trader: format!("{:?}", creator),
+            side: TradeSide::Buy,
+            token_amount: "1000".to_string(),
+            collateral_amount: "1".to_string(),
+        })
+        .await
+        .unwrap();
+
+    // Twelve holders of 1_000..12_000; the top ten hold 75_000 of 200_000
+    let source = MockHolders {
+        balances: (1..=12).map(|n| (Address::from_low_u64_be(n), U256::from(n * 1_000))).collect(),
+        candidates: std::sync::Mutex::new(Vec::new()),
+    };
+    let policy = HolderSnapshotPolicy {
+        interval_secs: 3600,
+        top_n: 5,
+        holders: Vec::new(),
+        curve_address: None,
+    };
+    let now = 1_700_000_000;
+
+    let counts = holders::snapshot_holders(&database, &source, &policy, chain_id, now).await.unwrap();
+    assert_eq!(counts, HolderSnapshotCounts { taken: 1, skipped: 0, failed: 0 });
+    assert!(source.candidates.lock().unwrap().contains(&creator));
+
+    let snapshot = database.get_latest_holder_snapshot(&token, chain_id).await.unwrap().unwrap();
+    assert_eq!(snapshot.taken_at, now);
+    assert_eq!(snapshot.top10_concentration_pct, Some(37.5));
+    let balances: Vec<&str> = snapshot.top_holders.iter().map(|holder| holder.balance.as_str()).collect();
+    assert_eq!(balances, vec!["12000", "11000", "10000", "9000", "8000"]);
+    assert_eq!(snapshot.top_holders[0].holder_address, format!("{:?}", Address::from_low_u64_be(12)));
+
+    // Not due one second before the interval is up
+    let counts = holders::snapshot_holders(&database, &source, &policy, chain_id, now + 3599).await.unwrap();
+    assert_eq!(counts, HolderSnapshotCounts { taken: 0, skipped: 1, failed: 0 });
+
+    let counts = holders::snapshot_holders(&database, &source, &policy, chain_id, now + 3600).await.unwrap();
+    assert_eq!(counts.taken, 1);
+    let snapshot = database.get_latest_holder_snapshot(&token, chain_id).await.unwrap().unwrap();
+    assert_eq!(snapshot.taken_at, now + 3600);
+
+    let timeline = migration::load_timeline(&database, &token, chain_id, 10).await.unwrap();
+    assert_eq!(timeline.top10_concentration_pct, Some(37.5));
+    assert_eq!(timeline.token.unwrap().creator_address, Some(format!("{:?}", creator)));
+}
+
+#[tokio::test]
+async fn test_pool_swap_size_distribution() {
+    let database = test_database().await;
+    let chain_id = 9_000_000 + (unique_id() % 1_000_000) as i64;
+    let (uniform, thin, unpriced) = (
+        format!("0x{:040x}", unique_id()),
+        format!("0x{:040x}", unique_id() + 1),
+        format!("0x{:040x}", unique_id() + 2),
+    );
+
+    let insert = |pool_address: &str, usd: Option<f64>, timestamp: i64| {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), 0, 1138);
+        swap_event.pool_address = pool_address.to_string();
+        swap_event.chain_id = chain_id;
+        swap_event.amount_in_usd = usd;
+        swap_event.timestamp = timestamp;
+        let database = database.clone();
+        async move { database.insert_swap(&swap_event).await.unwrap() }
+    };
+
+    // $1 to $100 uniformly, plus unpriced and out-of-window swaps that must not count
+    for usd in 1..=100 {
+        insert(&uniform, Some(usd as f64), 1_000 + usd).await;
+    }
+    insert(&uniform, None, 1_050).await;
+    insert(&uniform, Some(1_000_000.0), 2_000).await;
+
+    let distribution = database.get_pool_swap_size_distribution(&uniform, chain_id, 1_000, 2_000).await.unwrap();
+    assert!((distribution.p50 - 50.0).abs() < 1.0, "p50 is {}", distribution.p50);
+    let SwapSizeDistribution { p10, p25, p50, p75, p90, p99, mean } = distribution;
+    // Linear interpolation between the $1 steps
+    let expected = [(p10, 10.9), (p25, 25.75), (p50, 50.5), (p75, 75.25), (p90, 90.1), (p99, 99.01), (mean, 50.5)];
+    for (actual, expected) in expected {
+        assert!((actual - expected).abs() < 1e-9, "expected {}, got {}", expected, actual);
+    }
+
+    // Nine priced swaps are too few
+    for usd in 1..=9 {
+        insert(&thin, Some(usd as f64), 1_000 + usd).await;
+    }
+    assert!(database.get_pool_swap_size_distribution(&thin, chain_id, 1_000, 2_000).await.is_err());
+
+    for timestamp in 1_000..1_020 {
+        insert(&unpriced, None, timestamp).await;
+    }
+    let err = database.get_pool_swap_size_distribution(&unpriced, chain_id, 1_000, 2_000).await.unwrap_err();
+    assert!(err.to_string().contains("USD amount"));
+}
+
+#[tokio::test]
+async fn test_impermanent_loss_estimate() {
+    let database = test_database().await;
+    let chain_id = 10_000_000 + (unique_id() % 1_000_000) as i64;
+    let pool_address = &format!("0x{:040x}", unique_id());
+
+    let mut created = pool(pool_address, 50);
+    created.chain_id = chain_id;
+    database.upsert_pool(&created).await.unwrap();
+
+    let mut opened = created.clone();
+    opened.tick = Some(0);
+    database.insert_pool_snapshot(&opened, 100).await.unwrap();
+
+    // Tick 6931 is a 2x price move
+    let mut current = created.clone();
+    current.tick = Some(6931);
+    database.upsert_pool(&current).await.unwrap();
+
+    let loss = database.get_impermanent_loss_estimate(pool_address, chain_id, 150).await.unwrap();
+    assert!((loss - -0.057).abs() < 1e-3, "loss is {}", loss);
+
+    // Before the first snapshot there is no starting tick
+    assert!(database.get_impermanent_loss_estimate(pool_address, chain_id, 99).await.is_err());
+}
+
+#[tokio::test]
+async fn test_wallet_pnl_estimate() {
+    let database = test_database().await;
+    let chain_id = 11_000_000 + (unique_id() % 1_000_000) as i64;
+    let (wallet, other, unpriced) = (
+        format!("0x{:040x}", unique_id()),
+        format!("0x{:040x}", unique_id() + 1),
+        format!("0x{:040x}", unique_id() + 2),
+    );
+
+    let insert = |sender: &str, usd: Option<(f64, f64)>, timestamp: i64| {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), 0, 1141);
+        swap_event.chain_id = chain_id;
+        swap_event.sender_address = Some(sender.to_string());
+        swap_event.amount_in_usd = usd.map(|(spent, _)| spent);
+        swap_event.amount_out_usd = usd.map(|(_, received)| received);
+        swap_event.timestamp = timestamp;
+        let database = database.clone();
+        async move { database.insert_swap(&swap_event).await.unwrap() }
+    };
+
+    // Pays $1,000 for tokens worth $900, then sells them for $600
+    insert(&wallet, Some((1_000.0, 900.0)), 1_000).await;
+    insert(&wallet, Some((900.0, 600.0)), 1_500).await;
+    // Outside the window or sent by someone else
+    insert(&wallet, Some((10.0, 5_000.0)), 2_000).await;
+    insert(&other, Some((10.0, 5_000.0)), 1_200).await;
+
+    let pnl = database.get_wallet_pnl_estimate(&wallet, chain_id, 1_000, 2_000).await.unwrap();
+    assert_eq!(
+        pnl,
+        WalletPnL { total_spent_usd: 1_900.0, total_received_usd: 1_500.0, net_pnl_usd: -400.0, swap_count: 2 }
+    );
+
+    insert(&unpriced, Some((100.0, 99.0)), 1_000).await;
+    insert(&unpriced, None, 1_100).await;
+    let err = database.get_wallet_pnl_estimate(&unpriced, chain_id, 1_000, 2_000).await.unwrap_err();
+    assert!(err.to_string().contains("no USD amounts"));
+}
+
+#[tokio::test]
+async fn test_swap_count_per_sender() {
+    let database = test_database().await;
+    let chain_id = 34_000_000 + (unique_id() % 1_000_000) as i64;
+    let (casual, regular, bot) = (
+        format!("0x{:040x}", unique_id()),
+        format!("0x{:040x}", unique_id() + 1),
+        format!("0x{:040x}", unique_id() + 2),
+    );
+
+    let insert = |sender: &str, pool_address: &str, usd: Option<f64>| {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), 0, 1168);
+        swap_event.chain_id = chain_id;
+        swap_event.pool_address = pool_address.to_string();
+        swap_event.sender_address = Some(sender.to_string());
+        swap_event.amount_in_usd = usd;
+        swap_event.timestamp = 1_000;
+        let database = database.clone();
+        async move { database.insert_swap(&swap_event).await.unwrap() }
+    };
+
+    let (pool_a, pool_b) = ("0x00000000000000000000000000000000000000aa", "0x00000000000000000000000000000000000000bb");
+    insert(&casual, pool_a, Some(1.0)).await;
+    for _ in 0..5 {
+        insert(&regular, pool_a, None).await;
+    }
+    for i in 0..10 {
+        insert(&bot, if i % 2 == 0 { pool_a } else { pool_b }, Some(2.0)).await;
+    }
+
+    let activity = database.get_swap_count_per_sender(chain_id, 1_000, 2_000, 5).await.unwrap();
+    assert_eq!(
+        activity,
+        vec![
+            SenderActivity { sender_address: bot, swap_count: 10, total_volume_usd: Some(20.0), unique_pools: 2 },
+            SenderActivity { sender_address: regular, swap_count: 5, total_volume_usd: None, unique_pools: 1 },
+        ]
+    );
+
+    // The window ends before the swaps
+    assert!(database.get_swap_count_per_sender(chain_id, 0, 1_000, 1).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_pools_by_fee_tier() {
+    let database = test_database().await;
+    let chain_id = 38_000_000 + (unique_id() % 1_000_000) as i64;
+
+    // Two 0.3% pools, the second deeper, and one 0.05% pool
+    let mut addresses = Vec::new();
+    for (fee_tier, liquidity) in [(3000, 10), (3000, 500), (500, 100)] {
+        let mut pool_data = pool(&format!("0x{:040x}", unique_id()), 1);
+        pool_data.chain_id = chain_id;
+        pool_data.fee_tier = Some(fee_tier);
+        pool_data.liquidity = Some(liquidity);
+        database.upsert_pool(&pool_data).await.unwrap();
+        addresses.push(pool_data.pool_address);
+    }
+
+    let pools = database.get_pools_by_fee_tier(3000, chain_id).await.unwrap();
+    assert_eq!(
+        pools.iter().map(|pool| pool.pool_address.as_str()).collect::<Vec<_>>(),
+        vec![addresses[1].as_str(), addresses[0].as_str()]
+    );
+    assert_eq!(database.get_pools_by_fee_tier(500, chain_id).await.unwrap().len(), 1);
+    assert!(database.get_pools_by_fee_tier(10000, chain_id).await.unwrap().is_empty());
+
+    assert_eq!(database.get_fee_tier_distribution(chain_id).await.unwrap(), vec![(3000, 2), (500, 1)]);
+}
+
+#[tokio::test]
+async fn test_pools_updated_after_block() {
+    let database = test_database().await;
+    let chain_id = 40_000_000 + (unique_id() % 1_000_000) as i64;
+
+    let updated = pool(&format!("0x{:040x}", unique_id()), 10);
+    let stale = pool(&format!("0x{:040x}", unique_id()), 20);
+    for pool_data in [&updated, &stale] {
+        let mut pool_data = pool_data.clone();
+        pool_data.chain_id = chain_id;
+        database.upsert_pool(&pool_data).await.unwrap();
+    }
+
+    // Swaps refresh both pools; the second sees the same state and keeps its block
+    let mut refreshed = updated.clone();
+    refreshed.chain_id = chain_id;
+    refreshed.liquidity = Some(500);
+    refreshed.updated_at_block = Some(100);
+    database.upsert_pool(&refreshed).await.unwrap();
+    let mut unchanged = stale.clone();
+    unchanged.chain_id = chain_id;
+    unchanged.updated_at_block = Some(100);
+    database.upsert_pool(&unchanged).await.unwrap();
+
+    assert_eq!(database.get_pools_updated_after_block(99, chain_id).await.unwrap(), vec![updated.pool_address.clone()]);
+    assert!(database.get_pools_updated_after_block(100, chain_id).await.unwrap().is_empty());
+    assert_eq!(
+        database.get_pools_updated_after_block(0, chain_id).await.unwrap(),
+        vec![stale.pool_address.clone(), updated.pool_address.clone()]
+    );
+    assert!(database.get_pools_updated_after_block(0, chain_id + 1).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_block_stats_range() {
+    let database = test_database().await;
+    let chain_id = 37_000_000 + (unique_id() % 1_000_000) as i64;
+    let (pool_a, pool_b) = ("0x00000000000000000000000000000000000000aa", "0x00000000000000000000000000000000000000bb");
+
+    // Ten blocks: two swaps in one pool per block, a third in another pool in even blocks,
+    // and none at all in block 105
+    for block_number in (100..110).filter(|block| *block != 105) {
+        for (log_index, pool_address) in [(0, pool_a), (1, pool_a), (2, pool_b)] {
+            if pool_address == pool_b && block_number % 2 == 1 {
+                continue;
+            }
+            let mut swap_event = swap(&format!("0x{:064x}", unique_id()), log_index, block_number);
+            swap_event.chain_id = chain_id;
+            swap_event.pool_address = pool_address.to_string();
+            swap_event.amount_in_usd = (block_number != 109).then_some(10.0);
+            database.insert_swap(&swap_event).await.unwrap();
+        }
+    }
+
+    let stats = database.get_block_stats_range(chain_id, 100, 109).await.unwrap();
+    assert_eq!(stats.len(), 10);
+    assert_eq!(stats[0], BlockStats { block_number: 100, swap_count: 3, pool_count: 2, volume_usd: Some(30.0) });
+    assert_eq!(stats[1], BlockStats { block_number: 101, swap_count: 2, pool_count: 1, volume_usd: Some(20.0) });
+    assert_eq!(stats[5], BlockStats { block_number: 105, swap_count: 0, pool_count: 0, volume_usd: None });
+    assert_eq!(stats[9], BlockStats { block_number: 109, swap_count: 2, pool_count: 1, volume_usd: None });
+
+    assert!(database.get_block_stats_range(chain_id, 0, 1_000_000).await.is_err());
+}
+
+#[tokio::test]
+async fn test_protocol_stats() {
+    let database = test_database().await;
+    let chain_id = 12_000_000 + (unique_id() % 1_000_000) as i64;
+
+    let empty = database.get_protocol_stats(chain_id).await.unwrap();
+    assert_eq!(
+        empty,
+        ProtocolStats {
+            total_pools: 0,
+            total_swaps: 0,
+            total_volume_usd: None,
+            active_pools_24h: 0,
+            new_pools_24h: 0,
+            unique_tokens: 0,
+            last_block: 0,
+        }
+    );
+
+    // Two pools sharing token0, one of them traded today
+    let (active, idle) = (format!("0x{:040x}", unique_id()), format!("0x{:040x}", unique_id() + 1));
+    for (address, created_at_block) in [(&active, 1_100), (&idle, 1_200)] {
+        let mut pool = pool(address, created_at_block);
+        pool.chain_id = chain_id;
+        pool.token1_address = format!("0x{:040x}", created_at_block);
+        database.upsert_pool(&pool).await.unwrap();
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    let swaps = [(&active, Some(250.0), now - 60, 1_150), (&idle, None, now - 3 * 86_400, 1_250)];
+    for (pool_address, usd, timestamp, block) in swaps {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), 0, block);
+        swap_event.chain_id = chain_id;
+        swap_event.pool_address = pool_address.clone();
+        swap_event.amount_in_usd = usd;
+        swap_event.timestamp = timestamp;
+        database.insert_swap(&swap_event).await.unwrap();
+    }
+
+    let stats = database.get_protocol_stats(chain_id).await.unwrap();
+    assert_eq!(
+        stats,
+        ProtocolStats {
+            total_pools: 2,
+            total_swaps: 2,
+            total_volume_usd: Some(250.0),
+            active_pools_24h: 1,
+            new_pools_24h: 2,
+            unique_tokens: 3,
+            last_block: 1_250,
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_pool_ranking() {
+    let database = test_database().await;
+    let chain_id = 16_000_000 + (unique_id() % 1_000_000) as i64;
+
+    // `busy` trades more often with more traders, `big` trades more volume, `idle` not today
+    let (big, busy, idle) = (
+        format!("0x{:040x}", unique_id()),
+        format!("0x{:040x}", unique_id() + 1),
+        format!("0x{:040x}", unique_id() + 2),
+    );
+    for (address, fee_tier, liquidity) in [(&big, 3000, Some(500)), (&busy, 10000, Some(2_000)), (&idle, 500, None)] {
+        let mut pool = pool(address, 1_000);
+        pool.chain_id = chain_id;
+        pool.fee_tier = Some(fee_tier);
+        pool.liquidity = liquidity;
+        pool.token0_symbol = Some("MOON".to_string());
+        database.upsert_pool(&pool).await.unwrap();
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+    let swaps = [
+        (&big, "0xa1", 100.0, now - 60),
+        (&big, "0xa1", 100.0, now - 120),
+        (&busy, "0xb1", 50.0, now - 60),
+        (&busy, "0xb2", 50.0, now - 60),
+        (&busy, "0xb3", 50.0, now - 60),
+        // Outside the 24-hour window
+        (&busy, "0xb1", 1_000.0, now - 2 * 86_400),
+        (&idle, "0xc1", 1_000.0, now - 2 * 86_400),
+    ];
+    for (pool_address, sender, usd, timestamp) in swaps {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), 0, 1_100);
+        swap_event.chain_id = chain_id;
+        swap_event.pool_address = pool_address.clone();
+        swap_event.sender_address = Some(sender.to_string());
+        swap_event.amount_in_usd = Some(usd);
+        swap_event.timestamp = timestamp;
+        database.insert_swap(&swap_event).await.unwrap();
+    }
+
+    let expected = [
+        (PoolRankingMetric::Volume24h, [(&big, 200.0), (&busy, 150.0)]),
+        (PoolRankingMetric::SwapCount24h, [(&busy, 3.0), (&big, 2.0)]),
+        (PoolRankingMetric::FeeRevenue24h, [(&busy, 1.5), (&big, 0.6)]),
+        (PoolRankingMetric::UniqueTraders24h, [(&busy, 3.0), (&big, 1.0)]),
+        // Pools whose liquidity was never read are left out
+        (PoolRankingMetric::Liquidity, [(&busy, 2_000.0), (&big, 500.0)]),
+    ];
+    for (metric, pools) in expected {
+        let ranks = database.get_pool_ranking(chain_id, metric, 10).await.unwrap();
+        let ranked: Vec<_> = ranks.iter().map(|rank| (rank.rank, &rank.pool_address, rank.metric_value)).collect();
+        assert_eq!(ranked, vec![(1, pools[0].0, pools[0].1), (2, pools[1].0, pools[1].1)], "{:?}", metric);
+    }
+
+    let top = database.get_pool_ranking(chain_id, PoolRankingMetric::Volume24h, 1).await.unwrap();
+    assert_eq!(
+        top,
+        vec![PoolRank {
+            rank: 1,
+            pool_address: big.clone(),
+            metric_value: 200.0,
+            token0_symbol: Some("MOON".to_string()),
+            token1_symbol: None,
+            fee_tier: Some(3000),
+        }]
+    );
+}
+
+#[tokio::test]
+async fn test_replay_matches_golden_file() {
+    let database = test_database().await;
+    // Fixed ids so the output is the same every run; inserts are idempotent
+    let chain_id = 17_000_000;
+
+    let mut seeded = pool("0x0000000000000000000000000000000017000001", 100);
+    seeded.chain_id = chain_id;
+    seeded.token0_symbol = Some("MOON".to_string());
+    seeded.token1_symbol = Some("WETH".to_string());
+    seeded.fee_tier = Some(3000);
+    database.upsert_pool(&seeded).await.unwrap();
+    database.upsert_block(100, 1_700_000_200, chain_id).await.unwrap();
+
+    // Inserted out of order; the last one falls after the window
+    let swaps = [(2, 101, 1_700_000_202), (0, 100, 1_700_000_200), (1, 100, 1_700_000_200), (0, 150, 1_700_000_300)];
+    for (log_index, block_number, timestamp) in swaps {
+        let mut swap_event = swap(&format!("0x{:064x}", 17_000_000 + block_number), log_index, block_number);
+        swap_event.chain_id = chain_id;
+        swap_event.pool_address = seeded.pool_address.clone();
+        swap_event.timestamp = timestamp;
+        swap_event.amount_in_usd = Some(12.5);
+        swap_event.indexed_at = Some(timestamp * 1000 + 250);
+        database.insert_swap(&swap_event).await.unwrap();
+    }
+
+    let mut sink = JsonlSink::new(Vec::new());
+    let options = ReplayOptions { chain_id, from_ts: 1_700_000_000, to_ts: 1_700_000_300, speed: None, pretty: false };
+    let sent = replay::replay(&database, &options, &mut sink, None).await.unwrap();
+
+    let output = String::from_utf8(sink.into_inner()).unwrap();
+    assert_eq!(sent, 4);
+    assert_eq!(output, include_str!("golden/replay.jsonl"));
+}
+
+/// Records what it was sent and what was flushed, crashing on the send after `crash_after`.
+#[derive(Default)]
+struct RecordingSink {
+    sent: Vec<serde_json::Value>,
+    flushed: usize,
+    crash_after: Option<usize>,
+}
+
+#[async_trait]
+impl Sink for RecordingSink {
+    async fn send(&mut self, payload: &serde_json::Value) -> anyhow::Result<()> {
+        if self.crash_after.is_some_and(|crash_after| self.sent.len() >= crash_after) {
+            anyhow::bail!("scripted crash");
+        }
+        self.sent.push(payload.clone());
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        self.flushed = self.sent.len();
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_replay_resumes_after_acknowledged_offset() {
+    let database = test_database().await;
+    let chain_id = 21_000_000 + (unique_id() % 1_000_000) as i64;
+    for i in 0..10 {
+        let mut swap_event = swap(&format!("0x{:064x}", unique_id()), i % 2, 100 + i as i64 / 2);
+        swap_event.chain_id = chain_id;
+        swap_event.timestamp = 1_700_000_000 + i as i64;
+        database.insert_swap(&swap_event).await.unwrap();
+    }
+    let tx_hashes = |sink: &RecordingSink| -> Vec<String> {
+        sink.sent.iter().map(|payload| payload["event"]["tx_hash"].as_str().unwrap().to_string()).collect()
+    };
+
+    let options = ReplayOptions { chain_id, from_ts: 1_700_000_000, to_ts: 1_700_000_100, speed: None, pretty: false };
+    let offsets = SinkOffsetStore::new(database.clone(), "recording", chain_id).with_ack_every(3);
+
+    // Without offsets everything is sent
+    let mut all = RecordingSink::default();
+    assert_eq!(replay::replay(&database, &options, &mut all, None).await.unwrap(), 10);
+    let all = tx_hashes(&all);
+
+    // Crashes while sending the eighth event: six were acknowledged, the seventh was not
+    let mut crashed = RecordingSink { crash_after: Some(7), ..Default::default() };
+    assert!(replay::replay(&database, &options, &mut crashed, Some(&offsets)).await.is_err());
+    assert_eq!(crashed.flushed, 6);
+    let acknowledged = offsets.last_acknowledged().await.unwrap().unwrap();
+    assert_eq!((acknowledged.block_number(), acknowledged.log_index()), (102, 1));
+
+    // The restart resends only the unacknowledged tail
+    let mut restarted = RecordingSink::default();
+    assert_eq!(replay::replay(&database, &options, &mut restarted, Some(&offsets)).await.unwrap(), 4);
+    assert_eq!(tx_hashes(&crashed), all[..7]);
+    assert_eq!(tx_hashes(&restarted), all[6..]);
+
+    // Everything is acknowledged now, so another run sends nothing
+    let mut rerun = RecordingSink::default();
+    assert_eq!(replay::replay(&database, &options, &mut rerun, Some(&offsets)).await.unwrap(), 0);
+}
+
+/// Keeps uploaded objects in memory, failing the first `failures` puts.
+#[derive(Default)]
+struct MemoryStore {
+    objects: StdMutex<Vec<(String, Vec<u8>)>>,
+    failures: StdMutex<usize>,
+}
+
+#[async_trait]
+impl ObjectStore for MemoryStore {
+    async fn put_object(&self, key: &str, body: Vec<u8>) -> anyhow::Result<()> {
+        let mut failures = self.failures.lock().unwrap();
+        if *failures > 0 {
+            *failures -= 1;
+            anyhow::bail!("scripted upload failure");
+        }
+        self.objects.lock().unwrap().push((key.to_string(), body));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_archiver_uploads_completed_windows() {
+    let database = test_database().await;
+    let chain_id = 18_000_000 + (unique_id() % 1_000_000) as i64;
+    let policy = ArchivePolicy {
+        chain_id,
+        window_blocks: 100,
+        retry_initial_delay: Duration::from_millis(10),
+        retry_max_delay: Duration::from_millis(10),
+    };
+
+    // Three swaps in window 100-199, one in the unfinished 200-299
+    for (log_index, block_number) in [(0, 150), (1, 100), (0, 199), (0, 230)] {
+        let mut swap_event = swap(&format!("0x{:064x}", chain_id * 1000 + block_number), log_index, block_number);
+        swap_event.chain_id = chain_id;
+        swap_event.timestamp = 1_700_000_000 + block_number;
+        database.insert_swap(&swap_event).await.unwrap();
+    }
+
+    let store = Arc::new(MemoryStore { failures: StdMutex::new(1), ..Default::default() });
+    let budget = Arc::new(MemoryBudget::from_mb(1));
+    let (ranges, task) = Archiver::spawn(store.clone(), database.clone(), budget.clone(), policy);
+    ranges.send((120, 160)).unwrap();
+    ranges.send((161, 199)).unwrap();
+    ranges.send((200, 240)).unwrap();
+    drop(ranges);
+    task.await.unwrap();
+
+    // Uploaded once despite the failed first attempt, from the start of the window
+    let objects = store.objects.lock().unwrap().clone();
+    assert_eq!(objects.len(), 1);
+    let (key, body) = &objects[0];
+    assert_eq!(key, &format!("chain={}/date=2023-11-14/swaps-100-199.jsonl.gz", chain_id));
+    let mut jsonl = String::new();
+    GzDecoder::new(body.as_slice()).read_to_string(&mut jsonl).unwrap();
+    let archived: Vec<SwapEvent> = jsonl.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(archived.iter().map(|swap| swap.block_number).collect::<Vec<_>>(), vec![100, 150, 199]);
+
+    assert!(database.is_range_archived(chain_id, 100, 199).await.unwrap());
+    assert!(!database.is_range_archived(chain_id, 200, 299).await.unwrap());
+    assert_eq!(budget.in_flight_bytes(), 0);
+
+    // After a restart, windows already archived are not uploaded again
+    let (ranges, task) = Archiver::spawn(store.clone(), database.clone(), budget.clone(), policy);
+    ranges.send((100, 199)).unwrap();
+    drop(ranges);
+    task.await.unwrap();
+    assert_eq!(store.objects.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_native_price_tracks_deepest_stablecoin_pool() {
+    let database = test_database().await;
+    let chain_id = 20_000_000 + (unique_id() % 1_000_000) as i64;
+    let weth = format!("0x{:040x}", unique_id());
+    let usdc = format!("0x{:040x}", unique_id() + 1);
+    let usdt = format!("0x{:040x}", unique_id() + 2);
+    let stablecoins = vec![usdc.clone(), usdt.clone()];
+    let mut tracker = NativePriceTracker::new(chain_id, &weth, &stablecoins, Duration::ZERO);
+
+    // No pool yet: the price is unavailable rather than made up
+    assert_eq!(tracker.refresh(&database, 100, 1_000).await.unwrap(), None);
+    assert_eq!(tracker.native_usd_price(), None);
+
+    let stable_pool = |offset: u128, stablecoin: &str, liquidity: i64, price_usd: f64| {
+        let mut pool_data = pool(&format!("0x{:040x}", unique_id() + offset), 1);
+        pool_data.chain_id = chain_id;
+        pool_data.token0_address = weth.clone();
+        pool_data.token1_address = stablecoin.to_string();
+        pool_data.token0_decimals = Some(18);
+        pool_data.token1_decimals = Some(6);
+        pool_data.liquidity = Some(liquidity);
+        pool_data.tick = Some(price::price_to_tick(price_usd, 18, 6).unwrap());
+        pool_data
+    };
+    let shallow = stable_pool(10, &usdc, 1_000, 1_900.0);
+    let mut deep = stable_pool(11, &usdt, 50_000, 2_000.0);
+    database.upsert_pool(&shallow).await.unwrap();
+    database.upsert_pool(&deep).await.unwrap();
+
+    let reading = tracker.refresh(&database, 101, 1_060).await.unwrap().cloned().unwrap();
+    assert_eq!(reading.pool_address, deep.pool_address);
+    assert!((reading.price_usd - 2_000.0).abs() < 0.5, "{}", reading.price_usd);
+
+    // The next refresh reads the pool's new state
+    deep.tick = Some(price::price_to_tick(2_500.0, 18, 6).unwrap());
+    database.upsert_pool(&deep).await.unwrap();
+    let reading = tracker.refresh(&database, 102, 1_120).await.unwrap().cloned().unwrap();
+    assert!((reading.price_usd - 2_500.0).abs() < 0.5, "{}", reading.price_usd);
+
+    // Once the deep pool drains, the shallower one takes over
+    deep.liquidity = Some(0);
+    database.upsert_pool(&deep).await.unwrap();
+    let reading = tracker.refresh(&database, 103, 1_180).await.unwrap().cloned().unwrap();
+    assert_eq!(reading.pool_address, shallow.pool_address);
+
+    let series: Vec<(i64, String)> = database
+        .get_native_prices(chain_id, 0, 2_000)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|reading| (reading.block_number, reading.pool_address))
+        .collect();
+    assert_eq!(
+        series,
+        vec![(101, deep.pool_address.clone()), (102, deep.pool_address.clone()), (103, shallow.pool_address.clone())]
+    );
+}
+
+#[tokio::test]
+async fn test_rebuild_candles_replaces_corrupted_buckets() {
+    let database = test_database().await;
+    let chain_id = 37_000_000 + (unique_id() % 1_000_000) as i64;
+    let (weth, usdc, token) = (format!("0x{:040x}", unique_id() + 1), format!("0x{:040x}", unique_id() + 2), format!("0x{:040x}", unique_id() + 3));
+    let anchors = PriceAnchors { wrapped_native: Some(weth.clone()), stablecoins: vec![usdc.clone()] };
+
+    // The native token's pool, and a token traded against the native token
+    let new_pool = |token0: &str, decimals0: i32, token1: &str, decimals1: i32| {
+        let mut pool_data = pool(&format!("0x{:040x}", unique_id()), 1);
+        pool_data.chain_id = chain_id;
+        pool_data.token0_address = token0.to_string();
+        pool_data.token1_address = token1.to_string();
+        pool_data.token0_decimals = Some(decimals0);
+        pool_data.token1_decimals = Some(decimals1);
+        pool_data
+    };
+    let native_pool = new_pool(&weth, 18, &usdc, 6);
+    let token_pool = new_pool(&token, 18, &weth, 18);
+    database.upsert_pool(&native_pool).await.unwrap();
+    database.upsert_pool(&token_pool).await.unwrap();
+
+    // Swaps over two days as (seconds into the first day, token0 in, amount in, amount out);
+    // most hours have none
+    let day = 1_699_920_000;
+    let native_swaps = [(600, true, 1_000_000_000_000_000_000, 2_000_000_000), (4_000, false, 4_100_000_000, 2_000_000_000_000_000_000), (90_000, true, 500_000_000_000_000_000, 990_000_000)];
+    let token_swaps = [(1_200, true, 1_000_000_000_000_000_000, 2_000_000_000_000_000), (7_300, false, 1_000_000_000_000_000, 400_000_000_000_000_000), (88_000, true, 3_000_000_000_000_000_000, 7_000_000_000_000_000)];
+    for (pool_data, swaps) in [(&native_pool, native_swaps), (&token_pool, token_swaps)] {
+        for (offset, token0_in, amount_in, amount_out) in swaps {
+            let mut swap_event = swap(&format!("0x{:064x}", unique_id()), 0, offset);
+            swap_event.pool_address = pool_data.pool_address.clone();
+            swap_event.chain_id = chain_id;
+            swap_event.timestamp = day + offset;
+            (swap_event.token_in, swap_event.token_out) = match token0_in {
+                true => (pool_data.token0_address.clone(), pool_data.token1_address.clone()),
+                false => (pool_data.token1_address.clone(), pool_data.token0_address.clone()),
+            };
+            (swap_event.amount_in, swap_event.amount_out) = (amount_in, amount_out);
+            database.insert_swap(&swap_event).await.unwrap();
+        }
+    }
+
+    let rebuild = |pool_address: &str, from_ts: Option<i64>, to_ts: Option<i64>| {
+        let options = RebuildOptions { chain_id, pool_address: pool_address.to_string(), interval_secs: 3600, from_ts, to_ts };
+        let (database, anchors) = (database.clone(), anchors.clone());
+        async move { candles::rebuild(&database, &anchors, &options).await.unwrap() }
+    };
+    // Only the width buckets are stored in can be rebuilt
+    let options = RebuildOptions { chain_id, pool_address: native_pool.pool_address.clone(), interval_secs: 300, from_ts: None, to_ts: None };
+    assert!(candles::rebuild(&database, &anchors, &options).await.is_err());
+
+    // The native pool first, the token is priced against its readings
+    let report = rebuild(&native_pool.pool_address, None, None).await;
+    assert_eq!((report.from_ts, report.to_ts, report.days), (day, day + 90_001, 2));
+    assert_eq!((report.token_prices, report.native_prices), (26, 3));
+    let readings: Vec<(i64, i64)> = database
+        .get_native_prices(chain_id, day, day + 2 * 86_400)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|reading| (reading.block_number, reading.timestamp - day))
+        .collect();
+    assert_eq!(readings, vec![(600, 600), (4_000, 4_000), (90_000, 90_000)]);
+    rebuild(&token_pool.pool_address, None, None).await;
+
+    // From scratch: every hour at the last swap before it ends, in native tokens, at the
+    // native price read before then
+    let token0_price = |swaps: &[(i64, bool, i64, i64)], end: i64, decimals0: i32, decimals1: i32| {
+        let (_, token0_in, amount_in, amount_out) = *swaps.iter().rfind(|(offset, ..)| *offset < end).unwrap();
+        let (amount0, amount1) = if token0_in { (amount_in, amount_out) } else { (amount_out, amount_in) };
+        (amount1 as f64 / 10f64.powi(decimals1)) / (amount0 as f64 / 10f64.powi(decimals0))
+    };
+    let expected: Vec<(i64, f64)> = (0..25)
+        .map(|hour| {
+            let end = (hour + 1) * 3600;
+            let native_usd = token0_price(&native_swaps, end, 18, 6);
+            (day + hour * 3600, token0_price(&token_swaps, end, 18, 18) * native_usd)
+        })
+        .collect();
+    let stored = || async {
+        let buckets = database.get_token_usd_prices(&token, chain_id, day, day + 2 * 86_400).await.unwrap();
+        buckets.into_iter().map(|bucket| (bucket.bucket_start, bucket.price_usd)).collect::<Vec<_>>()
+    };
+    assert_eq!(stored().await, expected);
+
+    // Corrupt a bucket of each day, then rebuild them one day at a time
+    database.upsert_token_usd_price(&token, chain_id, day + 3 * 3600, 1e9, None).await.unwrap();
+    database.upsert_token_usd_price(&token, chain_id, day + 24 * 3600, 0.001, None).await.unwrap();
+    let report = rebuild(&token_pool.pool_address, Some(day), Some(day + 86_400)).await;
+    assert_eq!((report.days, report.token_prices, report.native_prices), (1, 24, 0));
+    assert_ne!(stored().await, expected);
+    rebuild(&token_pool.pool_address, Some(day + 86_400), None).await;
+    assert_eq!(stored().await, expected);
+
+    // Live writes of prices read from the pool wait while a rebuild holds its lock
+    let holder = sqlx::PgPool::connect(&env::var("DATABASE_URL").unwrap()).await.unwrap();
+    let mut connection = holder.acquire().await.unwrap();
+    let key = coordination::price_lock_key(chain_id, &token_pool.pool_address);
+    sqlx::query("SELECT pg_advisory_lock($1)").bind(key).execute(&mut *connection).await.unwrap();
+    database.upsert_token_usd_price(&token, chain_id, day, 1.0, None).await.unwrap();
+    let live = {
+        let (database, token, pool_address) = (database.clone(), token.clone(), token_pool.pool_address.clone());
+        tokio::spawn(async move { database.upsert_token_usd_price(&token, chain_id, day, 2.0, Some(&pool_address)).await })
+    };
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(!live.is_finished());
+    sqlx::query("SELECT pg_advisory_unlock($1)").bind(key).execute(&mut *connection).await.unwrap();
+    live.await.unwrap().unwrap();
+    assert_eq!(stored().await[0], (day, 2.0));
+}
+
+#[tokio::test]
+async fn test_enrich_usd_uses_historical_price_buckets() {
+    let database = test_database().await;
+    let chain_id = 19_000_000 + (unique_id() % 1_000_000) as i64;
+    let pool_address = format!("0x{:040x}", unique_id());
+    let mut pool_data = pool(&pool_address, 1);
+    pool_data.chain_id = chain_id;
+    pool_data.token0_address = format!("0x{:040x}", unique_id() + 1);
+    pool_data.token1_address = format!("0x{:040x}", unique_id() + 2);
+    pool_data.token0_decimals = Some(18);
+    pool_data.token1_decimals = Some(6);
+    database.upsert_pool(&pool_data).await.unwrap();
+
+    // Hourly token0 prices; token1 has none
+    let hour = 1_699_999_200;
+    for (bucket, price) in [(0, 2_000.0), (1, 2_100.0), (2, 2_200.0)] {
+        database.upsert_token_usd_price(&pool_data.token0_address, chain_id, hour + bucket * 3600, price, None).await.unwrap();
+    }
+
+    let insert = |name: &str, token_in: &str, amount_in: i64, amount_out: i64, timestamp: i64| {
+        let tx_hash = format!("0x{:064x}", unique_id());
+        let mut swap_event = swap(&tx_hash, 0, 1147);
+        swap_event.pool_address = pool_address.clone();
+        swap_event.chain_id = chain_id;
+        swap_event.token_in = token_in.to_string();
+        swap_event.token_out = if token_in == "token0" { "token1" } else { "token0" }.to_string();
+        swap_event.amount_in = amount_in;
+        swap_event.amount_out = amount_out;
+        swap_event.timestamp = timestamp;
+        let (database, name) = (database.clone(), name.to_string());
+        async move {
+            database.insert_swap(&swap_event).await.unwrap();
+            (name, tx_hash)
+        }
+    };
+    let one = 1_000_000_000_000_000_000;
+    let swaps = [
+        // Half past the second hour: priced from the bucket holding it
+        insert("mid_bucket", "token0", one, 2_000_000, hour + 5400).await,
+        // Before the first bucket, within the allowed age: priced from the first
+        insert("early", "token0", one, 2_000_000, hour - 600).await,
+        insert("bucket_start", "token0", one, 2_000_000, hour + 7200).await,
+        // Only the output side has a price
+        insert("token1_in", "token1", 1_000_000, one / 2, hour + 100).await,
+        // A day after the last bucket
+        insert("stale", "token0", one, 2_000_000, hour + 26 * 3600).await,
+    ];
+
+    let policy = EnrichPolicy {
+        chain_id,
+        batch_size: 2,
+        max_price_age_secs: 3600,
+        max_price_multiple: 10.0,
+        max_tvl_multiple: 100.0,
+        median_window_secs: 86400,
+    };
+    let cursor = enrich::cursor_name(0, hour + 86400 * 2);
+    let counts = enrich::enrich_usd(&database, &policy, &cursor, 0, hour + 86400 * 2).await.unwrap();
+    // The early swap predates every bucket, so there is no median to check it against
+    assert_eq!(counts, EnrichCounts { priced: 4, unpriced: 1, flagged: 0, unchecked: 1 });
+
+    let mut amounts = Vec::new();
+    for (name, tx_hash) in &swaps {
+        let stored = database.get_swap_by_tx_hash(tx_hash, chain_id).await.unwrap().remove(0);
+        amounts.push((name.as_str(), stored.amount_in_usd, stored.amount_out_usd));
+    }
+    assert_eq!(
+        amounts,
+        vec![
+            ("mid_bucket", Some(2_100.0), Some(2_100.0)),
+            ("early", Some(2_000.0), Some(2_000.0)),
+            ("bucket_start", Some(2_200.0), Some(2_200.0)),
+            ("token1_in", Some(1_000.0), Some(1_000.0)),
+            ("stale", None, None),
+        ]
+    );
+
+    // Priced and unpriceable swaps are both left alone by the next run
+    assert!(database.get_sync_cursor(&cursor, chain_id).await.unwrap().is_some());
+    let counts = enrich::enrich_usd(&database, &policy, &cursor, 0, hour + 86400 * 2).await.unwrap();
+    assert_eq!(counts, EnrichCounts::default());
+    let counts = enrich::enrich_usd(&database, &policy, "fresh_cursor", 0, hour + 86400 * 2).await.unwrap();
+    assert_eq!(counts, EnrichCounts::default());
+}
+
+#[tokio::test]
+async fn test_enrich_usd_flags_outliers() {
+    let database = test_database().await;
+    let chain_id = 36_000_000 + (unique_id() % 1_000_000) as i64;
+    let pool_address = format!("0x{:040x}", unique_id());
+    let mut pool_data = pool(&pool_address, 1);
+    pool_data.chain_id = chain_id;
+    pool_data.token0_address = format!("0x{:040x}", unique_id() + 1);
+    pool_data.token1_address = format!("0x{:040x}", unique_id() + 2);
+    pool_data.token0_decimals = Some(18);
+    pool_data.token1_decimals = Some(6);
+    // At a price of 1, each side holds 1e15 units in range
+    pool_data.liquidity = Some(1_000_000_000_000_000);
+    pool_data.sqrt_price_x96 = Some("79228162514264337593543950336".to_string());
+    database.upsert_pool(&pool_data).await.unwrap();
+
+    let hour = 1_699_999_200;
+    for bucket in 0..3 {
+        database.upsert_token_usd_price(&pool_data.token0_address, chain_id, hour + bucket * 3600, 2_000.0, None).await.unwrap();
+        database.upsert_token_usd_price(&pool_data.token1_address, chain_id, hour + bucket * 3600, 1.0, None).await.unwrap();
+    }
+
+    let insert = |amount_in: i64, amount_out: i64| {
+        let tx_hash = format!("0x{:064x}", unique_id());
+        let mut swap_event = swap(&tx_hash, 0, 1169);
+        swap_event.pool_address = pool_address.clone();
+        swap_event.chain_id = chain_id;
+        swap_event.amount_in = amount_in;
+        swap_event.amount_out = amount_out;
+        swap_event.timestamp = hour + 7300;
+        let database = database.clone();
+        async move {
+            database.insert_swap(&swap_event).await.unwrap();
+            tx_hash
+        }
+    };
+    let one = 1_000_000_000_000_000_000;
+    let fair = insert(one, 2_000_000_000).await;
+    // One token0 for two million dollars: a price 1,000 times the median
+    let inflated = insert(one, 2_000_000_000_000).await;
+    // Five times what the pool holds in range, with a multiple of 1,000 allowed
+    let oversized = insert(5 * one, 10_000_000_000).await;
+
+    let policy = EnrichPolicy {
+        chain_id,
+        batch_size: 10,
+        max_price_age_secs: 3600,
+        max_price_multiple: 10.0,
+        max_tvl_multiple: 1000.0,
+        median_window_secs: 86400,
+    };
+    let counts = enrich::enrich_usd(&database, &policy, "outliers", 0, hour + 86400).await.unwrap();
+    assert_eq!(counts, EnrichCounts { priced: 1, unpriced: 0, flagged: 2, unchecked: 0 });
+
+    let stored = database.get_swap_by_tx_hash(&fair, chain_id).await.unwrap().remove(0);
+    assert_eq!(stored.amount_in_usd, Some(2_000.0));
+    for tx_hash in [&inflated, &oversized] {
+        let stored = database.get_swap_by_tx_hash(tx_hash, chain_id).await.unwrap().remove(0);
+        assert_eq!((stored.amount_in_usd, stored.amount_out_usd), (None, None));
+    }
+
+    // Flagged swaps are not tried again and stay out of the volume
+    let counts = enrich::enrich_usd(&database, &policy, "outliers_again", 0, hour + 86400).await.unwrap();
+    assert_eq!(counts, EnrichCounts::default());
+    assert_eq!(database.get_protocol_stats(chain_id).await.unwrap().total_volume_usd, Some(2_000.0));
+}
+
+#[test]
+fn test_types_survive_database_round_trip() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let database = runtime.block_on(test_database());
+    let mut runner = TestRunner::new(ProptestConfig::with_cases(64));
+
+    runner
+        .run(&strategies::swap_event(), |mut swap| {
+            runtime.block_on(async {
+                database.insert_swap(&swap).await.unwrap();
+                let stored = database.get_swap_by_tx_hash(&swap.tx_hash, swap.chain_id).await.unwrap();
+                // Unset indexing times are filled in on insert
+                prop_assert!(stored.len() == 1 && stored[0].indexed_at.is_some());
+                swap.indexed_at = swap.indexed_at.or(stored[0].indexed_at);
+                prop_assert_eq!(&stored[0], &swap);
+                Ok::<_, TestCaseError>(())
+            })
+        })
+        .unwrap();
+
+    runner
+        .run(&strategies::pool_data(), |mut pool| {
+            runtime.block_on(async {
+                // Written by its own setter, not by the upsert
+                pool.has_nonstandard_token = false;
+                database.upsert_pool(&pool).await.unwrap();
+                let stored = database.get_pool(&pool.pool_address).await.unwrap();
+                // Row times are set by the database
+                prop_assert!(stored.as_ref().is_some_and(|stored| stored.created_at.is_some() && stored.updated_at.is_some()));
+                pool.created_at = stored.as_ref().and_then(|stored| stored.created_at);
+                pool.updated_at = stored.as_ref().and_then(|stored| stored.updated_at);
+                // A pool stored without an update block was last changed when it was created
+                pool.updated_at_block = pool.updated_at_block.or(pool.created_at_block);
+                prop_assert_eq!(stored, Some(pool));
+                Ok(())
+            })
+        })
+        .unwrap();
+
+    runner
+        .run(&strategies::token_data(), |mut token| {
+            runtime.block_on(async {
+                // Likewise written by the supply refresh, behaviour checks and curve indexing
+                token.supply_updated_at = None;
+                (token.is_fee_on_transfer, token.is_rebasing) = (false, false);
+                token.creator_address = None;
+                database.upsert_token(&token).await.unwrap();
+                prop_assert_eq!(database.get_token(&token.address, token.chain_id).await.unwrap(), Some(token));
+                Ok(())
+            })
+        })
+        .unwrap();
+}
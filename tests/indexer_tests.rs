@@ -0,0 +1,511 @@
+use ethers::abi::Token;
+use ethers::types::Address;
+use ethers::utils::keccak256;
+use futures::StreamExt;
+use moonshot_indexer::{
+    config::Config,
+    db::Database,
+    doctor,
+    error::IndexerError,
+    indexer::{replay_block, Indexer},
+    lifecycle::PoolStatus,
+    metrics,
+    moonshot::{LogDecoder, MoonshotHandler},
+    pool_balances::{self, BalanceDropPolicy},
+    testing::{pool_created_log, swap_log, MockChain},
+};
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+// Concurrent CREATE TABLE IF NOT EXISTS can race in Postgres, so indexers start one at a time
+static SCHEMA_LOCK: Mutex<()> = Mutex::const_new(());
+
+const FACTORY: &str = "0x0000000000000000000000000000000000000fac";
+
+fn unique_id() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos()
+}
+
+fn address(seed: &str) -> Address {
+    Address::from_slice(&keccak256(format!("{} {}", seed, unique_id()))[12..])
+}
+
+fn hex(address: Address) -> String {
+    format!("{:?}", address)
+}
+
+async fn indexer(chain: &MockChain, chain_id: u64) -> Indexer<MockChain> {
+    indexer_with(chain, chain_id, &[]).await
+}
+
+/// An indexer with `settings` on top of the test defaults.
+async fn indexer_with(chain: &MockChain, chain_id: u64, settings: &[(&str, String)]) -> Indexer<MockChain> {
+    let config = config_with(chain_id, settings);
+    let _guard = SCHEMA_LOCK.lock().await;
+    Indexer::with_provider(config, chain.provider()).await.expect("Should start over the mock chain")
+}
+
+fn config_with(chain_id: u64, settings: &[(&str, String)]) -> Config {
+    dotenv::dotenv().ok();
+
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    Config::from_lookup(|name| {
+        if let Some((_, value)) = settings.iter().find(|(setting, _)| *setting == name) {
+            return Some(value.clone());
+        }
+        match name {
+            "RPC_URL" => Some("wss://rpc.example.com".to_string()),
+            "DATABASE_URL" => Some(database_url.clone()),
+            "MOONSHOT_FACTORY_ADDRESS" => Some(FACTORY.to_string()),
+            "CHAIN_ID" => Some(chain_id.to_string()),
+            "SUPPLY_REFRESH_INTERVAL_SECS" | "HOLDER_SNAPSHOT_INTERVAL_SECS" => Some("0".to_string()),
+            _ => None,
+        }
+    })
+    .unwrap()
+}
+
+async fn database() -> Database {
+    dotenv::dotenv().ok();
+    Database::new(&env::var("DATABASE_URL").expect("DATABASE_URL must be set")).await.unwrap()
+}
+
+/// Scripts a pool created at `block` between two tokens with symbols and decimals.
+fn create_pool(chain: &MockChain, block: u64) -> (Address, Address, Address) {
+    let (token0, token1, pool) = (address("token0"), address("token1"), address("pool"));
+    chain.add_log(block, pool_created_log(FACTORY.parse().unwrap(), token0, token1, 3000, 60, pool));
+    chain.on_call(token0, "symbol()", vec![Token::String("MOON".to_string())]);
+    chain.on_call(token0, "decimals()", vec![Token::Uint(18.into())]);
+    chain.on_call(token1, "symbol()", vec![Token::String("WETH".to_string())]);
+    chain.on_call(token1, "decimals()", vec![Token::Uint(18.into())]);
+    (token0, token1, pool)
+}
+
+#[tokio::test]
+async fn test_indexes_pool_creation() {
+    let chain = MockChain::new(100);
+    let (token0, token1, pool) = create_pool(&chain, 50);
+    let chain_id = 13_000_000 + (unique_id() % 1_000_000) as u64;
+
+    let mut indexer = indexer(&chain, chain_id).await;
+    indexer.process_blocks().await.unwrap();
+    // The cursor follows the writer
+    indexer.drain_swap_writer().await;
+    assert_eq!(indexer.last_processed_block(), 100);
+
+    let stored = database().await.get_pool(&hex(pool)).await.unwrap().expect("Pool should be indexed");
+    assert_eq!((stored.token0_address, stored.token1_address), (hex(token0), hex(token1)));
+    assert_eq!((stored.token0_symbol.as_deref(), stored.token1_symbol.as_deref()), (Some("MOON"), Some("WETH")));
+    assert_eq!((stored.fee_tier, stored.created_at_block), (Some(3000), Some(50)));
+    assert_eq!(stored.chain_id, chain_id as i64);
+}
+
+#[tokio::test]
+async fn test_indexes_swaps_with_block_timestamps() {
+    let chain = MockChain::new(100);
+    let (_, _, pool) = create_pool(&chain, 40);
+    let trader = address("trader");
+    chain.add_log(60, swap_log(pool, trader, 1_000, -950, 12));
+    chain.add_log(60, swap_log(pool, trader, -500, 520, 10));
+    let chain_id = 14_000_000 + (unique_id() % 1_000_000) as u64;
+
+    let mut indexer = indexer(&chain, chain_id).await;
+    indexer.process_blocks().await.unwrap();
+    indexer.drain_swap_writer().await;
+
+    let swaps = database().await.get_swaps_by_pool(&hex(pool), chain_id as i64, 10).await.unwrap();
+    assert_eq!(swaps.len(), 2);
+    assert_eq!(swaps.iter().map(|swap| swap.log_index).collect::<Vec<_>>(), vec![0, 1]);
+    assert!(swaps.iter().all(|swap| swap.block_number == 60));
+    assert!(swaps.iter().all(|swap| swap.timestamp == MockChain::block_timestamp(60) as i64));
+    assert_eq!((swaps[0].amount_in, swaps[0].amount_out), (1_000, 950));
+
+    // The header fetched for the timestamp also recorded the block's gas market
+    let timestamp = MockChain::block_timestamp(60) as i64;
+    let gas = database().await.get_gas_stats(chain_id as i64, timestamp, timestamp + 1).await.unwrap();
+    assert_eq!(gas.len(), 1);
+    assert_eq!((gas[0].block_number, gas[0].timestamp), (60, timestamp));
+    assert_eq!(gas[0].base_fee_per_gas, Some(MockChain::block_base_fee(60) as i64));
+    assert_eq!((gas[0].gas_used_ratio, gas[0].median_priority_fee), (0.5, None));
+
+    // The last 50 processed blocks end at the head, with both swaps in block 60
+    let blocks = indexer.get_last_n_blocks_stats(50).await.unwrap();
+    assert_eq!((blocks.first().unwrap().block_number, blocks.last().unwrap().block_number), (51, 100));
+    let active: Vec<_> = blocks.iter().filter(|block| block.swap_count > 0).collect();
+    assert_eq!(active.len(), 1);
+    assert_eq!((active[0].block_number, active[0].swap_count, active[0].pool_count), (60, 2, 1));
+}
+
+#[tokio::test]
+async fn test_event_streams_follow_the_indexer() {
+    let chain = MockChain::new(100);
+    let (_, _, pool) = create_pool(&chain, 50);
+    let trader = address("trader");
+    chain.add_log(60, swap_log(pool, trader, 1_000, -950, 12));
+    let chain_id = 32_000_000 + (unique_id() % 1_000_000) as u64;
+
+    let mut indexer = indexer(&chain, chain_id).await;
+    let (swaps, pools) = (indexer.swap_stream(), indexer.pool_stream());
+    let consumer = tokio::spawn(async move {
+        let pools: Vec<_> = pools.collect().await;
+        let swaps: Vec<_> = swaps.collect().await;
+        (pools, swaps)
+    });
+
+    indexer.process_blocks().await.unwrap();
+    // A backfill over the same block streams only the swap it had not stored yet
+    chain.add_log(60, swap_log(pool, trader, -500, 520, 10));
+    indexer.backfill(60, 60).await.unwrap();
+
+    // Both streams end once the indexer is gone
+    drop(indexer);
+    let (pools, swaps) = tokio::time::timeout(std::time::Duration::from_secs(10), consumer).await.unwrap().unwrap();
+    assert_eq!(pools.iter().map(|pool| pool.pool_address.clone()).collect::<Vec<_>>(), vec![hex(pool)]);
+    assert_eq!(swaps.iter().map(|swap| swap.log_index).collect::<Vec<_>>(), vec![0, 1]);
+    assert!(swaps.iter().all(|swap| swap.block_number == 60 && swap.indexed_at.is_some()));
+}
+
+#[tokio::test]
+async fn test_swap_topic_discovery_registers_factory_pools() {
+    // Two contracts swapping the same tokens, neither created in range; the factory only
+    // deployed the first
+    let chain = MockChain::new(100);
+    let (token0, token1) = (address("token0"), address("token1"));
+    let (pool, lookalike) = (address("pool"), address("lookalike"));
+    for contract in [pool, lookalike] {
+        chain.on_call(contract, "token0()", vec![Token::Address(token0)]);
+        chain.on_call(contract, "token1()", vec![Token::Address(token1)]);
+        chain.on_call(contract, "fee()", vec![Token::Uint(3000.into())]);
+    }
+    chain.on_call(FACTORY.parse().unwrap(), "getPool(address,address,uint24)", vec![Token::Address(pool)]);
+    chain.on_call(token0, "symbol()", vec![Token::String("MOON".to_string())]);
+    chain.on_call(token0, "decimals()", vec![Token::Uint(18.into())]);
+    chain.on_call(token1, "symbol()", vec![Token::String("WETH".to_string())]);
+    chain.on_call(token1, "decimals()", vec![Token::Uint(18.into())]);
+    let trader = address("trader");
+    chain.add_log(60, swap_log(pool, trader, 1_000, -950, 12));
+    chain.add_log(60, swap_log(lookalike, trader, 1_000, -950, 12));
+    let chain_id = 33_000_000 + (unique_id() % 1_000_000) as u64;
+
+    // Without the setting, pools are only found through their creation
+    let mut indexer = indexer(&chain, chain_id).await;
+    indexer.process_blocks().await.unwrap();
+    assert!(database().await.get_pool(&hex(pool)).await.unwrap().is_none());
+
+    let settings = [("SWAP_TOPIC_DISCOVERY", "true".to_string()), ("SWAP_TOPIC_MAX_BLOCKS", "10".to_string())];
+    let mut indexer = indexer_with(&chain, chain_id, &settings).await;
+    let requests_before = chain.requests().len();
+    indexer.process_blocks().await.unwrap();
+    indexer.drain_swap_writer().await;
+
+    let stored = database().await.get_pool(&hex(pool)).await.unwrap().expect("Pool should be discovered");
+    assert_eq!((stored.token0_address, stored.token1_address), (hex(token0), hex(token1)));
+    assert_eq!((stored.fee_tier, stored.token0_symbol.as_deref(), stored.created_at_block), (Some(3000), Some("MOON"), None));
+    assert!(database().await.get_pool(&hex(lookalike)).await.unwrap().is_none());
+
+    // The swap that led to the pool is indexed in the same poll
+    let swaps = database().await.get_swaps_by_pool(&hex(pool), chain_id as i64, 10).await.unwrap();
+    assert_eq!(swaps.len(), 1);
+    assert_eq!(swaps[0].block_number, 60);
+
+    // Blocks 1 to 100 were scanned ten blocks at a time
+    let requests = chain.requests();
+    let log_requests = requests[requests_before..].iter().filter(|method| *method == "eth_getLogs").count();
+    assert!(log_requests >= 10, "{} eth_getLogs requests", log_requests);
+}
+
+#[tokio::test]
+async fn test_stats_are_kept_per_chain() {
+    let first_chain_id = 29_000_000 + (unique_id() % 1_000_000) as u64;
+    let second_chain_id = first_chain_id + 1_000_000;
+
+    // Two pools and three swaps on one chain, one of each on the other
+    let first = MockChain::new(100);
+    let trader = address("trader");
+    let (_, _, pool) = create_pool(&first, 40);
+    create_pool(&first, 41);
+    for block in [60, 61, 62] {
+        first.add_log(block, swap_log(pool, trader, 1_000, -950, 12));
+    }
+    let second = MockChain::new(100);
+    let (_, _, pool) = create_pool(&second, 40);
+    second.add_log(60, swap_log(pool, trader, 1_000, -950, 12));
+
+    for (chain, chain_id) in [(&first, first_chain_id), (&second, second_chain_id)] {
+        let mut indexer = indexer(chain, chain_id).await;
+        indexer.process_blocks().await.unwrap();
+        indexer.drain_swap_writer().await;
+    }
+
+    // The writer commits a range's stats after storing its swaps
+    let database = database().await;
+    let mut totals = Vec::new();
+    for _ in 0..100 {
+        totals = database
+            .get_all_stats()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|stats| [first_chain_id, second_chain_id].contains(&(stats.chain_id as u64)))
+            .map(|stats| (stats.chain_id as u64, stats.total_pools_indexed, stats.total_swaps_indexed, stats.blocks_behind()))
+            .collect();
+        if totals.iter().map(|total| total.2).sum::<i64>() == 4 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(totals, vec![(first_chain_id, 2, 3, Some(0)), (second_chain_id, 1, 1, Some(0))]);
+}
+
+#[tokio::test]
+async fn test_skips_excluded_pools() {
+    let chain = MockChain::new(100);
+    let (_, _, excluded) = create_pool(&chain, 40);
+    let (_, _, indexed) = create_pool(&chain, 41);
+    let trader = address("trader");
+    chain.add_log(60, swap_log(excluded, trader, 1_000, -950, 12));
+    chain.add_log(60, swap_log(indexed, trader, 1_000, -950, 12));
+    let chain_id = 14_000_000 + (unique_id() % 1_000_000) as u64;
+
+    // Listed in upper case to check the match ignores it
+    let excluded_pools = format!("0x{}", hex(excluded)[2..].to_uppercase());
+    let mut indexer = indexer_with(&chain, chain_id, &[("EXCLUDED_POOLS", excluded_pools)]).await;
+    indexer.process_blocks().await.unwrap();
+    indexer.drain_swap_writer().await;
+
+    let database = database().await;
+    assert!(database.get_pool(&hex(excluded)).await.unwrap().is_none());
+    assert!(database.get_swaps_by_pool(&hex(excluded), chain_id as i64, 10).await.unwrap().is_empty());
+    assert!(database.get_pool(&hex(indexed)).await.unwrap().is_some());
+    assert_eq!(database.get_swaps_by_pool(&hex(indexed), chain_id as i64, 10).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_lifecycle_follows_indexed_chain_time_and_waits_out_backlogs() {
+    let chain = MockChain::new(100);
+    let (_, _, pool) = create_pool(&chain, 10);
+    chain.add_log(20, swap_log(pool, address("trader"), 1_000, -950, 12));
+    let chain_id = 15_000_000 + (unique_id() % 1_000_000) as u64;
+
+    let mut indexer = indexer(&chain, chain_id).await;
+    indexer.process_blocks().await.unwrap();
+    indexer.drain_swap_writer().await;
+    let status = || async { database().await.get_pool_status(&hex(pool)).await.unwrap().unwrap() };
+    assert_eq!(status().await, PoolStatus::Active);
+
+    // Eleven days of blocks after the swap but far behind head: catching up, nothing demoted
+    chain.set_head(500_000);
+    let mut behind = indexer_with(&chain, chain_id, &[]).await;
+    chain.set_head(1_000_000);
+    behind.process_blocks().await.unwrap();
+    assert!(behind.last_processed_block() < 510_000);
+    assert_eq!(status().await, PoolStatus::Active);
+
+    // Caught up an hour of chain time after the swap: the wall clock is years later
+    chain.set_head(1_900);
+    indexer_with(&chain, chain_id, &[]).await.process_blocks().await.unwrap();
+    assert_eq!(status().await, PoolStatus::Active);
+
+    // Caught up ten days of chain time after the swap
+    chain.set_head(432_120);
+    indexer_with(&chain, chain_id, &[]).await.process_blocks().await.unwrap();
+    assert_eq!(status().await, PoolStatus::Archived);
+}
+
+#[tokio::test]
+async fn test_retries_range_after_rpc_error() {
+    let chain = MockChain::new(100);
+    let (_, _, pool) = create_pool(&chain, 70);
+    let chain_id = 15_000_000 + (unique_id() % 1_000_000) as u64;
+
+    let mut indexer = indexer(&chain, chain_id).await;
+    chain.fail_next("eth_getLogs", 1);
+    assert!(indexer.process_blocks().await.is_err());
+    // The failed range is not skipped
+    assert_eq!(indexer.last_processed_block(), 0);
+    assert!(database().await.get_pool(&hex(pool)).await.unwrap().is_none());
+
+    indexer.process_blocks().await.unwrap();
+    // The cursor follows the writer
+    indexer.drain_swap_writer().await;
+    assert_eq!(indexer.last_processed_block(), 100);
+    assert!(database().await.get_pool(&hex(pool)).await.unwrap().is_some());
+    assert!(chain.requests().iter().filter(|method| *method == "eth_getLogs").count() >= 2);
+}
+
+#[tokio::test]
+async fn test_batch_token_metadata_with_and_without_multicall() {
+    let chain = MockChain::new(100);
+    let (moon, usdc, no_symbol) = (address("moon"), address("usdc"), address("no_symbol"));
+    chain.on_call(moon, "symbol()", vec![Token::String("MOON".to_string())]);
+    chain.on_call(moon, "decimals()", vec![Token::Uint(18.into())]);
+    chain.on_call(usdc, "symbol()", vec![Token::String("USDC".to_string())]);
+    chain.on_call(usdc, "decimals()", vec![Token::Uint(6.into())]);
+    chain.on_call(no_symbol, "decimals()", vec![Token::Uint(9.into())]);
+    let expected = vec![(Some("MOON".to_string()), 18), (Some("USDC".to_string()), 6), (None, 18)];
+    let eth_calls = |chain: &MockChain| chain.requests().iter().filter(|method| *method == "eth_call").count();
+
+    // Without Multicall3 the failed multicall falls back to one call per read
+    let handler = MoonshotHandler::new(chain.provider());
+    assert_eq!(handler.batch_get_token_metadata(&[moon, usdc, no_symbol]).await.unwrap(), expected);
+    assert_eq!(eth_calls(&chain), 1 + 5);
+
+    chain.deploy_multicall();
+    let before = eth_calls(&chain);
+    assert_eq!(handler.batch_get_token_metadata(&[moon, usdc, no_symbol]).await.unwrap(), expected);
+    assert_eq!(eth_calls(&chain) - before, 1);
+}
+
+#[tokio::test]
+async fn test_token_balance_and_allowance() {
+    let chain = MockChain::new(100);
+    let (token, wallet, router) = (address("moon"), address("wallet"), address("router"));
+    chain.on_call(token, "balanceOf(address)", vec![Token::Uint(1_500.into())]);
+    chain.on_call(token, "allowance(address,address)", vec![Token::Uint(250.into())]);
+
+    let handler = MoonshotHandler::new(chain.provider());
+    assert_eq!(handler.get_token_balance(token, wallet).await.unwrap(), 1_500.into());
+    assert_eq!(handler.get_token_allowance(token, wallet, router).await.unwrap(), 250.into());
+
+    // A token without `allowance` fails rather than reading as zero
+    assert!(handler.get_token_allowance(address("usdc"), wallet, router).await.is_err());
+}
+
+#[tokio::test]
+async fn test_pool_balance_drops_alert_once() {
+    let chain = MockChain::new(45);
+    chain.deploy_multicall();
+    let (token0, token1, pool) = create_pool(&chain, 40);
+    chain.on_call(pool, "liquidity()", vec![Token::Uint(5_000.into())]);
+    chain.on_call(token1, "balanceOf(address)", vec![Token::Uint(2_000_000.into())]);
+    let trader = address("trader");
+    let chain_id = 39_000_000 + (unique_id() % 1_000_000) as u64;
+
+    let mut indexer = indexer_with(&chain, chain_id, &[("SAMPLE_POOL_BALANCES", "true".to_string())]).await;
+    let alerts = || metrics::POOL_BALANCE_DROPS.with_label_values(&["token0"]).get();
+    let before = alerts();
+
+    // token0 is pulled out of the pool at block 60, which has two swaps, and stays low
+    for (block, token0_balance) in [(50, 1_000_000u64), (55, 950_000), (60, 100_000), (65, 90_000)] {
+        chain.on_call(token0, "balanceOf(address)", vec![Token::Uint(token0_balance.into())]);
+        chain.add_log(block, swap_log(pool, trader, 1_000, -950, 12));
+        if block == 60 {
+            chain.add_log(block, swap_log(pool, trader, -500, 520, 10));
+        }
+        chain.set_head(block);
+        indexer.process_blocks().await.unwrap();
+    }
+    assert_eq!(alerts() - before, 1);
+
+    let snapshots = database().await.get_pool_balance_snapshots(&hex(pool), chain_id as i64, 0).await.unwrap();
+    assert_eq!(snapshots.iter().map(|snapshot| snapshot.block_number).collect::<Vec<_>>(), vec![50, 55, 60, 65]);
+    assert_eq!(snapshots[2].token0_balance.as_deref(), Some("100000"));
+    assert_eq!(snapshots[2].token1_balance.as_deref(), Some("2000000"));
+
+    let policy = BalanceDropPolicy::from_config(&config_with(chain_id, &[]));
+    let drops = pool_balances::detect_balance_drops(&snapshots, &policy);
+    assert_eq!(drops.iter().map(|drop| (drop.block_number, drop.token)).collect::<Vec<_>>(), vec![(60, 0)]);
+}
+
+#[tokio::test]
+async fn test_watched_pools_are_indexed_outside_the_scope() {
+    let chain = MockChain::new(100);
+    let (_, _, known) = create_pool(&chain, 40);
+    let chain_id = 16_000_000 + (unique_id() % 1_000_000) as u64;
+
+    // Neither pool is allowlisted
+    let allowlist = hex(address("allowlisted"));
+    let mut indexer = indexer_with(&chain, chain_id, &[("POOL_ALLOWLIST", allowlist)]).await;
+    indexer.process_blocks().await.unwrap();
+    let database = database().await;
+    assert!(database.get_untracked_pool_addresses().await.unwrap().contains(&hex(known)));
+
+    // The other pool was created by a factory the indexer does not follow
+    let (token0, token1, unseen) = (address("token0"), address("token1"), address("pool"));
+    chain.on_call(unseen, "token0()", vec![Token::Address(token0)]);
+    chain.on_call(unseen, "token1()", vec![Token::Address(token1)]);
+
+    indexer.watch_pool(&hex(known).to_uppercase().replace("0X", "0x")).unwrap();
+    indexer.watch_pool(&hex(unseen)).unwrap();
+    let err = indexer.pool_watcher().watch(&hex(known)).unwrap_err();
+    assert!(matches!(err.downcast_ref::<IndexerError>(), Some(IndexerError::AlreadyWatched { .. })));
+    assert!(indexer.watch_pool("0x1234").is_err());
+
+    let trader = address("trader");
+    chain.add_log(150, swap_log(known, trader, 1_000, -950, 12));
+    chain.add_log(150, swap_log(unseen, trader, 1_000, -950, 12));
+    chain.set_head(200);
+    indexer.process_blocks().await.unwrap();
+    indexer.drain_swap_writer().await;
+
+    let registered = database.get_pool(&hex(unseen)).await.unwrap().expect("Watched pool should be registered");
+    assert_eq!((registered.token0_address, registered.token1_address), (hex(token0), hex(token1)));
+    assert!(!database.get_untracked_pool_addresses().await.unwrap().contains(&hex(known)));
+    for pool in [known, unseen] {
+        assert_eq!(database.get_swaps_by_pool(&hex(pool), chain_id as i64, 10).await.unwrap().len(), 1);
+    }
+    let mut watched = database.get_watched_pools(chain_id as i64).await.unwrap();
+    watched.sort();
+    let mut expected = vec![hex(known), hex(unseen)];
+    expected.sort();
+    assert_eq!(watched, expected);
+}
+
+#[tokio::test]
+async fn test_doctor_flags_chain_id_mismatch_and_missing_factory_code() {
+    let chain = MockChain::new(20_000);
+    let provider = chain.provider();
+    let config = config_with(8453, &[]);
+
+    chain.set_chain_id(1);
+    let rpc = doctor::check_rpc(&provider, 8453).await;
+    assert!(!rpc.passed && rpc.detail.contains("chain id 1"), "{:?}", rpc);
+    chain.set_chain_id(8453);
+    assert!(doctor::check_rpc(&provider, 8453).await.passed);
+
+    let factory = doctor::check_factory(&provider, &config, 10_000).await;
+    assert!(!factory.passed && factory.detail.contains("no contract code"), "{:?}", factory);
+
+    // A contract, but no pools created in the sampled range
+    chain.deploy_code(FACTORY.parse().unwrap(), vec![0x60, 0x80]);
+    chain.add_log(5_000, pool_created_log(FACTORY.parse().unwrap(), address("token0"), address("token1"), 3000, 60, address("pool")));
+    let factory = doctor::check_factory(&provider, &config, 10_000).await;
+    assert!(!factory.passed && factory.detail.contains("no PoolCreated"), "{:?}", factory);
+    let factory = doctor::check_factory(&provider, &config, 15_000).await;
+    assert!(factory.passed, "{:?}", factory);
+
+    // The mock chain's blocks are from 2023
+    assert!(!doctor::check_clock(&provider).await.passed);
+}
+
+#[tokio::test]
+async fn test_replay_block_decodes_without_writing() {
+    let chain = MockChain::new(100);
+    let chain_id = 28_000_000 + (unique_id() % 1_000_000) as u64;
+    let (_, _, pool) = create_pool(&chain, 50);
+    chain.add_log(50, pool_created_log(address("other factory"), address("token0"), address("token1"), 3000, 60, address("pool")));
+    let trader = address("trader");
+    chain.add_log(50, swap_log(pool, trader, 1_000, -950, 12));
+    let mut truncated = swap_log(pool, trader, 1_000, -950, 12);
+    truncated.data = truncated.data[..64].to_vec().into();
+    chain.add_log(50, truncated);
+    chain.add_log(51, swap_log(pool, trader, 2_000, -1_900, 13));
+
+    // No indexer is set up, which would initialize the schema and store the scope
+    let config = config_with(chain_id, &[]);
+    let decoder = LogDecoder::from_config(&config).unwrap();
+    let report = replay_block(&chain.provider(), &decoder, &config, 50).await.unwrap();
+
+    assert_eq!(report.decoded_pools.len(), 1);
+    assert_eq!(report.decoded_pools[0].pool_address, hex(pool));
+    assert_eq!(report.decoded_swaps.len(), 1);
+    assert_eq!(report.decoded_swaps[0].amount_in, 1_000);
+    assert_eq!(report.decoded_swaps[0].timestamp, MockChain::block_timestamp(50) as i64);
+    assert_eq!(report.decoding_errors.len(), 1);
+    assert!(report.decoding_errors[0].starts_with("Swap log 3"), "{:?}", report.decoding_errors);
+
+    let database = database().await;
+    assert!(database.get_pool(&hex(pool)).await.unwrap().is_none());
+    assert!(database.get_swaps_by_pool(&hex(pool), chain_id as i64, 10).await.unwrap().is_empty());
+}
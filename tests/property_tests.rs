@@ -0,0 +1,139 @@
+use ethers::abi::{encode, Token};
+use ethers::types::{Address, Log, H256, I256, U256, U64};
+use ethers::utils::to_checksum;
+use moonshot_indexer::moonshot::{decode, get_factory_abi, get_pool_abi, LogDecoder};
+use moonshot_indexer::types::normalize_address;
+use moonshot_indexer::{PoolData, SwapEvent, TokenData};
+use proptest::prelude::*;
+
+mod strategies;
+
+fn h256() -> impl Strategy<Value = H256> {
+    any::<[u8; 32]>().prop_map(H256::from)
+}
+
+/// Topics that sometimes start with a real event signature, so noise also reaches the
+/// data decoding.
+fn topics(signature: H256) -> impl Strategy<Value = Vec<H256>> {
+    (any::<bool>(), proptest::collection::vec(h256(), 0..5)).prop_map(move |(signed, mut topics)| {
+        if signed && !topics.is_empty() {
+            topics[0] = signature;
+        }
+        topics
+    })
+}
+
+/// Arbitrary bytes, or noise the exact length of a Swap's data words.
+fn data() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        proptest::collection::vec(any::<u8>(), 0..512),
+        proptest::collection::vec(any::<u8>(), 5 * 32),
+    ]
+}
+
+fn noisy_log(signature: H256) -> impl Strategy<Value = Log> {
+    let ids = (any::<Option<u64>>(), any::<Option<[u8; 32]>>(), any::<Option<u64>>());
+    (any::<[u8; 20]>(), topics(signature), data(), ids).prop_map(
+        |(address, topics, data, (block_number, transaction_hash, log_index))| Log {
+            address: Address::from(address),
+            topics,
+            data: data.into(),
+            block_number: block_number.map(U64::from),
+            transaction_hash: transaction_hash.map(H256::from),
+            log_index: log_index.map(U256::from),
+            ..Default::default()
+        },
+    )
+}
+
+/// Any int128, weighted towards the edges of the 64-bit range amounts are stored in.
+fn raw_amount() -> impl Strategy<Value = i128> {
+    let (min, max) = (i64::MIN as i128, i64::MAX as i128);
+    prop_oneof![any::<i128>(), (min - 2)..=(min + 2), (max - 2)..=(max + 2), -2i128..=2]
+}
+
+fn swap_signature() -> H256 {
+    get_pool_abi().event("Swap").unwrap().signature()
+}
+
+proptest! {
+    #[test]
+    fn swap_event_survives_json(swap in strategies::swap_event()) {
+        let json = serde_json::to_string(&swap).unwrap();
+        prop_assert_eq!(serde_json::from_str::<SwapEvent>(&json).unwrap(), swap);
+    }
+
+    #[test]
+    fn pool_data_survives_json(pool in strategies::pool_data()) {
+        let json = serde_json::to_string(&pool).unwrap();
+        prop_assert_eq!(serde_json::from_str::<PoolData>(&json).unwrap(), pool);
+    }
+
+    #[test]
+    fn token_data_survives_json(token in strategies::token_data()) {
+        let json = serde_json::to_string(&token).unwrap();
+        prop_assert_eq!(serde_json::from_str::<TokenData>(&json).unwrap(), token);
+    }
+
+    #[test]
+    fn swap_noise_is_an_error_not_a_panic(topics in topics(swap_signature()), data in data()) {
+        let _ = decode::decode_swap(&topics, &data);
+    }
+
+    #[test]
+    fn log_noise_is_an_error_not_a_panic(log in noisy_log(swap_signature()), pool_created in noisy_log(
+        get_factory_abi().event("PoolCreated").unwrap().signature()
+    )) {
+        let decoder = LogDecoder::new();
+        let _ = decoder.decode_swap(&log, 2741);
+        let _ = decoder.decode_pool_created(&pool_created, 2741);
+    }
+
+    #[test]
+    fn encoded_swaps_decode_exactly(
+        amount0 in raw_amount(),
+        amount1 in raw_amount(),
+        liquidity in any::<u128>(),
+        tick in any::<i32>(),
+        sender in any::<[u8; 20]>(),
+    ) {
+        let topics = vec![swap_signature(), H256::from(Address::from(sender)), H256::zero()];
+        let data = encode(&[
+            Token::Int(I256::from(amount0).into_raw()),
+            Token::Int(I256::from(amount1).into_raw()),
+            Token::Uint(U256::one() << 96),
+            Token::Uint(U256::from(liquidity)),
+            Token::Int(I256::from(tick).into_raw()),
+        ]);
+
+        let swap = decode::decode_swap(&topics, &data).unwrap();
+        prop_assert_eq!((swap.amount0, swap.amount1, swap.liquidity, swap.tick), (amount0, amount1, liquidity, tick));
+        prop_assert_eq!(swap.sender, Address::from(sender));
+
+        // Amounts beyond 64 bits are rejected rather than truncated
+        let log = Log {
+            topics,
+            data: data.into(),
+            block_number: Some(U64::from(1)),
+            transaction_hash: Some(H256::zero()),
+            log_index: Some(U256::zero()),
+            ..Default::default()
+        };
+        if let Ok(event) = LogDecoder::new().decode_swap(&log, 2741) {
+            let (paid, received) = if amount0 > 0 { (amount0, amount1) } else { (amount1, amount0) };
+            prop_assert_eq!((event.amount_in as i128, event.amount_out as i128), (paid, -received));
+        }
+    }
+
+    #[test]
+    fn address_normalization_is_idempotent(address in any::<String>()) {
+        let normalized = normalize_address(&address);
+        prop_assert_eq!(normalize_address(&normalized), normalized);
+    }
+
+    #[test]
+    fn normalized_addresses_match_decoded_ones(address in any::<[u8; 20]>()) {
+        let address = Address::from(address);
+        prop_assert_eq!(normalize_address(&to_checksum(&address, None)), format!("{:?}", address));
+    }
+}
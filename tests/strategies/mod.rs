@@ -0,0 +1,159 @@
+//! Proptest strategies for the indexed types. Values stay within what the database columns
+//! hold, so the same strategies drive both the JSON and the database round-trips.
+
+// Each test crate compiles this module on its own and uses only some of it
+#![allow(dead_code)]
+
+use moonshot_indexer::dex::{DexId, DexRegistry};
+use moonshot_indexer::types::HexBytes;
+use moonshot_indexer::{PoolData, SwapEvent, TokenData};
+use proptest::prelude::*;
+
+pub fn address() -> impl Strategy<Value = String> {
+    "0x[0-9a-f]{40}"
+}
+
+pub fn tx_hash() -> impl Strategy<Value = String> {
+    "0x[0-9a-f]{64}"
+}
+
+/// Chain ids are stored as INTEGER.
+pub fn chain_id() -> impl Strategy<Value = i64> {
+    (1..=i32::MAX).prop_map(i64::from)
+}
+
+/// Ids of the preset DEXes, the only `dex_name` a database with the presets accepts.
+pub fn dex_name() -> impl Strategy<Value = DexId> {
+    proptest::sample::select(DexRegistry::presets().dexes().iter().map(|dex| dex.id.clone()).collect::<Vec<_>>())
+}
+
+/// Swap amounts, weighted towards the top of the 64-bit range.
+pub fn amount() -> impl Strategy<Value = i64> {
+    prop_oneof![Just(0), Just(i64::MAX), (i64::MAX - 1_000)..=i64::MAX, 0..=i64::MAX]
+}
+
+/// Whole cents, as DECIMAL(20, 2) stores them; 15 significant digits survive the FLOAT8
+/// conversions on the way in and out.
+pub fn usd() -> impl Strategy<Value = Option<f64>> {
+    proptest::option::of((0..1_000_000_000_000_000u64).prop_map(|cents| cents as f64 / 100.0))
+}
+
+/// Unsigned 256-bit integers in decimal, as NUMERIC(78, 0) prints them.
+pub fn uint256() -> impl Strategy<Value = String> {
+    "0|[1-9][0-9]{0,76}"
+}
+
+/// Text without control characters (Postgres rejects NUL) of up to `max_chars` characters.
+pub fn text(max_chars: usize) -> impl Strategy<Value = String> {
+    proptest::string::string_regex(&format!("\\PC{{0,{}}}", max_chars)).unwrap()
+}
+
+pub fn swap_event() -> impl Strategy<Value = SwapEvent> {
+    let ids = (tx_hash(), address(), any::<bool>(), 0..=i64::MAX, 0..=i64::MAX, 0..=i32::MAX, chain_id());
+    let amounts = (amount(), amount(), usd(), usd());
+    let extras = (
+        proptest::option::of(address()),
+        proptest::option::of(0..=i64::MAX),
+        proptest::option::of(proptest::collection::vec(any::<u8>(), 0..256).prop_map(HexBytes::from)),
+    );
+
+    (ids, amounts, extras).prop_map(
+        |(
+            (tx_hash, pool_address, zero_for_one, timestamp, block_number, log_index, chain_id),
+            (amount_in, amount_out, amount_in_usd, amount_out_usd),
+            (sender_address, indexed_at, calldata),
+        )| {
+            let (token_in, token_out) = if zero_for_one { ("token0", "token1") } else { ("token1", "token0") };
+            let mut swap = SwapEvent::new(
+                tx_hash,
+                pool_address,
+                token_in.to_string(),
+                token_out.to_string(),
+                amount_in,
+                amount_out,
+                timestamp,
+                block_number,
+                log_index,
+                chain_id,
+            );
+            swap.amount_in_usd = amount_in_usd;
+            swap.amount_out_usd = amount_out_usd;
+            swap.sender_address = sender_address;
+            swap.indexed_at = indexed_at;
+            swap.calldata = calldata;
+            swap
+        },
+    )
+}
+
+pub fn pool_data() -> impl Strategy<Value = PoolData> {
+    let tokens = (address(), address(), address(), proptest::option::of(text(20)), proptest::option::of(text(20)));
+    let decimals = (any::<Option<i32>>(), any::<Option<i32>>(), any::<Option<i32>>(), any::<Option<i32>>());
+    let state = (any::<Option<i64>>(), proptest::option::of(uint256()), any::<Option<i32>>());
+    let meta = (chain_id(), dex_name(), proptest::option::of(0..=i64::MAX), proptest::option::of(0..=i64::MAX), any::<bool>());
+
+    (tokens, decimals, state, meta).prop_map(
+        |(
+            (pool_address, token0_address, token1_address, token0_symbol, token1_symbol),
+            (token0_decimals, token1_decimals, fee_tier, tick_spacing),
+            (liquidity, sqrt_price_x96, tick),
+            (chain_id, dex_name, created_at_block, updated_at_block, has_nonstandard_token),
+        )| PoolData {
+            pool_address,
+            token0_address,
+            token1_address,
+            token0_symbol,
+            token1_symbol,
+            token0_decimals,
+            token1_decimals,
+            fee_tier,
+            tick_spacing,
+            liquidity,
+            sqrt_price_x96,
+            tick,
+            chain_id,
+            dex_name,
+            created_at_block,
+            updated_at_block,
+            has_nonstandard_token,
+            created_at: None,
+            updated_at: None,
+        },
+    )
+    .prop_filter("DEX deployed on the chain", |pool| DexRegistry::presets().check_chain(&pool.dex_name, pool.chain_id).is_ok())
+}
+
+pub fn token_data() -> impl Strategy<Value = TokenData> {
+    let metadata = (
+        address(),
+        proptest::option::of(text(100)),
+        proptest::option::of(text(20)),
+        any::<Option<i32>>(),
+        proptest::option::of(uint256()),
+    );
+    let flags = (
+        proptest::option::of(0..=i64::MAX),
+        any::<bool>(),
+        any::<bool>(),
+        proptest::option::of(address()),
+        chain_id(),
+    );
+
+    (metadata, flags).prop_map(
+        |(
+            (address, name, symbol, decimals, total_supply),
+            (supply_updated_at, is_fee_on_transfer, is_rebasing, creator_address, chain_id),
+        )| TokenData {
+            address,
+            name,
+            symbol,
+            decimals,
+            total_supply,
+            supply_updated_at,
+            is_fee_on_transfer,
+            is_rebasing,
+            creator_address,
+            chain_id,
+        },
+    )
+}